@@ -60,7 +60,8 @@ export default function AdminPage() {
   const [uploadStatus, setUploadStatus] = useState<string>('');
   const [isDragging, setIsDragging] = useState(false);
   const [scanStatus, setScanStatus] = useState<string>('');
-  const [maxSizeMb, setMaxSizeMb] = useState<string>('');
+  const [budgetMb, setBudgetMb] = useState<string>('');
+  const [lenientImport, setLenientImport] = useState<boolean>(false);
   const [renderAnalyzerEnabled, setRenderAnalyzerEnabled] = useState<boolean>(false);
   const [imports, setImports] = useState<ImportProgress[]>([]);
   const [importsLoading, setImportsLoading] = useState<boolean>(false);
@@ -207,8 +208,9 @@ export default function AdminPage() {
     for (const file of files) {
       const metadata = await getMetadata();
       const formData = new FormData();
-      formData.append('file', file);
+      // The name goes first so the backend knows it before the file streams in
       formData.append('filename', file.name);
+      formData.append('file', file);
 
       try {
         const response = await fetch(`${getBackendApiUrl()}/api/upload-dict`, {
@@ -312,8 +314,11 @@ export default function AdminPage() {
     setScanStatus('Scanning dictionaries...');
     try {
       const queryParams = new URLSearchParams();
-      if (maxSizeMb) {
-        queryParams.append('max_size_mb', maxSizeMb);
+      if (budgetMb) {
+        queryParams.append('budget_mb', budgetMb);
+      }
+      if (lenientImport) {
+        queryParams.append('mode', 'lenient');
       }
 
       const response = await fetch(
@@ -329,7 +334,11 @@ export default function AdminPage() {
         setScanStatus(`Error: ${data.error}`);
       } else {
         const dictCount = data.info?.length ?? 0;
-        setScanStatus(`Scan complete: Found ${dictCount} dictionaries`);
+        const deferred = data.report?.deferredAssets ?? 0;
+        setScanStatus(
+          `Scan complete: Found ${dictCount} dictionaries` +
+            (deferred ? `, ${deferred} loaded without their images` : '')
+        );
         await handlePrintDicts();
       }
     } catch (error) {
@@ -605,17 +614,28 @@ export default function AdminPage() {
 
         <div className="space-y-4">
           <div className="grid w-full max-w-sm items-center gap-1.5">
-            <Label htmlFor="maxSize">Max Dictionary Size (MB)</Label>
+            <Label htmlFor="loadBudget">Loading Budget (MB)</Label>
             <Input
               type="number"
-              id="maxSize"
-              placeholder="Leave empty for no limit"
-              value={maxSizeMb}
-              onChange={(e) => setMaxSizeMb(e.target.value)}
+              id="loadBudget"
+              placeholder="Leave empty for the server's budget"
+              value={budgetMb}
+              onChange={(e) => setBudgetMb(e.target.value)}
               className="max-w-[200px]"
             />
           </div>
 
+          <div className="flex items-center space-x-2">
+            <Switch
+              id="lenientImport"
+              checked={lenientImport}
+              onCheckedChange={setLenientImport}
+            />
+            <Label htmlFor="lenientImport" className="text-sm">
+              Skip malformed bank rows instead of storing them
+            </Label>
+          </div>
+
           <div className="flex gap-2">
             <Button 
               onClick={handlePrintDicts}
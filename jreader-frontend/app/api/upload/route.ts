@@ -151,6 +151,7 @@ export async function POST(request: Request) {
     const importId = formData.get('importId') as string; // For progress tracking
     const authToken = formData.get('authToken') as string; // For progress updates
     const epubFilename = formData.get('epubFilename') as string; // For server-side EPUB fetching
+    const replaceUploadId = formData.get('replaceUploadId') as string | null; // Book this upload replaces
 
     // Clean the URL: strip whitespace and trailing slashes
     const cleanedUrl = originalUrl ? originalUrl.trim().replace(/\/+$/, '') : null;
@@ -246,6 +247,24 @@ export async function POST(request: Request) {
       );
     }
 
+    // A replaced book keeps its upload id, so it has to be one of the user's
+    if (replaceUploadId) {
+      const { data: existingBook, error: existingBookError } = await supabase
+        .from('User Uploads')
+        .select('id')
+        .eq('id', replaceUploadId)
+        .eq('user_id', user.id)
+        .maybeSingle();
+
+      if (existingBookError || !existingBook) {
+        console.error('Book to replace not found:', { replaceUploadId, existingBookError });
+        return NextResponse.json(
+          { error: 'Book to replace not found' },
+          { status: 404 }
+        );
+      }
+    }
+
     // Get user's tier and enforce book limits
     const { data: userData, error: userError } = await supabase
       .from('Users')
@@ -297,8 +316,8 @@ export async function POST(request: Request) {
       canUpload: currentCount < bookLimit
     });
 
-    // Check if user has reached their limit
-    if (currentCount >= bookLimit) {
+    // Check if user has reached their limit (replacing a book doesn't add one)
+    if (!replaceUploadId && currentCount >= bookLimit) {
       const tier = userData.tier;
       let errorMessage = 'You have reached the maximum number of books for your current plan.';
 
@@ -349,7 +368,7 @@ export async function POST(request: Request) {
       }
       console.log('EPUB validation passed');
 
-      return await handleRegularUpload(supabase, user, file, uploadResponse, contents);
+      return await handleRegularUpload(supabase, user, file, uploadResponse, contents, replaceUploadId);
     }
   } catch (error) {
     console.error('Upload error:', error);
@@ -449,12 +468,12 @@ async function handleWebnovelImport(supabase: any, user: any, uploadResponse: an
   let contents: JSZip;
 
   try {
-    if (!epubFilename || !authToken) {
-      throw new Error('Missing epubFilename or authToken for EPUB fetch');
+    if (!epubFilename || !authToken || !importId) {
+      throw new Error('Missing epubFilename, authToken or importId for EPUB fetch');
     }
 
     // Get the API URL using the existing utility function
-    const downloadUrl = `${getBackendApiUrl()}/api/webnovel/download/${encodeURIComponent(epubFilename)}`;
+    const downloadUrl = `${getBackendApiUrl()}/api/webnovel/download/${encodeURIComponent(importId)}/${encodeURIComponent(epubFilename)}`;
     console.log('=== FETCHING EPUB FROM RUST BACKEND ===');
     console.log('Fetching EPUB from:', downloadUrl);
 
@@ -741,13 +760,14 @@ async function handleWebnovelImport(supabase: any, user: any, uploadResponse: an
   });
 }
 
-async function handleRegularUpload(supabase: any, user: any, file: File, uploadResponse: any, contents: any) {
+async function handleRegularUpload(supabase: any, user: any, file: File, uploadResponse: any, contents: any, replaceUploadId?: string | null) {
   console.log('=== REGULAR UPLOAD HANDLER ===');
 
   try {
     // Create a base path for this book in storage
-    // Generate a random UUID for the book
-    const uploadId = crypto.randomUUID();
+    // Generate a random UUID for the book, or keep the one of the book being
+    // replaced so its reading position and highlights carry over
+    const uploadId = replaceUploadId || crypto.randomUUID();
     const bookBasePath = `${user.id}/${uploadId}`;
     const uploadedFiles = [];
 
@@ -797,7 +817,7 @@ async function handleRegularUpload(supabase: any, user: any, file: File, uploadR
 
     const { data: uploadData, error: insertError } = await supabase
         .from('User Uploads')
-        .insert({
+        .upsert({
             id: uploadId,
             title: uploadResponse.bookMetadata.title,
             author: uploadResponse.bookMetadata.author,
@@ -819,6 +839,21 @@ async function handleRegularUpload(supabase: any, user: any, file: File, uploadR
 
     console.log('Successfully inserted book metadata:', uploadData.id);
 
+    if (replaceUploadId) {
+      const { error: deleteTocError } = await supabase
+        .from('Table of Contents')
+        .delete()
+        .eq('upload_id', uploadId);
+
+      if (deleteTocError) {
+        console.error('Failed to remove the replaced table of contents:', deleteTocError);
+        return NextResponse.json(
+          { error: 'Failed to replace table of contents: ' + deleteTocError.message },
+          { status: 500 }
+        );
+      }
+    }
+
     // Insert table of contents entries
     console.log('Inserting table of contents...');
     console.log('TOC entries count:', uploadResponse.bookMetadata.toc.length);
@@ -855,7 +890,7 @@ async function handleRegularUpload(supabase: any, user: any, file: File, uploadR
     });
 
     return NextResponse.json({
-      message: 'Upload successful',
+      message: replaceUploadId ? 'Book replaced' : 'Upload successful',
       data: {
         filename: file.name,
         basePath: bookBasePath,
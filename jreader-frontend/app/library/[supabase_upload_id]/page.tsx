@@ -27,7 +27,7 @@ import { SettingsProvider } from "@/contexts/SettingsContext"
 import { useDeviceDetect } from '@/hooks/useDeviceDetect'
 import { useKanjiStates, KanjiQueryEnabled, SubscriptionCheck } from '@/hooks/useKanjiStates'
 import { usePageTitle } from '@/hooks/usePageTitle'
-import { backendService } from '@/services/backendService'
+import { backendService, type ReaderSettings } from '@/services/backendService'
 import type { LookupTermResponse } from '@/types/backend-types'
 import { safeNumberStorage, safeBooleanStorage, safeJsonStorage, safeStringStorage } from '@/utils/safeStorage'
 import { safeHistory, safeDocument } from '@/utils/safeWindow'
@@ -178,6 +178,30 @@ export default function BookPage({
     }
   }, [updateUrl, updatePageTitle]);
 
+  // Reader settings follow the account: the stored ones replace the ones of
+  // this device, and changes are saved back
+  const readerSettingsRef = useRef<ReaderSettings | null>(null)
+  const saveReaderSettings = useMemo(
+    () => debounce((settings: ReaderSettings) => backendService.saveReaderPreferences(settings), 1000),
+    []
+  )
+
+  useEffect(() => {
+    if (!user) {
+      return;
+    }
+    backendService.getReaderPreferences().then(settings => {
+      if (!settings) {
+        return;
+      }
+      readerSettingsRef.current = settings;
+      setFontSize(settings.fontSize);
+      setVerticalMargin(settings.verticalMargin);
+      safeNumberStorage.setItem('reader-fontSize', settings.fontSize);
+      safeNumberStorage.setItem('reader-verticalMargin', settings.verticalMargin);
+    });
+  }, [user?.id]);
+
   const updateSetting = (type: 'fontSize' | 'verticalMargin', value: number) => {
     if (type === 'fontSize') {
       setFontSize(value);
@@ -187,6 +211,11 @@ export default function BookPage({
       safeNumberStorage.setItem('reader-verticalMargin', value);
     }
 
+    if (readerSettingsRef.current) {
+      readerSettingsRef.current = { ...readerSettingsRef.current, [type]: value };
+      saveReaderSettings(readerSettingsRef.current);
+    }
+
     const event = new CustomEvent('settingsupdate', {
       detail: { type, value }
     });
@@ -219,7 +248,7 @@ export default function BookPage({
       setIsLoading(true)
       try {
         console.log(`🔍 Starting debounced search for: "${text}" at position ${position}`)
-        const result = await backendService.lookupTerm(text, position)
+        const result = await backendService.lookupTerm(text, position, supabase_upload_id)
 
         if (result) {
           console.log('📗 Found definition, checking for kanji to cycle')
@@ -261,7 +290,7 @@ export default function BookPage({
         setIsLoading(false)
       }
     }, 300),
-    [knownKanji, encounteredKanji, cycleKanjiState]
+    [knownKanji, encounteredKanji, cycleKanjiState, supabase_upload_id]
   )
 
   const handleSearch = useCallback((
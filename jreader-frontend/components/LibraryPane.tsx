@@ -38,6 +38,7 @@ import { getBackendApiUrl } from '@/utils/api';
 import { debug } from '@/utils/debug';
 import { encodeFilename } from '@/utils/filename';
 import { getCurrentUsername } from '@/lib/client-auth';
+import { fetchImportProgress } from '@/utils/importProgress';
 import { createClient, getMetadata } from '@/utils/supabase/client';
 import { type SyosetuApiResponse } from '@/utils/syosetuApi';
 import { getGenreName } from '@/utils/syosetuGenres';
@@ -216,11 +217,27 @@ interface LibraryPaneProps {
   isAuthLoading?: boolean;
 }
 
-async function uploadToAxumServer(file: File) {
+interface DuplicateBook {
+  uploadId: string;
+  title: string;
+  author: string;
+  matchedBy: 'content' | 'title_and_author';
+}
+
+class DuplicateBookError extends Error {
+  constructor(message: string, public duplicate: DuplicateBook) {
+    super(message);
+  }
+}
+
+async function uploadToAxumServer(file: File, replaceUploadId?: string) {
   try {
     const metadata = await getMetadata();
     const formData = new FormData();
     formData.append('file', file);
+    if (replaceUploadId) {
+      formData.append('replace', replaceUploadId);
+    }
 
     const response = await fetch(`${getBackendApiUrl()}/api/upload`, {
       method: 'POST',
@@ -233,13 +250,18 @@ async function uploadToAxumServer(file: File) {
     if (!response.ok) {
       const errorText = await response.text();
       let errorMessage;
+      let errorData;
       try {
-        const errorData = JSON.parse(errorText);
+        errorData = JSON.parse(errorText);
         errorMessage = errorData.error || `Upload failed: ${response.statusText}`;
       } catch (e) {
         errorMessage = `Upload failed: ${response.statusText}`;
       }
 
+      if (response.status === 409 && errorData?.duplicate) {
+        throw new DuplicateBookError(errorMessage, errorData.duplicate);
+      }
+
       // Add file size information for payload size errors
       if (response.status === 413) {
         const fileSizeMB = (file.size / (1024 * 1024)).toFixed(2);
@@ -256,6 +278,56 @@ async function uploadToAxumServer(file: File) {
   }
 }
 
+const CONVERTIBLE_EXTENSIONS = ['.mobi', '.azw3', '.azw'];
+
+const isConvertible = (filename: string) =>
+  CONVERTIBLE_EXTENSIONS.some(ext => filename.toLowerCase().endsWith(ext));
+
+// Convert a MOBI or AZW3 book to EPUB on the Axum server. The conversion runs
+// as an import, so it's polled through the import progress API.
+async function convertOnAxumServer(file: File): Promise<File> {
+  const metadata = await getMetadata();
+  const headers = { 'Authorization': `Bearer ${metadata.accessToken}` };
+  const formData = new FormData();
+  formData.append('file', file);
+
+  const response = await fetch(`${getBackendApiUrl()}/api/upload/convert`, {
+    method: 'POST',
+    body: formData,
+    headers
+  });
+  const result = await response.json().catch(() => ({}));
+  if (!response.ok) {
+    throw new Error(result.error || `Conversion failed: ${response.statusText}`);
+  }
+  const importId: string = result.import_id;
+
+  for (;;) {
+    await new Promise(resolve => setTimeout(resolve, 2000));
+    const { imports } = await fetchImportProgress();
+    const status = imports.find(imp => imp.id === importId)?.status;
+    if (!status) {
+      throw new Error('Conversion was lost');
+    }
+    if (typeof status === 'object' && 'Failed' in status) {
+      throw new Error(status.Failed);
+    }
+    if (status === 'Cancelled') {
+      throw new Error('Conversion was cancelled');
+    }
+    if (status === 'Completed') {
+      break;
+    }
+  }
+
+  const epubResponse = await fetch(`${getBackendApiUrl()}/api/upload/convert/${importId}`, { headers });
+  if (!epubResponse.ok) {
+    throw new Error(`Failed to download converted book: ${epubResponse.statusText}`);
+  }
+  const epubName = file.name.replace(/\.[^.]+$/, '.epub');
+  return new File([await epubResponse.blob()], epubName, { type: 'application/epub+zip' });
+}
+
 interface TableOfContentsEntry {
   label: string;
   content_src: string;
@@ -271,6 +343,8 @@ interface AxumUploadResponse {
   cover_path: string | null;
   toc: TableOfContentsEntry[];
   spine: string[];
+  content_hash: string;
+  replaces_upload_id?: string;
 }
 
 export default function LibraryPane({ setActivePane, isAuthenticated = true, isAuthLoading = false }: LibraryPaneProps) {
@@ -672,8 +746,8 @@ export default function LibraryPane({ setActivePane, isAuthenticated = true, isA
           lastModified: new Date(file.lastModified).toISOString()
         });
 
-        if (!file.name.toLowerCase().endsWith('.epub')) {
-        setError(`File ${file.name} is not an epub file`);
+        if (!file.name.toLowerCase().endsWith('.epub') && !isConvertible(file.name)) {
+        setError(`File ${file.name} is not an epub, mobi or azw3 file`);
           continue;
         }
 
@@ -723,16 +797,39 @@ export default function LibraryPane({ setActivePane, isAuthenticated = true, isA
           // Only show upload status after confirming user is under limit
           setUploadStatuses(prev => [...prev, {
             filename: file.name,
-            status: 'uploading'
+            status: 'uploading',
+            message: isConvertible(file.name) ? 'Converting to EPUB...' : undefined
           }]);
+
+          // MOBI and AZW3 books are uploaded as the EPUB they convert to
+          const epubFile = isConvertible(file.name) ? await convertOnAxumServer(file) : file;
+          if (epubFile !== file) {
+            setUploadStatuses(prev => prev.map(status =>
+              status.filename === file.name
+                ? { ...status, message: undefined }
+                : status
+            ));
+          }
+
           console.log('=== About to upload to Axum server');
           // Upload to Axum backend
-          const rustResponse: AxumUploadResponse = await uploadToAxumServer(file);
+          let rustResponse: AxumUploadResponse;
+          try {
+            rustResponse = await uploadToAxumServer(epubFile);
+          } catch (error) {
+            if (!(error instanceof DuplicateBookError)) throw error;
+            const existingTitle = error.duplicate.title || file.name;
+            // Replacing keeps the upload id, so reading position and highlights stay
+            if (!window.confirm(`"${existingTitle}" is already in your library. Replace it with this file?`)) {
+              throw new Error(`"${existingTitle}" is already in your library`);
+            }
+            rustResponse = await uploadToAxumServer(epubFile, error.duplicate.uploadId);
+          }
           console.log('Axum upload response:', rustResponse);
 
           // Create form data for the Supabase upload
           const formData = new FormData();
-          formData.append('file', file);
+          formData.append('file', epubFile);
           formData.append('bookMetadata', JSON.stringify({
             title: rustResponse.title,
             author: rustResponse.author,
@@ -741,6 +838,9 @@ export default function LibraryPane({ setActivePane, isAuthenticated = true, isA
             toc: rustResponse.toc,
             spine: rustResponse.spine,
           }));
+          if (rustResponse.replaces_upload_id) {
+            formData.append('replaceUploadId', rustResponse.replaces_upload_id);
+          }
 
           console.log('=== About to send to Next.js API');
           // Send to our Next.js API route
@@ -1118,7 +1218,7 @@ export default function LibraryPane({ setActivePane, isAuthenticated = true, isA
             className="hidden"
             onChange={handleFileSelect}
             multiple
-            accept=".epub,.txt,.html"
+            accept=".epub,.mobi,.azw3,.azw,.txt,.html"
           />
           <label
             htmlFor="file-upload"
@@ -1128,7 +1228,7 @@ export default function LibraryPane({ setActivePane, isAuthenticated = true, isA
           </label>
           <span className="text-muted-foreground"> or drag files here</span>
           <p className="text-sm text-muted-foreground mt-2">
-            Supported file formats: EPUB
+            Supported file formats: EPUB, MOBI, AZW3
           </p>
         </div>
       )}
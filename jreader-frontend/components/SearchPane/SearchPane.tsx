@@ -28,6 +28,7 @@ import { useAutoSync } from '@/contexts/AutoSyncContext';
 import { useExtension } from '@/contexts/ExtensionContext';
 import { useKanjiStates, KanjiQueryEnabled, SubscriptionCheck } from '@/hooks/useKanjiStates'
 import { cn } from '@/lib/utils';
+import { backendService } from '@/services/backendService';
 import { makeDefinitionKey, parseDefinitionKey, type DefinitionKey, makeAudioSelectionKey, type AudioSelectionKey } from './definitionKey';
 import type { LookupTermResponse, FrequencyDataList, TermEntry, PitchAccentEntryList } from '@/types/backend-types';
 import { EXTENSION_CONTENT_SCRIPT_EVENT_ANKI_SYNC_CARDS, EXTENSION_CONTENT_SCRIPT_EVENT_ANKI_SYNC_CARDS_RESPONSE, EXTENSION_CONTENT_SCRIPT_EVENT_ANKI_OPEN_NOTE, EXTENSION_CONTENT_SCRIPT_EVENT_ANKI_OPEN_NOTE_RESPONSE } from '@/types/events'
@@ -379,7 +380,7 @@ function SearchPane({
   );
 
   // Create a single Supabase client instance with error handling
-  const [supabase, setSupabase] = useState(() => {
+  const [supabase] = useState(() => {
     try {
       return createClient();
     } catch (error) {
@@ -391,6 +392,8 @@ function SearchPane({
   // Audio state
   const [audioData, setAudioData] = useState<Record<string, AudioResponse>>({});
   const [audioLoading, setAudioLoading] = useState<Record<string, boolean>>({});
+  // Term+reading keys whose audio was already asked for in a batch
+  const audioBatchRequestedRef = React.useRef<Set<string>>(new Set());
   const [audioError, setAudioError] = useState<Record<string, string>>({});
 
   // Mined cards state
@@ -448,23 +451,6 @@ function SearchPane({
     return () => window.removeEventListener('keydown', onKey);
   }, []);
 
-  // Function to ensure Supabase client is available
-  const ensureSupabaseClient = useCallback(async () => {
-    if (supabase) {
-      return supabase;
-    }
-
-    try {
-      console.log('🔄 Recreating Supabase client...');
-      const newClient = createClient();
-      setSupabase(newClient);
-      return newClient;
-    } catch (error) {
-      console.error('Failed to recreate Supabase client:', error);
-      throw new Error('Supabase client not available');
-    }
-  }, [supabase]);
-
   // Function to handle dictionary checkbox changes for a given layer index
   const handleDictionaryCheck = useCallback((layerIndex: number, defKey: DefinitionKey, checked: boolean) => {
     console.log('DEBUG_MINING 🔧 handleDictionaryCheck called:', { layerIndex, defKey, checked });
@@ -878,6 +864,36 @@ function SearchPane({
     return groupByTerm(memoizedSearchResult.dictionaryResults, effectivePreferences.dictionaryOrder);
   }, [memoizedSearchResult?.dictionaryResults, effectivePreferences.dictionaryOrder]);
 
+  // Load the audio of all term groups in one request, instead of a request
+  // per card when its audio button is clicked or it is mined
+  useEffect(() => {
+    if (!isAuthenticated) return;
+    const items = termGroups
+      .map(group => ({ term: group.term, reading: group.reading || undefined }))
+      .filter(item => !audioBatchRequestedRef.current.has(`${item.term}|${item.reading || ''}`));
+    if (items.length === 0) return;
+
+    const keys = items.map(item => `${item.term}|${item.reading || ''}`);
+    keys.forEach(key => audioBatchRequestedRef.current.add(key));
+    setAudioLoading(prev => ({ ...prev, ...Object.fromEntries(keys.map(key => [key, true])) }));
+    backendService.getAudioBatch(items).then(results => {
+      if (results) {
+        // Results are in the order of the items
+        setAudioData(prev => ({
+          ...prev,
+          ...Object.fromEntries(results.map((result, i) => [
+            keys[i],
+            { type: 'audioSourceList', audioSources: result.audioSources }
+          ]))
+        }));
+      } else {
+        // Left to fetchAudio when the audio button is clicked
+        keys.forEach(key => audioBatchRequestedRef.current.delete(key));
+      }
+      setAudioLoading(prev => ({ ...prev, ...Object.fromEntries(keys.map(key => [key, false])) }));
+    });
+  }, [termGroups, isAuthenticated]);
+
   // Memoize pitch accent data to prevent unnecessary recalculations
   const pitchAccentData = useMemo(() => {
     if (!memoizedSearchResult?.pitchAccentResults) return {};
@@ -967,7 +983,7 @@ function SearchPane({
               } else if (definition.type === 'structured') {
                 dictDefinitions.push({ type: 'structured', content: definition.content, dictionary_title: dictEntry.title, dictionary_origin: dictEntry.origin });
               } else if (definition.type === 'deinflection') {
-                dictDefinitions.push({ type: 'simple', content: `Base form: ${definition.baseForm}`, dictionary_title: dictEntry.title, dictionary_origin: dictEntry.origin });
+                dictDefinitions.push({ type: 'simple', content: `Base form: ${definition.baseForm}${definition.inflections.length ? ` (${definition.inflections.map(i => i.label).join(' « ')})` : ''}`, dictionary_title: dictEntry.title, dictionary_origin: dictEntry.origin });
               }
             }
           }
@@ -1004,29 +1020,19 @@ function SearchPane({
           expressionAudio = extractAudioUrlWithoutParams(mediaPath);
         }
       } else {
-        // Attempt direct fetch like in create path
+        // Not loaded with the other term groups yet, attempt a fetch like in create path
         try {
-          const params = new URLSearchParams({ term });
-          if (reading) params.append('reading', reading);
-          const apiUrl = getBackendApiUrl();
-          const fullUrl = `${apiUrl}/api/audio?${params.toString()}`;
-          const supabaseClient = await ensureSupabaseClient();
-          const response = await fetch(fullUrl, {
-            headers: { 'Authorization': `Bearer ${(await supabaseClient.auth.getSession()).data.session?.access_token}` }
-          });
-          if (response.ok) {
-            const audioResponse: AudioResponse = await response.json();
-            if (audioResponse.audioSources?.length > 0) {
-              const selectedAudioSource = getSelectedAudioSource(
-                audioResponse.audioSources,
-                selectedAudioByLayer,
-                0,
-                currentLayerIndex
-              );
-              if (selectedAudioSource) {
-                const mediaPath = selectedAudioSource.url.replace('/audio/', '/media/');
-                expressionAudio = extractAudioUrlWithoutParams(mediaPath);
-              }
+          const [audioResult] = await backendService.getAudioBatch([{ term, reading }]) ?? [];
+          if (audioResult?.audioSources?.length) {
+            const selectedAudioSource = getSelectedAudioSource(
+              audioResult.audioSources,
+              selectedAudioByLayer,
+              0,
+              currentLayerIndex
+            );
+            if (selectedAudioSource) {
+              const mediaPath = selectedAudioSource.url.replace('/audio/', '/media/');
+              expressionAudio = extractAudioUrlWithoutParams(mediaPath);
             }
           }
         } catch {}
@@ -1034,7 +1040,7 @@ function SearchPane({
 
       return { term, reading, definitions, frequencyPairs, pitchAccent, expressionAudio };
     };
-  }, [prepareDataRef, searchStack, memoizedSearchResult, effectivePreferences.dictionaryOrder, effectivePreferences.freqDictionaryOrder, checkedByLayer, pitchAccentData, audioData, selectedAudioByLayer, ensureSupabaseClient, searchQuery]);
+  }, [prepareDataRef, searchStack, memoizedSearchResult, effectivePreferences.dictionaryOrder, effectivePreferences.freqDictionaryOrder, checkedByLayer, pitchAccentData, audioData, selectedAudioByLayer, searchQuery]);
 
   useEffect(() => {
     console.log('🔄 Effect running with:', {
@@ -1666,7 +1672,7 @@ function SearchPane({
                                               } else if (definition.type === 'deinflection') {
                                                 dictDefinitions.push({
                                                   type: 'simple',
-                                                  content: `Base form: ${definition.baseForm}`,
+                                                  content: `Base form: ${definition.baseForm}${definition.inflections.length ? ` (${definition.inflections.map(i => i.label).join(' « ')})` : ''}`,
                                                   dictionary_title: dictEntry.title,
                                                   dictionary_origin: dictEntry.origin
                                                 });
@@ -1742,31 +1748,16 @@ function SearchPane({
                                         // Audio data not available, fetch it now
                                         console.log('🎵 DEBUG: Audio data not loaded, fetching now...');
                                         try {
-                                          // Fetch audio directly instead of using the state-based fetchAudio function
-                                          const params = new URLSearchParams({ term });
-                                          if (reading) {
-                                            params.append('reading', reading);
-                                          }
-
-                                          const apiUrl = getBackendApiUrl();
-                                          const fullUrl = `${apiUrl}/api/audio?${params.toString()}`;
-                                          console.log('🎵 DEBUG: Making direct API call to:', fullUrl);
-
-                                          const supabaseClient = await ensureSupabaseClient();
-                                          const response = await fetch(fullUrl, {
-                                            headers: {
-                                              'Authorization': `Bearer ${(await supabaseClient.auth.getSession()).data.session?.access_token}`
-                                            }
-                                          });
+                                          // Not loaded with the other term groups yet, fetch it on its own
+                                          const [audioResult] = await backendService.getAudioBatch([{ term, reading }]) ?? [];
 
-                                          if (response.ok) {
-                                            const audioResponse: AudioResponse = await response.json();
-                                            console.log('🎵 DEBUG: Direct audio fetch successful:', audioResponse);
+                                          if (audioResult) {
+                                            console.log('🎵 DEBUG: Audio fetch successful:', audioResult);
 
                                             // Use the selected audio source or first if none selected
-                                            if (audioResponse.audioSources?.length > 0) {
+                                            if (audioResult.audioSources?.length > 0) {
                                               const selectedAudioSource = getSelectedAudioSource(
-                                                audioResponse.audioSources,
+                                                audioResult.audioSources,
                                                 selectedAudioByLayer,
                                                 currentSlideIndex,
                                                 index
@@ -1774,7 +1765,7 @@ function SearchPane({
                                               if (selectedAudioSource) {
                                                 const mediaPath = selectedAudioSource.url.replace('/audio/', '/media/');
                                                 expressionAudio = extractAudioUrlWithoutParams(mediaPath);
-                                                console.log('🎵 DEBUG: Audio URL processed from direct fetch:', {
+                                                console.log('🎵 DEBUG: Audio URL processed from fetch:', {
                                                   originalUrl: selectedAudioSource.url,
                                                   mediaPath,
                                                   finalExpressionAudio: expressionAudio
@@ -1782,7 +1773,7 @@ function SearchPane({
                                               }
                                             }
                                           } else {
-                                            console.error('🎵 DEBUG: Direct audio fetch failed:', response.status, response.statusText);
+                                            console.error('🎵 DEBUG: Audio fetch failed');
                                           }
                                         } catch (error) {
                                           console.error('🎵 DEBUG: Failed to fetch audio directly:', error);
@@ -1,9 +1,84 @@
-import type { LookupTermResponse } from '@/types/backend-types';
+import type { AudioBatchItem, AudioBatchResult, LookupTermResponse } from '@/types/backend-types';
 import { getBackendApiUrl } from '@/utils/api';
 import { getMetadata } from '@/utils/supabase/client';
 
+// Reader display settings stored with the account (GET/PUT /api/preferences/reader)
+export interface ReaderSettings {
+  fontFamily: string | null;
+  fontSize: number;
+  lineHeight: number;
+  verticalMargin: number;
+  writingMode: 'horizontal' | 'vertical';
+  theme: 'light' | 'dark' | 'asuka' | 'solarized-light' | 'solarized-dark';
+  customCss: string;
+}
+
+async function readerPreferencesRequest(init?: RequestInit): Promise<ReaderSettings | null> {
+  const metadata = await getMetadata();
+  if (!metadata.accessToken) {
+    return null;
+  }
+
+  try {
+    const response = await fetch(`${getBackendApiUrl()}/api/preferences/reader`, {
+      ...init,
+      headers: {
+        'Content-Type': 'application/json',
+        'Authorization': `Bearer ${metadata.accessToken}`,
+      },
+    });
+    if (!response.ok) {
+      throw new Error(`HTTP error! status: ${response.status}`);
+    }
+    const result = await response.json();
+    return result.reader;
+  } catch (error: any) {
+    console.log('❌ Reader preferences request failed:', error.message);
+    return null;
+  }
+}
+
 export const backendService = {
-  lookupTerm: async (term: string, position: number): Promise<LookupTermResponse | null> => {
+  // Audio sources for many terms in one request (POST /api/audio/batch), in
+  // the order of `items`. For lists of cards, instead of a request per card.
+  getAudioBatch: async (items: AudioBatchItem[]): Promise<AudioBatchResult[] | null> => {
+    if (items.length === 0) {
+      return [];
+    }
+    const metadata = await getMetadata();
+    const headers: Record<string, string> = {
+      'Content-Type': 'application/json',
+    };
+    if (metadata.accessToken) {
+      headers['Authorization'] = `Bearer ${metadata.accessToken}`;
+    }
+
+    try {
+      const response = await fetch(`${getBackendApiUrl()}/api/audio/batch`, {
+        method: 'POST',
+        headers,
+        body: JSON.stringify({ items }),
+      });
+      if (!response.ok) {
+        throw new Error(`HTTP error! status: ${response.status}`);
+      }
+      const result = await response.json();
+      return result.results;
+    } catch (error: any) {
+      console.log('❌ Batch audio request failed:', error.message);
+      return null;
+    }
+  },
+
+  getReaderPreferences: (): Promise<ReaderSettings | null> => readerPreferencesRequest(),
+
+  saveReaderPreferences: (settings: ReaderSettings): Promise<ReaderSettings | null> =>
+    readerPreferencesRequest({ method: 'PUT', body: JSON.stringify(settings) }),
+
+
+  // `book` is the upload id of the book being read, whose glossary overlays
+  // are consulted first
+  lookupTerm: async (term: string, position: number, book?: string): Promise<LookupTermResponse | null> => {
     console.log(`📤 Sending lookup request for term: "${term}" at position ${position}`);
     const metadata = await getMetadata();
 
@@ -22,7 +97,8 @@ export const backendService = {
         headers,
         body: JSON.stringify({
           term,
-          position
+          position,
+          ...(book ? { book } : {})
         })
       });
 
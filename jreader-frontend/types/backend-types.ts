@@ -1,6 +1,8 @@
 export interface LookupTermRequest {
     term: string;
     position: number;
+    // Upload id of the book being read, whose glossary overlays come first
+    book?: string;
   }
   
   export interface PitchAccentEntry {
@@ -23,6 +25,13 @@ export interface LookupTermRequest {
     reading: string | null;
     value: number | null;
     displayValue: string | null;
+    // Label of the user's frequency band the rank falls in
+    band?: string;
+  }
+
+  export interface FrequencyRank {
+    rank: number;
+    band: string | null;
   }
   
   export interface FrequencyDataList {
@@ -43,8 +52,15 @@ export interface LookupTermRequest {
     | {
         type: "deinflection";
         baseForm: string;
-        inflections: string[];
+        inflections: Inflection[];
       };
+
+  // One step of an inflection chain, e.g. passive or past
+  export interface Inflection {
+    reason: string;
+    localizationKey: string;
+    label: string;
+  }
   
   export interface TermEntry {
     text: string;
@@ -55,6 +71,12 @@ export interface LookupTermRequest {
     definitions: Definition[];
     sequenceNumber: number;
     termTags: string[];
+    // Set when found via the unknown word fallback rather than the tokenizer
+    heuristic?: boolean;
+    // The text the entry was found for, e.g. 食べられた for 食べる
+    matchedForm?: string;
+    // Inflections from the term to matchedForm, innermost first
+    inflections?: Inflection[];
   }
   
   export interface DictionaryResult {
@@ -62,10 +84,100 @@ export interface LookupTermRequest {
     revision: string;
     origin: string;
     entries: TermEntry[];
+    // Start collapsed, per the user's popup settings
+    collapsed?: boolean;
   }
   
   export interface LookupTermResponse {
     dictionaryResults: DictionaryResult[];
     pitchAccentResults: Record<string, PitchAccentResult>;
     frequencyDataLists: Record<string, FrequencyDataList>;
-  }
\ No newline at end of file
+    // Aggregate rank per term, only for users with frequency bands
+    frequencyRanks?: Record<string, FrequencyRank>;
+    relatedWords?: RelatedWord[];
+    // Other spellings and kana forms of the lexemes found
+    relatedEntries?: RelatedEntries[];
+    // The user's notes on the entries found, and notes others have shared
+    notes?: EntryNote[];
+    // JLPT level of the terms found and levels of their kanji
+    levels?: TermLevels[];
+    // The terms found that the user knows, e.g. synced from WaniKani
+    knownTerms?: string[];
+    // Recordings of the entries found, when requested with includeAudio
+    audio?: TermAudio[];
+  }
+
+  export interface TermAudio {
+    term: string;
+    reading: string;
+    audioSources: AudioSource[];
+  }
+
+  export type JlptLevel = 'N5' | 'N4' | 'N3' | 'N2' | 'N1';
+
+  export interface TermLevels {
+    term: string;
+    reading: string;
+    jlpt?: JlptLevel;
+    // The term's kanji that have a level, in order of appearance
+    kanji?: { kanji: string; jlpt?: JlptLevel; kanken?: string }[];
+  }
+
+  export interface EntryNote {
+    id: string;
+    dictionary: string;
+    term: string;
+    reading: string;
+    note: string;
+    isShared: boolean;
+    // Whether the note was written by the current user
+    isOwn: boolean;
+    createdAt: string;
+    updatedAt: string;
+  }
+
+  export interface RelatedEntries {
+    dictionary: string;
+    sequenceNumber: number;
+    // The terms of the results that belong to the lexeme
+    terms: string[];
+    variants: { text: string; reading: string }[];
+  }
+
+  export interface RelatedWord {
+    // The term of the results the word is related to
+    term: string;
+    related: string;
+    reading: string;
+    relation: 'transitivityPair';
+    transitivity: 'intransitive' | 'transitive';
+    dictionary: string;
+  }
+
+  export interface AudioSource {
+    name: string;
+    url: string;
+    durationMs?: number;
+    peaks?: number[];
+    // Gain to apply on playback for a consistent loudness across sources
+    gainDb?: number;
+    // The recording the user's autoplay rules picked, in lookup responses
+    autoplay?: boolean;
+    // Downstep position of the recorded accent, for sources that give it
+    pitch?: number;
+    // Whether the recording has the accent being studied
+    pitchMatch?: boolean;
+  }
+
+  export interface AudioBatchItem {
+    term: string;
+    reading?: string;
+  }
+
+  export interface AudioBatchResult extends AudioBatchItem {
+    audioSources: AudioSource[];
+  }
+
+  export interface AudioBatchResponse {
+    results: AudioBatchResult[];
+  }
@@ -0,0 +1,221 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Kinds of audio sources understood by local-audio-yomichan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceType {
+    Nhk,
+    AjtJp,
+    Forvo,
+    Jpod,
+    Ozk5,
+}
+
+/// A single entry of the `sources` array in config.json
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceConfig {
+    #[serde(rename = "type")]
+    pub source_type: SourceType,
+    pub id: String,
+    /// Directory of the source, relative to the audio files root
+    pub path: String,
+    pub display: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Parsed local-audio-yomichan config.json.
+///
+/// Sources are kept in file order, which is also their priority order.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AudioConfig {
+    pub sources: Vec<SourceConfig>,
+}
+
+/// The sources of a stock local-audio-yomichan collection, used when no
+/// config file is given
+const DEFAULT_CONFIG: &str = include_str!("../example-config.json");
+
+impl AudioConfig {
+    /// The stock sources that have a directory under `audio_files_path`
+    pub fn default_for(audio_files_path: &Path) -> Result<Self> {
+        let mut config = Self::from_json(DEFAULT_CONFIG)?;
+        config
+            .sources
+            .retain(|s| audio_files_path.join(&s.path).is_dir());
+        config.validate(audio_files_path)?;
+        Ok(config)
+    }
+
+    /// Read and parse a config file without checking it against the file system
+    pub fn load(config_path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(config_path)
+            .with_context(|| format!("Failed to read config file: {}", config_path.display()))?;
+        Self::from_json(&contents)
+            .with_context(|| format!("Invalid config file: {}", config_path.display()))
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self> {
+        // serde_json reports line/column and the offending key, which is precise
+        // enough for missing keys and unknown source types
+        let config: AudioConfig = serde_json::from_str(contents)?;
+        Ok(config)
+    }
+
+    /// Check that the config is usable against `audio_files_path`: ids must be
+    /// unique and every enabled source must point at an existing directory.
+    pub fn validate(&self, audio_files_path: &Path) -> Result<()> {
+        if self.sources.is_empty() {
+            anyhow::bail!("Config has no sources");
+        }
+
+        let mut seen_ids = HashSet::new();
+        for (i, source) in self.sources.iter().enumerate() {
+            if source.id.trim().is_empty() {
+                anyhow::bail!("sources[{i}]: \"id\" must not be empty");
+            }
+            if !seen_ids.insert(source.id.as_str()) {
+                anyhow::bail!("sources[{i}]: duplicate source id \"{}\"", source.id);
+            }
+            if source.display.trim().is_empty() {
+                anyhow::bail!(
+                    "sources[{i}] ({}): \"display\" must not be empty",
+                    source.id
+                );
+            }
+            if Path::new(&source.path).is_absolute() {
+                anyhow::bail!(
+                    "sources[{i}] ({}): \"path\" must be relative to the audio files directory, got {}",
+                    source.id,
+                    source.path
+                );
+            }
+
+            if !source.enabled {
+                continue;
+            }
+            let source_dir = audio_files_path.join(&source.path);
+            if !source_dir.is_dir() {
+                anyhow::bail!(
+                    "sources[{i}] ({}): directory does not exist: {}",
+                    source.id,
+                    source_dir.display()
+                );
+            }
+        }
+
+        if self.enabled_sources().next().is_none() {
+            anyhow::bail!("Config has no enabled sources");
+        }
+
+        Ok(())
+    }
+
+    /// Enabled sources in priority order
+    pub fn enabled_sources(&self) -> impl Iterator<Item = &SourceConfig> {
+        self.sources.iter().filter(|s| s.enabled)
+    }
+
+    /// Absolute directory for each enabled source, keyed by source id
+    pub fn source_dirs(&self, audio_files_path: &Path) -> Vec<(String, PathBuf)> {
+        self.enabled_sources()
+            .map(|s| (s.id.clone(), audio_files_path.join(&s.path)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_example_config() {
+        let config = AudioConfig::from_json(DEFAULT_CONFIG).unwrap();
+        assert_eq!(config.sources.len(), 6);
+        assert_eq!(config.sources[0].source_type, SourceType::Nhk);
+        assert_eq!(config.sources[1].source_type, SourceType::AjtJp);
+        assert!(config.sources.iter().all(|s| s.enabled));
+    }
+
+    #[test]
+    fn test_missing_key_is_reported() {
+        let err = AudioConfig::from_json(
+            r#"{"sources": [{"type": "nhk", "id": "nhk16", "display": "NHK"}]}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("missing field `path`"), "{err}");
+    }
+
+    #[test]
+    fn test_unknown_source_type_is_rejected() {
+        let err = AudioConfig::from_json(
+            r#"{"sources": [{"type": "nope", "id": "x", "path": "x", "display": "X"}]}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("unknown variant `nope`"), "{err}");
+    }
+
+    #[test]
+    fn test_validate_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("nhk16_files")).unwrap();
+
+        let config = AudioConfig::from_json(
+            r#"{"sources": [
+                {"type": "nhk", "id": "nhk16", "path": "nhk16_files", "display": "NHK16 %s"},
+                {"type": "forvo", "id": "forvo", "path": "forvo_files", "display": "Forvo (%s)"}
+            ]}"#,
+        )
+        .unwrap();
+        let err = config.validate(temp_dir.path()).unwrap_err();
+        assert!(err.to_string().contains("sources[1] (forvo)"), "{err}");
+
+        let config = AudioConfig::from_json(
+            r#"{"sources": [
+                {"type": "nhk", "id": "nhk16", "path": "nhk16_files", "display": "NHK16 %s"},
+                {"type": "forvo", "id": "forvo", "path": "forvo_files", "display": "Forvo (%s)", "enabled": false}
+            ]}"#,
+        )
+        .unwrap();
+        config.validate(temp_dir.path()).unwrap();
+        assert_eq!(config.source_dirs(temp_dir.path()).len(), 1);
+    }
+
+    #[test]
+    fn test_default_for_keeps_existing_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(AudioConfig::default_for(temp_dir.path()).is_err());
+
+        fs::create_dir_all(temp_dir.path().join("forvo_files")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("jpod_files")).unwrap();
+        let config = AudioConfig::default_for(temp_dir.path()).unwrap();
+        let ids: Vec<_> = config.sources.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, ["forvo", "jpod"]);
+    }
+
+    #[test]
+    fn test_duplicate_ids_are_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("jpod_files")).unwrap();
+
+        let config = AudioConfig::from_json(
+            r#"{"sources": [
+                {"type": "jpod", "id": "jpod", "path": "jpod_files", "display": "Jpod101"},
+                {"type": "jpod", "id": "jpod", "path": "jpod_files", "display": "Jpod101"}
+            ]}"#,
+        )
+        .unwrap();
+        let err = config.validate(temp_dir.path()).unwrap_err();
+        assert!(err.to_string().contains("duplicate source id"), "{err}");
+    }
+}
@@ -0,0 +1,240 @@
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// What to do with entries whose recording is identical to the recording of
+/// an earlier entry for the same expression
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DedupOptions {
+    /// Delete the duplicate entries, keeping the one of the highest priority source
+    pub remove: bool,
+    /// Write every duplicate to the `duplicates` table, replacing its contents
+    pub report: bool,
+}
+
+/// What a dedup pass found
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DedupSummary {
+    pub hashed: usize,
+    pub duplicates: usize,
+    pub removed: usize,
+}
+
+/// SHA-256 of the contents of an audio file, hex encoded
+pub fn hash_audio_file(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open audio file: {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("Failed to read audio file: {}", path.display()))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Hash the audio file of every entry and find the entries whose recording is
+/// byte for byte the same as that of an earlier entry for the same expression,
+/// e.g. one clip shipped by several merged sources.
+///
+/// Entries are compared in id order, which is source priority order, so the
+/// entry that is kept is the one queries already returned first. Depending on
+/// `options` the duplicates are deleted and/or listed in the `duplicates`
+/// table along with the id of the entry they duplicate. Entries whose file
+/// can't be read are skipped with a warning.
+///
+/// `source_dirs` maps a source id to its directory; sources not listed fall
+/// back to the `{source}_files` convention under `audio_files_path`.
+pub fn deduplicate_audio_entries(
+    db_path: &Path,
+    audio_files_path: &Path,
+    source_dirs: &HashMap<String, PathBuf>,
+    options: DedupOptions,
+) -> Result<DedupSummary> {
+    let mut conn = Connection::open(db_path)
+        .with_context(|| format!("Failed to open audio database: {}", db_path.display()))?;
+
+    let entries: Vec<(i64, String, String, String)> = {
+        let mut stmt =
+            conn.prepare("SELECT id, expression, source, file FROM entries ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    info!("Hashing {} audio files", entries.len());
+
+    let mut summary = DedupSummary::default();
+    // Files shared by several entries (e.g. spelling variants) are hashed once
+    let mut file_hashes: HashMap<PathBuf, Option<String>> = HashMap::new();
+    // First entry per (expression, content hash)
+    let mut kept: HashMap<(String, String), i64> = HashMap::new();
+    // (duplicate id, kept id, content hash)
+    let mut duplicates: Vec<(i64, i64, String)> = Vec::new();
+    for (i, (id, expression, source, file)) in entries.into_iter().enumerate() {
+        let path = crate::source_dir(audio_files_path, source_dirs, &source).join(&file);
+        let hash = match file_hashes.entry(path) {
+            Entry::Occupied(hash) => hash.get().clone(),
+            Entry::Vacant(slot) => {
+                let hash = match hash_audio_file(slot.key()) {
+                    Ok(hash) => {
+                        summary.hashed += 1;
+                        Some(hash)
+                    }
+                    Err(e) => {
+                        warn!("Failed to hash {}/{}: {:#}", source, file, e);
+                        None
+                    }
+                };
+                slot.insert(hash).clone()
+            }
+        };
+        if let Some(hash) = hash {
+            match kept.entry((expression, hash)) {
+                Entry::Occupied(first) => {
+                    duplicates.push((id, *first.get(), first.key().1.clone()));
+                }
+                Entry::Vacant(slot) => {
+                    slot.insert(id);
+                }
+            }
+        }
+        if (i + 1) % 10_000 == 0 {
+            info!("Hashed {} audio files", i + 1);
+        }
+    }
+    summary.duplicates = duplicates.len();
+
+    let tx = conn.transaction()?;
+    if options.report {
+        tx.execute_batch(
+            "DROP TABLE IF EXISTS duplicates;
+            CREATE TABLE duplicates (
+                entry_id INTEGER PRIMARY KEY,
+                kept_id INTEGER NOT NULL,
+                content_hash TEXT NOT NULL,
+                expression TEXT NOT NULL,
+                reading TEXT,
+                source TEXT NOT NULL,
+                speaker TEXT,
+                file TEXT NOT NULL
+            );
+            CREATE INDEX idx_duplicates_kept ON duplicates (kept_id);",
+        )?;
+        let mut insert = tx.prepare(
+            "INSERT INTO duplicates
+                (entry_id, kept_id, content_hash, expression, reading, source, speaker, file)
+             SELECT id, ?2, ?3, expression, reading, source, speaker, file
+             FROM entries WHERE id = ?1",
+        )?;
+        for (id, kept_id, hash) in &duplicates {
+            insert.execute(rusqlite::params![id, kept_id, hash])?;
+        }
+    }
+    if options.remove {
+        let mut delete = tx.prepare("DELETE FROM entries WHERE id = ?1")?;
+        for (id, _, _) in &duplicates {
+            summary.removed += delete.execute([id])?;
+        }
+    }
+    tx.commit()?;
+
+    info!(
+        "Found {} duplicate recordings, removed {}",
+        summary.duplicates, summary.removed
+    );
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_deduplicate_audio_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let audio_dir = temp_dir.path();
+        fs::create_dir_all(audio_dir.join("nhk16_files/audio")).unwrap();
+        fs::write(
+            audio_dir.join("nhk16_files/entries.json"),
+            r#"[{"kana": "たべる", "kanji": ["食べる"], "accents": [{"soundFile": "taberu.opus"}]},
+                {"kana": "のむ", "kanji": ["飲む"], "accents": [{"soundFile": "nomu.opus"}]}]"#,
+        )
+        .unwrap();
+        fs::write(audio_dir.join("nhk16_files/audio/taberu.opus"), b"taberu").unwrap();
+        fs::write(audio_dir.join("nhk16_files/audio/nomu.opus"), b"nomu").unwrap();
+        fs::create_dir_all(audio_dir.join("forvo_files/a")).unwrap();
+        fs::create_dir_all(audio_dir.join("forvo_files/b")).unwrap();
+        // Same clip as NHK's under another source, a different one, and the
+        // same bytes for another expression, which isn't a duplicate
+        fs::write(audio_dir.join("forvo_files/a/食べる.opus"), b"taberu").unwrap();
+        fs::write(
+            audio_dir.join("forvo_files/b/食べる.opus"),
+            b"taberu, slower",
+        )
+        .unwrap();
+        fs::write(audio_dir.join("forvo_files/a/飲む.opus"), b"taberu").unwrap();
+
+        let db_path = temp_dir.path().join("entries.db");
+        assert_eq!(
+            crate::bootstrap_audio_database_simple(audio_dir, &db_path).unwrap(),
+            5
+        );
+        let count = |table: &str| -> i64 {
+            Connection::open(&db_path)
+                .unwrap()
+                .query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |r| r.get(0))
+                .unwrap()
+        };
+
+        // Only reporting keeps the entries
+        let report = DedupOptions {
+            remove: false,
+            report: true,
+        };
+        let summary =
+            deduplicate_audio_entries(&db_path, audio_dir, &HashMap::new(), report).unwrap();
+        assert_eq!(
+            summary,
+            DedupSummary {
+                hashed: 5,
+                duplicates: 1,
+                removed: 0,
+            }
+        );
+        assert_eq!(count("entries"), 5);
+
+        let conn = Connection::open(&db_path).unwrap();
+        let (source, file, kept_source): (String, String, String) = conn
+            .query_row(
+                "SELECT d.source, d.file, e.source FROM duplicates d
+                 JOIN entries e ON e.id = d.kept_id",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            (source.as_str(), file.as_str(), kept_source.as_str()),
+            ("forvo", "a/食べる.opus", "nhk16")
+        );
+        drop(conn);
+
+        let remove = DedupOptions {
+            remove: true,
+            report: true,
+        };
+        let summary =
+            deduplicate_audio_entries(&db_path, audio_dir, &HashMap::new(), remove).unwrap();
+        assert_eq!(summary.removed, 1);
+        assert_eq!(count("entries"), 4);
+        assert_eq!(count("duplicates"), 1);
+        // Nothing left to remove on a second run
+        let summary =
+            deduplicate_audio_entries(&db_path, audio_dir, &HashMap::new(), remove).unwrap();
+        assert_eq!(summary.duplicates, 0);
+        assert_eq!(count("duplicates"), 0);
+    }
+}
@@ -1,77 +1,355 @@
+pub mod config;
+pub mod dedup;
+pub mod loudness;
+pub mod metadata;
+pub mod sources;
+pub mod validate;
+
 use anyhow::{Context, Result};
-use std::path::Path;
-use std::process::Command;
+use rusqlite::{Connection, OptionalExtension};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 use tracing::{debug, info};
 
+/// Size and modification time (ms since the epoch) of a file, recorded with
+/// its entries so an incremental bootstrap can tell when it changed. None for
+/// files that can't be read.
+fn file_stamp(path: &Path) -> (Option<i64>, Option<i64>) {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return (None, None);
+    };
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as i64);
+    (Some(metadata.len() as i64), mtime)
+}
+
+// The config at `config_path`, or the stock sources under `audio_files_path`
+fn load_config(audio_files_path: &Path, config_path: Option<&Path>) -> Result<config::AudioConfig> {
+    match config_path {
+        Some(config_path) => {
+            let config = config::AudioConfig::load(config_path)?;
+            config.validate(audio_files_path)?;
+            Ok(config)
+        }
+        None => config::AudioConfig::default_for(audio_files_path)
+            .with_context(|| format!("No audio sources found in {}", audio_files_path.display())),
+    }
+}
+
 /// Bootstrap the local-audio-yomichan SQLite database
 ///
-/// This function calls a Python script to initialize the database
-/// without requiring Anki to be running.
+/// Reads the index files and directories of every enabled source and writes
+/// them to the `entries` table, replacing any entries already there.
 ///
 /// # Arguments
 /// * `audio_files_path` - Path to the directory containing the audio files
 /// * `db_output_path` - Path where the SQLite database should be created
-/// * `config_path` - Optional path to a custom config.json file
+/// * `config_path` - Optional path to a custom config.json file; without one
+///   the stock sources that exist under `audio_files_path` are used
 ///
 /// # Returns
-/// * `Result<()>` - Success or error
+/// * `Result<usize>` - The number of entries written
 pub fn bootstrap_audio_database(
     audio_files_path: &Path,
     db_output_path: &Path,
     config_path: Option<&Path>,
-) -> Result<()> {
-    info!("Starting audio database bootstrap with Python script...");
+) -> Result<usize> {
+    info!("Starting audio database bootstrap...");
     debug!("Audio files path: {}", audio_files_path.display());
     debug!("Database output path: {}", db_output_path.display());
 
-    // Get the path to the bootstrap script
-    let script_path = std::env::current_dir()?.join("audio-db-bootstrap/bootstrap_script.py");
-    if !script_path.exists() {
-        anyhow::bail!("Bootstrap script not found at: {}", script_path.display());
-    }
+    let config = load_config(audio_files_path, config_path)?;
 
-    // Build the command
-    let mut cmd = Command::new("python3");
-    cmd.arg(script_path);
-    cmd.arg(audio_files_path);
-    cmd.arg(db_output_path);
+    let mut conn = Connection::open(db_output_path).with_context(|| {
+        format!(
+            "Failed to open audio database: {}",
+            db_output_path.display()
+        )
+    })?;
+    let tx = conn.transaction()?;
+    tx.execute_batch(
+        "DROP TABLE IF EXISTS entries;
+        CREATE TABLE entries (
+            id INTEGER PRIMARY KEY,
+            expression TEXT NOT NULL,
+            reading TEXT,
+            source TEXT NOT NULL,
+            speaker TEXT,
+            display TEXT,
+            file TEXT NOT NULL,
+            file_size INTEGER,
+            file_mtime INTEGER,
+            pitch INTEGER
+        );
+        CREATE INDEX idx_all ON entries (expression, reading, source);
+        CREATE INDEX idx_reading ON entries (reading);",
+    )?;
 
-    if let Some(config_path) = config_path {
-        cmd.arg(config_path);
+    let mut total = 0;
+    {
+        let mut insert = tx.prepare(
+            "INSERT INTO entries (expression, reading, source, speaker, display, file, file_size, file_mtime, pitch)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )?;
+        // Sources are inserted in priority order, which is the order queries return them in
+        for source in config.enabled_sources() {
+            let source_dir = audio_files_path.join(&source.path);
+            let entries = sources::read_source(source, &source_dir)?;
+            let mut stamps = HashMap::new();
+            for entry in &entries {
+                let (size, mtime) = *stamps
+                    .entry(entry.file.as_str())
+                    .or_insert_with(|| file_stamp(&source_dir.join(&entry.file)));
+                insert.execute(rusqlite::params![
+                    entry.expression,
+                    entry.reading,
+                    source.id,
+                    entry.speaker,
+                    entry.display,
+                    entry.file,
+                    size,
+                    mtime,
+                    entry.pitch
+                ])?;
+            }
+            info!("Added {} entries from {}", entries.len(), source.id);
+            total += entries.len();
+        }
     }
+    tx.commit()?;
 
-    // Set environment variable
-    cmd.env("WO_ANKI", "1");
+    info!(
+        "Successfully initialized audio database at: {}",
+        db_output_path.display()
+    );
+    Ok(total)
+}
 
-    debug!("Running command: {:?}", cmd);
+/// What an incremental bootstrap changed
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct IncrementalSummary {
+    pub added: usize,
+    /// Entries whose file changed, or whose display name or pitch did
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
 
-    // Execute the command
-    let output = cmd.output().context("Failed to execute bootstrap script")?;
+/// Bring an existing database up to date with the audio directory instead of
+/// rebuilding it: entries of new files are added, entries whose file changed
+/// (by size or modification time) are kept but lose their probed metadata so
+/// the metadata pass probes them again, and entries whose file is gone or
+/// whose source was disabled are removed. Unchanged entries keep their id and
+/// metadata.
+///
+/// New entries are appended, so their ids don't follow source priority order;
+/// rebuild from scratch after reordering sources. Databases from before file
+/// sizes were recorded have every file updated once. Without an existing
+/// `entries` table this is a full bootstrap.
+pub fn bootstrap_audio_database_incremental(
+    audio_files_path: &Path,
+    db_output_path: &Path,
+    config_path: Option<&Path>,
+) -> Result<IncrementalSummary> {
+    let config = load_config(audio_files_path, config_path)?;
 
-    // Print stdout and stderr
-    if !output.stdout.is_empty() {
-        info!("Script output: {}", String::from_utf8_lossy(&output.stdout));
+    let mut conn = Connection::open(db_output_path).with_context(|| {
+        format!(
+            "Failed to open audio database: {}",
+            db_output_path.display()
+        )
+    })?;
+    let has_entries = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'entries'",
+            [],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    if !has_entries {
+        drop(conn);
+        info!("No existing entries, running a full bootstrap");
+        let added = bootstrap_audio_database(audio_files_path, db_output_path, config_path)?;
+        return Ok(IncrementalSummary {
+            added,
+            ..Default::default()
+        });
     }
-    if !output.stderr.is_empty() {
-        info!("Script stderr: {}", String::from_utf8_lossy(&output.stderr));
+    metadata::ensure_metadata_columns(&conn)?;
+    ensure_entry_columns(&conn)?;
+
+    // (source, file, expression, reading, speaker) -> (id, (display, pitch), size, mtime)
+    type EntryKey = (String, String, String, Option<String>, Option<String>);
+    type ExistingEntry = (i64, (Option<String>, Option<i64>), Option<i64>, Option<i64>);
+    let existing: HashMap<EntryKey, ExistingEntry> = {
+        let mut stmt = conn.prepare(
+            "SELECT source, file, expression, reading, speaker, id, display, pitch, file_size, file_mtime
+             FROM entries",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                (
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ),
+                (
+                    row.get(5)?,
+                    (row.get(6)?, row.get(7)?),
+                    row.get(8)?,
+                    row.get(9)?,
+                ),
+            ))
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    info!(
+        "Comparing the audio directory with {} entries",
+        existing.len()
+    );
+
+    let mut summary = IncrementalSummary::default();
+    let mut seen: HashSet<i64> = HashSet::new();
+    let tx = conn.transaction()?;
+    {
+        let mut insert = tx.prepare(
+            "INSERT INTO entries (expression, reading, source, speaker, display, file, file_size, file_mtime, pitch)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )?;
+        let mut update_file = tx.prepare(
+            "UPDATE entries SET display = ?2, pitch = ?3, file_size = ?4, file_mtime = ?5,
+                 duration_ms = NULL, peaks = NULL, gain_db = NULL, loudness_target = NULL
+             WHERE id = ?1",
+        )?;
+        let mut update_info =
+            tx.prepare("UPDATE entries SET display = ?2, pitch = ?3 WHERE id = ?1")?;
+        for source in config.enabled_sources() {
+            let source_dir = audio_files_path.join(&source.path);
+            let entries = sources::read_source(source, &source_dir)?;
+            let mut stamps = HashMap::new();
+            let added = summary.added;
+            for entry in entries {
+                let (size, mtime) = *stamps
+                    .entry(entry.file.clone())
+                    .or_insert_with(|| file_stamp(&source_dir.join(&entry.file)));
+                let key = (
+                    source.id.clone(),
+                    entry.file,
+                    entry.expression,
+                    entry.reading,
+                    entry.speaker,
+                );
+                match existing.get(&key) {
+                    Some((id, (display, pitch), old_size, old_mtime)) => {
+                        // The same entry read twice, e.g. a headword listed twice
+                        if !seen.insert(*id) {
+                            continue;
+                        }
+                        if (*old_size, *old_mtime) != (size, mtime) {
+                            update_file.execute(rusqlite::params![
+                                id,
+                                entry.display,
+                                entry.pitch,
+                                size,
+                                mtime
+                            ])?;
+                            summary.updated += 1;
+                        } else if (display, pitch) != (&entry.display, &entry.pitch) {
+                            update_info.execute(rusqlite::params![
+                                id,
+                                entry.display,
+                                entry.pitch
+                            ])?;
+                            summary.updated += 1;
+                        } else {
+                            summary.unchanged += 1;
+                        }
+                    }
+                    None => {
+                        let (source_id, file, expression, reading, speaker) = key;
+                        insert.execute(rusqlite::params![
+                            expression,
+                            reading,
+                            source_id,
+                            speaker,
+                            entry.display,
+                            file,
+                            size,
+                            mtime,
+                            entry.pitch
+                        ])?;
+                        summary.added += 1;
+                    }
+                }
+            }
+            info!("Added {} entries from {}", summary.added - added, source.id);
+        }
+
+        let mut delete = tx.prepare("DELETE FROM entries WHERE id = ?1")?;
+        for (id, ..) in existing.values() {
+            if !seen.contains(id) {
+                summary.removed += delete.execute([id])?;
+            }
+        }
     }
+    tx.commit()?;
 
-    if output.status.success() {
-        info!(
-            "Successfully initialized audio database at: {}",
-            db_output_path.display()
-        );
-        Ok(())
-    } else {
-        anyhow::bail!("Bootstrap script failed with exit code: {}", output.status);
+    info!(
+        "Updated audio database at {}: {} added, {} updated, {} removed, {} unchanged",
+        db_output_path.display(),
+        summary.added,
+        summary.updated,
+        summary.removed,
+        summary.unchanged
+    );
+    Ok(summary)
+}
+
+// Add the file size, modification time and pitch columns to an entries table
+// from before they existed
+fn ensure_entry_columns(conn: &Connection) -> Result<()> {
+    let mut stmt = conn.prepare("PRAGMA table_info(entries)")?;
+    let columns: Vec<String> = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    if !columns.iter().any(|c| c == "file_size") {
+        conn.execute("ALTER TABLE entries ADD COLUMN file_size INTEGER", [])?;
+    }
+    if !columns.iter().any(|c| c == "file_mtime") {
+        conn.execute("ALTER TABLE entries ADD COLUMN file_mtime INTEGER", [])?;
+    }
+    if !columns.iter().any(|c| c == "pitch") {
+        conn.execute("ALTER TABLE entries ADD COLUMN pitch INTEGER", [])?;
     }
+    Ok(())
+}
+
+/// Directory of `source`: the one in `source_dirs`, or `{source}_files` under
+/// `audio_files_path` for sources not listed there
+pub(crate) fn source_dir(
+    audio_files_path: &Path,
+    source_dirs: &HashMap<String, PathBuf>,
+    source: &str,
+) -> PathBuf {
+    source_dirs
+        .get(source)
+        .cloned()
+        .unwrap_or_else(|| audio_files_path.join(format!("{source}_files")))
 }
 
 /// Simple wrapper to bootstrap the database with default settings
 pub fn bootstrap_audio_database_simple(
     audio_files_path: &Path,
     db_output_path: &Path,
-) -> Result<()> {
+) -> Result<usize> {
     bootstrap_audio_database(audio_files_path, db_output_path, None)
 }
 
@@ -81,23 +359,141 @@ mod tests {
     use std::fs;
     use tempfile::TempDir;
 
+    // expression, reading, source, display, file
+    type Row = (String, Option<String>, String, Option<String>, String);
+
     #[test]
     fn test_bootstrap_audio_database() {
-        // Create temporary directories for testing
         let temp_dir = TempDir::new().unwrap();
         let audio_dir = temp_dir.path().join("audio_files");
         let db_path = temp_dir.path().join("entries.db");
 
-        // Create the audio directory
+        // No stock source directories
         fs::create_dir_all(&audio_dir).unwrap();
+        assert!(bootstrap_audio_database_simple(&audio_dir, &db_path).is_err());
+
+        fs::create_dir_all(audio_dir.join("nhk16_files")).unwrap();
+        fs::write(
+            audio_dir.join("nhk16_files/entries.json"),
+            r#"[{"kana": "たべる", "kanji": ["食べる"], "accents": [{"soundFile": "taberu.opus"}]}]"#,
+        )
+        .unwrap();
+        fs::create_dir_all(audio_dir.join("forvo_files/speaker")).unwrap();
+        fs::write(audio_dir.join("forvo_files/speaker/食べる.opus"), b"").unwrap();
+
+        assert_eq!(
+            bootstrap_audio_database_simple(&audio_dir, &db_path).unwrap(),
+            2
+        );
+        // Re-running replaces the entries instead of adding to them
+        assert_eq!(
+            bootstrap_audio_database_simple(&audio_dir, &db_path).unwrap(),
+            2
+        );
+
+        let conn = Connection::open(&db_path).unwrap();
+        let rows: Vec<Row> = conn
+            .prepare("SELECT expression, reading, source, display, file FROM entries ORDER BY id")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            [
+                (
+                    "食べる".to_string(),
+                    Some("たべる".to_string()),
+                    "nhk16".to_string(),
+                    Some("NHK16 たべる".to_string()),
+                    "audio/taberu.opus".to_string()
+                ),
+                (
+                    "食べる".to_string(),
+                    None,
+                    "forvo".to_string(),
+                    None,
+                    "speaker/食べる.opus".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bootstrap_audio_database_incremental() {
+        let temp_dir = TempDir::new().unwrap();
+        let audio_dir = temp_dir.path().join("audio_files");
+        let db_path = temp_dir.path().join("entries.db");
+        fs::create_dir_all(audio_dir.join("nhk16_files/audio")).unwrap();
+        fs::write(
+            audio_dir.join("nhk16_files/entries.json"),
+            r#"[{"kana": "たべる", "kanji": ["食べる"], "accents": [{"soundFile": "taberu.opus"}]}]"#,
+        )
+        .unwrap();
+        fs::write(audio_dir.join("nhk16_files/audio/taberu.opus"), b"a").unwrap();
+        fs::create_dir_all(audio_dir.join("forvo_files/a")).unwrap();
+        fs::write(audio_dir.join("forvo_files/a/食べる.opus"), b"a").unwrap();
+        fs::write(audio_dir.join("forvo_files/a/来る.opus"), b"a").unwrap();
+
+        // Without a database it's a full bootstrap
+        let summary = bootstrap_audio_database_incremental(&audio_dir, &db_path, None).unwrap();
+        assert_eq!(summary.added, 3);
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "ALTER TABLE entries ADD COLUMN duration_ms INTEGER;
+             UPDATE entries SET duration_ms = 100;",
+        )
+        .unwrap();
+        let id_of = |conn: &Connection, expression: &str, source: &str| -> Option<i64> {
+            conn.query_row(
+                "SELECT id FROM entries WHERE expression = ?1 AND source = ?2",
+                [expression, source],
+                |row| row.get(0),
+            )
+            .optional()
+            .unwrap()
+        };
+        let kuru_id = id_of(&conn, "来る", "forvo");
+
+        fs::write(audio_dir.join("nhk16_files/audio/taberu.opus"), b"abc").unwrap();
+        fs::remove_file(audio_dir.join("forvo_files/a/食べる.opus")).unwrap();
+        fs::write(audio_dir.join("forvo_files/a/飲む.opus"), b"a").unwrap();
+        let summary = bootstrap_audio_database_incremental(&audio_dir, &db_path, None).unwrap();
+        assert_eq!(
+            summary,
+            IncrementalSummary {
+                added: 1,
+                updated: 1,
+                removed: 1,
+                unchanged: 1,
+            }
+        );
 
-        // This test would require the actual local-audio-yomichan Python package
-        // to be installed and the audio files to be present
-        // For now, we'll just test that the function doesn't panic
-        let result = bootstrap_audio_database_simple(&audio_dir, &db_path);
+        let duration = |expression: &str, source: &str| -> Option<i64> {
+            conn.query_row(
+                "SELECT duration_ms FROM entries WHERE expression = ?1 AND source = ?2",
+                [expression, source],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        // The changed file is probed again, the unchanged one keeps its id and metadata
+        assert_eq!(duration("食べる", "nhk16"), None);
+        assert_eq!(duration("来る", "forvo"), Some(100));
+        assert_eq!(id_of(&conn, "来る", "forvo"), kuru_id);
+        assert!(id_of(&conn, "食べる", "forvo").is_none());
+        assert!(id_of(&conn, "飲む", "forvo").is_some());
 
-        // The test will likely fail without the actual Python package,
-        // but we can at least verify the function signature is correct
-        assert!(result.is_err() || result.is_ok());
+        let summary = bootstrap_audio_database_incremental(&audio_dir, &db_path, None).unwrap();
+        assert_eq!(summary.unchanged, 3);
     }
 }
@@ -0,0 +1,170 @@
+//! Integrated loudness measurement following ITU-R BS.1770 / EBU R128.
+//!
+//! Only what the bootstrap needs: K-weighting, 400ms gating blocks and the
+//! absolute/relative gates. Channel weights for surround layouts are ignored
+//! since the audio collections are mono or stereo.
+
+use std::f64::consts::PI;
+
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+// Second-order IIR filter in direct form I
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[1] * self.y[0]
+            - self.a[2] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+// The two K-weighting stages (high shelf, then high pass), with coefficients
+// derived for an arbitrary sample rate as in libebur128
+fn k_weighting_filters(sample_rate: u32) -> (Biquad, Biquad) {
+    let rate = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    (shelf, high_pass)
+}
+
+fn block_loudness(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+/// Integrated loudness in LUFS of planar `channels` sampled at `sample_rate`.
+///
+/// Returns `None` for silence (everything below the absolute gate) or empty input.
+/// Clips shorter than one 400ms block are measured as a single block.
+pub fn integrated_loudness(channels: &[Vec<f32>], sample_rate: u32) -> Option<f64> {
+    let num_frames = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+    if num_frames == 0 || sample_rate == 0 {
+        return None;
+    }
+
+    // Squared K-weighted samples per channel
+    let weighted: Vec<Vec<f64>> = channels
+        .iter()
+        .map(|channel| {
+            let (mut shelf, mut high_pass) = k_weighting_filters(sample_rate);
+            channel[..num_frames]
+                .iter()
+                .map(|s| {
+                    let y = high_pass.process(shelf.process(*s as f64));
+                    y * y
+                })
+                .collect()
+        })
+        .collect();
+
+    // 400ms blocks with 75% overlap
+    let block_len = ((sample_rate as f64 * 0.4).round() as usize).min(num_frames);
+    let step = (block_len / 4).max(1);
+    let mut block_powers = Vec::new();
+    let mut start = 0;
+    while start + block_len <= num_frames {
+        let power: f64 = weighted
+            .iter()
+            .map(|w| w[start..start + block_len].iter().sum::<f64>() / block_len as f64)
+            .sum();
+        block_powers.push(power);
+        start += step;
+    }
+
+    let gated_mean = |threshold: f64| {
+        let gated: Vec<f64> = block_powers
+            .iter()
+            .copied()
+            .filter(|p| *p > 0.0 && block_loudness(*p) > threshold)
+            .collect();
+        (!gated.is_empty()).then(|| gated.iter().sum::<f64>() / gated.len() as f64)
+    };
+
+    let absolute_mean = gated_mean(ABSOLUTE_GATE_LUFS)?;
+    let relative_gate = block_loudness(absolute_mean) + RELATIVE_GATE_LU;
+    gated_mean(relative_gate).map(block_loudness)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f64, amplitude: f64, sample_rate: u32, seconds: f64) -> Vec<f32> {
+        let num_samples = (sample_rate as f64 * seconds) as usize;
+        (0..num_samples)
+            .map(|n| (amplitude * (2.0 * PI * freq * n as f64 / sample_rate as f64).sin()) as f32)
+            .collect()
+    }
+
+    #[test]
+    fn test_reference_tone() {
+        // EBU Tech 3341: a stereo 1kHz sine at -23 dBFS measures -23 LUFS
+        let amplitude = 10f64.powf(-23.0 / 20.0);
+        for sample_rate in [44100, 48000] {
+            let tone = sine(1000.0, amplitude, sample_rate, 2.0);
+            let loudness = integrated_loudness(&[tone.clone(), tone], sample_rate).unwrap();
+            assert!((loudness + 23.0).abs() < 0.1, "{sample_rate}: {loudness}");
+        }
+    }
+
+    #[test]
+    fn test_louder_signal_measures_louder() {
+        let quiet = sine(440.0, 0.1, 48000, 1.0);
+        let loud = sine(440.0, 0.4, 48000, 1.0);
+        let quiet = integrated_loudness(&[quiet], 48000).unwrap();
+        let loud = integrated_loudness(&[loud], 48000).unwrap();
+        // 4x amplitude is ~12dB
+        assert!(((loud - quiet) - 12.04).abs() < 0.1, "{quiet} {loud}");
+    }
+
+    #[test]
+    fn test_silence_and_short_clips() {
+        assert_eq!(integrated_loudness(&[vec![0.0; 48000]], 48000), None);
+        assert_eq!(integrated_loudness(&[], 48000), None);
+        // Shorter than a 400ms block still gets a measurement
+        let short = sine(1000.0, 0.5, 48000, 0.2);
+        assert!(integrated_loudness(&[short], 48000).is_some());
+    }
+}
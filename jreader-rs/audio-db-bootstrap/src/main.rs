@@ -1,7 +1,8 @@
 use anyhow::{Context, Result};
 use clap::Parser;
+use std::collections::HashMap;
 use std::path::PathBuf;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[derive(Parser)]
 #[command(name = "audio-db-bootstrap")]
@@ -19,6 +20,40 @@ struct Args {
     #[arg(short, long)]
     config: Option<PathBuf>,
 
+    /// Update the existing database with new, changed and removed files
+    /// instead of rebuilding it, keeping the metadata of unchanged entries
+    #[arg(long)]
+    incremental: bool,
+
+    /// Check the existing database instead of building one: report entries
+    /// whose file is missing and audio files no entry points at. Fails if any
+    /// file is missing
+    #[arg(long)]
+    validate: bool,
+
+    /// Hash the audio files and remove entries whose recording is identical to
+    /// that of a higher priority entry for the same expression
+    #[arg(long)]
+    dedup: bool,
+
+    /// Write the duplicate recordings found to a `duplicates` table. Without
+    /// --dedup they are only reported, not removed
+    #[arg(long)]
+    duplicates_report: bool,
+
+    /// Skip probing audio files for duration/waveform metadata
+    #[arg(long)]
+    skip_metadata: bool,
+
+    /// Number of peak buckets to store per file for waveform display (0 disables)
+    #[arg(long, default_value_t = 0)]
+    waveform_buckets: usize,
+
+    /// Measure loudness (EBU R128) and store the gain needed to reach this
+    /// target, e.g. -16 (LUFS). Disabled by default
+    #[arg(long, allow_hyphen_values = true)]
+    loudness_target: Option<f64>,
+
     /// Verbose output
     #[arg(short, long)]
     verbose: bool,
@@ -58,6 +93,46 @@ fn main() -> Result<()> {
         );
     }
 
+    // Use the source directories from the config when one is given
+    let source_dirs: HashMap<String, PathBuf> = match &args.config {
+        Some(config_path) => audio_db_bootstrap::config::AudioConfig::load(config_path)?
+            .source_dirs(&args.audio_files)
+            .into_iter()
+            .collect(),
+        None => HashMap::new(),
+    };
+
+    if args.validate {
+        let report = audio_db_bootstrap::validate::validate_audio_database(
+            &args.output,
+            &args.audio_files,
+            &source_dirs,
+        )
+        .context("Failed to validate audio database")?;
+        for missing in &report.missing {
+            warn!(
+                "Missing file for entry {} ({}): {} in source {}",
+                missing.id, missing.expression, missing.file, missing.source
+            );
+        }
+        for path in &report.orphaned {
+            warn!("Orphaned file: {}", path.display());
+        }
+        if !report.is_ok() {
+            anyhow::bail!(
+                "{} of {} entries point at missing files",
+                report.missing.len(),
+                report.entries
+            );
+        }
+        info!(
+            "✅ All {} entries have their file, {} orphaned files",
+            report.entries,
+            report.orphaned.len()
+        );
+        return Ok(());
+    }
+
     // Create output directory if it doesn't exist
     if let Some(parent) = args.output.parent() {
         if !parent.exists() {
@@ -66,21 +141,72 @@ fn main() -> Result<()> {
     }
 
     // Bootstrap the database
-    match audio_db_bootstrap::bootstrap_audio_database(
-        &args.audio_files,
-        &args.output,
-        args.config.as_deref(),
-    ) {
-        Ok(()) => {
-            info!(
-                "✅ Successfully created audio database at: {}",
-                args.output.display()
-            );
-            Ok(())
-        }
-        Err(e) => {
-            error!("❌ Failed to create audio database: {}", e);
-            Err(e)
+    if args.incremental {
+        let summary = audio_db_bootstrap::bootstrap_audio_database_incremental(
+            &args.audio_files,
+            &args.output,
+            args.config.as_deref(),
+        )
+        .context("Failed to update audio database")?;
+        info!(
+            "✅ Updated audio database at {}: {} added, {} updated, {} removed",
+            args.output.display(),
+            summary.added,
+            summary.updated,
+            summary.removed
+        );
+    } else {
+        match audio_db_bootstrap::bootstrap_audio_database(
+            &args.audio_files,
+            &args.output,
+            args.config.as_deref(),
+        ) {
+            Ok(count) => {
+                info!(
+                    "✅ Successfully created audio database with {} entries at: {}",
+                    count,
+                    args.output.display()
+                );
+            }
+            Err(e) => {
+                error!("❌ Failed to create audio database: {}", e);
+                return Err(e);
+            }
         }
     }
+
+    // Before the metadata pass, so removed duplicates aren't probed
+    if args.dedup || args.duplicates_report {
+        let summary = audio_db_bootstrap::dedup::deduplicate_audio_entries(
+            &args.output,
+            &args.audio_files,
+            &source_dirs,
+            audio_db_bootstrap::dedup::DedupOptions {
+                remove: args.dedup,
+                report: args.duplicates_report,
+            },
+        )
+        .context("Failed to deduplicate audio entries")?;
+        info!(
+            "✅ Found {} duplicate recordings in {} files, removed {}",
+            summary.duplicates, summary.hashed, summary.removed
+        );
+    }
+
+    if args.skip_metadata {
+        return Ok(());
+    }
+
+    let waveform_buckets = (args.waveform_buckets > 0).then_some(args.waveform_buckets);
+    let annotated = audio_db_bootstrap::metadata::annotate_audio_metadata(
+        &args.output,
+        &args.audio_files,
+        &source_dirs,
+        waveform_buckets,
+        args.loudness_target,
+    )
+    .context("Failed to probe audio metadata")?;
+    info!("✅ Stored duration metadata for {} entries", annotated);
+
+    Ok(())
 }
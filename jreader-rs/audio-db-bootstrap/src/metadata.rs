@@ -0,0 +1,520 @@
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use tracing::{debug, info, warn};
+
+/// Metadata probed from a single audio file
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioMetadata {
+    pub duration_ms: Option<i64>,
+    /// Peak amplitude per bucket scaled to 0-255, only present when requested
+    /// and the codec can be decoded
+    pub peaks: Option<Vec<u8>>,
+    /// Integrated loudness (EBU R128), only present when requested and the
+    /// codec can be decoded
+    pub loudness_lufs: Option<f64>,
+}
+
+/// What to compute beyond the duration. Both options require decoding the file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProbeOptions {
+    pub waveform_buckets: Option<usize>,
+    pub loudness: bool,
+}
+
+impl ProbeOptions {
+    fn needs_decoding(&self) -> bool {
+        self.waveform_buckets.is_some_and(|b| b > 0) || self.loudness
+    }
+}
+
+/// Probe an audio file for its duration and, depending on `options`, a coarse
+/// peak waveform and its integrated loudness.
+///
+/// Duration comes from the container when it is known there (e.g. Ogg Opus),
+/// so codecs symphonia can't decode still get a duration, just nothing else.
+pub fn probe_audio_file(path: &Path, options: &ProbeOptions) -> Result<AudioMetadata> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open audio file: {}", path.display()))?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .with_context(|| format!("Unsupported audio format: {}", path.display()))?;
+    let mut format = probed.format;
+
+    let track = format
+        .default_track()
+        .ok_or_else(|| anyhow::anyhow!("No audio track in {}", path.display()))?;
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+
+    let container_duration_ms = match (params.n_frames, params.time_base, params.sample_rate) {
+        (Some(n_frames), Some(time_base), _) => {
+            let time = time_base.calc_time(n_frames);
+            Some(time.seconds as i64 * 1000 + (time.frac * 1000.0).round() as i64)
+        }
+        (Some(n_frames), None, Some(sample_rate)) => {
+            Some((n_frames as f64 / sample_rate as f64 * 1000.0).round() as i64)
+        }
+        _ => None,
+    };
+    let container_only = AudioMetadata {
+        duration_ms: container_duration_ms,
+        peaks: None,
+        loudness_lufs: None,
+    };
+
+    if !options.needs_decoding() {
+        return Ok(container_only);
+    }
+
+    let mut decoder =
+        match symphonia::default::get_codecs().make(&params, &DecoderOptions::default()) {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                debug!(
+                    "No decoder for {}, skipping waveform and loudness: {}",
+                    path.display(),
+                    e
+                );
+                return Ok(container_only);
+            }
+        };
+
+    // Decoded samples, one Vec per channel
+    let mut channels: Vec<Vec<f32>> = Vec::new();
+    let mut sample_rate = params.sample_rate;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(e) => return Err(e).context(format!("Failed to read {}", path.display())),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(e)) => {
+                debug!("Skipping undecodable packet in {}: {}", path.display(), e);
+                continue;
+            }
+            Err(e) => return Err(e).context(format!("Failed to decode {}", path.display())),
+        };
+        let spec = *decoded.spec();
+        sample_rate.get_or_insert(spec.rate);
+        let num_channels = spec.channels.count().max(1);
+        if channels.len() < num_channels {
+            channels.resize(num_channels, Vec::new());
+        }
+
+        let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        samples.copy_interleaved_ref(decoded);
+        for frame in samples.samples().chunks(num_channels) {
+            for (channel, sample) in channels.iter_mut().zip(frame) {
+                channel.push(*sample);
+            }
+        }
+    }
+
+    let num_frames = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+    let sample_rate = sample_rate.unwrap_or(0);
+    let decoded_duration_ms =
+        (sample_rate > 0).then(|| (num_frames as f64 / sample_rate as f64 * 1000.0).round() as i64);
+
+    let peaks = options.waveform_buckets.filter(|b| *b > 0).map(|buckets| {
+        // Channels mixed down by taking the max absolute amplitude per frame
+        let amplitudes: Vec<f32> = (0..num_frames)
+            .map(|i| channels.iter().fold(0f32, |acc, c| acc.max(c[i].abs())))
+            .collect();
+        bucket_peaks(&amplitudes, buckets)
+    });
+    let loudness_lufs = if options.loudness {
+        crate::loudness::integrated_loudness(&channels, sample_rate)
+    } else {
+        None
+    };
+
+    Ok(AudioMetadata {
+        duration_ms: container_duration_ms.or(decoded_duration_ms),
+        peaks,
+        loudness_lufs,
+    })
+}
+
+// Reduce per-frame amplitudes to `buckets` peak values scaled to 0-255
+fn bucket_peaks(amplitudes: &[f32], buckets: usize) -> Vec<u8> {
+    if amplitudes.is_empty() {
+        return vec![0; buckets];
+    }
+    (0..buckets)
+        .map(|i| {
+            let start = i * amplitudes.len() / buckets;
+            let end = ((i + 1) * amplitudes.len() / buckets).max(start + 1);
+            let peak = amplitudes[start..end.min(amplitudes.len())]
+                .iter()
+                .fold(0f32, |acc, a| acc.max(*a));
+            (peak.clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect()
+}
+
+// Add the metadata columns to an existing entries table if they are missing
+pub(crate) fn ensure_metadata_columns(conn: &Connection) -> Result<()> {
+    let mut stmt = conn.prepare("PRAGMA table_info(entries)")?;
+    let columns: Vec<String> = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    if !columns.iter().any(|c| c == "duration_ms") {
+        conn.execute("ALTER TABLE entries ADD COLUMN duration_ms INTEGER", [])?;
+    }
+    if !columns.iter().any(|c| c == "peaks") {
+        conn.execute("ALTER TABLE entries ADD COLUMN peaks BLOB", [])?;
+    }
+    if !columns.iter().any(|c| c == "gain_db") {
+        conn.execute("ALTER TABLE entries ADD COLUMN gain_db REAL", [])?;
+    }
+    if !columns.iter().any(|c| c == "loudness_target") {
+        conn.execute("ALTER TABLE entries ADD COLUMN loudness_target REAL", [])?;
+    }
+    Ok(())
+}
+
+/// Probe every entry of the audio database that has no duration yet and store
+/// its duration, optional waveform and optional loudness gain in the
+/// `duration_ms` / `peaks` / `gain_db` columns.
+///
+/// `gain_db` is the gain that brings the entry to `loudness_target` LUFS; it is
+/// only computed when a target is given, and then also for entries annotated
+/// before without one or with a different target. The target is recorded in
+/// the `loudness_target` column even when the loudness can't be measured, so
+/// such entries aren't decoded again until the target changes. Durations and
+/// waveforms already stored are kept if a probe doesn't produce them.
+///
+/// `source_dirs` maps a source id to its directory; sources not listed fall
+/// back to the `{source}_files` convention under `audio_files_path`.
+///
+/// Returns the number of entries that were annotated.
+pub fn annotate_audio_metadata(
+    db_path: &Path,
+    audio_files_path: &Path,
+    source_dirs: &HashMap<String, PathBuf>,
+    waveform_buckets: Option<usize>,
+    loudness_target: Option<f64>,
+) -> Result<usize> {
+    let mut conn = Connection::open(db_path)
+        .with_context(|| format!("Failed to open audio database: {}", db_path.display()))?;
+    ensure_metadata_columns(&conn)?;
+
+    let pending: Vec<(i64, String, String)> = {
+        let mut stmt = conn.prepare(
+            "SELECT id, source, file FROM entries
+             WHERE duration_ms IS NULL OR (?1 IS NOT NULL AND loudness_target IS NOT ?1)",
+        )?;
+        let rows = stmt.query_map([loudness_target], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    info!("Probing {} audio files for metadata", pending.len());
+
+    let options = ProbeOptions {
+        waveform_buckets,
+        loudness: loudness_target.is_some(),
+    };
+    let tx = conn.transaction()?;
+    let mut annotated = 0;
+    {
+        let mut update = tx.prepare(
+            "UPDATE entries SET duration_ms = COALESCE(?1, duration_ms),
+                peaks = COALESCE(?2, peaks),
+                gain_db = CASE WHEN ?4 IS NULL THEN gain_db ELSE ?3 END,
+                loudness_target = COALESCE(?4, loudness_target) WHERE id = ?5",
+        )?;
+        for (i, (id, source, file)) in pending.iter().enumerate() {
+            let source_dir = crate::source_dir(audio_files_path, source_dirs, source);
+            match probe_audio_file(&source_dir.join(file), &options) {
+                Ok(metadata) => {
+                    let gain_db = loudness_target
+                        .zip(metadata.loudness_lufs)
+                        .map(|(target, loudness)| target - loudness);
+                    update.execute(rusqlite::params![
+                        metadata.duration_ms,
+                        metadata.peaks,
+                        gain_db,
+                        loudness_target,
+                        id
+                    ])?;
+                    annotated += 1;
+                }
+                Err(e) => warn!("Failed to probe {}/{}: {:#}", source, file, e),
+            }
+            if (i + 1) % 10_000 == 0 {
+                info!("Probed {}/{} audio files", i + 1, pending.len());
+            }
+        }
+    }
+    tx.commit()?;
+
+    info!("Annotated {} audio entries with metadata", annotated);
+    Ok(annotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    // Write a mono 16-bit PCM WAV file containing a 440Hz tone at half volume
+    fn write_test_wav(path: &Path, sample_rate: u32, num_samples: u32) {
+        let data_len = num_samples * 2;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        bytes.extend_from_slice(&1u16.to_le_bytes()); // mono
+        bytes.extend_from_slice(&sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        for n in 0..num_samples {
+            let t = n as f32 / sample_rate as f32;
+            let sample =
+                (0.5 * (2.0 * std::f32::consts::PI * 440.0 * t).sin() * i16::MAX as f32) as i16;
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_probe_wav_duration_and_peaks() {
+        let temp_dir = TempDir::new().unwrap();
+        let wav_path = temp_dir.path().join("tone.wav");
+        write_test_wav(&wav_path, 8000, 4000);
+
+        let metadata = probe_audio_file(&wav_path, &ProbeOptions::default()).unwrap();
+        assert_eq!(metadata.duration_ms, Some(500));
+        assert_eq!(metadata.peaks, None);
+        assert_eq!(metadata.loudness_lufs, None);
+
+        let options = ProbeOptions {
+            waveform_buckets: Some(10),
+            loudness: true,
+        };
+        let metadata = probe_audio_file(&wav_path, &options).unwrap();
+        assert_eq!(metadata.duration_ms, Some(500));
+        let peaks = metadata.peaks.unwrap();
+        assert_eq!(peaks.len(), 10);
+        assert!(peaks.iter().all(|p| (120..=135).contains(p)), "{peaks:?}");
+        // Half-scale mono tone: about -9 LUFS
+        let loudness = metadata.loudness_lufs.unwrap();
+        assert!((-10.0..-8.0).contains(&loudness), "{loudness}");
+    }
+
+    #[test]
+    fn test_bucket_peaks() {
+        assert_eq!(bucket_peaks(&[], 3), vec![0, 0, 0]);
+        assert_eq!(bucket_peaks(&[0.0, 1.0, 0.5, 0.25], 2), vec![255, 128]);
+        // More buckets than samples must not panic
+        assert_eq!(bucket_peaks(&[1.0], 2), vec![255, 255]);
+    }
+
+    #[test]
+    fn test_annotate_audio_metadata() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("nhk16_files");
+        std::fs::create_dir_all(&source_dir).unwrap();
+        write_test_wav(&source_dir.join("a.wav"), 8000, 8000);
+
+        let db_path = temp_dir.path().join("entries.db");
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE entries (id INTEGER PRIMARY KEY, expression TEXT, reading TEXT,
+                source TEXT, speaker TEXT, display TEXT, file TEXT);
+             INSERT INTO entries (expression, source, file) VALUES ('あ', 'nhk16', 'a.wav');
+             INSERT INTO entries (expression, source, file) VALUES ('い', 'nhk16', 'missing.wav');",
+        )
+        .unwrap();
+        drop(conn);
+
+        let annotated = annotate_audio_metadata(
+            &db_path,
+            temp_dir.path(),
+            &HashMap::new(),
+            Some(4),
+            Some(-16.0),
+        )
+        .unwrap();
+        assert_eq!(annotated, 1);
+
+        let conn = Connection::open(&db_path).unwrap();
+        let (duration, peaks, gain_db): (Option<i64>, Option<Vec<u8>>, Option<f64>) = conn
+            .query_row(
+                "SELECT duration_ms, peaks, gain_db FROM entries WHERE file = 'a.wav'",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(duration, Some(1000));
+        assert_eq!(peaks.map(|p| p.len()), Some(4));
+        // The half-scale tone is louder than the target, so it gets turned down
+        assert!(gain_db.unwrap() < -5.0, "{gain_db:?}");
+    }
+
+    #[test]
+    fn test_annotate_gain_after_earlier_pass() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("nhk16_files");
+        std::fs::create_dir_all(&source_dir).unwrap();
+        write_test_wav(&source_dir.join("a.wav"), 8000, 8000);
+
+        let db_path = temp_dir.path().join("entries.db");
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE entries (id INTEGER PRIMARY KEY, expression TEXT, reading TEXT,
+                source TEXT, speaker TEXT, display TEXT, file TEXT);
+             INSERT INTO entries (expression, source, file) VALUES ('あ', 'nhk16', 'a.wav');",
+        )
+        .unwrap();
+        drop(conn);
+
+        let query = |db_path: &Path| -> (Option<i64>, Option<Vec<u8>>, Option<f64>) {
+            Connection::open(db_path)
+                .unwrap()
+                .query_row(
+                    "SELECT duration_ms, peaks, gain_db FROM entries WHERE file = 'a.wav'",
+                    [],
+                    |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+                )
+                .unwrap()
+        };
+
+        let annotated =
+            annotate_audio_metadata(&db_path, temp_dir.path(), &HashMap::new(), Some(4), None)
+                .unwrap();
+        assert_eq!(annotated, 1);
+        let (duration, peaks, gain_db) = query(&db_path);
+        assert_eq!(duration, Some(1000));
+        assert_eq!(peaks.as_ref().map(|p| p.len()), Some(4));
+        assert_eq!(gain_db, None);
+
+        // Without a target, annotated entries are left alone
+        let annotated =
+            annotate_audio_metadata(&db_path, temp_dir.path(), &HashMap::new(), None, None)
+                .unwrap();
+        assert_eq!(annotated, 0);
+
+        // A target fills in the gain, keeping the waveform of the first pass
+        let annotated = annotate_audio_metadata(
+            &db_path,
+            temp_dir.path(),
+            &HashMap::new(),
+            None,
+            Some(-16.0),
+        )
+        .unwrap();
+        assert_eq!(annotated, 1);
+        let (duration, new_peaks, gain_db) = query(&db_path);
+        assert_eq!(duration, Some(1000));
+        assert_eq!(new_peaks, peaks);
+        let first_gain = gain_db.unwrap();
+        assert!(first_gain < -5.0, "{gain_db:?}");
+
+        // The same target again has nothing left to do
+        let annotated = annotate_audio_metadata(
+            &db_path,
+            temp_dir.path(),
+            &HashMap::new(),
+            None,
+            Some(-16.0),
+        )
+        .unwrap();
+        assert_eq!(annotated, 0);
+
+        // A new target recomputes the gain
+        let annotated = annotate_audio_metadata(
+            &db_path,
+            temp_dir.path(),
+            &HashMap::new(),
+            None,
+            Some(-20.0),
+        )
+        .unwrap();
+        assert_eq!(annotated, 1);
+        let (_, _, gain_db) = query(&db_path);
+        assert!(
+            (gain_db.unwrap() - (first_gain - 4.0)).abs() < 0.01,
+            "{gain_db:?}"
+        );
+    }
+
+    #[test]
+    fn test_annotate_skips_unmeasurable_entries_for_same_target() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("nhk16_files");
+        std::fs::create_dir_all(&source_dir).unwrap();
+        // Silence has no loudness, like files symphonia can't decode
+        let wav_path = source_dir.join("a.wav");
+        write_test_wav(&wav_path, 8000, 8000);
+        let mut bytes = std::fs::read(&wav_path).unwrap();
+        bytes[44..].fill(0);
+        std::fs::write(&wav_path, bytes).unwrap();
+
+        let db_path = temp_dir.path().join("entries.db");
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE entries (id INTEGER PRIMARY KEY, expression TEXT, reading TEXT,
+                source TEXT, speaker TEXT, display TEXT, file TEXT);
+             INSERT INTO entries (expression, source, file) VALUES ('あ', 'nhk16', 'a.wav');",
+        )
+        .unwrap();
+        drop(conn);
+
+        for expected in [1, 0] {
+            let annotated = annotate_audio_metadata(
+                &db_path,
+                temp_dir.path(),
+                &HashMap::new(),
+                None,
+                Some(-16.0),
+            )
+            .unwrap();
+            assert_eq!(annotated, expected);
+        }
+        let (gain_db, target): (Option<f64>, Option<f64>) = Connection::open(&db_path)
+            .unwrap()
+            .query_row("SELECT gain_db, loudness_target FROM entries", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(gain_db, None);
+        assert_eq!(target, Some(-16.0));
+    }
+}
@@ -0,0 +1,406 @@
+//! Readers for the local-audio-yomichan source directories.
+//!
+//! Each reader turns one source directory into the rows of the `entries`
+//! table. `file` is always relative to the source directory, which is how the
+//! metadata pass and the service resolve it. The layouts are:
+//!
+//! - `nhk`: `entries.json`, a list of `{kana, kanji, accents, subentries}`
+//!   records whose accents name a sound file under `audio/`
+//! - `ajt_jp`: `index.json` with `headwords` (expression to file names) and
+//!   `files` (file name to kana reading), files under `meta.media_dir`
+//! - `forvo`: `<speaker>/<expression>.<ext>`, no reading
+//! - `jpod`: `<reading> - <expression>.<ext>`, in any subdirectory
+
+use crate::config::{SourceConfig, SourceType};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::{debug, warn};
+
+/// Directory of the NHK16 sound files, relative to the source directory
+const NHK_AUDIO_DIR: &str = "audio";
+
+/// Extensions of the files the walking readers pick up
+const AUDIO_EXTENSIONS: [&str; 8] = ["mp3", "ogg", "opus", "oga", "m4a", "aac", "wav", "flac"];
+
+/// A row of the `entries` table, minus its source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioEntry {
+    pub expression: String,
+    pub reading: Option<String>,
+    pub speaker: Option<String>,
+    pub display: Option<String>,
+    pub file: String,
+    /// Downstep position of the recorded accent (0 for heiban), NHK only
+    pub pitch: Option<i64>,
+}
+
+/// Read the entries of `source`, whose files are in `source_dir`
+pub fn read_source(source: &SourceConfig, source_dir: &Path) -> Result<Vec<AudioEntry>> {
+    let entries = match source.source_type {
+        SourceType::Nhk => read_nhk(source, source_dir),
+        SourceType::AjtJp => read_ajt_jp(source, source_dir),
+        SourceType::Forvo => read_forvo(source_dir),
+        SourceType::Jpod => read_jpod(source, source_dir),
+        SourceType::Ozk5 => {
+            warn!(
+                "Skipping source {}: ozk5 collections are not supported",
+                source.id
+            );
+            Ok(Vec::new())
+        }
+    };
+    entries.with_context(|| format!("Failed to read audio source {}", source.id))
+}
+
+// The display template with `%s` replaced, e.g. "NHK16 %s" -> "NHK16 たべる"
+fn display(source: &SourceConfig, value: &str) -> Option<String> {
+    Some(source.display.replace("%s", value))
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T> {
+    let contents =
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_slice(&contents).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+#[derive(Deserialize)]
+struct NhkEntry {
+    kana: String,
+    #[serde(default)]
+    kanji: Vec<String>,
+    #[serde(default)]
+    accents: Vec<NhkAccent>,
+    #[serde(default)]
+    subentries: Vec<NhkSubentry>,
+}
+
+#[derive(Deserialize)]
+struct NhkSubentry {
+    head: String,
+    #[serde(default)]
+    accents: Vec<NhkAccent>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NhkAccent {
+    sound_file: Option<String>,
+    /// One part per word of the pronunciation, compounds have several
+    #[serde(default)]
+    accent: Vec<NhkAccentPart>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NhkAccentPart {
+    pitch_accent: Option<i64>,
+}
+
+impl NhkAccent {
+    // The accent of single-word pronunciations, those of compounds don't map
+    // to one downstep position
+    fn pitch(&self) -> Option<i64> {
+        match self.accent.as_slice() {
+            [part] => part.pitch_accent,
+            _ => None,
+        }
+    }
+}
+
+fn read_nhk(source: &SourceConfig, source_dir: &Path) -> Result<Vec<AudioEntry>> {
+    let records: Vec<NhkEntry> = read_json(&source_dir.join("entries.json"))?;
+    let mut entries = Vec::new();
+    for record in records {
+        let reading = record.kana;
+        let mut push = |expression: &str, accents: &[NhkAccent]| {
+            for accent in accents {
+                let Some(file) = &accent.sound_file else {
+                    continue;
+                };
+                entries.push(AudioEntry {
+                    expression: expression.to_string(),
+                    reading: Some(reading.clone()),
+                    speaker: None,
+                    display: display(source, &reading),
+                    file: format!("{NHK_AUDIO_DIR}/{file}"),
+                    pitch: accent.pitch(),
+                });
+            }
+        };
+        if record.kanji.is_empty() {
+            push(&reading, &record.accents);
+        }
+        for kanji in &record.kanji {
+            push(kanji, &record.accents);
+        }
+        for subentry in &record.subentries {
+            push(&subentry.head, &subentry.accents);
+        }
+    }
+    Ok(entries)
+}
+
+#[derive(Deserialize)]
+struct AjtIndex {
+    #[serde(default)]
+    meta: AjtMeta,
+    headwords: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    files: HashMap<String, AjtFile>,
+}
+
+#[derive(Default, Deserialize)]
+struct AjtMeta {
+    media_dir: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AjtFile {
+    kana_reading: Option<String>,
+    /// Downstep position, or several separated by commas
+    pitch_number: Option<String>,
+}
+
+fn read_ajt_jp(source: &SourceConfig, source_dir: &Path) -> Result<Vec<AudioEntry>> {
+    let index: AjtIndex = read_json(&source_dir.join("index.json"))?;
+    let mut headwords: Vec<_> = index.headwords.into_iter().collect();
+    headwords.sort();
+    let mut entries = Vec::new();
+    for (expression, files) in headwords {
+        for file in files {
+            let info = index.files.get(&file);
+            let reading = info.and_then(|f| f.kana_reading.clone());
+            let pitch = info
+                .and_then(|f| f.pitch_number.as_deref())
+                .and_then(|p| p.trim().parse().ok());
+            let file = match &index.meta.media_dir {
+                Some(media_dir) => format!("{media_dir}/{file}"),
+                None => file,
+            };
+            entries.push(AudioEntry {
+                display: display(source, reading.as_deref().unwrap_or(&expression)),
+                expression: expression.clone(),
+                reading,
+                speaker: None,
+                file,
+                pitch,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+fn read_forvo(source_dir: &Path) -> Result<Vec<AudioEntry>> {
+    let mut entries = Vec::new();
+    for speaker_dir in sorted_dir_entries(source_dir)? {
+        if !speaker_dir.is_dir() {
+            continue;
+        }
+        let Some(speaker) = speaker_dir.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        for path in sorted_dir_entries(&speaker_dir)? {
+            let Some(expression) = audio_file_stem(&path) else {
+                continue;
+            };
+            let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            entries.push(AudioEntry {
+                expression: expression.to_string(),
+                reading: None,
+                speaker: Some(speaker.to_string()),
+                display: None,
+                file: format!("{speaker}/{file_name}"),
+                pitch: None,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+fn read_jpod(source: &SourceConfig, source_dir: &Path) -> Result<Vec<AudioEntry>> {
+    let mut entries = Vec::new();
+    let mut pending = vec![source_dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for path in sorted_dir_entries(&dir)? {
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let Some(stem) = audio_file_stem(&path) else {
+                continue;
+            };
+            let Some((reading, expression)) = stem.split_once(" - ") else {
+                debug!(
+                    "Skipping {}: not named \"reading - expression\"",
+                    path.display()
+                );
+                continue;
+            };
+            let Some(file) = path.strip_prefix(source_dir).ok().and_then(|p| p.to_str()) else {
+                continue;
+            };
+            entries.push(AudioEntry {
+                expression: expression.to_string(),
+                reading: Some(reading.to_string()),
+                speaker: None,
+                display: display(source, reading),
+                file: file.replace(std::path::MAIN_SEPARATOR, "/"),
+                pitch: None,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+fn sorted_dir_entries(dir: &Path) -> Result<Vec<std::path::PathBuf>> {
+    let mut paths = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.sort();
+    Ok(paths)
+}
+
+/// Every audio file under `dir`, in any subdirectory
+pub(crate) fn audio_files(dir: &Path) -> Result<Vec<std::path::PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for path in sorted_dir_entries(&dir)? {
+            if path.is_dir() {
+                pending.push(path);
+            } else if audio_file_stem(&path).is_some() {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// The stem of `path` if it is an audio file
+fn audio_file_stem(path: &Path) -> Option<&str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    if !path.is_file() || !AUDIO_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+    path.file_stem()?.to_str()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AudioConfig;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn source(source_type: &str, display: &str) -> SourceConfig {
+        let config = AudioConfig::from_json(&format!(
+            r#"{{"sources": [{{"type": "{source_type}", "id": "test", "path": "test", "display": "{display}"}}]}}"#
+        ))
+        .unwrap();
+        config.sources.into_iter().next().unwrap()
+    }
+
+    fn touch(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"").unwrap();
+    }
+
+    #[test]
+    fn test_read_nhk() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("entries.json"),
+            r#"[
+                {"kana": "たべる", "kanji": ["食べる", "喰べる"],
+                 "accents": [{"soundFile": "taberu.opus", "accent": [{"pronunciation": "タベル", "pitchAccent": 2}]}],
+                 "subentries": [{"head": "食べ物", "accents": [{"soundFile": "tabemono.opus", "accent": [
+                     {"pronunciation": "タベ", "pitchAccent": 2}, {"pronunciation": "モノ", "pitchAccent": 0}
+                 ]}, {}]}]},
+                {"kana": "ああ", "kanji": [], "accents": [{"soundFile": "aa.opus"}]}
+            ]"#,
+        )
+        .unwrap();
+        let entries = read_source(&source("nhk", "NHK16 %s"), dir.path()).unwrap();
+        let rows: Vec<_> = entries
+            .iter()
+            .map(|e| (e.expression.as_str(), e.file.as_str()))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("食べる", "audio/taberu.opus"),
+                ("喰べる", "audio/taberu.opus"),
+                ("食べ物", "audio/tabemono.opus"),
+                ("ああ", "audio/aa.opus"),
+            ]
+        );
+        assert_eq!(entries[2].reading.as_deref(), Some("たべる"));
+        assert_eq!(entries[0].display.as_deref(), Some("NHK16 たべる"));
+        // Compounds have no single accent
+        let pitches: Vec<_> = entries.iter().map(|e| e.pitch).collect();
+        assert_eq!(pitches, [Some(2), Some(2), None, None]);
+    }
+
+    #[test]
+    fn test_read_ajt_jp() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("index.json"),
+            r#"{"meta": {"name": "SMK8", "media_dir": "media"},
+                "headwords": {"食べる": ["taberu.ogg"], "喰べる": ["taberu.ogg", "other.ogg"]},
+                "files": {"taberu.ogg": {"kana_reading": "たべる", "pitch_number": "2"}}}"#,
+        )
+        .unwrap();
+        let entries = read_source(&source("ajt_jp", "SMK8 %s"), dir.path()).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].expression, "喰べる");
+        assert_eq!(entries[0].file, "media/taberu.ogg");
+        assert_eq!(entries[0].display.as_deref(), Some("SMK8 たべる"));
+        assert_eq!(entries[0].pitch, Some(2));
+        assert_eq!(entries[1].reading, None);
+        assert_eq!(entries[2].expression, "食べる");
+    }
+
+    #[test]
+    fn test_read_forvo_and_jpod() {
+        let dir = TempDir::new().unwrap();
+        touch(&dir.path().join("forvo/strawberrybrown/食べる.opus"));
+        touch(&dir.path().join("forvo/strawberrybrown/notes.txt"));
+        touch(&dir.path().join("forvo/stray.opus"));
+        let entries =
+            read_source(&source("forvo", "Forvo (%s)"), &dir.path().join("forvo")).unwrap();
+        assert_eq!(
+            entries,
+            [AudioEntry {
+                expression: "食べる".to_string(),
+                reading: None,
+                speaker: Some("strawberrybrown".to_string()),
+                display: None,
+                file: "strawberrybrown/食べる.opus".to_string(),
+                pitch: None,
+            }]
+        );
+
+        touch(&dir.path().join("jpod/たべる - 食べる.mp3"));
+        touch(&dir.path().join("jpod/more/くう - 食う.MP3"));
+        touch(&dir.path().join("jpod/unnamed.mp3"));
+        let entries = read_source(&source("jpod", "Jpod101"), &dir.path().join("jpod")).unwrap();
+        let rows: Vec<_> = entries
+            .iter()
+            .map(|e| (e.expression.as_str(), e.reading.as_deref(), e.file.as_str()))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("食べる", Some("たべる"), "たべる - 食べる.mp3"),
+                ("食う", Some("くう"), "more/くう - 食う.MP3"),
+            ]
+        );
+    }
+}
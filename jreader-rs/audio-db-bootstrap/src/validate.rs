@@ -0,0 +1,155 @@
+use anyhow::{bail, Context, Result};
+use rusqlite::Connection;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// An entry whose audio file doesn't exist
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingFile {
+    pub id: i64,
+    pub expression: String,
+    pub source: String,
+    pub file: String,
+}
+
+/// What a validation pass found
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationReport {
+    /// Entries checked
+    pub entries: usize,
+    pub missing: Vec<MissingFile>,
+    /// Audio files in a source directory that no entry of the source points at
+    pub orphaned: Vec<PathBuf>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// Check that the file of every entry exists under its source directory, and
+/// list the audio files of those directories no entry points at.
+///
+/// `source_dirs` maps a source id to its directory; sources not listed fall
+/// back to the `{source}_files` convention under `audio_files_path`.
+pub fn validate_audio_database(
+    db_path: &Path,
+    audio_files_path: &Path,
+    source_dirs: &HashMap<String, PathBuf>,
+) -> Result<ValidationReport> {
+    if !db_path.is_file() {
+        bail!("Audio database does not exist: {}", db_path.display());
+    }
+    let conn = Connection::open(db_path)
+        .with_context(|| format!("Failed to open audio database: {}", db_path.display()))?;
+    let entries: Vec<(i64, String, String, String)> = {
+        let mut stmt =
+            conn.prepare("SELECT id, expression, source, file FROM entries ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    info!("Checking the files of {} entries", entries.len());
+
+    let mut report = ValidationReport {
+        entries: entries.len(),
+        ..Default::default()
+    };
+    // Files referenced by each source, relative to its directory. Configured
+    // sources without entries are walked too, all their files being orphans
+    let mut referenced: BTreeMap<String, HashSet<String>> = source_dirs
+        .keys()
+        .map(|source| (source.clone(), HashSet::new()))
+        .collect();
+    for (id, expression, source, file) in entries {
+        let path = crate::source_dir(audio_files_path, source_dirs, &source).join(&file);
+        if !path.is_file() {
+            report.missing.push(MissingFile {
+                id,
+                expression,
+                source: source.clone(),
+                file: file.clone(),
+            });
+        }
+        referenced.entry(source).or_default().insert(file);
+    }
+
+    for (source, files) in &referenced {
+        let source_dir = crate::source_dir(audio_files_path, source_dirs, source);
+        if !source_dir.is_dir() {
+            warn!(
+                "Directory of source {} does not exist: {}",
+                source,
+                source_dir.display()
+            );
+            continue;
+        }
+        for path in crate::sources::audio_files(&source_dir)? {
+            let Some(file) = path.strip_prefix(&source_dir).ok().and_then(|p| p.to_str()) else {
+                continue;
+            };
+            if !files.contains(&file.replace(std::path::MAIN_SEPARATOR, "/")) {
+                report.orphaned.push(path);
+            }
+        }
+    }
+
+    info!(
+        "Checked {} entries: {} missing files, {} orphaned files",
+        report.entries,
+        report.missing.len(),
+        report.orphaned.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_validate_audio_database() {
+        let temp_dir = TempDir::new().unwrap();
+        let audio_dir = temp_dir.path();
+        fs::create_dir_all(audio_dir.join("nhk16_files/audio")).unwrap();
+        fs::write(
+            audio_dir.join("nhk16_files/entries.json"),
+            r#"[{"kana": "たべる", "kanji": ["食べる"], "accents": [{"soundFile": "taberu.opus"}]},
+                {"kana": "のむ", "kanji": ["飲む"], "accents": [{"soundFile": "nomu.opus"}]}]"#,
+        )
+        .unwrap();
+        fs::write(audio_dir.join("nhk16_files/audio/taberu.opus"), b"").unwrap();
+        fs::write(audio_dir.join("nhk16_files/audio/unused.opus"), b"").unwrap();
+        fs::create_dir_all(audio_dir.join("forvo_files/a")).unwrap();
+        fs::write(audio_dir.join("forvo_files/a/食べる.opus"), b"").unwrap();
+
+        let db_path = temp_dir.path().join("entries.db");
+        crate::bootstrap_audio_database_simple(audio_dir, &db_path).unwrap();
+        let report = validate_audio_database(&db_path, audio_dir, &HashMap::new()).unwrap();
+        assert_eq!(report.entries, 3);
+        assert!(!report.is_ok());
+        assert_eq!(
+            report.missing,
+            [MissingFile {
+                id: 2,
+                expression: "飲む".to_string(),
+                source: "nhk16".to_string(),
+                file: "audio/nomu.opus".to_string(),
+            }]
+        );
+        assert_eq!(
+            report.orphaned,
+            [audio_dir.join("nhk16_files/audio/unused.opus")]
+        );
+
+        // Deleted after the bootstrap
+        fs::remove_file(audio_dir.join("forvo_files/a/食べる.opus")).unwrap();
+        let report = validate_audio_database(&db_path, audio_dir, &HashMap::new()).unwrap();
+        assert_eq!(report.missing.len(), 2);
+    }
+}
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::AudioEntry;
+
+/// `(expression, reading)` of a term query
+pub(crate) type TermKey = (String, Option<String>);
+
+/// Hit rate and size of the query cache of an [`AudioDB`](crate::AudioDB)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QueryCacheStats {
+    pub capacity: usize,
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// The results of the most recently used term queries
+pub(crate) struct QueryCache {
+    capacity: usize,
+    entries: HashMap<TermKey, (u64, Arc<Vec<AudioEntry>>)>,
+    /// Keys by when they were last used, oldest first
+    recency: BTreeMap<u64, TermKey>,
+    tick: u64,
+    /// Bumped by `clear`, so results of queries that ran across it aren't kept
+    generation: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl QueryCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            generation: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub(crate) fn get(&mut self, key: &TermKey) -> Option<Arc<Vec<AudioEntry>>> {
+        self.tick += 1;
+        let Some((used, entries)) = self.entries.get_mut(key) else {
+            self.misses += 1;
+            return None;
+        };
+        self.recency.remove(used);
+        *used = self.tick;
+        self.recency.insert(self.tick, key.clone());
+        self.hits += 1;
+        Some(entries.clone())
+    }
+
+    /// Keep the results of a query that started in `generation`
+    pub(crate) fn insert(&mut self, key: TermKey, entries: Arc<Vec<AudioEntry>>, generation: u64) {
+        if self.capacity == 0 || generation != self.generation {
+            return;
+        }
+        self.tick += 1;
+        if let Some((used, _)) = self.entries.insert(key.clone(), (self.tick, entries)) {
+            self.recency.remove(&used);
+        }
+        self.recency.insert(self.tick, key);
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.generation += 1;
+    }
+
+    pub(crate) fn stats(&self) -> QueryCacheStats {
+        QueryCacheStats {
+            capacity: self.capacity,
+            entries: self.entries.len(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(expression: &str) -> TermKey {
+        (expression.to_string(), None)
+    }
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let mut cache = QueryCache::new(2);
+        let generation = cache.generation();
+        cache.insert(key("a"), Arc::new(Vec::new()), generation);
+        cache.insert(key("b"), Arc::new(Vec::new()), generation);
+        assert!(cache.get(&key("a")).is_some());
+        cache.insert(key("c"), Arc::new(Vec::new()), generation);
+        // "b" was used longest ago
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("a")).is_some());
+        assert!(cache.get(&key("c")).is_some());
+        assert_eq!(
+            cache.stats(),
+            QueryCacheStats {
+                capacity: 2,
+                entries: 2,
+                hits: 3,
+                misses: 1,
+            }
+        );
+
+        cache.clear();
+        assert!(cache.get(&key("a")).is_none());
+        // Results of a query from before the clear are dropped
+        cache.insert(key("a"), Arc::new(Vec::new()), generation);
+        assert!(cache.get(&key("a")).is_none());
+    }
+}
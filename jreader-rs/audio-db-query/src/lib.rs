@@ -1,8 +1,21 @@
 use anyhow::Result;
 use camino::{Utf8Path as Path, Utf8PathBuf as PathBuf};
-use rusqlite::{Connection, OpenFlags, Row};
+use rusqlite::Row;
 use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+use unicode_normalization::UnicodeNormalization;
+
+mod cache;
+mod pool;
+mod writer;
+use cache::QueryCache;
+pub use cache::QueryCacheStats;
+use pool::{ConnectionPool, PooledConnection};
+pub use writer::{AudioDBWriter, NewAudioEntry};
+
+/// Term queries whose results [`AudioDB::new`] keeps
+pub const DEFAULT_CACHE_CAPACITY: usize = 4096;
 
 /// Audio database entry representing a row from the entries table
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,50 +27,242 @@ pub struct AudioEntry {
     pub speaker: Option<String>,
     pub display: Option<String>,
     pub file: String,
+    /// Length of the recording, if probed at bootstrap time
+    pub duration_ms: Option<i64>,
+    /// Peak waveform buckets (0-255), if generated at bootstrap time
+    pub peaks: Option<Vec<u8>>,
+    /// Gain in dB that normalizes this recording's loudness, if measured at bootstrap time
+    pub gain_db: Option<f64>,
+    /// Downstep position of the recorded accent (0 for heiban), for sources
+    /// that give it
+    pub pitch: Option<i64>,
 }
 
 /// Audio database query interface
 pub struct AudioDB {
     path: PathBuf,
-    conn: Mutex<Connection>,
+    conns: ConnectionPool,
+    // SELECT list for AudioEntry rows, see entry_columns()
+    entry_columns: String,
+    cache: Mutex<CacheState>,
+}
+
+struct CacheState {
+    queries: QueryCache,
+    /// Version of the database file the cached results were read from
+    file_version: Option<FileVersion>,
 }
 
+/// Modification time and size of the database file and its WAL, and the
+/// file change counter in the database header, which together change with
+/// every write. Checking them is much cheaper than a query.
+#[derive(Debug, PartialEq)]
+struct FileVersion {
+    main: (SystemTime, u64),
+    change_counter: Option<[u8; 4]>,
+    wal: Option<(SystemTime, u64)>,
+}
+
+fn file_version(path: &Path) -> Option<FileVersion> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let version = |path: &str| {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    };
+    // Bumped by every commit outside WAL mode, even when the modification
+    // time doesn't move
+    let change_counter = std::fs::File::open(path).ok().and_then(|mut file| {
+        let mut counter = [0; 4];
+        file.seek(SeekFrom::Start(24)).ok()?;
+        file.read_exact(&mut counter).ok()?;
+        Some(counter)
+    });
+    Some(FileVersion {
+        main: version(path.as_str())?,
+        change_counter,
+        wal: version(&format!("{path}-wal")),
+    })
+}
+
+// Optional columns, in the order row_to_audio_entry expects them after `file`
+const METADATA_COLUMNS: [&str; 4] = ["duration_ms", "peaks", "gain_db", "pitch"];
+
 impl AudioDB {
     /// Create a new AudioDB instance from a database file path (read-only)
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::with_cache_capacity(path, DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// Like [`AudioDB::new`], keeping the results of the `capacity` most
+    /// recently used term queries (0 for none). The cache is dropped when the
+    /// database file changes, or by [`AudioDB::invalidate_cache`].
+    pub fn with_cache_capacity<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self> {
         let path = path.as_ref().to_path_buf();
-        let conn = Connection::open_with_flags(
-            &path,
-            OpenFlags::SQLITE_OPEN_READ_ONLY
-                | OpenFlags::SQLITE_OPEN_NO_MUTEX
-                | OpenFlags::SQLITE_OPEN_URI,
-        )?;
+        let conns = ConnectionPool::open(path.clone())?;
+
+        let entry_columns = {
+            let conn = conns.get()?;
+            let mut stmt = conn.prepare("PRAGMA table_info(entries)")?;
+            let columns: Vec<String> = stmt
+                .query_map([], |row| row.get::<_, String>(1))?
+                .collect::<rusqlite::Result<_>>()?;
+            // Metadata columns are added by the bootstrap's probing pass, or by
+            // newer bootstraps, and may be missing
+            let mut select =
+                String::from("id, expression, reading, source, speaker, display, file");
+            for column in METADATA_COLUMNS {
+                if columns.iter().any(|c| c == column) {
+                    select.push_str(&format!(", {column}"));
+                } else {
+                    select.push_str(&format!(", NULL AS {column}"));
+                }
+            }
+            select
+        };
 
+        let cache = CacheState {
+            queries: QueryCache::new(capacity),
+            file_version: file_version(&path),
+        };
         Ok(Self {
             path,
-            conn: Mutex::new(conn),
+            conns,
+            entry_columns,
+            cache: Mutex::new(cache),
         })
     }
 
+    /// A connection for one query, see [`ConnectionPool`]
+    fn conn(&self) -> Result<PooledConnection<'_>> {
+        self.conns.get()
+    }
+
+    /// Columns selected for an AudioEntry
+    fn entry_columns(&self) -> &str {
+        &self.entry_columns
+    }
+
+    /// The query cache, emptied first if the database file changed since the
+    /// cached results were read
+    fn cache(&self) -> Result<MutexGuard<'_, CacheState>> {
+        let mut cache = self
+            .cache
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire cache lock: {e}"))?;
+        let version = file_version(&self.path);
+        if version != cache.file_version {
+            cache.file_version = version;
+            cache.queries.clear();
+        }
+        Ok(cache)
+    }
+
+    /// Drop the cached query results. Writes are noticed by the next query
+    /// anyway, this is for changes the file's size and modification time
+    /// might not show.
+    pub fn invalidate_cache(&self) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.file_version = file_version(&self.path);
+            cache.queries.clear();
+        }
+    }
+
+    pub fn cache_stats(&self) -> Result<QueryCacheStats> {
+        Ok(self.cache()?.queries.stats())
+    }
+
+    /// Entries of `expression` (and `reading`, if given), from the cache if
+    /// the same term was queried recently
+    fn term_entries(
+        &self,
+        expression: &str,
+        reading: Option<&str>,
+    ) -> Result<Arc<Vec<AudioEntry>>> {
+        let key = (expression.to_string(), reading.map(str::to_string));
+        let generation = {
+            let mut cache = self.cache()?;
+            if let Some(entries) = cache.queries.get(&key) {
+                return Ok(entries);
+            }
+            cache.queries.generation()
+        };
+        let entries = Arc::new(self.query_term(expression, reading)?);
+        self.cache()?
+            .queries
+            .insert(key, entries.clone(), generation);
+        Ok(entries)
+    }
+
+    fn query_term(&self, expression: &str, reading: Option<&str>) -> Result<Vec<AudioEntry>> {
+        let conn = self.conn()?;
+
+        let mut sql = format!(
+            "SELECT {} 
+             FROM entries 
+             WHERE expression = ?",
+            self.entry_columns()
+        );
+        let mut params: Vec<&str> = vec![expression];
+        if let Some(reading) = reading {
+            sql.push_str(" AND reading = ?");
+            params.push(reading);
+        }
+        sql.push_str(" ORDER BY source, speaker, display");
+
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(params), |row| {
+            self.row_to_audio_entry(row)
+        })?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            let entry = row.map_err(|e| anyhow::anyhow!("Database error: {}", e))?;
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
     /// Query for audio entries by expression and reading
     pub fn query_by_term_and_reading(
         &self,
         expression: &str,
         reading: &str,
     ) -> Result<Vec<AudioEntry>> {
-        let conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Failed to acquire connection lock: {e}"))?;
+        Ok(self.term_entries(expression, Some(reading))?.to_vec())
+    }
 
-        let mut stmt = conn.prepare(
-            "SELECT id, expression, reading, source, speaker, display, file 
+    /// Get a single entry by its id
+    pub fn get_entry(&self, id: i64) -> Result<Option<AudioEntry>> {
+        let conn = self.conn()?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM entries WHERE id = ?",
+            self.entry_columns()
+        ))?;
+        let mut rows = stmt.query_map([id], |row| self.row_to_audio_entry(row))?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Query for audio entries by expression only (reading can be null)
+    pub fn query_by_term(&self, expression: &str) -> Result<Vec<AudioEntry>> {
+        Ok(self.term_entries(expression, None)?.to_vec())
+    }
+
+    /// Query for audio entries by expression or reading (matches either)
+    pub fn query_by_term_or_reading(&self, term: &str) -> Result<Vec<AudioEntry>> {
+        let conn = self.conn()?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} 
              FROM entries 
-             WHERE expression = ? AND reading = ?
+             WHERE expression = ? OR reading = ?
              ORDER BY source, speaker, display",
-        )?;
+            self.entry_columns()
+        ))?;
 
-        let rows = stmt.query_map([expression, reading], |row| self.row_to_audio_entry(row))?;
+        let rows = stmt.query_map([term, term], |row| self.row_to_audio_entry(row))?;
 
         let mut entries = Vec::new();
         for row in rows {
@@ -68,21 +273,21 @@ impl AudioDB {
         Ok(entries)
     }
 
-    /// Query for audio entries by expression only (reading can be null)
-    pub fn query_by_term(&self, expression: &str) -> Result<Vec<AudioEntry>> {
-        let conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Failed to acquire connection lock: {e}"))?;
+    /// Query for audio entries by reading alone, whatever their expression.
+    /// Entries without a reading match when their expression is `reading`,
+    /// as kana-only terms often have no separate reading.
+    pub fn query_by_reading(&self, reading: &str) -> Result<Vec<AudioEntry>> {
+        let conn = self.conn()?;
 
-        let mut stmt = conn.prepare(
-            "SELECT id, expression, reading, source, speaker, display, file 
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} 
              FROM entries 
-             WHERE expression = ?
+             WHERE reading = ?1 OR (reading IS NULL AND expression = ?1)
              ORDER BY source, speaker, display",
-        )?;
+            self.entry_columns()
+        ))?;
 
-        let rows = stmt.query_map([expression], |row| self.row_to_audio_entry(row))?;
+        let rows = stmt.query_map([reading], |row| self.row_to_audio_entry(row))?;
 
         let mut entries = Vec::new();
         for row in rows {
@@ -93,21 +298,150 @@ impl AudioDB {
         Ok(entries)
     }
 
-    /// Query for audio entries by expression or reading (matches either)
-    pub fn query_by_term_or_reading(&self, term: &str) -> Result<Vec<AudioEntry>> {
-        let conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Failed to acquire connection lock: {e}"))?;
+    /// Query for audio entries by expression (and reading, if given), restricted to
+    /// the given sources. An empty `sources` slice means no source filtering.
+    pub fn query_by_term_filtered(
+        &self,
+        expression: &str,
+        reading: Option<&str>,
+        sources: &[&str],
+    ) -> Result<Vec<AudioEntry>> {
+        Ok(self
+            .term_entries(expression, reading)?
+            .iter()
+            .filter(|entry| sources.is_empty() || sources.contains(&entry.source.as_str()))
+            .cloned()
+            .collect())
+    }
 
-        let mut stmt = conn.prepare(
-            "SELECT id, expression, reading, source, speaker, display, file 
+    /// Query for audio entries of many expressions at once with a single IN-clause.
+    /// Callers are expected to group the results by expression (and reading).
+    pub fn query_by_terms(&self, expressions: &[&str]) -> Result<Vec<AudioEntry>> {
+        if expressions.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let conn = self.conn()?;
+
+        let placeholders = vec!["?"; expressions.len()].join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} 
              FROM entries 
-             WHERE expression = ? OR reading = ?
-             ORDER BY source, speaker, display",
+             WHERE expression IN ({placeholders})
+             ORDER BY expression, source, speaker, display",
+            self.entry_columns()
+        ))?;
+
+        let rows = stmt.query_map(rusqlite::params_from_iter(expressions), |row| {
+            self.row_to_audio_entry(row)
+        })?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            let entry = row.map_err(|e| anyhow::anyhow!("Database error: {}", e))?;
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
+    /// Query for audio entries whose expression starts with `expression_prefix`,
+    /// at most `limit` of them. This is a range scan on the expression index.
+    pub fn query_by_prefix(
+        &self,
+        expression_prefix: &str,
+        limit: usize,
+    ) -> Result<Vec<AudioEntry>> {
+        if expression_prefix.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let conn = self.conn()?;
+
+        // Every expression starting with the prefix sorts below prefix + the last code point
+        let upper_bound = format!("{expression_prefix}{}", char::MAX);
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} 
+             FROM entries 
+             WHERE expression >= ?1 AND expression < ?2
+             ORDER BY expression, source, speaker, display
+             LIMIT ?3",
+            self.entry_columns()
+        ))?;
+
+        let rows = stmt.query_map(
+            rusqlite::params![expression_prefix, upper_bound, limit as i64],
+            |row| self.row_to_audio_entry(row),
         )?;
 
-        let rows = stmt.query_map([term, term], |row| self.row_to_audio_entry(row))?;
+        let mut entries = Vec::new();
+        for row in rows {
+            let entry = row.map_err(|e| anyhow::anyhow!("Database error: {}", e))?;
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
+    /// Query for audio entries matching `term` up to Unicode and kana
+    /// variation: the expression or reading equals one of [`fuzzy_forms`] of
+    /// the term, so half-width, decomposed, hiragana and katakana spellings
+    /// all find each other.
+    pub fn query_fuzzy(&self, term: &str) -> Result<Vec<AudioEntry>> {
+        let forms = fuzzy_forms(term);
+        if forms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let conn = self.conn()?;
+
+        let placeholders = vec!["?"; forms.len()].join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} 
+             FROM entries 
+             WHERE expression IN ({placeholders}) OR reading IN ({placeholders})
+             ORDER BY expression, source, speaker, display",
+            self.entry_columns()
+        ))?;
+
+        let rows = stmt.query_map(
+            rusqlite::params_from_iter(forms.iter().chain(forms.iter())),
+            |row| self.row_to_audio_entry(row),
+        )?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            let entry = row.map_err(|e| anyhow::anyhow!("Database error: {}", e))?;
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
+    /// Query for audio entries whose display text matches the SQL LIKE
+    /// `pattern` (`%` and `_` are wildcards, ASCII letters match in either
+    /// case), optionally only of `source`, at most `limit` of them. The display
+    /// text of some sources carries pitch accent notation, e.g. NHK's.
+    pub fn query_display_like(
+        &self,
+        pattern: &str,
+        source: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<AudioEntry>> {
+        let conn = self.conn()?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} 
+             FROM entries 
+             WHERE display LIKE ?1 AND (?2 IS NULL OR source = ?2)
+             ORDER BY expression, source, speaker, display
+             LIMIT ?3",
+            self.entry_columns()
+        ))?;
+
+        let rows = stmt.query_map(rusqlite::params![pattern, source, limit as i64], |row| {
+            self.row_to_audio_entry(row)
+        })?;
 
         let mut entries = Vec::new();
         for row in rows {
@@ -118,12 +452,37 @@ impl AudioDB {
         Ok(entries)
     }
 
+    /// List distinct source/speaker pairs with the number of entries for each.
+    /// Sources without speaker information are reported with `speaker: None`.
+    pub fn list_speakers(&self) -> Result<Vec<SpeakerStats>> {
+        let conn = self.conn()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT source, speaker, COUNT(*) 
+             FROM entries 
+             GROUP BY source, speaker
+             ORDER BY source, COUNT(*) DESC, speaker",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok(SpeakerStats {
+                source: row.get(0)?,
+                speaker: row.get(1)?,
+                entry_count: row.get(2)?,
+            })
+        })?;
+
+        let mut speakers = Vec::new();
+        for row in rows {
+            speakers.push(row?);
+        }
+
+        Ok(speakers)
+    }
+
     /// Get statistics about the database
     pub fn get_stats(&self) -> Result<AudioDBStats> {
-        let conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Failed to acquire connection lock: {e}"))?;
+        let conn = self.conn()?;
 
         let total_entries: i64 =
             conn.query_row("SELECT COUNT(*) FROM entries", [], |row| row.get(0))?;
@@ -168,10 +527,60 @@ impl AudioDB {
             speaker: row.get(4)?,
             display: row.get(5)?,
             file: row.get(6)?,
+            duration_ms: row.get(7)?,
+            peaks: row.get(8)?,
+            gain_db: row.get(9)?,
+            pitch: row.get(10)?,
         })
     }
 }
 
+/// Spellings of `term` that [`AudioDB::query_fuzzy`] matches: the term as
+/// given, its NFC and NFKC forms (which fold half-width katakana and
+/// full-width Latin), and the hiragana and katakana versions of the NFKC form.
+/// Without repeats, empty for a blank term.
+pub fn fuzzy_forms(term: &str) -> Vec<String> {
+    let term = term.trim();
+    if term.is_empty() {
+        return Vec::new();
+    }
+    let folded: String = term.nfkc().collect();
+    let mut forms: Vec<String> = Vec::new();
+    for form in [
+        term.to_string(),
+        term.nfc().collect(),
+        katakana_to_hiragana(&folded),
+        hiragana_to_katakana(&folded),
+        folded,
+    ] {
+        if !forms.contains(&form) {
+            forms.push(form);
+        }
+    }
+    forms
+}
+
+// Offset between a katakana letter and its hiragana counterpart
+const KANA_OFFSET: u32 = 0x60;
+
+fn katakana_to_hiragana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ァ'..='ヶ' | 'ヽ' | 'ヾ' => char::from_u32(c as u32 - KANA_OFFSET).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+fn hiragana_to_katakana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ぁ'..='ゖ' | 'ゝ' | 'ゞ' => char::from_u32(c as u32 + KANA_OFFSET).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
 /// Statistics about the audio database
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AudioDBStats {
@@ -181,13 +590,18 @@ pub struct AudioDBStats {
     pub source_stats: Vec<(String, i64)>,
 }
 
-// Safe to implement Send and Sync because we use Mutex for connection access
-unsafe impl Send for AudioDB {}
-unsafe impl Sync for AudioDB {}
+/// Number of entries recorded by a single speaker of a source
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SpeakerStats {
+    pub source: String,
+    pub speaker: Option<String>,
+    pub entry_count: i64,
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rusqlite::Connection;
     use std::env;
 
     fn resolve_db_path() -> Option<PathBuf> {
@@ -268,6 +682,267 @@ mod tests {
         }
     }
 
+    fn create_test_db(dir: &tempfile::TempDir) -> PathBuf {
+        let db_path = PathBuf::from_path_buf(dir.path().join("entries.db")).unwrap();
+        let conn = Connection::open(db_path.as_str()).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE entries (
+                id INTEGER PRIMARY KEY,
+                expression TEXT NOT NULL,
+                reading TEXT,
+                source TEXT NOT NULL,
+                speaker TEXT,
+                display TEXT,
+                file TEXT NOT NULL
+            );
+            INSERT INTO entries (expression, reading, source, speaker, display, file) VALUES
+                ('食べる', 'たべる', 'nhk16', NULL, 'NHK16 たべる', 'taberu.opus'),
+                ('食べる', 'たべる', 'forvo', 'strawberrybrown', NULL, 'strawberrybrown/taberu.opus'),
+                ('食べる', 'たべる', 'jpod', NULL, 'Jpod101', 'taberu.mp3'),
+                ('食べる', 'くらべる', 'jpod', NULL, 'Jpod101', 'kuraberu.mp3');",
+        )
+        .unwrap();
+        db_path
+    }
+
+    #[test]
+    fn test_query_by_term_filtered() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = AudioDB::new(create_test_db(&dir)).unwrap();
+
+        let entries = db.query_by_term_filtered("食べる", None, &[]).unwrap();
+        assert_eq!(entries.len(), 4);
+
+        let entries = db
+            .query_by_term_filtered("食べる", Some("たべる"), &[])
+            .unwrap();
+        assert_eq!(entries.len(), 3);
+
+        let entries = db
+            .query_by_term_filtered("食べる", Some("たべる"), &["nhk16", "jpod"])
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries
+            .iter()
+            .all(|e| e.source == "nhk16" || e.source == "jpod"));
+
+        let entries = db
+            .query_by_term_filtered("食べる", None, &["unknown"])
+            .unwrap();
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn test_term_queries_are_cached() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db_path = create_test_db(&dir);
+        let db = AudioDB::new(&db_path).unwrap();
+
+        assert_eq!(db.query_by_term("食べる").unwrap().len(), 4);
+        assert_eq!(
+            db.query_by_term_filtered("食べる", None, &["jpod"])
+                .unwrap()
+                .len(),
+            2
+        );
+        let stats = db.cache_stats().unwrap();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 1));
+
+        let writer = AudioDBWriter::open(&db_path).unwrap();
+        writer
+            .insert(&NewAudioEntry {
+                expression: "食べる".to_string(),
+                reading: None,
+                source: "personal".to_string(),
+                speaker: None,
+                display: None,
+                file: "taberu.opus".to_string(),
+            })
+            .unwrap();
+        assert_eq!(db.query_by_term("食べる").unwrap().len(), 5);
+
+        let uncached = AudioDB::with_cache_capacity(&db_path, 0).unwrap();
+        uncached.query_by_term("食べる").unwrap();
+        assert_eq!(uncached.cache_stats().unwrap().entries, 0);
+    }
+
+    #[test]
+    fn test_metadata_columns() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db_path = create_test_db(&dir);
+
+        // Databases without metadata columns still load, just without durations
+        let db = AudioDB::new(&db_path).unwrap();
+        let entries = db.query_by_term("食べる").unwrap();
+        assert!(entries
+            .iter()
+            .all(|e| e.duration_ms.is_none() && e.peaks.is_none()));
+
+        let conn = Connection::open(db_path.as_str()).unwrap();
+        conn.execute_batch(
+            "ALTER TABLE entries ADD COLUMN duration_ms INTEGER;
+             ALTER TABLE entries ADD COLUMN peaks BLOB;
+             UPDATE entries SET duration_ms = 850, peaks = x'00ff' WHERE source = 'nhk16';",
+        )
+        .unwrap();
+
+        let db = AudioDB::new(&db_path).unwrap();
+        let entries = db
+            .query_by_term_filtered("食べる", None, &["nhk16"])
+            .unwrap();
+        assert_eq!(entries[0].duration_ms, Some(850));
+        assert_eq!(entries[0].peaks, Some(vec![0, 255]));
+        // gain_db and pitch weren't added, so they read as missing
+        assert_eq!(entries[0].gain_db, None);
+        assert_eq!(entries[0].pitch, None);
+    }
+
+    #[test]
+    fn test_concurrent_queries() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = AudioDB::with_cache_capacity(create_test_db(&dir), 0).unwrap();
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..20 {
+                        assert_eq!(db.query_by_term("食べる").unwrap().len(), 4);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_get_entry() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = AudioDB::new(create_test_db(&dir)).unwrap();
+
+        let entry = db.get_entry(2).unwrap().unwrap();
+        assert_eq!(entry.source, "forvo");
+        assert_eq!(entry.file, "strawberrybrown/taberu.opus");
+        assert!(db.get_entry(99).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_query_by_terms() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = AudioDB::new(create_test_db(&dir)).unwrap();
+
+        assert!(db.query_by_terms(&[]).unwrap().is_empty());
+
+        let entries = db.query_by_terms(&["食べる", "飲む"]).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert!(entries.iter().all(|e| e.expression == "食べる"));
+    }
+
+    #[test]
+    fn test_query_by_reading() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db_path = create_test_db(&dir);
+        let conn = Connection::open(db_path.as_str()).unwrap();
+        conn.execute_batch(
+            "INSERT INTO entries (expression, reading, source, speaker, display, file) VALUES
+                ('喰べる', 'たべる', 'jpod', NULL, 'Jpod101', 'taberu2.mp3'),
+                ('たべる', NULL, 'forvo', 'someone', NULL, 'someone/taberu.opus'),
+                ('たべる', 'くらべる', 'jpod', NULL, 'Jpod101', 'kuraberu2.mp3');",
+        )
+        .unwrap();
+        let db = AudioDB::new(&db_path).unwrap();
+
+        let entries = db.query_by_reading("たべる").unwrap();
+        assert_eq!(entries.len(), 5);
+        assert!(entries.iter().any(|e| e.expression == "喰べる"));
+        // An expression with a different reading doesn't match
+        assert!(entries.iter().all(|e| e.file != "kuraberu2.mp3"));
+        assert!(db.query_by_reading("のむ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_query_by_prefix() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = AudioDB::new(create_test_db(&dir)).unwrap();
+
+        let entries = db.query_by_prefix("食べ", 10).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(db.query_by_prefix("食べ", 2).unwrap().len(), 2);
+        assert!(db.query_by_prefix("食う", 10).unwrap().is_empty());
+        assert!(db.query_by_prefix("", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_query_fuzzy() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db_path = create_test_db(&dir);
+        let conn = Connection::open(db_path.as_str()).unwrap();
+        conn.execute_batch(
+            "INSERT INTO entries (expression, reading, source, speaker, display, file) VALUES
+                ('パン', 'ぱん', 'nhk16', NULL, 'NHK16 ぱん', 'pan.opus');",
+        )
+        .unwrap();
+        let db = AudioDB::new(&db_path).unwrap();
+
+        // Readings are matched in hiragana, expressions in katakana
+        assert_eq!(db.query_fuzzy("タベル").unwrap().len(), 3);
+        assert_eq!(db.query_fuzzy("ぱん").unwrap().len(), 1);
+        // Half-width katakana folds to full-width
+        assert_eq!(db.query_fuzzy("ﾊﾟﾝ").unwrap()[0].expression, "パン");
+        assert!(db.query_fuzzy(" ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_fuzzy_forms() {
+        assert_eq!(fuzzy_forms("たべる"), ["たべる", "タベル"]);
+        assert_eq!(fuzzy_forms("ﾃｽﾄ"), ["ﾃｽﾄ", "てすと", "テスト"]);
+        let decomposed: String = "が".nfd().collect();
+        assert_eq!(
+            fuzzy_forms(&decomposed),
+            [decomposed.clone(), "が".into(), "ガ".into()]
+        );
+        assert!(fuzzy_forms("").is_empty());
+    }
+
+    #[test]
+    fn test_query_display_like() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = AudioDB::new(create_test_db(&dir)).unwrap();
+
+        let entries = db.query_display_like("%たべる%", None, 10).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].source, "nhk16");
+        // ASCII matches in either case
+        assert_eq!(db.query_display_like("jpod%", None, 10).unwrap().len(), 2);
+        assert_eq!(db.query_display_like("jpod%", None, 1).unwrap().len(), 1);
+        assert!(db
+            .query_display_like("%", Some("forvo"), 10)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            db.query_display_like("%", Some("nhk16"), 10).unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_list_speakers() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = AudioDB::new(create_test_db(&dir)).unwrap();
+
+        let speakers = db.list_speakers().unwrap();
+        assert_eq!(speakers.len(), 3);
+        assert_eq!(
+            speakers[0],
+            SpeakerStats {
+                source: "forvo".to_string(),
+                speaker: Some("strawberrybrown".to_string()),
+                entry_count: 1,
+            }
+        );
+        assert_eq!(speakers[1].source, "jpod");
+        assert_eq!(speakers[1].speaker, None);
+        assert_eq!(speakers[1].entry_count, 2);
+    }
+
     #[test]
     fn test_query_by_term_and_reading() {
         if let Some(db_path) = resolve_db_path() {
@@ -0,0 +1,126 @@
+use anyhow::Result;
+use camino::Utf8PathBuf as PathBuf;
+use rusqlite::{Connection, OpenFlags};
+use std::ops::Deref;
+use std::sync::Mutex;
+
+/// Idle connections kept open by a [`ConnectionPool`]. More are opened when
+/// more queries run at once, and closed again when they're done.
+pub(crate) const MAX_IDLE_CONNECTIONS: usize = 8;
+
+/// Read-only connections to one database, so concurrent queries each get
+/// their own instead of waiting for a shared one
+pub(crate) struct ConnectionPool {
+    path: PathBuf,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl ConnectionPool {
+    /// Open the first connection right away, so a missing or unreadable
+    /// database fails here rather than on the first query
+    pub(crate) fn open(path: PathBuf) -> Result<Self> {
+        let conn = Self::connect(&path)?;
+        Ok(Self {
+            path,
+            idle: Mutex::new(vec![conn]),
+        })
+    }
+
+    fn connect(path: &PathBuf) -> Result<Connection> {
+        Ok(Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX
+                | OpenFlags::SQLITE_OPEN_URI,
+        )?)
+    }
+
+    /// An idle connection, or a new one if all are in use
+    pub(crate) fn get(&self) -> Result<PooledConnection<'_>> {
+        let idle = self
+            .idle
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire connection pool lock: {e}"))?
+            .pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => Self::connect(&self.path)?,
+        };
+        Ok(PooledConnection {
+            pool: self,
+            conn: Some(conn),
+        })
+    }
+
+    #[cfg(test)]
+    fn idle_count(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+}
+
+/// A connection of a [`ConnectionPool`], returned to it when dropped
+pub(crate) struct PooledConnection<'a> {
+    pool: &'a ConnectionPool,
+    conn: Option<Connection>,
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+            .as_ref()
+            .expect("connection is only taken on drop")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        let Some(conn) = self.conn.take() else {
+            return;
+        };
+        if let Ok(mut idle) = self.pool.idle.lock() {
+            if idle.len() < MAX_IDLE_CONNECTIONS {
+                idle.push(conn);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connections_are_reused() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = PathBuf::from_path_buf(dir.path().join("entries.db")).unwrap();
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch("CREATE TABLE entries (id INTEGER PRIMARY KEY)")
+            .unwrap();
+
+        let pool = ConnectionPool::open(path).unwrap();
+        {
+            let first = pool.get().unwrap();
+            let second = pool.get().unwrap();
+            assert_eq!(pool.idle_count(), 0);
+            let count = |conn: &Connection| {
+                conn.query_row("SELECT COUNT(*) FROM entries", [], |row| {
+                    row.get::<_, i64>(0)
+                })
+                .unwrap()
+            };
+            assert_eq!(count(&first), count(&second));
+        }
+        assert_eq!(pool.idle_count(), 2);
+
+        let conns: Vec<_> = (0..MAX_IDLE_CONNECTIONS + 2)
+            .map(|_| pool.get().unwrap())
+            .collect();
+        drop(conns);
+        assert_eq!(pool.idle_count(), MAX_IDLE_CONNECTIONS);
+
+        assert!(ConnectionPool::open(PathBuf::from("/nonexistent/entries.db")).is_err());
+    }
+}
@@ -0,0 +1,246 @@
+use anyhow::{bail, Result};
+use camino::Utf8Path as Path;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+/// The fields of an entry that can be written, see [`AudioDBWriter`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewAudioEntry {
+    pub expression: String,
+    pub reading: Option<String>,
+    pub source: String,
+    pub speaker: Option<String>,
+    pub display: Option<String>,
+    /// Path of the recording relative to the source's directory
+    pub file: String,
+}
+
+impl NewAudioEntry {
+    fn validate(&self) -> Result<()> {
+        if self.expression.trim().is_empty() {
+            bail!("Audio entry expression must not be empty");
+        }
+        if self.source.trim().is_empty() {
+            bail!("Audio entry source must not be empty");
+        }
+        if self.file.trim().is_empty() {
+            bail!("Audio entry file must not be empty");
+        }
+        Ok(())
+    }
+}
+
+/// Read-write access to an audio database, for adding single entries (e.g.
+/// personal recordings) without re-running the bootstrap.
+///
+/// [`AudioDB`](crate::AudioDB) instances on the same file see the changes on
+/// their next query. Metadata columns (duration, peaks, gain) of written
+/// entries are left empty.
+pub struct AudioDBWriter {
+    conn: Mutex<Connection>,
+}
+
+impl AudioDBWriter {
+    /// Open the database at `path` for writing, creating it with the
+    /// bootstrap's schema if it doesn't exist yet
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let conn = Connection::open(path.as_ref())?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS entries (
+                id INTEGER PRIMARY KEY,
+                expression TEXT NOT NULL,
+                reading TEXT,
+                source TEXT NOT NULL,
+                speaker TEXT,
+                display TEXT,
+                file TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_all ON entries (expression, reading, source);
+            CREATE INDEX IF NOT EXISTS idx_reading ON entries (reading);",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> Result<std::sync::MutexGuard<'_, Connection>> {
+        self.conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire connection lock: {e}"))
+    }
+
+    /// Add an entry, returning its id
+    pub fn insert(&self, entry: &NewAudioEntry) -> Result<i64> {
+        entry.validate()?;
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO entries (expression, reading, source, speaker, display, file)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                entry.expression,
+                entry.reading,
+                entry.source,
+                entry.speaker,
+                entry.display,
+                entry.file
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Add the entries that aren't in the database yet, in one transaction.
+    /// An entry is already there if one with the same expression, reading,
+    /// source and file exists. Returns how many were added.
+    pub fn insert_new(&self, entries: &[NewAudioEntry]) -> Result<usize> {
+        for entry in entries {
+            entry.validate()?;
+        }
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let mut added = 0;
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO entries (expression, reading, source, speaker, display, file)
+                 SELECT ?1, ?2, ?3, ?4, ?5, ?6
+                 WHERE NOT EXISTS (
+                     SELECT 1 FROM entries
+                     WHERE expression = ?1 AND reading IS ?2 AND source = ?3 AND file = ?6
+                 )",
+            )?;
+            for entry in entries {
+                added += insert.execute(params![
+                    entry.expression,
+                    entry.reading,
+                    entry.source,
+                    entry.speaker,
+                    entry.display,
+                    entry.file
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Replace the fields of entry `id`. Returns false if there is no such entry.
+    pub fn update(&self, id: i64, entry: &NewAudioEntry) -> Result<bool> {
+        entry.validate()?;
+        let updated = self.conn()?.execute(
+            "UPDATE entries
+             SET expression = ?2, reading = ?3, source = ?4, speaker = ?5, display = ?6, file = ?7
+             WHERE id = ?1",
+            params![
+                id,
+                entry.expression,
+                entry.reading,
+                entry.source,
+                entry.speaker,
+                entry.display,
+                entry.file
+            ],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Remove entry `id`. Returns false if there is no such entry.
+    pub fn delete(&self, id: i64) -> Result<bool> {
+        let deleted = self
+            .conn()?
+            .execute("DELETE FROM entries WHERE id = ?1", [id])?;
+        Ok(deleted > 0)
+    }
+
+    /// Remove every entry of `source`, returning how many were removed
+    pub fn delete_source(&self, source: &str) -> Result<usize> {
+        Ok(self
+            .conn()?
+            .execute("DELETE FROM entries WHERE source = ?1", [source])?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AudioDB;
+    use camino::Utf8PathBuf as PathBuf;
+
+    fn recording(expression: &str, file: &str) -> NewAudioEntry {
+        NewAudioEntry {
+            expression: expression.to_string(),
+            reading: Some("たべる".to_string()),
+            source: "personal".to_string(),
+            speaker: Some("me".to_string()),
+            display: None,
+            file: file.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_writer_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db_path = PathBuf::from_path_buf(dir.path().join("entries.db")).unwrap();
+        let writer = AudioDBWriter::open(&db_path).unwrap();
+        let db = AudioDB::new(&db_path).unwrap();
+
+        let id = writer.insert(&recording("食べる", "taberu.opus")).unwrap();
+        writer
+            .insert(&recording("食べる", "taberu-2.opus"))
+            .unwrap();
+        assert_eq!(db.query_by_term("食べる").unwrap().len(), 2);
+
+        let mut updated = recording("食べる", "taberu-slow.opus");
+        updated.display = Some("Slow".to_string());
+        assert!(writer.update(id, &updated).unwrap());
+        let entry = db
+            .query_by_term("食べる")
+            .unwrap()
+            .into_iter()
+            .find(|e| e.id == id)
+            .unwrap();
+        assert_eq!(entry.file, "taberu-slow.opus");
+        assert_eq!(entry.display.as_deref(), Some("Slow"));
+
+        assert!(writer.delete(id).unwrap());
+        assert!(!writer.delete(id).unwrap());
+        assert!(!writer.update(id, &updated).unwrap());
+        assert_eq!(writer.delete_source("personal").unwrap(), 1);
+        assert!(db.query_by_term("食べる").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_insert_new_skips_existing_entries() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db_path = PathBuf::from_path_buf(dir.path().join("entries.db")).unwrap();
+        let writer = AudioDBWriter::open(&db_path).unwrap();
+        let db = AudioDB::new(&db_path).unwrap();
+
+        writer.insert(&recording("食べる", "taberu.opus")).unwrap();
+        let mut no_reading = recording("食べる", "taberu.opus");
+        no_reading.reading = None;
+        let batch = [
+            recording("食べる", "taberu.opus"),
+            recording("食べる", "taberu-2.opus"),
+            no_reading.clone(),
+        ];
+        assert_eq!(writer.insert_new(&batch).unwrap(), 2);
+        assert_eq!(writer.insert_new(&batch).unwrap(), 0);
+        assert_eq!(db.query_by_term("食べる").unwrap().len(), 3);
+
+        // Nothing is added if any entry is incomplete
+        assert!(writer
+            .insert_new(&[recording("飲む", "nomu.opus"), recording("", "x.opus")])
+            .is_err());
+        assert!(db.query_by_term("飲む").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_writer_rejects_incomplete_entries() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db_path = PathBuf::from_path_buf(dir.path().join("entries.db")).unwrap();
+        let writer = AudioDBWriter::open(&db_path).unwrap();
+
+        assert!(writer.insert(&recording(" ", "taberu.opus")).is_err());
+        assert!(writer.insert(&recording("食べる", "")).is_err());
+    }
+}
@@ -66,13 +66,6 @@ if [ ! -f "./target/release/audio-db-bootstrap" ]; then
     cargo build --release -p audio-db-bootstrap
 fi
 
-# Set up environment variables
-export LOCAL_AUDIO_YOMICHAN_DATA_DIR="$AUDIO_FILES_PATH"
-if [ -n "$CONFIG_PATH" ]; then
-    CONFIG_DIR=$(dirname "$CONFIG_PATH")
-    export LOCAL_AUDIO_YOMICHAN_CONFIG_DIR="$CONFIG_DIR"
-fi
-
 # Build the command
 CMD="./target/release/audio-db-bootstrap -a \"$AUDIO_FILES_PATH\" -o \"$OUTPUT_DB_PATH\" -v"
 if [ -n "$CONFIG_PATH" ]; then
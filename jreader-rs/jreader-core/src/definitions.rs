@@ -0,0 +1,42 @@
+//! Plain text rendering of dictionary definitions.
+
+use yomitan_format::json_schema::structured_content;
+use yomitan_format::json_schema::term_bank_v3::Definition;
+
+/// Plain text of a dictionary definition, for exports that can't render
+/// structured content
+pub fn definition_text(definition: &Definition) -> String {
+    match definition {
+        Definition::Simple(s) => s.clone(),
+        Definition::Structured(s) => s
+            .content
+            .as_ref()
+            .map(structured_content::plain_text)
+            .unwrap_or_default(),
+        Definition::Deinflection(d) => d.base_form.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_definition_text() {
+        let structured: Definition = serde_json::from_value(serde_json::json!({
+            "type": "structured-content",
+            "content": [
+                {"tag": "ul", "content": [
+                    {"tag": "li", "content": "magic"},
+                    {"tag": "li", "content": ["sorcery", {"tag": "span", "content": " (arch.)"}]}
+                ]}
+            ]
+        }))
+        .unwrap();
+        assert_eq!(definition_text(&structured), "magic\nsorcery (arch.)");
+        assert_eq!(
+            definition_text(&Definition::Simple("to eat".to_string())),
+            "to eat"
+        );
+    }
+}
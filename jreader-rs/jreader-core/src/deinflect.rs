@@ -0,0 +1,594 @@
+//! Rule-based deinflection of Japanese verbs and adjectives, explaining how
+//! an inflected form in the text (食べられた) relates to the dictionary form
+//! that was looked up (食べる) as a chain of [`InflectionReason`]s.
+//!
+//! The tokenizer already finds dictionary forms; the rules here only name the
+//! steps in between. They're written from the dictionary form outwards, so
+//! the same table can be applied in reverse to conjugate a word.
+
+use std::collections::HashSet;
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+use wana_kana::ConvertJapanese;
+
+use crate::mecab::TokenFeature;
+
+/// Word classes of the forms a rule applies to, as bit flags
+pub mod word_class {
+    /// Ichidan verbs (食べる)
+    pub const V1: u8 = 1;
+    /// Godan verbs (書く)
+    pub const V5: u8 = 1 << 1;
+    /// する and する verbs
+    pub const VS: u8 = 1 << 2;
+    /// 来る
+    pub const VK: u8 = 1 << 3;
+    /// I-adjectives, and auxiliaries that inflect like them (ない, たい)
+    pub const ADJ_I: u8 = 1 << 4;
+    /// て-forms, which continue with auxiliary verbs (ている)
+    pub const TE: u8 = 1 << 5;
+    pub const VERB: u8 = V1 | V5 | VS | VK;
+
+    /// Class of a Yomitan rule identifier ("v1", "v5", "vs", "vk", "adj-i"),
+    /// also accepting JMdict's finer ones ("v5k", "vs-i")
+    pub fn from_rule_identifier(identifier: &str) -> Option<u8> {
+        let identifier = identifier.trim();
+        if identifier == "adj-i" {
+            Some(ADJ_I)
+        } else if identifier.starts_with("v1") {
+            Some(V1)
+        } else if identifier.starts_with("v5") {
+            Some(V5)
+        } else if identifier.starts_with("vs") {
+            Some(VS)
+        } else if identifier.starts_with("vk") {
+            Some(VK)
+        } else {
+            None
+        }
+    }
+
+    /// Yomitan rule identifier of a single class
+    pub fn rule_identifier(class: u8) -> Option<&'static str> {
+        match class {
+            V1 => Some("v1"),
+            V5 => Some("v5"),
+            VS => Some("vs"),
+            VK => Some("vk"),
+            ADJ_I => Some("adj-i"),
+            _ => None,
+        }
+    }
+}
+
+use word_class::*;
+use InflectionReason::*;
+
+/// One step of an inflection chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InflectionReason {
+    Negative,
+    Past,
+    Te,
+    Polite,
+    /// The ます stem (連用形)
+    Continuative,
+    Passive,
+    Causative,
+    Potential,
+    PotentialOrPassive,
+    Volitional,
+    Imperative,
+    /// ～ば
+    Provisional,
+    /// ～たら
+    Conditional,
+    /// ～たい
+    Desiderative,
+    /// ～く
+    Adverbial,
+    /// ～ている
+    Progressive,
+    /// ～てしまう, ～ちゃう
+    Completion,
+    /// A reason named by a dictionary that isn't one of the above
+    Other,
+}
+
+impl InflectionReason {
+    /// Key of the reason's name in the frontend's translations
+    pub fn localization_key(self) -> &'static str {
+        match self {
+            InflectionReason::Negative => "inflection.negative",
+            InflectionReason::Past => "inflection.past",
+            InflectionReason::Te => "inflection.te",
+            InflectionReason::Polite => "inflection.polite",
+            InflectionReason::Continuative => "inflection.continuative",
+            InflectionReason::Passive => "inflection.passive",
+            InflectionReason::Causative => "inflection.causative",
+            InflectionReason::Potential => "inflection.potential",
+            InflectionReason::PotentialOrPassive => "inflection.potentialOrPassive",
+            InflectionReason::Volitional => "inflection.volitional",
+            InflectionReason::Imperative => "inflection.imperative",
+            InflectionReason::Provisional => "inflection.provisional",
+            InflectionReason::Conditional => "inflection.conditional",
+            InflectionReason::Desiderative => "inflection.desiderative",
+            InflectionReason::Adverbial => "inflection.adverbial",
+            InflectionReason::Progressive => "inflection.progressive",
+            InflectionReason::Completion => "inflection.completion",
+            InflectionReason::Other => "inflection.other",
+        }
+    }
+
+    /// English name, as Yomitan dictionaries write it
+    pub fn label(self) -> &'static str {
+        match self {
+            InflectionReason::Negative => "negative",
+            InflectionReason::Past => "past",
+            InflectionReason::Te => "-te",
+            InflectionReason::Polite => "polite",
+            InflectionReason::Continuative => "masu stem",
+            InflectionReason::Passive => "passive",
+            InflectionReason::Causative => "causative",
+            InflectionReason::Potential => "potential",
+            InflectionReason::PotentialOrPassive => "potential or passive",
+            InflectionReason::Volitional => "volitional",
+            InflectionReason::Imperative => "imperative",
+            InflectionReason::Provisional => "-ba",
+            InflectionReason::Conditional => "-tara",
+            InflectionReason::Desiderative => "-tai",
+            InflectionReason::Adverbial => "-ku",
+            InflectionReason::Progressive => "progressive or perfect",
+            InflectionReason::Completion => "-chau",
+            InflectionReason::Other => "other",
+        }
+    }
+
+    /// The reason a dictionary's deinflection entry names, or `Other`
+    pub fn from_name(name: &str) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "negative" => InflectionReason::Negative,
+            "past" => InflectionReason::Past,
+            "-te" | "te" => InflectionReason::Te,
+            "polite" => InflectionReason::Polite,
+            "masu stem" | "continuative" => InflectionReason::Continuative,
+            "passive" => InflectionReason::Passive,
+            "causative" => InflectionReason::Causative,
+            "potential" => InflectionReason::Potential,
+            "potential or passive" => InflectionReason::PotentialOrPassive,
+            "volitional" => InflectionReason::Volitional,
+            "imperative" => InflectionReason::Imperative,
+            "-ba" | "ba" | "provisional" => InflectionReason::Provisional,
+            "-tara" | "tara" | "conditional" => InflectionReason::Conditional,
+            "-tai" | "tai" | "desiderative" => InflectionReason::Desiderative,
+            "-ku" | "ku" | "adverbial" => InflectionReason::Adverbial,
+            "progressive or perfect" | "progressive" => InflectionReason::Progressive,
+            "-chau" | "-shimau" | "completion" => InflectionReason::Completion,
+            _ => InflectionReason::Other,
+        }
+    }
+}
+
+/// An inflection reason as sent to clients
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Inflection {
+    pub reason: InflectionReason,
+    pub localization_key: &'static str,
+    /// Name of the reason, as the dictionary wrote it for `Other`
+    pub label: String,
+}
+
+impl From<InflectionReason> for Inflection {
+    fn from(reason: InflectionReason) -> Self {
+        Self {
+            reason,
+            localization_key: reason.localization_key(),
+            label: reason.label().to_string(),
+        }
+    }
+}
+
+impl Inflection {
+    pub fn from_name(name: &str) -> Self {
+        match InflectionReason::from_name(name) {
+            InflectionReason::Other => Self {
+                label: name.to_string(),
+                ..InflectionReason::Other.into()
+            },
+            reason => reason.into(),
+        }
+    }
+}
+
+/// Replacing the suffix `base` of a form of class `rules_out` with
+/// `inflected` gives a form of class `rules_in` (0 for forms that don't
+/// inflect further)
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub inflected: String,
+    pub base: String,
+    pub rules_in: u8,
+    pub rules_out: u8,
+    pub reasons: &'static [InflectionReason],
+}
+
+/// Endings after the ます stem
+const POLITE_ENDINGS: &[(&str, &[InflectionReason])] = &[
+    ("ます", &[Polite]),
+    ("ました", &[Polite, Past]),
+    ("ません", &[Polite, Negative]),
+    ("ませんでした", &[Polite, Negative, Past]),
+    ("ましょう", &[Polite, Volitional]),
+    ("まして", &[Polite, Te]),
+];
+
+/// Ichidan endings after the stem (the dictionary form without る), with the
+/// class of the result
+const ICHIDAN_ENDINGS: &[(&str, u8, &[InflectionReason])] = &[
+    ("ない", ADJ_I, &[Negative]),
+    ("た", 0, &[Past]),
+    ("て", TE, &[Te]),
+    ("られる", V1, &[PotentialOrPassive]),
+    ("れる", V1, &[Potential]),
+    ("させる", V1, &[Causative]),
+    ("よう", 0, &[Volitional]),
+    ("ろ", 0, &[Imperative]),
+    ("よ", 0, &[Imperative]),
+    ("れば", 0, &[Provisional]),
+    ("たら", 0, &[Conditional]),
+    ("たい", ADJ_I, &[Desiderative]),
+];
+
+/// I-adjective endings after the stem, also used by ない and たい
+const ADJECTIVE_ENDINGS: &[(&str, u8, &[InflectionReason])] = &[
+    ("くない", ADJ_I, &[Negative]),
+    ("かった", 0, &[Past]),
+    ("くて", 0, &[Te]),
+    ("ければ", 0, &[Provisional]),
+    ("かったら", 0, &[Conditional]),
+    ("く", 0, &[Adverbial]),
+];
+
+/// Godan endings: dictionary form, a/i/e/o stems, て-form and past
+const GODAN_ROWS: &[[&str; 7]] = &[
+    ["う", "わ", "い", "え", "お", "って", "った"],
+    ["く", "か", "き", "け", "こ", "いて", "いた"],
+    ["ぐ", "が", "ぎ", "げ", "ご", "いで", "いだ"],
+    ["す", "さ", "し", "せ", "そ", "して", "した"],
+    ["つ", "た", "ち", "て", "と", "って", "った"],
+    ["ぬ", "な", "に", "ね", "の", "んで", "んだ"],
+    ["ぶ", "ば", "び", "べ", "ぼ", "んで", "んだ"],
+    ["む", "ま", "み", "め", "も", "んで", "んだ"],
+    ["る", "ら", "り", "れ", "ろ", "って", "った"],
+];
+
+/// Stems of the forms of する and 来る: dictionary form, negative (a), ます
+/// (i), conditional (e), volitional (o) stems, passive and causative
+const IRREGULAR_VERBS: &[(u8, [&str; 5])] = &[
+    (VS, ["する", "し", "し", "すれ", "しよ"]),
+    (VK, ["くる", "こ", "き", "くれ", "こよ"]),
+    (VK, ["来る", "来", "来", "来れ", "来よ"]),
+];
+
+fn rules() -> Vec<Rule> {
+    let mut rules = Vec::new();
+    let mut add = |inflected: String,
+                   base: &str,
+                   rules_in: u8,
+                   rules_out: u8,
+                   reasons: &'static [InflectionReason]| {
+        rules.push(Rule {
+            inflected,
+            base: base.to_string(),
+            rules_in,
+            rules_out,
+            reasons,
+        })
+    };
+
+    for (ending, rules_in, reasons) in ICHIDAN_ENDINGS {
+        add(ending.to_string(), "る", *rules_in, V1, reasons);
+    }
+    for (ending, reasons) in POLITE_ENDINGS {
+        add(ending.to_string(), "る", 0, V1, reasons);
+    }
+
+    for [u, a, i, e, o, te, ta] in GODAN_ROWS {
+        add(format!("{a}ない"), u, ADJ_I, V5, &[Negative]);
+        add(ta.to_string(), u, 0, V5, &[Past]);
+        add(te.to_string(), u, TE, V5, &[Te]);
+        add(format!("{ta}ら"), u, 0, V5, &[Conditional]);
+        add(i.to_string(), u, 0, V5, &[Continuative]);
+        add(format!("{a}れる"), u, V1, V5, &[Passive]);
+        add(format!("{a}せる"), u, V1, V5, &[Causative]);
+        add(format!("{e}る"), u, V1, V5, &[Potential]);
+        add(format!("{o}う"), u, 0, V5, &[Volitional]);
+        add(e.to_string(), u, 0, V5, &[Imperative]);
+        add(format!("{e}ば"), u, 0, V5, &[Provisional]);
+        add(format!("{i}たい"), u, ADJ_I, V5, &[Desiderative]);
+        for (ending, reasons) in POLITE_ENDINGS {
+            add(format!("{i}{ending}"), u, 0, V5, reasons);
+        }
+    }
+    // 行く is the one godan verb in く with an って て-form
+    for iku in ["行く", "いく"] {
+        let stem = iku.trim_end_matches('く');
+        add(format!("{stem}って"), iku, TE, V5, &[Te]);
+        add(format!("{stem}った"), iku, 0, V5, &[Past]);
+        add(format!("{stem}ったら"), iku, 0, V5, &[Conditional]);
+    }
+
+    for (class, [base, a, i, e, o]) in IRREGULAR_VERBS {
+        let class = *class;
+        add(format!("{a}ない"), base, ADJ_I, class, &[Negative]);
+        add(format!("{i}た"), base, 0, class, &[Past]);
+        add(format!("{i}て"), base, TE, class, &[Te]);
+        add(format!("{i}たら"), base, 0, class, &[Conditional]);
+        add(format!("{i}たい"), base, ADJ_I, class, &[Desiderative]);
+        add(format!("{e}ば"), base, 0, class, &[Provisional]);
+        add(format!("{o}う"), base, 0, class, &[Volitional]);
+        for (ending, reasons) in POLITE_ENDINGS {
+            add(format!("{i}{ending}"), base, 0, class, reasons);
+        }
+        if class == VS {
+            add("される".to_string(), base, V1, class, &[Passive]);
+            add("させる".to_string(), base, V1, class, &[Causative]);
+            add("しろ".to_string(), base, 0, class, &[Imperative]);
+            add("せよ".to_string(), base, 0, class, &[Imperative]);
+            add("し".to_string(), base, 0, class, &[Continuative]);
+        } else {
+            add(format!("{a}られる"), base, V1, class, &[PotentialOrPassive]);
+            add(format!("{a}させる"), base, V1, class, &[Causative]);
+            add(format!("{a}い"), base, 0, class, &[Imperative]);
+        }
+    }
+
+    for (ending, rules_in, reasons) in ADJECTIVE_ENDINGS {
+        add(ending.to_string(), "い", *rules_in, ADJ_I, reasons);
+    }
+
+    // Auxiliary verbs after the て-form
+    for (te, contracted_completion) in [("て", "ちゃう"), ("で", "じゃう")] {
+        add(format!("{te}いる"), te, V1, TE, &[Progressive]);
+        add(format!("{te}る"), te, V1, TE, &[Progressive]);
+        add(format!("{te}しまう"), te, V5, TE, &[Completion]);
+        add(contracted_completion.to_string(), te, V5, TE, &[Completion]);
+    }
+
+    rules
+}
+
+lazy_static! {
+    pub static ref RULES: Vec<Rule> = rules();
+}
+
+/// Chains of rules are short in practice; this only guards against cycles
+const MAX_DEINFLECTION_DEPTH: usize = 8;
+
+/// A possible uninflected form of a word
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deinflection {
+    pub term: String,
+    /// Word classes the form must belong to, or 0 for the original text
+    pub rules: u8,
+    /// Steps from `term` out to the original text
+    pub reasons: Vec<InflectionReason>,
+}
+
+/// Every form `source` can be deinflected to, starting with `source` itself
+pub fn deinflect(source: &str) -> Vec<Deinflection> {
+    let mut results = vec![Deinflection {
+        term: source.to_string(),
+        rules: 0,
+        reasons: Vec::new(),
+    }];
+    let mut seen = HashSet::from([(source.to_string(), 0u8)]);
+    let mut i = 0;
+    while i < results.len() {
+        let current = results[i].clone();
+        i += 1;
+        if current.reasons.len() >= MAX_DEINFLECTION_DEPTH {
+            continue;
+        }
+        for rule in RULES.iter() {
+            if current.rules != 0 && current.rules & rule.rules_in == 0 {
+                continue;
+            }
+            let Some(stem) = current.term.strip_suffix(rule.inflected.as_str()) else {
+                continue;
+            };
+            if stem.is_empty() && rule.base.chars().count() < 2 {
+                continue;
+            }
+            let term = format!("{stem}{}", rule.base);
+            if !seen.insert((term.clone(), rule.rules_out)) {
+                continue;
+            }
+            let mut reasons = rule.reasons.to_vec();
+            reasons.extend(&current.reasons);
+            results.push(Deinflection {
+                term,
+                rules: rule.rules_out,
+                reasons,
+            });
+        }
+    }
+    results
+}
+
+/// Shortest chain of inflections from `dictionary_form` to `form`
+pub fn inflection_chain(form: &str, dictionary_form: &str) -> Option<Vec<InflectionReason>> {
+    deinflect(form)
+        .into_iter()
+        .filter(|d| d.rules & (VERB | ADJ_I) != 0 || d.rules == 0)
+        .filter(|d| d.term == dictionary_form)
+        .map(|d| d.reasons)
+        .min_by_key(Vec::len)
+}
+
+/// Forms one rule away from `term` of class `class`: the form, its class and
+/// the reason. Where rules for the same reason overlap, the most specific
+/// one wins (行って over 行いて).
+fn inflect(term: &str, class: u8) -> Vec<(String, u8, &'static [InflectionReason])> {
+    let matching: Vec<&Rule> = RULES
+        .iter()
+        .filter(|rule| rule.rules_out & class != 0 && term.ends_with(rule.base.as_str()))
+        .collect();
+    matching
+        .iter()
+        .filter(|rule| {
+            !matching
+                .iter()
+                .any(|other| other.reasons == rule.reasons && other.base.len() > rule.base.len())
+        })
+        .map(|rule| {
+            let stem = &term[..term.len() - rule.base.len()];
+            (
+                format!("{stem}{}", rule.inflected),
+                rule.rules_in,
+                rule.reasons,
+            )
+        })
+        .collect()
+}
+
+/// The conjugation table of a verb or adjective of class `class`: every form
+/// the rules produce, along with the past, て and conditional forms of its
+/// negative and たい forms
+pub fn conjugate(term: &str, class: u8) -> Vec<(String, Vec<InflectionReason>)> {
+    let mut forms = Vec::new();
+    for (form, rules_in, reasons) in inflect(term, class) {
+        if rules_in & ADJ_I != 0 {
+            for (further, further_rules_in, further_reasons) in inflect(&form, ADJ_I) {
+                if further_rules_in == 0 {
+                    let chain = reasons.iter().chain(further_reasons).copied().collect();
+                    forms.push((further, chain));
+                }
+            }
+        }
+        forms.push((form, reasons.to_vec()));
+    }
+    forms.sort_by_key(|(_, reasons)| reasons.len());
+    forms
+}
+
+/// How an entry found by a lookup relates to the text that was looked up
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryMatch {
+    /// The text the entry was found for, with its inflections
+    pub matched_form: String,
+    pub inflections: Vec<Inflection>,
+}
+
+/// The form in the text that `term` was found for, among the tokens of a
+/// lookup, and the inflections leading to it
+pub fn match_entry(token_features: &[TokenFeature], term: &str) -> Option<EntryMatch> {
+    for feature in token_features {
+        let Some(surface) = &feature.surface_form else {
+            continue;
+        };
+        let found_by_surface = surface == term || surface.to_hiragana() == term;
+        if !found_by_surface && feature.dictionary_form.as_deref() != Some(term) {
+            continue;
+        }
+        let forms = feature.inflected_form.iter().chain([surface]);
+        for form in forms {
+            if let Some(chain) = inflection_chain(form, term) {
+                return Some(EntryMatch {
+                    matched_form: form.clone(),
+                    inflections: chain.into_iter().map(Inflection::from).collect(),
+                });
+            }
+        }
+        return Some(EntryMatch {
+            matched_form: surface.clone(),
+            inflections: Vec::new(),
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_inflection_chain() {
+        let chain = |form, term| inflection_chain(form, term).unwrap();
+        assert_eq!(chain("食べられた", "食べる"), [PotentialOrPassive, Past]);
+        assert_eq!(chain("食べなかった", "食べる"), [Negative, Past]);
+        assert_eq!(
+            chain("書かれていた", "書く"),
+            [Passive, Te, Progressive, Past]
+        );
+        assert_eq!(chain("読みませんでした", "読む"), [Polite, Negative, Past]);
+        assert_eq!(chain("行っちゃう", "行く"), [Te, Completion]);
+        assert_eq!(
+            chain("勉強させられる", "勉強する"),
+            [Causative, PotentialOrPassive]
+        );
+        assert_eq!(chain("来なかった", "来る"), [Negative, Past]);
+        assert_eq!(chain("高くなければ", "高い"), [Negative, Provisional]);
+        assert_eq!(chain("猫", "猫"), []);
+        assert_eq!(inflection_chain("食べた", "食う"), None);
+    }
+
+    #[test]
+    fn test_conjugate() {
+        let forms: HashMap<String, Vec<InflectionReason>> =
+            conjugate("打つ", V5).into_iter().collect();
+        assert_eq!(forms["打って"], [Te]);
+        assert_eq!(forms["打たない"], [Negative]);
+        assert_eq!(forms["打たなかった"], [Negative, Past]);
+        assert_eq!(forms["打てる"], [Potential]);
+        assert_eq!(forms["打ちました"], [Polite, Past]);
+        // Every generated form deinflects back to the dictionary form
+        for (form, reasons) in &forms {
+            assert_eq!(
+                inflection_chain(form, "打つ").as_ref(),
+                Some(reasons),
+                "{form}"
+            );
+        }
+
+        let forms: Vec<String> = conjugate("行く", V5).into_iter().map(|(f, _)| f).collect();
+        assert!(forms.contains(&"行って".to_string()));
+        assert!(!forms.contains(&"行いて".to_string()));
+
+        let forms: HashMap<String, Vec<InflectionReason>> =
+            conjugate("高い", ADJ_I).into_iter().collect();
+        assert_eq!(forms["高くなかった"], [Negative, Past]);
+        assert!(conjugate("猫", V5).is_empty());
+        assert_eq!(word_class::from_rule_identifier("v5k"), Some(V5));
+    }
+
+    #[test]
+    fn test_inflection_from_name() {
+        assert_eq!(Inflection::from_name("past"), Past.into());
+        let unknown = Inflection::from_name("humble");
+        assert_eq!(unknown.reason, Other);
+        assert_eq!(unknown.label, "humble");
+        assert_eq!(unknown.localization_key, "inflection.other");
+    }
+
+    #[test]
+    fn test_match_entry() {
+        let mut verb = TokenFeature::from_feature_string(
+            "書か",
+            "動詞,自立,*,*,五段・カ行イ音便,未然形,書く,カカ,カカ",
+        );
+        verb.inflected_form = Some("書かれた".to_string());
+        let features = [verb];
+
+        let found = match_entry(&features, "書く").unwrap();
+        assert_eq!(found.matched_form, "書かれた");
+        let reasons: Vec<_> = found.inflections.iter().map(|i| i.reason).collect();
+        assert_eq!(reasons, [Passive, Past]);
+        assert!(match_entry(&features, "書").is_none());
+    }
+}
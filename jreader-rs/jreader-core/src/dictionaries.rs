@@ -0,0 +1,1745 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::levels::JlptLevel;
+use crate::preferences::UserPreferences;
+use anyhow::{Context, Error, Result};
+use camino::{Utf8Path as Path, Utf8PathBuf as PathBuf};
+use tokio::task::JoinSet;
+use tracing::{debug, error, info, instrument, trace, warn};
+use uuid::Uuid;
+use wana_kana::{ConvertJapanese, IsJapaneseStr};
+use yomitan_format::json_schema::index::{DictionaryIndex, FrequencyMode};
+use yomitan_format::json_schema::kanji_bank_v3::{KanjiBankV3, KanjiEntry};
+use yomitan_format::json_schema::kanji_meta_bank_v3::KanjiMetaBankV3;
+use yomitan_format::json_schema::tag_bank_v3::TagBankV3;
+use yomitan_format::json_schema::term_bank_v3::{TermBankV3, TermEntry};
+use yomitan_format::json_schema::term_meta_bank_v3::{
+    FrequencyData as MetaFrequencyData, PitchData, TermMetaBankV3, TermMetaData, TermMetaEntry,
+};
+use yomitan_format::kv_store::db::DictionaryDB;
+use yomitan_format::kv_store::IsYomitanSchema;
+use yomitan_format::NormalizedPathBuf;
+
+use crate::mecab::TokenFeature;
+use crate::pitch;
+use crate::reverse_lookup;
+use serde::Serialize;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DictionaryInfo {
+    pub title: String,
+    pub revision: String,
+    pub dictionary_type: DictionaryType,
+}
+
+/// Credits of a dictionary as its index.json gives them, for deployments to
+/// show where licenses require it
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DictionaryAttribution {
+    pub title: String,
+    pub revision: String,
+    pub dictionary_type: DictionaryType,
+    pub author: Option<String>,
+    pub url: Option<String>,
+    /// Attribution text, which usually carries the license
+    pub attribution: Option<String>,
+}
+
+pub struct LookupResult {
+    pub dict: Vec<DictionaryResult>,
+    // dictionary_result.entries[i].text -> reading -> PitchResult
+    pub pitch: HashMap<String, HashMap<String, PitchResult>>,
+    pub freq: HashMap<String, Vec<FrequencyData>>,
+}
+
+#[derive(Debug)]
+pub struct DictionaryResult {
+    pub title: String,
+    pub revision: String,
+    pub origin: String,
+    pub entries: Vec<TermEntry>,
+    /// Entries for words guessed by the unknown word fallback
+    pub heuristic_entries: Vec<TermEntry>,
+}
+
+#[derive(Debug)]
+pub struct PitchResult {
+    pub title: String,
+    pub pitch_accents: PitchAccents,
+}
+
+#[derive(Debug)]
+pub struct FrequencyData {
+    pub term: String,
+    pub reading: Option<String>,
+    pub value: Option<i32>,
+    pub display_value: Option<String>,
+}
+
+/// Candidate headwords taken from each term dictionary before ranking
+const MAX_SUGGESTION_CANDIDATES: usize = 200;
+
+/// Rows of a term bank read for a search pattern starting with a wildcard
+const MAX_LEADING_WILDCARD_SCAN: usize = 100_000;
+
+/// Whether a search query uses wildcard syntax, `*` or `?` in half or full width
+fn is_wildcard_query(query: &str) -> bool {
+    query.contains(['*', '?', '＊', '？'])
+}
+
+/// Full-width wildcards, as typed with a Japanese IME, to their ASCII form
+fn normalize_wildcards(query: &str) -> String {
+    query
+        .chars()
+        .map(|c| match c {
+            '＊' => '*',
+            '？' => '?',
+            _ => c,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Suggestion {
+    pub term: String,
+    /// Harmonic mean of the term's ranks in the enabled rank-based frequency
+    /// dictionaries that list it
+    pub frequency_rank: Option<f64>,
+    /// Left for the caller to fill in from its level lists
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jlpt: Option<JlptLevel>,
+}
+
+/// Matches taken from each term dictionary before ranking
+const MAX_REVERSE_LOOKUP_CANDIDATES: usize = 200;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReverseLookupCandidate {
+    pub term: String,
+    pub reading: String,
+    /// The definitions whose glosses matched the query
+    pub senses: Vec<ReverseLookupSense>,
+    pub frequency_rank: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReverseLookupSense {
+    pub dictionary: String,
+    pub definition: String,
+}
+
+/// Lexemes of a lookup whose variants are gathered, per dictionary
+const MAX_RELATED_SEQUENCES: usize = 10;
+
+/// Spellings and readings of one lexeme: the entries of a term dictionary
+/// sharing a sequence number
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RelatedEntries {
+    pub dictionary: String,
+    pub sequence_number: i64,
+    /// The terms of the lookup results that belong to the lexeme
+    pub terms: Vec<String>,
+    /// Every form of the lexeme, by headword
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Hash)]
+pub struct Variant {
+    pub text: String,
+    pub reading: String,
+}
+
+/// One sample lookup against a dictionary that hasn't been imported yet
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SandboxLookup {
+    pub term: String,
+    pub found: bool,
+    pub duration_ms: f64,
+    /// Entries as they would be deserialized for a real lookup
+    pub entries: Vec<serde_json::Value>,
+    pub error: Option<String>,
+}
+
+/// How many terms of a word list an enabled term dictionary has entries for,
+/// see [`YomitanDictionaries::term_coverage`]
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DictionaryCoverage {
+    pub title: String,
+    pub revision: String,
+    pub found: usize,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TermCoverage {
+    /// Distinct terms of the list
+    pub total: usize,
+    /// Terms found in at least one of the dictionaries
+    pub covered: usize,
+    pub dictionaries: Vec<DictionaryCoverage>,
+}
+
+/// How long loading one dictionary took, see [`YomitanDictionaries::load`]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DictionaryLoadTiming {
+    pub directory: String,
+    pub title: String,
+    /// None if the type couldn't be identified, in which case it wasn't loaded
+    pub dictionary_type: Option<DictionaryType>,
+    pub duration_ms: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DictionaryLoadSummary {
+    pub concurrency: usize,
+    pub duration_ms: f64,
+    pub dictionaries: Vec<DictionaryLoadTiming>,
+}
+
+impl DictionaryLoadSummary {
+    /// The `n` dictionaries that took longest to load, slowest first
+    pub fn slowest(&self, n: usize) -> Vec<&DictionaryLoadTiming> {
+        let mut timings: Vec<_> = self.dictionaries.iter().collect();
+        timings.sort_by(|a, b| b.duration_ms.total_cmp(&a.duration_ms));
+        timings.truncate(n);
+        timings
+    }
+}
+
+/// Dictionaries loaded at a time by [`YomitanDictionaries::new`]: one per
+/// core, up to 8
+pub fn default_load_concurrency() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(8)
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+pub enum DictionaryType {
+    Term,
+    Pitch,
+    Frequency,
+    Kanji,
+}
+
+pub struct YomitanTermDictionary(pub YomitanDictionary);
+pub struct YomitanPitchDictionary(pub YomitanDictionary);
+pub struct YomitanFrequencyDictionary(pub YomitanDictionary);
+pub struct YomitanKanjiDictionary(pub YomitanDictionary);
+
+#[derive(Clone)]
+pub struct YomitanDictionaries {
+    terms: Vec<Arc<YomitanTermDictionary>>,
+    // TODO: Support multiple pitch dictionaries
+    pitch: Vec<Arc<YomitanPitchDictionary>>,
+    // TODO: Support multiple frequency dictionaries
+    freq: Vec<Arc<YomitanFrequencyDictionary>>,
+    kanji: Vec<Arc<YomitanKanjiDictionary>>,
+    /// Filled on first read and reset whenever the dictionaries change, as
+    /// anonymous lookups need them on every request
+    info: OnceLock<Arc<Vec<DictionaryInfo>>>,
+    default_preferences: OnceLock<Arc<UserPreferences>>,
+}
+
+impl YomitanDictionaries {
+    /// Load every dictionary in `dict_dir`, see [`YomitanDictionaries::load`]
+    pub fn new(dict_dir: &Path) -> Result<Self, Error> {
+        Ok(Self::load(dict_dir, default_load_concurrency())?.0)
+    }
+
+    /// Load every dictionary in `dict_dir`, up to `concurrency` at a time,
+    /// with how long each took. Creates the directory if it doesn't exist.
+    #[instrument]
+    pub fn load(dict_dir: &Path, concurrency: usize) -> Result<(Self, DictionaryLoadSummary)> {
+        let started = Instant::now();
+        let mut dictionaries = YomitanDictionaries {
+            terms: Vec::new(),
+            freq: Vec::new(),
+            pitch: Vec::new(),
+            kanji: Vec::new(),
+            info: OnceLock::new(),
+            default_preferences: OnceLock::new(),
+        };
+
+        if !dict_dir.exists() {
+            info!("Dictionary directory does not exist, creating");
+            std::fs::create_dir_all(dict_dir).map_err(|e| {
+                error!(?e, "Failed to create dictionary directory");
+                anyhow::anyhow!("Failed to create dictionary directory {dict_dir}: {e}")
+            })?;
+            return Ok((dictionaries, DictionaryLoadSummary::default()));
+        }
+
+        let mut dict_paths = Vec::new();
+        for dict_path in dict_dir
+            .read_dir()
+            .context("Failed to read dictionary directory")?
+        {
+            let Ok(dict_path) = dict_path else {
+                warn!("Skipping unreadable directory entry");
+                continue;
+            };
+            if dict_path.path().is_dir() {
+                dict_paths.push(PathBuf::try_from(dict_path.path())?);
+            }
+        }
+        // Registered in a fixed order whatever order they finish loading in
+        dict_paths.sort();
+
+        let concurrency = concurrency.clamp(1, dict_paths.len().max(1));
+        let next = AtomicUsize::new(0);
+        let mut loaded = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..concurrency)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut loaded = Vec::new();
+                        while let Some(dict_path) =
+                            dict_paths.get(next.fetch_add(1, Ordering::Relaxed))
+                        {
+                            trace!("🔍 Loading dictionary from: {dict_path}");
+                            let dict_started = Instant::now();
+                            let dict = YomitanDictionary::load(dict_path);
+                            loaded.push((dict_path, dict, dict_started.elapsed()));
+                        }
+                        loaded
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("Dictionary loading thread panicked"))
+                .collect::<Vec<_>>()
+        });
+        loaded.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut timings = Vec::new();
+        for (dict_path, dict, duration) in loaded {
+            let (dict, dict_type) = dict?;
+            let duration_ms = duration.as_secs_f64() * 1000.0;
+            timings.push(DictionaryLoadTiming {
+                directory: dict.origin.clone(),
+                title: dict.index.title.clone(),
+                dictionary_type: dict_type.as_ref().ok().cloned(),
+                duration_ms,
+            });
+            let Ok(dict_type) = dict_type else {
+                warn!(?dict_path, "Failed to identify dictionary type");
+                continue;
+            };
+            info!(
+                title = %dict.index.title,
+                revision = %dict.index.revision,
+                type_name = ?dict_type,
+                duration_ms,
+                "🔍 Successfully loaded dictionary"
+            );
+            dictionaries.add(dict, dict_type);
+        }
+
+        let summary = DictionaryLoadSummary {
+            concurrency,
+            duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+            dictionaries: timings,
+        };
+        info!(
+            term_count = %dictionaries.terms.len(),
+            freq_count = %dictionaries.freq.len(),
+            pitch_count = %dictionaries.pitch.len(),
+            kanji_count = %dictionaries.kanji.len(),
+            total_count = %summary.dictionaries.len(),
+            concurrency,
+            duration_ms = summary.duration_ms,
+            slowest = ?summary.slowest(3).iter().map(|t| (&t.title, t.duration_ms)).collect::<Vec<_>>(),
+            "Dictionary loading complete"
+        );
+        Ok((dictionaries, summary))
+    }
+
+    fn invalidate_caches(&mut self) {
+        self.info = OnceLock::new();
+        self.default_preferences = OnceLock::new();
+    }
+
+    fn add(&mut self, dict: YomitanDictionary, dict_type: DictionaryType) {
+        self.invalidate_caches();
+        match dict_type {
+            DictionaryType::Term => self.terms.push(Arc::new(YomitanTermDictionary(dict))),
+            DictionaryType::Frequency => self.freq.push(Arc::new(YomitanFrequencyDictionary(dict))),
+            DictionaryType::Pitch => self.pitch.push(Arc::new(YomitanPitchDictionary(dict))),
+            DictionaryType::Kanji => self.kanji.push(Arc::new(YomitanKanjiDictionary(dict))),
+        }
+    }
+
+    pub fn register_dictionary(&mut self, dict_path: NormalizedPathBuf) -> Result<(), Error> {
+        let (dict, dict_type) = YomitanDictionary::load(&dict_path.path)?;
+        let dict_type = dict_type?;
+        // Check if a dictionary with the same title and revision already exists
+        if self.terms.iter().any(|d| {
+            d.0.index.title == dict.index.title && d.0.index.revision == dict.index.revision
+        }) {
+            error!(
+                "Dictionary with title {} and revision {} already exists",
+                dict.index.title, dict.index.revision
+            );
+            return Err(anyhow::anyhow!(
+                "Dictionary with title {} and revision {} already exists",
+                dict.index.title,
+                dict.index.revision
+            ));
+        }
+        info!(
+            "🔍 Successfully registering new dictionary: {} with type {:?}",
+            dict.index.title, dict_type
+        );
+        self.add(dict, dict_type);
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self, token_features, user_preferences), fields(surface_forms = ?token_features.iter().map(|t| &t.surface_form).collect::<Vec<_>>(), dictionary_title = self.terms[0].0.index.title.clone()))]
+    pub async fn lookup(
+        &self,
+        token_features: &Vec<TokenFeature>,
+        user_preferences: &UserPreferences,
+    ) -> Result<LookupResult> {
+        let dict_results = {
+            let mut join_set = JoinSet::new();
+
+            // Spawn tasks for all dictionary lookups
+            let mut filtered_dicts_count = 0;
+            for dict in self.terms.iter() {
+                let dict = dict.clone();
+                let dict_title = dict.0.index.title.clone();
+                let dict_revision = dict.0.index.revision.clone();
+                if !user_preferences
+                    .term_disabled_dictionaries
+                    .contains(&format!("{dict_title}#{dict_revision}"))
+                {
+                    let token_features = token_features.clone();
+                    // Entries are decompressed and parsed, keep that off the executor
+                    join_set.spawn_blocking(move || (dict_title, dict.lookup(&token_features)));
+                } else {
+                    filtered_dicts_count += 1;
+                }
+            }
+            if filtered_dicts_count > 0 {
+                info!(
+                    ?filtered_dicts_count,
+                    "🔍 Filtered out dictionaries during term lookup"
+                );
+            }
+
+            // Collect results
+            let mut dict_results = Vec::new();
+            while let Some(result) = join_set.join_next().await {
+                let (dict_title, result) = match result {
+                    Ok((dict_title, result)) => (dict_title, result),
+                    Err(e) => {
+                        warn!(?e, "(1) Error joining dictionary lookup task, skipping");
+                        continue;
+                    }
+                };
+                let result = match result {
+                    Ok(result) => result,
+                    Err(e) => {
+                        warn!(
+                            ?e,
+                            ?dict_title,
+                            "(2) Error joining dictionary lookup task, skipping"
+                        );
+                        continue;
+                    }
+                };
+                if result.entries.is_empty() && result.heuristic_entries.is_empty() {
+                    trace!("🔍 Skipping empty dictionary result: {}", dict_title);
+                    continue;
+                }
+                dict_results.push(result);
+            }
+            dict_results
+        };
+
+        let mut pitch_results: HashMap<String, HashMap<String, PitchResult>> = HashMap::new();
+
+        // Make a Set of all the terms+readings combinations we've found
+        let mut term_readings = HashSet::new();
+        for d in dict_results.iter() {
+            for entry in d.entries.iter().chain(&d.heuristic_entries) {
+                term_readings.insert((entry.text.clone(), entry.reading.clone()));
+            }
+        }
+
+        for (term, reading) in term_readings.iter() {
+            if let Some(pitch_entry) = self.pitch[0].lookup(term, reading)? {
+                let pitch_accents = PitchAccents::from(&pitch_entry);
+                pitch_results
+                    .entry(term.clone())
+                    .or_insert(HashMap::new())
+                    .insert(
+                        reading.clone(),
+                        PitchResult {
+                            title: self.pitch[0].0.index.title.clone(),
+                            pitch_accents,
+                        },
+                    );
+            }
+        }
+
+        trace!("🔍 Pitch results: {pitch_results:?}");
+
+        let mut filtered_dict_count: i32 = 0;
+        let mut freq_res: HashMap<String, Vec<FrequencyData>> = HashMap::new();
+        for freq_dict in self.freq.iter() {
+            let dict_title = freq_dict.0.index.title.clone();
+            let dict_revision = freq_dict.0.index.revision.clone();
+            if !user_preferences
+                .freq_disabled_dictionaries
+                .contains(&format!("{dict_title}#{dict_revision}"))
+            {
+                let single_dict_freq_results = freq_dict.lookup_terms(token_features)?;
+                // Convert frequency results to FrequencyData format
+                let freq_data: Vec<FrequencyData> = single_dict_freq_results
+                    .iter()
+                    .filter_map(|entry| {
+                        let freq_union = entry.maybe_frequency();
+                        if let Some(freq_union) = freq_union {
+                            Some(FrequencyData {
+                                term: entry.term.clone(),
+                                reading: freq_union.reading.clone(),
+                                value: freq_union.value,
+                                display_value: freq_union.display_value,
+                            })
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+                freq_res.insert(
+                    format!("{}#{}", freq_dict.0.index.title, freq_dict.0.index.revision),
+                    freq_data,
+                );
+            } else {
+                filtered_dict_count += 1;
+            }
+        }
+        if filtered_dict_count > 0 {
+            info!(
+                ?filtered_dict_count,
+                "🔍 Filtered out dictionaries during frequency lookup"
+            );
+        }
+
+        trace!("🔍 Frequency results: {:?}", freq_res);
+
+        Ok(LookupResult {
+            dict: dict_results,
+            pitch: pitch_results,
+            freq: freq_res,
+        })
+    }
+
+    /// First entry for an exact `term` in the user's highest priority enabled
+    /// term dictionary that has one, along with that dictionary's title
+    pub fn lookup_first_entry(
+        &self,
+        term: &str,
+        user_preferences: &UserPreferences,
+    ) -> Result<Option<(String, TermEntry)>> {
+        let rank = |dict: &YomitanTermDictionary| {
+            let key = format!("{}#{}", dict.0.index.title, dict.0.index.revision);
+            user_preferences
+                .term_dictionary_order
+                .iter()
+                .position(|k| *k == key)
+                .unwrap_or(usize::MAX)
+        };
+        let mut dicts: Vec<_> = self
+            .terms
+            .iter()
+            .filter(|d| {
+                !user_preferences
+                    .term_disabled_dictionaries
+                    .contains(&format!("{}#{}", d.0.index.title, d.0.index.revision))
+            })
+            .collect();
+        dicts.sort_by_key(|d| rank(d));
+
+        for dict in dicts {
+            if let Some(entry) = dict
+                .lookup_term(term.to_string())?
+                .and_then(|entries| entries.into_iter().find(|e| e.text == term))
+            {
+                return Ok(Some((dict.0.index.title.clone(), entry)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Variants of the lexemes found by a lookup, grouped by the sequence
+    /// numbers of their entries. Lexemes with a single spelling are left out.
+    pub fn related_entries(&self, results: &[DictionaryResult]) -> Result<Vec<RelatedEntries>> {
+        let mut related = Vec::new();
+        for result in results {
+            let Some(dict) = self
+                .terms
+                .iter()
+                .find(|d| d.0.index.title == result.title && d.0.index.revision == result.revision)
+            else {
+                continue;
+            };
+            // A sequence number of 0 means the dictionary doesn't group entries
+            let mut sequences = Vec::new();
+            for entry in &result.entries {
+                if entry.sequence_number > 0 && !sequences.contains(&entry.sequence_number) {
+                    sequences.push(entry.sequence_number);
+                }
+            }
+            sequences.truncate(MAX_RELATED_SEQUENCES);
+            let mut variants = dict.lookup_sequences(&sequences)?;
+
+            for sequence in sequences {
+                let Some(entries) = variants.remove(&sequence) else {
+                    continue;
+                };
+                let mut forms: Vec<Variant> = Vec::new();
+                for entry in entries {
+                    let variant = Variant {
+                        text: entry.text,
+                        reading: entry.reading,
+                    };
+                    if !forms.contains(&variant) {
+                        forms.push(variant);
+                    }
+                }
+                let spellings: HashSet<&str> = forms.iter().map(|v| v.text.as_str()).collect();
+                if spellings.len() < 2 {
+                    continue;
+                }
+                let mut terms: Vec<String> = Vec::new();
+                for entry in result
+                    .entries
+                    .iter()
+                    .filter(|e| e.sequence_number == sequence)
+                {
+                    if !terms.contains(&entry.text) {
+                        terms.push(entry.text.clone());
+                    }
+                }
+                related.push(RelatedEntries {
+                    dictionary: result.title.clone(),
+                    sequence_number: sequence,
+                    terms,
+                    variants: forms,
+                });
+            }
+        }
+        Ok(related)
+    }
+
+    /// Headwords starting with `query` in the enabled term dictionaries, most
+    /// frequent first. Terms no frequency dictionary knows come last, shortest first.
+    /// A query with wildcards (`打*`, `*込む`, `打?`) matches headwords against
+    /// the whole pattern instead.
+    pub fn suggest(
+        &self,
+        query: &str,
+        limit: usize,
+        user_preferences: &UserPreferences,
+    ) -> Result<Vec<Suggestion>> {
+        let wildcard_pattern = is_wildcard_query(query).then(|| normalize_wildcards(query));
+        let mut candidates = BTreeSet::new();
+        for dict in self.terms.iter().filter(|d| {
+            !user_preferences
+                .term_disabled_dictionaries
+                .contains(&format!("{}#{}", d.0.index.title, d.0.index.revision))
+        }) {
+            let Some(term_bank) = dict.0.term_bank.get()? else {
+                continue;
+            };
+            match &wildcard_pattern {
+                Some(pattern) => {
+                    // A leading wildcard can't use the key index and reads the
+                    // whole term bank, so cap the rows it may read
+                    let max_scanned = pattern
+                        .starts_with(['*', '?'])
+                        .then_some(MAX_LEADING_WILDCARD_SCAN);
+                    let matches = term_bank.get_keys_matching_wildcard(
+                        pattern,
+                        MAX_SUGGESTION_CANDIDATES,
+                        max_scanned,
+                    )?;
+                    if matches.scan_limited {
+                        debug!(
+                            title = %dict.0.index.title,
+                            pattern,
+                            "Wildcard search stopped at the scan limit"
+                        );
+                    }
+                    candidates.extend(matches.keys);
+                }
+                None => candidates
+                    .extend(term_bank.get_keys_with_prefix(query, MAX_SUGGESTION_CANDIDATES)?),
+            }
+        }
+
+        let freq_dicts = self.rank_frequency_dictionaries(user_preferences);
+        let mut suggestions = Vec::with_capacity(candidates.len());
+        for term in candidates {
+            let frequency_rank = Self::frequency_rank(&freq_dicts, &term)?;
+            suggestions.push(Suggestion {
+                term,
+                frequency_rank,
+                jlpt: None,
+            });
+        }
+        suggestions.sort_by(|a, b| match (a.frequency_rank, b.frequency_rank) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => a.term.chars().count().cmp(&b.term.chars().count()),
+        });
+        suggestions.truncate(limit);
+        Ok(suggestions)
+    }
+
+    /// Frequency rank of each of `terms` that an enabled rank-based frequency
+    /// dictionary lists, as ranked for suggestions
+    pub fn frequency_ranks<'a>(
+        &self,
+        terms: impl IntoIterator<Item = &'a str>,
+        user_preferences: &UserPreferences,
+    ) -> Result<HashMap<String, f64>> {
+        let freq_dicts = self.rank_frequency_dictionaries(user_preferences);
+        let mut ranks = HashMap::new();
+        for term in terms {
+            if let Some(rank) = Self::frequency_rank(&freq_dicts, term)? {
+                ranks.insert(term.to_string(), rank);
+            }
+        }
+        Ok(ranks)
+    }
+
+    /// "title#revision" of the enabled frequency dictionaries whose values
+    /// are ranks
+    pub fn rank_frequency_dictionary_keys(
+        &self,
+        user_preferences: &UserPreferences,
+    ) -> HashSet<String> {
+        self.rank_frequency_dictionaries(user_preferences)
+            .iter()
+            .map(|d| format!("{}#{}", d.0.index.title, d.0.index.revision))
+            .collect()
+    }
+
+    /// Enabled frequency dictionaries usable for ranking. Occurrence counts
+    /// aren't comparable to ranks, so only rank-based dictionaries (the default
+    /// for Yomitan) are included.
+    fn rank_frequency_dictionaries(
+        &self,
+        user_preferences: &UserPreferences,
+    ) -> Vec<&Arc<YomitanFrequencyDictionary>> {
+        self.freq
+            .iter()
+            .filter(|d| {
+                !matches!(
+                    d.0.index.frequency_mode,
+                    Some(FrequencyMode::OccurrenceBased)
+                ) && !user_preferences
+                    .freq_disabled_dictionaries
+                    .contains(&format!("{}#{}", d.0.index.title, d.0.index.revision))
+            })
+            .collect()
+    }
+
+    /// Harmonic mean of the term's ranks in the frequency dictionaries that list it
+    fn frequency_rank(
+        freq_dicts: &[&Arc<YomitanFrequencyDictionary>],
+        term: &str,
+    ) -> Result<Option<f64>> {
+        let mut ranks = Vec::new();
+        for dict in freq_dicts {
+            if let Some(rank) = dict.best_rank(term)? {
+                ranks.push(rank);
+            }
+        }
+        Ok((!ranks.is_empty())
+            .then(|| ranks.len() as f64 / ranks.iter().map(|r| 1.0 / r).sum::<f64>()))
+    }
+
+    /// Japanese terms whose glosses contain all words of an English `query`,
+    /// searched in the enabled term dictionaries that have a reverse index under
+    /// `db_dir`. Terms with the shortest matching gloss come first, then the
+    /// most frequent.
+    pub fn reverse_lookup(
+        &self,
+        db_dir: &Path,
+        query: &str,
+        limit: usize,
+        user_preferences: &UserPreferences,
+    ) -> Result<Vec<ReverseLookupCandidate>> {
+        let mut words = reverse_lookup::gloss_words(query);
+        words.truncate(reverse_lookup::MAX_QUERY_WORDS);
+
+        let mut candidates: Vec<ReverseLookupCandidate> = Vec::new();
+        let mut gloss_words: Vec<usize> = Vec::new();
+        for dict in self.terms.iter().filter(|d| {
+            !user_preferences
+                .term_disabled_dictionaries
+                .contains(&format!("{}#{}", d.0.index.title, d.0.index.revision))
+        }) {
+            let matches = reverse_lookup::search(
+                &db_dir.join(&dict.0.origin),
+                &words,
+                MAX_REVERSE_LOOKUP_CANDIDATES,
+            )?;
+            for m in matches {
+                let Some(entries) = dict.lookup_term(m.key)? else {
+                    continue;
+                };
+                let Some(entry) = entries.get(m.entry) else {
+                    continue;
+                };
+                let Some(definition) = entry.definitions.get(m.definition) else {
+                    continue;
+                };
+                let sense = ReverseLookupSense {
+                    dictionary: dict.0.index.title.clone(),
+                    definition: crate::definitions::definition_text(definition),
+                };
+                match candidates
+                    .iter()
+                    .position(|c| c.term == entry.text && c.reading == entry.reading)
+                {
+                    Some(i) => {
+                        candidates[i].senses.push(sense);
+                        gloss_words[i] = gloss_words[i].min(m.gloss_words);
+                    }
+                    None => {
+                        candidates.push(ReverseLookupCandidate {
+                            term: entry.text.clone(),
+                            reading: entry.reading.clone(),
+                            senses: vec![sense],
+                            frequency_rank: None,
+                        });
+                        gloss_words.push(m.gloss_words);
+                    }
+                }
+            }
+        }
+
+        let freq_dicts = self.rank_frequency_dictionaries(user_preferences);
+        for candidate in &mut candidates {
+            candidate.frequency_rank = Self::frequency_rank(&freq_dicts, &candidate.term)?;
+        }
+        let mut ranked: Vec<_> = gloss_words.into_iter().zip(candidates).collect();
+        ranked.sort_by(|(a_len, a), (b_len, b)| {
+            a_len
+                .cmp(b_len)
+                .then_with(|| match (a.frequency_rank, b.frequency_rank) {
+                    (Some(a), Some(b)) => a.total_cmp(&b),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                })
+        });
+        Ok(ranked
+            .into_iter()
+            .map(|(_, candidate)| candidate)
+            .take(limit)
+            .collect())
+    }
+
+    /// How many of `terms` each enabled term dictionary has entries for, as
+    /// exact headword matches like [`YomitanDictionaries::evaluate_terms`].
+    /// Every dictionary's term bank is read with one bulk lookup.
+    pub fn term_coverage(
+        &self,
+        terms: &[String],
+        user_preferences: &UserPreferences,
+    ) -> Result<TermCoverage> {
+        let terms: Vec<&str> = terms
+            .iter()
+            .map(String::as_str)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let mut covered = HashSet::new();
+        let mut dictionaries = Vec::new();
+        for dict in self.terms.iter().filter(|d| {
+            !user_preferences
+                .term_disabled_dictionaries
+                .contains(&format!("{}#{}", d.0.index.title, d.0.index.revision))
+        }) {
+            let found = match dict.0.term_bank.get()? {
+                Some(term_bank) => term_bank.get_many(&terms)?,
+                None => HashMap::new(),
+            };
+            dictionaries.push(DictionaryCoverage {
+                title: dict.0.index.title.clone(),
+                revision: dict.0.index.revision.clone(),
+                found: found.len(),
+            });
+            covered.extend(found.into_keys());
+        }
+        Ok(TermCoverage {
+            total: terms.len(),
+            covered: covered.len(),
+            dictionaries,
+        })
+    }
+
+    /// Exact lookups of `terms` in every loaded dictionary, each timed. Used to
+    /// try out a dictionary before importing it, so failures are reported per
+    /// term rather than aborting the run.
+    pub fn evaluate_terms(&self, terms: &[String]) -> Vec<SandboxLookup> {
+        terms
+            .iter()
+            .map(|term| {
+                let start = Instant::now();
+                let result = self.lookup_all(term);
+                let duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+                match result {
+                    Ok(entries) => SandboxLookup {
+                        term: term.clone(),
+                        found: !entries.is_empty(),
+                        duration_ms,
+                        entries,
+                        error: None,
+                    },
+                    Err(e) => SandboxLookup {
+                        term: term.clone(),
+                        found: false,
+                        duration_ms,
+                        entries: Vec::new(),
+                        error: Some(format!("{e:#}")),
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Entries for an exact `term` in dictionaries of every type
+    fn lookup_all(&self, term: &str) -> Result<Vec<serde_json::Value>> {
+        let mut entries = Vec::new();
+        for dict in &self.terms {
+            for entry in dict.lookup_term(term.to_string())?.unwrap_or_default() {
+                entries.push(serde_json::to_value(entry)?);
+            }
+        }
+        for dict in &self.freq {
+            for entry in dict.lookup_term(term.to_string())?.unwrap_or_default() {
+                entries.push(serde_json::to_value(entry)?);
+            }
+        }
+        for dict in &self.pitch {
+            for entry in dict.lookup_term(term)?.unwrap_or_default() {
+                entries.push(serde_json::to_value(entry)?);
+            }
+        }
+        for dict in &self.kanji {
+            for entry in dict.lookup(term.to_string())?.unwrap_or_default() {
+                entries.push(serde_json::to_value(entry)?);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Term dictionaries with the name of their directory under `DICTS_PATH/db`
+    pub fn term_dictionary_dirs(&self) -> Vec<(DictionaryInfo, String)> {
+        self.terms
+            .iter()
+            .map(|d| {
+                (
+                    DictionaryInfo {
+                        title: d.0.index.title.clone(),
+                        revision: d.0.index.revision.clone(),
+                        dictionary_type: DictionaryType::Term,
+                    },
+                    d.0.origin.clone(),
+                )
+            })
+            .collect()
+    }
+
+    pub fn get_dictionaries_info(&self) -> Vec<DictionaryInfo> {
+        self.dictionaries_info().as_ref().clone()
+    }
+
+    /// Info of every loaded dictionary, shared rather than rebuilt per call
+    pub fn dictionaries_info(&self) -> Arc<Vec<DictionaryInfo>> {
+        self.info
+            .get_or_init(|| Arc::new(self.build_dictionaries_info()))
+            .clone()
+    }
+
+    /// Preferences of users who haven't set any: every dictionary enabled,
+    /// in title order
+    pub fn default_preferences(&self) -> Arc<UserPreferences> {
+        self.default_preferences
+            .get_or_init(|| {
+                Arc::new(UserPreferences::default(
+                    Uuid::nil(),
+                    self.get_dictionaries_info(),
+                ))
+            })
+            .clone()
+    }
+
+    fn build_dictionaries_info(&self) -> Vec<DictionaryInfo> {
+        let mut dictionary_infos: Vec<DictionaryInfo> = Vec::new();
+        dictionary_infos.extend(
+            self.terms
+                .iter()
+                .map(|d| DictionaryInfo {
+                    title: d.0.index.title.clone(),
+                    revision: d.0.index.revision.clone(),
+                    dictionary_type: DictionaryType::Term,
+                })
+                .collect::<Vec<DictionaryInfo>>(),
+        );
+        dictionary_infos.extend(
+            self.pitch
+                .iter()
+                .map(|d| DictionaryInfo {
+                    title: d.0.index.title.clone(),
+                    revision: d.0.index.revision.clone(),
+                    dictionary_type: DictionaryType::Pitch,
+                })
+                .collect::<Vec<DictionaryInfo>>(),
+        );
+        dictionary_infos.extend(
+            self.freq
+                .iter()
+                .map(|d| DictionaryInfo {
+                    title: d.0.index.title.clone(),
+                    revision: d.0.index.revision.clone(),
+                    dictionary_type: DictionaryType::Frequency,
+                })
+                .collect::<Vec<DictionaryInfo>>(),
+        );
+        dictionary_infos.extend(
+            self.kanji
+                .iter()
+                .map(|d| DictionaryInfo {
+                    title: d.0.index.title.clone(),
+                    revision: d.0.index.revision.clone(),
+                    dictionary_type: DictionaryType::Kanji,
+                })
+                .collect::<Vec<DictionaryInfo>>(),
+        );
+        dictionary_infos
+    }
+
+    /// Attributions of every loaded dictionary, ordered like
+    /// [`YomitanDictionaries::get_dictionaries_info`]
+    pub fn attributions(&self) -> Vec<DictionaryAttribution> {
+        let dictionaries = self
+            .terms
+            .iter()
+            .map(|d| (&d.0, DictionaryType::Term))
+            .chain(self.pitch.iter().map(|d| (&d.0, DictionaryType::Pitch)))
+            .chain(self.freq.iter().map(|d| (&d.0, DictionaryType::Frequency)))
+            .chain(self.kanji.iter().map(|d| (&d.0, DictionaryType::Kanji)));
+        dictionaries
+            .map(|(dict, dictionary_type)| DictionaryAttribution {
+                title: dict.index.title.clone(),
+                revision: dict.index.revision.clone(),
+                dictionary_type,
+                author: dict.index.author.clone(),
+                url: dict.index.url.clone(),
+                attribution: dict.index.attribution.clone(),
+            })
+            .collect()
+    }
+
+    fn all(&self) -> impl Iterator<Item = &YomitanDictionary> {
+        self.terms
+            .iter()
+            .map(|d| &d.0)
+            .chain(self.pitch.iter().map(|d| &d.0))
+            .chain(self.freq.iter().map(|d| &d.0))
+            .chain(self.kanji.iter().map(|d| &d.0))
+    }
+
+    /// Close the bank databases that haven't been used for `idle`, until the
+    /// next lookup that needs them. Returns how many were closed.
+    pub fn close_idle(&self, idle: Duration) -> usize {
+        self.all().map(|dict| dict.close_idle_banks(idle)).sum()
+    }
+
+    /// Bank databases currently open, across all dictionaries
+    pub fn open_banks(&self) -> usize {
+        self.all().map(YomitanDictionary::open_banks).sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.terms.clear();
+        self.pitch.clear();
+        self.freq.clear();
+        self.kanji.clear();
+        self.invalidate_caches();
+        debug!("Cleared content of yomi_dicts");
+    }
+}
+
+/// A bank database of a dictionary, opened by its first use rather than when
+/// the dictionary is registered, so that instances with many rarely used
+/// dictionaries don't hold a connection to each of them
+pub struct LazyBank<S: IsYomitanSchema> {
+    dir: PathBuf,
+    /// Whether the dictionary has the bank, checked once when it's loaded
+    exists: bool,
+    open: Mutex<Option<OpenBank<S>>>,
+}
+
+struct OpenBank<S: IsYomitanSchema> {
+    db: Arc<DictionaryDB<S>>,
+    last_used: Instant,
+}
+
+impl<S: IsYomitanSchema + Send + 'static> LazyBank<S> {
+    fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            exists: DictionaryDB::<S>::exists(dir),
+            open: Mutex::new(None),
+        }
+    }
+
+    /// The bank's database, opened if it isn't yet. None if the dictionary
+    /// has no such bank.
+    pub fn get(&self) -> Result<Option<Arc<DictionaryDB<S>>>> {
+        if !self.exists {
+            return Ok(None);
+        }
+        let mut open = self
+            .open
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire bank lock: {e}"))?;
+        if let Some(bank) = open.as_mut() {
+            bank.last_used = Instant::now();
+            return Ok(Some(bank.db.clone()));
+        }
+        let Some(db) = DictionaryDB::<S>::open_ro(&self.dir)? else {
+            return Ok(None);
+        };
+        trace!(dir = %self.dir, schema = S::get_schema_name(), "📂 Opened dictionary bank");
+        let db = Arc::new(db);
+        *open = Some(OpenBank {
+            db: db.clone(),
+            last_used: Instant::now(),
+        });
+        Ok(Some(db))
+    }
+
+    /// Whether the dictionary has the bank and it has any entries
+    fn has_rows(&self) -> Result<bool> {
+        match self.get()? {
+            Some(db) => db.has_rows(),
+            None => Ok(false),
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.open.lock().is_ok_and(|open| open.is_some())
+    }
+
+    /// Close the database if it hasn't been used for `idle`. Lookups already
+    /// holding it keep it until they finish. Returns whether it was closed.
+    fn close_if_idle(&self, idle: Duration) -> bool {
+        let Ok(mut open) = self.open.lock() else {
+            return false;
+        };
+        if open
+            .as_ref()
+            .is_some_and(|bank| bank.last_used.elapsed() >= idle)
+        {
+            *open = None;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+pub struct YomitanDictionary {
+    pub origin: String,
+    pub index: DictionaryIndex,
+    pub kanji_bank: LazyBank<KanjiBankV3>,
+    pub kanji_meta_bank: LazyBank<KanjiMetaBankV3>,
+    pub tag_bank: LazyBank<TagBankV3>,
+    pub term_bank: LazyBank<TermBankV3>,
+    pub term_meta_bank: LazyBank<TermMetaBankV3>,
+}
+
+impl YomitanDictionary {
+    fn new(dict_path: &Path) -> Result<Self, Error> {
+        let origin = dict_path
+            .file_name()
+            .expect(&format!(
+                "Expected dictionary path to have file name component: {dict_path}"
+            ))
+            .to_string();
+
+        let index: DictionaryIndex = {
+            let mut index_str = String::new();
+            std::fs::File::open(format!("{dict_path}/index.json"))?
+                .read_to_string(&mut index_str)?;
+            serde_json::from_str(&index_str)?
+        };
+
+        let kanji_bank = LazyBank::new(dict_path);
+        let kanji_meta_bank = LazyBank::new(dict_path);
+        let tag_bank = LazyBank::new(dict_path);
+        let term_bank = LazyBank::new(dict_path);
+        let term_meta_bank = LazyBank::new(dict_path);
+
+        Ok(Self {
+            origin,
+            index,
+            kanji_bank,
+            kanji_meta_bank,
+            tag_bank,
+            term_bank,
+            term_meta_bank,
+        })
+    }
+
+    /// The dictionary in `dict_path` and its type, keeping only the metadata:
+    /// the banks opened to identify it are closed again until a lookup
+    fn load(dict_path: &Path) -> Result<(Self, Result<DictionaryType>)> {
+        let dict = Self::new(dict_path)?;
+        let dict_type = dict.identify_dictionary_type();
+        dict.close_idle_banks(Duration::ZERO);
+        Ok((dict, dict_type))
+    }
+
+    /// Close the banks that haven't been used for `idle`, returning how many
+    fn close_idle_banks(&self, idle: Duration) -> usize {
+        [
+            self.kanji_bank.close_if_idle(idle),
+            self.kanji_meta_bank.close_if_idle(idle),
+            self.tag_bank.close_if_idle(idle),
+            self.term_bank.close_if_idle(idle),
+            self.term_meta_bank.close_if_idle(idle),
+        ]
+        .into_iter()
+        .filter(|&closed| closed)
+        .count()
+    }
+
+    fn open_banks(&self) -> usize {
+        [
+            self.kanji_bank.is_open(),
+            self.kanji_meta_bank.is_open(),
+            self.tag_bank.is_open(),
+            self.term_bank.is_open(),
+            self.term_meta_bank.is_open(),
+        ]
+        .into_iter()
+        .filter(|&open| open)
+        .count()
+    }
+
+    pub fn identify_dictionary_type(&self) -> Result<DictionaryType> {
+        // - Term dictionaries have a non-empty term_bank
+        // - Pitch/frequency dictionaries have a non-empty term_meta_bank and empty term_bank
+        //   (need to check the data in term_meta_bank to distinguish between pitch and frequency)
+        // - Kanji dictionaries have a non-empty kanji_bank
+
+        let term_bank = self.term_bank.has_rows()?;
+        let term_meta_bank = self.term_meta_bank.has_rows()?;
+        let kanji_bank = self.kanji_bank.has_rows()?;
+
+        if kanji_bank || self.index.revision.contains("kanji") {
+            Ok(DictionaryType::Kanji)
+        } else if term_meta_bank {
+            // Have to distinguish based on the data in the term_meta_bank
+            let first_row = self
+                .term_meta_bank
+                .get()?
+                .expect("Term meta bank not found")
+                .get_first_row()?;
+            if let Some(first_row) = first_row {
+                let entry: Vec<TermMetaEntry> = serde_json::from_str(&first_row)?;
+                let first_entry = entry
+                    .first()
+                    .expect(&format!("Term meta bank is empty for {}", self.index.title));
+                if let TermMetaData::Frequency(_) = &first_entry.data {
+                    Ok(DictionaryType::Frequency)
+                } else if let TermMetaData::Pitch(_) = &first_entry.data {
+                    Ok(DictionaryType::Pitch)
+                } else {
+                    Err(anyhow::anyhow!(
+                        "(1) Unsupported dictionary type for {}",
+                        self.index.title
+                    ))
+                }
+            } else {
+                Err(anyhow::anyhow!("Term meta bank is empty"))
+            }
+        } else if term_bank {
+            Ok(DictionaryType::Term)
+        } else {
+            error!("Unsupported dictionary type for {}", self.index.title);
+            Err(anyhow::anyhow!(
+                "(2) Unsupported dictionary type for {}",
+                self.index.title
+            ))?
+        }
+    }
+}
+
+impl YomitanTermDictionary {
+    /// Open the term dictionary in `dict_path` outside of a
+    /// [`YomitanDictionaries`] collection, e.g. a user's custom dictionary
+    pub fn open(dict_path: &Path) -> Result<Self> {
+        Ok(Self(YomitanDictionary::new(dict_path)?))
+    }
+
+    #[tracing::instrument(skip(self, token_features), fields(surface_forms = ?token_features.iter().map(|t| &t.surface_form).collect::<Vec<_>>(), dictionary_title = self.0.index.title.clone()))]
+    pub fn lookup(&self, token_features: &Vec<TokenFeature>) -> Result<DictionaryResult> {
+        let mut results = Vec::new();
+        let mut heuristic_results = Vec::new();
+
+        trace!("📝 Search order:");
+        for (index, feature) in token_features.iter().enumerate() {
+            trace!("🔎 Search attempt #{}", index + 1);
+            let results = if feature.heuristic {
+                &mut heuristic_results
+            } else {
+                &mut results
+            };
+
+            // Try surface form first
+            if let Some(surface) = &feature.surface_form {
+                trace!("  ▶️ Searching surface form: '{}'... ", surface);
+                // Try original form
+                if let Some(entries) = self.lookup_term(surface.clone())? {
+                    trace!("✅ Found!");
+                    results.extend(entries);
+                } else {
+                    // If it's katakana, try converting to hiragana
+                    if surface.as_str().is_katakana() {
+                        let hiragana = surface.to_hiragana();
+                        trace!("  ▶️ Searching hiragana form: '{}'... ", hiragana);
+                        if let Some(entries) = self.lookup_term(hiragana)? {
+                            trace!("✅ Found!");
+                            results.extend(entries);
+                        } else {
+                            trace!("❌ Not found");
+                        }
+                    } else {
+                        trace!("❌ Not found");
+                    }
+                }
+            }
+
+            // Try dictionary form if different
+            if let Some(dict_form) = &feature.dictionary_form {
+                if Some(dict_form) != feature.surface_form.as_ref() {
+                    trace!("  ▶️ Searching dictionary form: '{}'... ", dict_form);
+                    match self.lookup_term(dict_form.clone())? {
+                        Some(entries) => {
+                            trace!("✅ Found!");
+                            results.extend(entries);
+                        }
+                        None => trace!("❌ Not found"),
+                    }
+                }
+            }
+
+            // Print full token info for debugging
+            trace!("   Token details:");
+            trace!("     Surface form: {:?}", feature.surface_form);
+            trace!("     Dictionary form: {:?}", feature.dictionary_form);
+            trace!("     Part of speech: {:?}", feature.pos);
+            if feature.pos_subtype_1.is_some() {
+                trace!("     POS subtype: {:?}", feature.pos_subtype_1);
+            }
+        }
+        Ok(DictionaryResult {
+            title: self.0.index.title.clone(),
+            revision: self.0.index.revision.clone(),
+            origin: self.0.origin.clone(),
+            entries: results,
+            heuristic_entries: heuristic_results,
+        })
+    }
+
+    /// Entries with one of the `sequences`, by sequence number
+    fn lookup_sequences(&self, sequences: &[i64]) -> Result<HashMap<i64, Vec<TermEntry>>> {
+        let Some(term_bank) = self.0.term_bank.get()? else {
+            return Ok(HashMap::new());
+        };
+        // Dictionaries imported before the sequence index have their whole
+        // term bank scanned instead
+        let keys: BTreeSet<String> = if term_bank.has_sequence_index()? {
+            let mut keys = BTreeSet::new();
+            for sequence in sequences {
+                keys.extend(term_bank.get_by_sequence(*sequence)?);
+            }
+            keys
+        } else {
+            term_bank
+                .get_keys_with_sequences(sequences)?
+                .into_iter()
+                .collect()
+        };
+        let mut entries: HashMap<i64, Vec<TermEntry>> = HashMap::new();
+        for key in keys {
+            for entry in self.lookup_term(key)?.unwrap_or_default() {
+                if sequences.contains(&entry.sequence_number) {
+                    entries
+                        .entry(entry.sequence_number)
+                        .or_default()
+                        .push(entry);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    #[tracing::instrument(skip(self), fields(dictionary_title = self.0.index.title.clone()))]
+    fn lookup_term(&self, term: String) -> Result<Option<Vec<TermEntry>>> {
+        let res = self
+            .0
+            .term_bank
+            .get()?
+            .expect("Term bank not found")
+            .get(&term)?;
+        if let Some(res) = res {
+            trace!("📖 Raw JSON for term '{}': {}", term, res);
+
+            let entries = match serde_json::from_str::<Vec<TermEntry>>(&res) {
+                Ok(entries) => {
+                    trace!(
+                        "✅ Successfully deserialized {} entries for term '{}'",
+                        entries.len(),
+                        term
+                    );
+                    entries
+                }
+                Err(e) => {
+                    error!(
+                        error = %e,
+                        raw_json = %res,
+                        term = %term,
+                        "❌ Deserialization failed for term. Raw JSON above."
+                    );
+                    // Try to deserialize as serde_json::Value to inspect the structure
+                    if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(&res) {
+                        debug!(
+                            "📋 JSON structure: {}",
+                            serde_json::to_string_pretty(&json_value)?
+                        );
+                        if json_value.is_array() {
+                            for (idx, item) in json_value.as_array().unwrap().iter().enumerate() {
+                                debug!("  Entry[{}]: {:?}", idx, item);
+                                if let Some(obj) = item.as_array() {
+                                    debug!("    Length: {}", obj.len());
+                                    for (field_idx, field) in obj.iter().enumerate() {
+                                        if field.is_null() {
+                                            warn!("    Field[{}] is NULL", field_idx);
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    return Err(anyhow::anyhow!(
+                        "Error deserializing term entries for term: {}\n\nCaused by: {}",
+                        term,
+                        e
+                    ));
+                }
+            };
+            Ok(Some(entries))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl YomitanFrequencyDictionary {
+    #[tracing::instrument(skip(self, token_features), fields(dictionary_title = self.0.index.title.clone()))]
+    fn lookup_terms(&self, token_features: &Vec<TokenFeature>) -> Result<Vec<TermMetaEntry>> {
+        let dictionary_forms = token_features
+            .iter()
+            .filter_map(|f| match f.dictionary_form.as_ref() {
+                Some(dict_form) => Some(dict_form),
+                None => {
+                    warn!(token = ?f, "Dictionary form not found");
+                    None
+                }
+            })
+            .collect::<HashSet<&String>>();
+
+        let mut results = Vec::new();
+        for term in dictionary_forms {
+            if let Some(entries) = self.lookup_term(term.clone())? {
+                results.extend(entries);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Lowest (best) positive numeric frequency listed for `term`
+    fn best_rank(&self, term: &str) -> Result<Option<f64>> {
+        let number = |value: &serde_json::Value| {
+            value
+                .as_f64()
+                .or_else(|| value.get("value").and_then(|v| v.as_f64()))
+        };
+        let ranks = self
+            .lookup_term(term.to_string())?
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| match entry.data {
+                TermMetaData::Frequency(MetaFrequencyData::SimpleNumber(n)) => Some(n as f64),
+                TermMetaData::Frequency(MetaFrequencyData::Detailed(details)) => details
+                    .value
+                    .or_else(|| details.frequency.as_ref().and_then(number)),
+                _ => None,
+            })
+            .filter(|rank| *rank > 0.0);
+        Ok(ranks.min_by(|a, b| a.total_cmp(b)))
+    }
+
+    fn lookup_term(&self, term: String) -> Result<Option<Vec<TermMetaEntry>>> {
+        let res = self
+            .0
+            .term_meta_bank
+            .get()?
+            .expect("Term meta bank not found")
+            .get(&term)?;
+        if let Some(res) = res {
+            let entries = serde_json::from_str(&res)?;
+            Ok(Some(entries))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl YomitanPitchDictionary {
+    fn lookup_term(&self, term: &str) -> Result<Option<Vec<TermMetaEntry>>> {
+        let res = self
+            .0
+            .term_meta_bank
+            .get()?
+            .expect("Term meta bank not found")
+            .get(term)?;
+        match res {
+            Some(res) => Ok(Some(serde_json::from_str(&res)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn lookup(&self, term: &str, reading: &str) -> Result<Option<PitchData>> {
+        let res = self
+            .0
+            .term_meta_bank
+            .get()?
+            .expect("Term meta bank not found")
+            .get(&term)?;
+        if let Some(res) = res {
+            let entries: Vec<TermMetaEntry> = serde_json::from_str(&res)?;
+            for entry in entries {
+                if entry.term == term {
+                    if let TermMetaData::Pitch(pitch_data) = &entry.data {
+                        if pitch_data.reading == reading {
+                            return Ok(Some(pitch_data.clone()));
+                        }
+                    }
+                }
+            }
+            Ok(None)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PitchAccent {
+    pub reading: String, // e.g., "ふちゅうい"
+    pub position: u8,    // e.g., 2
+    pub mora_count: u8,  // e.g., 4
+    /// 0-based indices of morae with a nasal sound
+    pub nasal: Vec<usize>,
+    /// 0-based indices of devoiced morae
+    pub devoice: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub struct PitchAccents(pub Vec<PitchAccent>);
+
+impl From<&PitchData> for PitchAccents {
+    fn from(pitch_data: &PitchData) -> Self {
+        let mut pitch_accents = Vec::new();
+        for pitch in pitch_data.pitches.iter() {
+            pitch_accents.push(PitchAccent {
+                reading: pitch_data.reading.clone(),
+                position: pitch.position as u8,
+                mora_count: pitch::split_morae(&pitch_data.reading).len() as u8,
+                nasal: pitch::mora_indices(pitch.nasal.as_ref()),
+                devoice: pitch::mora_indices(pitch.devoice.as_ref()),
+            });
+        }
+        PitchAccents(pitch_accents)
+    }
+}
+
+impl YomitanKanjiDictionary {
+    // TODO: Handle dicts which have term_bank rather than kanji_bank
+    fn lookup(&self, kanji: String) -> Result<Option<Vec<KanjiEntry>>> {
+        let res = self
+            .0
+            .kanji_bank
+            .get()?
+            .expect("Kanji bank not found")
+            .get(&kanji)?;
+        if let Some(res) = res {
+            let entries = serde_json::from_str(&res)?;
+            Ok(Some(entries))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yomitan_format::StripExtension;
+
+    fn term_dictionary(dict_dir: &Path, title: &str) {
+        let dir = dict_dir.join(title);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("index.json"),
+            format!(r#"{{"title": "{title}", "revision": "1", "format": 3}}"#),
+        )
+        .unwrap();
+        let dir = NormalizedPathBuf::new(&dir, StripExtension::None).unwrap();
+        DictionaryDB::<TermBankV3>::new(dir)
+            .unwrap()
+            .replace("食べる", &[serde_json::json!(["食べる", "たべる"])])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let dict_dir = Path::from_path(dir.path()).unwrap();
+        for title in ["c", "a", "d", "b"] {
+            term_dictionary(dict_dir, title);
+        }
+        // Not identifiable, so timed but not loaded
+        std::fs::create_dir(dict_dir.join("empty")).unwrap();
+        std::fs::write(
+            dict_dir.join("empty/index.json"),
+            r#"{"title": "empty", "revision": "1"}"#,
+        )
+        .unwrap();
+
+        let (mut dictionaries, summary) = YomitanDictionaries::load(dict_dir, 3).unwrap();
+        let titles: Vec<_> = dictionaries
+            .get_dictionaries_info()
+            .into_iter()
+            .map(|info| info.title)
+            .collect();
+        assert_eq!(titles, ["a", "b", "c", "d"]);
+        assert_eq!(
+            dictionaries.default_preferences().term_dictionary_order,
+            ["a#1", "b#1", "c#1", "d#1"]
+        );
+        assert_eq!(dictionaries.open_banks(), 0);
+        assert_eq!(summary.concurrency, 3);
+        assert_eq!(summary.dictionaries.len(), 5);
+        assert_eq!(summary.dictionaries[4].dictionary_type, None);
+        assert_eq!(summary.slowest(2).len(), 2);
+
+        dictionaries.clear();
+        assert!(dictionaries.dictionaries_info().is_empty());
+        assert!(dictionaries
+            .default_preferences()
+            .term_dictionary_order
+            .is_empty());
+
+        let (dictionaries, summary) = YomitanDictionaries::load(&dict_dir.join("new"), 3).unwrap();
+        assert!(dictionaries.get_dictionaries_info().is_empty());
+        assert!(summary.dictionaries.is_empty());
+        assert!(dict_dir.join("new").exists());
+    }
+
+    #[test]
+    fn test_term_coverage() {
+        let dir = tempfile::tempdir().unwrap();
+        let dict_dir = Path::from_path(dir.path()).unwrap();
+        for title in ["a", "b"] {
+            term_dictionary(dict_dir, title);
+        }
+        let dir = NormalizedPathBuf::new(&dict_dir.join("b"), StripExtension::None).unwrap();
+        DictionaryDB::<TermBankV3>::new(dir)
+            .unwrap()
+            .replace("飲む", &[serde_json::json!(["飲む", "のむ"])])
+            .unwrap();
+        let dictionaries = YomitanDictionaries::new(dict_dir).unwrap();
+
+        let terms = ["食べる", "飲む", "見る", "食べる"].map(String::from);
+        let mut preferences =
+            UserPreferences::default(Uuid::nil(), dictionaries.get_dictionaries_info());
+        let coverage = dictionaries.term_coverage(&terms, &preferences).unwrap();
+        assert_eq!(coverage.total, 3);
+        assert_eq!(coverage.covered, 2);
+        let found: Vec<_> = coverage
+            .dictionaries
+            .iter()
+            .map(|d| (d.title.as_str(), d.found))
+            .collect();
+        assert_eq!(found, [("a", 1), ("b", 2)]);
+
+        preferences
+            .term_disabled_dictionaries
+            .insert("b#1".to_string());
+        let coverage = dictionaries.term_coverage(&terms, &preferences).unwrap();
+        assert_eq!(coverage.covered, 1);
+        assert_eq!(coverage.dictionaries.len(), 1);
+    }
+
+    #[test]
+    fn test_lazy_bank() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir =
+            NormalizedPathBuf::new(Path::from_path(dir.path()).unwrap(), StripExtension::None)
+                .unwrap();
+        DictionaryDB::<TermBankV3>::new(dir.clone()).unwrap();
+
+        let term_bank = LazyBank::<TermBankV3>::new(&dir.path);
+        assert!(!term_bank.is_open());
+        assert!(term_bank.get().unwrap().is_some());
+        assert!(term_bank.is_open());
+        assert!(!term_bank.close_if_idle(Duration::from_secs(60)));
+        assert!(term_bank.close_if_idle(Duration::ZERO));
+        assert!(!term_bank.is_open());
+        assert!(!term_bank.has_rows().unwrap());
+
+        let kanji_bank = LazyBank::<KanjiBankV3>::new(&dir.path);
+        assert!(kanji_bank.get().unwrap().is_none());
+        assert!(!kanji_bank.is_open());
+    }
+}
@@ -0,0 +1,114 @@
+//! Aligning a word's reading with how it is written, so that furigana only
+//! sits above the kanji: 食べ物 read たべもの becomes 食(た)べ物(もの).
+
+use wana_kana::ConvertJapanese;
+
+/// A run of a written word, with the reading to show above it if it needs one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuriganaSegment {
+    pub text: String,
+    pub reading: Option<String>,
+}
+
+fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3041}'..='\u{309F}' | '\u{30A1}'..='\u{30FA}' | 'ー')
+}
+
+/// Whether `text` has any character that furigana would go above
+pub fn needs_furigana(text: &str) -> bool {
+    text.chars().any(|c| !is_kana(c) && !c.is_whitespace())
+}
+
+/// Split `surface` into kana runs and runs of everything else
+fn runs(surface: &str) -> Vec<(bool, String)> {
+    let mut runs: Vec<(bool, String)> = Vec::new();
+    for c in surface.chars() {
+        match runs.last_mut() {
+            Some((kana, run)) if *kana == is_kana(c) => run.push(c),
+            _ => runs.push((is_kana(c), c.to_string())),
+        }
+    }
+    runs
+}
+
+/// Readings for `runs[i..]` given the rest of the reading (in hiragana), or
+/// None if the kana of the surface can't be matched in it
+fn align(runs: &[(bool, String)], reading: &str) -> Option<Vec<FuriganaSegment>> {
+    let Some(((kana, text), rest)) = runs.split_first() else {
+        return reading.is_empty().then(Vec::new);
+    };
+    if *kana {
+        let reading = reading.strip_prefix(text.to_hiragana().as_str())?;
+        let mut segments = align(rest, reading)?;
+        segments.insert(
+            0,
+            FuriganaSegment {
+                text: text.clone(),
+                reading: None,
+            },
+        );
+        return Some(segments);
+    }
+    if reading.is_empty() {
+        return None;
+    }
+    // Try the shortest reading first, moving on if the kana after it don't match
+    for (end, _) in reading.char_indices().skip(1).chain([(reading.len(), ' ')]) {
+        if let Some(mut segments) = align(rest, &reading[end..]) {
+            segments.insert(
+                0,
+                FuriganaSegment {
+                    text: text.clone(),
+                    reading: Some(reading[..end].to_string()),
+                },
+            );
+            return Some(segments);
+        }
+    }
+    None
+}
+
+/// Furigana for `surface` read as `reading` (in hiragana or katakana). If the
+/// kana of the surface don't line up with the reading, the whole word gets
+/// the whole reading.
+pub fn furigana(surface: &str, reading: &str) -> Vec<FuriganaSegment> {
+    let reading = reading.to_hiragana();
+    if !needs_furigana(surface) || reading.is_empty() {
+        return vec![FuriganaSegment {
+            text: surface.to_string(),
+            reading: None,
+        }];
+    }
+    align(&runs(surface), &reading).unwrap_or_else(|| {
+        vec![FuriganaSegment {
+            text: surface.to_string(),
+            reading: Some(reading),
+        }]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(segments: &[FuriganaSegment]) -> String {
+        segments
+            .iter()
+            .map(|s| match &s.reading {
+                Some(reading) => format!("{}({reading})", s.text),
+                None => s.text.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_furigana() {
+        assert_eq!(render(&furigana("食べ物", "タベモノ")), "食(た)べ物(もの)");
+        assert_eq!(render(&furigana("見上げる", "みあげる")), "見上(みあ)げる");
+        assert_eq!(render(&furigana("お茶", "おちゃ")), "お茶(ちゃ)");
+        assert_eq!(render(&furigana("ひらがな", "ひらがな")), "ひらがな");
+        assert_eq!(render(&furigana("コーヒー", "コーヒー")), "コーヒー");
+        // Readings that don't match the kana still get shown
+        assert_eq!(render(&furigana("今日は", "きょうわ")), "今日は(きょうわ)");
+    }
+}
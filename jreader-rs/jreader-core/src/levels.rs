@@ -0,0 +1,385 @@
+//! JLPT and Kanken (漢字検定) levels of words and kanji, for tagging lookup
+//! results and filtering by level.
+//!
+//! The level lists aren't bundled, as the published ones come under various
+//! licenses. They're read from tab-separated files in a directory, each line
+//! being one word or kanji, with `#` starting a comment line:
+//!
+//! - `jlpt.tsv`: `term<TAB>reading<TAB>level`, the reading may be left empty
+//! - `jlpt_kanji.tsv`: `kanji<TAB>level`
+//! - `kanken.tsv`: `kanji<TAB>level`
+//!
+//! JLPT levels are written `N1` to `N5` (or just the digit), Kanken levels
+//! `10` to `1` with `準` (or `pre-`) for the pre-levels, e.g. `準2級`. Missing
+//! files just leave their levels out.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tracing::{info, warn};
+
+pub const JLPT_VOCAB_FILE: &str = "jlpt.tsv";
+pub const JLPT_KANJI_FILE: &str = "jlpt_kanji.tsv";
+pub const KANKEN_FILE: &str = "kanken.tsv";
+
+/// A JLPT level, ordered from the easiest (N5) to the hardest (N1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum JlptLevel {
+    N5,
+    N4,
+    N3,
+    N2,
+    N1,
+}
+
+impl FromStr for JlptLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let digits = ascii_digits(s.strip_prefix(['N', 'n', 'Ｎ', 'ｎ']).unwrap_or(s));
+        match digits.as_deref() {
+            Some("5") => Ok(Self::N5),
+            Some("4") => Ok(Self::N4),
+            Some("3") => Ok(Self::N3),
+            Some("2") => Ok(Self::N2),
+            Some("1") => Ok(Self::N1),
+            _ => Err(anyhow!("Unknown JLPT level {s:?}: use N1 to N5")),
+        }
+    }
+}
+
+/// A Kanken level, from 10級 (the easiest) to 1級
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KankenLevel {
+    pub grade: u8,
+    /// 準 levels come just before `grade`, e.g. 準2級 between 3級 and 2級
+    pub pre: bool,
+}
+
+impl KankenLevel {
+    // Ordering key, larger is harder
+    fn difficulty(&self) -> u8 {
+        (10 - self.grade) * 2 - u8::from(self.pre)
+    }
+}
+
+impl PartialOrd for KankenLevel {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for KankenLevel {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.difficulty().cmp(&other.difficulty())
+    }
+}
+
+impl FromStr for KankenLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let trimmed = s.trim();
+        let (pre, rest) = match trimmed
+            .strip_prefix('準')
+            .or_else(|| trimmed.strip_prefix("pre-"))
+            .or_else(|| trimmed.strip_prefix("pre"))
+        {
+            Some(rest) => (true, rest),
+            None => (false, trimmed),
+        };
+        let grade = ascii_digits(rest.strip_suffix('級').unwrap_or(rest))
+            .and_then(|digits| digits.parse::<u8>().ok())
+            .filter(|grade| (1..=10).contains(grade))
+            .ok_or_else(|| anyhow!("Unknown Kanken level {s:?}: use 10 to 1, e.g. 準2級"))?;
+        // Only 2級 and 1級 have pre-levels
+        if pre && grade > 2 {
+            return Err(anyhow!(
+                "Unknown Kanken level {s:?}: only 準2級 and 準1級 exist"
+            ));
+        }
+        Ok(Self { grade, pre })
+    }
+}
+
+impl fmt::Display for KankenLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pre = if self.pre { "準" } else { "" };
+        write!(f, "{pre}{}級", self.grade)
+    }
+}
+
+impl Serialize for KankenLevel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for KankenLevel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+// `s` with full-width digits, as Japanese lists often write them, made
+// ASCII. None if `s` isn't all digits.
+fn ascii_digits(s: &str) -> Option<String> {
+    s.chars()
+        .map(|c| match c {
+            '0'..='9' => Some(c),
+            '０'..='９' => char::from_u32(c as u32 - '０' as u32 + '0' as u32),
+            _ => None,
+        })
+        .collect::<Option<String>>()
+        .filter(|digits| !digits.is_empty())
+}
+
+/// Levels of one kanji of a term
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KanjiLevel {
+    pub kanji: char,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jlpt: Option<JlptLevel>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kanken: Option<KankenLevel>,
+}
+
+/// Levels of a term of a lookup result
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TermLevels {
+    pub term: String,
+    pub reading: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jlpt: Option<JlptLevel>,
+    /// The term's kanji that have a level, in order of appearance
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub kanji: Vec<KanjiLevel>,
+}
+
+/// The level lists, see the module documentation
+#[derive(Debug, Default)]
+pub struct LevelData {
+    /// Term to its levels by reading, None for lines without a reading
+    vocab: HashMap<String, Vec<(Option<String>, JlptLevel)>>,
+    kanji_jlpt: HashMap<char, JlptLevel>,
+    kanken: HashMap<char, KankenLevel>,
+}
+
+impl LevelData {
+    /// Read the level lists in `dir`. Lines that can't be parsed are skipped
+    /// with a warning.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut data = Self::default();
+        if let Some(content) = read_list(&dir.join(JLPT_VOCAB_FILE))? {
+            data.add_vocab(&content);
+        }
+        if let Some(content) = read_list(&dir.join(JLPT_KANJI_FILE))? {
+            data.kanji_jlpt = parse_kanji_list(&content, JLPT_KANJI_FILE);
+        }
+        if let Some(content) = read_list(&dir.join(KANKEN_FILE))? {
+            data.kanken = parse_kanji_list(&content, KANKEN_FILE);
+        }
+        info!(
+            vocab = data.vocab.len(),
+            kanji_jlpt = data.kanji_jlpt.len(),
+            kanken = data.kanken.len(),
+            "Loaded level lists"
+        );
+        Ok(data)
+    }
+
+    fn add_vocab(&mut self, content: &str) {
+        for (line_number, fields) in list_lines(content) {
+            let (term, reading, level) = match fields.as_slice() {
+                [term, reading, level] => (*term, *reading, *level),
+                [term, level] => (*term, "", *level),
+                _ => {
+                    warn!(line_number, "Skipping malformed line of {JLPT_VOCAB_FILE}");
+                    continue;
+                }
+            };
+            let Ok(level) = level.parse() else {
+                warn!(
+                    line_number,
+                    level, "Skipping unknown level in {JLPT_VOCAB_FILE}"
+                );
+                continue;
+            };
+            let reading = (!reading.is_empty()).then(|| reading.to_string());
+            self.vocab
+                .entry(term.to_string())
+                .or_default()
+                .push((reading, level));
+        }
+    }
+
+    /// Whether no list was loaded
+    pub fn is_empty(&self) -> bool {
+        self.vocab.is_empty() && self.kanji_jlpt.is_empty() && self.kanken.is_empty()
+    }
+
+    /// JLPT level of `term` read as `reading`. Lines without a reading match
+    /// any reading; without a `reading` the easiest level of the term is used.
+    pub fn jlpt_level(&self, term: &str, reading: Option<&str>) -> Option<JlptLevel> {
+        let levels = self.vocab.get(term)?;
+        match reading {
+            Some(reading) => levels
+                .iter()
+                .find(|(r, _)| r.as_deref() == Some(reading))
+                .or_else(|| levels.iter().find(|(r, _)| r.is_none()))
+                .map(|(_, level)| *level),
+            None => levels.iter().map(|(_, level)| *level).min(),
+        }
+    }
+
+    /// Levels of the distinct kanji of `term` that have one
+    pub fn kanji_levels(&self, term: &str) -> Vec<KanjiLevel> {
+        let mut levels: Vec<KanjiLevel> = Vec::new();
+        for kanji in term.chars() {
+            if levels.iter().any(|level| level.kanji == kanji) {
+                continue;
+            }
+            let jlpt = self.kanji_jlpt.get(&kanji).copied();
+            let kanken = self.kanken.get(&kanji).copied();
+            if jlpt.is_some() || kanken.is_some() {
+                levels.push(KanjiLevel {
+                    kanji,
+                    jlpt,
+                    kanken,
+                });
+            }
+        }
+        levels
+    }
+
+    /// Levels of `term` read as `reading`, None if neither the term nor its
+    /// kanji have one
+    pub fn term_levels(&self, term: &str, reading: &str) -> Option<TermLevels> {
+        let jlpt = self.jlpt_level(term, Some(reading));
+        let kanji = self.kanji_levels(term);
+        (jlpt.is_some() || !kanji.is_empty()).then(|| TermLevels {
+            term: term.to_string(),
+            reading: reading.to_string(),
+            jlpt,
+            kanji,
+        })
+    }
+}
+
+// Content of a list file, None if there is none
+fn read_list(path: &Path) -> Result<Option<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+// Tab-separated fields of the lines that aren't blank or comments, with their
+// line numbers
+fn list_lines(content: &str) -> impl Iterator<Item = (usize, Vec<&str>)> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.starts_with('#') && !line.trim().is_empty())
+        .map(|(i, line)| (i + 1, line.split('\t').map(str::trim).collect()))
+}
+
+fn parse_kanji_list<L: FromStr>(content: &str, file: &str) -> HashMap<char, L> {
+    let mut levels = HashMap::new();
+    for (line_number, fields) in list_lines(content) {
+        let mut kanji = fields.first().map(|k| k.chars()).into_iter().flatten();
+        let (Some(kanji), None, Some(level)) = (kanji.next(), kanji.next(), fields.get(1)) else {
+            warn!(line_number, "Skipping malformed line of {file}");
+            continue;
+        };
+        match level.parse() {
+            Ok(level) => {
+                levels.insert(kanji, level);
+            }
+            Err(_) => warn!(line_number, level, "Skipping unknown level in {file}"),
+        }
+    }
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_levels() {
+        assert_eq!("N3".parse::<JlptLevel>().unwrap(), JlptLevel::N3);
+        assert_eq!("n1".parse::<JlptLevel>().unwrap(), JlptLevel::N1);
+        assert_eq!("５".parse::<JlptLevel>().unwrap(), JlptLevel::N5);
+        assert!("N6".parse::<JlptLevel>().is_err());
+        assert!(JlptLevel::N5 < JlptLevel::N1);
+
+        let pre_2: KankenLevel = "準2級".parse().unwrap();
+        assert_eq!(
+            pre_2,
+            KankenLevel {
+                grade: 2,
+                pre: true
+            }
+        );
+        assert_eq!(pre_2, "pre-2".parse().unwrap());
+        assert_eq!(pre_2.to_string(), "準2級");
+        assert_eq!("10".parse::<KankenLevel>().unwrap().grade, 10);
+        assert!("準3級".parse::<KankenLevel>().is_err());
+        assert!("11".parse::<KankenLevel>().is_err());
+        let three: KankenLevel = "3級".parse().unwrap();
+        let two: KankenLevel = "2".parse().unwrap();
+        assert!(three < pre_2 && pre_2 < two);
+    }
+
+    #[test]
+    fn test_level_data() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(JLPT_VOCAB_FILE),
+            "# term\treading\tlevel\n食べる\tたべる\tN5\n方\tかた\tN4\n方\tほう\tN5\n上手\t\tN5\nbroken\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join(KANKEN_FILE), "食\t9級\n方\t9\n").unwrap();
+        let levels = LevelData::load(dir.path()).unwrap();
+        assert!(!levels.is_empty());
+
+        assert_eq!(levels.jlpt_level("方", Some("かた")), Some(JlptLevel::N4));
+        assert_eq!(levels.jlpt_level("方", None), Some(JlptLevel::N5));
+        assert_eq!(
+            levels.jlpt_level("上手", Some("じょうず")),
+            Some(JlptLevel::N5)
+        );
+        assert_eq!(levels.jlpt_level("食べる", Some("くべる")), None);
+
+        let term = levels.term_levels("食べ食", "たべる").unwrap();
+        assert_eq!(term.jlpt, None);
+        assert_eq!(
+            term.kanji,
+            [KanjiLevel {
+                kanji: '食',
+                jlpt: None,
+                kanken: Some(KankenLevel {
+                    grade: 9,
+                    pre: false
+                }),
+            }]
+        );
+        assert_eq!(levels.term_levels("猫", "ねこ"), None);
+
+        // Without any list files nothing is tagged
+        assert!(LevelData::load(&dir.path().join("missing"))
+            .unwrap()
+            .is_empty());
+    }
+}
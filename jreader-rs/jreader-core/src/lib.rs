@@ -0,0 +1,117 @@
+//! Dictionary lookup core of jReader, for embedding in desktop apps (e.g.
+//! Tauri) without running the HTTP server.
+//!
+//! The API has three parts:
+//!
+//! - Loading: [`Engine::load`] opens the imported dictionaries (the `db`
+//!   directory under `DICTS_PATH` for the server) and the zstd-compressed
+//!   MeCab dictionary used for tokenization.
+//! - Lookup: [`Engine::lookup`] finds the word at a character position of a
+//!   sentence, returning term, pitch accent and frequency results shaped by
+//!   the user's [`UserPreferences`].
+//! - Audio: [`audio::AudioDB`] queries a pronunciation audio database built by
+//!   `audio-db-bootstrap`.
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use camino::Utf8Path;
+//! use jreader_core::Engine;
+//!
+//! let engine = Engine::load(
+//!     Utf8Path::new("dicts/db"),
+//!     Utf8Path::new("dicts/system.dic.zst"),
+//! )?;
+//! let preferences = engine.default_preferences();
+//! let result = engine.lookup("猫が走った", 2, &preferences).await?;
+//! for dict in &result.dict {
+//!     println!("{}: {} entries", dict.title, dict.entries.len());
+//! }
+//!
+//! let audio = jreader_core::audio::AudioDB::new("audio/audio.db")?;
+//! let clips = audio.query_by_term_and_reading("走る", "はしる")?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod definitions;
+pub mod deinflect;
+pub mod dictionaries;
+pub mod furigana;
+pub mod levels;
+pub mod mecab;
+pub mod pitch;
+pub mod preferences;
+pub mod reverse_lookup;
+pub mod verb_pairs;
+
+pub use audio_db_query as audio;
+pub use yomitan_format;
+
+use anyhow::{Context, Result};
+use camino::Utf8Path as Path;
+use std::sync::Arc;
+
+use dictionaries::{LookupResult, YomitanDictionaries};
+use preferences::UserPreferences;
+
+/// Load a zstd-compressed vibrato/MeCab system dictionary as a tokenizer
+pub fn load_tokenizer(mecab_dict_path: &Path) -> Result<vibrato::Tokenizer> {
+    let file = std::fs::File::open(mecab_dict_path)
+        .with_context(|| format!("Failed to open MeCab dictionary file: {mecab_dict_path}"))?;
+    let reader = zstd::Decoder::new(file).with_context(|| {
+        format!("Failed to create zstd decoder for MeCab dictionary file: {mecab_dict_path}")
+    })?;
+    let dict = vibrato::Dictionary::read(reader)
+        .with_context(|| format!("Failed to read MeCab dictionary file: {mecab_dict_path}"))?;
+    Ok(vibrato::Tokenizer::new(dict))
+}
+
+/// Loaded dictionaries plus the tokenizer that finds words in running text
+pub struct Engine {
+    dictionaries: YomitanDictionaries,
+    tokenizer: vibrato::Tokenizer,
+}
+
+impl Engine {
+    /// Load the imported dictionaries in `dicts_db_dir` and the MeCab
+    /// dictionary at `mecab_dict_path`
+    pub fn load(dicts_db_dir: &Path, mecab_dict_path: &Path) -> Result<Self> {
+        let dictionaries = YomitanDictionaries::new(dicts_db_dir)
+            .context("Failed to load Yomitan dictionaries")?;
+        let tokenizer = load_tokenizer(mecab_dict_path)?;
+        Ok(Self::from_parts(dictionaries, tokenizer))
+    }
+
+    pub fn from_parts(dictionaries: YomitanDictionaries, tokenizer: vibrato::Tokenizer) -> Self {
+        Self {
+            dictionaries,
+            tokenizer,
+        }
+    }
+
+    pub fn dictionaries(&self) -> &YomitanDictionaries {
+        &self.dictionaries
+    }
+
+    /// Preferences with every loaded dictionary enabled, for apps without
+    /// per-user settings
+    pub fn default_preferences(&self) -> Arc<UserPreferences> {
+        self.dictionaries.default_preferences()
+    }
+
+    /// Look up the word at character `position` of `text`
+    pub async fn lookup(
+        &self,
+        text: &str,
+        position: usize,
+        user_preferences: &UserPreferences,
+    ) -> Result<LookupResult> {
+        let token_features = {
+            let mut worker = self.tokenizer.new_worker();
+            mecab::analyze_tokens(&mut worker, text, position)
+        };
+        self.dictionaries
+            .lookup(&token_features, user_preferences)
+            .await
+    }
+}
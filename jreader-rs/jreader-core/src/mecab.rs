@@ -0,0 +1,363 @@
+use std::ops::Range;
+
+use tracing::trace;
+use vibrato::dictionary::LexType;
+use vibrato::tokenizer::worker::Worker;
+
+// MeCab feature string (Japanese)
+#[derive(Debug, Clone)]
+pub struct TokenFeature {
+    // Surface form (表層形) - The actual text as it appears
+    pub surface_form: Option<String>,
+    // Part of Speech (品詞) - e.g., 名詞(noun), 動詞(verb), 助詞(particle)
+    pub pos: Option<String>,
+    // POS Subtype 1 (品詞細分類1) - e.g., 一般(general), 固有名詞(proper noun)
+    pub pos_subtype_1: Option<String>,
+    // POS Subtype 2 (品詞細分類2) - e.g., 域(region) for place names
+    pub pos_subtype_2: Option<String>,
+    // POS Subtype 3 (品詞細分類3) - Additional classification
+    pub pos_subtype_3: Option<String>,
+    // Conjugation Type (活用型) - How it conjugates ( for non-conjugating words)
+    pub conjugation_type: Option<String>,
+    // Conjugation Form (活用形) - Current conjugation ( for non-conjugating words)
+    pub conjugation_form: Option<String>,
+    // Dictionary Form (原形) - Base/dictionary form
+    pub dictionary_form: Option<String>,
+    // Reading (読み) - Katakana reading
+    pub reading: Option<String>,
+    // Pronunciation (発音) - Actual pronunciation (can differ from reading)
+    pub pronunciation: Option<String>,
+    // Guessed from the characters around an unknown token rather than found by
+    // the tokenizer
+    pub heuristic: bool,
+    // For verbs and adjectives, the surface form along with the auxiliaries
+    // inflecting it (食べ + られ + た)
+    pub inflected_form: Option<String>,
+}
+
+impl TokenFeature {
+    pub fn from_feature_string(surface_form: &str, feature_string: &str) -> Self {
+        trace!("🔍 Feature string: {}", feature_string);
+        let fields: Vec<&str> = feature_string.split(',').collect();
+        // Pad with None values if we don't have enough fields
+        let mut padded_fields = vec![None; 9];
+        for (i, field) in fields.iter().enumerate() {
+            padded_fields[i] = if *field == "*" {
+                None
+            } else {
+                Some((*field).to_string())
+            };
+        }
+
+        Self {
+            surface_form: Some(surface_form.to_string()),
+            pos: padded_fields[0].clone(),
+            pos_subtype_1: padded_fields[1].clone(),
+            pos_subtype_2: padded_fields[2].clone(),
+            pos_subtype_3: padded_fields[3].clone(),
+            conjugation_type: padded_fields[4].clone(),
+            conjugation_form: padded_fields[5].clone(),
+            dictionary_form: padded_fields[6].clone(),
+            reading: padded_fields[7].clone(),
+            pronunciation: padded_fields[8].clone(),
+            heuristic: false,
+            inflected_form: None,
+        }
+    }
+
+    /// A candidate word guessed by the unknown word fallback
+    fn heuristic(surface_form: String) -> Self {
+        Self {
+            dictionary_form: Some(surface_form.clone()),
+            surface_form: Some(surface_form),
+            pos: None,
+            pos_subtype_1: None,
+            pos_subtype_2: None,
+            pos_subtype_3: None,
+            conjugation_type: None,
+            conjugation_form: None,
+            reading: None,
+            pronunciation: None,
+            heuristic: true,
+            inflected_form: None,
+        }
+    }
+
+    /// Whether the token inflects the verb or adjective before it, as an
+    /// auxiliary (ない, た, ます), a suffix verb (れる, させる), a て or ば, or
+    /// a verb continuing a て-form (いる, しまう)
+    fn continues_inflection(&self) -> bool {
+        let dictionary_form = self.dictionary_form.as_deref().unwrap_or_default();
+        match (self.pos.as_deref(), self.pos_subtype_1.as_deref()) {
+            (Some("助動詞"), _) => {
+                ["ない", "た", "ます", "たい", "う", "よう", "ぬ", "ん"].contains(&dictionary_form)
+            }
+            (Some("動詞"), Some("接尾" | "非自立")) => [
+                "れる",
+                "られる",
+                "せる",
+                "させる",
+                "いる",
+                "る",
+                "しまう",
+                "ちゃう",
+                "じゃう",
+            ]
+            .contains(&dictionary_form),
+            (Some("助詞"), Some("接続助詞")) => {
+                matches!(self.surface_form.as_deref(), Some("て" | "で" | "ば"))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The surface of a verb or adjective followed by the tokens inflecting it,
+/// if any follow
+fn inflected_surface(token: &TokenFeature, following: &[TokenFeature]) -> Option<String> {
+    if !matches!(token.pos.as_deref(), Some("動詞" | "形容詞")) {
+        return None;
+    }
+    let inflections: Vec<&str> = following
+        .iter()
+        .take_while(|f| f.continues_inflection())
+        .filter_map(|f| f.surface_form.as_deref())
+        .collect();
+    if inflections.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "{}{}",
+        token.surface_form.as_deref().unwrap_or_default(),
+        inflections.concat()
+    ))
+}
+
+fn is_katakana(c: char) -> bool {
+    // Includes the prolonged sound mark ー
+    ('\u{30A1}'..='\u{30FC}').contains(&c) && c != '・'
+}
+
+fn is_kanji(c: char) -> bool {
+    ('\u{4E00}'..='\u{9FFF}').contains(&c) || ('\u{3400}'..='\u{4DBF}').contains(&c) || c == '々'
+}
+
+/// Candidate words for an unknown token (neologisms, names), which the
+/// tokenizer can't split into dictionary words. Within a katakana run the
+/// whole run and its shorter prefixes are tried, since the tokenizer may have
+/// cut the run up or joined it with a suffix; within a kanji compound each
+/// kanji from `position` on is tried on its own.
+fn unknown_word_candidates(text: &str, token: Range<usize>, position: usize) -> Vec<TokenFeature> {
+    let chars: Vec<char> = text.chars().collect();
+    let Some(&clicked) = chars.get(position) else {
+        return Vec::new();
+    };
+    let token_surface: String = chars[token.clone()].iter().collect();
+
+    let mut candidates: Vec<String> = Vec::new();
+    if is_katakana(clicked) {
+        let start = (0..position)
+            .rev()
+            .take_while(|&i| is_katakana(chars[i]))
+            .last()
+            .unwrap_or(position);
+        let end = (position..chars.len())
+            .take_while(|&i| is_katakana(chars[i]))
+            .last()
+            .map_or(position, |i| i + 1);
+        // Longest first, and every candidate still covers the clicked character
+        for prefix_end in ((position + 1).max(start + 2)..=end).rev() {
+            candidates.push(chars[start..prefix_end].iter().collect());
+        }
+    } else if is_kanji(clicked) && token.len() > 1 {
+        candidates.extend(
+            chars[position..token.end]
+                .iter()
+                .filter(|c| is_kanji(**c))
+                .map(|c| c.to_string()),
+        );
+    }
+
+    candidates.dedup();
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != token_surface)
+        .map(TokenFeature::heuristic)
+        .collect()
+}
+
+/// Auxiliaries after a verb or adjective considered for its inflected form
+const MAX_INFLECTION_TOKENS: usize = 6;
+
+/// Character offsets at which the tokens of `text` start
+pub fn token_starts(worker: &mut Worker, text: &str) -> Vec<usize> {
+    worker.reset_sentence(text);
+    worker.tokenize();
+    worker
+        .token_iter()
+        .map(|token| text[..token.range_byte().start].chars().count())
+        .collect()
+}
+
+pub fn analyze_tokens(worker: &mut Worker, text: &str, position: usize) -> Vec<TokenFeature> {
+    worker.reset_sentence(text);
+    worker.tokenize();
+    let tokens = worker.token_iter().collect::<Vec<_>>();
+
+    let mut entries = Vec::new();
+
+    // Find token at position and analyze compounds
+    for (i, token) in tokens.iter().enumerate() {
+        // Convert byte range to char indices
+        let start_char = text[..token.range_byte().start].chars().count();
+        let end_char = start_char + token.surface().chars().count();
+        let char_range = start_char..end_char;
+
+        if char_range.contains(&position) {
+            let feature = TokenFeature::from_feature_string(token.surface(), token.feature());
+
+            if token.lex_type() == LexType::Unknown {
+                trace!("🔍 Unknown token: {}", token.surface());
+                entries.extend(unknown_word_candidates(text, char_range.clone(), position));
+            }
+
+            // Handle compound words and verbs
+            if let Some("詞") = feature.pos.as_deref() {
+                if i + 1 < tokens.len() {
+                    let next_token = &tokens[i + 1];
+                    let next_feature = TokenFeature::from_feature_string(
+                        next_token.surface(),
+                        next_token.feature(),
+                    );
+
+                    if next_feature.pos.as_deref() == Some("動詞") {
+                        let compound = TokenFeature {
+                            surface_form: Some(format!(
+                                "{}{}",
+                                token.surface(),
+                                next_token.surface()
+                            )),
+                            dictionary_form: Some(format!(
+                                "{}{}",
+                                feature
+                                    .dictionary_form
+                                    .as_ref()
+                                    .unwrap_or(&token.surface().to_string()),
+                                next_feature
+                                    .dictionary_form
+                                    .as_ref()
+                                    .unwrap_or(&next_token.surface().to_string())
+                            )),
+                            ..feature.clone()
+                        };
+                        entries.push(compound);
+                    }
+                }
+            } else if let Some("名詞") = feature.pos.as_deref() {
+                let mut compound_surface = token.surface().to_string();
+                let mut j = i + 1;
+                while j < tokens.len() {
+                    let next_token = &tokens[j];
+                    let next_feature = TokenFeature::from_feature_string(
+                        next_token.surface(),
+                        next_token.feature(),
+                    );
+
+                    if next_feature.pos.as_deref() == Some("名詞") {
+                        compound_surface.push_str(next_token.surface());
+                        entries.push(TokenFeature {
+                            surface_form: Some(compound_surface.clone()),
+                            dictionary_form: Some(compound_surface.clone()),
+                            ..feature.clone()
+                        });
+                        j += 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
+
+            // Always include the individual token
+            let following: Vec<TokenFeature> = tokens[i + 1..]
+                .iter()
+                .take(MAX_INFLECTION_TOKENS)
+                .map(|t| TokenFeature::from_feature_string(t.surface(), t.feature()))
+                .collect();
+            let inflected_form = inflected_surface(&feature, &following);
+            entries.push(TokenFeature {
+                inflected_form,
+                ..feature
+            });
+        }
+    }
+
+    entries
+        .sort_by_key(|entry| std::cmp::Reverse(entry.surface_form.as_ref().map_or(0, |s| s.len())));
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn surfaces(candidates: Vec<TokenFeature>) -> Vec<String> {
+        assert!(candidates.iter().all(|c| c.heuristic));
+        candidates
+            .into_iter()
+            .map(|c| c.surface_form.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_unknown_word_candidates() {
+        // Katakana run cut short by the tokenizer: the whole run comes first,
+        // then its prefixes down to the clicked character, skipping the token
+        // itself
+        let text = "ボカロイドの曲";
+        assert_eq!(
+            surfaces(unknown_word_candidates(text, 0..3, 1)),
+            vec!["ボカロイド", "ボカロイ", "ボカ"]
+        );
+        // Clicking further in keeps prefixes that reach the clicked character
+        assert_eq!(
+            surfaces(unknown_word_candidates(text, 3..5, 3)),
+            vec!["ボカロイド", "ボカロイ"]
+        );
+
+        // Kanji compound: each kanji from the clicked one on
+        let text = "超電磁砲を撃つ";
+        assert_eq!(
+            surfaces(unknown_word_candidates(text, 0..4, 1)),
+            vec!["電", "磁", "砲"]
+        );
+        // A single kanji token is already looked up as is
+        assert!(unknown_word_candidates(text, 3..4, 3).is_empty());
+
+        assert!(unknown_word_candidates("ねこ", 0..2, 0).is_empty());
+    }
+
+    #[test]
+    fn test_inflected_surface() {
+        let token = |surface, feature| TokenFeature::from_feature_string(surface, feature);
+        let verb = token("食べ", "動詞,自立,*,*,一段,未然形,食べる,タベ,タベ");
+        let following = [
+            token("られ", "動詞,接尾,*,*,一段,連用形,られる,ラレ,ラレ"),
+            token("て", "助詞,接続助詞,*,*,*,*,て,テ,テ"),
+            token("い", "動詞,非自立,*,*,一段,連用形,いる,イ,イ"),
+            token("た", "助動詞,*,*,*,特殊・タ,基本形,た,タ,タ"),
+            token(
+                "らしい",
+                "助動詞,*,*,*,形容詞・イ段,基本形,らしい,ラシイ,ラシイ",
+            ),
+        ];
+        assert_eq!(
+            inflected_surface(&verb, &following).as_deref(),
+            Some("食べられていた")
+        );
+        assert_eq!(inflected_surface(&verb, &following[4..]), None);
+
+        let noun = token("猫", "名詞,一般,*,*,*,*,猫,ネコ,ネコ");
+        assert_eq!(inflected_surface(&noun, &following), None);
+    }
+}
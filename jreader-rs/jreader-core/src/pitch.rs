@@ -0,0 +1,119 @@
+//! Mora-level pitch accent helpers, so clients can draw accent graphs without
+//! reimplementing the rules.
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PitchLevel {
+    #[serde(rename = "H")]
+    High,
+    #[serde(rename = "L")]
+    Low,
+}
+
+/// Small kana that combine with the previous kana into a single mora
+fn is_combining_kana(c: char) -> bool {
+    matches!(
+        c,
+        'ゃ' | 'ゅ'
+            | 'ょ'
+            | 'ぁ'
+            | 'ぃ'
+            | 'ぅ'
+            | 'ぇ'
+            | 'ぉ'
+            | 'ゎ'
+            | 'ャ'
+            | 'ュ'
+            | 'ョ'
+            | 'ァ'
+            | 'ィ'
+            | 'ゥ'
+            | 'ェ'
+            | 'ォ'
+            | 'ヮ'
+    )
+}
+
+/// Split a kana reading into morae, e.g. きょうと -> [きょ, う, と].
+/// っ and ー are morae of their own.
+pub fn split_morae(reading: &str) -> Vec<String> {
+    let mut morae: Vec<String> = Vec::new();
+    for c in reading.chars() {
+        match morae.last_mut() {
+            Some(last) if is_combining_kana(c) => last.push(c),
+            _ => morae.push(c.to_string()),
+        }
+    }
+    morae
+}
+
+/// High/low pitch of each mora for a downstep at `position`, plus one extra
+/// level for a following particle (which tells 平板 from 尾高 apart).
+pub fn pitch_pattern(position: usize, mora_count: usize) -> Vec<PitchLevel> {
+    (0..=mora_count)
+        .map(|i| {
+            let high = match position {
+                // 平板: low start, then high through the particle
+                0 => i > 0,
+                // 頭高: only the first mora is high
+                1 => i == 0,
+                // 中高/尾高: high from the second mora up to the downstep
+                _ => i > 0 && i < position,
+            };
+            if high {
+                PitchLevel::High
+            } else {
+                PitchLevel::Low
+            }
+        })
+        .collect()
+}
+
+/// Mora positions from a Yomitan `nasal`/`devoice` field, which is either a
+/// single 1-based position or an array of them. Returned as 0-based indices
+/// into the mora list.
+pub fn mora_indices(value: Option<&serde_json::Value>) -> Vec<usize> {
+    let to_index = |v: &serde_json::Value| v.as_u64().filter(|p| *p > 0).map(|p| p as usize - 1);
+    let mut indices: Vec<usize> = match value {
+        Some(serde_json::Value::Array(values)) => values.iter().filter_map(to_index).collect(),
+        Some(v) => to_index(v).into_iter().collect(),
+        None => Vec::new(),
+    };
+    indices.sort_unstable();
+    indices.dedup();
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PitchLevel::{High as H, Low as L};
+
+    #[test]
+    fn test_split_morae() {
+        assert_eq!(split_morae("きょうと"), vec!["きょ", "う", "と"]);
+        assert_eq!(split_morae("がっこう"), vec!["が", "っ", "こ", "う"]);
+        assert_eq!(split_morae("ファイル"), vec!["ファ", "イ", "ル"]);
+        assert_eq!(split_morae("ラーメン"), vec!["ラ", "ー", "メ", "ン"]);
+    }
+
+    #[test]
+    fn test_pitch_pattern() {
+        // さくら (0), いのち (1), こころ (2), おとこ (3)
+        assert_eq!(pitch_pattern(0, 3), vec![L, H, H, H]);
+        assert_eq!(pitch_pattern(1, 3), vec![H, L, L, L]);
+        assert_eq!(pitch_pattern(2, 3), vec![L, H, L, L]);
+        assert_eq!(pitch_pattern(3, 3), vec![L, H, H, L]);
+    }
+
+    #[test]
+    fn test_mora_indices() {
+        assert_eq!(mora_indices(None), Vec::<usize>::new());
+        assert_eq!(mora_indices(Some(&serde_json::json!(3))), vec![2]);
+        assert_eq!(
+            mora_indices(Some(&serde_json::json!([4, 2, 0]))),
+            vec![1, 3]
+        );
+    }
+}
@@ -0,0 +1,497 @@
+//! Per-user dictionary preferences that shape lookups.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use wana_kana::ConvertJapanese;
+
+use crate::dictionaries::{DictionaryInfo, DictionaryType};
+
+#[derive(Debug)]
+pub struct UserPreferences {
+    pub user_id: Uuid,
+    // Term dictionaries
+    pub term_dictionary_order: Vec<String>,
+    pub term_disabled_dictionaries: HashSet<String>,
+    pub term_spoiler_dictionaries: HashSet<String>,
+    pub freq_dictionary_order: Vec<String>,
+    pub freq_disabled_dictionaries: HashSet<String>,
+    // Audio sources (e.g. "nhk16", "forvo")
+    pub audio_disabled_sources: HashSet<String>,
+    // Preferred audio sources, most preferred first
+    pub audio_source_order: Vec<String>,
+    // How books are displayed in the reader
+    pub reader: ReaderSettings,
+    // What lookup results show in the popup
+    pub popup: PopupSettings,
+}
+
+pub const MIN_FONT_SIZE: f32 = 0.5;
+pub const MAX_FONT_SIZE: f32 = 4.0;
+pub const MIN_LINE_HEIGHT: f32 = 1.0;
+pub const MAX_LINE_HEIGHT: f32 = 3.0;
+pub const MAX_VERTICAL_MARGIN: f32 = 25.0;
+pub const MAX_FONT_FAMILY_LENGTH: usize = 200;
+pub const MAX_CUSTOM_CSS_LENGTH: usize = 20_000;
+pub const MAX_POPUP_DEFINITIONS: usize = 100;
+pub const MAX_FREQUENCY_BANDS: usize = 10;
+pub const MAX_FREQUENCY_BAND_LABEL_LENGTH: usize = 32;
+pub const MAX_AUDIO_SOURCES: usize = 100;
+pub const MAX_AUTOPLAY_RULES: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WritingMode {
+    #[default]
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReaderTheme {
+    #[default]
+    Light,
+    Dark,
+    Asuka,
+    SolarizedLight,
+    SolarizedDark,
+}
+
+/// Display settings of the reader, kept with the account so that they follow
+/// the user across devices
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ReaderSettings {
+    /// CSS font family, or the book's own fonts if unset
+    pub font_family: Option<String>,
+    /// Font size in `em`
+    pub font_size: f32,
+    pub line_height: f32,
+    /// Margin above and below the text in `vh`
+    pub vertical_margin: f32,
+    pub writing_mode: WritingMode,
+    pub theme: ReaderTheme,
+    /// Stylesheet applied on top of the book's own
+    pub custom_css: String,
+}
+
+impl Default for ReaderSettings {
+    fn default() -> Self {
+        Self {
+            font_family: None,
+            font_size: 1.0,
+            line_height: 1.8,
+            vertical_margin: 0.5,
+            writing_mode: WritingMode::default(),
+            theme: ReaderTheme::default(),
+            custom_css: String::new(),
+        }
+    }
+}
+
+impl ReaderSettings {
+    /// Check that the settings are in range and can't break out of the
+    /// stylesheet they're injected into
+    pub fn validate(&self) -> Result<(), String> {
+        let in_range =
+            |value: f32, min: f32, max: f32| value.is_finite() && (min..=max).contains(&value);
+        if !in_range(self.font_size, MIN_FONT_SIZE, MAX_FONT_SIZE) {
+            return Err(format!(
+                "Font size must be between {MIN_FONT_SIZE} and {MAX_FONT_SIZE}"
+            ));
+        }
+        if !in_range(self.line_height, MIN_LINE_HEIGHT, MAX_LINE_HEIGHT) {
+            return Err(format!(
+                "Line height must be between {MIN_LINE_HEIGHT} and {MAX_LINE_HEIGHT}"
+            ));
+        }
+        if !in_range(self.vertical_margin, 0.0, MAX_VERTICAL_MARGIN) {
+            return Err(format!(
+                "Vertical margin must be between 0 and {MAX_VERTICAL_MARGIN}"
+            ));
+        }
+        if let Some(font_family) = &self.font_family {
+            if font_family.chars().count() > MAX_FONT_FAMILY_LENGTH {
+                return Err(format!(
+                    "Font family must be at most {MAX_FONT_FAMILY_LENGTH} characters"
+                ));
+            }
+            if font_family.contains([';', '{', '}', '<', '>']) {
+                return Err("Font family must be a list of font names".to_string());
+            }
+        }
+        if self.custom_css.chars().count() > MAX_CUSTOM_CSS_LENGTH {
+            return Err(format!(
+                "Custom CSS must be at most {MAX_CUSTOM_CSS_LENGTH} characters"
+            ));
+        }
+        // The CSS ends up in a <style> element of the page
+        if self.custom_css.contains("</") {
+            return Err("Custom CSS must not contain markup".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl UserPreferences {
+    pub fn default(user_id: Uuid, dictionary_info: Vec<DictionaryInfo>) -> Self {
+        // Use the format "title#revision" for the dictionary order
+        let term_dictionaries = dictionary_info
+            .iter()
+            .filter(|d| d.dictionary_type == DictionaryType::Term)
+            .collect::<Vec<_>>();
+        let mut term_dictionary_order = term_dictionaries
+            .iter()
+            .map(|d| format!("{}#{}", d.title, d.revision))
+            .collect::<Vec<_>>();
+        term_dictionary_order.sort();
+
+        let freq_dictionaries = dictionary_info
+            .iter()
+            .filter(|d| d.dictionary_type == DictionaryType::Frequency)
+            .collect::<Vec<_>>();
+        let mut freq_dictionary_order = freq_dictionaries
+            .iter()
+            .map(|d| format!("{}#{}", d.title, d.revision))
+            .collect::<Vec<_>>();
+        freq_dictionary_order.sort();
+        Self {
+            user_id,
+            term_dictionary_order: term_dictionary_order,
+            term_disabled_dictionaries: HashSet::new(),
+            term_spoiler_dictionaries: HashSet::new(),
+            freq_dictionary_order: freq_dictionary_order,
+            freq_disabled_dictionaries: HashSet::new(),
+            audio_disabled_sources: HashSet::new(),
+            audio_source_order: Vec::new(),
+            reader: ReaderSettings::default(),
+            popup: PopupSettings::default(),
+        }
+    }
+
+    /// Sort `items` by the user's audio source order. Sources the user didn't
+    /// rank come after the ranked ones, in the order they were in.
+    pub fn sort_by_audio_source_order<T>(&self, items: &mut [T], source: impl Fn(&T) -> &str) {
+        items.sort_by_key(|item| {
+            let source = source(item);
+            self.audio_source_order
+                .iter()
+                .position(|s| s == source)
+                .unwrap_or(self.audio_source_order.len())
+        });
+    }
+}
+
+/// Check a list of audio source ids as stored in the preferences
+pub fn validate_audio_sources<'a>(
+    sources: impl IntoIterator<Item = &'a String>,
+) -> Result<(), String> {
+    let mut count = 0;
+    for source in sources {
+        count += 1;
+        if source.trim().is_empty() || source.contains(',') {
+            return Err(format!("Invalid audio source: {source:?}"));
+        }
+    }
+    if count > MAX_AUDIO_SOURCES {
+        return Err(format!(
+            "At most {MAX_AUDIO_SOURCES} audio sources can be listed"
+        ));
+    }
+    Ok(())
+}
+
+/// Sections and limits of the lookup popup. They're applied to lookup
+/// responses on the server, so clients render what they get.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PopupSettings {
+    pub show_pitch_accent: bool,
+    pub show_frequency: bool,
+    pub show_tags: bool,
+    /// Dictionaries ("title#revision") whose results start collapsed
+    pub collapsed_dictionaries: HashSet<String>,
+    /// Definitions shown per entry, or all of them if unset
+    pub max_definitions: Option<usize>,
+    /// Bands frequency ranks are colored by, from the most frequent
+    pub frequency_bands: Vec<FrequencyBand>,
+    /// Rules picking the recording played when the popup opens, tried in
+    /// order. Nothing plays if none of them matches, or there are none.
+    pub audio_autoplay: Vec<AutoplayRule>,
+}
+
+/// A way of picking the recording of a term to play automatically
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "rule")]
+pub enum AutoplayRule {
+    /// The first recording from this source, e.g. "nhk16"
+    Source { source: String },
+    /// The first recording whose reading is that of the entry
+    MatchingReading,
+    /// The first recording, whatever it is
+    First,
+}
+
+impl AutoplayRule {
+    fn pick<'a>(
+        &self,
+        mut recordings: impl Iterator<Item = (usize, (&'a str, &'a str))>,
+        reading: &str,
+    ) -> Option<usize> {
+        match self {
+            Self::Source { source } => recordings.find(|(_, (s, _))| s == source),
+            Self::MatchingReading => {
+                let reading = reading.to_hiragana();
+                recordings.find(|(_, (_, r))| r.to_hiragana() == reading)
+            }
+            Self::First => recordings.next(),
+        }
+        .map(|(i, _)| i)
+    }
+}
+
+/// Ranks up to `max_rank` (e.g. the top 5000 words) and below the previous
+/// band's are shown as `label` in `color`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrequencyBand {
+    pub label: String,
+    pub max_rank: u32,
+    /// Hex color, `#rgb` or `#rrggbb`
+    pub color: String,
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+impl Default for PopupSettings {
+    fn default() -> Self {
+        Self {
+            show_pitch_accent: true,
+            show_frequency: true,
+            show_tags: true,
+            collapsed_dictionaries: HashSet::new(),
+            max_definitions: None,
+            frequency_bands: Vec::new(),
+            audio_autoplay: vec![
+                AutoplayRule::Source {
+                    source: "nhk16".to_string(),
+                },
+                AutoplayRule::MatchingReading,
+            ],
+        }
+    }
+}
+
+impl PopupSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self
+            .max_definitions
+            .is_some_and(|max| !(1..=MAX_POPUP_DEFINITIONS).contains(&max))
+        {
+            return Err(format!(
+                "Max definitions must be between 1 and {MAX_POPUP_DEFINITIONS}"
+            ));
+        }
+        if self.frequency_bands.len() > MAX_FREQUENCY_BANDS {
+            return Err(format!(
+                "At most {MAX_FREQUENCY_BANDS} frequency bands can be defined"
+            ));
+        }
+        let mut previous_max_rank = 0;
+        for band in &self.frequency_bands {
+            let label_length = band.label.trim().chars().count();
+            if label_length == 0 || label_length > MAX_FREQUENCY_BAND_LABEL_LENGTH {
+                return Err(format!(
+                    "Frequency band labels must be 1 to {MAX_FREQUENCY_BAND_LABEL_LENGTH} characters"
+                ));
+            }
+            if !is_hex_color(&band.color) {
+                return Err(format!(
+                    "Frequency band color must be a hex color: {}",
+                    band.color
+                ));
+            }
+            if band.max_rank <= previous_max_rank {
+                return Err("Frequency bands must be in increasing order of rank".to_string());
+            }
+            previous_max_rank = band.max_rank;
+        }
+        if self.audio_autoplay.len() > MAX_AUTOPLAY_RULES {
+            return Err(format!(
+                "At most {MAX_AUTOPLAY_RULES} autoplay rules can be defined"
+            ));
+        }
+        validate_audio_sources(self.audio_autoplay.iter().filter_map(|rule| match rule {
+            AutoplayRule::Source { source } => Some(source),
+            _ => None,
+        }))
+    }
+
+    /// Which of the recordings of an entry read `reading` plays automatically.
+    /// `recording` gives the source of each and its reading, or its
+    /// expression for recordings without one.
+    pub fn autoplay<T>(
+        &self,
+        recordings: &[T],
+        reading: &str,
+        recording: impl Fn(&T) -> (&str, &str),
+    ) -> Option<usize> {
+        self.audio_autoplay
+            .iter()
+            .find_map(|rule| rule.pick(recordings.iter().map(&recording).enumerate(), reading))
+    }
+
+    /// The band a frequency rank falls in, if any
+    pub fn frequency_band(&self, rank: f64) -> Option<&FrequencyBand> {
+        self.frequency_bands
+            .iter()
+            .find(|band| rank <= band.max_rank as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reader_settings() {
+        let settings: ReaderSettings = serde_json::from_str(
+            r#"{"fontSize": 1.5, "writingMode": "vertical", "theme": "solarized-dark"}"#,
+        )
+        .unwrap();
+        assert_eq!(settings.font_size, 1.5);
+        assert_eq!(settings.writing_mode, WritingMode::Vertical);
+        assert_eq!(settings.theme, ReaderTheme::SolarizedDark);
+        assert_eq!(settings.line_height, ReaderSettings::default().line_height);
+        assert!(settings.validate().is_ok());
+
+        let invalid = [
+            ReaderSettings {
+                font_size: 10.0,
+                ..settings.clone()
+            },
+            ReaderSettings {
+                line_height: f32::NAN,
+                ..settings.clone()
+            },
+            ReaderSettings {
+                font_family: Some("serif; color: red".to_string()),
+                ..settings.clone()
+            },
+            ReaderSettings {
+                custom_css: "</style><script>".to_string(),
+                ..settings.clone()
+            },
+        ];
+        for settings in invalid {
+            assert!(settings.validate().is_err(), "{settings:?}");
+        }
+    }
+
+    #[test]
+    fn test_popup_settings() {
+        let settings: PopupSettings = serde_json::from_str(
+            r#"{"showPitchAccent": false, "collapsedDictionaries": ["JMdict#1"]}"#,
+        )
+        .unwrap();
+        assert!(!settings.show_pitch_accent);
+        assert!(settings.show_frequency);
+        assert!(settings.collapsed_dictionaries.contains("JMdict#1"));
+        assert!(settings.validate().is_ok());
+
+        let zero = PopupSettings {
+            max_definitions: Some(0),
+            ..settings.clone()
+        };
+        assert!(zero.validate().is_err());
+    }
+
+    #[test]
+    fn test_audio_autoplay() {
+        let recordings = [("forvo", "食べる"), ("jpod", "タベル"), ("nhk16", "たべる")];
+        let autoplay = |settings: &PopupSettings, reading| {
+            settings.autoplay(&recordings, reading, |&(source, reading)| (source, reading))
+        };
+        let settings = PopupSettings::default();
+        assert_eq!(autoplay(&settings, "たべる"), Some(2));
+
+        let settings: PopupSettings = serde_json::from_str(
+            r#"{"audioAutoplay": [{"rule": "source", "source": "shinmeikai8"}, {"rule": "matchingReading"}]}"#,
+        )
+        .unwrap();
+        assert!(settings.validate().is_ok());
+        // Katakana readings match too
+        assert_eq!(autoplay(&settings, "たべる"), Some(1));
+        assert_eq!(autoplay(&settings, "くう"), None);
+
+        let first = PopupSettings {
+            audio_autoplay: vec![AutoplayRule::First],
+            ..Default::default()
+        };
+        assert_eq!(autoplay(&first, "くう"), Some(0));
+        let none = PopupSettings {
+            audio_autoplay: Vec::new(),
+            ..Default::default()
+        };
+        assert_eq!(autoplay(&none, "たべる"), None);
+
+        let invalid = PopupSettings {
+            audio_autoplay: vec![AutoplayRule::Source {
+                source: "nhk16,forvo".to_string(),
+            }],
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_frequency_bands() {
+        let band = |label: &str, max_rank, color: &str| FrequencyBand {
+            label: label.to_string(),
+            max_rank,
+            color: color.to_string(),
+        };
+        let settings = PopupSettings {
+            frequency_bands: vec![band("common", 5000, "#2e7d32"), band("rare", 30000, "#fa0")],
+            ..Default::default()
+        };
+        assert!(settings.validate().is_ok());
+        let label = |rank| settings.frequency_band(rank).map(|b| b.label.as_str());
+        assert_eq!(label(1.0), Some("common"));
+        assert_eq!(label(5000.0), Some("common"));
+        assert_eq!(label(5000.5), Some("rare"));
+        assert_eq!(label(40000.0), None);
+
+        let unordered = PopupSettings {
+            frequency_bands: vec![band("rare", 30000, "#fa0"), band("common", 5000, "#fff")],
+            ..Default::default()
+        };
+        assert!(unordered.validate().is_err());
+        let bad_color = PopupSettings {
+            frequency_bands: vec![band("common", 5000, "red;}")],
+            ..Default::default()
+        };
+        assert!(bad_color.validate().is_err());
+    }
+
+    #[test]
+    fn test_audio_source_order() {
+        let mut preferences = UserPreferences::default(Uuid::nil(), Vec::new());
+        preferences.audio_source_order = vec!["nhk16".to_string(), "forvo".to_string()];
+        let mut sources = vec!["jpod", "forvo", "shinmeikai8", "nhk16", "forvo"];
+        preferences.sort_by_audio_source_order(&mut sources, |s| s);
+        assert_eq!(sources, ["nhk16", "forvo", "forvo", "jpod", "shinmeikai8"]);
+
+        assert!(validate_audio_sources(&preferences.audio_source_order).is_ok());
+        assert!(validate_audio_sources(&["nhk16,jpod".to_string()]).is_err());
+        assert!(validate_audio_sources(&[" ".to_string()]).is_err());
+        let too_many: Vec<String> = (0..=MAX_AUDIO_SOURCES).map(|i| i.to_string()).collect();
+        assert!(validate_audio_sources(&too_many).is_err());
+    }
+}
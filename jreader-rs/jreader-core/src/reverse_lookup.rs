@@ -0,0 +1,345 @@
+//! English → Japanese lookup for bilingual dictionaries.
+//!
+//! The glosses of a term dictionary are split into words at import and stored
+//! in a `reverse_entry` table in `reverse_index.db` next to the dictionary's
+//! other databases. Each row points back at the term bank key, entry and
+//! definition it came from. Monolingual dictionaries get an empty index marked
+//! as unsupported, so they aren't scanned again.
+
+use anyhow::Result;
+use camino::Utf8Path as Path;
+use rusqlite::{params, params_from_iter, Connection, OpenFlags, OptionalExtension};
+use yomitan_format::json_schema::index::DictionaryIndex;
+use yomitan_format::json_schema::term_bank_v3::{Definition, TermEntry};
+
+use crate::definitions::definition_text;
+
+const REVERSE_INDEX_FILE: &str = "reverse_index.db";
+
+/// Words of a query beyond this are ignored
+pub const MAX_QUERY_WORDS: usize = 8;
+
+/// Without a `targetLanguage` in the index, a dictionary is treated as
+/// bilingual when at least this share of its definitions are Latin script
+const MIN_LATIN_DEFINITION_SHARE: f64 = 0.5;
+
+const STOP_WORDS: [&str; 16] = [
+    "a", "an", "the", "to", "of", "in", "on", "at", "for", "or", "and", "be", "as", "by", "etc",
+    "one's",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReverseMatch {
+    pub key: String,
+    /// Position of the entry among the term bank entries for `key`
+    pub entry: usize,
+    /// Position of the matching definition within the entry
+    pub definition: usize,
+    /// Word count of the shortest matching gloss, so "run" ranks above
+    /// "to run away from home"
+    pub gloss_words: usize,
+}
+
+fn is_latin(c: char) -> bool {
+    c.is_ascii_alphanumeric() || ('\u{00C0}'..='\u{024F}').contains(&c)
+}
+
+/// Whether most letters of a definition are Latin script
+fn is_latin_text(text: &str) -> bool {
+    let (mut letters, mut latin) = (0, 0);
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        letters += 1;
+        latin += is_latin(c) as usize;
+    }
+    latin > 0 && latin * 2 >= letters
+}
+
+/// Lowercased words of a gloss or query, without stop words or notes in
+/// parentheses, e.g. "to run (of a machine)" -> ["run"]
+pub fn gloss_words(text: &str) -> Vec<String> {
+    let mut stripped = String::with_capacity(text.len());
+    let mut depth = 0usize;
+    for c in text.chars() {
+        match c {
+            '(' | '（' => depth += 1,
+            ')' | '）' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => stripped.push(c),
+            _ => {}
+        }
+    }
+
+    let mut words: Vec<String> = Vec::new();
+    for word in stripped.split(|c: char| !is_latin(c) && c != '\'') {
+        let word = word.trim_matches('\'').to_lowercase();
+        if word.chars().any(|c| c.is_alphabetic())
+            && !STOP_WORDS.contains(&word.as_str())
+            && !words.contains(&word)
+        {
+            words.push(word);
+        }
+    }
+    words
+}
+
+/// Separate glosses of a definition, e.g. "to run; to dash" -> ["to run", "to dash"]
+fn split_glosses(text: &str) -> impl Iterator<Item = &str> {
+    text.split([';', '；', '\n'])
+        .map(str::trim)
+        .filter(|g| !g.is_empty())
+}
+
+fn is_japanese_language(language: &str) -> bool {
+    language.eq_ignore_ascii_case("ja") || language.to_ascii_lowercase().starts_with("ja-")
+}
+
+/// Build the reverse index of the dictionary in `dict_dir`. Returns whether the
+/// dictionary supports reverse lookup, or `None` when it has no term bank.
+pub fn build_index(dict_dir: &Path) -> Result<Option<bool>> {
+    let db_path = dict_dir.join("term_bank_dict.db");
+    if !db_path.exists() {
+        return Ok(None);
+    }
+    let index: DictionaryIndex =
+        serde_json::from_str(&std::fs::read_to_string(dict_dir.join("index.json"))?)?;
+
+    let tmp_path = dict_dir.join(format!("{REVERSE_INDEX_FILE}.tmp"));
+    if tmp_path.exists() {
+        std::fs::remove_file(&tmp_path)?;
+    }
+    let mut target = Connection::open(&tmp_path)?;
+    target.execute_batch(
+        "CREATE TABLE reverse_index (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            supported INTEGER NOT NULL
+        );
+        CREATE TABLE reverse_entry (
+            word TEXT NOT NULL,
+            key TEXT NOT NULL,
+            entry INTEGER NOT NULL,
+            definition INTEGER NOT NULL,
+            gloss_words INTEGER NOT NULL
+        );",
+    )?;
+
+    let (mut definitions, mut latin_definitions) = (0u64, 0u64);
+    {
+        let source = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let tx = target.transaction()?;
+        {
+            let mut insert = tx.prepare("INSERT INTO reverse_entry VALUES (?1, ?2, ?3, ?4, ?5)")?;
+            let mut stmt = source.prepare("SELECT key, json FROM term_entry")?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let key: String = row.get(0)?;
+                let json: String = row.get(1)?;
+                let Ok(entries) = serde_json::from_str::<Vec<TermEntry>>(&json) else {
+                    continue;
+                };
+                for (entry_index, entry) in entries.iter().enumerate() {
+                    for (definition_index, definition) in entry.definitions.iter().enumerate() {
+                        if matches!(definition, Definition::Deinflection(_)) {
+                            continue;
+                        }
+                        definitions += 1;
+                        let text = definition_text(definition);
+                        if !is_latin_text(&text) {
+                            continue;
+                        }
+                        latin_definitions += 1;
+                        for gloss in split_glosses(&text) {
+                            let words = gloss_words(gloss);
+                            for word in &words {
+                                insert.execute(params![
+                                    word,
+                                    key,
+                                    entry_index as i64,
+                                    definition_index as i64,
+                                    words.len() as i64,
+                                ])?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        tx.commit()?;
+    }
+
+    let supported = match index.target_language.as_deref() {
+        Some(language) => !is_japanese_language(language),
+        None => {
+            definitions > 0
+                && latin_definitions as f64 / definitions as f64 >= MIN_LATIN_DEFINITION_SHARE
+        }
+    };
+    if supported {
+        target.execute_batch("CREATE INDEX idx_reverse_word ON reverse_entry(word);")?;
+    } else {
+        target.execute_batch("DELETE FROM reverse_entry; VACUUM;")?;
+    }
+    target.execute(
+        "INSERT INTO reverse_index (id, supported) VALUES (1, ?1)",
+        params![supported],
+    )?;
+    drop(target);
+    std::fs::rename(&tmp_path, dict_dir.join(REVERSE_INDEX_FILE))?;
+    Ok(Some(supported))
+}
+
+/// Whether the dictionary supports reverse lookup, or `None` when its index
+/// hasn't been built
+pub fn index_status(dict_dir: &Path) -> Result<Option<bool>> {
+    let db_path = dict_dir.join(REVERSE_INDEX_FILE);
+    if !db_path.exists() {
+        return Ok(None);
+    }
+    let conn = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    Ok(conn
+        .query_row(
+            "SELECT supported FROM reverse_index WHERE id = 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+/// Definitions with a gloss containing all `words`, shortest glosses first.
+/// Only the first `MAX_QUERY_WORDS` words are searched for. Dictionaries
+/// without a reverse index have no matches.
+pub fn search(dict_dir: &Path, words: &[String], limit: usize) -> Result<Vec<ReverseMatch>> {
+    let words = &words[..words.len().min(MAX_QUERY_WORDS)];
+    let db_path = dict_dir.join(REVERSE_INDEX_FILE);
+    if words.is_empty() || !db_path.exists() {
+        return Ok(Vec::new());
+    }
+    let conn = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let placeholders = vec!["?"; words.len()].join(", ");
+    let sql = format!(
+        "SELECT key, entry, definition, MIN(gloss_words) AS len
+         FROM reverse_entry
+         WHERE word IN ({placeholders})
+         GROUP BY key, entry, definition
+         HAVING COUNT(DISTINCT word) = {}
+         ORDER BY len, key
+         LIMIT {limit}",
+        words.len()
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let matches = stmt
+        .query_map(params_from_iter(words), |row| {
+            Ok(ReverseMatch {
+                key: row.get(0)?,
+                entry: row.get::<_, i64>(1)? as usize,
+                definition: row.get::<_, i64>(2)? as usize,
+                gloss_words: row.get::<_, i64>(3)? as usize,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camino::Utf8PathBuf;
+    use serde_json::json;
+
+    fn write_dictionary(dict_dir: &Path, index: serde_json::Value, terms: &[serde_json::Value]) {
+        std::fs::write(dict_dir.join("index.json"), index.to_string()).unwrap();
+        let conn = Connection::open(dict_dir.join("term_bank_dict.db")).unwrap();
+        conn.execute_batch("CREATE TABLE term_entry (key TEXT, json TEXT)")
+            .unwrap();
+        for term in terms {
+            conn.execute(
+                "INSERT INTO term_entry VALUES (?1, ?2)",
+                params![term[0].as_str().unwrap(), json!([term]).to_string()],
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_gloss_words() {
+        assert_eq!(gloss_words("to run (of a machine)"), vec!["run"]);
+        assert_eq!(gloss_words("Run Away!"), vec!["run", "away"]);
+        assert_eq!(gloss_words("see 走る"), vec!["see"]);
+        assert!(gloss_words("1. 走ること").is_empty());
+    }
+
+    #[test]
+    fn test_build_and_search() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dict_dir = Utf8PathBuf::try_from(temp_dir.path().to_path_buf()).unwrap();
+        assert_eq!(build_index(&dict_dir).unwrap(), None);
+
+        write_dictionary(
+            &dict_dir,
+            json!({"title": "Test", "revision": "1", "format": 3}),
+            &[
+                json!([
+                    "走る",
+                    "はしる",
+                    "",
+                    "v5",
+                    0,
+                    ["to run; to dash", "to travel (of a vehicle)"],
+                    1,
+                    ""
+                ]),
+                json!([
+                    "逃げる",
+                    "にげる",
+                    "",
+                    "v1",
+                    0,
+                    ["to run away; to escape"],
+                    2,
+                    ""
+                ]),
+            ],
+        );
+        assert_eq!(index_status(&dict_dir).unwrap(), None);
+        assert_eq!(build_index(&dict_dir).unwrap(), Some(true));
+        assert_eq!(index_status(&dict_dir).unwrap(), Some(true));
+
+        let keys = |words: &[&str]| {
+            let words: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+            search(&dict_dir, &words, 10)
+                .unwrap()
+                .into_iter()
+                .map(|m| (m.key, m.definition, m.gloss_words))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            keys(&["run"]),
+            vec![("走る".to_string(), 0, 1), ("逃げる".to_string(), 0, 2)]
+        );
+        assert_eq!(keys(&["run", "away"]), vec![("逃げる".to_string(), 0, 2)]);
+        assert_eq!(keys(&["travel"]), vec![("走る".to_string(), 1, 1)]);
+    }
+
+    #[test]
+    fn test_monolingual_dictionary_is_unsupported() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dict_dir = Utf8PathBuf::try_from(temp_dir.path().to_path_buf()).unwrap();
+        write_dictionary(
+            &dict_dir,
+            json!({"title": "国語", "revision": "1", "format": 3}),
+            &[json!([
+                "走る",
+                "はしる",
+                "",
+                "v5",
+                0,
+                ["足を速く動かして進む。"],
+                1,
+                ""
+            ])],
+        );
+        assert_eq!(build_index(&dict_dir).unwrap(), Some(false));
+        assert_eq!(index_status(&dict_dir).unwrap(), Some(false));
+        assert!(search(&dict_dir, &["run".to_string()], 10)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+//! Pairs of intransitive and transitive verbs (上がる↔上げる), so a lookup of
+//! one can point to the other.
+
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Transitivity {
+    Intransitive,
+    Transitive,
+}
+
+const VERB_PAIRS: &str = include_str!("verb_pairs.tsv");
+
+lazy_static! {
+    /// Each verb of a pair to its counterparts, with their transitivity
+    static ref COUNTERPARTS: HashMap<&'static str, Vec<(&'static str, Transitivity)>> = {
+        let mut counterparts: HashMap<_, Vec<_>> = HashMap::new();
+        for line in VERB_PAIRS.lines() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let Some((intransitive, transitive)) = line.split_once('\t') else {
+                continue;
+            };
+            let (intransitive, transitive) = (intransitive.trim(), transitive.trim());
+            counterparts
+                .entry(intransitive)
+                .or_default()
+                .push((transitive, Transitivity::Transitive));
+            counterparts
+                .entry(transitive)
+                .or_default()
+                .push((intransitive, Transitivity::Intransitive));
+        }
+        counterparts
+    };
+}
+
+/// The verbs `verb` pairs with, along with their transitivity
+pub fn counterparts(verb: &str) -> &'static [(&'static str, Transitivity)] {
+    COUNTERPARTS.get(verb).map_or(&[], Vec::as_slice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counterparts() {
+        assert_eq!(
+            counterparts("上がる"),
+            [("上げる", Transitivity::Transitive)]
+        );
+        assert_eq!(
+            counterparts("上げる"),
+            [("上がる", Transitivity::Intransitive)]
+        );
+        assert!(counterparts("食べる").is_empty());
+        // Every line of the dataset is a pair
+        for line in VERB_PAIRS.lines().filter(|l| !l.starts_with('#')) {
+            assert_eq!(line.split('\t').count(), 2, "{line}");
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use tracing::debug;
+
+/// Decoded samples of a recording, interleaved by channel
+pub struct DecodedAudio {
+    pub samples: Vec<f32>,
+    pub channels: usize,
+    pub sample_rate: u32,
+}
+
+impl DecodedAudio {
+    /// Number of samples per channel
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    /// The channels mixed down to one
+    pub fn mono(&self) -> Vec<f32> {
+        self.samples
+            .chunks(self.channels)
+            .map(|frame| frame.iter().sum::<f32>() / self.channels as f32)
+            .collect()
+    }
+}
+
+/// Decode an audio file with symphonia, refusing recordings longer than
+/// `max_secs`. Opus isn't among the codecs symphonia decodes.
+pub fn decode(path: &Path, max_secs: u32) -> Result<DecodedAudio> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open audio file: {}", path.display()))?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+    let mut format = symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .context("Unsupported audio format")?
+        .format;
+    let track = format
+        .default_track()
+        .ok_or_else(|| anyhow::anyhow!("No audio track in the recording"))?;
+    let track_id = track.id;
+    let mut sample_rate = track.codec_params.sample_rate;
+    let mut channels = track.codec_params.channels.map(|c| c.count());
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .context("Unsupported audio codec")?;
+
+    let mut samples: Vec<f32> = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(e) => return Err(e).context("Failed to read the recording"),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(e)) => {
+                debug!("Skipping undecodable packet: {}", e);
+                continue;
+            }
+            Err(e) => return Err(e).context("Failed to decode the recording"),
+        };
+        let spec = *decoded.spec();
+        let rate = *sample_rate.get_or_insert(spec.rate);
+        let num_channels = *channels.get_or_insert(spec.channels.count().max(1));
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+        samples.extend_from_slice(buffer.samples());
+        if samples.len() > max_secs as usize * rate as usize * num_channels {
+            bail!("Recordings can be at most {max_secs} seconds long");
+        }
+    }
+
+    let sample_rate = sample_rate.unwrap_or(0);
+    if sample_rate == 0 || samples.is_empty() {
+        bail!("The recording is empty");
+    }
+    Ok(DecodedAudio {
+        samples,
+        channels: channels.unwrap_or(1).max(1),
+        sample_rate,
+    })
+}
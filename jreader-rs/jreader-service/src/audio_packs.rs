@@ -0,0 +1,224 @@
+//! Packs of pronunciation recordings uploaded through `/api/upload-audio-pack`.
+//!
+//! A pack is a zip of audio files with an `index.json` naming the source the
+//! recordings belong to and listing one entry per recording:
+//!
+//! ```json
+//! {
+//!   "source": "my_voice",
+//!   "entries": [
+//!     { "expression": "食べる", "reading": "たべる", "speaker": "me", "file": "taberu.opus" }
+//!   ]
+//! }
+//! ```
+//!
+//! `file` is the recording's path in the zip. The recordings are extracted to
+//! `{source}_files/` in an audio data directory, where the media routes look
+//! for them, and the entries are then added to the audio database.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{Read, Seek};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use audio_db_query::NewAudioEntry;
+use serde::Deserialize;
+use zip::ZipArchive;
+
+pub const INDEX_FILE: &str = "index.json";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AudioPackIndex {
+    pub source: String,
+    pub entries: Vec<AudioPackEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AudioPackEntry {
+    pub expression: String,
+    #[serde(default)]
+    pub reading: Option<String>,
+    #[serde(default)]
+    pub speaker: Option<String>,
+    #[serde(default)]
+    pub display: Option<String>,
+    pub file: String,
+}
+
+impl AudioPackIndex {
+    fn validate(&self) -> Result<()> {
+        // Becomes a directory name and part of the media URLs
+        let valid_source = !self.source.is_empty()
+            && self
+                .source
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid_source {
+            bail!(
+                "Invalid source {:?}: use letters, digits, '_' and '-'",
+                self.source
+            );
+        }
+        if self.entries.is_empty() {
+            bail!("{INDEX_FILE} lists no entries");
+        }
+        for entry in &self.entries {
+            let relative = Path::new(&entry.file)
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)));
+            if entry.file.is_empty() || !relative {
+                bail!("Invalid file {:?} in {INDEX_FILE}", entry.file);
+            }
+        }
+        Ok(())
+    }
+
+    /// The entries to add to the audio database
+    pub fn new_entries(&self) -> Vec<NewAudioEntry> {
+        self.entries
+            .iter()
+            .map(|entry| NewAudioEntry {
+                expression: entry.expression.clone(),
+                reading: entry.reading.clone(),
+                source: self.source.clone(),
+                speaker: entry.speaker.clone(),
+                display: entry.display.clone(),
+                file: entry.file.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Read and check the index of `archive`, without extracting anything
+pub fn read_index<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<AudioPackIndex> {
+    let index_file = archive
+        .by_name(INDEX_FILE)
+        .with_context(|| format!("Audio pack has no {INDEX_FILE}"))?;
+    let index: AudioPackIndex = serde_json::from_reader(index_file)
+        .with_context(|| format!("Failed to parse {INDEX_FILE}"))?;
+    index.validate()?;
+
+    let files: HashSet<PathBuf> = (0..archive.len())
+        .filter_map(|i| archive.by_index(i).ok()?.enclosed_name())
+        .collect();
+    if let Some(missing) = index
+        .entries
+        .iter()
+        .find(|entry| !files.contains(Path::new(&entry.file)))
+    {
+        bail!(
+            "{} is listed in {INDEX_FILE} but not in the pack",
+            missing.file
+        );
+    }
+    Ok(index)
+}
+
+/// Extract the recordings of the pack at `archive_path` into
+/// `audio_dir/{source}_files`, replacing files of the same name. Returns the
+/// index and the number of files extracted.
+pub fn extract(archive_path: &Path, audio_dir: &Path) -> Result<(AudioPackIndex, usize)> {
+    let mut archive = ZipArchive::new(File::open(archive_path)?)
+        .context("Audio pack is not a valid zip archive")?;
+    extract_archive(&mut archive, audio_dir)
+}
+
+fn extract_archive<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    audio_dir: &Path,
+) -> Result<(AudioPackIndex, usize)> {
+    let index = read_index(archive)?;
+    let source_dir = audio_dir.join(format!("{}_files", index.source));
+    fs::create_dir_all(&source_dir)
+        .with_context(|| format!("Failed to create {}", source_dir.display()))?;
+
+    let mut extracted = 0;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() || file.name() == INDEX_FILE {
+            continue;
+        }
+        let Some(name) = file.enclosed_name() else {
+            bail!("Unsafe path in audio pack: {}", file.name());
+        };
+        let target = source_dir.join(name);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = File::create(&target)
+            .with_context(|| format!("Failed to create {}", target.display()))?;
+        std::io::copy(&mut file, &mut out)
+            .with_context(|| format!("Failed to extract {}", file.name()))?;
+        extracted += 1;
+    }
+    Ok((index, extracted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+
+    fn pack(files: &[(&str, &str)]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_extract_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = r#"{
+            "source": "my_voice",
+            "entries": [
+                {"expression": "食べる", "reading": "たべる", "file": "taberu.opus"},
+                {"expression": "飲む", "speaker": "me", "file": "verbs/nomu.opus"}
+            ]
+        }"#;
+        let mut archive = pack(&[
+            (INDEX_FILE, index),
+            ("taberu.opus", "a"),
+            ("verbs/nomu.opus", "bb"),
+        ]);
+        let (index, extracted) = extract_archive(&mut archive, dir.path()).unwrap();
+        assert_eq!(extracted, 2);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("my_voice_files/verbs/nomu.opus")).unwrap(),
+            "bb"
+        );
+        let entries = index.new_entries();
+        assert_eq!(entries[0].source, "my_voice");
+        assert_eq!(entries[0].reading.as_deref(), Some("たべる"));
+        assert_eq!(entries[1].speaker.as_deref(), Some("me"));
+    }
+
+    #[test]
+    fn test_read_index_rejects_bad_packs() {
+        let index = |source: &str, file: &str| {
+            format!(
+                r#"{{"source": "{source}", "entries": [{{"expression": "食べる", "file": "{file}"}}]}}"#
+            )
+        };
+        let error = |files: &[(&str, &str)]| read_index(&mut pack(files)).unwrap_err().to_string();
+
+        assert!(error(&[("taberu.opus", "")]).contains("no index.json"));
+        assert!(
+            error(&[(INDEX_FILE, &index("my voice", "taberu.opus"))]).contains("Invalid source")
+        );
+        assert!(error(&[(INDEX_FILE, &index("../up", "taberu.opus"))]).contains("Invalid source"));
+        assert!(error(&[(INDEX_FILE, &index("mine", "../taberu.opus"))]).contains("Invalid file"));
+        assert!(error(&[(INDEX_FILE, &index("mine", "taberu.opus"))]).contains("not in the pack"));
+        assert!(read_index(&mut pack(&[
+            (INDEX_FILE, &index("mine", "taberu.opus")),
+            ("taberu.opus", "")
+        ]))
+        .is_ok());
+    }
+}
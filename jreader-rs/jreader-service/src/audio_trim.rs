@@ -0,0 +1,226 @@
+//! Audio files with the silence at either end cut off, for tight clips on
+//! cards (`?trim=1` on the audio routes).
+//!
+//! Trimmed copies are decoded and written as WAV into a cache directory that
+//! mirrors the audio directory layout. Files that have nothing to trim or that
+//! can't be decoded (e.g. Opus) get an empty `.untrimmed` marker instead, so
+//! that they are served as they are without decoding them again.
+
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use crate::audio_decode::{self, DecodedAudio};
+
+/// Longer recordings are served untrimmed
+pub const MAX_TRIM_SECS: u32 = 60;
+
+const FRAME_MS: usize = 10;
+// Frames quieter than this fraction of the loudest frame (about -30 dB) are silence
+const SILENCE_RATIO: f32 = 0.03;
+// Kept on either side of the sound so that soft onsets aren't clipped
+const PADDING_MS: usize = 60;
+// Trimming less than this isn't worth re-encoding for
+const MIN_SAVING_MS: usize = 100;
+
+/// Range of frames of `audio` to keep: the sound between the leading and
+/// trailing silence, padded a little. None if the recording is all silence.
+pub fn sound_bounds(audio: &DecodedAudio) -> Option<Range<usize>> {
+    let frame_len = (audio.sample_rate as usize * FRAME_MS / 1000).max(1);
+    let energies: Vec<f32> = audio
+        .samples
+        .chunks(frame_len * audio.channels)
+        .map(|frame| (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt())
+        .collect();
+    let loudest = energies.iter().fold(0f32, |a, b| a.max(*b));
+    if loudest == 0.0 {
+        return None;
+    }
+    let threshold = loudest * SILENCE_RATIO;
+    let first = energies.iter().position(|e| *e > threshold)?;
+    let last = energies.iter().rposition(|e| *e > threshold)?;
+
+    let padding = audio.sample_rate as usize * PADDING_MS / 1000;
+    let start = (first * frame_len).saturating_sub(padding);
+    let end = ((last + 1) * frame_len + padding).min(audio.frames());
+    Some(start..end)
+}
+
+/// Write `frames` of `audio` as a 16-bit PCM WAV file
+pub fn write_wav(out: &mut impl Write, audio: &DecodedAudio, frames: Range<usize>) -> Result<()> {
+    let samples = &audio.samples[frames.start * audio.channels..frames.end * audio.channels];
+    let channels = audio.channels as u16;
+    let data_len = (samples.len() * 2) as u32;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?; // PCM
+    out.write_all(&channels.to_le_bytes())?;
+    out.write_all(&audio.sample_rate.to_le_bytes())?;
+    out.write_all(&(audio.sample_rate * channels as u32 * 2).to_le_bytes())?;
+    out.write_all(&(channels * 2).to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())?;
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+        out.write_all(&sample.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Trimmed copies of audio files
+pub struct TrimmedAudioCache {
+    cache_dir: PathBuf,
+}
+
+impl TrimmedAudioCache {
+    pub fn new(cache_dir: PathBuf) -> Self {
+        Self { cache_dir }
+    }
+
+    /// The cache in AUDIO_TRIM_CACHE_DIR, like the audio routes read
+    /// AUDIO_DATA_DIRS on every request
+    pub fn from_env() -> Self {
+        Self::new(PathBuf::from(
+            std::env::var("AUDIO_TRIM_CACHE_DIR")
+                .unwrap_or_else(|_| "./data/audio-trim-cache".to_string()),
+        ))
+    }
+
+    /// Trimmed copy of the audio file at `source`, whose path relative to its
+    /// audio directory is `rel_path`. None if the file should be served as it
+    /// is. Blocks while decoding, so call it from a blocking task.
+    pub fn trimmed(&self, source: &Path, rel_path: &Path) -> Result<Option<PathBuf>> {
+        let cached = self.cache_dir.join(rel_path);
+        let trimmed = append_extension(&cached, "wav");
+        let untrimmed = append_extension(&cached, "untrimmed");
+        let source_modified = std::fs::metadata(source)
+            .and_then(|m| m.modified())
+            .with_context(|| format!("Failed to read {}", source.display()))?;
+        // A replaced source is newer than what was made from the old one
+        let fresh = |path: &Path| {
+            std::fs::metadata(path)
+                .and_then(|m| m.modified())
+                .is_ok_and(|made| made >= source_modified)
+        };
+        if fresh(&trimmed) {
+            return Ok(Some(trimmed));
+        }
+        if fresh(&untrimmed) {
+            return Ok(None);
+        }
+
+        let parent = cached.parent().unwrap_or(&self.cache_dir);
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+        let bounds = match audio_decode::decode(source, MAX_TRIM_SECS) {
+            Ok(audio) => sound_bounds(&audio)
+                .filter(|bounds| {
+                    let saved = audio.frames() - bounds.len();
+                    saved * 1000 / audio.sample_rate as usize >= MIN_SAVING_MS
+                })
+                .map(|bounds| (audio, bounds)),
+            Err(e) => {
+                debug!("Not trimming {}: {e:#}", source.display());
+                None
+            }
+        };
+        let Some((audio, bounds)) = bounds else {
+            std::fs::File::create(&untrimmed)
+                .with_context(|| format!("Failed to create {}", untrimmed.display()))?;
+            return Ok(None);
+        };
+
+        // Written aside and moved into place so readers never see half a file
+        let mut file = tempfile::NamedTempFile::new_in(parent)?;
+        {
+            let mut out = std::io::BufWriter::new(file.as_file_mut());
+            write_wav(&mut out, &audio, bounds)?;
+            out.flush()?;
+        }
+        file.persist(&trimmed)
+            .with_context(|| format!("Failed to write {}", trimmed.display()))?;
+        Ok(Some(trimmed))
+    }
+}
+
+// `path` with `.extension` added after its existing extension
+fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 8000;
+
+    // Stereo recording of `silence` seconds of silence, `tone` of a tone, and
+    // `silence` of silence again
+    fn padded_tone(silence: f32, tone: f32) -> DecodedAudio {
+        let silent = (silence * RATE as f32) as usize;
+        let sounding = (tone * RATE as f32) as usize;
+        let samples = (0..2 * silent + sounding)
+            .flat_map(|i| {
+                let s = if (silent..silent + sounding).contains(&i) {
+                    0.5 * (i as f32 * 0.3).sin()
+                } else {
+                    0.0
+                };
+                [s, s]
+            })
+            .collect();
+        DecodedAudio {
+            samples,
+            channels: 2,
+            sample_rate: RATE,
+        }
+    }
+
+    #[test]
+    fn test_sound_bounds() {
+        let audio = padded_tone(0.5, 0.3);
+        let bounds = sound_bounds(&audio).unwrap();
+        let padding = RATE as usize * PADDING_MS / 1000;
+        assert_eq!(bounds, 4000 - padding..6400 + padding);
+
+        let silence = padded_tone(0.5, 0.0);
+        assert_eq!(sound_bounds(&silence), None);
+    }
+
+    #[test]
+    fn test_trimmed_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.wav");
+        let audio = padded_tone(0.5, 0.3);
+        let mut file = std::fs::File::create(&source).unwrap();
+        write_wav(&mut file, &audio, 0..audio.frames()).unwrap();
+
+        let cache = TrimmedAudioCache::new(dir.path().join("cache"));
+        let rel_path = Path::new("nhk16_files/a.wav");
+        let trimmed = cache.trimmed(&source, rel_path).unwrap().unwrap();
+        assert_eq!(trimmed, dir.path().join("cache/nhk16_files/a.wav.wav"));
+        let decoded = audio_decode::decode(&trimmed, MAX_TRIM_SECS).unwrap();
+        assert_eq!(decoded.channels, 2);
+        assert_eq!(decoded.frames(), sound_bounds(&audio).unwrap().len());
+        assert_eq!(cache.trimmed(&source, rel_path).unwrap(), Some(trimmed));
+
+        // Already tight, and not audio at all
+        let tight = dir.path().join("tight.wav");
+        let mut file = std::fs::File::create(&tight).unwrap();
+        let audio = padded_tone(0.0, 0.3);
+        write_wav(&mut file, &audio, 0..audio.frames()).unwrap();
+        assert_eq!(cache.trimmed(&tight, Path::new("tight.wav")).unwrap(), None);
+        assert!(dir.path().join("cache/tight.wav.untrimmed").exists());
+        let text = dir.path().join("notes.mp3");
+        std::fs::write(&text, "not audio").unwrap();
+        assert_eq!(cache.trimmed(&text, Path::new("notes.mp3")).unwrap(), None);
+    }
+}
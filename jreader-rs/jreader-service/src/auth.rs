@@ -1,6 +1,6 @@
 use anyhow::Result;
 use axum::body::Body;
-use axum::http::StatusCode;
+use axum::http::{HeaderMap, StatusCode};
 use axum::{extract::Request, response::Response};
 use jsonwebtoken::{DecodingKey, Validation};
 use serde::Deserialize;
@@ -86,6 +86,44 @@ impl AuthService for AuthServiceImpl {
     }
 }
 
+/// The user `headers` authenticate as: the X-Username header, or else the
+/// subject of the bearer token. None without either, Err if the token doesn't
+/// verify.
+pub async fn authenticate<A: AuthService>(
+    auth_service: &A,
+    headers: &HeaderMap,
+) -> Result<Option<String>> {
+    // Accept X-Username header as an alternative to JWT Bearer token
+    // (used by the SQLite-based self-hosted auth system)
+    let username_header = headers
+        .get("X-Username")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    if let Some(username) = username_header {
+        // Username-based auth: use the username directly as user_id
+        return Ok(Some(username));
+    }
+
+    let token = headers
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .map(|t| {
+            trace!("Token before: {:?}", t);
+            let stripped = t.strip_prefix("Bearer ");
+            trace!("Token after: {:?}", stripped);
+            stripped.unwrap_or(t).trim().to_string()
+        });
+    match token {
+        Some(token) => {
+            let user_id = auth_service.verify_token(token).await?;
+            trace!("User ID: {:?}", user_id);
+            Ok(Some(user_id))
+        }
+        None => Ok(None),
+    }
+}
+
 #[derive(Clone)]
 pub struct AuthMiddleware<S, A> {
     inner: S,
@@ -93,10 +131,17 @@ pub struct AuthMiddleware<S, A> {
 }
 
 fn is_admin_route(path: &str) -> bool {
-    matches!(
-        path,
-        "/api/upload-dict" | "/api/print-dicts" | "/api/scan-dicts" | "/api/import-progress/admin"
-    )
+    path.starts_with("/api/admin/")
+        || matches!(
+            path,
+            "/api/upload-dict"
+                | "/api/upload-audio-pack"
+                | "/api/audio/stats"
+                | "/api/audio/reload"
+                | "/api/print-dicts"
+                | "/api/scan-dicts"
+                | "/api/import-progress/admin"
+        )
 }
 
 impl<S, A> Service<Request> for AuthMiddleware<S, A>
@@ -118,53 +163,23 @@ where
         let mut inner = self.inner.clone();
 
         Box::pin(async move {
-            // Accept X-Username header as an alternative to JWT Bearer token
-            // (used by the SQLite-based self-hosted auth system)
-            let username_header = req
-                .headers()
-                .get("X-Username")
-                .and_then(|v| v.to_str().ok())
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty());
-
-            let token = req
-                .headers()
-                .get("Authorization")
-                .and_then(|v| v.to_str().ok())
-                .map(|t| {
-                    trace!("Token before: {:?}", t);
-                    let stripped = t.strip_prefix("Bearer ");
-                    trace!("Token after: {:?}", stripped);
-                    stripped.unwrap_or(t).trim().to_string()
-                });
-
-            let user_id = if let Some(username) = username_header {
-                // Username-based auth: use the username directly as user_id
-                req.headers_mut()
-                    .insert("user_id", username.parse().unwrap());
-                username
-            } else {
-                match token {
-                    Some(token) => match auth_service.verify_token(token).await {
-                        Ok(user_id) => {
-                            trace!("User ID: {:?}", user_id);
-                            req.headers_mut()
-                                .insert("user_id", user_id.parse().unwrap());
-                            user_id
-                        }
-                        Err(_) => {
-                            return Ok(Response::builder()
-                                .status(StatusCode::UNAUTHORIZED)
-                                .body(axum::body::Body::from("Invalid token"))
-                                .unwrap())
-                        }
-                    },
-                    None => {
-                        return Ok(Response::builder()
-                            .status(StatusCode::UNAUTHORIZED)
-                            .body(axum::body::Body::from("No authorization token provided"))
-                            .unwrap())
-                    }
+            let user_id = match authenticate(&auth_service, req.headers()).await {
+                Ok(Some(user_id)) => {
+                    req.headers_mut()
+                        .insert("user_id", user_id.parse().unwrap());
+                    user_id
+                }
+                Ok(None) => {
+                    return Ok(Response::builder()
+                        .status(StatusCode::UNAUTHORIZED)
+                        .body(axum::body::Body::from("No authorization token provided"))
+                        .unwrap())
+                }
+                Err(_) => {
+                    return Ok(Response::builder()
+                        .status(StatusCode::UNAUTHORIZED)
+                        .body(axum::body::Body::from("Invalid token"))
+                        .unwrap())
                 }
             };
 
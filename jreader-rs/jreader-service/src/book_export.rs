@@ -0,0 +1,150 @@
+//! Copies of library books prepared for reading offline on e-readers (Kindle,
+//! Kobo), e.g. with furigana on the words the reader is unlikely to know or
+//! with the words they have studied in bold.
+
+use anyhow::Result;
+use std::collections::HashSet;
+use vibrato::tokenizer::worker::Worker;
+
+use crate::epub::{self, escape_xml, EpubBuilder, EpubSource};
+use crate::furigana::{self, FuriganaSegment};
+use crate::mecab::TokenFeature;
+
+/// Words ranked at most this in the frequency dictionaries are common enough
+/// to go without furigana
+pub const DEFAULT_FREQUENCY_THRESHOLD: u32 = 5000;
+
+/// Ruby markup for a word, with parentheses for reading systems without ruby
+/// support
+pub fn ruby_markup(segments: &[FuriganaSegment]) -> String {
+    let mut markup = String::new();
+    for segment in segments {
+        match &segment.reading {
+            Some(reading) => markup.push_str(&format!(
+                "<ruby>{}<rp>(</rp><rt>{}</rt><rp>)</rp></ruby>",
+                escape_xml(&segment.text),
+                escape_xml(reading)
+            )),
+            None => markup.push_str(&escape_xml(&segment.text)),
+        }
+    }
+    markup
+}
+
+/// How a word is marked up in an exported book
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WordMarkup {
+    /// Show the reading above the kanji of the word
+    pub furigana: bool,
+    /// Set the word in bold as one the reader has studied
+    pub highlight: bool,
+}
+
+/// Class of the bold elements wrapped around highlighted words, for styling
+/// them from a user stylesheet
+pub const HIGHLIGHT_CLASS: &str = "jreader-vocab";
+
+/// Markup for `text` with each word marked up the way `mark` picks, or None
+/// if no word got any
+pub fn mark_up_text(
+    worker: &mut Worker,
+    text: &str,
+    mark: &mut impl FnMut(&TokenFeature) -> WordMarkup,
+) -> Option<String> {
+    worker.reset_sentence(text);
+    worker.tokenize();
+    let mut markup = String::with_capacity(text.len() * 2);
+    let mut marked = false;
+    let mut last = 0;
+    for token in worker.token_iter() {
+        let range = token.range_byte();
+        markup.push_str(&escape_xml(&text[last..range.start]));
+        last = range.end;
+
+        let surface = token.surface();
+        let feature = TokenFeature::from_feature_string(surface, token.feature());
+        let word = mark(&feature);
+        let mut word_markup = match &feature.reading {
+            Some(reading) if word.furigana && furigana::needs_furigana(surface) => {
+                marked = true;
+                ruby_markup(&furigana::furigana(surface, reading))
+            }
+            _ => escape_xml(surface),
+        };
+        if word.highlight {
+            word_markup = format!("<b class=\"{HIGHLIGHT_CLASS}\">{word_markup}</b>");
+            marked = true;
+        }
+        markup.push_str(&word_markup);
+    }
+    markup.push_str(&escape_xml(&text[last..]));
+    marked.then_some(markup)
+}
+
+/// A copy of `source` with the words of its documents marked up the way
+/// `mark` picks
+pub fn mark_up_epub(
+    source: &EpubSource,
+    worker: &mut Worker,
+    mut mark: impl FnMut(&TokenFeature) -> WordMarkup,
+) -> Result<EpubBuilder> {
+    epub::repackage(source, |item, data| {
+        if !item.is_document() {
+            return Ok(data);
+        }
+        let Ok(html) = String::from_utf8(data) else {
+            anyhow::bail!("Document {} is not UTF-8", item.href);
+        };
+        Ok(epub::rewrite_text(&html, |text| mark_up_text(worker, text, &mut mark)).into_bytes())
+    })
+}
+
+/// Whether a word is one of the `terms` the user has saved, by its dictionary
+/// form or as written
+pub fn is_studied(feature: &TokenFeature, terms: &HashSet<String>) -> bool {
+    [&feature.dictionary_form, &feature.surface_form]
+        .into_iter()
+        .flatten()
+        .any(|form| terms.contains(form))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_text_with_ruby() {
+        let html = "<html><head><title>食べ物</title></head><body>\
+                    <p class=\"x\">食べ物</p><p>A &amp; B</p>\
+                    <ruby>食べ物<rt>たべもの</rt></ruby><br/></body></html>";
+        let mut seen = Vec::new();
+        let rewritten = epub::rewrite_text(html, |text| {
+            seen.push(text.to_string());
+            (text == "食べ物").then(|| ruby_markup(&furigana::furigana(text, "タベモノ")))
+        });
+        // Existing ruby and the head are left alone, entities are unescaped
+        assert_eq!(seen, ["食べ物", "A & B"]);
+        assert_eq!(
+            rewritten,
+            "<html><head><title>食べ物</title></head><body>\
+             <p class=\"x\"><ruby>食<rp>(</rp><rt>た</rt><rp>)</rp></ruby>べ\
+             <ruby>物<rp>(</rp><rt>もの</rt><rp>)</rp></ruby></p><p>A &amp; B</p>\
+             <ruby>食べ物<rt>たべもの</rt></ruby><br/></body></html>"
+        );
+    }
+
+    #[test]
+    fn test_is_studied() {
+        let terms: HashSet<String> = ["食べる", "今日"].map(String::from).into();
+        let conjugated = TokenFeature::from_feature_string(
+            "食べた",
+            "動詞,自立,*,*,一段,連用タ接続,食べる,タベ,タベ",
+        );
+        assert!(is_studied(&conjugated, &terms));
+        let unknown = TokenFeature::from_feature_string("今日", "名詞,副詞可能,*,*,*,*,*,*,*");
+        assert!(is_studied(&unknown, &terms));
+        let other =
+            TokenFeature::from_feature_string("明日", "名詞,副詞可能,*,*,*,*,明日,アシタ,アシタ");
+        assert!(!is_studied(&other, &terms));
+    }
+}
@@ -0,0 +1,182 @@
+//! Single files (images, stylesheets, chapters) of a user's books, for the
+//! reader to fetch as chapters reference them.
+//!
+//! A book is either extracted into `UPLOADS_DIR/{user_id}/{upload_id}/` or
+//! stored whole as `UPLOADS_DIR/{user_id}/{upload_id}.epub`. Files of stored
+//! EPUBs are extracted one at a time when first asked for, into a cache
+//! directory that mirrors the extracted layout.
+
+use anyhow::{Context, Result};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use crate::library::user_library_dir;
+
+/// Larger entries are refused rather than extracted, which also guards
+/// against zip bombs
+pub const MAX_RESOURCE_BYTES: u64 = 100 * 1024 * 1024;
+
+/// `path` as a relative path within a book, or None if it could point outside
+/// of it
+pub fn resource_path(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path.trim_start_matches('/'));
+    let safe = path.components().all(|c| matches!(c, Component::Normal(_)));
+    (safe && path.components().next().is_some()).then(|| path.to_path_buf())
+}
+
+/// Stored EPUB of a book
+pub fn stored_epub_path(library_dir: &Path, upload_id: &str) -> Result<PathBuf> {
+    // Upload ids are directory names, so the same validation applies
+    Ok(user_library_dir(library_dir, upload_id)?.with_extension("epub"))
+}
+
+/// Extracted copies of the files of stored EPUBs
+pub struct BookResourceCache {
+    cache_dir: PathBuf,
+}
+
+impl BookResourceCache {
+    pub fn new(cache_dir: PathBuf) -> Self {
+        Self { cache_dir }
+    }
+
+    /// File on disk for `rel_path` of a book of the library in `library_dir`,
+    /// extracting it from the stored EPUB if the book isn't extracted. None if
+    /// the book or the file doesn't exist.
+    pub fn resolve(
+        &self,
+        library_dir: &Path,
+        user_id: &str,
+        upload_id: &str,
+        rel_path: &Path,
+    ) -> Result<Option<PathBuf>> {
+        let book_dir = user_library_dir(library_dir, upload_id)?;
+        if book_dir.is_dir() {
+            let path = book_dir.join(rel_path);
+            return Ok(path.is_file().then_some(path));
+        }
+
+        let epub_path = stored_epub_path(library_dir, upload_id)?;
+        let Ok(epub_modified) = std::fs::metadata(&epub_path).and_then(|m| m.modified()) else {
+            return Ok(None);
+        };
+        let cached = user_library_dir(&user_library_dir(&self.cache_dir, user_id)?, upload_id)?
+            .join(rel_path);
+        // A replaced EPUB is newer than what was extracted from the old one
+        let fresh = std::fs::metadata(&cached)
+            .and_then(|m| m.modified())
+            .is_ok_and(|extracted| extracted >= epub_modified);
+        if fresh {
+            return Ok(Some(cached));
+        }
+
+        let file = std::fs::File::open(&epub_path)
+            .with_context(|| format!("Failed to open {}", epub_path.display()))?;
+        let mut archive = zip::ZipArchive::new(file)
+            .with_context(|| format!("Invalid EPUB: {}", epub_path.display()))?;
+        let name = rel_path.to_string_lossy().replace('\\', "/");
+        let mut entry = match archive.by_name(&name) {
+            Ok(entry) if entry.is_file() => entry,
+            Ok(_) | Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if entry.size() > MAX_RESOURCE_BYTES {
+            anyhow::bail!("{name} is too large to extract ({} bytes)", entry.size());
+        }
+
+        let parent = cached.parent().context("Cache path has no parent")?;
+        std::fs::create_dir_all(parent)?;
+        // Extract next to the cached file and move it into place, so that
+        // concurrent requests never see a partial file
+        let mut temp = tempfile::NamedTempFile::new_in(parent)?;
+        let copied = std::io::copy(
+            &mut (&mut entry).take(MAX_RESOURCE_BYTES + 1),
+            temp.as_file_mut(),
+        )?;
+        if copied > MAX_RESOURCE_BYTES {
+            anyhow::bail!("{name} is too large to extract");
+        }
+        temp.persist(&cached)?;
+        Ok(Some(cached))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_resource_path() {
+        assert_eq!(
+            resource_path("/OEBPS/images/cover.jpg"),
+            Some(PathBuf::from("OEBPS/images/cover.jpg"))
+        );
+        assert_eq!(resource_path("OEBPS/../../other-user/book"), None);
+        assert_eq!(resource_path("./OEBPS/cover.jpg"), None);
+        assert_eq!(resource_path(""), None);
+    }
+
+    #[test]
+    fn test_extracts_from_stored_epub() {
+        let temp_dir = TempDir::new().unwrap();
+        let library_dir = temp_dir.path().join("library");
+        std::fs::create_dir_all(&library_dir).unwrap();
+        let epub_path = library_dir.join("book-1.epub");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&epub_path).unwrap());
+        zip.start_file(
+            "OEBPS/images/cover.jpg",
+            zip::write::SimpleFileOptions::default(),
+        )
+        .unwrap();
+        zip.write_all(b"jpeg data").unwrap();
+        zip.finish().unwrap();
+
+        let cache = BookResourceCache::new(temp_dir.path().join("cache"));
+        let cover = Path::new("OEBPS/images/cover.jpg");
+        let path = cache
+            .resolve(&library_dir, "alice", "book-1", cover)
+            .unwrap()
+            .unwrap();
+        assert!(path.starts_with(temp_dir.path().join("cache/alice/book-1")));
+        assert_eq!(std::fs::read(&path).unwrap(), b"jpeg data");
+        // Served from the cache from now on
+        assert_eq!(
+            cache
+                .resolve(&library_dir, "alice", "book-1", cover)
+                .unwrap(),
+            Some(path)
+        );
+
+        let missing = Path::new("OEBPS/missing.png");
+        assert_eq!(
+            cache
+                .resolve(&library_dir, "alice", "book-1", missing)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            cache
+                .resolve(&library_dir, "alice", "book-2", cover)
+                .unwrap(),
+            None
+        );
+
+        // Extracted books are served as they are
+        let book_dir = library_dir.join("book-3/OEBPS");
+        std::fs::create_dir_all(&book_dir).unwrap();
+        std::fs::write(book_dir.join("style.css"), "p {}").unwrap();
+        assert_eq!(
+            cache
+                .resolve(
+                    &library_dir,
+                    "alice",
+                    "book-3",
+                    Path::new("OEBPS/style.css")
+                )
+                .unwrap(),
+            Some(book_dir.join("style.css"))
+        );
+    }
+}
@@ -0,0 +1,406 @@
+//! Service settings that can be changed at runtime.
+//!
+//! Values are read from the environment (and `.env`) at startup and again on
+//! `POST /api/admin/config/reload`. Readers take a cheap snapshot with
+//! [`ConfigHandle::current`]; long-running tasks that need to react to a change
+//! subscribe to the watch channel instead.
+
+use anyhow::{anyhow, bail, Result};
+use arc_swap::ArcSwap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{info, warn};
+use tracing_subscriber::{filter::LevelFilter, reload, EnvFilter, Registry};
+
+use crate::ebook_convert::EbookConverterKind;
+
+pub const DEFAULT_LOG_FILTER: &str = "jreader_service_server=debug,jreader_service=debug,jreader_service::http_handlers=debug,yomitan_format=debug,info";
+
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceConfig {
+    /// `RUST_LOG`
+    pub log_filter: String,
+    /// `WEBNOVEL_TIMEOUT_SECONDS`: how long a syosetu2epub run may take
+    pub webnovel_timeout_seconds: u64,
+    /// `DICT_USAGE_FLUSH_SECONDS`
+    pub dict_usage_flush_seconds: u64,
+    /// `AUDIO_BATCH_MAX_ITEMS`: limit on terms per `/api/audio/batch` request
+    pub audio_batch_max_items: usize,
+    /// `EBOOK_CONVERTER`: how MOBI and AZW3 uploads are converted to EPUB
+    pub ebook_converter: EbookConverterKind,
+    /// `EBOOK_CONVERT_TIMEOUT_SECONDS`: how long an `ebook-convert` run may take
+    pub ebook_convert_timeout_seconds: u64,
+    /// `TELEMETRY_ENABLED`: count anonymous usage for the admin, off by default
+    pub telemetry_enabled: bool,
+    /// `DICT_UPLOAD_MAX_MB`: largest dictionary archive `/api/upload-dict` accepts
+    pub dict_upload_max_mb: u64,
+    /// `AUDIO_PACK_UPLOAD_MAX_MB`: largest audio pack `/api/upload-audio-pack` accepts
+    pub audio_pack_upload_max_mb: u64,
+    /// `DICT_IDLE_CLOSE_SECONDS`: how long a dictionary bank database stays
+    /// open without lookups, 0 to keep them open
+    pub dict_idle_close_seconds: u64,
+    /// `GUEST_ACCESS`: whether the public routes answer requests without a login
+    pub guest_access: bool,
+    /// `GUEST_REQUESTS_PER_MINUTE`: limit on public route requests per guest
+    /// address, 0 for no limit
+    pub guest_requests_per_minute: u32,
+    /// `GUEST_AUDIO`: whether guests may use the audio endpoints
+    pub guest_audio: bool,
+}
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    match std::env::var(key) {
+        Ok(value) => value.trim().parse().unwrap_or_else(|_| {
+            warn!(key, value, "⚠️ Invalid config value, using default");
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+impl ServiceConfig {
+    pub fn from_env() -> Self {
+        Self {
+            log_filter: std::env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_LOG_FILTER.into()),
+            webnovel_timeout_seconds: env_or("WEBNOVEL_TIMEOUT_SECONDS", 1800),
+            dict_usage_flush_seconds: env_or("DICT_USAGE_FLUSH_SECONDS", 300).max(1),
+            audio_batch_max_items: env_or("AUDIO_BATCH_MAX_ITEMS", 500).max(1),
+            ebook_converter: env_or("EBOOK_CONVERTER", EbookConverterKind::Native),
+            ebook_convert_timeout_seconds: env_or("EBOOK_CONVERT_TIMEOUT_SECONDS", 300).max(1),
+            telemetry_enabled: env_or("TELEMETRY_ENABLED", false),
+            dict_upload_max_mb: env_or("DICT_UPLOAD_MAX_MB", 500).max(1),
+            audio_pack_upload_max_mb: env_or("AUDIO_PACK_UPLOAD_MAX_MB", 2000).max(1),
+            dict_idle_close_seconds: env_or("DICT_IDLE_CLOSE_SECONDS", 600),
+            guest_access: env_or("GUEST_ACCESS", true),
+            guest_requests_per_minute: env_or("GUEST_REQUESTS_PER_MINUTE", 120),
+            guest_audio: env_or("GUEST_AUDIO", false),
+        }
+    }
+
+    pub fn dict_upload_max_bytes(&self) -> u64 {
+        self.dict_upload_max_mb * 1024 * 1024
+    }
+
+    pub fn audio_pack_upload_max_bytes(&self) -> u64 {
+        self.audio_pack_upload_max_mb * 1024 * 1024
+    }
+
+    pub fn dict_usage_flush_interval(&self) -> Duration {
+        Duration::from_secs(self.dict_usage_flush_seconds)
+    }
+
+    pub fn dict_idle_close_after(&self) -> Option<Duration> {
+        (self.dict_idle_close_seconds > 0)
+            .then(|| Duration::from_secs(self.dict_idle_close_seconds))
+    }
+
+    /// Names of the settings that differ from `other`
+    pub fn changed_keys(&self, other: &ServiceConfig) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.log_filter != other.log_filter {
+            changed.push("logFilter");
+        }
+        if self.webnovel_timeout_seconds != other.webnovel_timeout_seconds {
+            changed.push("webnovelTimeoutSeconds");
+        }
+        if self.dict_usage_flush_seconds != other.dict_usage_flush_seconds {
+            changed.push("dictUsageFlushSeconds");
+        }
+        if self.audio_batch_max_items != other.audio_batch_max_items {
+            changed.push("audioBatchMaxItems");
+        }
+        if self.ebook_converter != other.ebook_converter {
+            changed.push("ebookConverter");
+        }
+        if self.ebook_convert_timeout_seconds != other.ebook_convert_timeout_seconds {
+            changed.push("ebookConvertTimeoutSeconds");
+        }
+        if self.telemetry_enabled != other.telemetry_enabled {
+            changed.push("telemetryEnabled");
+        }
+        if self.dict_upload_max_mb != other.dict_upload_max_mb {
+            changed.push("dictUploadMaxMb");
+        }
+        if self.audio_pack_upload_max_mb != other.audio_pack_upload_max_mb {
+            changed.push("audioPackUploadMaxMb");
+        }
+        if self.dict_idle_close_seconds != other.dict_idle_close_seconds {
+            changed.push("dictIdleCloseSeconds");
+        }
+        if self.guest_access != other.guest_access {
+            changed.push("guestAccess");
+        }
+        if self.guest_requests_per_minute != other.guest_requests_per_minute {
+            changed.push("guestRequestsPerMinute");
+        }
+        if self.guest_audio != other.guest_audio {
+            changed.push("guestAudio");
+        }
+        changed
+    }
+}
+
+pub struct ConfigHandle {
+    current: ArcSwap<ServiceConfig>,
+    sender: watch::Sender<Arc<ServiceConfig>>,
+}
+
+impl ConfigHandle {
+    pub fn new(config: ServiceConfig) -> Self {
+        let config = Arc::new(config);
+        let (sender, _) = watch::channel(config.clone());
+        Self {
+            current: ArcSwap::new(config),
+            sender,
+        }
+    }
+
+    pub fn current(&self) -> Arc<ServiceConfig> {
+        self.current.load_full()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Arc<ServiceConfig>> {
+        self.sender.subscribe()
+    }
+
+    /// Replace the config, notifying subscribers if anything changed.
+    /// Returns the names of the changed settings.
+    pub fn set(&self, config: ServiceConfig) -> Vec<&'static str> {
+        let changed = config.changed_keys(&self.current());
+        if !changed.is_empty() {
+            let config = Arc::new(config);
+            self.current.store(config.clone());
+            self.sender.send_replace(config);
+        }
+        changed
+    }
+
+    /// Re-read `.env` and the environment
+    pub fn reload(&self) -> Result<Vec<&'static str>> {
+        match dotenvy::dotenv_override() {
+            Ok(_) => {}
+            // Running without a .env file is fine, the environment still applies
+            Err(e) if e.not_found() => {}
+            Err(e) => return Err(e.into()),
+        }
+        let changed = self.set(ServiceConfig::from_env());
+        info!(?changed, "🔄 Reloaded service config");
+        Ok(changed)
+    }
+}
+
+/// Runtime control of the tracing filter. The `logFilter` setting is the base
+/// filter; `/api/admin/log-level` layers per-module overrides on top of it,
+/// which survive config reloads until they are cleared.
+pub struct LogLevels {
+    handle: LogFilterHandle,
+    state: Mutex<LogLevelsState>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLevelsState {
+    pub base: String,
+    /// Module path -> level
+    pub overrides: BTreeMap<String, String>,
+}
+
+impl LogLevelsState {
+    /// The filter actually installed: the base filter without any directives
+    /// for overridden modules, followed by the overrides
+    pub fn effective_filter(&self) -> String {
+        let base = self.base.split(',').map(str::trim).filter(|directive| {
+            let target = directive.split(['=', '[']).next().unwrap_or_default();
+            !directive.is_empty() && !self.overrides.contains_key(target)
+        });
+        let overrides = self
+            .overrides
+            .iter()
+            .map(|(module, level)| format!("{module}={level}"));
+        base.map(str::to_string)
+            .chain(overrides)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Validate a module path and level, normalizing the level to lowercase
+pub fn parse_module_level(module: &str, level: &str) -> Result<(String, String)> {
+    let module = module.trim();
+    if module.is_empty()
+        || !module
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+    {
+        bail!("Invalid module path: {module:?}");
+    }
+    let level: LevelFilter = level
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid log level: {level:?}"))?;
+    Ok((module.to_string(), level.to_string().to_lowercase()))
+}
+
+impl LogLevels {
+    pub fn new(handle: LogFilterHandle, base: String) -> Self {
+        Self {
+            handle,
+            state: Mutex::new(LogLevelsState {
+                base,
+                overrides: BTreeMap::new(),
+            }),
+        }
+    }
+
+    pub fn state(&self) -> LogLevelsState {
+        self.state.lock().unwrap().clone()
+    }
+
+    fn apply(&self, update: impl FnOnce(&mut LogLevelsState)) -> Result<LogLevelsState> {
+        let mut state = self.state.lock().unwrap();
+        let mut new_state = state.clone();
+        update(&mut new_state);
+        let filter = new_state.effective_filter();
+        let env_filter = EnvFilter::try_new(&filter)
+            .map_err(|e| anyhow!("Invalid log filter {filter:?}: {e}"))?;
+        self.handle.reload(env_filter)?;
+        info!(filter = %filter, "🔄 Log filter updated");
+        *state = new_state;
+        Ok(state.clone())
+    }
+
+    pub fn set_base(&self, base: String) -> Result<LogLevelsState> {
+        self.apply(|state| state.base = base)
+    }
+
+    /// Set the level of one module, or remove its override with `None`
+    pub fn set_module_level(&self, module: &str, level: Option<&str>) -> Result<LogLevelsState> {
+        match level {
+            Some(level) => {
+                let (module, level) = parse_module_level(module, level)?;
+                self.apply(|state| {
+                    state.overrides.insert(module, level);
+                })
+            }
+            None => self.apply(|state| {
+                state.overrides.remove(module.trim());
+            }),
+        }
+    }
+
+    pub fn clear_overrides(&self) -> Result<LogLevelsState> {
+        self.apply(|state| state.overrides.clear())
+    }
+}
+
+/// Apply `logFilter` changes to the global tracing filter
+pub fn spawn_log_filter_watcher(
+    log_levels: Arc<LogLevels>,
+    mut receiver: watch::Receiver<Arc<ServiceConfig>>,
+) {
+    tokio::spawn(async move {
+        while receiver.changed().await.is_ok() {
+            let new_filter = receiver.borrow_and_update().log_filter.clone();
+            if new_filter == log_levels.state().base {
+                continue;
+            }
+            if let Err(e) = log_levels.set_base(new_filter) {
+                warn!("⚠️ Failed to update log filter: {e}");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ServiceConfig {
+        ServiceConfig {
+            log_filter: "info".to_string(),
+            webnovel_timeout_seconds: 1800,
+            dict_usage_flush_seconds: 300,
+            audio_batch_max_items: 500,
+            ebook_converter: EbookConverterKind::Native,
+            ebook_convert_timeout_seconds: 300,
+            telemetry_enabled: false,
+            dict_upload_max_mb: 500,
+            audio_pack_upload_max_mb: 2000,
+            dict_idle_close_seconds: 600,
+            guest_access: true,
+            guest_requests_per_minute: 120,
+            guest_audio: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_notifies_only_on_change() {
+        let handle = ConfigHandle::new(config());
+        let mut receiver = handle.subscribe();
+
+        assert!(handle.set(config()).is_empty());
+        assert!(!receiver.has_changed().unwrap());
+
+        let changed = handle.set(ServiceConfig {
+            dict_usage_flush_seconds: 60,
+            log_filter: "debug".to_string(),
+            ..config()
+        });
+        assert_eq!(changed, vec!["logFilter", "dictUsageFlushSeconds"]);
+        assert!(receiver.has_changed().unwrap());
+        assert_eq!(receiver.borrow_and_update().dict_usage_flush_seconds, 60);
+        assert_eq!(handle.current().log_filter, "debug");
+    }
+
+    #[test]
+    fn test_effective_filter_with_overrides() {
+        let mut state = LogLevelsState {
+            base: "yomitan_format=info,jreader_service=debug,warn".to_string(),
+            overrides: BTreeMap::new(),
+        };
+        assert_eq!(state.effective_filter(), state.base);
+
+        state
+            .overrides
+            .insert("yomitan_format".to_string(), "trace".to_string());
+        state
+            .overrides
+            .insert("audio_db_query".to_string(), "debug".to_string());
+        assert_eq!(
+            state.effective_filter(),
+            "jreader_service=debug,warn,audio_db_query=debug,yomitan_format=trace"
+        );
+
+        assert_eq!(
+            parse_module_level("yomitan_format::parser", "DEBUG").unwrap(),
+            ("yomitan_format::parser".to_string(), "debug".to_string())
+        );
+        assert!(parse_module_level("yomitan_format", "loud").is_err());
+        assert!(parse_module_level("a=b,c", "info").is_err());
+    }
+
+    #[test]
+    fn test_log_levels_reload() {
+        let (_layer, handle): (reload::Layer<EnvFilter, Registry>, _) =
+            reload::Layer::new(EnvFilter::new("info"));
+        let log_levels = LogLevels::new(handle, "info".to_string());
+
+        let state = log_levels
+            .set_module_level("yomitan_format", Some("debug"))
+            .unwrap();
+        assert_eq!(state.effective_filter(), "info,yomitan_format=debug");
+
+        // Reloading the config replaces the base but keeps overrides
+        let state = log_levels.set_base("warn".to_string()).unwrap();
+        assert_eq!(state.effective_filter(), "warn,yomitan_format=debug");
+
+        let state = log_levels.set_module_level("yomitan_format", None).unwrap();
+        assert!(state.overrides.is_empty());
+    }
+}
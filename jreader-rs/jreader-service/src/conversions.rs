@@ -1,4 +1,5 @@
-use crate::{dictionaries, http_handlers};
+use crate::deinflect::Inflection;
+use crate::{dictionaries, http_handlers, pitch};
 use std::collections::HashMap;
 use wana_kana::ConvertJapanese;
 use yomitan_format::json_schema::term_bank_v3;
@@ -17,6 +18,9 @@ pub fn convert_term_entry(entry: &term_bank_v3::TermEntry) -> http_handlers::Ter
             .collect(),
         sequence_number: entry.sequence_number,
         term_tags: entry.tags.clone().unwrap_or_default(),
+        heuristic: false,
+        matched_form: None,
+        inflections: Vec::new(),
     }
 }
 
@@ -37,7 +41,11 @@ pub fn convert_definition(definition: &term_bank_v3::Definition) -> http_handler
         },
         term_bank_v3::Definition::Deinflection(d) => http_handlers::Definition::Deinflection {
             base_form: d.base_form.clone(),
-            inflections: d.inflections.clone(),
+            inflections: d
+                .inflections
+                .iter()
+                .map(|name| Inflection::from_name(name))
+                .collect(),
         },
     }
 }
@@ -49,7 +57,21 @@ pub fn convert_dictionary_result(
         title: result.title.clone(),
         revision: result.revision.clone(),
         origin: result.origin.clone(),
-        entries: result.entries.iter().map(convert_term_entry).collect(),
+        entries: result
+            .entries
+            .iter()
+            .map(convert_term_entry)
+            .chain(
+                result
+                    .heuristic_entries
+                    .iter()
+                    .map(|entry| http_handlers::TermEntry {
+                        heuristic: true,
+                        ..convert_term_entry(entry)
+                    }),
+            )
+            .collect(),
+        collapsed: false,
     }
 }
 
@@ -76,6 +98,7 @@ pub fn convert_single_frequency_data(
         reading: f.reading.clone().map(|r| r.to_hiragana()),
         value: f.value,
         display_value: f.display_value.clone(),
+        band: None,
     }
 }
 
@@ -107,9 +130,14 @@ pub fn convert_pitch_result(
 }
 
 pub fn convert_pitch_accent(pa: &dictionaries::PitchAccent) -> http_handlers::PitchAccentEntry {
+    let reading = pa.reading.clone().to_hiragana();
     http_handlers::PitchAccentEntry {
-        reading: pa.reading.clone().to_hiragana(),
+        morae: pitch::split_morae(&reading),
+        pattern: pitch::pitch_pattern(pa.position as usize, pa.mora_count as usize),
+        reading,
         position: pa.position as u32,
         mora_count: pa.mora_count as u32,
+        nasal: pa.nasal.clone(),
+        devoiced: pa.devoice.clone(),
     }
 }
@@ -0,0 +1,508 @@
+//! Build Yomitan frequency and pitch dictionaries from raw CSV/TSV data, for
+//! sources that don't ship as Yomitan archives.
+//!
+//! The generated archive (a synthesized `index.json` plus meta banks) is
+//! written to `DICTS_PATH/yomitan` and imported like any uploaded dictionary.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+
+/// Entries per generated bank file, in line with what Yomitan dictionaries use
+const BANK_SIZE: usize = 10_000;
+
+/// A column given by header name or by 0-based index
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum ColumnRef {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvFormat {
+    /// Field delimiter, `,` by default. Use `\t` for TSV.
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    #[serde(default = "default_has_header")]
+    pub has_header: bool,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_has_header() -> bool {
+    true
+}
+
+impl Default for CsvFormat {
+    fn default() -> Self {
+        Self {
+            delimiter: default_delimiter(),
+            has_header: default_has_header(),
+        }
+    }
+}
+
+/// Metadata used to synthesize the dictionary's `index.json`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DictionaryMetadata {
+    pub title: String,
+    /// Defaults to `csv-{date}`
+    pub revision: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub attribution: Option<String>,
+    pub url: Option<String>,
+}
+
+impl DictionaryMetadata {
+    pub fn validate(&self) -> Result<()> {
+        if self.title.trim().is_empty() {
+            bail!("Dictionary title is required");
+        }
+        Ok(())
+    }
+
+    pub fn revision(&self) -> String {
+        self.revision
+            .clone()
+            .unwrap_or_else(|| format!("csv-{}", chrono::Utc::now().format("%Y-%m-%d")))
+    }
+
+    /// File name of the generated archive in `DICTS_PATH/yomitan`
+    pub fn archive_filename(&self) -> String {
+        format!("{}.zip", sanitize_filename::sanitize(self.title.trim()))
+    }
+
+    fn index_json(&self, extra: serde_json::Value) -> serde_json::Value {
+        let mut index = serde_json::json!({
+            "title": self.title.trim(),
+            "revision": self.revision(),
+            "format": 3,
+            "sequenced": false,
+            "sourceLanguage": "ja",
+        });
+        let optional = [
+            ("author", &self.author),
+            ("description", &self.description),
+            ("attribution", &self.attribution),
+            ("url", &self.url),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                index[key] = value.clone().into();
+            }
+        }
+        if let (Some(index), serde_json::Value::Object(extra)) = (index.as_object_mut(), extra) {
+            index.extend(extra);
+        }
+        index
+    }
+}
+
+/// Rows of a CSV file with columns resolved by name or index
+pub struct CsvTable {
+    headers: Option<csv::StringRecord>,
+    rows: Vec<csv::StringRecord>,
+}
+
+impl CsvTable {
+    pub fn parse(data: &[u8], format: &CsvFormat) -> Result<Self> {
+        if !format.delimiter.is_ascii() {
+            bail!("Delimiter must be an ASCII character");
+        }
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(format.delimiter as u8)
+            .has_headers(format.has_header)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(data);
+        let headers = if format.has_header {
+            Some(
+                reader
+                    .headers()
+                    .context("Failed to read CSV header")?
+                    .clone(),
+            )
+        } else {
+            None
+        };
+        let rows = reader
+            .records()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("Failed to parse CSV")?;
+        Ok(Self { headers, rows })
+    }
+
+    pub fn column_index(&self, column: &ColumnRef) -> Result<usize> {
+        match column {
+            ColumnRef::Index(index) => Ok(*index),
+            ColumnRef::Name(name) => self
+                .headers
+                .as_ref()
+                .ok_or_else(|| anyhow!("Column {name:?} given by name but the file has no header"))?
+                .iter()
+                .position(|h| h.trim_start_matches('\u{feff}') == name)
+                .ok_or_else(|| anyhow!("Column {name:?} not found in header")),
+        }
+    }
+
+    pub fn rows(&self) -> &[csv::StringRecord] {
+        &self.rows
+    }
+}
+
+/// Non-empty field of a row
+pub fn field(row: &csv::StringRecord, index: usize) -> Option<&str> {
+    row.get(index).filter(|f| !f.is_empty())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FrequencyValueKind {
+    /// Lower is more frequent
+    Rank,
+    /// Higher is more frequent, e.g. corpus counts
+    Occurrence,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrequencyColumns {
+    pub term: ColumnRef,
+    pub reading: Option<ColumnRef>,
+    pub value: ColumnRef,
+}
+
+/// How to turn a CSV file into a frequency dictionary
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrequencyImportSpec {
+    #[serde(flatten)]
+    pub metadata: DictionaryMetadata,
+    #[serde(flatten)]
+    pub format: CsvFormat,
+    pub columns: FrequencyColumns,
+    pub value_kind: FrequencyValueKind,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvImportReport {
+    pub entries: usize,
+    /// Rows without a term or with an unusable value
+    pub skipped_rows: usize,
+    /// 1-based line numbers of the first few skipped rows
+    pub skipped_lines: Vec<u64>,
+}
+
+impl CsvImportReport {
+    fn skip(&mut self, row: &csv::StringRecord) {
+        self.skipped_rows += 1;
+        if self.skipped_lines.len() < 20 {
+            if let Some(position) = row.position() {
+                self.skipped_lines.push(position.line());
+            }
+        }
+    }
+}
+
+/// A Yomitan archive generated from CSV data
+pub struct GeneratedDictionary {
+    pub archive: Vec<u8>,
+    pub report: CsvImportReport,
+}
+
+fn frequency_value(raw: &str) -> Option<serde_json::Value> {
+    let raw = raw.replace(',', "");
+    if let Ok(value) = raw.parse::<i32>() {
+        return (value >= 0).then(|| value.into());
+    }
+    // Yomitan only takes integers as plain values, so keep the original
+    // text for display
+    let value: f64 = raw
+        .parse()
+        .ok()
+        .filter(|v: &f64| v.is_finite() && *v >= 0.0)?;
+    Some(serde_json::json!({ "value": value, "displayValue": raw }))
+}
+
+pub fn build_frequency_dictionary(
+    data: &[u8],
+    spec: &FrequencyImportSpec,
+) -> Result<GeneratedDictionary> {
+    spec.metadata.validate()?;
+    let table = CsvTable::parse(data, &spec.format)?;
+    let term_column = table.column_index(&spec.columns.term)?;
+    let reading_column = spec
+        .columns
+        .reading
+        .as_ref()
+        .map(|c| table.column_index(c))
+        .transpose()?;
+    let value_column = table.column_index(&spec.columns.value)?;
+
+    let mut report = CsvImportReport::default();
+    let mut entries = Vec::new();
+    for row in table.rows() {
+        let (Some(term), Some(value)) = (
+            field(row, term_column),
+            field(row, value_column).and_then(frequency_value),
+        ) else {
+            report.skip(row);
+            continue;
+        };
+        let reading = reading_column
+            .and_then(|c| field(row, c))
+            .filter(|reading| *reading != term);
+        let data = match reading {
+            Some(reading) => serde_json::json!({ "reading": reading, "frequency": value }),
+            None => value,
+        };
+        entries.push(serde_json::json!([term, "freq", data]));
+    }
+    if entries.is_empty() {
+        bail!("No usable rows found in the CSV file");
+    }
+    report.entries = entries.len();
+
+    let frequency_mode = match spec.value_kind {
+        FrequencyValueKind::Rank => "rank-based",
+        FrequencyValueKind::Occurrence => "occurrence-based",
+    };
+    let index = spec
+        .metadata
+        .index_json(serde_json::json!({ "frequencyMode": frequency_mode }));
+    let archive = write_archive(&index, "term_meta_bank", &entries)?;
+    Ok(GeneratedDictionary { archive, report })
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PitchColumns {
+    pub term: ColumnRef,
+    /// Defaults to the term, for kana-only sources
+    pub reading: Option<ColumnRef>,
+    /// Downstep positions, e.g. `0` or `1,3`. Brackets as in `[0]` are ignored.
+    pub accent: ColumnRef,
+}
+
+/// How to turn a CSV/TSV accent dump into a pitch dictionary
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PitchImportSpec {
+    #[serde(flatten)]
+    pub metadata: DictionaryMetadata,
+    #[serde(flatten)]
+    pub format: CsvFormat,
+    pub columns: PitchColumns,
+}
+
+/// Downstep positions in an accent field. Multiple accents may be separated
+/// by commas, Japanese commas, middle dots, slashes or whitespace.
+fn accent_positions(raw: &str) -> Option<Vec<i32>> {
+    let positions: Vec<i32> = raw
+        .split(|c: char| matches!(c, ',' | '、' | '・' | '/' | ';') || c.is_whitespace())
+        .map(|p| p.trim_matches(|c| matches!(c, '[' | ']' | '(' | ')' | '（' | '）')))
+        .filter(|p| !p.is_empty())
+        .map(|p| p.parse().ok().filter(|p: &i32| *p >= 0))
+        .collect::<Option<_>>()?;
+    (!positions.is_empty()).then_some(positions)
+}
+
+pub fn build_pitch_dictionary(data: &[u8], spec: &PitchImportSpec) -> Result<GeneratedDictionary> {
+    spec.metadata.validate()?;
+    let table = CsvTable::parse(data, &spec.format)?;
+    let term_column = table.column_index(&spec.columns.term)?;
+    let reading_column = spec
+        .columns
+        .reading
+        .as_ref()
+        .map(|c| table.column_index(c))
+        .transpose()?;
+    let accent_column = table.column_index(&spec.columns.accent)?;
+
+    // Dumps often list each accent of a word on its own row, so rows are
+    // merged per (term, reading) in order of first appearance
+    let mut report = CsvImportReport::default();
+    let mut words: Vec<((String, String), Vec<i32>)> = Vec::new();
+    let mut word_index: HashMap<(String, String), usize> = HashMap::new();
+    for row in table.rows() {
+        let (Some(term), Some(positions)) = (
+            field(row, term_column),
+            field(row, accent_column).and_then(accent_positions),
+        ) else {
+            report.skip(row);
+            continue;
+        };
+        let reading = reading_column.and_then(|c| field(row, c)).unwrap_or(term);
+        let key = (term.to_string(), reading.to_string());
+        let index = *word_index.entry(key.clone()).or_insert_with(|| {
+            words.push((key, Vec::new()));
+            words.len() - 1
+        });
+        let word_positions = &mut words[index].1;
+        for position in positions {
+            if !word_positions.contains(&position) {
+                word_positions.push(position);
+            }
+        }
+    }
+    if words.is_empty() {
+        bail!("No usable rows found in the file");
+    }
+
+    let entries: Vec<_> = words
+        .into_iter()
+        .map(|((term, reading), positions)| {
+            let pitches: Vec<_> = positions
+                .into_iter()
+                .map(|position| serde_json::json!({ "position": position }))
+                .collect();
+            serde_json::json!([term, "pitch", { "reading": reading, "pitches": pitches }])
+        })
+        .collect();
+    report.entries = entries.len();
+
+    let index = spec.metadata.index_json(serde_json::json!({}));
+    let archive = write_archive(&index, "term_meta_bank", &entries)?;
+    Ok(GeneratedDictionary { archive, report })
+}
+
+/// Zip up an index and its entries, split into `{bank_prefix}_{n}.json` files
+pub fn write_archive(
+    index: &serde_json::Value,
+    bank_prefix: &str,
+    entries: &[serde_json::Value],
+) -> Result<Vec<u8>> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    zip.start_file("index.json", options)?;
+    zip.write_all(&serde_json::to_vec(index)?)?;
+    for (i, bank) in entries.chunks(BANK_SIZE).enumerate() {
+        zip.start_file(format!("{bank_prefix}_{}.json", i + 1), options)?;
+        zip.write_all(&serde_json::to_vec(bank)?)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use yomitan_format::json_schema::index::{DictionaryIndex, FrequencyMode};
+    use yomitan_format::json_schema::term_meta_bank_v3::{
+        FrequencyData, PitchData, TermMetaBankV3, TermMetaData,
+    };
+
+    fn read_archive_file(archive: &[u8], name: &str) -> String {
+        let mut zip = zip::ZipArchive::new(Cursor::new(archive)).unwrap();
+        let mut contents = String::new();
+        zip.by_name(name)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    #[test]
+    fn test_build_frequency_dictionary() {
+        let csv = "word,kana,count\n食べる,たべる,1200\n猫,ねこ,35.5\n,,3\nカード,カード,12\n犬,いぬ,many\n";
+        let spec: FrequencyImportSpec = serde_json::from_value(serde_json::json!({
+            "title": "Novel counts",
+            "revision": "2024-01",
+            "columns": { "term": "word", "reading": "kana", "value": 2 },
+            "valueKind": "occurrence"
+        }))
+        .unwrap();
+
+        let generated = build_frequency_dictionary(csv.as_bytes(), &spec).unwrap();
+        assert_eq!(generated.report.entries, 3);
+        assert_eq!(generated.report.skipped_rows, 2);
+        assert_eq!(generated.report.skipped_lines, vec![4, 6]);
+
+        let index: DictionaryIndex =
+            serde_json::from_str(&read_archive_file(&generated.archive, "index.json")).unwrap();
+        assert_eq!(index.title, "Novel counts");
+        assert_eq!(index.revision, "2024-01");
+        assert!(matches!(
+            index.frequency_mode,
+            Some(FrequencyMode::OccurrenceBased)
+        ));
+        index.validate().unwrap();
+
+        // The generated bank must parse as a regular Yomitan term meta bank
+        let bank: TermMetaBankV3 = serde_json::from_str(&read_archive_file(
+            &generated.archive,
+            "term_meta_bank_1.json",
+        ))
+        .unwrap();
+        assert_eq!(bank.len(), 3);
+        match &bank[0].data {
+            TermMetaData::Frequency(FrequencyData::Detailed(details)) => {
+                assert_eq!(details.reading.as_deref(), Some("たべる"));
+                assert_eq!(details.frequency, Some(serde_json::json!(1200)));
+            }
+            other => panic!("Unexpected frequency data: {other:?}"),
+        }
+        // A reading equal to the term is left out
+        assert_eq!(
+            bank[2].data,
+            TermMetaData::Frequency(FrequencyData::SimpleNumber(12))
+        );
+    }
+
+    #[test]
+    fn test_missing_column() {
+        let spec: FrequencyImportSpec = serde_json::from_value(serde_json::json!({
+            "title": "Ranks",
+            "delimiter": "\t",
+            "hasHeader": false,
+            "columns": { "term": 0, "value": "rank" },
+            "valueKind": "rank"
+        }))
+        .unwrap();
+        assert!(build_frequency_dictionary("猫\t1\n".as_bytes(), &spec).is_err());
+    }
+
+    #[test]
+    fn test_build_pitch_dictionary() {
+        let tsv = "語\t読み\tアクセント\n箸\tはし\t1\n橋\tはし\t2\n端\tはし\t[0]\n日本\tにほん\t2\n日本\tにほん\t2・3\nこんにちは\t\t5\n雨\tあめ\t?\n";
+        let spec: PitchImportSpec = serde_json::from_value(serde_json::json!({
+            "title": "OJAD accents",
+            "delimiter": "\t",
+            "columns": { "term": "語", "reading": "読み", "accent": "アクセント" }
+        }))
+        .unwrap();
+
+        let generated = build_pitch_dictionary(tsv.as_bytes(), &spec).unwrap();
+        assert_eq!(generated.report.entries, 5);
+        assert_eq!(generated.report.skipped_lines, vec![8]);
+
+        let bank: TermMetaBankV3 = serde_json::from_str(&read_archive_file(
+            &generated.archive,
+            "term_meta_bank_1.json",
+        ))
+        .unwrap();
+        let pitch = |i: usize| match &bank[i].data {
+            TermMetaData::Pitch(PitchData { reading, pitches }) => (
+                bank[i].term.as_str(),
+                reading.clone(),
+                pitches.iter().map(|p| p.position).collect::<Vec<_>>(),
+            ),
+            other => panic!("Unexpected meta data: {other:?}"),
+        };
+        assert_eq!(pitch(2), ("端", "はし".to_string(), vec![0]));
+        // Rows for the same word are merged
+        assert_eq!(pitch(3), ("日本", "にほん".to_string(), vec![2, 3]));
+        // Kana-only words use the term as the reading
+        assert_eq!(pitch(4), ("こんにちは", "こんにちは".to_string(), vec![5]));
+    }
+}
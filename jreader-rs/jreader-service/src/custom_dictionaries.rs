@@ -0,0 +1,687 @@
+//! Custom dictionaries: term dictionaries a user writes themselves through
+//! the API, looked up alongside the installed dictionaries for that user only.
+//! A dictionary can be attached to a book as a glossary overlay (character
+//! names, in-world terms), which is only consulted for lookups made in that
+//! book, before any other dictionary.
+//!
+//! Each one is a regular Yomitan dictionary directory (`index.json` plus a
+//! term bank database) under the user's `LIBRARY_DATA_DIR`, edited in place.
+//! The sequence number of an entry is its id, so entries are unique across
+//! the headwords they are stored under.
+
+use anyhow::{Context, Result};
+use jreader_core::dictionaries::{DictionaryResult, YomitanTermDictionary};
+use jreader_core::mecab::TokenFeature;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::warn;
+use uuid::Uuid;
+use yomitan_format::json_schema::term_bank_v3::{Definition, TermBankV3, TermEntry};
+use yomitan_format::kv_store::db::DictionaryDB;
+use yomitan_format::{NormalizedPathBuf, StripExtension};
+
+use crate::library::user_library_dir;
+
+pub const MAX_TITLE_LENGTH: usize = 100;
+pub const MAX_DICTIONARIES_PER_USER: usize = 20;
+pub const MAX_DEFINITIONS_PER_ENTRY: usize = 50;
+
+const CUSTOM_DICTIONARIES_DIR: &str = "custom-dictionaries";
+/// Revision of every custom dictionary, which keeps their results apart from
+/// an installed dictionary with the same title
+const CUSTOM_REVISION: &str = "custom";
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomDictionary {
+    pub id: Uuid,
+    pub title: String,
+    pub description: Option<String>,
+    /// Upload id of the book this dictionary is an overlay of
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub book_id: Option<String>,
+    pub entry_count: usize,
+}
+
+/// The fields of `index.json` we read back. `bookId` isn't a Yomitan field,
+/// which other readers ignore.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredIndex {
+    title: String,
+    description: Option<String>,
+    book_id: Option<String>,
+}
+
+/// A term entry as the API takes it. Tags are single words, as Yomitan
+/// stores them space separated.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomEntryInput {
+    pub term: String,
+    #[serde(default)]
+    pub reading: String,
+    /// Plain strings or Yomitan structured content
+    pub definitions: Vec<Definition>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Deinflection rules of the term, e.g. `v5` or `adj-i`
+    #[serde(default)]
+    pub rule_identifiers: String,
+    #[serde(default)]
+    pub term_tags: Vec<String>,
+    #[serde(default)]
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomEntry {
+    pub id: i64,
+    #[serde(flatten)]
+    pub entry: CustomEntryInput,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomDictionaryWithEntries {
+    #[serde(flatten)]
+    pub dictionary: CustomDictionary,
+    pub entries: Vec<CustomEntry>,
+}
+
+/// Trim and check a dictionary title, returning the cleaned up title
+pub fn validate_title(title: &str) -> Result<String, String> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Err("Dictionary title must not be empty".to_string());
+    }
+    if title.chars().count() > MAX_TITLE_LENGTH {
+        return Err(format!(
+            "Dictionary title must be at most {} characters",
+            MAX_TITLE_LENGTH
+        ));
+    }
+    Ok(title.to_string())
+}
+
+/// Trim and check an entry, returning the cleaned up entry
+pub fn validate_entry(entry: CustomEntryInput) -> Result<CustomEntryInput, String> {
+    let term = entry.term.trim().to_string();
+    if term.is_empty() {
+        return Err("Term must not be empty".to_string());
+    }
+    let definitions: Vec<Definition> = entry
+        .definitions
+        .into_iter()
+        .filter_map(|definition| match definition {
+            Definition::Simple(text) => {
+                let text = text.trim();
+                (!text.is_empty()).then(|| Definition::Simple(text.to_string()))
+            }
+            definition => Some(definition),
+        })
+        .collect();
+    if definitions.is_empty() {
+        return Err("An entry needs at least one definition".to_string());
+    }
+    if definitions.len() > MAX_DEFINITIONS_PER_ENTRY {
+        return Err(format!(
+            "An entry can have at most {MAX_DEFINITIONS_PER_ENTRY} definitions"
+        ));
+    }
+    let split_tags = |tags: &[String]| -> Vec<String> {
+        let mut split: Vec<String> = Vec::new();
+        for tag in tags.iter().flat_map(|t| t.split_whitespace()) {
+            if !split.iter().any(|t| t == tag) {
+                split.push(tag.to_string());
+            }
+        }
+        split
+    };
+    Ok(CustomEntryInput {
+        term,
+        reading: entry.reading.trim().to_string(),
+        definitions,
+        tags: split_tags(&entry.tags),
+        rule_identifiers: entry
+            .rule_identifiers
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+        term_tags: split_tags(&entry.term_tags),
+        score: entry.score,
+    })
+}
+
+impl CustomEntryInput {
+    /// The term bank row of the entry
+    fn to_row(&self, id: i64) -> Result<serde_json::Value> {
+        Ok(serde_json::json!([
+            self.term,
+            self.reading,
+            self.tags.join(" "),
+            self.rule_identifiers,
+            self.score,
+            serde_json::to_value(&self.definitions)?,
+            id,
+            self.term_tags.join(" "),
+        ]))
+    }
+}
+
+impl CustomEntry {
+    fn from_row(row: &serde_json::Value) -> Result<Self> {
+        let entry: TermEntry = serde_json::from_value(row.clone())?;
+        Ok(Self {
+            id: entry.sequence_number,
+            entry: CustomEntryInput {
+                term: entry.text,
+                reading: entry.reading,
+                definitions: entry.definitions,
+                tags: entry.tags.unwrap_or_default(),
+                rule_identifiers: entry.rule_identifiers,
+                term_tags: entry.term_tags.unwrap_or_default(),
+                score: entry.score,
+            },
+        })
+    }
+}
+
+/// Custom dictionaries, stored per user under `LIBRARY_DATA_DIR`
+pub struct CustomDictionaryStore {
+    data_dir: PathBuf,
+    // Entry ids are allocated from the highest one in use
+    write_lock: Mutex<()>,
+}
+
+impl CustomDictionaryStore {
+    pub fn new(data_dir: PathBuf) -> Self {
+        Self {
+            data_dir,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    fn user_dir(&self, user_id: &str) -> Result<PathBuf> {
+        Ok(user_library_dir(&self.data_dir, user_id)?.join(CUSTOM_DICTIONARIES_DIR))
+    }
+
+    fn dictionary_dir(&self, user_id: &str, dict_id: Uuid) -> Result<PathBuf> {
+        Ok(self.user_dir(user_id)?.join(dict_id.to_string()))
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, ()>> {
+        self.write_lock
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Custom dictionary lock poisoned: {e}"))
+    }
+
+    // Ids of the user's dictionaries, in no particular order
+    fn dictionary_ids(&self, user_id: &str) -> Result<Vec<Uuid>> {
+        let user_dir = self.user_dir(user_id)?;
+        if !user_dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut ids = Vec::new();
+        for entry in std::fs::read_dir(&user_dir)
+            .with_context(|| format!("Failed to read {}", user_dir.display()))?
+        {
+            let entry = entry?;
+            if let Some(dict_id) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| Uuid::parse_str(name).ok())
+            {
+                ids.push(dict_id);
+            }
+        }
+        Ok(ids)
+    }
+
+    /// The user's custom dictionaries, by title
+    pub fn list(&self, user_id: &str) -> Result<Vec<CustomDictionary>> {
+        let mut dictionaries = Vec::new();
+        for dict_id in self.dictionary_ids(user_id)? {
+            match self.get(user_id, dict_id)? {
+                Some(dictionary) => dictionaries.push(dictionary.dictionary),
+                None => warn!(%dict_id, "⚠️ Skipping custom dictionary without index"),
+            }
+        }
+        dictionaries.sort_by(|a, b| a.title.cmp(&b.title).then_with(|| a.id.cmp(&b.id)));
+        Ok(dictionaries)
+    }
+
+    /// Create an empty dictionary, as an overlay of `book_id` if given.
+    /// Errors with a message for the user when they already have as many as
+    /// they can or the book id is invalid.
+    pub fn create(
+        &self,
+        user_id: &str,
+        title: &str,
+        description: Option<String>,
+        book_id: Option<String>,
+    ) -> Result<Result<CustomDictionary, String>> {
+        // Upload ids are directory names, so the same validation applies
+        if let Some(book_id) = &book_id {
+            if user_library_dir(Path::new(""), book_id).is_err() {
+                return Ok(Err("Invalid book id".to_string()));
+            }
+        }
+        let _guard = self.lock()?;
+        if self.dictionary_ids(user_id)?.len() >= MAX_DICTIONARIES_PER_USER {
+            return Ok(Err(format!(
+                "A user can have at most {MAX_DICTIONARIES_PER_USER} custom dictionaries"
+            )));
+        }
+        let dictionary = CustomDictionary {
+            id: Uuid::new_v4(),
+            title: title.to_string(),
+            description: description.filter(|d| !d.trim().is_empty()),
+            book_id,
+            entry_count: 0,
+        };
+        let dict_dir = self.dictionary_dir(user_id, dictionary.id)?;
+        std::fs::create_dir_all(&dict_dir)
+            .with_context(|| format!("Failed to create {}", dict_dir.display()))?;
+        let mut index = serde_json::json!({
+            "title": dictionary.title,
+            "revision": CUSTOM_REVISION,
+            "format": 3,
+            "sequenced": true,
+            "sourceLanguage": "ja",
+        });
+        if let Some(description) = &dictionary.description {
+            index["description"] = description.clone().into();
+        }
+        if let Some(book_id) = &dictionary.book_id {
+            index["bookId"] = book_id.clone().into();
+        }
+        std::fs::write(dict_dir.join("index.json"), serde_json::to_vec(&index)?)?;
+        open_term_bank(&dict_dir)?;
+        Ok(Ok(dictionary))
+    }
+
+    /// A dictionary with all its entries, by term
+    pub fn get(&self, user_id: &str, dict_id: Uuid) -> Result<Option<CustomDictionaryWithEntries>> {
+        let dict_dir = self.dictionary_dir(user_id, dict_id)?;
+        let Some(index) = read_index(&dict_dir)? else {
+            return Ok(None);
+        };
+        let mut entries = Vec::new();
+        for (_, json) in open_term_bank(&dict_dir)?.get_all()? {
+            let rows: Vec<serde_json::Value> = serde_json::from_str(&json)?;
+            for row in &rows {
+                entries.push(CustomEntry::from_row(row)?);
+            }
+        }
+        Ok(Some(CustomDictionaryWithEntries {
+            dictionary: CustomDictionary {
+                id: dict_id,
+                title: index.title,
+                description: index.description,
+                book_id: index.book_id,
+                entry_count: entries.len(),
+            },
+            entries,
+        }))
+    }
+
+    /// Returns false if there is no such dictionary
+    pub fn delete(&self, user_id: &str, dict_id: Uuid) -> Result<bool> {
+        let _guard = self.lock()?;
+        let dict_dir = self.dictionary_dir(user_id, dict_id)?;
+        if !dict_dir.exists() {
+            return Ok(false);
+        }
+        std::fs::remove_dir_all(&dict_dir)
+            .with_context(|| format!("Failed to delete {}", dict_dir.display()))?;
+        Ok(true)
+    }
+
+    /// Add an entry, returning it with its id. None if there is no such
+    /// dictionary.
+    pub fn add_entry(
+        &self,
+        user_id: &str,
+        dict_id: Uuid,
+        entry: CustomEntryInput,
+    ) -> Result<Option<CustomEntry>> {
+        let _guard = self.lock()?;
+        let Some(db) = self.open_existing(user_id, dict_id)? else {
+            return Ok(None);
+        };
+        let id = db.max_sequence()? + 1;
+        let mut rows = rows_at(&db, &entry.term)?;
+        rows.push(entry.to_row(id)?);
+        db.replace(&entry.term, &rows)?;
+        Ok(Some(CustomEntry { id, entry }))
+    }
+
+    /// Replace an entry, moving it when its term changes. None if there is no
+    /// such dictionary or entry.
+    pub fn update_entry(
+        &self,
+        user_id: &str,
+        dict_id: Uuid,
+        entry_id: i64,
+        entry: CustomEntryInput,
+    ) -> Result<Option<CustomEntry>> {
+        let _guard = self.lock()?;
+        let Some(db) = self.open_existing(user_id, dict_id)? else {
+            return Ok(None);
+        };
+        let Some((key, mut rows, position)) = find_entry(&db, entry_id)? else {
+            return Ok(None);
+        };
+        let row = entry.to_row(entry_id)?;
+        if key == entry.term {
+            rows[position] = row;
+            db.replace(&key, &rows)?;
+        } else {
+            rows.remove(position);
+            db.replace(&key, &rows)?;
+            let mut moved_to = rows_at(&db, &entry.term)?;
+            moved_to.push(row);
+            db.replace(&entry.term, &moved_to)?;
+        }
+        Ok(Some(CustomEntry {
+            id: entry_id,
+            entry,
+        }))
+    }
+
+    /// Returns false if there is no such dictionary or entry
+    pub fn delete_entry(&self, user_id: &str, dict_id: Uuid, entry_id: i64) -> Result<bool> {
+        let _guard = self.lock()?;
+        let Some(db) = self.open_existing(user_id, dict_id)? else {
+            return Ok(false);
+        };
+        let Some((key, mut rows, position)) = find_entry(&db, entry_id)? else {
+            return Ok(false);
+        };
+        rows.remove(position);
+        db.replace(&key, &rows)?;
+        Ok(true)
+    }
+
+    /// Results of the user's custom dictionaries for a lookup made in `book`,
+    /// the overlays of the book first, then by title. Only dictionaries with
+    /// matching entries are included.
+    pub fn lookup(
+        &self,
+        user_id: &str,
+        book: Option<&str>,
+        token_features: &Vec<TokenFeature>,
+    ) -> Result<Vec<DictionaryResult>> {
+        let mut results = Vec::new();
+        for dict_id in self.dictionary_ids(user_id)? {
+            let dict_dir = self.dictionary_dir(user_id, dict_id)?;
+            let Some(index) = read_index(&dict_dir)? else {
+                continue;
+            };
+            let is_overlay = match index.book_id.as_deref() {
+                Some(book_id) if Some(book_id) == book => true,
+                Some(_) => continue,
+                None => false,
+            };
+            let result =
+                YomitanTermDictionary::open(&utf8_path(&dict_dir)?)?.lookup(token_features)?;
+            if !result.entries.is_empty() || !result.heuristic_entries.is_empty() {
+                results.push((is_overlay, result));
+            }
+        }
+        results.sort_by(|(a_overlay, a), (b_overlay, b)| {
+            b_overlay.cmp(a_overlay).then_with(|| a.title.cmp(&b.title))
+        });
+        Ok(results.into_iter().map(|(_, result)| result).collect())
+    }
+
+    fn open_existing(
+        &self,
+        user_id: &str,
+        dict_id: Uuid,
+    ) -> Result<Option<DictionaryDB<TermBankV3>>> {
+        let dict_dir = self.dictionary_dir(user_id, dict_id)?;
+        if !dict_dir.join("index.json").exists() {
+            return Ok(None);
+        }
+        Ok(Some(open_term_bank(&dict_dir)?))
+    }
+}
+
+// The stored index of a dictionary, None if there is none
+fn read_index(dict_dir: &Path) -> Result<Option<StoredIndex>> {
+    let index_path = dict_dir.join("index.json");
+    match std::fs::read(&index_path) {
+        Ok(data) => {
+            Ok(Some(serde_json::from_slice(&data).with_context(|| {
+                format!("Invalid index: {}", index_path.display())
+            })?))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn utf8_path(path: &Path) -> Result<camino::Utf8PathBuf> {
+    camino::Utf8PathBuf::from_path_buf(path.to_path_buf())
+        .map_err(|path| anyhow::anyhow!("Path is not valid UTF-8: {}", path.display()))
+}
+
+// The writable term bank of a dictionary, created if missing
+fn open_term_bank(dict_dir: &Path) -> Result<DictionaryDB<TermBankV3>> {
+    let dict_dir = NormalizedPathBuf::new(&utf8_path(dict_dir)?, StripExtension::None)?;
+    DictionaryDB::new(dict_dir)
+}
+
+fn rows_at(db: &DictionaryDB<TermBankV3>, key: &str) -> Result<Vec<serde_json::Value>> {
+    match db.get(key)? {
+        Some(json) => Ok(serde_json::from_str(&json)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+// The key the entry is stored under, the rows there and the entry's position
+fn find_entry(
+    db: &DictionaryDB<TermBankV3>,
+    entry_id: i64,
+) -> Result<Option<(String, Vec<serde_json::Value>, usize)>> {
+    for key in db.get_by_sequence(entry_id)? {
+        let rows = rows_at(db, &key)?;
+        if let Some(position) = rows
+            .iter()
+            .position(|row| row.get(6).and_then(|id| id.as_i64()) == Some(entry_id))
+        {
+            return Ok(Some((key, rows, position)));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const USER: &str = "00000000-0000-0000-0000-000000000001";
+
+    fn input(term: &str, definition: &str) -> CustomEntryInput {
+        CustomEntryInput {
+            term: term.to_string(),
+            reading: String::new(),
+            definitions: vec![Definition::Simple(definition.to_string())],
+            tags: Vec::new(),
+            rule_identifiers: String::new(),
+            term_tags: Vec::new(),
+            score: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_validate_entry() {
+        let entry = CustomEntryInput {
+            term: " 推し ".to_string(),
+            reading: "おし ".to_string(),
+            definitions: vec![
+                Definition::Simple(" fave ".to_string()),
+                Definition::Simple(" ".to_string()),
+            ],
+            tags: vec!["slang n".to_string(), "n".to_string()],
+            ..input("", "")
+        };
+        let entry = validate_entry(entry).unwrap();
+        assert_eq!(entry.term, "推し");
+        assert_eq!(entry.reading, "おし");
+        assert_eq!(entry.definitions, [Definition::Simple("fave".to_string())]);
+        assert_eq!(entry.tags, ["slang", "n"]);
+
+        assert!(validate_entry(input(" ", "fave")).is_err());
+        assert!(validate_entry(input("推し", " ")).is_err());
+        assert!(validate_title(&"x".repeat(MAX_TITLE_LENGTH + 1)).is_err());
+        assert_eq!(validate_title(" 用語集 ").unwrap(), "用語集");
+    }
+
+    #[test]
+    fn test_entries() {
+        let dir = TempDir::new().unwrap();
+        let store = CustomDictionaryStore::new(dir.path().to_path_buf());
+        assert!(store.list(USER).unwrap().is_empty());
+
+        let dictionary = store
+            .create(USER, "用語集", Some("My terms".to_string()), None)
+            .unwrap()
+            .unwrap();
+        let first = store
+            .add_entry(USER, dictionary.id, input("推し", "fave"))
+            .unwrap()
+            .unwrap();
+        let second = store
+            .add_entry(USER, dictionary.id, input("推し", "pushing"))
+            .unwrap()
+            .unwrap();
+        assert_eq!((first.id, second.id), (1, 2));
+
+        let updated = store
+            .update_entry(
+                USER,
+                dictionary.id,
+                first.id,
+                input("推し活", "fan activities"),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.id, first.id);
+        let listed = store.get(USER, dictionary.id).unwrap().unwrap();
+        assert_eq!(listed.dictionary.entry_count, 2);
+        assert_eq!(listed.entries, [second.clone(), updated]);
+
+        assert!(store.delete_entry(USER, dictionary.id, second.id).unwrap());
+        assert!(!store.delete_entry(USER, dictionary.id, second.id).unwrap());
+        assert!(store
+            .update_entry(USER, dictionary.id, second.id, input("推し", "fave"))
+            .unwrap()
+            .is_none());
+        // The highest id is free again once its entry is deleted
+        let third = store
+            .add_entry(USER, dictionary.id, input("推し", "fave"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(third.id, 2);
+
+        assert_eq!(store.list(USER).unwrap()[0].entry_count, 2);
+        assert!(store
+            .add_entry(USER, Uuid::new_v4(), input("推し", "fave"))
+            .unwrap()
+            .is_none());
+        assert!(store.delete(USER, dictionary.id).unwrap());
+        assert!(store.list(USER).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let dir = TempDir::new().unwrap();
+        let store = CustomDictionaryStore::new(dir.path().to_path_buf());
+        let dictionary = store.create(USER, "用語集", None, None).unwrap().unwrap();
+        store
+            .add_entry(USER, dictionary.id, input("推し", "fave"))
+            .unwrap();
+        store.create(USER, "Empty", None, None).unwrap().unwrap();
+
+        let token = |surface: &str| {
+            vec![TokenFeature::from_feature_string(
+                surface,
+                &format!("名詞,一般,*,*,*,*,{surface}"),
+            )]
+        };
+        let results = store.lookup(USER, None, &token("推し")).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].title, "用語集");
+        assert_eq!(results[0].revision, CUSTOM_REVISION);
+        assert_eq!(
+            results[0].entries[0].definitions,
+            input("", "fave").definitions
+        );
+        assert!(store.lookup(USER, None, &token("推す")).unwrap().is_empty());
+        assert!(store
+            .lookup("other-user", None, &token("推し"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_book_overlay() {
+        let dir = TempDir::new().unwrap();
+        let store = CustomDictionaryStore::new(dir.path().to_path_buf());
+        let general = store
+            .create(USER, "A glossary", None, None)
+            .unwrap()
+            .unwrap();
+        let overlay = store
+            .create(USER, "Characters", None, Some("book-1".to_string()))
+            .unwrap()
+            .unwrap();
+        for dictionary in [&general, &overlay] {
+            store
+                .add_entry(USER, dictionary.id, input("マリ", &dictionary.title))
+                .unwrap();
+        }
+        let token = vec![TokenFeature::from_feature_string(
+            "マリ",
+            "名詞,固有名詞,人名,*,*,*,マリ",
+        )];
+
+        let titles = |book| -> Vec<String> {
+            store
+                .lookup(USER, book, &token)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.title)
+                .collect()
+        };
+        assert_eq!(titles(Some("book-1")), ["Characters", "A glossary"]);
+        assert_eq!(titles(Some("book-2")), ["A glossary"]);
+        assert_eq!(titles(None), ["A glossary"]);
+
+        let listed = store.get(USER, overlay.id).unwrap().unwrap();
+        assert_eq!(listed.dictionary.book_id.as_deref(), Some("book-1"));
+        assert!(store
+            .create(USER, "Bad", None, Some("../book".to_string()))
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_dictionary_limit() {
+        let dir = TempDir::new().unwrap();
+        let store = CustomDictionaryStore::new(dir.path().to_path_buf());
+        for i in 0..MAX_DICTIONARIES_PER_USER {
+            store
+                .create(USER, &i.to_string(), None, None)
+                .unwrap()
+                .unwrap();
+        }
+        assert!(store.create(USER, "one more", None, None).unwrap().is_err());
+    }
+}
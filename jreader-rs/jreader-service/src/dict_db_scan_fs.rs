@@ -1,8 +1,18 @@
+use crate::dict_stats::DictionaryStats;
 use crate::dictionaries::YomitanDictionaries;
+use crate::disk_space::{self, DiskWatchdog};
+use crate::import_failures;
+use crate::loading_policy::{ArchiveCandidate, ArchiveKind, LoadDecision, LoadingPolicy};
+use crate::reverse_lookup;
+use crate::scan_reports::{self, ArchiveOutcome, ArchiveReport, ScanReport, SkipReason};
+use crate::static_assets::{AssetManifest, AssetStore};
 use anyhow::{Context, Result};
 use camino::Utf8PathBuf as PathBuf;
+use std::collections::BTreeMap;
 use std::fs::{self, File};
+use std::io::{Read, Seek};
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::RwLock;
 use tracing::{debug, error, info, instrument, trace, warn};
 use uuid::Uuid;
@@ -16,22 +26,31 @@ use yomitan_format::kv_store::db::DictionaryDB;
 use yomitan_format::kv_store::utils::{
     CreateTaskParams, ProgressGroupId, ProgressStateTable, ProgressTaskType,
 };
-use yomitan_format::kv_store::{GroupedJSON, IsYomitanSchema};
-use yomitan_format::{NormalizedFilename, NormalizedPathBuf};
+use yomitan_format::kv_store::{
+    encoding, ArchiveJsonFile, GroupedJSON, ImportMode, IsYomitanSchema, SchemaErrorReport,
+};
+use yomitan_format::normalization::normalize_name;
+use yomitan_format::{NormalizedFilename, NormalizedPathBuf, StripExtension};
 use zip::ZipArchive;
 
+/// Import and register the archives in `DICTS_PATH/yomitan` that the loading
+/// policy (with `budget_mb` if given) has room for. What happened to each
+/// archive is saved as a scan report, which is also returned.
 #[instrument(skip(progress_state, yomi_dicts))]
 pub async fn scan_fs(
     progress_state: Arc<ProgressStateTable>,
     yomi_dicts: Option<Arc<RwLock<YomitanDictionaries>>>,
-    max_size_mb: Option<u64>,
-) -> Result<()> {
+    budget_mb: Option<u64>,
+    mode: ImportMode,
+) -> Result<ScanReport> {
     let dicts_path: PathBuf = {
         dotenvy::dotenv().context(format!("Failed to load .env file"))?;
         let dicts_path =
             std::env::var("DICTS_PATH").context(format!("Failed to load DICTS_PATH"))?;
         PathBuf::from(dicts_path)
     };
+    let policy = LoadingPolicy::from_env().with_budget(budget_mb);
+    let mut report = ScanReport::new(mode, policy.budget_mb);
 
     let yomitan_dir_path = &dicts_path.join("yomitan");
     info!(path = %yomitan_dir_path, "Scanning directory");
@@ -50,110 +69,169 @@ pub async fn scan_fs(
 
             let total_entries = entries.len();
             info!(total_entries = %total_entries, "Found entries in directory");
-            let mut zip_count = 0;
+            let archives: Vec<PathBuf> = entries
+                .iter()
+                .map(|entry| {
+                    PathBuf::try_from(entry.path()).expect(&format!(
+                        "Failed to convert path to PathBuf for {}",
+                        entry.path().display()
+                    ))
+                })
+                .filter(|path| path.is_file() && path.extension().map_or(false, |s| s == "zip"))
+                .collect();
+            let zip_count = archives.len();
             let mut processed_count = 0;
             let mut skipped_count = 0;
             let mut error_count = 0;
-            let mut size_filtered_count = 0;
-
-            for entry in entries {
-                let yomitan_dict_path = PathBuf::try_from(entry.path()).expect(&format!(
-                    "Failed to convert path to PathBuf for {}",
-                    entry.path().display()
-                ));
-
-                if yomitan_dict_path.is_file() {
-                    if yomitan_dict_path.extension().map_or(false, |s| s == "zip") {
-                        zip_count += 1;
-
-                        // Check file size if max_size_mb is specified
-                        if let Some(max_size) = max_size_mb {
-                            if let Ok(metadata) = fs::metadata(&yomitan_dict_path) {
-                                let size_mb = metadata.len() / (1024 * 1024);
-                                if size_mb > max_size {
-                                    size_filtered_count += 1;
-                                    let filename = yomitan_dict_path
-                                        .file_name()
-                                        .unwrap_or_default()
-                                        .to_string();
-                                    info!(
-                                        %filename,
-                                        %size_mb,
-                                        progress = %(processed_count + skipped_count + error_count),
-                                        total = %zip_count,
-                                        "Skipping large dictionary"
-                                    );
-                                    continue;
-                                }
-                            }
-                        }
+            let mut over_budget_count = 0;
+            let mut deferred_count = 0;
 
-                        let normalized = NormalizedPathBuf::new(&yomitan_dict_path);
+            // Every archive is looked at first so the policy can weigh them
+            // against each other
+            let candidates: Vec<ArchiveCandidate> = archives.iter().map(survey_archive).collect();
+            let decisions = policy.plan(&candidates);
 
-                        // Check if dictionary already exists
-                        // let dict_dir = dicts_path.join("db").join(&normalized.filename.0);
-                        let dict_dir = NormalizedPathBuf::new(
-                            &dicts_path.join("db").join(&normalized.filename.0),
-                        );
-                        if dict_dir.path.exists() {
-                            skipped_count += 1;
-                            info!(
-                                filename = %normalized.filename.0,
-                                progress = %(processed_count + skipped_count + error_count),
-                                total = %zip_count,
-                                "Dictionary already exists, skipping ahead to registration"
-                            );
-                        } else {
-                            if normalized.path != yomitan_dict_path {
-                                info!(
-                                    normalized_path = ?normalized,
-                                    "Moving file to normalized path"
-                                );
-                                tokio::fs::rename(yomitan_dict_path, &normalized.path).await?;
-                            }
+            for ((yomitan_dict_path, candidate), decision) in
+                archives.into_iter().zip(&candidates).zip(decisions)
+            {
+                let archive_started = Instant::now();
+                if decision == LoadDecision::Skip {
+                    over_budget_count += 1;
+                    let filename = yomitan_dict_path
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string();
+                    let needed_mb = disk_space::megabytes(candidate.bank_bytes);
+                    report.archives.push(ArchiveReport {
+                        archive: filename.clone(),
+                        outcome: ArchiveOutcome::Skipped(SkipReason::OverBudget { needed_mb }),
+                        duration_ms: 0,
+                        deferred_assets: false,
+                        registration_error: None,
+                    });
+                    info!(
+                        %filename,
+                        %needed_mb,
+                        progress = %(processed_count + skipped_count + error_count),
+                        total = %zip_count,
+                        "Skipping dictionary that doesn't fit the loading budget"
+                    );
+                    continue;
+                }
+                let copy_assets = decision == LoadDecision::Full;
 
-                            info!(
-                                filename = %normalized.filename.0,
-                                progress = %(processed_count + skipped_count + error_count + 1),
-                                total = %zip_count,
-                                "Processing archive"
-                            );
-
-                            if let Err(e) = process_archive(
-                                dicts_path.clone(),
-                                normalized.clone(),
-                                progress_state.clone(),
-                                dict_dir.clone(),
-                            )
-                            .await
-                            {
-                                error_count += 1;
-                                error!(?e, ?normalized, "Error processing archive");
-                                continue; // TODO: Remove usage of continue for better control flow
-                            } else {
-                                processed_count += 1;
-                            }
-                        }
+                let normalized =
+                    NormalizedPathBuf::new(&yomitan_dict_path, StripExtension::Only("zip"))?;
 
-                        if let Some(yomi_dicts) = yomi_dicts.clone() {
-                            if let Err(e) = yomi_dicts
-                                .write()
-                                .await
-                                .register_dictionary(dict_dir.clone())
-                            {
-                                warn!(?e, filename = ?normalized.filename.0, dict_dir = ?dict_dir, "Failed to register dictionary");
-                            } else {
-                                info!(
-                                    filename = ?normalized.filename.0,
-                                    dict_dir = ?dict_dir,
-                                    "Added dictionary to YomitanDictionaries"
-                                );
+                // Check if dictionary already exists
+                let dict_dir = NormalizedPathBuf::new(
+                    &dicts_path.join("db").join(&normalized.filename.0),
+                    StripExtension::None,
+                )?;
+                let outcome;
+                let mut deferred_assets;
+                if dict_dir.path.exists() {
+                    skipped_count += 1;
+                    outcome = ArchiveOutcome::Skipped(SkipReason::AlreadyImported);
+                    info!(
+                        filename = %normalized.filename.0,
+                        progress = %(processed_count + skipped_count + error_count),
+                        total = %zip_count,
+                        "Dictionary already exists, skipping ahead to registration"
+                    );
+                    deferred_assets = has_deferred_assets(&dict_dir.path);
+                    if deferred_assets && copy_assets {
+                        match load_deferred_assets(
+                            &dicts_path,
+                            &yomitan_dict_path,
+                            &normalized.filename,
+                            progress_state.clone(),
+                            &dict_dir,
+                        ) {
+                            Ok(()) => {
+                                deferred_assets = false;
+                                info!(filename = %normalized.filename.0, "Loaded deferred static assets");
+                            }
+                            Err(e) => {
+                                warn!(?e, filename = %normalized.filename.0, "Failed to load deferred static assets")
                             }
-                        } else {
-                            debug!("YomitanDictionaries not found, skipping registration");
                         }
                     }
+                } else {
+                    if normalized.path != yomitan_dict_path {
+                        info!(
+                            normalized_path = ?normalized,
+                            "Moving file to normalized path"
+                        );
+                        tokio::fs::rename(yomitan_dict_path, &normalized.path).await?;
+                    }
+
+                    info!(
+                        filename = %normalized.filename.0,
+                        progress = %(processed_count + skipped_count + error_count + 1),
+                        total = %zip_count,
+                        copy_assets,
+                        "Processing archive"
+                    );
+
+                    if let Err(e) = process_archive(
+                        dicts_path.clone(),
+                        normalized.clone(),
+                        progress_state.clone(),
+                        dict_dir.clone(),
+                        mode,
+                        copy_assets,
+                    )
+                    .await
+                    {
+                        error_count += 1;
+                        error!(?e, ?normalized, "Error processing archive");
+                        report.archives.push(ArchiveReport {
+                            archive: normalized.filename.0.clone(),
+                            outcome: ArchiveOutcome::Failed {
+                                error_chain: e.chain().map(|e| e.to_string()).collect(),
+                            },
+                            duration_ms: archive_started.elapsed().as_millis() as u64,
+                            deferred_assets: false,
+                            registration_error: None,
+                        });
+                        continue; // TODO: Remove usage of continue for better control flow
+                    } else {
+                        processed_count += 1;
+                        outcome = ArchiveOutcome::Imported;
+                        deferred_assets = !copy_assets;
+                    }
+                }
+                if deferred_assets {
+                    deferred_count += 1;
+                }
+
+                let mut registration_error = None;
+                if let Some(yomi_dicts) = yomi_dicts.clone() {
+                    if let Err(e) = yomi_dicts
+                        .write()
+                        .await
+                        .register_dictionary(dict_dir.clone())
+                    {
+                        warn!(?e, filename = ?normalized.filename.0, dict_dir = ?dict_dir, "Failed to register dictionary");
+                        registration_error = Some(format!("{e:#}"));
+                    } else {
+                        info!(
+                            filename = ?normalized.filename.0,
+                            dict_dir = ?dict_dir,
+                            "Added dictionary to YomitanDictionaries"
+                        );
+                    }
+                } else {
+                    debug!("YomitanDictionaries not found, skipping registration");
                 }
+                report.archives.push(ArchiveReport {
+                    archive: normalized.filename.0.clone(),
+                    outcome,
+                    duration_ms: archive_started.elapsed().as_millis() as u64,
+                    deferred_assets,
+                    registration_error,
+                });
             }
 
             info!(
@@ -161,14 +239,266 @@ pub async fn scan_fs(
                 zip_files = %zip_count,
                 processed = %processed_count,
                 skipped = %skipped_count,
-                size_filtered = %size_filtered_count,
+                over_budget = %over_budget_count,
+                deferred_assets = %deferred_count,
                 errors = %error_count,
                 "Scan complete"
             );
         }
-        Err(e) => error!(?e, "Error reading directory"),
+        Err(e) => {
+            error!(?e, "Error reading directory");
+            report.error = Some(format!("Failed to read {yomitan_dir_path}: {e}"));
+        }
+    }
+
+    report.finish();
+    let saved = {
+        let (dicts_path, report) = (dicts_path.clone(), report.clone());
+        tokio::task::spawn_blocking(move || scan_reports::save(&dicts_path, &report)).await?
+    };
+    match saved {
+        Ok(()) => info!(id = %report.id, "Saved scan report"),
+        Err(e) => warn!(?e, "Failed to save scan report"),
     }
+    Ok(report)
+}
 
+/// Import a single archive from `DICTS_PATH/yomitan` and register it, without
+/// rescanning everything else
+#[instrument(skip(progress_state, yomi_dicts))]
+pub async fn import_archive(
+    archive_path: &std::path::Path,
+    progress_state: Arc<ProgressStateTable>,
+    yomi_dicts: Arc<RwLock<YomitanDictionaries>>,
+) -> Result<()> {
+    let dicts_path =
+        PathBuf::from(std::env::var("DICTS_PATH").context("Failed to load DICTS_PATH")?);
+    let archive_path = PathBuf::try_from(archive_path.to_path_buf())?;
+    let normalized = NormalizedPathBuf::new(&archive_path, StripExtension::Only("zip"))?;
+    if normalized.path != archive_path {
+        tokio::fs::rename(&archive_path, &normalized.path).await?;
+    }
+    let dict_dir =
+        import_archive_into(dicts_path, normalized, progress_state, ImportMode::Strict).await?;
+    yomi_dicts.write().await.register_dictionary(dict_dir)?;
+    Ok(())
+}
+
+/// Import an archive into the `db` directory under `dicts_path` without
+/// registering it. Returns the dictionary's directory.
+pub async fn import_archive_into(
+    dicts_path: PathBuf,
+    archive_path: NormalizedPathBuf,
+    progress_state: Arc<ProgressStateTable>,
+    mode: ImportMode,
+) -> Result<NormalizedPathBuf> {
+    let dict_dir = NormalizedPathBuf::new(
+        &dicts_path.join("db").join(&archive_path.filename.0),
+        StripExtension::None,
+    )?;
+    if dict_dir.path.exists() {
+        anyhow::bail!(
+            "Dictionary {} was already imported",
+            archive_path.filename.0
+        );
+    }
+    fs::create_dir_all(dicts_path.join("db"))?;
+    process_archive(
+        dicts_path,
+        archive_path,
+        progress_state,
+        dict_dir.clone(),
+        mode,
+        true,
+    )
+    .await?;
+    Ok(dict_dir)
+}
+
+/// Space a dictionary's SQLite databases take relative to its JSON banks,
+/// leaving room for the indexes
+const DB_SIZE_FACTOR: u64 = 2;
+
+/// Static assets copied between free space checks
+const ASSETS_PER_SPACE_CHECK: usize = 100;
+
+/// Rough upper bound on the disk space an import takes, before deduplication
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ImportEstimate {
+    /// The banks as SQLite databases
+    banks: u64,
+    static_assets: u64,
+}
+
+fn estimate_import<R: Read + Seek>(archive: &mut ZipArchive<R>) -> ImportEstimate {
+    let mut estimate = ImportEstimate {
+        banks: 0,
+        static_assets: 0,
+    };
+    for i in 0..archive.len() {
+        let Ok(file) = archive.by_index(i) else {
+            continue;
+        };
+        if file.is_dir() {
+            continue;
+        } else if file.name().ends_with(".json") {
+            estimate.banks += file.size() * DB_SIZE_FACTOR;
+        } else {
+            estimate.static_assets += file.size();
+        }
+    }
+    estimate
+}
+
+/// Marker left in a dictionary's directory when its static assets weren't
+/// copied for lack of room in the loading budget
+const DEFERRED_ASSETS_FILE: &str = "deferred_assets";
+
+fn has_deferred_assets(dict_dir: &PathBuf) -> bool {
+    dict_dir.join(DEFERRED_ASSETS_FILE).exists()
+}
+
+/// Bytes of a term meta bank read to tell frequency from pitch dictionaries
+const META_SNIFF_BYTES: u64 = 64 * 1024;
+
+/// What the loading policy needs to know about the archive at `path`. An
+/// archive that can't be read is of unknown kind and size, so that its
+/// import is still attempted and the error reported.
+fn survey_archive(path: &PathBuf) -> ArchiveCandidate {
+    let name = path.file_stem().unwrap_or_default().to_string();
+    let survey = || -> Result<ArchiveCandidate> {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let estimate = estimate_import(&mut archive);
+        Ok(ArchiveCandidate {
+            name: name.clone(),
+            title: archive_title(&mut archive),
+            kind: archive_kind(&mut archive),
+            bank_bytes: estimate.banks,
+            asset_bytes: estimate.static_assets,
+        })
+    };
+    survey().unwrap_or_else(|e| {
+        warn!(?e, %path, "Failed to read archive for the loading policy");
+        ArchiveCandidate {
+            name,
+            title: None,
+            kind: ArchiveKind::Unknown,
+            bank_bytes: 0,
+            asset_bytes: 0,
+        }
+    })
+}
+
+fn archive_title<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Option<String> {
+    let mut contents = Vec::new();
+    archive
+        .by_name("index.json")
+        .ok()?
+        .read_to_end(&mut contents)
+        .ok()?;
+    let index: DictionaryIndex =
+        serde_json::from_str(&encoding::decode_json(&contents).ok()?).ok()?;
+    Some(index.title)
+}
+
+/// Kind of dictionary in `archive`, told from its bank files before it is
+/// imported. The registered dictionary's type is worked out from its
+/// databases instead, see `YomitanDictionary::identify_dictionary_type`.
+fn archive_kind<R: Read + Seek>(archive: &mut ZipArchive<R>) -> ArchiveKind {
+    let bank = |prefix: &str| {
+        archive
+            .file_names()
+            .find(|name| {
+                let file = name.rsplit('/').next().unwrap_or(name);
+                file.starts_with(prefix) && file.ends_with(".json")
+            })
+            .map(str::to_string)
+    };
+    if bank("kanji_bank_").is_some() {
+        return ArchiveKind::Kanji;
+    }
+    if bank("term_bank_").is_some() {
+        return ArchiveKind::Term;
+    }
+    let Some(meta_bank) = bank("term_meta_bank_") else {
+        return ArchiveKind::Unknown;
+    };
+    let mut head = Vec::new();
+    let read = archive
+        .by_name(&meta_bank)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| Ok(file.take(META_SNIFF_BYTES).read_to_end(&mut head)?));
+    if read.is_err() {
+        return ArchiveKind::Unknown;
+    }
+    let head = String::from_utf8_lossy(&head);
+    if head.contains("\"freq\"") {
+        ArchiveKind::Frequency
+    } else if head.contains("\"pitch\"") {
+        ArchiveKind::Pitch
+    } else {
+        ArchiveKind::Unknown
+    }
+}
+
+/// Copy the static assets that were deferred when the dictionary in
+/// `dict_dir` was imported from `archive_path`
+fn load_deferred_assets(
+    dicts_path: &PathBuf,
+    archive_path: &PathBuf,
+    dict_filename: &NormalizedFilename,
+    progress_state: Arc<ProgressStateTable>,
+    dict_dir: &NormalizedPathBuf,
+) -> Result<()> {
+    let mut archive = ZipArchive::new(File::open(archive_path)?)?;
+    let index: DictionaryIndex =
+        serde_json::from_slice(&fs::read(dict_dir.path.join("index.json"))?)?;
+    let watchdog = DiskWatchdog::from_env(dicts_path.as_std_path());
+    check_disk_space(dicts_path, dict_filename, &mut archive, &watchdog, true)?;
+    copy_static_assets(
+        dicts_path.clone(),
+        dict_filename.clone(),
+        &mut archive,
+        progress_state,
+        &index,
+        ProgressGroupId(Uuid::new_v4()),
+        &watchdog,
+    )?;
+    fs::remove_file(dict_dir.path.join(DEFERRED_ASSETS_FILE))?;
+    Ok(())
+}
+
+/// Refuse an import the disk doesn't have room for, keeping the free space
+/// the watchdog requires
+fn check_disk_space(
+    dicts_path: &PathBuf,
+    dict_filename: &NormalizedFilename,
+    archive: &mut ZipArchive<File>,
+    watchdog: &DiskWatchdog,
+    copy_assets: bool,
+) -> Result<()> {
+    let estimate = estimate_import(archive);
+    let needed = if copy_assets {
+        estimate.banks + estimate.static_assets
+    } else {
+        estimate.banks
+    };
+    let available = disk_space::available_bytes(dicts_path.as_std_path())
+        .context("Failed to check free disk space")?;
+    if needed.saturating_add(watchdog.min_free_bytes()) > available {
+        anyhow::bail!(
+            "Not enough disk space to import {}: about {} MB needed plus {} MB kept free, {} MB available",
+            dict_filename.0,
+            disk_space::megabytes(needed),
+            disk_space::megabytes(watchdog.min_free_bytes()),
+            disk_space::megabytes(available)
+        );
+    }
+    debug!(
+        needed_mb = disk_space::megabytes(needed),
+        available_mb = disk_space::megabytes(available),
+        "Disk space preflight passed"
+    );
     Ok(())
 }
 
@@ -177,6 +507,8 @@ async fn process_archive(
     archive_path: NormalizedPathBuf,
     progress_state: Arc<ProgressStateTable>,
     dict_dir: NormalizedPathBuf,
+    mode: ImportMode,
+    copy_assets: bool,
 ) -> Result<()> {
     let zip_file = std::fs::File::open(archive_path.path.as_path())?;
     let mut archive = ZipArchive::new(zip_file)?;
@@ -188,85 +520,229 @@ async fn process_archive(
         );
     } else {
         debug!("Dictionary filename: {}", archive_path.filename.0);
-        // Create directory and process index file
-        fs::create_dir(dict_dir.path.as_path())?;
-        info!("Created dictionary directory: {:?}", dict_dir.path);
-
-        let index_json_file_path = dict_dir.path.join("index.json");
-        {
-            let mut index_json_zip_file = archive.by_name("index.json")?;
-            let mut index_json_file = File::create(&index_json_file_path)?;
-            std::io::copy(&mut index_json_zip_file, &mut index_json_file)?;
-        }
-
-        let index: DictionaryIndex =
-            serde_json::from_str(&std::fs::read_to_string(index_json_file_path)?)?;
-
-        let group_id = ProgressGroupId(Uuid::new_v4());
-        process_schema::<TermBankV3>(
-            dict_dir.clone(),
+        let watchdog = DiskWatchdog::from_env(dicts_path.as_std_path());
+        check_disk_space(
+            &dicts_path,
+            &archive_path.filename,
             &mut archive,
-            progress_state.clone(),
-            &index,
-            group_id,
+            &watchdog,
+            copy_assets,
         )?;
-        process_schema::<TagBankV3>(
-            dict_dir.clone(),
-            &mut archive,
-            progress_state.clone(),
-            &index,
-            group_id,
-        )?;
-        process_schema::<TermMetaBankV3>(
-            dict_dir.clone(),
-            &mut archive,
-            progress_state.clone(),
-            &index,
-            group_id,
-        )?;
-        process_schema::<KanjiBankV3>(
-            dict_dir.clone(),
-            &mut archive,
-            progress_state.clone(),
-            &index,
-            group_id,
-        )?;
-        process_schema::<KanjiMetaBankV3>(
-            dict_dir.clone(),
+
+        if let Err(e) = import_contents(
+            copy_assets.then_some(&dicts_path),
+            &archive_path,
             &mut archive,
-            progress_state.clone(),
-            &index,
-            group_id,
-        )?;
+            progress_state,
+            &dict_dir,
+            &watchdog,
+            mode,
+        ) {
+            match import_failures::capture(&dicts_path, &archive_path.path, &e) {
+                Ok(report) => warn!(
+                    id = %report.id,
+                    archive = %report.archive,
+                    "Saved diagnostics for failed import"
+                ),
+                Err(capture_error) => {
+                    warn!(
+                        ?capture_error,
+                        "Failed to save diagnostics for failed import"
+                    )
+                }
+            }
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Rows skipped by a lenient import and schema violations found by a
+/// validating one, by schema name, saved with the dictionary
+pub const SCHEMA_ERRORS_FILE: &str = "schema_errors.json";
+
+pub type SchemaErrors = BTreeMap<String, SchemaErrorReport>;
+
+fn save_schema_errors(dict_dir: &PathBuf, title: &str, schema_errors: &SchemaErrors) -> Result<()> {
+    if schema_errors.is_empty() {
+        return Ok(());
+    }
+    for (schema, report) in schema_errors {
+        warn!(
+            %title,
+            %schema,
+            skipped = report.skipped,
+            violations = report.violations.len(),
+            "Bank files don't match their schema"
+        );
+    }
+    fs::write(
+        dict_dir.join(SCHEMA_ERRORS_FILE),
+        serde_json::to_vec_pretty(schema_errors)?,
+    )?;
+    Ok(())
+}
+
+/// Rows skipped and violations found when the dictionary in `dict_dir` was
+/// imported leniently or with validation
+pub fn load_schema_errors(dict_dir: &camino::Utf8Path) -> Result<SchemaErrors> {
+    let path = dict_dir.join(SCHEMA_ERRORS_FILE);
+    if !path.exists() {
+        return Ok(SchemaErrors::new());
+    }
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+/// Import the banks of `archive` into `dict_dir`, and its static assets into
+/// `assets_dicts_path` unless that is None, in which case they are deferred
+fn import_contents(
+    assets_dicts_path: Option<&PathBuf>,
+    archive_path: &NormalizedPathBuf,
+    archive: &mut ZipArchive<File>,
+    progress_state: Arc<ProgressStateTable>,
+    dict_dir: &NormalizedPathBuf,
+    watchdog: &DiskWatchdog,
+    mode: ImportMode,
+) -> Result<()> {
+    // Create directory and process index file
+    fs::create_dir(dict_dir.path.as_path())?;
+    info!("Created dictionary directory: {:?}", dict_dir.path);
+
+    // Saved as UTF-8 without a byte order mark, which is how it's read back
+    // when the dictionary is registered
+    let index_json = {
+        let mut contents = Vec::new();
+        archive.by_name("index.json")?.read_to_end(&mut contents)?;
+        encoding::decode_json(&contents)
+            .with_context(|| ArchiveJsonFile("index.json".to_string()))?
+            .into_owned()
+    };
+    fs::write(dict_dir.path.join("index.json"), &index_json)?;
+
+    let index: DictionaryIndex = serde_json::from_str(&index_json)
+        .with_context(|| ArchiveJsonFile("index.json".to_string()))?;
+
+    let group_id = ProgressGroupId(Uuid::new_v4());
+    let mut schema_errors = SchemaErrors::new();
+    let report = process_schema::<TermBankV3>(
+        dict_dir.clone(),
+        archive,
+        progress_state.clone(),
+        &index,
+        group_id,
+        watchdog,
+        mode,
+    )?;
+    if !report.is_empty() {
+        schema_errors.insert(TermBankV3::get_schema_name().to_string(), report);
+    }
+    let report = process_schema::<TagBankV3>(
+        dict_dir.clone(),
+        archive,
+        progress_state.clone(),
+        &index,
+        group_id,
+        watchdog,
+        mode,
+    )?;
+    if !report.is_empty() {
+        schema_errors.insert(TagBankV3::get_schema_name().to_string(), report);
+    }
+    let report = process_schema::<TermMetaBankV3>(
+        dict_dir.clone(),
+        archive,
+        progress_state.clone(),
+        &index,
+        group_id,
+        watchdog,
+        mode,
+    )?;
+    if !report.is_empty() {
+        schema_errors.insert(TermMetaBankV3::get_schema_name().to_string(), report);
+    }
+    let report = process_schema::<KanjiBankV3>(
+        dict_dir.clone(),
+        archive,
+        progress_state.clone(),
+        &index,
+        group_id,
+        watchdog,
+        mode,
+    )?;
+    if !report.is_empty() {
+        schema_errors.insert(KanjiBankV3::get_schema_name().to_string(), report);
+    }
+    let report = process_schema::<KanjiMetaBankV3>(
+        dict_dir.clone(),
+        archive,
+        progress_state.clone(),
+        &index,
+        group_id,
+        watchdog,
+        mode,
+    )?;
+    if !report.is_empty() {
+        schema_errors.insert(KanjiMetaBankV3::get_schema_name().to_string(), report);
+    }
+    save_schema_errors(&dict_dir.path, &index.title, &schema_errors)?;
+    if let Some(dicts_path) = assets_dicts_path {
         copy_static_assets(
             dicts_path.clone(),
             archive_path.filename.clone(),
-            &mut archive,
+            archive,
             progress_state.clone(),
             &index,
             group_id,
+            watchdog,
         )?;
+    } else {
+        info!(title = %index.title, "Deferring static assets for the loading budget");
+        fs::write(dict_dir.path.join(DEFERRED_ASSETS_FILE), b"")?;
+    }
+
+    match DictionaryStats::compute(&dict_dir.path) {
+        Ok(Some(stats)) => {
+            if let Err(e) = stats.save(&dict_dir.path) {
+                warn!(?e, title = %index.title, "Failed to save dictionary stats");
+            }
+        }
+        Ok(None) => {}
+        Err(e) => warn!(?e, title = %index.title, "Failed to compute dictionary stats"),
     }
 
+    match reverse_lookup::build_index(&dict_dir.path) {
+        Ok(Some(supported)) => {
+            debug!(title = %index.title, supported, "Built reverse lookup index")
+        }
+        Ok(None) => {}
+        Err(e) => warn!(?e, title = %index.title, "Failed to build reverse lookup index"),
+    }
     Ok(())
 }
 
+/// Import the `SchemaType` banks of `archive`. Returns the rows a lenient
+/// import skipped, or the violations a validating import found.
 fn process_schema<SchemaType: IsYomitanSchema>(
     dict_dir: NormalizedPathBuf,
     archive: &mut ZipArchive<File>,
     progress_state: Arc<ProgressStateTable>,
     index: &DictionaryIndex,
     group_id: ProgressGroupId,
-) -> Result<()>
+    watchdog: &DiskWatchdog,
+    mode: ImportMode,
+) -> Result<SchemaErrorReport>
 where
     SchemaType: Send + 'static,
 {
-    let grouped_json = GroupedJSON::new_from_archive::<SchemaType>(
+    watchdog.wait_for_space()?;
+    let (grouped_json, report) = GroupedJSON::new_from_archive::<SchemaType>(
         archive,
         progress_state.clone(),
         index.title.clone(),
         index.revision.clone(),
         group_id,
+        mode,
     )?;
     if grouped_json.0.len() > 0 {
         info!(
@@ -295,7 +771,7 @@ where
             ),
         }
     }
-    Ok(())
+    Ok(report)
 }
 
 fn copy_static_assets(
@@ -305,13 +781,16 @@ fn copy_static_assets(
     progress_state: Arc<ProgressStateTable>,
     index: &DictionaryIndex,
     group_id: ProgressGroupId,
+    watchdog: &DiskWatchdog,
 ) -> Result<()> {
-    // Any files that are not JSON should be copied over to the dictionaries-static/{dict_name} directory
-    let dict_static_dir = &dicts_path.join("static").join(&dict_filename.0);
+    // Any files that are not JSON are static assets, stored content-addressed
+    // with a manifest for the dictionary
+    let static_dir = dicts_path.join("static");
+    let store = AssetStore::new(static_dir.clone());
 
-    if dict_static_dir.exists() {
+    if store.has_manifest(&dict_filename.0) || static_dir.join(&dict_filename.0).exists() {
         info!(
-            "Dictionary static directory already exists, skipping: {}",
+            "Dictionary static assets already exist, skipping: {}",
             dict_filename.0
         );
     } else {
@@ -340,26 +819,97 @@ fn copy_static_assets(
 
             let task_id = progress_state.create_task(params, group_id)?;
 
+            let mut manifest = AssetManifest::default();
+            let mut duplicate_files = 0;
+            let mut saved_bytes = 0;
             for i in 0..archive.len() {
                 let mut file = archive.by_index(i)?;
-                let name = file.name().replace('\\', "/");
+                let name =
+                    encoding::decode_entry_name(file.name_raw(), file.name()).replace('\\', "/");
 
                 if name.ends_with(".json") || file.is_dir() {
                     continue;
                 }
 
-                let outpath = dict_static_dir.join(name);
-                if let Some(p) = outpath.parent() {
-                    fs::create_dir_all(p)?;
+                if manifest.files.len() % ASSETS_PER_SPACE_CHECK == 0 {
+                    watchdog.wait_for_space()?;
                 }
-                let mut outfile = File::create(&outpath)?;
-                std::io::copy(&mut file, &mut outfile)?;
-
-                trace!("Copied file to: {outpath}");
+                let (asset, existed) = store.put(&mut file)?;
+                if existed {
+                    duplicate_files += 1;
+                    saved_bytes += asset.size;
+                }
+                trace!("Stored {name} as {}", asset.hash);
+                manifest.files.insert(normalize_name(&name), asset);
                 progress_state.increment(&task_id, 1)?;
             }
-            info!("Copied {} static assets for {}", total_files, index.title);
+            store.save_manifest(&dict_filename.0, &manifest)?;
+            info!(
+                duplicate_files,
+                saved_bytes, "Stored {} static assets for {}", total_files, index.title
+            );
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    fn test_estimate_import() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("index.json", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(&[b' '; 100]).unwrap();
+        writer
+            .add_directory("img/", SimpleFileOptions::default())
+            .unwrap();
+        writer
+            .start_file("img/a.png", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(&[0; 50]).unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        assert_eq!(
+            estimate_import(&mut archive),
+            ImportEstimate {
+                banks: 100 * DB_SIZE_FACTOR,
+                static_assets: 50,
+            }
+        );
+    }
+
+    #[test]
+    fn test_archive_kind() {
+        let archive = |files: &[(&str, &str)]| {
+            let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+            for (name, contents) in files {
+                writer
+                    .start_file(*name, SimpleFileOptions::default())
+                    .unwrap();
+                writer.write_all(contents.as_bytes()).unwrap();
+            }
+            ZipArchive::new(writer.finish().unwrap()).unwrap()
+        };
+        let index = ("index.json", r#"{"title": "JPDB", "revision": "1"}"#);
+
+        let mut terms = archive(&[index, ("term_bank_1.json", "[]"), ("img/a.png", "")]);
+        assert_eq!(archive_kind(&mut terms), ArchiveKind::Term);
+        assert_eq!(archive_title(&mut terms).as_deref(), Some("JPDB"));
+        let mut freq = archive(&[index, ("term_meta_bank_1.json", r#"[["猫", "freq", 120]]"#)]);
+        assert_eq!(archive_kind(&mut freq), ArchiveKind::Frequency);
+        let mut pitch = archive(&[(
+            "term_meta_bank_1.json",
+            r#"[["猫", "pitch", {"reading": "ねこ", "pitches": [{"position": 1}]}]]"#,
+        )]);
+        assert_eq!(archive_kind(&mut pitch), ArchiveKind::Pitch);
+        assert_eq!(archive_title(&mut pitch), None);
+        let mut kanji = archive(&[("kanji_bank_1.json", "[]")]);
+        assert_eq!(archive_kind(&mut kanji), ArchiveKind::Kanji);
+        assert_eq!(archive_kind(&mut archive(&[index])), ArchiveKind::Unknown);
+    }
+}
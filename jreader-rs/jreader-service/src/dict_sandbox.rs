@@ -0,0 +1,129 @@
+//! Trying out an uploaded dictionary before importing it. The archive is
+//! imported into a temporary directory, sample terms are looked up there, and
+//! the directory is removed afterwards, so nothing is registered or kept.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf as PathBuf;
+use serde::Serialize;
+use yomitan_format::kv_store::utils::ProgressStateTable;
+use yomitan_format::kv_store::ImportMode;
+use yomitan_format::{NormalizedPathBuf, StripExtension};
+
+use crate::dict_db_scan_fs::{self, SchemaErrors};
+use crate::dict_stats::DictionaryStats;
+use crate::dictionaries::{DictionaryType, SandboxLookup, YomitanDictionaries};
+
+/// Sample terms evaluated per request
+pub const MAX_SANDBOX_TERMS: usize = 100;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SandboxReport {
+    pub title: String,
+    pub revision: String,
+    pub dictionary_type: DictionaryType,
+    /// Time taken to import the archive into the sandbox
+    pub import_ms: f64,
+    pub stats: Option<DictionaryStats>,
+    pub lookups: Vec<SandboxLookup>,
+    /// Bank rows skipped by a lenient import, or schema violations found by a
+    /// validating one, by schema
+    pub schema_errors: SchemaErrors,
+}
+
+/// Import the archive at `archive_path` into a temporary directory and look up
+/// `terms` in it
+pub async fn evaluate_archive(
+    archive_path: PathBuf,
+    terms: Vec<String>,
+    mode: ImportMode,
+) -> Result<SandboxReport> {
+    let sandbox = tempfile::TempDir::new().context("Failed to create sandbox directory")?;
+    let dicts_path = PathBuf::try_from(sandbox.path().to_path_buf())?;
+
+    // The archive is only read, so it's used in place rather than copied
+    let mut archive = NormalizedPathBuf::new(&archive_path, StripExtension::Only("zip"))?;
+    archive.path = archive_path;
+
+    let start = Instant::now();
+    let dict_dir = dict_db_scan_fs::import_archive_into(
+        dicts_path.clone(),
+        archive,
+        Arc::new(ProgressStateTable::new(None)?),
+        mode,
+    )
+    .await?;
+    let import_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    let report = tokio::task::spawn_blocking(move || -> Result<SandboxReport> {
+        let dictionaries = YomitanDictionaries::new(&dicts_path.join("db"))?;
+        let info = dictionaries
+            .get_dictionaries_info()
+            .into_iter()
+            .next()
+            .context("The archive doesn't contain a usable dictionary")?;
+        Ok(SandboxReport {
+            title: info.title,
+            revision: info.revision,
+            dictionary_type: info.dictionary_type,
+            import_ms,
+            stats: DictionaryStats::load(&dict_dir.path)?,
+            lookups: dictionaries.evaluate_terms(&terms),
+            schema_errors: dict_db_scan_fs::load_schema_errors(&dict_dir.path)?,
+        })
+    })
+    .await??;
+
+    // Dropping the directory handle removes the sandbox; the dictionary
+    // databases were closed when the lookup task finished
+    drop(sandbox);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    #[tokio::test]
+    async fn test_evaluate_archive() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let archive_path = PathBuf::try_from(temp_dir.path().join("test-dict.zip")).unwrap();
+        {
+            let mut zip = zip::ZipWriter::new(std::fs::File::create(&archive_path).unwrap());
+            zip.start_file("index.json", SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(br#"{"title": "Sandbox", "revision": "1", "format": 3}"#)
+                .unwrap();
+            zip.start_file("term_bank_1.json", SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(
+                r#"[["猫", "ねこ", "", "", 0, ["cat"], 1, ""], ["犬", "いぬ", "", "", "high", ["dog"], 2, ""]]"#
+                    .as_bytes(),
+            )
+            .unwrap();
+            zip.finish().unwrap();
+        }
+
+        let report = evaluate_archive(
+            archive_path.clone(),
+            vec!["猫".to_string(), "犬".to_string()],
+            ImportMode::Lenient,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.title, "Sandbox");
+        assert_eq!(report.dictionary_type, DictionaryType::Term);
+        assert_eq!(report.stats.unwrap().entries, 1);
+        assert!(report.lookups[0].found);
+        assert_eq!(report.lookups[0].entries[0]["reading"], "ねこ");
+        assert!(!report.lookups[1].found);
+        assert_eq!(report.schema_errors["Term Bank V3"].skipped, 1);
+        // The archive itself is left alone
+        assert!(archive_path.exists());
+    }
+}
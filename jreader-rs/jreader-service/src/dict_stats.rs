@@ -0,0 +1,266 @@
+//! Per-dictionary content statistics (definition lengths, media references,
+//! structured vs plain content), to help users choose between dictionaries.
+//!
+//! Stats are computed from the imported term bank and stored in a
+//! `dictionary_stats` table in `stats.db` next to the dictionary's other
+//! databases.
+
+use anyhow::Result;
+use camino::Utf8Path as Path;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::Serialize;
+
+const AUDIO_EXTENSIONS: [&str; 7] = [".mp3", ".ogg", ".opus", ".m4a", ".wav", ".aac", ".flac"];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DictionaryStats {
+    pub entries: u64,
+    pub definitions: u64,
+    /// Characters of text per definition, ignoring markup
+    pub average_definition_length: f64,
+    pub plain_definitions: u64,
+    pub structured_definitions: u64,
+    pub image_definitions: u64,
+    pub entries_with_images: u64,
+    pub entries_with_audio: u64,
+}
+
+#[derive(Default)]
+struct MediaRefs {
+    image: bool,
+    audio: bool,
+}
+
+fn is_audio_path(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    AUDIO_EXTENSIONS.iter().any(|ext| path.ends_with(ext))
+}
+
+/// Count text characters and note media references in structured content
+fn walk_structured_content(value: &serde_json::Value, text_len: &mut usize, media: &mut MediaRefs) {
+    match value {
+        serde_json::Value::String(s) => *text_len += s.chars().count(),
+        serde_json::Value::Array(items) => {
+            for item in items {
+                walk_structured_content(item, text_len, media);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            if map.get("tag").and_then(|t| t.as_str()) == Some("img") {
+                media.image = true;
+            }
+            for key in ["path", "href"] {
+                if map
+                    .get(key)
+                    .and_then(|p| p.as_str())
+                    .is_some_and(is_audio_path)
+                {
+                    media.audio = true;
+                }
+            }
+            if let Some(content) = map.get("content") {
+                walk_structured_content(content, text_len, media);
+            }
+        }
+        _ => {}
+    }
+}
+
+impl DictionaryStats {
+    /// Add a raw term bank entry: `[text, reading, tags, rules, score, definitions, ...]`
+    pub fn add_entry(&mut self, entry: &serde_json::Value) {
+        let Some(definitions) = entry.get(5).and_then(|d| d.as_array()) else {
+            return;
+        };
+        self.entries += 1;
+
+        let mut media = MediaRefs::default();
+        let mut total_len = self.average_definition_length * self.definitions as f64;
+        for definition in definitions {
+            let mut text_len = 0;
+            match definition {
+                serde_json::Value::String(s) => {
+                    self.plain_definitions += 1;
+                    text_len = s.chars().count();
+                }
+                serde_json::Value::Object(map) => match map.get("type").and_then(|t| t.as_str()) {
+                    Some("text") => {
+                        self.plain_definitions += 1;
+                        text_len = map
+                            .get("text")
+                            .and_then(|t| t.as_str())
+                            .map_or(0, |t| t.chars().count());
+                    }
+                    Some("image") => {
+                        self.image_definitions += 1;
+                        media.image = true;
+                    }
+                    Some("structured-content") => {
+                        self.structured_definitions += 1;
+                        if let Some(content) = map.get("content") {
+                            walk_structured_content(content, &mut text_len, &mut media);
+                        }
+                    }
+                    _ => continue,
+                },
+                // Deinflection definitions point at another entry
+                _ => continue,
+            }
+            self.definitions += 1;
+            total_len += text_len as f64;
+        }
+        if self.definitions > 0 {
+            self.average_definition_length = total_len / self.definitions as f64;
+        }
+        self.entries_with_images += media.image as u64;
+        self.entries_with_audio += media.audio as u64;
+    }
+
+    /// Compute stats from the imported term bank of the dictionary in `dict_dir`.
+    /// Returns `None` for dictionaries without a term bank.
+    pub fn compute(dict_dir: &Path) -> Result<Option<Self>> {
+        let db_path = dict_dir.join("term_bank_dict.db");
+        if !db_path.exists() {
+            return Ok(None);
+        }
+        let conn = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let mut stmt = conn.prepare("SELECT json FROM term_entry")?;
+        let mut rows = stmt.query([])?;
+        let mut stats = Self::default();
+        while let Some(row) = rows.next()? {
+            let json: String = row.get(0)?;
+            let entries: Vec<serde_json::Value> = serde_json::from_str(&json)?;
+            for entry in &entries {
+                stats.add_entry(entry);
+            }
+        }
+        Ok(Some(stats))
+    }
+
+    pub fn save(&self, dict_dir: &Path) -> Result<()> {
+        let conn = Connection::open(dict_dir.join("stats.db"))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS dictionary_stats (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                entries INTEGER NOT NULL,
+                definitions INTEGER NOT NULL,
+                average_definition_length REAL NOT NULL,
+                plain_definitions INTEGER NOT NULL,
+                structured_definitions INTEGER NOT NULL,
+                image_definitions INTEGER NOT NULL,
+                entries_with_images INTEGER NOT NULL,
+                entries_with_audio INTEGER NOT NULL
+            )",
+        )?;
+        conn.execute(
+            "INSERT OR REPLACE INTO dictionary_stats VALUES (1, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                self.entries as i64,
+                self.definitions as i64,
+                self.average_definition_length,
+                self.plain_definitions as i64,
+                self.structured_definitions as i64,
+                self.image_definitions as i64,
+                self.entries_with_images as i64,
+                self.entries_with_audio as i64,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn load(dict_dir: &Path) -> Result<Option<Self>> {
+        let db_path = dict_dir.join("stats.db");
+        if !db_path.exists() {
+            return Ok(None);
+        }
+        let conn = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let stats = conn
+            .query_row(
+                "SELECT entries, definitions, average_definition_length, plain_definitions,
+                        structured_definitions, image_definitions, entries_with_images,
+                        entries_with_audio
+                 FROM dictionary_stats WHERE id = 1",
+                [],
+                |row| {
+                    Ok(Self {
+                        entries: row.get::<_, i64>(0)? as u64,
+                        definitions: row.get::<_, i64>(1)? as u64,
+                        average_definition_length: row.get(2)?,
+                        plain_definitions: row.get::<_, i64>(3)? as u64,
+                        structured_definitions: row.get::<_, i64>(4)? as u64,
+                        image_definitions: row.get::<_, i64>(5)? as u64,
+                        entries_with_images: row.get::<_, i64>(6)? as u64,
+                        entries_with_audio: row.get::<_, i64>(7)? as u64,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(stats)
+    }
+
+    /// Stored stats, computing and storing them first for dictionaries imported
+    /// before stats existed
+    pub fn load_or_compute(dict_dir: &Path) -> Result<Option<Self>> {
+        if let Some(stats) = Self::load(dict_dir)? {
+            return Ok(Some(stats));
+        }
+        let stats = Self::compute(dict_dir)?;
+        if let Some(stats) = &stats {
+            stats.save(dict_dir)?;
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camino::Utf8PathBuf;
+    use serde_json::json;
+
+    #[test]
+    fn test_add_entries_and_store() {
+        let mut stats = DictionaryStats::default();
+        stats.add_entry(
+            &json!(["猫", "ねこ", "", "", 0, ["cat", {"type": "text", "text": "feline"}], 1, ""]),
+        );
+        stats.add_entry(&json!(["犬", "いぬ", "", "", 0, [
+            {"type": "structured-content", "content": [
+                {"tag": "span", "content": "dog"},
+                {"tag": "img", "path": "img/dog.png"},
+                {"tag": "a", "href": "audio/inu.mp3", "content": "▶"}
+            ]},
+            {"type": "image", "path": "img/dog2.png"}
+        ], 2, ""]));
+        stats.add_entry(&json!([
+            "食べた",
+            "たべた",
+            "",
+            "",
+            0,
+            [["食べる", ["past"]]],
+            3,
+            ""
+        ]));
+
+        assert_eq!(stats.entries, 3);
+        assert_eq!(stats.definitions, 4);
+        assert_eq!(stats.plain_definitions, 2);
+        assert_eq!(stats.structured_definitions, 1);
+        assert_eq!(stats.image_definitions, 1);
+        assert_eq!(stats.entries_with_images, 1);
+        assert_eq!(stats.entries_with_audio, 1);
+        // "cat" + "feline" + "dog▶" + image
+        assert_eq!(stats.average_definition_length, 13.0 / 4.0);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dict_dir = Utf8PathBuf::try_from(temp_dir.path().to_path_buf()).unwrap();
+        assert!(DictionaryStats::load(&dict_dir).unwrap().is_none());
+        assert!(DictionaryStats::load_or_compute(&dict_dir)
+            .unwrap()
+            .is_none());
+        stats.save(&dict_dir).unwrap();
+        assert_eq!(DictionaryStats::load(&dict_dir).unwrap(), Some(stats));
+    }
+}
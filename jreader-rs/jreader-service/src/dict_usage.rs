@@ -0,0 +1,428 @@
+use anyhow::Result;
+use deadpool_postgres::Pool;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{watch, RwLock};
+use tracing::{debug, info, warn};
+
+use crate::config::ServiceConfig;
+use crate::dictionaries::{DictionaryInfo, DictionaryResult, DictionaryType};
+
+// Registers of a `TermSketch` are indexed by this many bits of the term hash
+const SKETCH_INDEX_BITS: u32 = 12;
+const SKETCH_REGISTERS: usize = 1 << SKETCH_INDEX_BITS;
+
+/// HyperLogLog estimate of the number of distinct terms a dictionary served,
+/// in a fixed 4 KiB however many terms it sees. Sketches merge by taking the
+/// larger of each register, so the one in the database can take in the terms
+/// of every flush, across restarts, without counting a term twice.
+#[derive(Debug, Clone, PartialEq)]
+struct TermSketch {
+    registers: Vec<u8>,
+}
+
+impl Default for TermSketch {
+    fn default() -> Self {
+        Self {
+            registers: vec![0; SKETCH_REGISTERS],
+        }
+    }
+}
+
+impl TermSketch {
+    /// The sketch stored in the database, a new one if it is missing or invalid
+    fn from_bytes(bytes: Option<Vec<u8>>) -> Self {
+        match bytes {
+            Some(registers) if registers.len() == SKETCH_REGISTERS => Self { registers },
+            _ => Self::default(),
+        }
+    }
+
+    fn insert(&mut self, term: &str) {
+        // A hash that stays the same across builds, as sketches are persisted
+        let digest = Sha256::digest(term.as_bytes());
+        let hash = u64::from_be_bytes(digest[..8].try_into().unwrap());
+        let index = (hash >> (64 - SKETCH_INDEX_BITS)) as usize;
+        let rank =
+            ((hash << SKETCH_INDEX_BITS).leading_zeros() + 1).min(64 - SKETCH_INDEX_BITS + 1);
+        self.registers[index] = self.registers[index].max(rank as u8);
+    }
+
+    fn merge(&mut self, other: &TermSketch) {
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+    }
+
+    fn estimate(&self) -> u64 {
+        let m = SKETCH_REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        // Linear counting is more accurate while few registers are set
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+/// In-memory hit/miss counters for a single term dictionary, keyed elsewhere by
+/// `"title#revision"` (the same key user preferences use).
+#[derive(Debug, Default)]
+struct DictionaryUsageCounters {
+    hits: u64,
+    misses: u64,
+    unique_terms: TermSketch,
+    last_hit_at: Option<chrono::DateTime<chrono::Utc>>,
+    // Deltas accumulated since the last successful flush to Supabase
+    pending_hits: u64,
+    pending_misses: u64,
+}
+
+// Counter deltas for one dictionary, taken out of memory during a flush
+struct PendingUsage {
+    dictionary: String,
+    hits: i64,
+    misses: i64,
+    unique_terms: TermSketch,
+    last_hit_at: Option<std::time::SystemTime>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DictionaryUsage {
+    pub title: String,
+    pub revision: String,
+    pub hits: u64,
+    pub misses: u64,
+    pub unique_terms: u64,
+    pub last_hit_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Tracks which term dictionaries actually produce results for lookups.
+///
+/// Counters are kept in memory and periodically flushed to the
+/// `"public"."Dictionary Usage"` table when a database pool is available,
+/// along with the sketch of the distinct terms in `"unique_terms_sketch"`.
+pub struct DictionaryUsageTracker {
+    pool: Option<Arc<Pool>>,
+    counters: RwLock<HashMap<String, DictionaryUsageCounters>>,
+}
+
+fn dictionary_key(title: &str, revision: &str) -> String {
+    format!("{title}#{revision}")
+}
+
+impl DictionaryUsageTracker {
+    pub fn new(pool: Option<Arc<Pool>>) -> Self {
+        Self {
+            pool,
+            counters: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Record the outcome of a single lookup. Every dictionary in `queried` that
+    /// did not contribute to `results` is counted as a miss.
+    pub async fn record_lookup(&self, queried: &[DictionaryInfo], results: &[DictionaryResult]) {
+        let now = chrono::Utc::now();
+        let mut counters = self.counters.write().await;
+
+        for dict in queried {
+            let entry = counters
+                .entry(dictionary_key(&dict.title, &dict.revision))
+                .or_default();
+            let result = results.iter().find(|r| {
+                r.title == dict.title && r.revision == dict.revision && !r.entries.is_empty()
+            });
+
+            match result {
+                Some(result) => {
+                    entry.hits += 1;
+                    entry.pending_hits += 1;
+                    entry.last_hit_at = Some(now);
+                    for term_entry in &result.entries {
+                        entry.unique_terms.insert(&term_entry.text);
+                    }
+                }
+                None => {
+                    entry.misses += 1;
+                    entry.pending_misses += 1;
+                }
+            }
+        }
+    }
+
+    /// Snapshot of usage for every known term dictionary, including ones that
+    /// have never been queried. Sorted by hit count, most used first.
+    pub async fn get_usage(&self, dictionaries: &[DictionaryInfo]) -> Vec<DictionaryUsage> {
+        let counters = self.counters.read().await;
+        let mut usage: Vec<DictionaryUsage> = dictionaries
+            .iter()
+            .filter(|d| d.dictionary_type == DictionaryType::Term)
+            .map(|d| {
+                let counter = counters.get(&dictionary_key(&d.title, &d.revision));
+                DictionaryUsage {
+                    title: d.title.clone(),
+                    revision: d.revision.clone(),
+                    hits: counter.map(|c| c.hits).unwrap_or(0),
+                    misses: counter.map(|c| c.misses).unwrap_or(0),
+                    unique_terms: counter.map(|c| c.unique_terms.estimate()).unwrap_or(0),
+                    last_hit_at: counter.and_then(|c| c.last_hit_at),
+                }
+            })
+            .collect();
+        usage.sort_by(|a, b| b.hits.cmp(&a.hits).then_with(|| a.title.cmp(&b.title)));
+        usage
+    }
+
+    /// Write pending counter deltas to Supabase. Deltas are restored if the
+    /// write fails so that nothing is lost before the next flush.
+    pub async fn flush(&self) -> Result<usize> {
+        let pool = self
+            .pool
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Database not available"))?;
+
+        let pending: Vec<PendingUsage> = {
+            let mut counters = self.counters.write().await;
+            counters
+                .iter_mut()
+                .filter(|(_, c)| c.pending_hits > 0 || c.pending_misses > 0)
+                .map(|(key, c)| {
+                    let row = PendingUsage {
+                        dictionary: key.clone(),
+                        hits: c.pending_hits as i64,
+                        misses: c.pending_misses as i64,
+                        unique_terms: c.unique_terms.clone(),
+                        last_hit_at: c.last_hit_at.map(|t| t.into()),
+                    };
+                    c.pending_hits = 0;
+                    c.pending_misses = 0;
+                    row
+                })
+                .collect()
+        };
+
+        if pending.is_empty() {
+            return Ok(0);
+        }
+
+        let result = self.write_pending(pool, &pending).await;
+        if result.is_err() {
+            let mut counters = self.counters.write().await;
+            for row in &pending {
+                let entry = counters.entry(row.dictionary.clone()).or_default();
+                entry.pending_hits += row.hits as u64;
+                entry.pending_misses += row.misses as u64;
+            }
+        }
+        result.map(|_| pending.len())
+    }
+
+    async fn write_pending(&self, pool: &Pool, pending: &[PendingUsage]) -> Result<()> {
+        let mut client = pool.get().await?;
+        let transaction = client.transaction().await?;
+        let select_sketch = transaction
+            .prepare(
+                r#"SELECT "unique_terms_sketch" FROM "public"."Dictionary Usage"
+                   WHERE "dictionary" = $1 FOR UPDATE"#,
+            )
+            .await?;
+        let statement = transaction
+            .prepare(
+                r#"INSERT INTO "public"."Dictionary Usage"
+                   ("dictionary", "hits", "misses", "unique_terms", "unique_terms_sketch", "last_hit_at")
+                   VALUES ($1, $2, $3, $4, $5, $6)
+                   ON CONFLICT ("dictionary") DO UPDATE SET
+                   "hits" = "Dictionary Usage"."hits" + EXCLUDED."hits",
+                   "misses" = "Dictionary Usage"."misses" + EXCLUDED."misses",
+                   "unique_terms" = EXCLUDED."unique_terms",
+                   "unique_terms_sketch" = EXCLUDED."unique_terms_sketch",
+                   "last_hit_at" = GREATEST("Dictionary Usage"."last_hit_at", EXCLUDED."last_hit_at")"#,
+            )
+            .await?;
+
+        for row in pending {
+            let mut sketch = TermSketch::from_bytes(
+                transaction
+                    .query_opt(&select_sketch, &[&row.dictionary])
+                    .await?
+                    .and_then(|r| r.get(0)),
+            );
+            sketch.merge(&row.unique_terms);
+            transaction
+                .execute(
+                    &statement,
+                    &[
+                        &row.dictionary,
+                        &row.hits,
+                        &row.misses,
+                        &(sketch.estimate() as i64),
+                        &sketch.registers,
+                        &row.last_hit_at,
+                    ],
+                )
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Spawn a background task that flushes counters at the configured
+    /// interval, picking up interval changes from config reloads.
+    /// Does nothing when there is no database to flush to.
+    pub fn spawn_flush_task(self: &Arc<Self>, mut config: watch::Receiver<Arc<ServiceConfig>>) {
+        if self.pool.is_none() {
+            warn!("⚠️ No database pool, dictionary usage stats will only be kept in memory");
+            return;
+        }
+
+        let tracker = self.clone();
+        tokio::spawn(async move {
+            let mut interval = config.borrow_and_update().dict_usage_flush_interval();
+            let mut ticker = tokio::time::interval(interval);
+            // The first tick completes immediately
+            ticker.tick().await;
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    changed = config.changed() => {
+                        if changed.is_err() {
+                            break;
+                        }
+                        let new_interval = config.borrow_and_update().dict_usage_flush_interval();
+                        if new_interval != interval {
+                            info!(?new_interval, "🔄 Dictionary usage flush interval changed");
+                            interval = new_interval;
+                            ticker = tokio::time::interval_at(
+                                tokio::time::Instant::now() + interval,
+                                interval,
+                            );
+                        }
+                        continue;
+                    }
+                }
+                match tracker.flush().await {
+                    Ok(0) => debug!("No dictionary usage to flush"),
+                    Ok(count) => info!(count, "✅ Flushed dictionary usage stats"),
+                    Err(e) => warn!("⚠️ Failed to flush dictionary usage stats: {e}"),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yomitan_format::json_schema::term_bank_v3::TermEntry;
+
+    fn term_dict(title: &str) -> DictionaryInfo {
+        DictionaryInfo {
+            title: title.to_string(),
+            revision: "1".to_string(),
+            dictionary_type: DictionaryType::Term,
+        }
+    }
+
+    fn result_for(title: &str, terms: &[&str]) -> DictionaryResult {
+        DictionaryResult {
+            title: title.to_string(),
+            revision: "1".to_string(),
+            origin: String::new(),
+            entries: terms
+                .iter()
+                .map(|t| TermEntry {
+                    text: t.to_string(),
+                    reading: String::new(),
+                    tags: None,
+                    rule_identifiers: String::new(),
+                    score: 0.0,
+                    definitions: vec![],
+                    sequence_number: 0,
+                    term_tags: None,
+                })
+                .collect(),
+            heuristic_entries: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_lookup_counts_hits_and_misses() {
+        let tracker = DictionaryUsageTracker::new(None);
+        let queried = vec![term_dict("JMdict"), term_dict("Daijirin")];
+
+        tracker
+            .record_lookup(&queried, &[result_for("JMdict", &["食べる"])])
+            .await;
+        tracker
+            .record_lookup(&queried, &[result_for("JMdict", &["食べる", "食う"])])
+            .await;
+
+        let usage = tracker
+            .get_usage(&[
+                term_dict("JMdict"),
+                term_dict("Daijirin"),
+                term_dict("Unused"),
+            ])
+            .await;
+
+        assert_eq!(usage.len(), 3);
+        assert_eq!(usage[0].title, "JMdict");
+        assert_eq!(usage[0].hits, 2);
+        assert_eq!(usage[0].misses, 0);
+        assert_eq!(usage[0].unique_terms, 2);
+        assert!(usage[0].last_hit_at.is_some());
+
+        let daijirin = usage.iter().find(|u| u.title == "Daijirin").unwrap();
+        assert_eq!(daijirin.hits, 0);
+        assert_eq!(daijirin.misses, 2);
+        assert!(daijirin.last_hit_at.is_none());
+
+        let unused = usage.iter().find(|u| u.title == "Unused").unwrap();
+        assert_eq!(unused.hits + unused.misses, 0);
+    }
+
+    #[test]
+    fn test_term_sketch_estimate_and_merge() {
+        let mut sketch = TermSketch::default();
+        assert_eq!(sketch.estimate(), 0);
+        for _ in 0..3 {
+            sketch.insert("食べる");
+        }
+        assert_eq!(sketch.estimate(), 1);
+
+        let mut first = TermSketch::default();
+        let mut second = TermSketch::default();
+        for i in 0..20_000 {
+            first.insert(&format!("term{i}"));
+            second.insert(&format!("term{}", i + 10_000));
+        }
+        let estimate = first.estimate() as f64;
+        assert!((estimate / 20_000.0 - 1.0).abs() < 0.05, "{estimate}");
+
+        // Terms both saw are counted once, however often sketches are merged
+        first.merge(&second);
+        first.merge(&second);
+        let estimate = first.estimate() as f64;
+        assert!((estimate / 30_000.0 - 1.0).abs() < 0.05, "{estimate}");
+
+        assert_eq!(TermSketch::from_bytes(Some(first.registers.clone())), first);
+        assert_eq!(
+            TermSketch::from_bytes(Some(vec![1, 2])),
+            TermSketch::default()
+        );
+        assert_eq!(TermSketch::from_bytes(None), TermSketch::default());
+    }
+
+    #[tokio::test]
+    async fn test_flush_without_database_fails() {
+        let tracker = DictionaryUsageTracker::new(None);
+        assert!(tracker.flush().await.is_err());
+    }
+}
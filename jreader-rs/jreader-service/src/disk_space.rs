@@ -0,0 +1,147 @@
+//! Free disk space checks, so large writes under `DICTS_PATH` can be refused
+//! up front instead of failing part way.
+
+use std::ffi::CString;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+/// `IMPORT_MIN_FREE_MB` when unset
+const DEFAULT_IMPORT_MIN_FREE_MB: u64 = 512;
+/// `IMPORT_PAUSE_MAX_SECONDS` when unset
+const DEFAULT_IMPORT_PAUSE_MAX_SECONDS: u64 = 1800;
+/// How often a paused import checks the free space again
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Bytes available to unprivileged processes on the filesystem holding `path`
+// The statvfs field types differ between platforms
+#[allow(clippy::unnecessary_cast)]
+pub fn available_bytes(path: &Path) -> io::Result<u64> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `c_path` is NUL-terminated and `stat` is only read once
+    // statvfs has filled it in
+    let stat = unsafe {
+        if libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stat.assume_init()
+    };
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Keeps imports from filling the disk: between steps, an import waits while
+/// free space is under a threshold, and gives up if it stays there too long
+pub struct DiskWatchdog {
+    path: PathBuf,
+    min_free_bytes: u64,
+    max_pause: Duration,
+}
+
+impl DiskWatchdog {
+    pub fn new(path: impl Into<PathBuf>, min_free_bytes: u64, max_pause: Duration) -> Self {
+        Self {
+            path: path.into(),
+            min_free_bytes,
+            max_pause,
+        }
+    }
+
+    /// Watchdog for imports under `path`, with the threshold from
+    /// `IMPORT_MIN_FREE_MB` and the longest pause from `IMPORT_PAUSE_MAX_SECONDS`
+    pub fn from_env(path: impl Into<PathBuf>) -> Self {
+        let env_or = |key: &str, default: u64| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(default)
+        };
+        Self::new(
+            path,
+            env_or("IMPORT_MIN_FREE_MB", DEFAULT_IMPORT_MIN_FREE_MB) * 1024 * 1024,
+            Duration::from_secs(env_or(
+                "IMPORT_PAUSE_MAX_SECONDS",
+                DEFAULT_IMPORT_PAUSE_MAX_SECONDS,
+            )),
+        )
+    }
+
+    /// Free space the watchdog keeps, which preflight checks add to estimates
+    pub fn min_free_bytes(&self) -> u64 {
+        self.min_free_bytes
+    }
+
+    /// Block while free space is under the threshold. Errors once the pause
+    /// has lasted longer than allowed.
+    pub fn wait_for_space(&self) -> io::Result<()> {
+        let started = Instant::now();
+        let mut paused = false;
+        loop {
+            let available = available_bytes(&self.path)?;
+            if available >= self.min_free_bytes {
+                if paused {
+                    info!(
+                        free_mb = megabytes(available),
+                        "▶️ Free disk space recovered, resuming import"
+                    );
+                }
+                return Ok(());
+            }
+            if started.elapsed() >= self.max_pause {
+                return Err(io::Error::new(
+                    io::ErrorKind::StorageFull,
+                    format!(
+                        "Free disk space stayed under {} MB ({} MB free), giving up on the import",
+                        megabytes(self.min_free_bytes),
+                        megabytes(available)
+                    ),
+                ));
+            }
+            if !paused {
+                warn!(
+                    free_mb = megabytes(available),
+                    min_free_mb = megabytes(self.min_free_bytes),
+                    "⏸️ Low disk space, pausing import until space is freed"
+                );
+                paused = true;
+            }
+            std::thread::sleep(PAUSE_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Megabytes, rounded up, for error messages
+pub fn megabytes(bytes: u64) -> u64 {
+    bytes.div_ceil(1024 * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_available_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(available_bytes(dir.path()).unwrap() > 0);
+        assert!(available_bytes(&dir.path().join("missing")).is_err());
+        assert_eq!(megabytes(1), 1);
+        assert_eq!(megabytes(3 * 1024 * 1024), 3);
+    }
+
+    #[test]
+    fn test_watchdog() {
+        let dir = tempfile::tempdir().unwrap();
+        DiskWatchdog::new(dir.path(), 0, Duration::ZERO)
+            .wait_for_space()
+            .unwrap();
+        let full = DiskWatchdog::new(dir.path(), u64::MAX, Duration::ZERO)
+            .wait_for_space()
+            .unwrap_err();
+        assert_eq!(full.kind(), io::ErrorKind::StorageFull);
+    }
+}
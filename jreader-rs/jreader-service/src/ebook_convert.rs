@@ -0,0 +1,126 @@
+//! Conversion of uploaded MOBI and AZW3 books to EPUB, so that they can go
+//! through the same pipeline as EPUB uploads.
+//!
+//! The converter is picked by `EBOOK_CONVERTER`: Calibre's `ebook-convert`
+//! (`EBOOK_CONVERT_BIN`), which keeps the styling of the book, or the native
+//! parser in [`crate::mobi`], which needs nothing installed.
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::path::Path;
+use std::time::Duration;
+use tracing::info;
+
+use crate::config::ServiceConfig;
+use crate::mobi::{self, MobiFormat};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EbookConverterKind {
+    Native,
+    EbookConvert,
+}
+
+impl std::str::FromStr for EbookConverterKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "native" => Ok(EbookConverterKind::Native),
+            "ebook-convert" => Ok(EbookConverterKind::EbookConvert),
+            _ => bail!("Unknown ebook converter: {s}"),
+        }
+    }
+}
+
+#[async_trait]
+pub trait EbookConverter: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Convert the book at `input` to an EPUB at `output`
+    async fn convert(&self, input: &Path, format: MobiFormat, output: &Path) -> Result<()>;
+}
+
+/// Calibre's `ebook-convert`, killed if it runs for longer than `timeout`
+pub struct EbookConvertCommand {
+    pub bin: String,
+    pub timeout: Duration,
+}
+
+#[async_trait]
+impl EbookConverter for EbookConvertCommand {
+    fn name(&self) -> &'static str {
+        "ebook-convert"
+    }
+
+    async fn convert(&self, input: &Path, _format: MobiFormat, output: &Path) -> Result<()> {
+        let child = tokio::process::Command::new(&self.bin)
+            .arg(input)
+            .arg(output)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.bin))?;
+        // Dropping the child on timeout kills it
+        let result = tokio::time::timeout(self.timeout, child.wait_with_output())
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "Conversion timed out after {} seconds",
+                    self.timeout.as_secs()
+                )
+            })??;
+        if !result.status.success() {
+            let stderr = String::from_utf8_lossy(&result.stderr);
+            let last_lines: Vec<&str> = stderr.lines().rev().take(5).collect();
+            bail!(
+                "{} failed ({}): {}",
+                self.bin,
+                result.status,
+                last_lines.into_iter().rev().collect::<Vec<_>>().join("\n")
+            );
+        }
+        if !output.is_file() {
+            bail!("{} didn't write an EPUB", self.bin);
+        }
+        Ok(())
+    }
+}
+
+pub struct NativeConverter;
+
+#[async_trait]
+impl EbookConverter for NativeConverter {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    async fn convert(&self, input: &Path, _format: MobiFormat, output: &Path) -> Result<()> {
+        let (input, output) = (input.to_path_buf(), output.to_path_buf());
+        tokio::task::spawn_blocking(move || {
+            let data = std::fs::read(&input)
+                .with_context(|| format!("Failed to read {}", input.display()))?;
+            let book = mobi::to_epub(&data)?;
+            let file = std::fs::File::create(&output)
+                .with_context(|| format!("Failed to create {}", output.display()))?;
+            book.write_epub(std::io::BufWriter::new(file))?;
+            info!(title = book.title(), "📚 Converted book natively");
+            Ok(())
+        })
+        .await?
+    }
+}
+
+/// The converter configured in `config`
+pub fn converter(config: &ServiceConfig) -> Box<dyn EbookConverter> {
+    match config.ebook_converter {
+        EbookConverterKind::Native => Box::new(NativeConverter),
+        EbookConverterKind::EbookConvert => Box::new(EbookConvertCommand {
+            bin: std::env::var("EBOOK_CONVERT_BIN").unwrap_or_else(|_| "ebook-convert".into()),
+            timeout: Duration::from_secs(config.ebook_convert_timeout_seconds),
+        }),
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::Result;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+pub const MAX_NOTE_LENGTH: usize = 2000;
+
+/// A user's note on a dictionary entry, identified by dictionary title, term
+/// and reading. Shared notes are shown to every user of the instance.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryNote {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub user_id: String,
+    pub dictionary: String,
+    pub term: String,
+    pub reading: String,
+    pub note: String,
+    pub is_shared: bool,
+    /// Whether the note belongs to the user it is returned to
+    pub is_own: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewEntryNote {
+    pub dictionary: String,
+    pub term: String,
+    #[serde(default)]
+    pub reading: String,
+    pub note: String,
+    #[serde(default)]
+    pub is_shared: bool,
+}
+
+/// The entry a note is attached to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EntryKey {
+    pub dictionary: String,
+    pub term: String,
+    pub reading: String,
+}
+
+/// Trim and check the text of a note, returning the cleaned up text
+pub fn validate_note(note: &str) -> Result<String, String> {
+    let note = note.trim();
+    if note.is_empty() {
+        return Err("Note must not be empty".to_string());
+    }
+    if note.chars().count() > MAX_NOTE_LENGTH {
+        return Err(format!(
+            "Note must be at most {} characters",
+            MAX_NOTE_LENGTH
+        ));
+    }
+    Ok(note.to_string())
+}
+
+const NOTE_COLUMNS: &str = r#""id", "user_id", "dictionary", "term", "reading", "note", "is_shared", "created_at", "updated_at""#;
+
+fn row_to_note(row: &Row, viewer_id: &str) -> EntryNote {
+    let user_id: String = row.get("user_id");
+    EntryNote {
+        id: row.get("id"),
+        is_own: user_id == viewer_id,
+        user_id,
+        dictionary: row.get("dictionary"),
+        term: row.get("term"),
+        reading: row.get("reading"),
+        note: row.get("note"),
+        is_shared: row.get("is_shared"),
+        created_at: row.get::<_, SystemTime>("created_at").into(),
+        updated_at: row.get::<_, SystemTime>("updated_at").into(),
+    }
+}
+
+/// Entry notes stored in the `"Entry Notes"` table.
+///
+/// Notes can only be changed by their author; methods taking a `user_id`
+/// return `None`/`false` for notes that don't exist or belong to someone else.
+pub struct EntryNotesSupabase {
+    pool: Option<Arc<Pool>>,
+}
+
+impl EntryNotesSupabase {
+    pub fn new(pool: Option<Arc<Pool>>) -> Self {
+        Self { pool }
+    }
+
+    fn pool(&self) -> Result<&Pool> {
+        self.pool
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Database not available"))
+    }
+
+    pub async fn add_note(&self, user_id: &str, note: &NewEntryNote) -> Result<EntryNote> {
+        let client = self.pool()?.get().await?;
+        let id = Uuid::new_v4();
+        let now = SystemTime::now();
+        client
+            .execute(
+                r#"INSERT INTO "public"."Entry Notes"
+                   ("id", "user_id", "dictionary", "term", "reading", "note", "is_shared", "created_at", "updated_at")
+                   VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8)"#,
+                &[
+                    &id,
+                    &user_id,
+                    &note.dictionary,
+                    &note.term,
+                    &note.reading,
+                    &note.note,
+                    &note.is_shared,
+                    &now,
+                ],
+            )
+            .await?;
+
+        Ok(EntryNote {
+            id,
+            user_id: user_id.to_string(),
+            dictionary: note.dictionary.clone(),
+            term: note.term.clone(),
+            reading: note.reading.clone(),
+            note: note.note.clone(),
+            is_shared: note.is_shared,
+            is_own: true,
+            created_at: now.into(),
+            updated_at: now.into(),
+        })
+    }
+
+    /// The user's own notes, most recently updated first
+    pub async fn get_user_notes(&self, user_id: &str) -> Result<Vec<EntryNote>> {
+        let client = self.pool()?.get().await?;
+        let rows = client
+            .query(
+                &format!(
+                    r#"SELECT {NOTE_COLUMNS} FROM "public"."Entry Notes"
+                       WHERE "user_id" = $1 ORDER BY "updated_at" DESC"#
+                ),
+                &[&user_id],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row_to_note(row, user_id)).collect())
+    }
+
+    /// Notes on any of `entries` the user can see: their own and the shared
+    /// notes of others, own notes first, then oldest first
+    pub async fn notes_for_entries(
+        &self,
+        user_id: &str,
+        entries: &[EntryKey],
+    ) -> Result<Vec<EntryNote>> {
+        if entries.is_empty() {
+            return Ok(Vec::new());
+        }
+        let client = self.pool()?.get().await?;
+        let dictionaries: Vec<&str> = entries.iter().map(|e| e.dictionary.as_str()).collect();
+        let terms: Vec<&str> = entries.iter().map(|e| e.term.as_str()).collect();
+        let readings: Vec<&str> = entries.iter().map(|e| e.reading.as_str()).collect();
+        let rows = client
+            .query(
+                &format!(
+                    r#"SELECT {NOTE_COLUMNS} FROM "public"."Entry Notes"
+                       WHERE ("user_id" = $1 OR "is_shared")
+                         AND ("dictionary", "term", "reading") IN
+                             (SELECT * FROM unnest($2::text[], $3::text[], $4::text[]))
+                       ORDER BY ("user_id" = $1) DESC, "created_at""#
+                ),
+                &[&user_id, &dictionaries, &terms, &readings],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row_to_note(row, user_id)).collect())
+    }
+
+    /// Change the text or sharing of a note, returning the updated note
+    pub async fn update_note(
+        &self,
+        user_id: &str,
+        note_id: Uuid,
+        note: Option<&str>,
+        is_shared: Option<bool>,
+    ) -> Result<Option<EntryNote>> {
+        let client = self.pool()?.get().await?;
+        let row = client
+            .query_opt(
+                &format!(
+                    r#"UPDATE "public"."Entry Notes"
+                       SET "note" = COALESCE($3, "note"),
+                           "is_shared" = COALESCE($4, "is_shared"),
+                           "updated_at" = $5
+                       WHERE "id" = $1 AND "user_id" = $2
+                       RETURNING {NOTE_COLUMNS}"#
+                ),
+                &[&note_id, &user_id, &note, &is_shared, &SystemTime::now()],
+            )
+            .await?;
+        Ok(row.map(|row| row_to_note(&row, user_id)))
+    }
+
+    pub async fn delete_note(&self, user_id: &str, note_id: Uuid) -> Result<bool> {
+        let client = self.pool()?.get().await?;
+        let deleted = client
+            .execute(
+                r#"DELETE FROM "public"."Entry Notes" WHERE "id" = $1 AND "user_id" = $2"#,
+                &[&note_id, &user_id],
+            )
+            .await?;
+        Ok(deleted > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_note() {
+        assert_eq!(validate_note("  主人公の口癖 ").unwrap(), "主人公の口癖");
+        assert!(validate_note(" \n ").is_err());
+        assert!(validate_note(&"語".repeat(MAX_NOTE_LENGTH)).is_ok());
+        assert!(validate_note(&"語".repeat(MAX_NOTE_LENGTH + 1)).is_err());
+    }
+}
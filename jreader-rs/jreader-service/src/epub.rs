@@ -0,0 +1,1262 @@
+//! Native reading and writing of EPUB packages.
+//!
+//! `EpubSource` parses the package document and table of contents of a book
+//! extracted into a directory (as books in the library are). `EpubBuilder`
+//! writes a new EPUB 3 package, with an NCX for EPUB 2 reading systems, either
+//! zipped or extracted.
+
+use std::collections::HashMap;
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use lazy_static::lazy_static;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use regex::{Captures, Regex};
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+
+const CONTAINER_PATH: &str = "META-INF/container.xml";
+const CONTENT_DIR: &str = "OEBPS";
+const PACKAGE_HREF: &str = "content.opf";
+const NAV_HREF: &str = "nav.xhtml";
+const NCX_HREF: &str = "toc.ncx";
+const NCX_MEDIA_TYPE: &str = "application/x-dtbncx+xml";
+
+lazy_static! {
+    static ref MARKUP_REFERENCE: Regex =
+        Regex::new(r#"(\b(?:href|src|xlink:href|poster)\s*=\s*)(?:"([^"]*)"|'([^']*)')"#).unwrap();
+    static ref MARKUP_TAG: Regex =
+        Regex::new(r"(?s)<!--.*?-->|<!\[CDATA\[.*?\]\]>|<[^>]*>").unwrap();
+    static ref CSS_REFERENCE: Regex =
+        Regex::new(r#"url\(\s*(?:"([^"]*)"|'([^']*)'|([^)'"\s]*))\s*\)"#).unwrap();
+}
+
+#[derive(Debug, Clone)]
+pub struct ManifestItem {
+    pub id: String,
+    /// Decoded path relative to the package document
+    pub href: String,
+    pub media_type: String,
+    pub properties: Option<String>,
+}
+
+impl ManifestItem {
+    fn has_property(&self, property: &str) -> bool {
+        self.properties
+            .as_deref()
+            .is_some_and(|p| p.split_whitespace().any(|p| p == property))
+    }
+
+    pub fn is_document(&self) -> bool {
+        matches!(
+            self.media_type.as_str(),
+            "application/xhtml+xml" | "text/html"
+        )
+    }
+}
+
+/// An entry of a table of contents
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NavPoint {
+    pub label: String,
+    /// Decoded path relative to the package document, with an optional
+    /// `#fragment`
+    pub href: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<NavPoint>,
+}
+
+/// An EPUB extracted into a directory
+#[derive(Debug)]
+pub struct EpubSource {
+    root: PathBuf,
+    /// Directory of the package document within `root`, "" for the root
+    package_dir: String,
+    pub title: String,
+    pub creators: Vec<String>,
+    pub language: Option<String>,
+    pub page_progression_direction: Option<String>,
+    pub manifest: Vec<ManifestItem>,
+    /// Ids of the manifest items in reading order
+    pub spine: Vec<String>,
+    pub cover_id: Option<String>,
+    pub toc: Vec<NavPoint>,
+}
+
+impl EpubSource {
+    pub fn open_dir(root: &Path) -> Result<Self> {
+        let package_path = match std::fs::read(root.join(CONTAINER_PATH)) {
+            Ok(container) => rootfile_path(&container)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => find_package(root)?,
+            Err(e) => return Err(e.into()),
+        };
+        let package = std::fs::read(root.join(&package_path))
+            .with_context(|| format!("Failed to read package document {package_path}"))?;
+        let mut source = parse_package(&package)
+            .with_context(|| format!("Failed to parse package document {package_path}"))?;
+        source.root = root.to_path_buf();
+        source.package_dir = parent_dir(&package_path).to_string();
+
+        let nav = source.manifest.iter().find(|item| item.has_property("nav"));
+        let ncx = source
+            .manifest
+            .iter()
+            .find(|item| item.media_type == NCX_MEDIA_TYPE);
+        source.toc = match (nav, ncx) {
+            (Some(nav), _) => {
+                let html = String::from_utf8_lossy(&source.read(&nav.href)?).to_string();
+                parse_nav(&html, parent_dir(&nav.href))
+            }
+            (None, Some(ncx)) => parse_ncx(&source.read(&ncx.href)?, parent_dir(&ncx.href))?,
+            (None, None) => Vec::new(),
+        };
+        Ok(source)
+    }
+
+    /// Contents of the file at `href`, relative to the package document
+    pub fn read(&self, href: &str) -> Result<Vec<u8>> {
+        let path = join_path(&self.package_dir, href);
+        std::fs::read(self.root.join(&path)).with_context(|| format!("Failed to read {path}"))
+    }
+
+    pub fn item(&self, id: &str) -> Option<&ManifestItem> {
+        self.manifest.iter().find(|item| item.id == id)
+    }
+}
+
+/// Path of the package document named by `META-INF/container.xml`
+fn rootfile_path(container: &[u8]) -> Result<String> {
+    let mut reader = Reader::from_bytes(container);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref e) | Event::Empty(ref e) if e.local_name() == b"rootfile" => {
+                if let Some(path) = attribute(&reader, e, b"full-path") {
+                    return Ok(path);
+                }
+            }
+            Event::Eof => anyhow::bail!("container.xml names no package document"),
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+/// First `.opf` file found, for extracted books without a container.xml
+fn find_package(root: &Path) -> Result<String> {
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read book directory: {}", dir.display()))?
+        {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|e| e == "opf") {
+                let relative = path.strip_prefix(root)?;
+                return Ok(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }
+    anyhow::bail!("No package document found in {}", root.display())
+}
+
+fn attribute(reader: &Reader<&[u8]>, e: &BytesStart, key: &[u8]) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key == key)
+        .and_then(|a| a.unescape_and_decode_value(reader).ok())
+}
+
+fn parse_package(package: &[u8]) -> Result<EpubSource> {
+    let mut source = EpubSource {
+        root: PathBuf::new(),
+        package_dir: String::new(),
+        title: String::new(),
+        creators: Vec::new(),
+        language: None,
+        page_progression_direction: None,
+        manifest: Vec::new(),
+        spine: Vec::new(),
+        cover_id: None,
+        toc: Vec::new(),
+    };
+    let mut reader = Reader::from_bytes(package);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut text_of: Option<Vec<u8>> = None;
+    let mut cover_image = None;
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref e)
+                if matches!(e.local_name(), b"title" | b"creator" | b"language") =>
+            {
+                text_of = Some(e.local_name().to_vec());
+            }
+            Event::Text(ref e) => {
+                let text = e.unescape_and_decode(&reader)?;
+                match text_of.as_deref() {
+                    Some(b"title") if source.title.is_empty() => source.title = text,
+                    Some(b"creator") => source.creators.push(text),
+                    Some(b"language") if source.language.is_none() => source.language = Some(text),
+                    _ => {}
+                }
+            }
+            Event::End(_) => text_of = None,
+            Event::Start(ref e) | Event::Empty(ref e) => match e.local_name() {
+                b"meta" if attribute(&reader, e, b"name").as_deref() == Some("cover") => {
+                    source.cover_id = attribute(&reader, e, b"content");
+                }
+                b"item" => {
+                    let id = attribute(&reader, e, b"id");
+                    // Items outside the package or on the web are skipped
+                    let href = attribute(&reader, e, b"href")
+                        .and_then(|href| resolve_href("", &href))
+                        .map(|(href, _)| href);
+                    if let (Some(id), Some(href)) = (id, href) {
+                        let item = ManifestItem {
+                            id,
+                            href,
+                            media_type: attribute(&reader, e, b"media-type").unwrap_or_default(),
+                            properties: attribute(&reader, e, b"properties"),
+                        };
+                        if item.has_property("cover-image") {
+                            cover_image = Some(item.id.clone());
+                        }
+                        source.manifest.push(item);
+                    }
+                }
+                b"spine" => {
+                    source.page_progression_direction =
+                        attribute(&reader, e, b"page-progression-direction");
+                }
+                b"itemref" => source.spine.extend(attribute(&reader, e, b"idref")),
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    // EPUB 3 marks the cover on the item, EPUB 2 with a <meta>
+    source.cover_id = cover_image.or(source.cover_id);
+    Ok(source)
+}
+
+/// Table of contents of an EPUB 3 navigation document in `dir`
+fn parse_nav(html: &str, dir: &str) -> Vec<NavPoint> {
+    let document = Html::parse_document(html);
+    let nav_selector = Selector::parse("nav").unwrap();
+    let navs: Vec<_> = document.select(&nav_selector).collect();
+    let toc_nav = navs
+        .iter()
+        .find(|nav| {
+            nav.value()
+                .attrs()
+                .any(|(name, value)| name.ends_with("type") && value.contains("toc"))
+        })
+        .or(navs.first());
+    let Some(list) = toc_nav.and_then(|nav| child_element(nav, "ol")) else {
+        return Vec::new();
+    };
+    nav_list(list, dir)
+}
+
+fn child_element<'a>(element: &ElementRef<'a>, name: &str) -> Option<ElementRef<'a>> {
+    element
+        .children()
+        .filter_map(ElementRef::wrap)
+        .find(|child| child.value().name() == name)
+}
+
+fn nav_list(list: ElementRef, dir: &str) -> Vec<NavPoint> {
+    let mut points = Vec::new();
+    for item in list
+        .children()
+        .filter_map(ElementRef::wrap)
+        .filter(|child| child.value().name() == "li")
+    {
+        let children = child_element(&item, "ol")
+            .map(|list| nav_list(list, dir))
+            .unwrap_or_default();
+        let (label, href) = match child_element(&item, "a") {
+            Some(link) => (
+                link.text().collect::<String>(),
+                link.value()
+                    .attr("href")
+                    .and_then(|href| resolve_href(dir, href))
+                    .map(|(path, fragment)| with_fragment(path, fragment)),
+            ),
+            None => (
+                child_element(&item, "span")
+                    .map(|span| span.text().collect())
+                    .unwrap_or_default(),
+                None,
+            ),
+        };
+        match href {
+            Some(href) => points.push(NavPoint {
+                label: label.trim().to_string(),
+                href,
+                children,
+            }),
+            // A heading without a link of its own: keep its entries
+            None => points.extend(children),
+        }
+    }
+    points
+}
+
+/// Table of contents of an NCX document in `dir`
+fn parse_ncx(ncx: &[u8], dir: &str) -> Result<Vec<NavPoint>> {
+    let mut reader = Reader::from_bytes(ncx);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut roots = Vec::new();
+    let mut open: Vec<NavPoint> = Vec::new();
+    let mut in_label = false;
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref e) => match e.local_name() {
+                b"navPoint" => open.push(NavPoint {
+                    label: String::new(),
+                    href: String::new(),
+                    children: Vec::new(),
+                }),
+                b"navLabel" => in_label = true,
+                b"content" => set_ncx_content(&reader, e, dir, &mut open),
+                _ => {}
+            },
+            Event::Empty(ref e) if e.local_name() == b"content" => {
+                set_ncx_content(&reader, e, dir, &mut open);
+            }
+            Event::Text(ref e) if in_label => {
+                if let Some(point) = open.last_mut() {
+                    point.label.push_str(&e.unescape_and_decode(&reader)?);
+                }
+            }
+            Event::End(ref e) => match e.local_name() {
+                b"navLabel" => in_label = false,
+                b"navPoint" => {
+                    if let Some(mut point) = open.pop() {
+                        point.label = point.label.trim().to_string();
+                        let siblings = match open.last_mut() {
+                            Some(parent) => &mut parent.children,
+                            None => &mut roots,
+                        };
+                        // An entry without a target of its own: keep its children
+                        if point.href.is_empty() {
+                            siblings.extend(point.children);
+                        } else {
+                            siblings.push(point);
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(roots)
+}
+
+fn set_ncx_content(reader: &Reader<&[u8]>, e: &BytesStart, dir: &str, open: &mut [NavPoint]) {
+    let href = attribute(reader, e, b"src").and_then(|src| resolve_href(dir, &src));
+    if let (Some(point), Some((path, fragment))) = (open.last_mut(), href) {
+        point.href = with_fragment(path, fragment);
+    }
+}
+
+fn with_fragment(path: String, fragment: Option<String>) -> String {
+    match fragment {
+        Some(fragment) => format!("{path}#{fragment}"),
+        None => path,
+    }
+}
+
+fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("")
+}
+
+fn join_path(dir: &str, path: &str) -> String {
+    if dir.is_empty() {
+        path.to_string()
+    } else {
+        format!("{dir}/{path}")
+    }
+}
+
+/// Resolve a (percent-encoded) reference against the directory `dir`, giving
+/// the decoded path and fragment. `None` for URLs with a scheme, absolute
+/// paths, references to the current document and paths leaving the package.
+fn resolve_href(dir: &str, href: &str) -> Option<(String, Option<String>)> {
+    let href = href.trim();
+    if href.is_empty() || href.starts_with(['#', '/']) {
+        return None;
+    }
+    if let Some(colon) = href.find(':') {
+        if !href[..colon].contains(['/', '?', '#']) {
+            return None;
+        }
+    }
+    let (path, fragment) = match href.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment.to_string())),
+        None => (href, None),
+    };
+    let path = path.split('?').next().unwrap_or_default();
+    let path = urlencoding::decode(path)
+        .map(|p| p.into_owned())
+        .unwrap_or_else(|_| path.to_string());
+
+    let mut segments: Vec<&str> = dir.split('/').filter(|s| !s.is_empty()).collect();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some((segments.join("/"), fragment))
+}
+
+/// Percent-encode each segment of a decoded path
+fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| urlencoding::encode(segment).into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Encoded reference to `path` from a document in `from_dir`
+fn relative_href(from_dir: &str, path: &str) -> String {
+    let from: Vec<&str> = from_dir.split('/').filter(|s| !s.is_empty()).collect();
+    let to: Vec<&str> = path.split('/').collect();
+    let common = from
+        .iter()
+        .zip(&to[..to.len() - 1])
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut href = "../".repeat(from.len() - common);
+    href.push_str(&encode_path(&to[common..].join("/")));
+    href
+}
+
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A content document with `body` as its body, linking to `stylesheet` if
+/// given. Both documents and stylesheets are expected one directory below the
+/// package document, as in `text/` and `styles/`.
+pub fn xhtml_document(title: &str, language: &str, body: &str, stylesheet: Option<&str>) -> String {
+    let language = escape_xml(language);
+    let mut html = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n");
+    html.push_str(&format!(
+        "<html xmlns=\"http://www.w3.org/1999/xhtml\" xml:lang=\"{language}\" lang=\"{language}\">\n<head><title>{}</title>",
+        escape_xml(title)
+    ));
+    if let Some(stylesheet) = stylesheet {
+        html.push_str(&format!(
+            "<link rel=\"stylesheet\" type=\"text/css\" href=\"../{}\"/>",
+            escape_xml(stylesheet)
+        ));
+    }
+    html.push_str(&format!("</head>\n<body>\n{body}\n</body>\n</html>\n"));
+    html
+}
+
+struct BuilderItem {
+    id: String,
+    href: String,
+    media_type: String,
+    properties: Option<String>,
+    data: Vec<u8>,
+}
+
+/// A new EPUB 3 book. Content is added with paths relative to the package
+/// document; the package document, navigation document and NCX are
+/// generated when the book is written.
+pub struct EpubBuilder {
+    identifier: String,
+    title: String,
+    creators: Vec<String>,
+    language: String,
+    page_progression_direction: Option<String>,
+    items: Vec<BuilderItem>,
+    spine: Vec<String>,
+    cover_id: Option<String>,
+    toc: Vec<NavPoint>,
+}
+
+impl EpubBuilder {
+    pub fn new(title: &str) -> Self {
+        Self {
+            identifier: format!("urn:uuid:{}", Uuid::new_v4()),
+            title: title.to_string(),
+            creators: Vec::new(),
+            language: "ja".to_string(),
+            page_progression_direction: None,
+            items: Vec::new(),
+            spine: Vec::new(),
+            cover_id: None,
+            toc: Vec::new(),
+        }
+    }
+
+    pub fn creator(&mut self, creator: &str) -> &mut Self {
+        self.creators.push(creator.to_string());
+        self
+    }
+
+    pub fn language(&mut self, language: &str) -> &mut Self {
+        self.language = language.to_string();
+        self
+    }
+
+    pub fn page_progression_direction(&mut self, direction: &str) -> &mut Self {
+        self.page_progression_direction = Some(direction.to_string());
+        self
+    }
+
+    /// Add a file at the decoded path `href`, returning its manifest id
+    pub fn add_item(
+        &mut self,
+        href: &str,
+        media_type: &str,
+        properties: Option<&str>,
+        data: Vec<u8>,
+    ) -> Result<String> {
+        if self.items.iter().any(|item| item.href == href) {
+            anyhow::bail!("Duplicate EPUB item {href}");
+        }
+        let id = format!("item{}", self.items.len() + 1);
+        self.items.push(BuilderItem {
+            id: id.clone(),
+            href: href.to_string(),
+            media_type: media_type.to_string(),
+            properties: properties.filter(|p| !p.is_empty()).map(str::to_string),
+            data,
+        });
+        Ok(id)
+    }
+
+    pub fn add_to_spine(&mut self, id: &str) {
+        self.spine.push(id.to_string());
+    }
+
+    pub fn set_cover(&mut self, id: &str) {
+        self.cover_id = Some(id.to_string());
+    }
+
+    pub fn set_toc(&mut self, toc: Vec<NavPoint>) {
+        self.toc = toc;
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn creators(&self) -> &[String] {
+        &self.creators
+    }
+
+    pub fn toc(&self) -> &[NavPoint] {
+        &self.toc
+    }
+
+    /// Paths of the documents in reading order
+    pub fn spine_hrefs(&self) -> Vec<&str> {
+        self.spine
+            .iter()
+            .filter_map(|id| self.items.iter().find(|item| &item.id == id))
+            .map(|item| item.href.as_str())
+            .collect()
+    }
+
+    /// The table of contents, or a single entry for the first document if
+    /// none was set, since a navigation document can't be empty
+    fn toc_or_default(&self) -> Vec<NavPoint> {
+        if !self.toc.is_empty() {
+            return self.toc.clone();
+        }
+        self.spine_hrefs()
+            .first()
+            .map(|href| NavPoint {
+                label: self.title.clone(),
+                href: href.to_string(),
+                children: Vec::new(),
+            })
+            .into_iter()
+            .collect()
+    }
+
+    /// Path for a generated file, moved aside if the book has a file of that
+    /// name itself (e.g. its own `nav.xhtml` kept in the spine)
+    fn generated_href(&self, name: &str) -> String {
+        let taken = |href: &str| self.items.iter().any(|item| item.href == href);
+        let mut href = name.to_string();
+        let mut n = 1;
+        while taken(&href) {
+            href = format!("generated-{n}-{name}");
+            n += 1;
+        }
+        href
+    }
+
+    fn package_document(&self) -> String {
+        let mut opf = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        opf.push_str(&format!(
+            "<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"BookId\" xml:lang=\"{}\">\n",
+            escape_xml(&self.language)
+        ));
+        opf.push_str("<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n");
+        opf.push_str(&format!(
+            "<dc:identifier id=\"BookId\">{}</dc:identifier>\n",
+            escape_xml(&self.identifier)
+        ));
+        opf.push_str(&format!(
+            "<dc:title>{}</dc:title>\n",
+            escape_xml(&self.title)
+        ));
+        for creator in &self.creators {
+            opf.push_str(&format!(
+                "<dc:creator>{}</dc:creator>\n",
+                escape_xml(creator)
+            ));
+        }
+        opf.push_str(&format!(
+            "<dc:language>{}</dc:language>\n",
+            escape_xml(&self.language)
+        ));
+        opf.push_str(&format!(
+            "<meta property=\"dcterms:modified\">{}</meta>\n",
+            chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+        ));
+        if let Some(cover_id) = &self.cover_id {
+            opf.push_str(&format!(
+                "<meta name=\"cover\" content=\"{}\"/>\n",
+                escape_xml(cover_id)
+            ));
+        }
+        opf.push_str("</metadata>\n<manifest>\n");
+        opf.push_str(&format!(
+            "<item id=\"nav\" href=\"{}\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+            escape_xml(&encode_path(&self.generated_href(NAV_HREF)))
+        ));
+        opf.push_str(&format!(
+            "<item id=\"ncx\" href=\"{}\" media-type=\"{NCX_MEDIA_TYPE}\"/>\n",
+            escape_xml(&encode_path(&self.generated_href(NCX_HREF)))
+        ));
+        for item in &self.items {
+            let mut properties: Vec<&str> = item
+                .properties
+                .as_deref()
+                .map(|p| p.split_whitespace().collect())
+                .unwrap_or_default();
+            if self.cover_id.as_ref() == Some(&item.id) && !properties.contains(&"cover-image") {
+                properties.push("cover-image");
+            }
+            opf.push_str(&format!(
+                "<item id=\"{}\" href=\"{}\" media-type=\"{}\"",
+                item.id,
+                escape_xml(&encode_path(&item.href)),
+                escape_xml(&item.media_type)
+            ));
+            if !properties.is_empty() {
+                opf.push_str(&format!(
+                    " properties=\"{}\"",
+                    escape_xml(&properties.join(" "))
+                ));
+            }
+            opf.push_str("/>\n");
+        }
+        opf.push_str("</manifest>\n");
+        match &self.page_progression_direction {
+            Some(direction) => opf.push_str(&format!(
+                "<spine toc=\"ncx\" page-progression-direction=\"{}\">\n",
+                escape_xml(direction)
+            )),
+            None => opf.push_str("<spine toc=\"ncx\">\n"),
+        }
+        for id in &self.spine {
+            opf.push_str(&format!("<itemref idref=\"{}\"/>\n", escape_xml(id)));
+        }
+        opf.push_str("</spine>\n</package>\n");
+        opf
+    }
+
+    fn nav_document(&self, toc: &[NavPoint]) -> String {
+        fn push_list(html: &mut String, points: &[NavPoint]) {
+            html.push_str("<ol>\n");
+            for point in points {
+                html.push_str(&format!(
+                    "<li><a href=\"{}\">{}</a>",
+                    escape_xml(&encode_href(&point.href)),
+                    escape_xml(&point.label)
+                ));
+                if !point.children.is_empty() {
+                    push_list(html, &point.children);
+                }
+                html.push_str("</li>\n");
+            }
+            html.push_str("</ol>\n");
+        }
+
+        let language = escape_xml(&self.language);
+        let mut html =
+            String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n");
+        html.push_str(&format!(
+            "<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" xml:lang=\"{language}\" lang=\"{language}\">\n"
+        ));
+        html.push_str(&format!(
+            "<head><title>{}</title></head>\n<body>\n<nav epub:type=\"toc\" id=\"toc\">\n",
+            escape_xml(&self.title)
+        ));
+        push_list(&mut html, toc);
+        html.push_str("</nav>\n</body>\n</html>\n");
+        html
+    }
+
+    fn ncx_document(&self, toc: &[NavPoint]) -> String {
+        fn depth(points: &[NavPoint]) -> usize {
+            points
+                .iter()
+                .map(|point| 1 + depth(&point.children))
+                .max()
+                .unwrap_or(0)
+        }
+        // playOrder numbers the entries in document order across all levels
+        fn push_points(ncx: &mut String, points: &[NavPoint], play_order: &mut usize) {
+            for point in points {
+                *play_order += 1;
+                ncx.push_str(&format!(
+                    "<navPoint id=\"navPoint-{play_order}\" playOrder=\"{play_order}\"><navLabel><text>{}</text></navLabel><content src=\"{}\"/>\n",
+                    escape_xml(&point.label),
+                    escape_xml(&encode_href(&point.href))
+                ));
+                push_points(ncx, &point.children, play_order);
+                ncx.push_str("</navPoint>\n");
+            }
+        }
+
+        let mut ncx = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        ncx.push_str(
+            "<ncx xmlns=\"http://www.daisy.org/z3986/2005/ncx/\" version=\"2005-1\">\n<head>\n",
+        );
+        ncx.push_str(&format!(
+            "<meta name=\"dtb:uid\" content=\"{}\"/>\n<meta name=\"dtb:depth\" content=\"{}\"/>\n",
+            escape_xml(&self.identifier),
+            depth(toc).max(1)
+        ));
+        ncx.push_str("<meta name=\"dtb:totalPageCount\" content=\"0\"/>\n<meta name=\"dtb:maxPageNumber\" content=\"0\"/>\n</head>\n");
+        ncx.push_str(&format!(
+            "<docTitle><text>{}</text></docTitle>\n<navMap>\n",
+            escape_xml(&self.title)
+        ));
+        push_points(&mut ncx, toc, &mut 0);
+        ncx.push_str("</navMap>\n</ncx>\n");
+        ncx
+    }
+
+    /// Every file of the book as (path in the container, contents), starting
+    /// with the `mimetype` file
+    fn files(&self) -> Vec<(String, Vec<u8>)> {
+        let toc = self.toc_or_default();
+        let package_href = self.generated_href(PACKAGE_HREF);
+        let container = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n<rootfiles>\n<rootfile full-path=\"{CONTENT_DIR}/{}\" media-type=\"application/oebps-package+xml\"/>\n</rootfiles>\n</container>\n",
+            escape_xml(&package_href)
+        );
+        let mut files = vec![
+            ("mimetype".to_string(), b"application/epub+zip".to_vec()),
+            (CONTAINER_PATH.to_string(), container.into_bytes()),
+            (
+                format!("{CONTENT_DIR}/{package_href}"),
+                self.package_document().into_bytes(),
+            ),
+            (
+                format!("{CONTENT_DIR}/{}", self.generated_href(NAV_HREF)),
+                self.nav_document(&toc).into_bytes(),
+            ),
+            (
+                format!("{CONTENT_DIR}/{}", self.generated_href(NCX_HREF)),
+                self.ncx_document(&toc).into_bytes(),
+            ),
+        ];
+        for item in &self.items {
+            files.push((format!("{CONTENT_DIR}/{}", item.href), item.data.clone()));
+        }
+        files
+    }
+
+    /// Write the book as an EPUB file
+    pub fn write_epub<W: Write + Seek>(&self, writer: W) -> Result<()> {
+        let mut zip = zip::ZipWriter::new(writer);
+        let stored =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let deflated =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (path, data) in self.files() {
+            // The mimetype has to come first and uncompressed
+            let options = if path == "mimetype" { stored } else { deflated };
+            zip.start_file(path, options)?;
+            zip.write_all(&data)?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+
+    /// Write the book extracted into `dir`, as books in the library are
+    pub fn write_dir(&self, dir: &Path) -> Result<()> {
+        for (path, data) in self.files() {
+            let path = dir.join(path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, data)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Encode the path of a `path#fragment` reference, keeping the fragment
+fn encode_href(href: &str) -> String {
+    match href.split_once('#') {
+        Some((path, fragment)) => format!("{}#{fragment}", encode_path(path)),
+        None => encode_path(href),
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeStats {
+    pub documents: usize,
+    pub assets: usize,
+    /// Assets identical to one already in the merged book, stored only once
+    pub deduplicated_assets: usize,
+    pub saved_bytes: u64,
+}
+
+/// Replace the references in `content` (markup, or CSS if `css`) for which
+/// `replace` returns a new encoded href
+fn replace_references(
+    content: &str,
+    css: bool,
+    mut replace: impl FnMut(&str) -> Option<String>,
+) -> String {
+    let content = CSS_REFERENCE.replace_all(content, |caps: &Captures| {
+        let href = caps
+            .get(1)
+            .or(caps.get(2))
+            .or(caps.get(3))
+            .unwrap()
+            .as_str();
+        match replace(href) {
+            Some(href) => format!("url(\"{href}\")"),
+            None => caps[0].to_string(),
+        }
+    });
+    if css {
+        return content.into_owned();
+    }
+    MARKUP_REFERENCE
+        .replace_all(&content, |caps: &Captures| {
+            let href = caps.get(2).or(caps.get(3)).unwrap().as_str();
+            match replace(href) {
+                Some(href) => format!("{}\"{}\"", &caps[1], escape_xml(&href)),
+                None => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// Elements whose text is left alone when rewriting a document: existing
+/// furigana, code and metadata
+const UNTOUCHED_ELEMENTS: &[&str] = &["ruby", "rt", "rp", "script", "style", "head", "title"];
+
+/// Rewrite the text of an (X)HTML document outside of `UNTOUCHED_ELEMENTS`.
+/// `replace` gets each piece of text between tags unescaped and returns the
+/// markup to put in its place, or None to keep it.
+pub fn rewrite_text(html: &str, mut replace: impl FnMut(&str) -> Option<String>) -> String {
+    let mut rewritten = String::with_capacity(html.len());
+    let mut untouched_depth = 0usize;
+    let mut last = 0;
+    for tag in MARKUP_TAG.find_iter(html) {
+        let text = &html[last..tag.start()];
+        rewritten.push_str(&rewrite_text_node(text, untouched_depth, &mut replace));
+        last = tag.end();
+
+        let tag = tag.as_str();
+        rewritten.push_str(tag);
+        if tag.starts_with("<!") || tag.starts_with("<?") || tag.ends_with("/>") {
+            continue;
+        }
+        let closing = tag.starts_with("</");
+        let name = tag
+            .trim_start_matches(['<', '/'])
+            .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let name = name.rsplit(':').next().unwrap_or_default();
+        if UNTOUCHED_ELEMENTS.contains(&name) {
+            untouched_depth = if closing {
+                untouched_depth.saturating_sub(1)
+            } else {
+                untouched_depth + 1
+            };
+        }
+    }
+    rewritten.push_str(&rewrite_text_node(
+        &html[last..],
+        untouched_depth,
+        &mut replace,
+    ));
+    rewritten
+}
+
+fn rewrite_text_node<'a>(
+    text: &'a str,
+    untouched_depth: usize,
+    replace: &mut impl FnMut(&str) -> Option<String>,
+) -> std::borrow::Cow<'a, str> {
+    if untouched_depth > 0 || text.trim().is_empty() {
+        return text.into();
+    }
+    let Ok(unescaped) = quick_xml::escape::unescape(text.as_bytes()) else {
+        return text.into();
+    };
+    let unescaped = String::from_utf8_lossy(&unescaped);
+    match replace(&unescaped) {
+        Some(markup) => markup.into(),
+        None => text.into(),
+    }
+}
+
+/// A copy of `source` with each file passed through `transform`, e.g. to
+/// add annotations to its documents. The navigation document and NCX are
+/// regenerated from the source's table of contents.
+pub fn repackage(
+    source: &EpubSource,
+    mut transform: impl FnMut(&ManifestItem, Vec<u8>) -> Result<Vec<u8>>,
+) -> Result<EpubBuilder> {
+    let mut builder = EpubBuilder::new(&source.title);
+    for creator in &source.creators {
+        builder.creator(creator);
+    }
+    if let Some(language) = &source.language {
+        builder.language(language);
+    }
+    if let Some(direction) = &source.page_progression_direction {
+        builder.page_progression_direction(direction);
+    }
+
+    let mut ids = HashMap::new();
+    for item in &source.manifest {
+        let in_spine = source.spine.contains(&item.id);
+        if item.media_type == NCX_MEDIA_TYPE || (item.has_property("nav") && !in_spine) {
+            continue;
+        }
+        let properties = item.properties.as_deref().map(|p| {
+            p.split_whitespace()
+                .filter(|p| !matches!(*p, "nav" | "cover-image"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+        let data = transform(item, source.read(&item.href)?)?;
+        let id = builder.add_item(&item.href, &item.media_type, properties.as_deref(), data)?;
+        ids.insert(item.id.clone(), id);
+    }
+    for idref in &source.spine {
+        match ids.get(idref) {
+            Some(id) => builder.add_to_spine(id),
+            None => warn!(%idref, book = %source.title, "Spine item missing from manifest"),
+        }
+    }
+    if let Some(cover) = source.cover_id.as_ref().and_then(|id| ids.get(id)) {
+        builder.set_cover(cover);
+    }
+    builder.set_toc(source.toc.clone());
+    Ok(builder)
+}
+
+/// Table of contents of a book moved into the directory `prefix`
+fn prefixed_toc(points: &[NavPoint], prefix: &str) -> Vec<NavPoint> {
+    points
+        .iter()
+        .map(|point| NavPoint {
+            label: point.label.clone(),
+            href: format!("{prefix}/{}", point.href),
+            children: prefixed_toc(&point.children, prefix),
+        })
+        .collect()
+}
+
+/// Combine several books (e.g. chapter-range chunks of the same novel) into
+/// one. Each book's files go under `partN/`, keeping their layout so links
+/// within a book still work; the spines and tables of contents are
+/// concatenated in order, and assets identical to one of an earlier book are
+/// stored once, with references to them rewritten.
+pub fn merge_books(
+    sources: &[EpubSource],
+    title: Option<&str>,
+) -> Result<(EpubBuilder, MergeStats)> {
+    let first = sources.first().context("No books to merge")?;
+    let title = title
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .unwrap_or(&first.title);
+    let mut builder = EpubBuilder::new(title);
+    for creator in &first.creators {
+        builder.creator(creator);
+    }
+    if let Some(language) = &first.language {
+        builder.language(language);
+    }
+    if let Some(direction) = &first.page_progression_direction {
+        builder.page_progression_direction(direction);
+    }
+
+    let mut stats = MergeStats::default();
+    // Hash of an asset -> (merged id, merged path)
+    let mut stored: HashMap<[u8; 32], (String, String)> = HashMap::new();
+    let mut toc = Vec::new();
+    for (i, source) in sources.iter().enumerate() {
+        let prefix = format!("part{}", i + 1);
+        let in_spine = |item: &ManifestItem| source.spine.contains(&item.id);
+        // The navigation document and NCX are generated for the merged book
+        let items: Vec<&ManifestItem> = source
+            .manifest
+            .iter()
+            .filter(|item| item.media_type != NCX_MEDIA_TYPE)
+            .filter(|item| !item.has_property("nav") || in_spine(item))
+            .collect();
+
+        // Images and fonts first, then stylesheets (which may refer to them),
+        // then documents, so that every reference to a deduplicated file can
+        // be rewritten
+        let mut ids = HashMap::new();
+        // Path in the source -> path in the merged book, for deduplicated files
+        let mut moved: HashMap<String, String> = HashMap::new();
+        let stage = |item: &ManifestItem| match item.media_type.as_str() {
+            _ if item.is_document() => 2,
+            "text/css" => 1,
+            _ => 0,
+        };
+        for current_stage in 0..3 {
+            for item in items.iter().filter(|item| stage(item) == current_stage) {
+                let new_path = format!("{prefix}/{}", item.href);
+                let original = source.read(&item.href)?;
+                let dir = parent_dir(&item.href);
+                let content = (current_stage > 0)
+                    .then(|| std::str::from_utf8(&original).ok())
+                    .flatten();
+
+                // A stylesheet is the same as a stored one if its text is and
+                // its references lead to the same files
+                let mut hasher = Sha256::new();
+                hasher.update(&original);
+                let data = match content {
+                    Some(content) => {
+                        let css = current_stage == 1;
+                        replace_references(content, css, |href| {
+                            let (target, fragment) = resolve_href(dir, href)?;
+                            let new_target = moved.get(&target);
+                            hasher.update(b"\0");
+                            hasher.update(new_target.unwrap_or(&format!("{prefix}/{target}")));
+                            let href = relative_href(parent_dir(&new_path), new_target?);
+                            Some(with_fragment(href, fragment))
+                        })
+                        .into_bytes()
+                    }
+                    None => original,
+                };
+                let hash: [u8; 32] = hasher.finalize().into();
+
+                let properties = item.properties.as_deref().map(|p| {
+                    p.split_whitespace()
+                        .filter(|p| !matches!(*p, "nav" | "cover-image"))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                });
+                if item.is_document() {
+                    stats.documents += 1;
+                } else {
+                    stats.assets += 1;
+                    if let Some((id, path)) = stored.get(&hash) {
+                        stats.deduplicated_assets += 1;
+                        stats.saved_bytes += data.len() as u64;
+                        ids.insert(item.id.clone(), id.clone());
+                        moved.insert(item.href.clone(), path.clone());
+                        continue;
+                    }
+                }
+                let id =
+                    builder.add_item(&new_path, &item.media_type, properties.as_deref(), data)?;
+                if !item.is_document() {
+                    stored.insert(hash, (id.clone(), new_path));
+                }
+                ids.insert(item.id.clone(), id);
+            }
+        }
+
+        for idref in &source.spine {
+            match ids.get(idref) {
+                Some(id) => builder.add_to_spine(id),
+                None => warn!(%idref, book = %source.title, "Spine item missing from manifest"),
+            }
+        }
+        if i == 0 {
+            if let Some(cover) = source.cover_id.as_ref().and_then(|id| ids.get(id)) {
+                builder.set_cover(cover);
+            }
+        }
+
+        if source.toc.is_empty() {
+            // Keep books without a table of contents reachable from it
+            let first_document = source.spine.iter().find_map(|id| source.item(id));
+            if let Some(document) = first_document {
+                toc.push(NavPoint {
+                    label: source.title.clone(),
+                    href: format!("{prefix}/{}", document.href),
+                    children: Vec::new(),
+                });
+            }
+        } else {
+            toc.extend(prefixed_toc(&source.toc, &prefix));
+        }
+    }
+    builder.set_toc(toc);
+    Ok((builder, stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(dir: &Path, title: &str, chapters: &[&str]) -> EpubSource {
+        let mut builder = EpubBuilder::new(title);
+        builder.creator("作者").page_progression_direction("rtl");
+        builder
+            .add_item(
+                "style/book.css",
+                "text/css",
+                None,
+                b"body { background: url(../images/bg.png) }".to_vec(),
+            )
+            .unwrap();
+        builder
+            .add_item("images/bg.png", "image/png", None, b"same image".to_vec())
+            .unwrap();
+        let cover = builder
+            .add_item(
+                "images/cover.jpg",
+                "image/jpeg",
+                None,
+                title.as_bytes().to_vec(),
+            )
+            .unwrap();
+        builder.set_cover(&cover);
+        let mut toc = Vec::new();
+        for (n, chapter) in chapters.iter().enumerate() {
+            let href = format!("text/第{n}話.xhtml");
+            let html = format!(
+                "<html><head><link href=\"../style/book.css\" rel=\"stylesheet\"/></head><body><img src=\"../images/bg.png\"/><h1>{chapter}</h1></body></html>"
+            );
+            let id = builder
+                .add_item(&href, "application/xhtml+xml", None, html.into_bytes())
+                .unwrap();
+            builder.add_to_spine(&id);
+            toc.push(NavPoint {
+                label: chapter.to_string(),
+                href,
+                children: Vec::new(),
+            });
+        }
+        builder.set_toc(toc);
+        builder.write_dir(dir).unwrap();
+        EpubSource::open_dir(dir).unwrap()
+    }
+
+    #[test]
+    fn test_merge_books() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let first = chunk(
+            &temp_dir.path().join("1"),
+            "小説 1-2",
+            &["第一話", "第二話"],
+        );
+        let second = chunk(&temp_dir.path().join("2"), "小説 3", &["第三話"]);
+        assert_eq!(first.title, "小説 1-2");
+        assert_eq!(first.creators, ["作者"]);
+        assert_eq!(first.toc[1].href, "text/第1話.xhtml");
+
+        let (merged, stats) = merge_books(&[first, second], Some("小説")).unwrap();
+        assert_eq!(stats.documents, 3);
+        // The stylesheet and background are shared, the covers differ
+        assert_eq!(stats.deduplicated_assets, 2);
+
+        let merged_dir = temp_dir.path().join("merged");
+        merged.write_dir(&merged_dir).unwrap();
+        let book = EpubSource::open_dir(&merged_dir).unwrap();
+        assert_eq!(book.title, "小説");
+        assert_eq!(book.page_progression_direction.as_deref(), Some("rtl"));
+        assert_eq!(book.spine.len(), 3);
+        let labels: Vec<_> = book.toc.iter().map(|p| p.label.as_str()).collect();
+        assert_eq!(labels, ["第一話", "第二話", "第三話"]);
+        assert_eq!(book.toc[2].href, "part2/text/第0話.xhtml");
+        assert_eq!(
+            book.read(&book.item(book.cover_id.as_ref().unwrap()).unwrap().href)
+                .unwrap(),
+            "小説 1-2".as_bytes()
+        );
+
+        // The second book's document now points at the first book's copies
+        let document = String::from_utf8(book.read("part2/text/第0話.xhtml").unwrap()).unwrap();
+        assert!(document.contains("href=\"../../part1/style/book.css\""));
+        assert!(document.contains("src=\"../../part1/images/bg.png\""));
+        assert!(book.read("part2/style/book.css").is_err());
+
+        let mut epub = std::io::Cursor::new(Vec::new());
+        merged.write_epub(&mut epub).unwrap();
+        let mut archive = zip::ZipArchive::new(epub).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+    }
+
+    #[test]
+    fn test_resolve_href() {
+        assert_eq!(
+            resolve_href("text", "../images/%E7%94%BB.png#top"),
+            Some(("images/画.png".to_string(), Some("top".to_string())))
+        );
+        assert_eq!(resolve_href("", "../outside.png"), None);
+        assert_eq!(resolve_href("text", "https://example.com/a.png"), None);
+        assert_eq!(resolve_href("text", "#note"), None);
+        assert_eq!(
+            relative_href("part2/text", "part1/images/画.png"),
+            "../../part1/images/%E7%94%BB.png"
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Book glossaries: the unknown terms of a book that appear often enough to be
+//! worth studying before reading, with their top definition.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::library::{user_library_dir, LibraryFrequencyEntry};
+
+pub const DEFAULT_MIN_OCCURRENCES: u64 = 3;
+pub const DEFAULT_MAX_TERMS: usize = 300;
+pub const MAX_GLOSSARY_TERMS: usize = 2000;
+
+#[derive(Debug, Clone)]
+pub struct GlossaryOptions {
+    /// Terms appearing fewer times than this in the book are left out
+    pub min_occurrences: u64,
+    pub max_terms: usize,
+    /// Terms the user already knows
+    pub known_terms: HashSet<String>,
+}
+
+impl Default for GlossaryOptions {
+    fn default() -> Self {
+        Self {
+            min_occurrences: DEFAULT_MIN_OCCURRENCES,
+            max_terms: DEFAULT_MAX_TERMS,
+            known_terms: HashSet::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GlossaryEntry {
+    pub term: String,
+    pub reading: Option<String>,
+    /// Occurrences in the book
+    pub count: u64,
+    pub dictionary: String,
+    pub definition: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Glossary {
+    pub upload_id: String,
+    pub title: String,
+    pub generated_at: chrono::DateTime<chrono::Utc>,
+    pub entries: Vec<GlossaryEntry>,
+}
+
+/// Candidate terms of a book's frequency list, in list order. Known terms and
+/// terms without any Japanese characters (numbers, Latin words) are skipped.
+pub fn select_terms<'a>(
+    book_terms: &'a [LibraryFrequencyEntry],
+    options: &'a GlossaryOptions,
+) -> impl Iterator<Item = &'a LibraryFrequencyEntry> {
+    book_terms.iter().filter(move |entry| {
+        entry.count >= options.min_occurrences
+            && !options.known_terms.contains(&entry.term)
+            && entry.term.chars().any(is_japanese_char)
+    })
+}
+
+pub fn is_japanese_char(c: char) -> bool {
+    matches!(c,
+        '\u{3041}'..='\u{309F}' // Hiragana
+        | '\u{30A0}'..='\u{30FF}' // Katakana
+        | '\u{4E00}'..='\u{9FFF}' // CJK unified ideographs
+        | '\u{3400}'..='\u{4DBF}' // CJK extension A
+        | '々')
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Glossary {
+    pub fn to_csv(&self) -> Result<String> {
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        wtr.write_record(["term", "reading", "count", "dictionary", "definition"])?;
+        for entry in &self.entries {
+            wtr.write_record([
+                entry.term.as_str(),
+                entry.reading.as_deref().unwrap_or(""),
+                &entry.count.to_string(),
+                &entry.dictionary,
+                &entry.definition,
+            ])?;
+        }
+        Ok(String::from_utf8(wtr.into_inner()?)?)
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html lang=\"ja\">\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n<table>\n<tr><th>Term</th><th>Reading</th><th>Count</th><th>Definition</th></tr>\n",
+            escape_html(&self.title)
+        );
+        for entry in &self.entries {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape_html(&entry.term),
+                escape_html(entry.reading.as_deref().unwrap_or("")),
+                entry.count,
+                escape_html(&entry.definition).replace('\n', "<br>")
+            ));
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+
+    /// Anki's text import format, which creates Basic notes in a deck named
+    /// after the book
+    pub fn to_anki(&self) -> String {
+        let field = |s: &str| escape_html(s).replace(['\t', '\n'], "<br>");
+        let mut out = format!(
+            "#separator:tab\n#html:true\n#notetype:Basic\n#deck:{}\n",
+            self.title.replace(['\n', '\t'], " ")
+        );
+        for entry in &self.entries {
+            let back = match &entry.reading {
+                Some(reading) => format!("{}<br>{}", field(reading), field(&entry.definition)),
+                None => field(&entry.definition),
+            };
+            out.push_str(&format!("{}\t{}\n", field(&entry.term), back));
+        }
+        out
+    }
+}
+
+/// Generated glossaries, stored per user and book under `LIBRARY_DATA_DIR`
+pub struct GlossaryStore {
+    data_dir: PathBuf,
+}
+
+impl GlossaryStore {
+    pub fn new(data_dir: PathBuf) -> Self {
+        Self { data_dir }
+    }
+
+    fn glossary_path(&self, user_id: &str, upload_id: &str) -> Result<PathBuf> {
+        let dir = user_library_dir(&self.data_dir, user_id)?.join("glossaries");
+        // Upload ids are directory names, so the same path validation applies
+        user_library_dir(&dir, upload_id)?;
+        Ok(dir.join(format!("{upload_id}.json")))
+    }
+
+    pub async fn save(&self, user_id: &str, glossary: &Glossary) -> Result<()> {
+        let path = self.glossary_path(user_id, &glossary.upload_id)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, serde_json::to_vec(glossary)?).await?;
+        Ok(())
+    }
+
+    pub async fn load(&self, user_id: &str, upload_id: &str) -> Result<Option<Glossary>> {
+        let path = self.glossary_path(user_id, upload_id)?;
+        match tokio::fs::read(&path).await {
+            Ok(data) => {
+                Ok(Some(serde_json::from_slice(&data).with_context(|| {
+                    format!("Invalid glossary: {}", path.display())
+                })?))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn freq(term: &str, count: u64) -> LibraryFrequencyEntry {
+        LibraryFrequencyEntry {
+            term: term.to_string(),
+            reading: None,
+            count,
+            book_count: 1,
+        }
+    }
+
+    #[test]
+    fn test_select_terms() {
+        let book_terms = vec![
+            freq("魔法", 12),
+            freq("する", 10),
+            freq("2024", 5),
+            freq("剣", 3),
+            freq("竜", 1),
+        ];
+        let options = GlossaryOptions {
+            min_occurrences: 2,
+            known_terms: HashSet::from(["する".to_string()]),
+            ..Default::default()
+        };
+        let terms: Vec<_> = select_terms(&book_terms, &options)
+            .map(|e| e.term.as_str())
+            .collect();
+        assert_eq!(terms, vec!["魔法", "剣"]);
+    }
+
+    #[tokio::test]
+    async fn test_exports_and_store() {
+        let glossary = Glossary {
+            upload_id: "book-1".to_string(),
+            title: "魔法使い <上>".to_string(),
+            generated_at: chrono::Utc::now(),
+            entries: vec![GlossaryEntry {
+                term: "魔法".to_string(),
+                reading: Some("まほう".to_string()),
+                count: 12,
+                dictionary: "JMdict".to_string(),
+                definition: "magic\nsorcery".to_string(),
+            }],
+        };
+
+        assert_eq!(
+            glossary.to_csv().unwrap(),
+            "term,reading,count,dictionary,definition\n魔法,まほう,12,JMdict,\"magic\nsorcery\"\n"
+        );
+        assert!(glossary.to_html().contains("<h1>魔法使い &lt;上&gt;</h1>"));
+        assert!(glossary
+            .to_anki()
+            .ends_with("#deck:魔法使い <上>\n魔法\tまほう<br>magic<br>sorcery\n"));
+
+        let temp_dir = TempDir::new().unwrap();
+        let store = GlossaryStore::new(temp_dir.path().to_path_buf());
+        assert!(store.load("alice", "book-1").await.unwrap().is_none());
+        store.save("alice", &glossary).await.unwrap();
+        let loaded = store.load("alice", "book-1").await.unwrap().unwrap();
+        assert_eq!(loaded.entries, glossary.entries);
+        assert!(store.load("alice", "../bob").await.is_err());
+    }
+}
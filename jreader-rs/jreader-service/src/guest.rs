@@ -0,0 +1,430 @@
+//! Guest access to the public routes (lookups, search, shared decks).
+//!
+//! Requests to those routes go through [`GuestLayer`]. Requests with a valid
+//! login are passed on with their `user_id` header like on the authenticated
+//! routes. Anything else is a guest: it gets a temporary guest id in the
+//! `jreader_guest` cookie, is limited to `GUEST_REQUESTS_PER_MINUTE` requests,
+//! and may only use the audio endpoints if `GUEST_AUDIO` is set. The limit
+//! is per client address whenever it's known, so dropping or rotating the
+//! cookie doesn't get around it. With `GUEST_ACCESS=false` only
+//! `/api/capabilities` answers without a login.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, Request};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use tower::{Layer, Service};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::auth::{self, AuthService};
+use crate::config::{ConfigHandle, ServiceConfig};
+
+pub const GUEST_COOKIE: &str = "jreader_guest";
+const GUEST_COOKIE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+// Windows are pruned once this many clients are tracked
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+// Answered without a login even when guest access is off
+const CAPABILITIES_ROUTE: &str = "/api/capabilities";
+
+/// Features guests can never use, as named in `/api/capabilities`
+const LOGIN_ONLY_FEATURES: [&str; 12] = [
+    "library",
+    "uploads",
+    "webnovel",
+    "vocabLists",
+    "entryNotes",
+    "customDictionaries",
+    "preferences",
+    "audioBatch",
+    "pronunciation",
+    "dictionaryAdmin",
+    "integrations",
+    "readingStats",
+];
+/// Features of the public routes, open to guests when guest access is on
+const PUBLIC_FEATURES: [&str; 4] = ["lookup", "search", "reverseLookup", "sharedDecks"];
+
+/// Who made a request to a public route, added to its extensions by
+/// [`GuestLayer`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Visitor {
+    User(String),
+    Guest(Uuid),
+}
+
+/// Features that need a login with `config`, as named in `/api/capabilities`
+pub fn login_required_features(config: &ServiceConfig) -> Vec<&'static str> {
+    let mut features = LOGIN_ONLY_FEATURES.to_vec();
+    if !config.guest_audio || !config.guest_access {
+        features.push("audio");
+    }
+    if !config.guest_access {
+        features.extend(PUBLIC_FEATURES);
+    }
+    features
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Issues guest ids signed with a key made at startup, so clients can't pick
+/// their own. Cookies from before a restart are replaced.
+struct GuestIds {
+    key: [u8; 32],
+}
+
+impl GuestIds {
+    fn new() -> Self {
+        let mut key = [0; 32];
+        key[..16].copy_from_slice(Uuid::new_v4().as_bytes());
+        key[16..].copy_from_slice(Uuid::new_v4().as_bytes());
+        Self { key }
+    }
+
+    fn mac(&self, id: &Uuid) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).unwrap();
+        mac.update(id.as_bytes());
+        mac
+    }
+
+    /// Cookie value of a new guest
+    fn issue(&self) -> (Uuid, String) {
+        let id = Uuid::new_v4();
+        let sig = self.mac(&id).finalize().into_bytes();
+        (id, format!("{id}.{}", URL_SAFE_NO_PAD.encode(&sig[..16])))
+    }
+
+    /// Guest id of a cookie value, None if it wasn't issued here
+    fn verify(&self, value: &str) -> Option<Uuid> {
+        let (id, sig) = value.split_once('.')?;
+        let id = Uuid::parse_str(id).ok()?;
+        let sig = URL_SAFE_NO_PAD.decode(sig).ok()?;
+        if sig.len() != 16 {
+            return None;
+        }
+        self.mac(&id).verify_truncated_left(&sig).ok()?;
+        Some(id)
+    }
+}
+
+// Value of cookie `name` in the Cookie headers of a request
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|pair| {
+            let (key, value) = pair.trim().split_once('=')?;
+            (key == name).then_some(value)
+        })
+}
+
+struct RateWindow {
+    started: Instant,
+    count: u32,
+}
+
+/// Requests per client in fixed one-minute windows
+#[derive(Default)]
+struct RateLimiter {
+    windows: Mutex<HashMap<String, RateWindow>>,
+}
+
+impl RateLimiter {
+    /// Count a request from `client`. Err with the time until the next window
+    /// if the client already made `per_minute` requests in this one.
+    fn check(&self, client: &str, per_minute: u32, now: Instant) -> Result<(), Duration> {
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() >= MAX_TRACKED_CLIENTS && !windows.contains_key(client) {
+            windows.retain(|_, window| now.duration_since(window.started) < RATE_WINDOW);
+        }
+        let window = windows.entry(client.to_string()).or_insert(RateWindow {
+            started: now,
+            count: 0,
+        });
+        let elapsed = now.duration_since(window.started);
+        if elapsed >= RATE_WINDOW {
+            window.started = now;
+            window.count = 0;
+        } else if window.count >= per_minute {
+            return Err(RATE_WINDOW - elapsed);
+        }
+        window.count += 1;
+        Ok(())
+    }
+}
+
+struct GuestState {
+    config: Arc<ConfigHandle>,
+    ids: GuestIds,
+    limiter: RateLimiter,
+}
+
+#[derive(Clone)]
+pub struct GuestLayer<A: AuthService> {
+    auth_service: A,
+    state: Arc<GuestState>,
+}
+
+impl<A: AuthService> GuestLayer<A> {
+    pub fn new(auth_service: A, config: Arc<ConfigHandle>) -> Self {
+        Self {
+            auth_service,
+            state: Arc::new(GuestState {
+                config,
+                ids: GuestIds::new(),
+                limiter: RateLimiter::default(),
+            }),
+        }
+    }
+}
+
+impl<S, A> Layer<S> for GuestLayer<A>
+where
+    A: AuthService + Clone + Send + Sync + 'static,
+{
+    type Service = GuestMiddleware<S, A>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GuestMiddleware {
+            inner,
+            auth_service: self.auth_service.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GuestMiddleware<S, A> {
+    inner: S,
+    auth_service: A,
+    state: Arc<GuestState>,
+}
+
+fn error_response(status: StatusCode, error: &str) -> Response {
+    (status, Json(json!({ "error": error }))).into_response()
+}
+
+impl<S, A> Service<Request> for GuestMiddleware<S, A>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    A: AuthService + Clone + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let auth_service = self.auth_service.clone();
+        let state = self.state.clone();
+        let mut inner = self.inner.clone();
+
+        Box::pin(async move {
+            // Only ever set from a verified login
+            req.headers_mut().remove("user_id");
+            // A token that doesn't verify (e.g. an expired session) is a guest
+            // here, the public routes never needed one
+            if let Ok(Some(user_id)) = auth::authenticate(&auth_service, req.headers()).await {
+                req.headers_mut()
+                    .insert("user_id", user_id.parse().unwrap());
+                req.extensions_mut().insert(Visitor::User(user_id));
+                return inner.call(req).await;
+            }
+
+            let config = state.config.current();
+            let path = req.uri().path().to_string();
+            if !config.guest_access && path != CAPABILITIES_ROUTE {
+                return Ok(error_response(StatusCode::UNAUTHORIZED, "Login required"));
+            }
+
+            let cookie_id = cookie(req.headers(), GUEST_COOKIE).and_then(|v| state.ids.verify(v));
+            let (guest_id, new_cookie) = match cookie_id {
+                Some(id) => (id, None),
+                None => {
+                    let (id, value) = state.ids.issue();
+                    (id, Some(value))
+                }
+            };
+            if config.guest_requests_per_minute > 0 {
+                let client = match (req.extensions().get::<ConnectInfo<SocketAddr>>(), cookie_id) {
+                    (Some(ConnectInfo(addr)), _) => format!("addr:{}", addr.ip()),
+                    (None, Some(id)) => format!("guest:{id}"),
+                    (None, None) => "anonymous".to_string(),
+                };
+                if let Err(retry_after) =
+                    state
+                        .limiter
+                        .check(&client, config.guest_requests_per_minute, Instant::now())
+                {
+                    debug!(client, route = path, "Guest rate limit reached");
+                    let mut response =
+                        error_response(StatusCode::TOO_MANY_REQUESTS, "Too many requests");
+                    response.headers_mut().insert(
+                        header::RETRY_AFTER,
+                        HeaderValue::from(retry_after.as_secs().max(1)),
+                    );
+                    return Ok(response);
+                }
+            }
+            if path.starts_with("/api/audio") && !config.guest_audio {
+                return Ok(error_response(
+                    StatusCode::FORBIDDEN,
+                    "Login required for audio",
+                ));
+            }
+
+            req.extensions_mut().insert(Visitor::Guest(guest_id));
+            let mut response = inner.call(req).await?;
+            if let Some(value) = new_cookie {
+                let cookie = format!(
+                    "{GUEST_COOKIE}={value}; Max-Age={}; Path=/; HttpOnly; SameSite=Lax",
+                    GUEST_COOKIE_MAX_AGE.as_secs()
+                );
+                match HeaderValue::from_str(&cookie) {
+                    Ok(cookie) => {
+                        response.headers_mut().append(header::SET_COOKIE, cookie);
+                    }
+                    Err(e) => warn!(?e, "Failed to set guest cookie"),
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guest_ids() {
+        let ids = GuestIds::new();
+        let (id, value) = ids.issue();
+        assert_eq!(ids.verify(&value), Some(id));
+
+        let other = Uuid::new_v4();
+        let (_, sig) = value.split_once('.').unwrap();
+        assert_eq!(ids.verify(&format!("{other}.{sig}")), None);
+        assert_eq!(GuestIds::new().verify(&value), None);
+        assert_eq!(ids.verify(&id.to_string()), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_str(&format!("theme=dark; {GUEST_COOKIE}={value}")).unwrap(),
+        );
+        assert_eq!(cookie(&headers, GUEST_COOKIE), Some(value.as_str()));
+        assert_eq!(cookie(&headers, "session"), None);
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::default();
+        let start = Instant::now();
+        assert!(limiter.check("a", 2, start).is_ok());
+        assert!(limiter.check("a", 2, start).is_ok());
+        let retry_after = limiter
+            .check("a", 2, start + Duration::from_secs(20))
+            .unwrap_err();
+        assert_eq!(retry_after, Duration::from_secs(40));
+        assert!(limiter.check("b", 2, start).is_ok());
+        assert!(limiter.check("a", 2, start + RATE_WINDOW).is_ok());
+    }
+
+    #[derive(Clone)]
+    struct RejectingAuth;
+
+    impl AuthService for RejectingAuth {
+        async fn verify_token(&self, _token: String) -> anyhow::Result<String> {
+            anyhow::bail!("Invalid token")
+        }
+    }
+
+    fn guest_request(addr: &str, guest_cookie: Option<&str>) -> Request {
+        let mut request = Request::get("/api/lookup")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        if let Some(value) = guest_cookie {
+            request.headers_mut().insert(
+                header::COOKIE,
+                HeaderValue::from_str(&format!("{GUEST_COOKIE}={value}")).unwrap(),
+            );
+        }
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(addr.parse::<SocketAddr>().unwrap()));
+        request
+    }
+
+    // Value of the guest cookie a response sets
+    fn set_cookie(response: &Response) -> Option<String> {
+        let value = response.headers().get(header::SET_COOKIE)?.to_str().ok()?;
+        let (pair, _) = value.split_once(';')?;
+        pair.strip_prefix(&format!("{GUEST_COOKIE}="))
+            .map(String::from)
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_with_rotated_cookies() {
+        let config = ConfigHandle::new(ServiceConfig {
+            guest_access: true,
+            guest_requests_per_minute: 2,
+            ..ServiceConfig::from_env()
+        });
+        let mut service =
+            GuestLayer::new(RejectingAuth, Arc::new(config)).layer(tower::service_fn(
+                |_: Request| async { Ok::<_, Infallible>(StatusCode::OK.into_response()) },
+            ));
+
+        // Each cookieless request gets a new cookie, which doesn't reset the
+        // limit of the address
+        let first = service
+            .call(guest_request("10.0.0.1:1000", None))
+            .await
+            .unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        let first_cookie = set_cookie(&first).unwrap();
+        let second = service
+            .call(guest_request("10.0.0.1:1001", None))
+            .await
+            .unwrap();
+        assert_eq!(second.status(), StatusCode::OK);
+        let second_cookie = set_cookie(&second).unwrap();
+        assert_ne!(first_cookie, second_cookie);
+
+        for cookie in [&first_cookie, &second_cookie] {
+            let response = service
+                .call(guest_request("10.0.0.1:1002", Some(cookie)))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert!(response.headers().contains_key(header::RETRY_AFTER));
+        }
+
+        let other = service
+            .call(guest_request("10.0.0.2:1000", None))
+            .await
+            .unwrap();
+        assert_eq!(other.status(), StatusCode::OK);
+    }
+}
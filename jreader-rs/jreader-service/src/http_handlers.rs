@@ -1,16 +1,19 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path as StdPath, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
+use arc_swap::ArcSwapOption;
 use axum::body::Body;
+use axum::extract::multipart::{Field, Multipart};
 use axum::extract::Path;
 use axum::extract::{Query, State};
-use axum::http::HeaderMap;
+use axum::http::{HeaderMap, HeaderValue, Method};
 use axum::response::Response;
+use axum::Extension;
 use axum::{http::StatusCode, Json};
-use axum_typed_multipart::{TryFromMultipart, TypedMultipart};
+use axum_typed_multipart::{FieldData, TryFromMultipart, TypedMultipart};
 use base64::{
     engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
     Engine as _,
@@ -23,20 +26,71 @@ use sha2::Sha256;
 use std::fs;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tempfile::NamedTempFile;
-use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use tokio::sync::RwLock;
-use tracing::{error, info, instrument, warn};
+use tokio_util::io::ReaderStream;
+use tracing::{debug, error, info, instrument, warn};
 use unicode_normalization::UnicodeNormalization;
 use uuid::Uuid;
+use wana_kana::ConvertJapanese;
 use yomitan_format::kv_store::utils::ProgressStateTable;
-
-use crate::dictionaries::{DictionaryType, YomitanDictionaries};
+use yomitan_format::kv_store::ImportMode;
+use yomitan_format::normalization;
+
+use crate::audio_packs;
+use crate::audio_trim::TrimmedAudioCache;
+use crate::book_export;
+use crate::book_resources::{self, BookResourceCache};
+use crate::config::{ConfigHandle, LogLevels, LogLevelsState};
+use crate::csv_dictionaries::{
+    build_frequency_dictionary, build_pitch_dictionary, FrequencyImportSpec, GeneratedDictionary,
+    PitchImportSpec,
+};
+use crate::custom_dictionaries::{self, CustomDictionaryStore, CustomEntryInput};
+use crate::deinflect::{self, EntryMatch, Inflection};
+use crate::dict_db_scan_fs;
+use crate::dict_stats::DictionaryStats;
+use crate::dict_usage::DictionaryUsageTracker;
+use crate::dictionaries::{DictionaryType, RelatedEntries, TermCoverage, YomitanDictionaries};
+use crate::disk_space;
+use crate::ebook_convert;
+use crate::entry_notes::{self, EntryKey, EntryNote, EntryNotesSupabase, NewEntryNote};
+use crate::epub;
+use crate::glossary::{self, Glossary, GlossaryEntry, GlossaryOptions, GlossaryStore};
+use crate::guest::{self, Visitor};
+use crate::http_util::FileValidators;
+use crate::import_failures::{self, ImportFailureBundle};
 use crate::import_progress::{ImportProgressManager, ImportStatus};
-use crate::user_preferences::{UserPreferencesStoreAsync, UserPreferencesSupabase};
+use crate::integrations::{IntegrationsSupabase, Provider};
+use crate::levels::{JlptLevel, LevelData, TermLevels};
+use crate::library::{
+    self, LibraryFrequencyStore, LibraryJobKind, LibraryJobManager, LibraryTermCount,
+};
+use crate::library_search::LibrarySearch;
+use crate::library_shelf::{self, LibraryShelfStore, ShelfEntry, ShelfQuery};
+use crate::lookup_cache::{LookupCache, LookupCacheKey, WARM_POSITIONS};
+use crate::lookup_diagnostics::{DiagnosticsSettings, LookupRecord, LookupRecorder};
+use crate::mobi::{self, MobiFormat};
+use crate::pdf_import;
+use crate::pitch::PitchLevel;
+use crate::pronunciation;
+use crate::reading_stats::{self, ReadingStatsStore};
+use crate::scan_reports::{self, ScanReport};
+use crate::startup_timings::StartupTimings;
+use crate::static_assets::{AssetStorageReport, AssetStore};
+use crate::telemetry::{Telemetry, TelemetryEvent};
+use crate::tts::{self, TtsService};
+use crate::user_preferences::{
+    validate_audio_sources, PopupSettings, ReaderSettings, UserPreferences,
+    UserPreferencesStoreAsync, UserPreferencesSupabase,
+};
 use crate::users::UsersSupabase;
+use crate::verb_pairs::{self, Transitivity};
+use crate::vocab_lists::{
+    validate_list_name, NewVocabEntry, VocabList, VocabListsSupabase, MAX_PUBLIC_DECKS_PAGE_SIZE,
+};
 use crate::xml;
-use crate::{conversions, mecab};
-use crate::dict_db_scan_fs;
+use crate::{conversions, http_util, mecab};
 
 // Helper function to format duration in a human-readable way
 fn format_duration(duration: Duration) -> String {
@@ -62,7 +116,8 @@ fn resolve_python_interpreter() -> PathBuf {
     }
 
     // 2) Prefer project venv (relative to CWD at runtime)
-    let syosetu_dir = std::env::var("SYOSETU2EPUB_DIR").unwrap_or_else(|_| "syosetu2epub".to_string());
+    let syosetu_dir =
+        std::env::var("SYOSETU2EPUB_DIR").unwrap_or_else(|_| "syosetu2epub".to_string());
     let venv_rel = StdPath::new(&syosetu_dir).join(".venv/bin/python");
     if venv_rel.is_file() {
         // Use absolute path but don't canonicalize to avoid resolving symlinks
@@ -73,7 +128,7 @@ fn resolve_python_interpreter() -> PathBuf {
     // 3) Fallback to system python3 on PATH
     PathBuf::from("python3")
 }
-use audio_db_query::AudioDB;
+use audio_db_query::{AudioDB, AudioDBWriter, AudioEntry};
 
 /// Extract user ID from request headers (set by auth middleware)
 fn extract_user_id_from_headers(headers: &HeaderMap) -> Result<String, String> {
@@ -84,17 +139,38 @@ fn extract_user_id_from_headers(headers: &HeaderMap) -> Result<String, String> {
         .ok_or_else(|| "User ID not found in headers".to_string())
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct LookupTermRequest {
     pub term: String,
     pub position: i32,
+    /// Also look up the next few token boundaries in the background, so
+    /// lookups there are served from the cache
+    #[serde(default)]
+    pub warm: bool,
+    /// Upload id of the book the lookup is made in, whose glossary overlays
+    /// are consulted first
+    #[serde(default)]
+    pub book: Option<String>,
+    /// Also return the recordings of the entries found, with the one the
+    /// user's autoplay rules pick marked
+    #[serde(default)]
+    pub include_audio: bool,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct AudioQueryParams {
     pub term: String,
     pub reading: Option<String>,
+    /// Comma-separated list of sources to restrict results to (e.g. "nhk16,forvo")
+    pub sources: Option<String>,
+    /// Match recordings by reading (or the term if no reading is given) under
+    /// any expression, e.g. for kana-only terms or spellings with no audio
+    #[serde(default)]
+    pub reading_only: bool,
+    /// Downstep position of the accent being studied. Recordings of it come
+    /// first and are marked
+    pub pitch: Option<i64>,
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -103,6 +179,16 @@ pub struct PitchAccentEntry {
     pub reading: String,
     pub position: u32,
     pub mora_count: u32,
+    /// The reading split into morae
+    pub morae: Vec<String>,
+    /// Pitch of each mora, plus one for a following particle
+    pub pattern: Vec<PitchLevel>,
+    /// Indices into `morae` of nasal morae
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub nasal: Vec<usize>,
+    /// Indices into `morae` of devoiced morae
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub devoiced: Vec<usize>,
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -111,29 +197,32 @@ pub struct PitchAccentEntryList {
     pub entries: Vec<PitchAccentEntry>,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PitchAccentResult {
     pub title: String,
     pub entries: HashMap<String, PitchAccentEntryList>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct FrequencyData {
     pub term: String,
     pub reading: Option<String>,
     pub value: Option<i32>,
     pub display_value: Option<String>,
+    /// Label of the user's frequency band the rank falls in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub band: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct FrequencyDataList {
     pub items: Vec<FrequencyData>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
 pub enum Definition {
@@ -147,11 +236,11 @@ pub enum Definition {
     },
     Deinflection {
         base_form: String,
-        inflections: Vec<String>,
+        inflections: Vec<Inflection>,
     },
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct TermEntry {
     pub text: String,
@@ -162,29 +251,160 @@ pub struct TermEntry {
     pub definitions: Vec<Definition>,
     pub sequence_number: i64,
     pub term_tags: Vec<String>,
+    /// Found for a word guessed from the characters around a token the
+    /// tokenizer didn't know, so possibly not the word being read
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub heuristic: bool,
+    /// The text the entry was found for, as inflected in the text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched_form: Option<String>,
+    /// Inflections from the entry's term to `matched_form`, innermost first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inflections: Vec<Inflection>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct DictionaryResult {
     pub title: String,
     pub revision: String,
     pub origin: String,
     pub entries: Vec<TermEntry>,
+    /// Shown collapsed until expanded, per the user's popup settings
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub collapsed: bool,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct LookupTermResponse {
     pub dictionary_results: Vec<DictionaryResult>,
     pub pitch_accent_results: HashMap<String, PitchAccentResult>,
     pub frequency_data_lists: HashMap<String, FrequencyDataList>,
+    /// Counts from the user's imported library frequency list, keyed by term
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub library_frequency: HashMap<String, LibraryTermCount>,
+    /// Aggregate frequency rank of each term, keyed by term. Only computed
+    /// for users with frequency bands.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub frequency_ranks: HashMap<String, FrequencyRank>,
+    /// Words related to the terms found, e.g. the transitive or intransitive
+    /// counterpart of a verb
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub related_words: Vec<RelatedWord>,
+    /// Other spellings and kana forms of the lexemes found, grouped by the
+    /// sequence numbers dictionaries give their entries
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub related_entries: Vec<RelatedEntries>,
+    /// The user's notes on the entries found, and notes others have shared
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<EntryNote>,
+    /// JLPT level of each term and reading found, and levels of its kanji
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub levels: Vec<TermLevels>,
+    /// The terms found that the user knows, e.g. from WaniKani
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub known_terms: Vec<String>,
+    /// Recordings of the entries found, when asked for with `includeAudio`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub audio: Vec<TermAudio>,
+}
+
+/// The recordings of one term and reading of the lookup results
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TermAudio {
+    pub term: String,
+    pub reading: String,
+    pub audio_sources: Vec<AudioSource>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", tag = "relation")]
+pub enum Relation {
+    /// The other verb of a transitive/intransitive pair
+    TransitivityPair { transitivity: Transitivity },
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RelatedWord {
+    /// The term of the lookup results the word is related to
+    pub term: String,
+    pub related: String,
+    pub reading: String,
+    #[serde(flatten)]
+    pub relation: Relation,
+    /// Dictionary the related word was found in
+    pub dictionary: String,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FrequencyRank {
+    /// Harmonic mean of the ranks in the enabled rank-based dictionaries
+    pub rank: f64,
+    pub band: Option<String>,
+}
+
+impl LookupTermResponse {
+    /// Label the ranks of `rank_dictionaries` ("title#revision") and the
+    /// aggregate ranks with the user's frequency band they fall in.
+    /// Occurrence counts aren't ranks, so they're left alone.
+    pub fn apply_frequency_bands(
+        &mut self,
+        settings: &PopupSettings,
+        rank_dictionaries: &HashSet<String>,
+    ) {
+        let band = |rank: f64| settings.frequency_band(rank).map(|b| b.label.clone());
+        for (dictionary, list) in &mut self.frequency_data_lists {
+            if !rank_dictionaries.contains(dictionary) {
+                continue;
+            }
+            for item in &mut list.items {
+                item.band = item.value.and_then(|value| band(value as f64));
+            }
+        }
+        for rank in self.frequency_ranks.values_mut() {
+            rank.band = band(rank.rank);
+        }
+    }
+
+    /// Leave out what the user's popup doesn't show, so the response is
+    /// exactly what gets rendered
+    pub fn apply_popup_settings(&mut self, settings: &PopupSettings) {
+        if !settings.show_pitch_accent {
+            self.pitch_accent_results.clear();
+        }
+        if !settings.show_frequency {
+            self.frequency_data_lists.clear();
+            self.library_frequency.clear();
+            self.frequency_ranks.clear();
+        }
+        for result in &mut self.dictionary_results {
+            result.collapsed = settings
+                .collapsed_dictionaries
+                .contains(&format!("{}#{}", result.title, result.revision));
+            for entry in &mut result.entries {
+                if !settings.show_tags {
+                    entry.tags.clear();
+                    entry.term_tags.clear();
+                }
+                if let Some(max) = settings.max_definitions {
+                    entry.definitions.truncate(max);
+                }
+            }
+        }
+    }
 }
 
 #[derive(TryFromMultipart)]
 pub struct UploadBookRequest {
     #[form_data(limit = "unlimited")]
     file: NamedTempFile,
+    /// Upload id of the book in the library this upload replaces, which skips
+    /// the duplicate check
+    replace: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -210,26 +430,71 @@ pub struct UploadBookResponse {
     cover_path: Option<String>,
     toc: Vec<TableOfContentsEntry>,
     spine: Vec<String>,
+    /// See `library::epub_content_hash`
+    content_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replaces_upload_id: Option<String>,
 }
 
+/// A CSV/TSV file plus the JSON-encoded spec describing how to convert it
 #[derive(TryFromMultipart)]
-pub struct UploadDictRequest {
+pub struct ImportCsvDictRequest {
     #[form_data(limit = "unlimited")]
     file: NamedTempFile,
-    filename: String,
+    spec: String,
 }
 
 pub struct LookupTermContext {
     pub yomi_dicts: Arc<RwLock<YomitanDictionaries>>,
     pub tokenizer: Option<vibrato::Tokenizer>,
+    /// Lookups tokenizing or reading dictionary entries at once, on the
+    /// blocking pool (LOOKUP_BLOCKING_LIMIT)
+    pub lookup_permits: Arc<tokio::sync::Semaphore>,
+    /// Pronunciation audio database (AUDIO_DB_PATH), if configured. Replaced
+    /// by `POST /api/audio/reload`, requests in flight keep the one they loaded
+    pub audio_db: ArcSwapOption<AudioDB>,
+    /// Text-to-speech fallback for terms without recordings (TTS_BACKEND)
+    pub tts: Option<Arc<TtsService>>,
     pub user_preferences_db: Arc<RwLock<UserPreferencesSupabase>>,
     pub users_db: Arc<UsersSupabase>,
     pub import_progress_manager: Arc<ImportProgressManager>,
+    pub dict_usage: Arc<DictionaryUsageTracker>,
+    pub telemetry: Arc<Telemetry>,
+    pub vocab_lists_db: Arc<VocabListsSupabase>,
+    pub entry_notes_db: Arc<EntryNotesSupabase>,
+    /// Tokens of the learning services users connected, and the words synced
+    /// from them
+    pub integrations: Arc<IntegrationsSupabase>,
+    /// Root of the extracted user books (UPLOADS_DIR), if configured
+    pub uploads_dir: Option<PathBuf>,
+    /// Whether the syosetu2epub script worked at startup
+    pub webnovel_import: bool,
+    /// JLPT and Kanken levels (LEVELS_PATH), empty if no lists are installed
+    pub levels: Arc<LevelData>,
+    pub library_jobs: Arc<LibraryJobManager>,
+    pub library_frequency: Arc<LibraryFrequencyStore>,
+    pub glossaries: Arc<GlossaryStore>,
+    pub library_search: Arc<LibrarySearch>,
+    pub library_shelves: Arc<LibraryShelfStore>,
+    pub reading_stats: Arc<ReadingStatsStore>,
+    pub custom_dictionaries: Arc<CustomDictionaryStore>,
+    pub book_resources: Arc<BookResourceCache>,
+    /// How long this instance took to start, see `startup_timings`
+    pub startup_timings: Arc<StartupTimings>,
+    pub config: Arc<ConfigHandle>,
+    pub log_levels: Arc<LogLevels>,
+    pub lookup_recorder: Arc<LookupRecorder>,
+    pub lookup_cache: Arc<LookupCache<LookupCacheKey, LookupTermResponse>>,
 }
 
 #[derive(Deserialize)]
 pub struct ScanDictsQuery {
-    max_size_mb: Option<u64>,
+    /// Overrides `DICT_LOAD_BUDGET_MB` for this scan, see `loading_policy`
+    budget_mb: Option<u64>,
+    /// `lenient` skips malformed bank rows instead of storing them; `validate`
+    /// (with the `schema-validation` feature) reports every schema violation
+    #[serde(default)]
+    mode: ImportMode,
 }
 
 #[derive(Deserialize)]
@@ -243,6 +508,162 @@ pub async fn lookup_term(
     State(context): State<Arc<LookupTermContext>>,
     headers: HeaderMap,
     Json(payload): Json<LookupTermRequest>,
+) -> Result<Json<LookupTermResponse>, (StatusCode, Json<serde_json::Value>)> {
+    record_telemetry(&context, TelemetryEvent::Lookup).await;
+    let user_id = headers
+        .get("user_id")
+        .and_then(|h| h.to_str().ok())
+        .map(str::to_string);
+    if !context.lookup_recorder.should_record(user_id.as_deref()) {
+        return lookup_term_cached(&context, &headers, payload).await;
+    }
+
+    let request = serde_json::to_value(&payload).unwrap_or_default();
+    let started = Instant::now();
+    let result = lookup_term_cached(&context, &headers, payload).await;
+    let (status, response) = match &result {
+        Ok(Json(response)) => (
+            StatusCode::OK,
+            serde_json::to_value(response).unwrap_or_default(),
+        ),
+        Err((status, Json(body))) => (*status, body.clone()),
+    };
+    let record = LookupRecord::new(
+        user_id,
+        request,
+        status.as_u16(),
+        response,
+        started.elapsed(),
+    );
+    // Write in the background so diagnostics don't slow down the lookup
+    tokio::spawn(async move {
+        if let Err(e) = context.lookup_recorder.record(&record).await {
+            warn!(?e, "⚠️ Failed to record lookup");
+        }
+    });
+    result
+}
+
+/// Serve a lookup from the cache when possible, and start warming the
+/// following token boundaries if asked to
+async fn lookup_term_cached(
+    context: &Arc<LookupTermContext>,
+    headers: &HeaderMap,
+    payload: LookupTermRequest,
+) -> Result<Json<LookupTermResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let user_id = headers
+        .get("user_id")
+        .and_then(|h| h.to_str().ok())
+        .map(str::to_string);
+    if payload.warm {
+        spawn_warm_lookups(context.clone(), headers.clone(), &payload);
+    }
+
+    let key = LookupCacheKey {
+        user_id,
+        term: payload.term.clone(),
+        position: payload.position as usize,
+        book: payload.book.clone(),
+        include_audio: payload.include_audio,
+    };
+    if let Some(response) = context.lookup_cache.get(&key) {
+        debug!(term = %key.term, position = key.position, "🔥 Lookup served from cache");
+        return Ok(Json(response));
+    }
+    let result = lookup_term_inner(context, headers, payload, true).await;
+    if let Ok(Json(response)) = &result {
+        context.lookup_cache.insert(key, response.clone());
+    }
+    result
+}
+
+/// Look up the token boundaries after `payload.position` in the background and
+/// cache the results. These lookups don't count towards dictionary usage.
+fn spawn_warm_lookups(
+    context: Arc<LookupTermContext>,
+    headers: HeaderMap,
+    payload: &LookupTermRequest,
+) {
+    if context.tokenizer.is_none() {
+        return;
+    }
+    let position = payload.position as usize;
+    let user_id = headers
+        .get("user_id")
+        .and_then(|h| h.to_str().ok())
+        .map(str::to_string);
+    let term = payload.term.clone();
+    let book = payload.book.clone();
+    let include_audio = payload.include_audio;
+
+    tokio::spawn(async move {
+        let text = term.clone();
+        let Ok(positions) =
+            tokenize_blocking(&context, move |worker| mecab::token_starts(worker, &text)).await
+        else {
+            return;
+        };
+        let positions = positions
+            .into_iter()
+            .filter(|p| *p > position)
+            .take(WARM_POSITIONS);
+        for position in positions {
+            let key = LookupCacheKey {
+                user_id: user_id.clone(),
+                term: term.clone(),
+                position,
+                book: book.clone(),
+                include_audio,
+            };
+            if context.lookup_cache.contains(&key) {
+                continue;
+            }
+            let request = LookupTermRequest {
+                term: term.clone(),
+                position: position as i32,
+                warm: false,
+                book: book.clone(),
+                include_audio,
+            };
+            if let Ok(Json(response)) = lookup_term_inner(&context, &headers, request, false).await
+            {
+                context.lookup_cache.insert(key, response);
+            }
+        }
+    });
+}
+
+/// Run `f` with a worker of the lookup tokenizer on the blocking pool, once a
+/// lookup permit is free. Tokenizing long texts takes a while, which on the
+/// executor would stall unrelated requests.
+async fn tokenize_blocking<T: Send + 'static>(
+    context: &Arc<LookupTermContext>,
+    f: impl FnOnce(&mut vibrato::tokenizer::worker::Worker<'_>) -> T + Send + 'static,
+) -> Result<T, ApiError> {
+    let _permit = context
+        .lookup_permits
+        .acquire()
+        .await
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+    let blocking_context = context.clone();
+    tokio::task::spawn_blocking(move || {
+        let tokenizer = blocking_context.tokenizer.as_ref()?;
+        let mut worker = tokenizer.new_worker();
+        Some(f(&mut worker))
+    })
+    .await
+    .map_err(|e| {
+        error!(?e, "❌ Tokenizer task failed");
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to tokenize")
+    })?
+    .ok_or_else(|| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Tokenizer not loaded"))
+}
+
+async fn lookup_term_inner(
+    context: &Arc<LookupTermContext>,
+    headers: &HeaderMap,
+    payload: LookupTermRequest,
+    record_usage: bool,
 ) -> Result<Json<LookupTermResponse>, (StatusCode, Json<serde_json::Value>)> {
     let term = payload.term;
     let position = payload.position as usize;
@@ -254,17 +675,11 @@ pub async fn lookup_term(
         term.chars().nth(position).unwrap_or(' ')
     );
 
-    let mut worker = context
-        .tokenizer
-        .as_ref()
-        .ok_or_else(|| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({ "error": "Tokenizer not loaded" })),
-            )
-        })?
-        .new_worker();
-    let token_features = mecab::analyze_tokens(&mut worker, &term, position);
+    let text = term.clone();
+    let token_features = tokenize_blocking(context, move |worker| {
+        mecab::analyze_tokens(worker, &text, position)
+    })
+    .await?;
 
     // Get user preferences - either from authenticated user or use defaults
     let user_preferences = if let Some(user_id_header) = headers.get("user_id") {
@@ -282,7 +697,7 @@ pub async fn lookup_term(
             )
         })?;
 
-        context
+        let preferences = context
             .user_preferences_db
             .read()
             .await
@@ -296,27 +711,67 @@ pub async fn lookup_term(
                         serde_json::json!({ "error": format!("Failed to get user preferences: {e}") }),
                     ),
                 )
-            })?
+            })?;
+        Arc::new(preferences)
     } else {
         // User is not authenticated - use default preferences (all dictionaries enabled)
         info!("Using default preferences for unauthenticated request");
-        let dictionary_info = context.yomi_dicts.read().await.get_dictionaries_info();
-        // Use a nil UUID for anonymous users
-        crate::user_preferences::UserPreferences::default(Uuid::nil(), dictionary_info)
+        context.yomi_dicts.read().await.default_preferences()
     };
+    // Dictionary entries are parsed on the blocking pool, under a permit too
+    let permit = context
+        .lookup_permits
+        .acquire()
+        .await
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
     let lookup_result = context
         .yomi_dicts
         .read()
         .await
         .lookup(&token_features, &user_preferences)
-        .await
-        .map_err(|e| {
-            error!(?e, "Failed to lookup term");
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({ "error": format!("Failed to lookup term: {e}") })),
-            )
-        })?;
+        .await;
+    drop(permit);
+    let mut lookup_result = lookup_result.map_err(|e| {
+        error!(?e, "Failed to lookup term");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": format!("Failed to lookup term: {e}") })),
+        )
+    })?;
+
+    // Record which of the dictionaries consulted for this lookup produced results
+    if record_usage {
+        let queried_dicts: Vec<_> = context
+            .yomi_dicts
+            .read()
+            .await
+            .dictionaries_info()
+            .iter()
+            .filter(|d| {
+                d.dictionary_type == DictionaryType::Term
+                    && !user_preferences
+                        .term_disabled_dictionaries
+                        .contains(&format!("{}#{}", d.title, d.revision))
+            })
+            .cloned()
+            .collect();
+        context
+            .dict_usage
+            .record_lookup(&queried_dicts, &lookup_result.dict)
+            .await;
+    }
+
+    // The user's own dictionaries come first, overlays of the book before all
+    if let Some(user_id) = headers.get("user_id").and_then(|h| h.to_str().ok()) {
+        let custom_results = context
+            .custom_dictionaries
+            .lookup(user_id, payload.book.as_deref(), &token_features)
+            .unwrap_or_else(|e| {
+                warn!(?e, "⚠️ Failed to look up custom dictionaries");
+                Vec::new()
+            });
+        lookup_result.dict.splice(0..0, custom_results);
+    }
 
     info!(
         "📊 Search results: {} entries found. Top entry is {:?}",
@@ -324,15 +779,14 @@ pub async fn lookup_term(
         lookup_result
             .dict
             .first()
-            .map(|d| d.entries.first().map(|e| e.text.clone()))
-            .flatten()
+            .and_then(|d| d.entries.first().map(|e| e.text.clone()))
     );
 
     if lookup_result.dict.is_empty() {
-        return Err((
+        Err((
             StatusCode::NOT_FOUND,
             Json(serde_json::json!({ "error": "No dictionary entries found" })),
-        ));
+        ))
     } else {
         let mut pitch_accent_results: HashMap<String, PitchAccentResult> = HashMap::new();
         for (term, result) in lookup_result.pitch.iter() {
@@ -357,28 +811,213 @@ pub async fn lookup_term(
             );
         }
 
-        Ok(Json(LookupTermResponse {
-            dictionary_results: lookup_result
-                .dict
-                .iter()
-                .map(conversions::convert_dictionary_result)
-                .collect(),
+        let result_terms: Vec<&str> = lookup_result
+            .dict
+            .iter()
+            .flat_map(|d| d.entries.iter().map(|e| e.text.as_str()))
+            .collect();
+        let library_frequency = match headers.get("user_id").and_then(|h| h.to_str().ok()) {
+            Some(user_id) => context
+                .library_frequency
+                .lookup(user_id, &result_terms)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!(?e, "⚠️ Failed to load library frequency list");
+                    HashMap::new()
+                }),
+            None => HashMap::new(),
+        };
+        let frequency_bands = &user_preferences.popup.frequency_bands;
+        let (rank_dictionaries, frequency_ranks) = if frequency_bands.is_empty() {
+            (HashSet::new(), HashMap::new())
+        } else {
+            let yomi_dicts = context.yomi_dicts.read().await;
+            let terms: HashSet<&str> = result_terms.iter().copied().collect();
+            let ranks = yomi_dicts
+                .frequency_ranks(terms, &user_preferences)
+                .unwrap_or_else(|e| {
+                    warn!(?e, "⚠️ Failed to rank result terms by frequency");
+                    HashMap::new()
+                });
+            (
+                yomi_dicts.rank_frequency_dictionary_keys(&user_preferences),
+                ranks
+                    .into_iter()
+                    .map(|(term, rank)| (term, FrequencyRank { rank, band: None }))
+                    .collect(),
+            )
+        };
+
+        let mut matches: HashMap<String, Option<EntryMatch>> = HashMap::new();
+        let mut dictionary_results: Vec<DictionaryResult> = lookup_result
+            .dict
+            .iter()
+            .map(conversions::convert_dictionary_result)
+            .collect();
+        for entry in dictionary_results.iter_mut().flat_map(|d| &mut d.entries) {
+            let entry_match = matches
+                .entry(entry.text.clone())
+                .or_insert_with(|| deinflect::match_entry(&token_features, &entry.text));
+            if let Some(entry_match) = entry_match {
+                entry.matched_form = Some(entry_match.matched_form.clone());
+                entry.inflections = entry_match.inflections.clone();
+            }
+        }
+
+        let (related_words, related_entries) = {
+            let yomi_dicts = context.yomi_dicts.read().await;
+            let related_entries = yomi_dicts
+                .related_entries(&lookup_result.dict)
+                .unwrap_or_else(|e| {
+                    warn!(?e, "⚠️ Failed to gather variants of the result terms");
+                    Vec::new()
+                });
+            (
+                verb_counterparts(&yomi_dicts, &dictionary_results, &user_preferences),
+                related_entries,
+            )
+        };
+
+        let notes = match headers.get("user_id").and_then(|h| h.to_str().ok()) {
+            Some(user_id) => {
+                let entries: Vec<EntryKey> = dictionary_results
+                    .iter()
+                    .flat_map(|d| {
+                        d.entries.iter().map(|e| EntryKey {
+                            dictionary: d.title.clone(),
+                            term: e.text.clone(),
+                            reading: e.reading.clone(),
+                        })
+                    })
+                    .collect::<HashSet<_>>()
+                    .into_iter()
+                    .collect();
+                context
+                    .entry_notes_db
+                    .notes_for_entries(user_id, &entries)
+                    .await
+                    .unwrap_or_else(|e| {
+                        warn!(?e, "⚠️ Failed to load entry notes");
+                        Vec::new()
+                    })
+            }
+            None => Vec::new(),
+        };
+
+        let known_terms = match headers.get("user_id").and_then(|h| h.to_str().ok()) {
+            Some(user_id) => {
+                let mut seen = HashSet::new();
+                let terms: Vec<&str> = dictionary_results
+                    .iter()
+                    .flat_map(|d| d.entries.iter().map(|e| e.text.as_str()))
+                    .filter(|term| seen.insert(*term))
+                    .collect();
+                match context.integrations.known_terms(user_id, &terms).await {
+                    Ok(known) => terms
+                        .into_iter()
+                        .filter(|term| known.contains(*term))
+                        .map(String::from)
+                        .collect(),
+                    Err(e) => {
+                        warn!(?e, "⚠️ Failed to load known words");
+                        Vec::new()
+                    }
+                }
+            }
+            None => Vec::new(),
+        };
+
+        let mut seen = HashSet::new();
+        let levels = dictionary_results
+            .iter()
+            .flat_map(|d| &d.entries)
+            .filter(|e| seen.insert((e.text.as_str(), e.reading.as_str())))
+            .filter_map(|e| context.levels.term_levels(&e.text, &e.reading))
+            .collect();
+
+        let audio = if payload.include_audio {
+            lookup_audio(
+                context,
+                &dictionary_results,
+                &pitch_accent_results,
+                &user_preferences,
+            )
+        } else {
+            Vec::new()
+        };
+
+        let mut response = LookupTermResponse {
+            notes,
+            levels,
+            known_terms,
+            audio,
+            library_frequency,
+            frequency_ranks,
+            related_words,
+            related_entries,
+            dictionary_results,
             frequency_data_lists: conversions::convert_frequency_data(&lookup_result.freq),
             pitch_accent_results,
-        }))
+        };
+        response.apply_frequency_bands(&user_preferences.popup, &rank_dictionaries);
+        response.apply_popup_settings(&user_preferences.popup);
+        Ok(Json(response))
+    }
+}
+
+/// Transitive or intransitive counterparts of the verbs among `results` that
+/// the user's dictionaries have entries for
+fn verb_counterparts(
+    yomi_dicts: &YomitanDictionaries,
+    results: &[DictionaryResult],
+    user_preferences: &crate::user_preferences::UserPreferences,
+) -> Vec<RelatedWord> {
+    let mut seen = HashSet::new();
+    let mut related_words = Vec::new();
+    let verbs = results.iter().flat_map(|d| &d.entries).filter(|e| {
+        e.rule_identifiers.split_whitespace().any(|r| {
+            deinflect::word_class::from_rule_identifier(r)
+                .is_some_and(|class| class & deinflect::word_class::VERB != 0)
+        })
+    });
+    for entry in verbs {
+        if !seen.insert(entry.text.as_str()) {
+            continue;
+        }
+        for (counterpart, transitivity) in verb_pairs::counterparts(&entry.text) {
+            match yomi_dicts.lookup_first_entry(counterpart, user_preferences) {
+                Ok(Some((dictionary, counterpart_entry))) => related_words.push(RelatedWord {
+                    term: entry.text.clone(),
+                    related: counterpart.to_string(),
+                    reading: counterpart_entry.reading.to_hiragana(),
+                    relation: Relation::TransitivityPair {
+                        transitivity: *transitivity,
+                    },
+                    dictionary,
+                }),
+                Ok(None) => {}
+                Err(e) => warn!(?e, counterpart, "⚠️ Failed to look up verb counterpart"),
+            }
+        }
     }
+    related_words
 }
 
+/// Read the metadata of an EPUB about to be added to the library. Uploads of
+/// a book already in the library are rejected with 409 and the existing book,
+/// unless they are meant to replace it (keeping its upload id, so reading
+/// progress and highlights stay with the book).
 pub async fn upload_book(
+    State(context): State<Arc<LookupTermContext>>,
     headers: HeaderMap,
     TypedMultipart(upload): TypedMultipart<UploadBookRequest>,
 ) -> Result<Json<UploadBookResponse>, (StatusCode, Json<serde_json::Value>)> {
-    let user_id = headers.get("user_id").unwrap().to_str().unwrap();
-    let user_id = Uuid::parse_str(user_id).unwrap();
-    info!(?user_id, "Processing uploaded EPUB file");
+    let user_id = require_user_id(&headers)?;
+    record_telemetry(&context, TelemetryEvent::BookUpload).await;
+    info!(%user_id, "Processing uploaded EPUB file");
     let temp_path = upload.file.path();
 
-    let res = get_book_metadata(temp_path).map_err(|e| {
+    let mut res = get_book_metadata(temp_path).map_err(|e| {
         error!(?e, "Failed to get book metadata");
         (
             StatusCode::BAD_REQUEST,
@@ -390,63 +1029,366 @@ pub async fn upload_book(
         author = res.author,
         "Successfully parsed EPUB"
     );
+
+    // Without the extracted books there is nothing to compare against
+    let Some(uploads_dir) = context.uploads_dir.clone() else {
+        res.replaces_upload_id = upload.replace;
+        return Ok(Json(res));
+    };
+    let library_dir = library::user_library_dir(&uploads_dir, &user_id)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    let books = library::list_books(&library_dir).map_err(|e| {
+        error!(?e, "❌ Failed to list library books");
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read library")
+    })?;
+
+    if let Some(upload_id) = upload.replace {
+        if !books.iter().any(|b| b.upload_id == upload_id) {
+            return Err(api_error(StatusCode::NOT_FOUND, "Book not found"));
+        }
+        let content_hash = res.content_hash.clone();
+        context
+            .library_shelves
+            .update(&user_id, |shelf| {
+                shelf.book_mut(&upload_id).content_hash = Some(content_hash);
+            })
+            .await
+            .map_err(library_shelf_error)?;
+        info!(%upload_id, "Upload replaces a book of the library");
+        res.replaces_upload_id = Some(upload_id);
+        return Ok(Json(res));
+    }
+
+    let shelf = context
+        .library_shelves
+        .load(&user_id)
+        .await
+        .map_err(library_shelf_error)?;
+    let known_hashes: HashMap<String, String> = shelf
+        .books
+        .into_iter()
+        .filter_map(|(upload_id, book)| Some((upload_id, book.content_hash?)))
+        .collect();
+    let known_count = known_hashes.len();
+    let (content_hash, title, author) = (
+        res.content_hash.clone(),
+        res.title.clone(),
+        res.author.clone(),
+    );
+    let (duplicate, content_hashes) = tokio::task::spawn_blocking(move || {
+        let mut content_hashes = known_hashes;
+        library::find_duplicate(
+            &books,
+            &content_hash,
+            (&title, &author),
+            &mut content_hashes,
+        )
+        .map(|duplicate| (duplicate, content_hashes))
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|r| r)
+    .map_err(|e| {
+        error!(?e, "❌ Failed to check for duplicate books");
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read library")
+    })?;
+
+    // Hashing a whole library is slow, so keep the hashes for next time
+    if content_hashes.len() > known_count {
+        let result = context
+            .library_shelves
+            .update(&user_id, |shelf| {
+                for (upload_id, hash) in content_hashes {
+                    shelf.book_mut(&upload_id).content_hash = Some(hash);
+                }
+            })
+            .await;
+        if let Err(e) = result {
+            warn!(?e, "⚠️ Failed to save book content hashes");
+        }
+    }
+
+    if let Some(duplicate) = duplicate {
+        info!(upload_id = %duplicate.upload_id, matched_by = ?duplicate.matched_by, "Upload is already in the library");
+        return Err((
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "error": "This book is already in your library",
+                "duplicate": duplicate,
+            })),
+        ));
+    }
     Ok(Json(res))
 }
 
-pub async fn webnovel_start(
-    State(context): State<Arc<LookupTermContext>>,
-    Query(params): Query<WebnovelQuery>,
-    headers: HeaderMap,
-) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
-    info!(url = ?params.url, "=== Starting webnovel import request ===");
+#[derive(TryFromMultipart)]
+pub struct ConvertBookRequest {
+    #[form_data(limit = "unlimited")]
+    file: FieldData<NamedTempFile>,
+}
 
-    // Extract user ID from JWT token
-    let user_id = match extract_user_id_from_headers(&headers) {
-        Ok(id) => id,
-        Err(e) => {
-            error!(?e, "Failed to extract user ID from headers");
-            return Err((
-                StatusCode::UNAUTHORIZED,
-                Json(serde_json::json!({ "error": "Unauthorized" })),
-            ));
-        }
-    };
+/// Name of the converted book in the import's directory
+const CONVERTED_EPUB_NAME: &str = "converted.epub";
 
-    // Check if user already has an active import
+/// Convert an uploaded MOBI or AZW3 book to EPUB in the background. The
+/// conversion is tracked as an import; once it has completed the EPUB is
+/// fetched from `/api/upload/convert/:import_id` and uploaded like any other.
+#[instrument(skip(context, headers, upload))]
+pub async fn start_book_conversion(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    TypedMultipart(upload): TypedMultipart<ConvertBookRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
     if context
         .import_progress_manager
         .has_active_imports(&user_id)
         .await
     {
-        error!(user_id = %user_id, "User already has an active import");
-        return Err((
+        return Err(api_error(
             StatusCode::CONFLICT,
-            Json(serde_json::json!({
-                "error": "You already have an import in progress. Please wait for it to complete before starting a new one."
-            })),
+            "You already have an import in progress. Please wait for it to complete before starting a new one.",
         ));
     }
 
-    // Clean the URL: strip whitespace and trailing slashes
-    let cleaned_url = params.url.trim().trim_end_matches('/');
-    info!(original_url = ?params.url, cleaned_url = ?cleaned_url, "URL cleaned");
-
-    // Start tracking import progress
+    let mut header = Vec::with_capacity(4096);
+    let read = match tokio::fs::File::open(upload.file.contents.path()).await {
+        Ok(file) => file.take(4096).read_to_end(&mut header).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = read {
+        error!(?e, "❌ Failed to read uploaded book");
+        return Err(api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read upload",
+        ));
+    }
+    let format = mobi::detect(&header)
+        .ok_or_else(|| api_error(StatusCode::BAD_REQUEST, "Not a MOBI or AZW3 book"))?;
+
+    let file_name = upload
+        .file
+        .metadata
+        .file_name
+        .clone()
+        .unwrap_or_else(|| format!("book.{}", format.extension()));
     let import_id = context
         .import_progress_manager
-        .start_import(user_id.clone(), cleaned_url.to_string())
+        .start_import(user_id.clone(), file_name.clone())
         .await;
-    info!(import_id = %import_id, user_id = %user_id, "Started tracking import progress");
-
-    // Clone context for background task
-    let context_clone = context.clone();
-    let cleaned_url_clone = cleaned_url.to_string();
-    let import_id_clone = import_id.clone();
 
-    // Spawn background task to handle the actual import
-    tokio::spawn(async move {
-        webnovel_import_task(context_clone, cleaned_url_clone, import_id_clone).await;
-    });
+    // Conversions share the per-import directories of webnovel imports, so
+    // they're cleared on startup too
+    let import_dir = webnovel_import_dir(&import_id);
+    let source = import_dir.join(format!("source.{}", format.extension()));
+    let copied = match tokio::fs::create_dir_all(&import_dir).await {
+        Ok(()) => tokio::fs::copy(upload.file.contents.path(), &source).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = copied {
+        error!(?e, ?import_dir, "❌ Failed to store uploaded book");
+        context
+            .import_progress_manager
+            .update_status(
+                &import_id,
+                ImportStatus::Failed(format!("Failed to store upload: {e}")),
+            )
+            .await;
+        return Err(api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to store upload",
+        ));
+    }
+
+    info!(%import_id, %user_id, %file_name, format = format.name(), "📚 Starting book conversion");
+    tokio::spawn(book_conversion_task(context, import_id, source, format));
+    Ok(Json(serde_json::json!({
+        "status": "accepted",
+        "import_id": import_id,
+    })))
+}
+
+async fn book_conversion_task(
+    context: Arc<LookupTermContext>,
+    import_id: Uuid,
+    source: PathBuf,
+    format: MobiFormat,
+) {
+    let progress = &context.import_progress_manager;
+    let converter = ebook_convert::converter(&context.config.current());
+    progress
+        .update_status(&import_id, ImportStatus::Processing)
+        .await;
+    progress
+        .add_log(
+            &import_id,
+            format!(
+                "Converting {} to EPUB with {}...",
+                format.name(),
+                converter.name()
+            ),
+        )
+        .await;
+
+    let output = source.with_file_name(CONVERTED_EPUB_NAME);
+    let converted = converter.convert(&source, format, &output).await;
+    if let Err(e) = tokio::fs::remove_file(&source).await {
+        warn!(?e, ?source, "⚠️ Failed to remove converted upload");
+    }
+    if let Err(e) = converted {
+        error!(?e, %import_id, converter = converter.name(), "❌ Book conversion failed");
+        progress
+            .update_status(
+                &import_id,
+                ImportStatus::Failed(format!("Conversion failed: {e}")),
+            )
+            .await;
+        return;
+    }
+    // Not EpubGenerated, which the import viewer takes for a finished
+    // webnovel download
+    progress
+        .add_log(&import_id, "Reading book metadata...".to_string())
+        .await;
+
+    // Run the converted book through the same metadata pipeline as uploads, so
+    // a broken conversion fails here rather than halfway through the upload
+    let metadata = tokio::task::spawn_blocking(move || get_book_metadata(&output))
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|r| r);
+    match metadata {
+        Ok(metadata) => {
+            progress
+                .add_log(
+                    &import_id,
+                    format!(
+                        "Converted \"{}\" by {} ({} pages)",
+                        metadata.title, metadata.author, metadata.total_pages
+                    ),
+                )
+                .await;
+            progress
+                .update_status(&import_id, ImportStatus::Completed)
+                .await;
+            info!(%import_id, title = metadata.title, "✅ Book conversion completed");
+        }
+        Err(e) => {
+            error!(?e, %import_id, "❌ Converted book is invalid");
+            progress
+                .update_status(
+                    &import_id,
+                    ImportStatus::Failed(format!("Converted book is invalid: {e}")),
+                )
+                .await;
+        }
+    }
+}
+
+/// Download the EPUB of a completed conversion. The import's files are removed
+/// afterwards, so a conversion can only be downloaded once.
+#[instrument(skip(context, headers))]
+pub async fn download_converted_book(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(import_id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let import = context
+        .import_progress_manager
+        .get_progress(&import_id)
+        .await
+        .filter(|import| import.user_id == user_id)
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Import not found"))?;
+    if import.status != ImportStatus::Completed {
+        return Err(api_error(
+            StatusCode::CONFLICT,
+            "The conversion hasn't completed",
+        ));
+    }
+
+    let import_dir = webnovel_import_dir(&import_id);
+    let epub = tokio::fs::read(import_dir.join(CONVERTED_EPUB_NAME))
+        .await
+        .map_err(|e| {
+            warn!(?e, %import_id, "⚠️ Converted book not found");
+            api_error(StatusCode::NOT_FOUND, "Converted book not found")
+        })?;
+    if let Err(e) = tokio::fs::remove_dir_all(&import_dir).await {
+        warn!(?e, ?import_dir, "⚠️ Failed to remove conversion directory");
+    }
+
+    let stem = StdPath::new(&import.url)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| import_id.to_string());
+    Response::builder()
+        .header("Content-Type", "application/epub+zip")
+        .header(
+            "Content-Disposition",
+            http_util::attachment_disposition(
+                &format!("{stem}.epub"),
+                Some(&format!("{import_id}.epub")),
+            ),
+        )
+        .body(Body::from(epub))
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))
+}
+
+pub async fn webnovel_start(
+    State(context): State<Arc<LookupTermContext>>,
+    Query(params): Query<WebnovelQuery>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    info!(url = ?params.url, "=== Starting webnovel import request ===");
+
+    // Extract user ID from JWT token
+    let user_id = match extract_user_id_from_headers(&headers) {
+        Ok(id) => id,
+        Err(e) => {
+            error!(?e, "Failed to extract user ID from headers");
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({ "error": "Unauthorized" })),
+            ));
+        }
+    };
+
+    // Check if user already has an active import
+    if context
+        .import_progress_manager
+        .has_active_imports(&user_id)
+        .await
+    {
+        error!(user_id = %user_id, "User already has an active import");
+        return Err((
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "error": "You already have an import in progress. Please wait for it to complete before starting a new one."
+            })),
+        ));
+    }
+
+    // Clean the URL: strip whitespace and trailing slashes
+    let cleaned_url = params.url.trim().trim_end_matches('/');
+    info!(original_url = ?params.url, cleaned_url = ?cleaned_url, "URL cleaned");
+
+    // Start tracking import progress
+    let import_id = context
+        .import_progress_manager
+        .start_import(user_id.clone(), cleaned_url.to_string())
+        .await;
+    info!(import_id = %import_id, user_id = %user_id, "Started tracking import progress");
+
+    // Clone context for background task
+    let context_clone = context.clone();
+    let cleaned_url_clone = cleaned_url.to_string();
+    let import_id_clone = import_id.clone();
+
+    // Spawn background task to handle the actual import
+    tokio::spawn(async move {
+        webnovel_import_task(context_clone, cleaned_url_clone, import_id_clone).await;
+    });
 
     // Return OK immediately
     info!(import_id = %import_id, "Webnovel import request accepted, processing in background");
@@ -456,6 +1398,82 @@ pub async fn webnovel_start(
     })))
 }
 
+/// Where syosetu2epub writes EPUBs; cleared on startup
+fn webnovel_output_dir() -> PathBuf {
+    PathBuf::from(
+        std::env::var("WEBNOVEL_TEMP_OUTPUT_DIR")
+            .unwrap_or_else(|_| std::env::temp_dir().to_string_lossy().to_string()),
+    )
+}
+
+/// Each import gets its own directory, since long novels may be split into
+/// several EPUBs
+fn webnovel_import_dir(import_id: &Uuid) -> PathBuf {
+    webnovel_output_dir().join(import_id.to_string())
+}
+
+/// Compare filenames with runs of digits by value, so `vol2.epub` comes before
+/// `vol10.epub`
+fn cmp_volume_filenames(a: &str, b: &str) -> std::cmp::Ordering {
+    fn chunks(s: &str) -> Vec<(bool, &str)> {
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        for (i, c) in s.char_indices().skip(1) {
+            let prev = s[..i].chars().next_back().unwrap_or_default();
+            if c.is_ascii_digit() != prev.is_ascii_digit() {
+                chunks.push((
+                    s[start..i].starts_with(|c: char| c.is_ascii_digit()),
+                    &s[start..i],
+                ));
+                start = i;
+            }
+        }
+        if start < s.len() {
+            chunks.push((
+                s[start..].starts_with(|c: char| c.is_ascii_digit()),
+                &s[start..],
+            ));
+        }
+        chunks
+    }
+    for (x, y) in chunks(a).into_iter().zip(chunks(b)) {
+        let ordering = match (x, y) {
+            ((true, x), (true, y)) => {
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            ((_, x), (_, y)) => x.cmp(y),
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+/// EPUBs generated in `dir`, in volume order
+fn list_epub_files(dir: &StdPath) -> std::io::Result<Vec<PathBuf>> {
+    let mut epub_files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| {
+            entry.ok().and_then(|entry| {
+                let path = entry.path();
+                if path.extension().and_then(|s| s.to_str()) == Some("epub") {
+                    Some(path)
+                } else {
+                    None
+                }
+            })
+        })
+        .collect();
+    epub_files.sort_by(|a, b| {
+        cmp_volume_filenames(
+            &a.file_name().unwrap_or_default().to_string_lossy(),
+            &b.file_name().unwrap_or_default().to_string_lossy(),
+        )
+    });
+    Ok(epub_files)
+}
+
 async fn webnovel_import_task(
     context: Arc<LookupTermContext>,
     cleaned_url: String,
@@ -480,7 +1498,8 @@ async fn webnovel_import_task(
         .await;
 
     // Get the path to the syosetu2epub script
-    let syosetu_base = std::env::var("SYOSETU2EPUB_DIR").unwrap_or_else(|_| "./syosetu2epub".to_string());
+    let syosetu_base =
+        std::env::var("SYOSETU2EPUB_DIR").unwrap_or_else(|_| "./syosetu2epub".to_string());
     let syosetu_script_path = std::env::var("SYOSETU_SCRIPT_PATH")
         .unwrap_or_else(|_| format!("{}/syosetu2epub.py", syosetu_base));
     info!(script_path = ?syosetu_script_path, "Using syosetu2epub script path");
@@ -512,10 +1531,20 @@ async fn webnovel_import_task(
         )
         .await;
 
-    // Get output directory from environment variable
-    let output_dir = std::env::var("WEBNOVEL_TEMP_OUTPUT_DIR")
-        .unwrap_or_else(|_| std::env::temp_dir().to_string_lossy().to_string());
+    // Each import writes into its own directory under WEBNOVEL_TEMP_OUTPUT_DIR
+    let output_dir = webnovel_import_dir(&import_id);
     info!(output_dir = ?output_dir, "Using output directory for EPUB files");
+    if let Err(e) = std::fs::create_dir_all(&output_dir) {
+        error!(?e, output_dir = ?output_dir, "Failed to create output directory");
+        context
+            .import_progress_manager
+            .update_status(
+                &import_id,
+                ImportStatus::Failed(format!("Failed to create output directory: {e}")),
+            )
+            .await;
+        return;
+    }
 
     let mut cmd = tokio::process::Command::new(&python_path);
 
@@ -734,11 +1763,8 @@ async fn webnovel_import_task(
     });
 
     // Wait for the process to complete with timeout
-    // Get timeout from environment variable, default to 30 minutes for long novels
-    let timeout_seconds = std::env::var("WEBNOVEL_TIMEOUT_SECONDS")
-        .unwrap_or_else(|_| "1800".to_string()) // 30 minutes default
-        .parse::<u64>()
-        .unwrap_or(1800); // fallback to 30 minutes if parsing fails
+    // (WEBNOVEL_TIMEOUT_SECONDS, 30 minutes by default for long novels)
+    let timeout_seconds = context.config.current().webnovel_timeout_seconds;
 
     info!(
         timeout_seconds = timeout_seconds,
@@ -866,21 +1892,10 @@ async fn webnovel_import_task(
         )
         .await;
 
-    // Find the generated EPUB file in the output directory
+    // Find the generated EPUB files in the output directory
     info!(output_dir = ?output_dir, "Searching for generated EPUB files in output directory");
-    let epub_files: Vec<_> = match std::fs::read_dir(&output_dir) {
-        Ok(entries) => entries
-            .filter_map(|entry| {
-                entry.ok().and_then(|entry| {
-                    let path = entry.path();
-                    if path.extension().and_then(|s| s.to_str()) == Some("epub") {
-                        Some(path)
-                    } else {
-                        None
-                    }
-                })
-            })
-            .collect(),
+    let epub_files = match list_epub_files(&output_dir) {
+        Ok(epub_files) => epub_files,
         Err(e) => {
             error!(?e, output_dir = ?output_dir, "Failed to read output directory");
             let error_msg = format!("Failed to read output directory: {e}");
@@ -911,72 +1926,53 @@ async fn webnovel_import_task(
         return; // Exit the background task
     }
 
-    let epub_path = &epub_files[0];
-    info!(epub_path = ?epub_path, "Using first EPUB file");
-
-    // Extract metadata from the generated EPUB
-    info!(epub_path = ?epub_path, "Extracting metadata from EPUB");
-    let metadata = match get_book_metadata(epub_path) {
-        Ok(metadata) => metadata,
-        Err(e) => {
-            error!(?e, epub_path = ?epub_path, "Failed to extract metadata from generated EPUB");
-            let error_msg = format!("Failed to extract metadata: {e}");
-            context
-                .import_progress_manager
-                .update_status(&import_id, ImportStatus::Failed(error_msg))
-                .await;
-            return; // Exit the background task
-        }
-    };
-    info!(
-        title = %metadata.title,
-        author = %metadata.author,
-        total_pages = metadata.total_pages,
-        toc_entries = metadata.toc.len(),
-        spine_entries = metadata.spine.len(),
-        "Successfully extracted metadata"
-    );
+    // Check that every generated EPUB can be read before reporting success
+    let mut total_size = 0;
+    for epub_path in &epub_files {
+        info!(epub_path = ?epub_path, "Extracting metadata from EPUB");
+        let metadata = match get_book_metadata(epub_path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                error!(?e, epub_path = ?epub_path, "Failed to extract metadata from generated EPUB");
+                let error_msg = format!("Failed to extract metadata: {e}");
+                context
+                    .import_progress_manager
+                    .update_status(&import_id, ImportStatus::Failed(error_msg))
+                    .await;
+                return; // Exit the background task
+            }
+        };
+        info!(
+            title = %metadata.title,
+            author = %metadata.author,
+            total_pages = metadata.total_pages,
+            toc_entries = metadata.toc.len(),
+            spine_entries = metadata.spine.len(),
+            "Successfully extracted metadata"
+        );
+        total_size += std::fs::metadata(epub_path).map(|m| m.len()).unwrap_or(0);
+    }
 
-    // Read the EPUB file content
-    info!(epub_path = ?epub_path, "Reading EPUB file content");
-    let epub_content = match tokio::fs::read(epub_path).await {
-        Ok(content) => content,
-        Err(e) => {
-            error!(?e, epub_path = ?epub_path, "Failed to read generated EPUB file");
-            let error_msg = format!("Failed to read EPUB file: {e}");
-            context
-                .import_progress_manager
-                .update_status(&import_id, ImportStatus::Failed(error_msg))
-                .await;
-            return; // Exit the background task
-        }
+    // EPUBs are ready - status already set to EpubGenerated above
+    let log = if epub_files.len() == 1 {
+        "EPUB generated and ready for upload".to_string()
+    } else {
+        format!(
+            "{} EPUB volumes generated and ready for upload",
+            epub_files.len()
+        )
     };
-    info!(
-        epub_size_bytes = epub_content.len(),
-        "Successfully read EPUB file"
-    );
-
-    // Get the filename
-    let filename = epub_path
-        .file_name()
-        .and_then(|name| name.to_str())
-        .unwrap_or("webnovel.epub");
-    info!(filename = %filename, "Determined filename");
-
-    // EPUB is already in the output directory, no need to copy or delete
-    info!(epub_path = ?epub_path, "EPUB file is ready for serving from output directory");
-
-    // EPUB is ready - status already set to EpubGenerated above
     context
         .import_progress_manager
-        .add_log(
-            &import_id,
-            "EPUB generated and ready for upload".to_string(),
-        )
+        .add_log(&import_id, log)
         .await;
 
     // Background task completed successfully
-    info!(filename = %filename, epub_size_bytes = epub_content.len(), "=== Webnovel import completed successfully ===");
+    info!(
+        epub_count = epub_files.len(),
+        epub_size_bytes = total_size,
+        "=== Webnovel import completed successfully ==="
+    );
 }
 
 pub async fn webnovel_fetch(
@@ -1034,29 +2030,15 @@ pub async fn webnovel_fetch(
         .update_status(&import.id, ImportStatus::Processing)
         .await;
 
-    // Get the output directory and find the EPUB file
-    let output_dir = std::env::var("WEBNOVEL_TEMP_OUTPUT_DIR")
-        .unwrap_or_else(|_| std::env::temp_dir().to_string_lossy().to_string());
-
-    let epub_files: Vec<_> = std::fs::read_dir(&output_dir)
-        .map_err(|e| {
-            error!(?e, output_dir = ?output_dir, "Failed to read output directory");
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({ "error": "Failed to read output directory" })),
-            )
-        })?
-        .filter_map(|entry| {
-            entry.ok().and_then(|entry| {
-                let path = entry.path();
-                if path.extension().and_then(|s| s.to_str()) == Some("epub") {
-                    Some(path)
-                } else {
-                    None
-                }
-            })
-        })
-        .collect();
+    // Find the EPUB files generated for this import
+    let output_dir = webnovel_import_dir(&import.id);
+    let epub_files = list_epub_files(&output_dir).map_err(|e| {
+        error!(?e, output_dir = ?output_dir, "Failed to read output directory");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": "Failed to read output directory" })),
+        )
+    })?;
 
     if epub_files.is_empty() {
         error!(output_dir = ?output_dir, "No EPUB files found");
@@ -1066,55 +2048,70 @@ pub async fn webnovel_fetch(
         ));
     }
 
-    let epub_path = &epub_files[0];
-    info!(epub_path = ?epub_path, "Using first EPUB file");
-
-    // Extract metadata from the generated EPUB
-    let metadata = get_book_metadata(epub_path).map_err(|e| {
-        error!(?e, epub_path = ?epub_path, "Failed to extract metadata from generated EPUB");
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({ "error": format!("Failed to extract metadata: {e}") })),
-        )
-    })?;
-
-    // Read the EPUB file content
-    let epub_content = tokio::fs::read(epub_path).await.map_err(|e| {
-        error!(?e, epub_path = ?epub_path, "Failed to read generated EPUB file");
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({ "error": format!("Failed to read EPUB file: {e}") })),
-        )
-    })?;
-
-    // Get the filename
-    let filename = epub_path
-        .file_name()
-        .and_then(|name| name.to_str())
-        .unwrap_or("webnovel.epub");
+    // Long novels may be split into several volumes
+    let mut files = Vec::new();
+    for epub_path in &epub_files {
+        let metadata = get_book_metadata(epub_path).map_err(|e| {
+            error!(?e, epub_path = ?epub_path, "Failed to extract metadata from generated EPUB");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "error": format!("Failed to extract metadata: {e}") })),
+            )
+        })?;
+        let size_bytes = tokio::fs::metadata(epub_path)
+            .await
+            .map_err(|e| {
+                error!(?e, epub_path = ?epub_path, "Failed to read generated EPUB file");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(serde_json::json!({ "error": format!("Failed to read EPUB file: {e}") })),
+                )
+            })?
+            .len();
+        let filename = epub_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("webnovel.epub")
+            .to_string();
+        files.push(serde_json::json!({
+            "filename": filename,
+            "size_bytes": size_bytes,
+            "download_path": format!(
+                "/api/webnovel/download/{}/{}",
+                import.id,
+                urlencoding::encode(&filename)
+            ),
+            "metadata": {
+                "title": metadata.title,
+                "author": metadata.author,
+                "total_pages": metadata.total_pages,
+                "cover_path": metadata.cover_path,
+                "toc": metadata.toc,
+                "spine": metadata.spine,
+            },
+        }));
+    }
 
-    info!(filename = %filename, epub_size_bytes = epub_content.len(), "=== Webnovel fetch completed successfully ===");
+    info!(
+        epub_count = files.len(),
+        "=== Webnovel fetch completed successfully ==="
+    );
+    // The first volume is also returned at the top level for existing clients
     Ok(Json(serde_json::json!({
-        "metadata": {
-            "title": metadata.title,
-            "author": metadata.author,
-            "total_pages": metadata.total_pages,
-            "cover_path": metadata.cover_path,
-            "toc": metadata.toc,
-            "spine": metadata.spine,
-        },
-        "filename": filename,
-        "import_id": import.id
+        "metadata": files[0]["metadata"],
+        "filename": files[0]["filename"],
+        "import_id": import.id,
+        "files": files,
     })))
 }
 
-/// Download a temporary EPUB file for the Next.js API
+/// Download one of the EPUB files generated for an import, for the Next.js API
 pub async fn download_webnovel_file(
     State(context): State<Arc<LookupTermContext>>,
-    Path(filename): Path<String>,
+    Path((import_id, filename)): Path<(Uuid, String)>,
     headers: HeaderMap,
 ) -> Result<Response<Body>, (StatusCode, Json<serde_json::Value>)> {
-    info!(filename = %filename, "Download request for EPUB file");
+    info!(%import_id, filename = %filename, "Download request for EPUB file");
 
     // Check for service-to-service authentication
     let service_token = headers.get("X-Service-Auth").and_then(|v| v.to_str().ok());
@@ -1157,9 +2154,8 @@ pub async fn download_webnovel_file(
     // Note: We trust the service authentication token to ensure this request comes from Next.js API
     // The user authentication provides audit logging, but the service token is the primary security mechanism
 
-    let output_dir = std::env::var("WEBNOVEL_TEMP_OUTPUT_DIR")
-        .unwrap_or_else(|_| std::env::temp_dir().to_string_lossy().to_string());
-    let file_path = std::path::Path::new(&output_dir).join(&filename);
+    let output_dir = webnovel_import_dir(&import_id);
+    let file_path = output_dir.join(&filename);
 
     info!(file_path = ?file_path, "Looking for file");
 
@@ -1189,6 +2185,10 @@ pub async fn download_webnovel_file(
         // Don't fail the request if cleanup fails
     } else {
         info!(file_path = ?file_path, "Temporary file deleted after serving");
+        // Remove the import's directory once its last volume has been served
+        if std::fs::remove_dir(&output_dir).is_ok() {
+            info!(output_dir = ?output_dir, "Removed empty import output directory");
+        }
     }
 
     // Return file as response
@@ -1198,7 +2198,7 @@ pub async fn download_webnovel_file(
         .header("Content-Type", "application/epub+zip")
         .header(
             "Content-Disposition",
-            format!("attachment; filename=\"{}\"", filename),
+            http_util::attachment_disposition(&filename, None),
         )
         .body(body)
         .map_err(|e| {
@@ -1292,20 +2292,55 @@ pub async fn get_all_imports_admin(
     })))
 }
 
-/// Cancel an import
-#[instrument(skip(context, headers))]
-pub async fn cancel_import(
+/// Get per-dictionary lookup hit/miss statistics (admin only)
+pub async fn get_dict_usage(
     State(context): State<Arc<LookupTermContext>>,
-    headers: HeaderMap,
-    Path(import_id): Path<String>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
-    info!(import_id = %import_id, "Cancelling import");
+    info!("Getting dictionary usage stats for admin");
 
-    // Parse import_id as Uuid
-    let import_id = match Uuid::parse_str(&import_id) {
-        Ok(id) => id,
-        Err(e) => {
-            error!(?e, "Invalid import ID format");
+    // Admin check is handled by the auth middleware
+    let dictionary_info = context.yomi_dicts.read().await.get_dictionaries_info();
+    let usage = context.dict_usage.get_usage(&dictionary_info).await;
+
+    Ok(Json(serde_json::json!({
+        "dictionaries": usage
+    })))
+}
+
+/// Get the instance's anonymous usage counts per day (admin only)
+pub async fn get_telemetry(
+    State(context): State<Arc<LookupTermContext>>,
+) -> Json<serde_json::Value> {
+    // Admin check is handled by the auth middleware
+    Json(serde_json::json!({
+        "enabled": context.config.current().telemetry_enabled,
+        "retentionDays": crate::telemetry::RETENTION_DAYS,
+        "totals": context.telemetry.totals().await,
+        "days": context.telemetry.daily_counts().await,
+    }))
+}
+
+/// Count `event` in the usage telemetry, if the instance opted in
+async fn record_telemetry(context: &LookupTermContext, event: TelemetryEvent) {
+    if context.config.current().telemetry_enabled {
+        context.telemetry.record(event).await;
+    }
+}
+
+/// Cancel an import
+#[instrument(skip(context, headers))]
+pub async fn cancel_import(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(import_id): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    info!(import_id = %import_id, "Cancelling import");
+
+    // Parse import_id as Uuid
+    let import_id = match Uuid::parse_str(&import_id) {
+        Ok(id) => id,
+        Err(e) => {
+            error!(?e, "Invalid import ID format");
             return Err((
                 StatusCode::BAD_REQUEST,
                 Json(serde_json::json!({ "error": "Invalid import ID format" })),
@@ -1496,13 +2531,15 @@ fn get_book_metadata(filepath: &StdPath) -> Result<UploadBookResponse> {
     let book = xml::load_book(filepath)?;
     let cover_path = book.cover_zip_path.map(|p| p.to_string_lossy().to_string());
 
-    let epub_meta_bin = std::env::var("EPUB_METADATA_BIN")
-        .unwrap_or_else(|_| "epub-metadata".to_string());
+    let epub_meta_bin =
+        std::env::var("EPUB_METADATA_BIN").unwrap_or_else(|_| "epub-metadata".to_string());
 
     let output = std::process::Command::new(&epub_meta_bin)
         .arg(filepath)
         .output()
-        .context(format!("Failed to run epub-metadata binary: {epub_meta_bin}"))?;
+        .context(format!(
+            "Failed to run epub-metadata binary: {epub_meta_bin}"
+        ))?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -1519,6 +2556,8 @@ fn get_book_metadata(filepath: &StdPath) -> Result<UploadBookResponse> {
         cover_path,
         toc: epub_meta.toc,
         spine: epub_meta.spine,
+        content_hash: library::epub_content_hash(filepath)?,
+        replaces_upload_id: None,
     })
 }
 
@@ -1551,51 +2590,455 @@ pub async fn print_dicts(State(context): State<Arc<LookupTermContext>>) -> Json<
     }))
 }
 
-/// Allows the frontend to upload a dictionary file (scanning happens separately)
+/// Bytes of an upload received between import progress updates
+const UPLOAD_PROGRESS_INTERVAL: u64 = 4 * 1024 * 1024;
+
+/// Allows the frontend to upload a dictionary file (scanning happens separately).
+///
+/// The archive is streamed to disk chunk by chunk, each chunk written before
+/// the next is read, so a slow disk slows the client down rather than
+/// buffering in memory. Uploads over the configured limit, or bigger than the
+/// free space under `DICTS_PATH`, are refused before anything is written. The
+/// transfer shows up in the import progress API under the archive's name.
 pub async fn upload_dict(
-    _headers: HeaderMap,
-    TypedMultipart(upload): TypedMultipart<UploadDictRequest>,
-) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
-    // TODO: Check if user is admin
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let max_bytes = context.config.current().dict_upload_max_bytes();
+    let too_large = || {
+        api_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            &format!(
+                "Dictionary uploads are limited to {} MB",
+                disk_space::megabytes(max_bytes)
+            ),
+        )
+    };
+    let content_length = upload_content_length(&headers);
+    if content_length.is_some_and(|length| length > max_bytes) {
+        return Err(too_large());
+    }
 
-    let dicts_path = std::env::var("DICTS_PATH")
-        .context("DICTS_PATH environment variable not set")
-        .map_err(|e| {
-            error!(?e, "Failed to get DICTS_PATH");
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({ "error": e.to_string() })),
-            )
-        })?;
+    let dicts_path = std::env::var("DICTS_PATH").map_err(|_| {
+        error!("Failed to get DICTS_PATH");
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "DICTS_PATH not set")
+    })?;
     let yomitan_dir_path = StdPath::new(&dicts_path).join("yomitan");
-
     tokio::fs::create_dir_all(&yomitan_dir_path)
         .await
         .map_err(|e| {
             error!(?e, "Failed to create dictionary directory");
-            (
+            api_error(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({ "error": format!("Failed to create directory: {e}") })),
+                &format!("Failed to create directory: {e}"),
             )
         })?;
+    check_upload_space(&yomitan_dir_path, content_length)?;
 
-    tokio::fs::copy(upload.file.path(), yomitan_dir_path.join(&upload.filename))
+    let mut filename = None;
+    let mut upload = None;
+    while let Some(field) = multipart
+        .next_field()
         .await
-        .map_err(|e| {
-            error!(?e, "Failed to copy dictionary file");
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({ "error": format!("Failed to copy file: {e}") })),
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &format!("Invalid upload: {e}")))?
+    {
+        match field.name() {
+            Some("filename") => {
+                filename = Some(field.text().await.map_err(|e| {
+                    api_error(StatusCode::BAD_REQUEST, &format!("Invalid upload: {e}"))
+                })?);
+            }
+            Some("file") if upload.is_none() => {
+                let name = field.file_name().unwrap_or("dictionary.zip").to_string();
+                let import_id = context
+                    .import_progress_manager
+                    .start_import(user_id.clone(), name.clone())
+                    .await;
+                context
+                    .import_progress_manager
+                    .update_status(&import_id, ImportStatus::Uploading)
+                    .await;
+                // Hidden from the directory scan until complete
+                let part_path = yomitan_dir_path.join(format!(".{import_id}.part"));
+                let received = receive_upload(
+                    &context,
+                    &import_id,
+                    field,
+                    &part_path,
+                    content_length,
+                    max_bytes,
+                    too_large,
+                )
+                .await;
+                match received {
+                    Ok(size) => upload = Some((import_id, name, part_path, size)),
+                    Err((status, message)) => {
+                        let _ = tokio::fs::remove_file(&part_path).await;
+                        let reason = message["error"].as_str().unwrap_or_default().to_string();
+                        context
+                            .import_progress_manager
+                            .update_status(&import_id, ImportStatus::Failed(reason))
+                            .await;
+                        return Err((status, message));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let Some((import_id, name, part_path, size)) = upload else {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "No dictionary file uploaded",
+        ));
+    };
+
+    let filename = sanitize_filename::sanitize(filename.as_deref().unwrap_or(&name));
+    let result = if filename.is_empty() || filename.starts_with('.') {
+        Err(api_error(StatusCode::BAD_REQUEST, "Invalid file name"))
+    } else {
+        tokio::fs::rename(&part_path, yomitan_dir_path.join(&filename))
+            .await
+            .map_err(|e| {
+                error!(?e, "Failed to move dictionary file into place");
+                api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    &format!("Failed to store file: {e}"),
+                )
+            })
+    };
+    if let Err((status, message)) = result {
+        let _ = tokio::fs::remove_file(&part_path).await;
+        let reason = message["error"].as_str().unwrap_or_default().to_string();
+        context
+            .import_progress_manager
+            .update_status(&import_id, ImportStatus::Failed(reason))
+            .await;
+        return Err((status, message));
+    }
+    context
+        .import_progress_manager
+        .update_status(&import_id, ImportStatus::Completed)
+        .await;
+
+    info!(%filename, size, yomitan_dir = ?yomitan_dir_path, "Dictionary uploaded successfully");
+
+    Ok(Json(serde_json::json!({
+        "message": format!("Dictionary uploaded successfully: {filename}"),
+        "importId": import_id,
+        "size": size,
+    })))
+}
+
+// The size of the whole request body of an upload, a little more than the
+// file itself
+fn upload_content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(axum::http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+}
+
+// Refuse an upload of `content_length` bytes that wouldn't fit on the disk of `dir`
+fn check_upload_space(dir: &StdPath, content_length: Option<u64>) -> Result<(), ApiError> {
+    let Some(length) = content_length else {
+        return Ok(());
+    };
+    let available = disk_space::available_bytes(dir).map_err(|e| {
+        error!(?e, "Failed to check free disk space");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to check free disk space",
+        )
+    })?;
+    if length > available {
+        return Err(api_error(
+            StatusCode::INSUFFICIENT_STORAGE,
+            &format!(
+                "Not enough disk space for the upload: {} MB needed, {} MB free",
+                disk_space::megabytes(length),
+                disk_space::megabytes(available)
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Write the chunks of an uploaded file to `path` as they arrive, reporting
+/// progress and stopping at `max_bytes` (with the error from `too_large`) or
+/// when the import is cancelled. Returns the size of the file.
+async fn receive_upload(
+    context: &LookupTermContext,
+    import_id: &Uuid,
+    mut field: Field<'_>,
+    path: &StdPath,
+    expected: Option<u64>,
+    max_bytes: u64,
+    too_large: impl Fn() -> ApiError,
+) -> Result<u64, ApiError> {
+    let write_error = |e: std::io::Error| {
+        error!(?e, "Failed to write upload");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to write file: {e}"),
+        )
+    };
+    let mut file = tokio::fs::File::create(path).await.map_err(write_error)?;
+    let progress = &context.import_progress_manager;
+    let mut received = 0;
+    let mut reported = 0;
+    while let Some(chunk) = field
+        .chunk()
+        .await
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &format!("Upload interrupted: {e}")))?
+    {
+        received += chunk.len() as u64;
+        if received > max_bytes {
+            return Err(too_large());
+        }
+        file.write_all(&chunk).await.map_err(write_error)?;
+        if received - reported >= UPLOAD_PROGRESS_INTERVAL {
+            progress
+                .set_transfer_progress(import_id, received, expected)
+                .await;
+            reported = received;
+            let cancelled = progress
+                .get_progress(import_id)
+                .await
+                .is_some_and(|p| p.status == ImportStatus::Cancelled);
+            if cancelled {
+                return Err(api_error(StatusCode::CONFLICT, "Upload cancelled"));
+            }
+        }
+    }
+    file.flush().await.map_err(write_error)?;
+    progress
+        .set_transfer_progress(import_id, received, expected)
+        .await;
+    Ok(received)
+}
+
+/// Audio pack entries added to the audio database per transaction, with a
+/// progress update after each
+const AUDIO_PACK_BATCH_SIZE: usize = 1000;
+
+/// Upload a pack of pronunciation recordings, see [`audio_packs`].
+///
+/// The zip is streamed to disk like a dictionary upload, into the first of
+/// `AUDIO_DATA_DIRS`. Extracting it and adding its entries to the audio
+/// database happen in the background, reported through the import progress
+/// API under the returned `importId`. Entries the database already has are
+/// left alone, so a pack can be uploaded again with recordings added to it.
+pub async fn upload_audio_pack(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let max_bytes = context.config.current().audio_pack_upload_max_bytes();
+    let too_large = || {
+        api_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            &format!(
+                "Audio packs are limited to {} MB",
+                disk_space::megabytes(max_bytes)
+            ),
+        )
+    };
+    let content_length = upload_content_length(&headers);
+    if content_length.is_some_and(|length| length > max_bytes) {
+        return Err(too_large());
+    }
+
+    let db_path = std::env::var("AUDIO_DB_PATH").map_err(|_| {
+        api_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Audio database not configured",
+        )
+    })?;
+    let audio_dir = std::env::var("AUDIO_DATA_DIRS")
+        .ok()
+        .and_then(|dirs| {
+            dirs.split(',')
+                .map(str::trim)
+                .find(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+        })
+        .ok_or_else(|| {
+            api_error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Audio files are not configured",
             )
         })?;
+    tokio::fs::create_dir_all(&audio_dir).await.map_err(|e| {
+        error!(?e, ?audio_dir, "Failed to create audio directory");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to create directory: {e}"),
+        )
+    })?;
+    check_upload_space(&audio_dir, content_length)?;
+
+    let mut upload = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &format!("Invalid upload: {e}")))?
+    {
+        if field.name() != Some("file") || upload.is_some() {
+            continue;
+        }
+        let name = field.file_name().unwrap_or("audio-pack.zip").to_string();
+        let import_id = context
+            .import_progress_manager
+            .start_import(user_id.clone(), name)
+            .await;
+        context
+            .import_progress_manager
+            .update_status(&import_id, ImportStatus::Uploading)
+            .await;
+        // Hidden, and outside the `{source}_files` directories audio is served from
+        let part_path = audio_dir.join(format!(".{import_id}.part"));
+        let received = receive_upload(
+            &context,
+            &import_id,
+            field,
+            &part_path,
+            content_length,
+            max_bytes,
+            too_large,
+        )
+        .await;
+        match received {
+            Ok(size) => upload = Some((import_id, part_path, size)),
+            Err((status, message)) => {
+                let _ = tokio::fs::remove_file(&part_path).await;
+                let reason = message["error"].as_str().unwrap_or_default().to_string();
+                context
+                    .import_progress_manager
+                    .update_status(&import_id, ImportStatus::Failed(reason))
+                    .await;
+                return Err((status, message));
+            }
+        }
+    }
+    let Some((import_id, part_path, size)) = upload else {
+        return Err(api_error(StatusCode::BAD_REQUEST, "No audio pack uploaded"));
+    };
 
-    info!(filename = ?upload.filename, yomitan_dir = ?yomitan_dir_path, "Dictionary uploaded successfully");
+    info!(%import_id, size, ?audio_dir, "🎵 Audio pack uploaded, importing");
+    tokio::spawn(audio_pack_import_task(
+        context.clone(),
+        import_id,
+        part_path,
+        audio_dir,
+        db_path,
+    ));
 
     Ok(Json(serde_json::json!({
-        "message": format!("Dictionary uploaded successfully: {}", upload.filename)
+        "message": "Audio pack uploaded, importing",
+        "importId": import_id,
+        "size": size,
     })))
 }
 
+async fn audio_pack_import_task(
+    context: Arc<LookupTermContext>,
+    import_id: Uuid,
+    archive: PathBuf,
+    audio_dir: PathBuf,
+    db_path: String,
+) {
+    let result = import_audio_pack(&context, &import_id, &archive, audio_dir, db_path).await;
+    if let Err(e) = tokio::fs::remove_file(&archive).await {
+        warn!(?e, ?archive, "⚠️ Failed to remove uploaded audio pack");
+    }
+
+    let progress = &context.import_progress_manager;
+    let cancelled = progress
+        .get_progress(&import_id)
+        .await
+        .is_some_and(|p| p.status == ImportStatus::Cancelled);
+    if cancelled {
+        return;
+    }
+    match result {
+        Ok(()) => {
+            progress
+                .update_status(&import_id, ImportStatus::Completed)
+                .await
+        }
+        Err(e) => {
+            error!(?e, %import_id, "❌ Audio pack import failed");
+            progress
+                .update_status(&import_id, ImportStatus::Failed(format!("{e:#}")))
+                .await;
+        }
+    }
+}
+
+// Extract the pack and add its entries a batch at a time, stopping early if
+// the import is cancelled. Extracted files are kept either way.
+async fn import_audio_pack(
+    context: &LookupTermContext,
+    import_id: &Uuid,
+    archive: &StdPath,
+    audio_dir: PathBuf,
+    db_path: String,
+) -> anyhow::Result<()> {
+    let progress = &context.import_progress_manager;
+    progress
+        .update_status(import_id, ImportStatus::Unpacking)
+        .await;
+    let archive = archive.to_path_buf();
+    let (index, extracted) =
+        tokio::task::spawn_blocking(move || audio_packs::extract(&archive, &audio_dir)).await??;
+    progress
+        .add_log(
+            import_id,
+            format!("Extracted {extracted} files for source {}", index.source),
+        )
+        .await;
+
+    progress
+        .update_status(import_id, ImportStatus::Processing)
+        .await;
+    let writer =
+        Arc::new(tokio::task::spawn_blocking(move || AudioDBWriter::open(db_path)).await??);
+    let entries = index.new_entries();
+    let mut checked = 0;
+    let mut added = 0;
+    for batch in entries.chunks(AUDIO_PACK_BATCH_SIZE) {
+        let cancelled = progress
+            .get_progress(import_id)
+            .await
+            .is_some_and(|p| p.status == ImportStatus::Cancelled);
+        if cancelled {
+            return Ok(());
+        }
+        let writer = writer.clone();
+        let batch = batch.to_vec();
+        checked += batch.len();
+        added += tokio::task::spawn_blocking(move || writer.insert_new(&batch)).await??;
+        progress
+            .add_log(
+                import_id,
+                format!(
+                    "Added {added} new entries ({checked} of {} checked)",
+                    entries.len()
+                ),
+            )
+            .await;
+    }
+    if let Some(audio_db) = context.audio_db.load_full() {
+        audio_db.invalidate_cache();
+    }
+    info!(%import_id, source = index.source, added, "🎵 Audio pack imported");
+    Ok(())
+}
+
 pub async fn scan_dicts(
     State(context): State<Arc<LookupTermContext>>,
     Query(params): Query<ScanDictsQuery>,
@@ -1610,10 +3053,12 @@ pub async fn scan_dicts(
     })?);
     // Clear out yomi_dicts so that we can scan from scratch
     context.yomi_dicts.write().await.clear();
-    let _ = dict_db_scan_fs::scan_fs(
+    context.lookup_cache.clear();
+    let report = dict_db_scan_fs::scan_fs(
         progress_state,
         Some(context.yomi_dicts.clone()),
-        params.max_size_mb,
+        params.budget_mb,
+        params.mode,
     )
     .await
     .map_err(|e| {
@@ -1630,12 +3075,14 @@ pub async fn scan_dicts(
     info!(?info, "Dictionaries scanned successfully");
 
     Ok(Json(serde_json::json!({
-        "info": info
+        "info": info,
+        "report": report.summary(),
     })))
 }
 
 /// Custom static file handler that properly handles URL decoding and Unicode normalization
 pub async fn serve_static_file(
+    headers: HeaderMap,
     Path(file_path): Path<String>,
 ) -> Result<Response<Body>, (StatusCode, String)> {
     let dicts_path = std::env::var("DICTS_PATH").map_err(|_| {
@@ -1649,12 +3096,15 @@ pub async fn serve_static_file(
     let decoded_path = urlencoding::decode(&file_path)
         .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid URL encoding".to_string()))?;
 
-    // Normalize the path to NFD for filesystem compatibility (macOS/APFS stores filenames in NFD)
-    let normalized_path = decoded_path.nfd().collect::<String>();
-
-    // Construct the full path
+    // Construct the full path, through the asset manifest for dictionaries
+    // imported with content-addressed assets, or else whichever normalization
+    // form of the name is on disk
     let base_static = StdPath::new(&dicts_path).join("static");
-    let full_path = base_static.join(&normalized_path);
+    let full_path = AssetStore::new(camino::Utf8PathBuf::from(&dicts_path).join("static"))
+        .resolve(&decoded_path)
+        .map(PathBuf::from)
+        .or_else(|| normalization::find_existing(&base_static, &decoded_path))
+        .unwrap_or_else(|| base_static.join(normalization::normalize_name(&decoded_path)));
 
     info!(
         "Static file request: {} -> {}",
@@ -1678,12 +3128,26 @@ pub async fn serve_static_file(
         return Err((StatusCode::FORBIDDEN, "Access denied".to_string()));
     }
 
+    let mut resp_headers = HeaderMap::new();
+    let validators = fs::metadata(&canonical_path)
+        .ok()
+        .and_then(|metadata| FileValidators::new(&metadata));
+    if let Some(validators) = &validators {
+        validators.insert_headers(&mut resp_headers);
+    }
+    if validators.is_some_and(|v| v.not_modified(&headers)) {
+        return Ok(not_modified_response(resp_headers));
+    }
+
     // Read the file
     let content = fs::read(&canonical_path)
         .map_err(|_| (StatusCode::NOT_FOUND, "File not found".to_string()))?;
 
-    // Determine content type based on file extension
-    let content_type = match full_path.extension().and_then(|s| s.to_str()) {
+    // Determine content type based on the requested file's extension
+    let content_type = match StdPath::new(decoded_path.as_ref())
+        .extension()
+        .and_then(|s| s.to_str())
+    {
         Some("png") => "image/png",
         Some("jpg") | Some("jpeg") => "image/jpeg",
         Some("gif") => "image/gif",
@@ -1694,7 +3158,7 @@ pub async fn serve_static_file(
         _ => "application/octet-stream",
     };
 
-    let response = Response::builder()
+    let mut response = Response::builder()
         .status(StatusCode::OK)
         .header("Content-Type", content_type)
         .body(Body::from(content))
@@ -1704,20 +3168,25 @@ pub async fn serve_static_file(
                 "Failed to build response".to_string(),
             )
         })?;
+    response.headers_mut().extend(resp_headers);
 
     Ok(response)
 }
 
-/// Helper function to find an audio file across multiple directories
+/// Helper function to find an audio file across multiple directories, in
+/// either normalization form of `rel_path`.
 /// Returns the canonical path of the first matching file found
 async fn find_audio_file_in_dirs(
     audio_dirs: &str,
-    normalized_path: &str,
+    rel_path: &str,
 ) -> Result<PathBuf, (StatusCode, String)> {
     let dirs: Vec<&str> = audio_dirs.split(',').map(|s| s.trim()).collect();
 
     for audio_dir in dirs {
-        let full_path = StdPath::new(audio_dir).join(normalized_path);
+        let Some(full_path) = normalization::find_existing(StdPath::new(audio_dir), rel_path)
+        else {
+            continue;
+        };
 
         // Try to canonicalize the audio directory
         let canonical_dir = match StdPath::new(audio_dir).canonicalize() {
@@ -1750,10 +3219,47 @@ async fn find_audio_file_in_dirs(
     ))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct AudioFileQuery {
+    /// `1` to cut off the silence at either end of the recording
+    trim: Option<String>,
+}
+
+impl AudioFileQuery {
+    fn trim(&self) -> bool {
+        matches!(self.trim.as_deref(), Some("1" | "true"))
+    }
+}
+
+// The trimmed copy of the audio file at `path` (found for `rel_path`) if one
+// was asked for and there is silence to trim, otherwise None
+async fn trimmed_audio_file(
+    query: &AudioFileQuery,
+    path: &StdPath,
+    rel_path: &str,
+) -> Option<PathBuf> {
+    if !query.trim() {
+        return None;
+    }
+    let rel_path = book_resources::resource_path(rel_path)?;
+    let cache = TrimmedAudioCache::from_env();
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || cache.trimmed(&path, &rel_path))
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|result| result)
+        .unwrap_or_else(|e| {
+            warn!(?e, "⚠️ Failed to trim audio file, serving it untrimmed");
+            None
+        })
+}
+
 /// Audio file handler that serves audio files from the local-audio-yomichan data directory
 pub async fn serve_audio_file(
+    method: Method,
     headers: HeaderMap,
     Path(file_path): Path<String>,
+    Query(query): Query<AudioFileQuery>,
 ) -> Result<Response<Body>, (StatusCode, String)> {
     // Check user authentication
     let user_id = headers
@@ -1779,18 +3285,18 @@ pub async fn serve_audio_file(
     let decoded_path = urlencoding::decode(&file_path)
         .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid URL encoding".to_string()))?;
 
-    // Normalize the path to NFD for filesystem compatibility
-    let normalized_path = decoded_path.nfd().collect::<String>();
-
     info!("Audio file request: {}", file_path);
 
-    // Find the file across all audio directories
-    let canonical_path = find_audio_file_in_dirs(&audio_data_dirs, &normalized_path).await?;
+    // Synthesized clips live in the TTS cache, not the audio directories
+    if let Some(name) = decoded_path.strip_prefix(tts::URL_PREFIX) {
+        return tts_clip_response(&method, &headers, name).await;
+    }
 
-    // Read the file
-    let content = tokio::fs::read(&canonical_path)
-        .await
-        .map_err(|_| (StatusCode::NOT_FOUND, "Audio file not found".to_string()))?;
+    // Find the file across all audio directories
+    let canonical_path = find_audio_file_in_dirs(&audio_data_dirs, &decoded_path).await?;
+    if let Some(trimmed) = trimmed_audio_file(&query, &canonical_path, &decoded_path).await {
+        return media_file_response(&method, &headers, &trimmed, "audio/wav", None).await;
+    }
 
     // Determine content type based on file extension
     let content_type = match canonical_path.extension().and_then(|s| s.to_str()) {
@@ -1801,62 +3307,384 @@ pub async fn serve_audio_file(
         _ => "audio/opus", // Default to opus
     };
 
-    let response = Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", content_type)
-        .header("Accept-Ranges", "bytes")
-        .body(Body::from(content))
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to build response".to_string(),
-            )
-        })?;
-
-    Ok(response)
+    media_file_response(&method, &headers, &canonical_path, content_type, None).await
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct AudioResponse {
-    pub type_: String,
-    pub audio_sources: Vec<AudioSource>,
-}
+/// Response for a media file, shared by the audio and image routes: the whole
+/// file or the requested byte range. HEAD requests (which axum routes to the
+/// GET handlers) get the same headers without the file being read. The body
+/// is streamed from the file rather than read into memory. Requests whose
+/// `If-None-Match`/`If-Modified-Since` match the file get a 304.
+async fn media_file_response(
+    method: &Method,
+    headers: &HeaderMap,
+    path: &StdPath,
+    content_type: &str,
+    cache_control: Option<&'static str>,
+) -> Result<Response, (StatusCode, String)> {
+    let metadata = tokio::fs::metadata(path)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("File not found: {e}")))?;
+    let total_len = metadata.len();
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct AudioSource {
-    pub name: String,
-    pub url: String,
-}
+    let mut resp_headers = HeaderMap::new();
+    let validators = FileValidators::new(&metadata);
+    if let Some(validators) = &validators {
+        validators.insert_headers(&mut resp_headers);
+    }
+    if let Some(cache_control) = cache_control {
+        resp_headers.insert("Cache-Control", HeaderValue::from_static(cache_control));
+    }
+    if validators.is_some_and(|v| v.not_modified(headers)) {
+        return Ok(not_modified_response(resp_headers));
+    }
 
-/// Audio API endpoint that queries the local-audio-yomichan database
-pub async fn get_audio(
-    State(context): State<Arc<LookupTermContext>>,
-    Query(params): Query<AudioQueryParams>,
-) -> Result<Json<AudioResponse>, (StatusCode, Json<serde_json::Value>)> {
-    let audio_db_path = std::env::var("AUDIO_DB_PATH").map_err(|_| {
-        error!("AUDIO_DB_PATH environment variable not set");
+    resp_headers.insert("Accept-Ranges", HeaderValue::from_static("bytes"));
+    resp_headers.insert(
+        "Content-Type",
+        HeaderValue::from_str(content_type)
+            .unwrap_or(HeaderValue::from_static("application/octet-stream")),
+    );
+
+    // Handle Range (Safari requires this)
+    let range = headers.get("range").and_then(|v| v.to_str().ok());
+    let (status, start, len) = match byte_range(range, total_len) {
+        Ok(Some((start, end))) => {
+            resp_headers.insert(
+                "Content-Range",
+                format!("bytes {start}-{end}/{total_len}").parse().unwrap(),
+            );
+            (StatusCode::PARTIAL_CONTENT, start, end - start + 1)
+        }
+        Ok(None) => (StatusCode::OK, 0, total_len),
+        Err(()) => {
+            return Err((
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                format!("bytes */{total_len}"),
+            ))
+        }
+    };
+    resp_headers.insert("Content-Length", HeaderValue::from(len));
+
+    let body = if method == Method::HEAD {
+        Body::empty()
+    } else {
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| (StatusCode::NOT_FOUND, format!("File not found: {e}")))?;
+        file.seek(std::io::SeekFrom::Start(start))
+            .await
+            .map_err(|_| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Range read error".to_string(),
+                )
+            })?;
+        // Streamed in chunks so that large recordings aren't held in memory
+        Body::from_stream(ReaderStream::new(file.take(len)))
+    };
+
+    let mut response = Response::builder().status(status).body(body).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({ "error": "Audio database not configured" })),
+            "Failed to build response".to_string(),
         )
     })?;
+    *response.headers_mut() = resp_headers;
+    Ok(response)
+}
 
-    let audio_db = AudioDB::new(&audio_db_path).map_err(|e| {
-        error!(?e, "Failed to open audio database at {}", audio_db_path);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({ "error": format!("Failed to open audio database: {}", e) })),
-        )
-    })?;
+// 304 with the validators (and caching headers) of the full response
+fn not_modified_response(headers: HeaderMap) -> Response {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::NOT_MODIFIED;
+    *response.headers_mut() = headers;
+    response
+}
+
+/// First and last byte (inclusive) requested by a `Range` header on a file of
+/// `total_len` bytes: `bytes=start-end`, `bytes=start-` or the suffix form
+/// `bytes=-len`. None if there is no byte range to honour, in which case the
+/// whole file is sent; Err if the range starts past the end of the file. An
+/// end past the end of the file is clamped to its last byte.
+fn byte_range(range: Option<&str>, total_len: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some((first, last)) = range
+        .and_then(|range| range.trim().strip_prefix("bytes="))
+        // Only the first of several ranges is served
+        .and_then(|ranges| ranges.split(',').next())
+        .and_then(|range| range.trim().split_once('-'))
+    else {
+        return Ok(None);
+    };
+    let parse = |n: &str| n.trim().parse::<u64>().ok();
+    let (start, end) = match (first.trim().is_empty(), last.trim().is_empty()) {
+        (true, true) => return Ok(None),
+        (true, false) => {
+            let suffix = parse(last).ok_or(())?;
+            if suffix == 0 {
+                return Err(());
+            }
+            (
+                total_len.saturating_sub(suffix),
+                total_len.saturating_sub(1),
+            )
+        }
+        (false, true) => (parse(first).ok_or(())?, total_len.saturating_sub(1)),
+        (false, false) => (parse(first).ok_or(())?, parse(last).ok_or(())?),
+    };
+    if start >= total_len || start > end {
+        return Err(());
+    }
+    Ok(Some((start, end.min(total_len - 1))))
+}
+
+/// `OPTIONS` on the media routes, for clients probing what they support.
+/// CORS preflight requests are answered by the CORS layer before this.
+pub async fn media_options() -> Response {
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header("Allow", "GET, HEAD, OPTIONS")
+        .header("Accept-Ranges", "bytes")
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioResponse {
+    pub type_: String,
+    pub audio_sources: Vec<AudioSource>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioSource {
+    pub name: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peaks: Option<Vec<u8>>,
+    /// Gain to apply on playback so that all sources play at a consistent loudness
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gain_db: Option<f64>,
+    /// `"tts"` for synthesized clips
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<&'static str>,
+    /// The recording the user's autoplay rules picked, in lookup responses
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub autoplay: bool,
+    /// Downstep position of the recorded accent, for sources that give it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch: Option<i64>,
+    /// Whether the recording has the accent being studied: the one asked for,
+    /// or the first the pitch dictionaries give in lookups
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub pitch_match: bool,
+}
 
-    let entries = if let Some(reading) = &params.reading {
-        audio_db.query_by_term_and_reading(&params.term, reading)
+// Human readable name for an audio entry, e.g. "Forvo (speaker)"
+fn audio_source_name(entry: &AudioEntry) -> String {
+    if let Some(speaker) = &entry.speaker {
+        if let Some(display) = &entry.display {
+            format!("{} ({})", display, speaker)
+        } else {
+            format!("{} ({})", entry.source, speaker)
+        }
+    } else if let Some(display) = &entry.display {
+        display.clone()
     } else {
-        audio_db.query_by_term(&params.term)
+        entry.source.clone()
     }
-    .map_err(|e| {
+}
+
+/// Open the audio database at `path` with the query cache size from
+/// AUDIO_DB_CACHE_SIZE
+pub fn open_audio_db(path: &str) -> Result<AudioDB> {
+    AudioDB::with_cache_capacity(
+        path,
+        std::env::var("AUDIO_DB_CACHE_SIZE")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(audio_db_query::DEFAULT_CACHE_CAPACITY),
+    )
+}
+
+// The local-audio-yomichan database configured by AUDIO_DB_PATH
+fn audio_db(context: &LookupTermContext) -> Result<Arc<AudioDB>, ApiError> {
+    context.audio_db.load_full().ok_or_else(|| {
+        error!("❌ Audio database is not configured");
+        api_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Audio database not configured",
+        )
+    })
+}
+
+// The preferences of the user making an audio request, None for anonymous
+// requests or if they can't be loaded, in which case audio is returned as is
+async fn audio_preferences(
+    context: &LookupTermContext,
+    user_id: Option<Uuid>,
+) -> Option<UserPreferences> {
+    match context.user_preferences_db.read().await.get(user_id?).await {
+        Ok(preferences) => Some(preferences),
+        Err(e) => {
+            warn!(
+                ?e,
+                "Failed to get user preferences, not filtering audio sources"
+            );
+            None
+        }
+    }
+}
+
+// Drop the sources the user has turned off and put the rest in the user's
+// preferred order
+fn apply_audio_preferences(preferences: &UserPreferences, entries: &mut Vec<AudioEntry>) {
+    entries.retain(|entry| !preferences.audio_disabled_sources.contains(&entry.source));
+    preferences.sort_by_audio_source_order(entries, |entry| entry.source.as_str());
+}
+
+/// Most terms of a lookup whose recordings are returned with it
+const MAX_LOOKUP_AUDIO_TERMS: usize = 10;
+
+// Recordings of the terms and readings of lookup results, filtered and ordered
+// by the user's audio preferences, those with the accent the pitch dictionaries
+// give first. The autoplay rules pick at most one, among the recordings of the
+// first entry.
+fn lookup_audio(
+    context: &LookupTermContext,
+    results: &[DictionaryResult],
+    pitch_accent_results: &HashMap<String, PitchAccentResult>,
+    preferences: &UserPreferences,
+) -> Vec<TermAudio> {
+    let Some(audio_db) = context.audio_db.load_full() else {
+        return Vec::new();
+    };
+    let mut seen = HashSet::new();
+    let terms = results
+        .iter()
+        .flat_map(|d| &d.entries)
+        .filter(|e| seen.insert((e.text.as_str(), e.reading.as_str())))
+        .take(MAX_LOOKUP_AUDIO_TERMS);
+
+    let mut audio = Vec::new();
+    for (i, entry) in terms.enumerate() {
+        let reading = Some(entry.reading.as_str()).filter(|r| !r.is_empty());
+        let mut entries = match audio_db.query_by_term_filtered(&entry.text, reading, &[]) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!(?e, term = %entry.text, "⚠️ Failed to query audio database");
+                continue;
+            }
+        };
+        apply_audio_preferences(preferences, &mut entries);
+        if entries.is_empty() {
+            continue;
+        }
+        let pitch = studied_pitch(pitch_accent_results, &entry.text, &entry.reading);
+        rank_by_pitch(&mut entries, pitch);
+        let autoplay = if i == 0 {
+            preferences
+                .popup
+                .autoplay(&entries, reading.unwrap_or(&entry.text), |e| {
+                    (
+                        e.source.as_str(),
+                        e.reading.as_deref().unwrap_or(&e.expression),
+                    )
+                })
+        } else {
+            None
+        };
+        let audio_sources = entries
+            .into_iter()
+            .enumerate()
+            .map(|(j, e)| AudioSource {
+                name: audio_source_name(&e),
+                url: format!("/audio/{}_files/{}", e.source, e.file),
+                duration_ms: e.duration_ms,
+                peaks: e.peaks,
+                gain_db: e.gain_db,
+                tag: None,
+                autoplay: autoplay == Some(j),
+                pitch_match: pitch.is_some() && e.pitch == pitch,
+                pitch: e.pitch,
+            })
+            .collect();
+        audio.push(TermAudio {
+            term: entry.text.clone(),
+            reading: entry.reading.clone(),
+            audio_sources,
+        });
+    }
+    audio
+}
+
+// Downstep position of the first accent the pitch dictionaries give `term`
+// read `reading`
+fn studied_pitch(
+    pitch_accent_results: &HashMap<String, PitchAccentResult>,
+    term: &str,
+    reading: &str,
+) -> Option<i64> {
+    let reading = reading.to_hiragana();
+    let mut accents = pitch_accent_results
+        .get(term)?
+        .entries
+        .values()
+        .flat_map(|list| &list.entries);
+    accents
+        .find(|accent| accent.reading == reading)
+        .map(|accent| accent.position as i64)
+}
+
+// Put the recordings of the accent `pitch` first, keeping the order otherwise
+fn rank_by_pitch(entries: &mut [AudioEntry], pitch: Option<i64>) {
+    if let Some(pitch) = pitch {
+        entries.sort_by_key(|entry| entry.pitch != Some(pitch));
+    }
+}
+
+/// Audio API endpoint that queries the local-audio-yomichan database
+pub async fn get_audio(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Query(params): Query<AudioQueryParams>,
+) -> Result<Json<AudioResponse>, (StatusCode, Json<serde_json::Value>)> {
+    record_telemetry(&context, TelemetryEvent::Audio).await;
+    let audio_db = audio_db(&context)?;
+
+    let requested_sources: Vec<&str> = params
+        .sources
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    let preferences = audio_preferences(
+        &context,
+        headers
+            .get("user_id")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|s| Uuid::parse_str(s).ok()),
+    )
+    .await;
+
+    let entries = if params.reading_only {
+        let reading = params.reading.as_deref().unwrap_or(&params.term);
+        audio_db.query_by_reading(reading).map(|mut entries| {
+            if !requested_sources.is_empty() {
+                entries.retain(|entry| requested_sources.contains(&entry.source.as_str()));
+            }
+            entries
+        })
+    } else {
+        audio_db.query_by_term_filtered(&params.term, params.reading.as_deref(), &requested_sources)
+    };
+    let mut entries = entries.map_err(|e| {
         error!(
             ?e,
             "Failed to query audio database for term: {}", params.term
@@ -1866,30 +3694,44 @@ pub async fn get_audio(
             Json(serde_json::json!({ "error": format!("Failed to query audio database: {}", e) })),
         )
     })?;
+    if let Some(preferences) = &preferences {
+        apply_audio_preferences(preferences, &mut entries);
+    }
+    rank_by_pitch(&mut entries, params.pitch);
 
-    let audio_sources = entries
+    let mut audio_sources: Vec<AudioSource> = entries
         .into_iter()
         .map(|entry| {
             // Construct the correct audio file path: {source}_files/{file}
             let correct_path = format!("{}_files/{}", entry.source, entry.file);
             let url = format!("/audio/{}", correct_path);
 
-            // Construct display name
-            let name = if let Some(speaker) = &entry.speaker {
-                if let Some(display) = &entry.display {
-                    format!("{} ({})", display, speaker)
-                } else {
-                    format!("{} ({})", entry.source, speaker)
-                }
-            } else if let Some(display) = &entry.display {
-                display.clone()
-            } else {
-                entry.source.clone()
-            };
-
-            AudioSource { name, url }
+            AudioSource {
+                name: audio_source_name(&entry),
+                url,
+                duration_ms: entry.duration_ms,
+                peaks: entry.peaks,
+                gain_db: entry.gain_db,
+                tag: None,
+                autoplay: false,
+                pitch_match: params.pitch.is_some() && entry.pitch == params.pitch,
+                pitch: entry.pitch,
+            }
         })
         .collect();
+    if audio_sources.is_empty() {
+        audio_sources.extend(
+            tts_audio_source(
+                &context,
+                &headers,
+                &params.term,
+                params.reading.as_deref(),
+                &requested_sources,
+                preferences.as_ref(),
+            )
+            .await,
+        );
+    }
 
     Ok(Json(AudioResponse {
         type_: "audioSourceList".to_string(),
@@ -1897,275 +3739,4155 @@ pub async fn get_audio(
     }))
 }
 
-#[derive(Deserialize)]
-pub struct SigQuery {
-    exp: u64,
-    sig: String,
+// Synthesized clip of a term without recordings, if TTS is configured, the
+// request comes from a signed in user (guests can neither play `/audio/` URLs
+// nor should they run the backend) and neither the request nor the user's
+// preferences leave the "tts" source out
+async fn tts_audio_source(
+    context: &LookupTermContext,
+    headers: &HeaderMap,
+    term: &str,
+    reading: Option<&str>,
+    requested_sources: &[&str],
+    preferences: Option<&UserPreferences>,
+) -> Option<AudioSource> {
+    let tts = context.tts.as_ref()?;
+    headers.get("user_id")?;
+    if !requested_sources.is_empty() && !requested_sources.contains(&tts::SOURCE) {
+        return None;
+    }
+    if preferences.is_some_and(|p| p.audio_disabled_sources.contains(tts::SOURCE)) {
+        return None;
+    }
+    // The reading, when given, keeps the backend from misreading kanji
+    let text = reading
+        .filter(|reading| !reading.trim().is_empty())
+        .unwrap_or(term)
+        .trim();
+    if text.is_empty() || text.chars().count() > tts::MAX_TEXT_CHARS {
+        return None;
+    }
+    match tts.clip(text).await {
+        Ok(name) => Some(AudioSource {
+            name: tts.backend().display_name().to_string(),
+            url: format!("/audio/{}{name}", tts::URL_PREFIX),
+            duration_ms: None,
+            peaks: None,
+            gain_db: None,
+            tag: Some(tts::SOURCE),
+            autoplay: false,
+            pitch: None,
+            pitch_match: false,
+        }),
+        Err(e) => {
+            warn!(?e, text, "⚠️ Failed to synthesize audio");
+            None
+        }
+    }
 }
 
-type HmacSha256 = Hmac<Sha256>;
-
-/// Generate HMAC signature for a given path, expiry, and key
-/// This matches the Next.js frontend signing logic exactly
-pub fn generate_hmac_signature(path: &str, exp: u64, key: &str) -> String {
-    let method = "GET";
-    let canonical = format!("{method}\n{path}\nexp={}", exp);
+// Hard cap on terms per batch request regardless of AUDIO_BATCH_MAX_ITEMS, keeps
+// the IN-clause well below SQLite's variable limit
+const MAX_AUDIO_BATCH_SIZE: usize = 5000;
+// Terms without recordings a batch request falls back to TTS for, as each may
+// wait on the backend
+const MAX_AUDIO_BATCH_TTS: usize = 20;
 
-    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).unwrap();
-    mac.update(canonical.as_bytes());
-    let sig_bytes = mac.finalize().into_bytes();
-    URL_SAFE_NO_PAD.encode(sig_bytes)
+#[derive(Deserialize, Debug)]
+pub struct AudioBatchItem {
+    pub term: String,
+    pub reading: Option<String>,
 }
 
-/// Verify HMAC signature for signed URLs
-/// Returns Ok(()) if signature is valid, Err with appropriate status code otherwise
-fn verify_signed_url(
-    rel_path: &str,
-    q: &SigQuery,
-    path_prefix: &str,
-    error_prefix: &str,
-) -> Result<(), (StatusCode, String)> {
-    // 1) Check expiry
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "System time error".to_string(),
-            )
-        })?
-        .as_secs();
-    if q.exp < now {
-        return Err((StatusCode::UNAUTHORIZED, "URL expired".to_string()));
-    }
+#[derive(Deserialize, Debug)]
+pub struct AudioBatchRequest {
+    pub items: Vec<AudioBatchItem>,
+}
 
-    // 2) Verify HMAC (must match Next.js signer)
-    let key = std::env::var("MEDIA_URL_KEY").map_err(|_| {
-        error!("{} MEDIA_URL_KEY not configured", error_prefix);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "MEDIA_URL_KEY not configured".to_string(),
-        )
-    })?;
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioBatchResult {
+    pub term: String,
+    pub reading: Option<String>,
+    pub audio_sources: Vec<AudioSource>,
+}
 
-    let path_for_sig = format!("{}{}", path_prefix, rel_path);
-    let expected_sig = generate_hmac_signature(&path_for_sig, q.exp, &key);
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioBatchResponse {
+    pub results: Vec<AudioBatchResult>,
+}
 
-    let sig_bytes = URL_SAFE_NO_PAD
-        .decode(q.sig.as_bytes())
-        .map_err(|_| (StatusCode::UNAUTHORIZED, "Bad signature (b64)".to_string()))?;
+/// Sign `rel_path` under `prefix` so it can be fetched without an auth header,
+/// as checked by `verify_signed_url`
+fn sign_url(prefix: &str, rel_path: &str, ttl: Duration) -> Result<String> {
+    let key = std::env::var("MEDIA_URL_KEY").context("MEDIA_URL_KEY not configured")?;
+    let exp = (SystemTime::now().duration_since(UNIX_EPOCH)? + ttl).as_secs();
+    let sig = generate_hmac_signature(&format!("{}{}", prefix, rel_path), exp, &key);
+    let encoded_path = rel_path
+        .split('/')
+        .map(|segment| urlencoding::encode(segment).into_owned())
+        .collect::<Vec<_>>()
+        .join("/");
+    Ok(format!(
+        "{}{}?exp={}&sig={}",
+        prefix, encoded_path, exp, sig
+    ))
+}
 
-    let actual_sig = URL_SAFE_NO_PAD.encode(sig_bytes);
-    if actual_sig != expected_sig {
-        return Err((StatusCode::UNAUTHORIZED, "Bad signature".to_string()));
-    }
+/// Sign a path under /media/ so it can be fetched without an auth header.
+/// `rel_path` is relative to the audio data directories, e.g. "nhk16_files/foo.opus".
+pub fn sign_media_url(rel_path: &str, ttl: Duration) -> Result<String> {
+    sign_url("/media/", rel_path, ttl)
+}
 
-    Ok(())
+/// Prefix of the signed URLs of a book's files
+fn book_resource_prefix(user_id: &str, upload_id: &str) -> String {
+    format!("/book-resource/{user_id}/{upload_id}/")
 }
 
-/// Signed URL media handler for serving audio files with HMAC verification
-pub async fn serve_signed_media(
-    Path(rel_path): Path<String>,
-    Query(q): Query<SigQuery>,
+/// Fetch audio for many terms at once (e.g. before a review session), returning
+/// pre-signed media URLs grouped per requested term/reading pair
+#[instrument(skip(context, headers, payload))]
+pub async fn get_audio_batch(
+    State(context): State<Arc<LookupTermContext>>,
     headers: HeaderMap,
-) -> Result<Response, (StatusCode, String)> {
-    // Verify HMAC signature
-    verify_signed_url(&rel_path, &q, "/media/", "🎵")?;
-
-    // 3) Resolve file safely
-    let clean = StdPath::new(&rel_path);
-    if clean
-        .components()
-        .any(|c| matches!(c, std::path::Component::ParentDir))
-    {
-        return Err((StatusCode::BAD_REQUEST, "Invalid path".to_string()));
+    Json(payload): Json<AudioBatchRequest>,
+) -> Result<Json<AudioBatchResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let max_items = context
+        .config
+        .current()
+        .audio_batch_max_items
+        .min(MAX_AUDIO_BATCH_SIZE);
+    if payload.items.len() > max_items {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": format!("Too many items, at most {} are allowed", max_items)
+            })),
+        ));
     }
+    info!("🎵 Batch audio lookup for {} terms", payload.items.len());
 
-    let audio_dirs = std::env::var("AUDIO_DATA_DIRS").map_err(|_| {
-        error!("🎵 AUDIO_DATA_DIRS not configured");
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "AUDIO_DATA_DIRS not configured".to_string(),
-        )
-    })?;
+    let audio_db = audio_db(&context)?;
 
-    // Find the file across all audio directories
-    let full = find_audio_file_in_dirs(&audio_dirs, rel_path.as_str()).await?;
+    let preferences = audio_preferences(
+        &context,
+        extract_user_id_from_headers(&headers)
+            .ok()
+            .and_then(|s| Uuid::parse_str(&s).ok()),
+    )
+    .await;
 
-    let content = tokio::fs::read(&full).await.map_err(|e| {
-        error!("🎵 File read error: {}", e);
-        (StatusCode::NOT_FOUND, format!("File not found: {}", e))
-    })?;
+    let mut expressions: Vec<&str> = payload.items.iter().map(|i| i.term.as_str()).collect();
+    expressions.sort_unstable();
+    expressions.dedup();
 
-    let meta = tokio::fs::metadata(&full).await.map_err(|_| {
+    let mut entries = audio_db.query_by_terms(&expressions).map_err(|e| {
+        error!(?e, "Failed to query audio database for batch");
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to get file metadata".to_string(),
+            Json(serde_json::json!({ "error": format!("Failed to query audio database: {}", e) })),
         )
     })?;
-    let total_len = meta.len();
 
-    // 4) MIME type — IMPORTANT for Safari
-    // Prefer .ogg for Ogg Opus. If your files have .opus but are Ogg container,
-    // force audio/ogg (Safari dislikes audio/opus).
-    let mut mime = mime_guess::from_path(&full)
-        .first_or_octet_stream()
-        .essence_str()
-        .to_string();
-    if full.extension().and_then(|s| s.to_str()) == Some("opus") {
-        // If your container is Ogg Opus:
-        mime = "audio/ogg".to_string();
-        // If WebM Opus, use: mime = "audio/webm".to_string();
+    if let Some(preferences) = &preferences {
+        apply_audio_preferences(preferences, &mut entries);
     }
 
-    // 5) Handle Range (Safari requires this)
-    let range_hdr = headers.get("range").and_then(|v| v.to_str().ok());
-
-    let mut resp_headers = axum::http::HeaderMap::new();
-    resp_headers.insert("Accept-Ranges", "bytes".parse().unwrap());
-    resp_headers.insert("Content-Type", mime.parse().unwrap());
-
-    if let Some(range) = range_hdr {
-        if let Some(r) = range.strip_prefix("bytes=") {
-            let mut parts = r.splitn(2, '-');
-            let start: u64 = parts.next().and_then(|s| s.parse().ok()).unwrap_or(0);
-            let end: u64 = parts
-                .next()
-                .and_then(|e| if e.is_empty() { None } else { e.parse().ok() })
-                .unwrap_or(total_len.saturating_sub(1));
+    let mut entries_by_term: HashMap<&str, Vec<&AudioEntry>> = HashMap::new();
+    for entry in &entries {
+        entries_by_term
+            .entry(entry.expression.as_str())
+            .or_default()
+            .push(entry);
+    }
 
-            if start > end || end >= total_len {
-                return Err((
-                    StatusCode::RANGE_NOT_SATISFIABLE,
-                    format!("bytes */{total_len}"),
-                ));
+    let ttl = AUDIO_URL_TTL;
+    let mut tts_attempts = 0;
+    let mut results = Vec::with_capacity(payload.items.len());
+    for item in payload.items {
+        let mut audio_sources = Vec::new();
+        for entry in entries_by_term
+            .get(item.term.as_str())
+            .into_iter()
+            .flatten()
+        {
+            if item.reading.is_some() && entry.reading != item.reading {
+                continue;
             }
-
-            let chunk_len = end - start + 1;
-            let chunk = content.get(start as usize..(end + 1) as usize).ok_or((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Range read error".to_string(),
-            ))?;
-
-            resp_headers.insert(
-                "Content-Range",
-                format!("bytes {start}-{end}/{total_len}").parse().unwrap(),
-            );
-            resp_headers.insert("Content-Length", chunk_len.to_string().parse().unwrap());
-
-            let mut response = Response::builder()
-                .status(StatusCode::PARTIAL_CONTENT)
-                .body(Body::from(chunk.to_vec()))
-                .map_err(|_| {
+            let url = sign_media_url(&format!("{}_files/{}", entry.source, entry.file), ttl)
+                .map_err(|e| {
+                    error!(?e, "Failed to sign media URL");
                     (
                         StatusCode::INTERNAL_SERVER_ERROR,
-                        "Failed to build response".to_string(),
+                        Json(serde_json::json!({ "error": format!("Failed to sign media URL: {}", e) })),
                     )
                 })?;
-
-            *response.headers_mut() = resp_headers;
-            return Ok(response);
+            audio_sources.push(AudioSource {
+                name: audio_source_name(entry),
+                url,
+                duration_ms: entry.duration_ms,
+                peaks: entry.peaks.clone(),
+                gain_db: entry.gain_db,
+                tag: None,
+                autoplay: false,
+                pitch: entry.pitch,
+                pitch_match: false,
+            });
+        }
+        if audio_sources.is_empty() && tts_attempts < MAX_AUDIO_BATCH_TTS {
+            tts_attempts += 1;
+            let tts_source = tts_audio_source(
+                &context,
+                &headers,
+                &item.term,
+                item.reading.as_deref(),
+                &[],
+                preferences.as_ref(),
+            )
+            .await;
+            if let Some(mut source) = tts_source {
+                let rel_path = source.url.trim_start_matches("/audio/");
+                source.url = sign_media_url(rel_path, ttl).map_err(|e| {
+                    error!(?e, "Failed to sign media URL");
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(serde_json::json!({ "error": format!("Failed to sign media URL: {}", e) })),
+                    )
+                })?;
+                audio_sources.push(source);
+            }
         }
+        results.push(AudioBatchResult {
+            term: item.term,
+            reading: item.reading,
+            audio_sources,
+        });
     }
 
-    // 6) Full response
-    resp_headers.insert("Content-Length", total_len.to_string().parse().unwrap());
+    Ok(Json(AudioBatchResponse { results }))
+}
 
-    let mut response = Response::builder()
-        .status(StatusCode::OK)
-        .body(Body::from(content))
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to build response".to_string(),
-            )
-        })?;
+pub const MAX_SIGNED_AUDIO_FILES: usize = 500;
+const AUDIO_URL_TTL: Duration = Duration::from_secs(3600);
 
-    *response.headers_mut() = resp_headers;
-    Ok(response)
+#[derive(Debug, Deserialize)]
+pub struct SignAudioRequest {
+    /// Audio files relative to the audio data directories, e.g.
+    /// "nhk16_files/audio/foo.opus", or the `/audio/...` URLs `/api/audio`
+    /// returns
+    files: Vec<String>,
 }
 
-/// Signed URL image handler for serving dictionary images with HMAC verification
-pub async fn serve_signed_image(
+// The path under the audio data directories of a file to sign, None if it
+// could point outside of them
+fn audio_rel_path(file: &str) -> Option<&str> {
+    let rel_path = file
+        .strip_prefix("/audio/")
+        .unwrap_or(file)
+        .trim_start_matches('/');
+    let valid = !rel_path.is_empty()
+        && !rel_path.contains('\\')
+        && StdPath::new(rel_path)
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)));
+    valid.then_some(rel_path)
+}
+
+/// Signed `/media/` URLs for audio files, so clients other than the web app
+/// (which signs with MEDIA_URL_KEY itself) can play them without an auth
+/// header
+///
+/// Files of sources the user disabled are signed too: those preferences only
+/// decide which recordings lookups offer, and the user could play any of the
+/// files through `/audio/*path` anyway.
+#[instrument(skip(request))]
+pub async fn sign_audio_urls(
+    Json(request): Json<SignAudioRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    if request.files.len() > MAX_SIGNED_AUDIO_FILES {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            &format!("At most {MAX_SIGNED_AUDIO_FILES} files can be signed at once"),
+        ));
+    }
+
+    let mut urls = serde_json::Map::new();
+    for file in request.files {
+        let Some(rel_path) = audio_rel_path(&file) else {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid path: {file}"),
+            ));
+        };
+        let url = sign_media_url(rel_path, AUDIO_URL_TTL).map_err(|e| {
+            error!(?e, "Failed to sign media URL");
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to sign media URL",
+            )
+        })?;
+        urls.insert(file, url.into());
+    }
+
+    Ok(Json(serde_json::json!({ "urls": urls })))
+}
+
+pub const DEFAULT_AUDIO_SUGGESTIONS: usize = 20;
+pub const MAX_AUDIO_SUGGESTIONS: usize = 100;
+
+#[derive(Deserialize, Debug)]
+pub struct AudioSuggestionsQuery {
+    pub term: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct AudioSuggestion {
+    pub expression: String,
+    pub reading: Option<String>,
+}
+
+/// Expressions with audio close to a term that has none of its own: kana and
+/// Unicode variants of the term first, then expressions it is a prefix of
+pub async fn get_audio_suggestions(
+    State(context): State<Arc<LookupTermContext>>,
+    Query(params): Query<AudioSuggestionsQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let audio_db = audio_db(&context)?;
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_AUDIO_SUGGESTIONS)
+        .min(MAX_AUDIO_SUGGESTIONS);
+    let query_error = |e: anyhow::Error| {
+        error!(?e, "Failed to query audio suggestions for: {}", params.term);
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to query audio database",
+        )
+    };
+
+    let mut entries = audio_db.query_fuzzy(&params.term).map_err(query_error)?;
+    let folded: String = params.term.trim().nfkc().collect();
+    // Several entries (sources, speakers) usually share an expression
+    entries.extend(
+        audio_db
+            .query_by_prefix(&folded, limit * 4)
+            .map_err(query_error)?,
+    );
+    let mut suggestions: Vec<AudioSuggestion> = Vec::new();
+    for entry in entries {
+        let suggestion = AudioSuggestion {
+            expression: entry.expression,
+            reading: entry.reading,
+        };
+        if !suggestions.contains(&suggestion) {
+            suggestions.push(suggestion);
+        }
+    }
+    suggestions.truncate(limit);
+
+    Ok(Json(serde_json::json!({ "suggestions": suggestions })))
+}
+
+pub const DEFAULT_AUDIO_DISPLAY_RESULTS: usize = 50;
+pub const MAX_AUDIO_DISPLAY_RESULTS: usize = 500;
+
+#[derive(Deserialize, Debug)]
+pub struct AudioDisplayQuery {
+    /// SQL LIKE pattern matched against the display text, e.g. `%[2]%`
+    pub pattern: String,
+    pub source: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioDisplayMatch {
+    pub expression: String,
+    pub reading: Option<String>,
+    pub display: Option<String>,
+    #[serde(flatten)]
+    pub audio_source: AudioSource,
+}
+
+/// Audio entries by their display text, which for some sources (e.g. NHK)
+/// shows the accent pattern, so pitch study tools can find every recording
+/// of a pattern
+pub async fn search_audio_display(
+    State(context): State<Arc<LookupTermContext>>,
+    Query(params): Query<AudioDisplayQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let audio_db = audio_db(&context)?;
+    if params.pattern.trim_matches('%').is_empty() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Pattern must not be empty or only wildcards",
+        ));
+    }
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_AUDIO_DISPLAY_RESULTS)
+        .min(MAX_AUDIO_DISPLAY_RESULTS);
+
+    let entries = audio_db
+        .query_display_like(&params.pattern, params.source.as_deref(), limit)
+        .map_err(|e| {
+            error!(?e, "Failed to query audio by display: {}", params.pattern);
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to query audio database",
+            )
+        })?;
+    let matches: Vec<AudioDisplayMatch> = entries
+        .into_iter()
+        .map(|entry| AudioDisplayMatch {
+            audio_source: AudioSource {
+                name: audio_source_name(&entry),
+                url: format!("/audio/{}_files/{}", entry.source, entry.file),
+                duration_ms: entry.duration_ms,
+                peaks: entry.peaks,
+                gain_db: entry.gain_db,
+                tag: None,
+                autoplay: false,
+                pitch: entry.pitch,
+                pitch_match: false,
+            },
+            expression: entry.expression,
+            reading: entry.reading,
+            display: entry.display,
+        })
+        .collect();
+
+    Ok(Json(serde_json::json!({ "matches": matches })))
+}
+
+/// List audio speakers per source with entry counts, so clients can rank or mute them
+pub async fn get_audio_speakers(
+    State(context): State<Arc<LookupTermContext>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    let audio_db = audio_db(&context)?;
+
+    let speakers = audio_db.list_speakers().map_err(|e| {
+        error!(?e, "Failed to list audio speakers");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": format!("Failed to list audio speakers: {}", e) })),
+        )
+    })?;
+
+    Ok(Json(serde_json::json!({
+        "speakers": speakers
+    })))
+}
+
+/// Totals of the audio database with breakdowns by source and by speaker, for
+/// the admin to check what a bootstrap or audio pack import added
+pub async fn get_audio_stats(
+    State(context): State<Arc<LookupTermContext>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let audio_db = audio_db(&context)?;
+    let query_error = |e: anyhow::Error| {
+        error!(?e, "❌ Failed to get audio database stats");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to get audio database stats: {e}"),
+        )
+    };
+    let stats = audio_db.get_stats().map_err(query_error)?;
+    let speakers = audio_db.list_speakers().map_err(query_error)?;
+    let cache = audio_db.cache_stats().map_err(query_error)?;
+
+    Ok(Json(serde_json::json!({
+        "stats": stats,
+        "speakers": speakers,
+        "cache": cache,
+    })))
+}
+
+/// Re-open the audio database from AUDIO_DB_PATH and swap it in, so a freshly
+/// bootstrapped database is used without a restart. The current database is
+/// kept if the new one can't be opened.
+pub async fn reload_audio_db(
+    State(context): State<Arc<LookupTermContext>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    // Admin check is handled by the auth middleware
+    let db_path = std::env::var("AUDIO_DB_PATH").map_err(|_| {
+        api_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Audio database not configured",
+        )
+    })?;
+    let open_path = db_path.clone();
+    let (audio_db, stats) = tokio::task::spawn_blocking(move || {
+        let audio_db = open_audio_db(&open_path)?;
+        let stats = audio_db.get_stats()?;
+        anyhow::Ok((audio_db, stats))
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|result| result)
+    .map_err(|e| {
+        error!(?e, ?db_path, "❌ Failed to reload audio database");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to reload audio database: {e}"),
+        )
+    })?;
+
+    let previous = context.audio_db.swap(Some(Arc::new(audio_db)));
+    info!(
+        ?db_path,
+        total_entries = stats.total_entries,
+        replaced = previous.is_some(),
+        "🔄 Reloaded audio database"
+    );
+    Ok(Json(serde_json::json!({
+        "path": db_path,
+        "stats": stats,
+    })))
+}
+
+#[derive(TryFromMultipart)]
+pub struct ComparePronunciationRequest {
+    #[form_data(limit = "10MiB")]
+    recording: FieldData<NamedTempFile>,
+    /// Id of the audio database entry to compare against
+    reference_id: i64,
+}
+
+/// Compare the user's recording of a word with a reference recording from the
+/// audio database, returning a score and both pitch contours aligned
+#[instrument(skip(context, headers, request))]
+pub async fn compare_pronunciation(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    TypedMultipart(request): TypedMultipart<ComparePronunciationRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let reference = audio_db(&context)?
+        .get_entry(request.reference_id)
+        .map_err(|e| {
+            error!(?e, "❌ Failed to query audio database");
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to query audio database",
+            )
+        })?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Reference audio not found"))?;
+    let audio_data_dirs = std::env::var("AUDIO_DATA_DIRS").map_err(|_| {
+        api_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Audio files are not configured",
+        )
+    })?;
+    let reference_path = find_audio_file_in_dirs(
+        &audio_data_dirs,
+        &format!("{}_files/{}", reference.source, reference.file),
+    )
+    .await
+    .map_err(|(status, message)| api_error(status, &message))?;
+
+    let recording = request.recording;
+    let comparison = tokio::task::spawn_blocking(move || {
+        pronunciation::compare_files(&reference_path, recording.contents.path())
+    })
+    .await
+    .map_err(|e| {
+        error!(?e, "❌ Pronunciation comparison task failed");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Pronunciation comparison failed",
+        )
+    })?
+    .map_err(|e| {
+        info!(%user_id, reference_id = request.reference_id, "Can't compare recordings: {e:#}");
+        api_error(StatusCode::UNPROCESSABLE_ENTITY, &format!("{e:#}"))
+    })?;
+
+    Ok(Json(serde_json::json!({ "comparison": comparison })))
+}
+
+#[derive(Deserialize)]
+pub struct SigQuery {
+    exp: u64,
+    sig: String,
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Generate HMAC signature for a given path, expiry, and key
+/// This matches the Next.js frontend signing logic exactly
+pub fn generate_hmac_signature(path: &str, exp: u64, key: &str) -> String {
+    let method = "GET";
+    let canonical = format!("{method}\n{path}\nexp={}", exp);
+
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).unwrap();
+    mac.update(canonical.as_bytes());
+    let sig_bytes = mac.finalize().into_bytes();
+    URL_SAFE_NO_PAD.encode(sig_bytes)
+}
+
+/// Verify HMAC signature for signed URLs
+/// Returns Ok(()) if signature is valid, Err with appropriate status code otherwise
+fn verify_signed_url(
+    rel_path: &str,
+    q: &SigQuery,
+    path_prefix: &str,
+    error_prefix: &str,
+) -> Result<(), (StatusCode, String)> {
+    // 1) Check expiry
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "System time error".to_string(),
+            )
+        })?
+        .as_secs();
+    if q.exp < now {
+        return Err((StatusCode::UNAUTHORIZED, "URL expired".to_string()));
+    }
+
+    // 2) Verify HMAC (must match Next.js signer)
+    let key = std::env::var("MEDIA_URL_KEY").map_err(|_| {
+        error!("{} MEDIA_URL_KEY not configured", error_prefix);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "MEDIA_URL_KEY not configured".to_string(),
+        )
+    })?;
+
+    let path_for_sig = format!("{}{}", path_prefix, rel_path);
+    let expected_sig = generate_hmac_signature(&path_for_sig, q.exp, &key);
+
+    let sig_bytes = URL_SAFE_NO_PAD
+        .decode(q.sig.as_bytes())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Bad signature (b64)".to_string()))?;
+
+    let actual_sig = URL_SAFE_NO_PAD.encode(sig_bytes);
+    if actual_sig != expected_sig {
+        return Err((StatusCode::UNAUTHORIZED, "Bad signature".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Who may read dictionary static files under `/dicts/*path`, set per
+/// deployment with `DICTS_STATIC_ACCESS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DictsStaticAccess {
+    /// Anyone (the default)
+    Public,
+    /// HMAC signed URLs, as for `/media/img/*path`, signed for `/dicts/{path}`
+    Signed,
+    /// Signed in users, through the auth layer as for `/audio/*path`
+    Auth,
+}
+
+impl std::str::FromStr for DictsStaticAccess {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "public" => Ok(Self::Public),
+            "signed" => Ok(Self::Signed),
+            "auth" => Ok(Self::Auth),
+            other => Err(anyhow::anyhow!(
+                "Invalid DICTS_STATIC_ACCESS {other:?}, expected public, signed or auth"
+            )),
+        }
+    }
+}
+
+impl DictsStaticAccess {
+    pub fn from_env() -> anyhow::Result<Self> {
+        std::env::var("DICTS_STATIC_ACCESS")
+            .unwrap_or_default()
+            .parse()
+    }
+}
+
+/// `/dicts/*path` for deployments that require signed URLs
+pub async fn serve_signed_static_file(
+    headers: HeaderMap,
+    Path(file_path): Path<String>,
+    Query(q): Query<SigQuery>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    verify_signed_url(&file_path, &q, "/dicts/", "📚")?;
+    serve_static_file(headers, Path(file_path)).await
+}
+
+// The synthesized clip `name` of the TTS cache
+async fn tts_clip_response(
+    method: &Method,
+    headers: &HeaderMap,
+    name: &str,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let path = tts::clip_path(name)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Audio file not found".to_string()))?;
+    let content_type = match path.extension().and_then(|s| s.to_str()) {
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") => "audio/ogg",
+        _ => "audio/wav",
+    };
+    media_file_response(method, headers, &path, content_type, None).await
+}
+
+/// Signed URL media handler for serving audio files with HMAC verification
+pub async fn serve_signed_media(
+    method: Method,
     Path(rel_path): Path<String>,
     Query(q): Query<SigQuery>,
+    Query(query): Query<AudioFileQuery>,
+    headers: HeaderMap,
 ) -> Result<Response, (StatusCode, String)> {
     // Verify HMAC signature
-    verify_signed_url(&rel_path, &q, "/media/img/", "🖼️")?;
+    verify_signed_url(&rel_path, &q, "/media/", "🎵")?;
 
-    // 3) Resolve file safely with proper Unicode normalization (same as serve_static_file)
-    // URL decode the path (Next.js doesn't decode it)
-    let decoded_path = urlencoding::decode(&rel_path)
-        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid URL encoding".to_string()))?;
+    if let Some(name) = rel_path.strip_prefix(tts::URL_PREFIX) {
+        return tts_clip_response(&method, &headers, name).await;
+    }
 
-    // Normalize the path to NFD for filesystem compatibility (macOS/APFS stores filenames in NFD)
-    let normalized_path = decoded_path.nfd().collect::<String>();
+    // 3) Resolve file safely
+    let clean = StdPath::new(&rel_path);
+    if clean
+        .components()
+        .any(|c| matches!(c, std::path::Component::ParentDir))
+    {
+        return Err((StatusCode::BAD_REQUEST, "Invalid path".to_string()));
+    }
 
-    // Use the same static file path as the existing serve_static_file function
-    let static_path = std::env::var("DICTS_PATH").map_err(|_| {
+    let audio_dirs = std::env::var("AUDIO_DATA_DIRS").map_err(|_| {
+        error!("🎵 AUDIO_DATA_DIRS not configured");
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            "DICTS_PATH not configured".to_string(),
+            "AUDIO_DATA_DIRS not configured".to_string(),
+        )
+    })?;
+
+    // Find the file across all audio directories
+    let full = find_audio_file_in_dirs(&audio_dirs, rel_path.as_str()).await?;
+    if let Some(trimmed) = trimmed_audio_file(&query, &full, &rel_path).await {
+        return media_file_response(&method, &headers, &trimmed, "audio/wav", None).await;
+    }
+
+    // 4) MIME type — IMPORTANT for Safari
+    // Prefer .ogg for Ogg Opus. If your files have .opus but are Ogg container,
+    // force audio/ogg (Safari dislikes audio/opus).
+    let mut mime = mime_guess::from_path(&full)
+        .first_or_octet_stream()
+        .essence_str()
+        .to_string();
+    if full.extension().and_then(|s| s.to_str()) == Some("opus") {
+        // If your container is Ogg Opus:
+        mime = "audio/ogg".to_string();
+        // If WebM Opus, use: mime = "audio/webm".to_string();
+    }
+
+    media_file_response(&method, &headers, &full, &mime, None).await
+}
+
+/// Signed URL image handler for serving dictionary images with HMAC verification
+pub async fn serve_signed_image(
+    method: Method,
+    headers: HeaderMap,
+    Path(rel_path): Path<String>,
+    Query(q): Query<SigQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    // Verify HMAC signature
+    verify_signed_url(&rel_path, &q, "/media/img/", "🖼️")?;
+
+    // 3) Resolve file safely with proper Unicode normalization (same as serve_static_file)
+    // URL decode the path (Next.js doesn't decode it)
+    let decoded_path = urlencoding::decode(&rel_path)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid URL encoding".to_string()))?;
+
+    // Use the same static file path as the existing serve_static_file function
+    let static_path = std::env::var("DICTS_PATH").map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "DICTS_PATH not configured".to_string(),
+        )
+    })?;
+
+    // Construct the full path (same as serve_static_file)
+    let base_static = StdPath::new(&static_path).join("static");
+    let full_path = AssetStore::new(camino::Utf8PathBuf::from(&static_path).join("static"))
+        .resolve(&decoded_path)
+        .map(PathBuf::from)
+        .or_else(|| normalization::find_existing(&base_static, &decoded_path))
+        .unwrap_or_else(|| base_static.join(normalization::normalize_name(&decoded_path)));
+
+    // Security check: ensure the path is within the static directory
+    let static_dir = base_static.canonicalize().map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to canonicalize static dir".to_string(),
+        )
+    })?;
+
+    let canonical_path = full_path
+        .canonicalize()
+        .map_err(|_| (StatusCode::NOT_FOUND, "File not found".to_string()))?;
+
+    if !canonical_path.starts_with(&static_dir) {
+        return Err((StatusCode::FORBIDDEN, "Access denied".to_string()));
+    }
+
+    info!(
+        "🖼️ Image request: rel_path={}, static_path={}, full_path={}, canonical_path={}",
+        rel_path,
+        static_path,
+        full_path.display(),
+        canonical_path.display()
+    );
+
+    // 4) MIME type, from the requested path since stored assets have no extension
+    let mime = mime_guess::from_path(decoded_path.as_ref())
+        .first_or_octet_stream()
+        .essence_str()
+        .to_string();
+
+    media_file_response(
+        &method,
+        &headers,
+        &canonical_path,
+        &mime,
+        Some("public, max-age=3600"),
+    )
+    .await
+}
+
+/// Response for `rel_path` of one of `user_id`'s books
+async fn book_resource_response(
+    context: &Arc<LookupTermContext>,
+    method: &Method,
+    headers: &HeaderMap,
+    user_id: &str,
+    upload_id: &str,
+    rel_path: &str,
+) -> Result<Response, (StatusCode, String)> {
+    let rel_path = book_resources::resource_path(rel_path)
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "Invalid path".to_string()))?;
+    let uploads_dir = context.uploads_dir.as_deref().ok_or_else(|| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Library processing is not configured".to_string(),
+        )
+    })?;
+    let library_dir = library::user_library_dir(uploads_dir, user_id)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let blocking_context = context.clone();
+    let (user_id, upload_id) = (user_id.to_string(), upload_id.to_string());
+    let path = tokio::task::spawn_blocking(move || {
+        blocking_context
+            .book_resources
+            .resolve(&library_dir, &user_id, &upload_id, &rel_path)
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|r| r)
+    .map_err(|e| {
+        error!(?e, "📖 Failed to read book resource");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read book".to_string(),
+        )
+    })?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "File not found".to_string()))?;
+
+    let mime = mime_guess::from_path(&path)
+        .first_or_octet_stream()
+        .essence_str()
+        .to_string();
+    media_file_response(method, headers, &path, &mime, Some("private, max-age=3600")).await
+}
+
+/// A file of one of the current user's books, e.g. an image a chapter
+/// references. Files of stored EPUBs are extracted when first asked for.
+pub async fn get_book_resource(
+    State(context): State<Arc<LookupTermContext>>,
+    method: Method,
+    headers: HeaderMap,
+    Path((upload_id, rel_path)): Path<(String, String)>,
+) -> Result<Response, (StatusCode, String)> {
+    let user_id = extract_user_id_from_headers(&headers).map_err(|_| {
+        (
+            StatusCode::UNAUTHORIZED,
+            "User not authenticated".to_string(),
+        )
+    })?;
+    book_resource_response(&context, &method, &headers, &user_id, &upload_id, &rel_path).await
+}
+
+pub const MAX_SIGNED_BOOK_RESOURCES: usize = 500;
+const BOOK_RESOURCE_URL_TTL: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Deserialize)]
+pub struct SignBookResourcesRequest {
+    paths: Vec<String>,
+}
+
+/// Signed URLs for files of one of the current user's books, for references
+/// in chapters (images, stylesheets) that can't send an auth header
+#[instrument(skip(headers, request))]
+pub async fn sign_book_resource_urls(
+    headers: HeaderMap,
+    Path(upload_id): Path<String>,
+    Json(request): Json<SignBookResourcesRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    if request.paths.len() > MAX_SIGNED_BOOK_RESOURCES {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            &format!("At most {MAX_SIGNED_BOOK_RESOURCES} paths can be signed at once"),
+        ));
+    }
+    // Upload ids are directory names
+    library::user_library_dir(StdPath::new(""), &upload_id)
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid upload id"))?;
+
+    let prefix = book_resource_prefix(&user_id, &upload_id);
+    let mut urls = serde_json::Map::new();
+    for path in request.paths {
+        let Some(rel_path) = book_resources::resource_path(&path) else {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid path: {path}"),
+            ));
+        };
+        let url =
+            sign_url(&prefix, &rel_path.to_string_lossy(), BOOK_RESOURCE_URL_TTL).map_err(|e| {
+                error!(?e, "Failed to sign book resource URL");
+                api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to sign book resource URL",
+                )
+            })?;
+        urls.insert(path, url.into());
+    }
+
+    Ok(Json(serde_json::json!({ "urls": urls })))
+}
+
+/// Signed URL handler for the files of a user's book
+pub async fn serve_signed_book_resource(
+    State(context): State<Arc<LookupTermContext>>,
+    method: Method,
+    headers: HeaderMap,
+    Path((user_id, upload_id, rel_path)): Path<(String, String, String)>,
+    Query(q): Query<SigQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    verify_signed_url(
+        &rel_path,
+        &q,
+        &book_resource_prefix(&user_id, &upload_id),
+        "📖",
+    )?;
+    book_resource_response(&context, &method, &headers, &user_id, &upload_id, &rel_path).await
+}
+
+type ApiError = (StatusCode, Json<serde_json::Value>);
+
+fn api_error(status: StatusCode, message: &str) -> ApiError {
+    (status, Json(serde_json::json!({ "error": message })))
+}
+
+// Extract the user id for handlers that require a signed in user
+fn require_user_id(headers: &HeaderMap) -> Result<String, ApiError> {
+    extract_user_id_from_headers(headers).map_err(|e| {
+        error!(?e, "Failed to extract user ID from headers");
+        api_error(StatusCode::UNAUTHORIZED, "Unauthorized")
+    })
+}
+
+fn vocab_db_error(e: anyhow::Error) -> ApiError {
+    error!(?e, "❌ Vocab list database error");
+    api_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Vocab list database error",
+    )
+}
+
+fn vocab_list_not_found() -> ApiError {
+    api_error(StatusCode::NOT_FOUND, "Vocab list not found")
+}
+
+#[derive(Deserialize)]
+pub struct VocabListNameRequest {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VocabReorderRequest {
+    entry_ids: Vec<Uuid>,
+}
+
+/// Create a new vocab list for the current user
+#[instrument(skip(context, headers, request))]
+pub async fn create_vocab_list(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Json(request): Json<VocabListNameRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let name =
+        validate_list_name(&request.name).map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+
+    let list = context
+        .vocab_lists_db
+        .create_list(&user_id, &name)
+        .await
+        .map_err(vocab_db_error)?;
+    info!(list_id = %list.id, user_id = %user_id, "✅ Created vocab list");
+
+    Ok(Json(serde_json::json!({ "list": list })))
+}
+
+/// List the current user's vocab lists, most recently updated first
+#[instrument(skip(context, headers))]
+pub async fn get_vocab_lists(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let lists = context
+        .vocab_lists_db
+        .get_user_lists(&user_id)
+        .await
+        .map_err(vocab_db_error)?;
+
+    Ok(Json(serde_json::json!({ "lists": lists })))
+}
+
+/// Get a single vocab list with its entries in order
+#[instrument(skip(context, headers))]
+pub async fn get_vocab_list(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(list_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let list = context
+        .vocab_lists_db
+        .get_list(&user_id, list_id)
+        .await
+        .map_err(vocab_db_error)?
+        .ok_or_else(vocab_list_not_found)?;
+
+    Ok(Json(serde_json::json!({ "list": list })))
+}
+
+#[instrument(skip(context, headers, request))]
+pub async fn rename_vocab_list(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(list_id): Path<Uuid>,
+    Json(request): Json<VocabListNameRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let name =
+        validate_list_name(&request.name).map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+
+    let renamed = context
+        .vocab_lists_db
+        .rename_list(&user_id, list_id, &name)
+        .await
+        .map_err(vocab_db_error)?;
+    if !renamed {
+        return Err(vocab_list_not_found());
+    }
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+#[instrument(skip(context, headers))]
+pub async fn delete_vocab_list(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(list_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let deleted = context
+        .vocab_lists_db
+        .delete_list(&user_id, list_id)
+        .await
+        .map_err(vocab_db_error)?;
+    if !deleted {
+        return Err(vocab_list_not_found());
+    }
+    info!(list_id = %list_id, user_id = %user_id, "🗑️ Deleted vocab list");
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Append a lookup result to the end of a vocab list
+#[instrument(skip(context, headers, entry))]
+pub async fn add_vocab_list_entry(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(list_id): Path<Uuid>,
+    Json(entry): Json<NewVocabEntry>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    if entry.term.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Term must not be empty"));
+    }
+
+    let entry = context
+        .vocab_lists_db
+        .add_entry(&user_id, list_id, &entry)
+        .await
+        .map_err(vocab_db_error)?
+        .ok_or_else(vocab_list_not_found)?;
+
+    Ok(Json(serde_json::json!({ "entry": entry })))
+}
+
+#[instrument(skip(context, headers))]
+pub async fn remove_vocab_list_entry(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path((list_id, entry_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let removed = context
+        .vocab_lists_db
+        .remove_entry(&user_id, list_id, entry_id)
+        .await
+        .map_err(vocab_db_error)?;
+    if !removed {
+        return Err(api_error(
+            StatusCode::NOT_FOUND,
+            "Vocab list entry not found",
+        ));
+    }
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Reorder the entries of a vocab list. The body must list every entry id
+/// of the list exactly once, in the new order.
+#[instrument(skip(context, headers, request))]
+pub async fn reorder_vocab_list(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(list_id): Path<Uuid>,
+    Json(request): Json<VocabReorderRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    context
+        .vocab_lists_db
+        .reorder_entries(&user_id, list_id, &request.entry_ids)
+        .await
+        .map_err(vocab_db_error)?
+        .ok_or_else(vocab_list_not_found)?
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Create a read-only share token for a vocab list. Sharing again replaces
+/// the previous token, which revokes old links.
+#[instrument(skip(context, headers))]
+pub async fn share_vocab_list(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(list_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let share_token = context
+        .vocab_lists_db
+        .share_list(&user_id, list_id)
+        .await
+        .map_err(vocab_db_error)?
+        .ok_or_else(vocab_list_not_found)?;
+    info!(list_id = %list_id, "🔗 Shared vocab list");
+
+    Ok(Json(serde_json::json!({ "shareToken": share_token })))
+}
+
+#[instrument(skip(context, headers))]
+pub async fn unshare_vocab_list(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(list_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let unshared = context
+        .vocab_lists_db
+        .unshare_list(&user_id, list_id)
+        .await
+        .map_err(vocab_db_error)?;
+    if !unshared {
+        return Err(vocab_list_not_found());
+    }
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Read-only view of a shared vocab list (no auth, the token grants access)
+#[instrument(skip(context, share_token))]
+pub async fn get_shared_vocab_list(
+    State(context): State<Arc<LookupTermContext>>,
+    Path(share_token): Path<String>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let mut list = context
+        .vocab_lists_db
+        .get_shared_list(&share_token)
+        .await
+        .map_err(vocab_db_error)?
+        .ok_or_else(vocab_list_not_found)?;
+    // Viewers don't get to see (or re-share) the token itself
+    list.list.share_token = None;
+
+    Ok(Json(serde_json::json!({ "list": list })))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VocabVisibilityRequest {
+    is_public: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PublicDecksQuery {
+    q: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+/// Publish or unpublish a vocab list in the public deck browser
+#[instrument(skip(context, headers, request))]
+pub async fn set_vocab_list_visibility(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(list_id): Path<Uuid>,
+    Json(request): Json<VocabVisibilityRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let updated = context
+        .vocab_lists_db
+        .set_public(&user_id, list_id, request.is_public)
+        .await
+        .map_err(vocab_db_error)?;
+    if !updated {
+        return Err(vocab_list_not_found());
+    }
+    info!(list_id = %list_id, is_public = request.is_public, "Updated vocab list visibility");
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Browse public decks, optionally filtered by name
+#[instrument(skip(context))]
+pub async fn get_public_decks(
+    State(context): State<Arc<LookupTermContext>>,
+    Query(params): Query<PublicDecksQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let limit = params
+        .limit
+        .unwrap_or(20)
+        .clamp(1, MAX_PUBLIC_DECKS_PAGE_SIZE);
+    let offset = params.offset.unwrap_or(0).max(0);
+    let decks = context
+        .vocab_lists_db
+        .search_public_lists(params.q.as_deref(), limit, offset)
+        .await
+        .map_err(vocab_db_error)?;
+
+    Ok(Json(public_decks_json(decks)))
+}
+
+// The public deck listing, without the owners' share tokens: a share link
+// keeps working after its deck is made private again
+fn public_decks_json(mut decks: Vec<VocabList>) -> serde_json::Value {
+    for deck in &mut decks {
+        deck.share_token = None;
+    }
+    serde_json::json!({ "decks": decks })
+}
+
+#[instrument(skip(context))]
+pub async fn get_public_deck(
+    State(context): State<Arc<LookupTermContext>>,
+    Path(list_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let mut deck = context
+        .vocab_lists_db
+        .get_public_list(list_id)
+        .await
+        .map_err(vocab_db_error)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Deck not found"))?;
+    deck.list.share_token = None;
+
+    Ok(Json(serde_json::json!({ "deck": deck })))
+}
+
+/// Copy a public deck with all of its entries into the current user's lists
+#[instrument(skip(context, headers))]
+pub async fn clone_public_deck(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(list_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let list = context
+        .vocab_lists_db
+        .clone_public_list(&user_id, list_id)
+        .await
+        .map_err(vocab_db_error)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Deck not found"))?;
+    info!(source_list_id = %list_id, list_id = %list.id, user_id = %user_id, "✅ Cloned public deck");
+
+    Ok(Json(serde_json::json!({ "list": list })))
+}
+
+fn custom_dictionary_error(e: anyhow::Error) -> ApiError {
+    error!(?e, "❌ Custom dictionary error");
+    api_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Custom dictionary storage error",
+    )
+}
+
+fn custom_dictionary_not_found() -> ApiError {
+    api_error(StatusCode::NOT_FOUND, "Custom dictionary not found")
+}
+
+fn custom_dictionary_entry_not_found() -> ApiError {
+    api_error(StatusCode::NOT_FOUND, "Custom dictionary entry not found")
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateCustomDictionaryRequest {
+    title: String,
+    description: Option<String>,
+    /// Upload id of a book to attach the dictionary to as a glossary overlay
+    book_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomDictionariesQuery {
+    /// Only list the overlays of this book
+    book_id: Option<String>,
+}
+
+/// Create an empty custom dictionary for the current user
+#[instrument(skip(context, headers, request))]
+pub async fn create_custom_dictionary(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Json(request): Json<CreateCustomDictionaryRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let title = custom_dictionaries::validate_title(&request.title)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+
+    let dictionary = context
+        .custom_dictionaries
+        .create(&user_id, &title, request.description, request.book_id)
+        .map_err(custom_dictionary_error)?
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+    info!(dict_id = %dictionary.id, user_id = %user_id, "✅ Created custom dictionary");
+
+    Ok(Json(serde_json::json!({ "dictionary": dictionary })))
+}
+
+/// List the current user's custom dictionaries by title
+#[instrument(skip(context, headers))]
+pub async fn get_custom_dictionaries(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Query(query): Query<CustomDictionariesQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let mut dictionaries = context
+        .custom_dictionaries
+        .list(&user_id)
+        .map_err(custom_dictionary_error)?;
+    if let Some(book_id) = &query.book_id {
+        dictionaries.retain(|d| d.book_id.as_ref() == Some(book_id));
+    }
+
+    Ok(Json(serde_json::json!({ "dictionaries": dictionaries })))
+}
+
+/// Get a custom dictionary with all of its entries
+#[instrument(skip(context, headers))]
+pub async fn get_custom_dictionary(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(dict_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let dictionary = context
+        .custom_dictionaries
+        .get(&user_id, dict_id)
+        .map_err(custom_dictionary_error)?
+        .ok_or_else(custom_dictionary_not_found)?;
+
+    Ok(Json(serde_json::json!({ "dictionary": dictionary })))
+}
+
+#[instrument(skip(context, headers))]
+pub async fn delete_custom_dictionary(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(dict_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let deleted = context
+        .custom_dictionaries
+        .delete(&user_id, dict_id)
+        .map_err(custom_dictionary_error)?;
+    if !deleted {
+        return Err(custom_dictionary_not_found());
+    }
+    context.lookup_cache.clear();
+    info!(dict_id = %dict_id, user_id = %user_id, "🗑️ Deleted custom dictionary");
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+#[instrument(skip(context, headers, entry))]
+pub async fn add_custom_dictionary_entry(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(dict_id): Path<Uuid>,
+    Json(entry): Json<CustomEntryInput>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let entry = custom_dictionaries::validate_entry(entry)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+
+    let entry = context
+        .custom_dictionaries
+        .add_entry(&user_id, dict_id, entry)
+        .map_err(custom_dictionary_error)?
+        .ok_or_else(custom_dictionary_not_found)?;
+    context.lookup_cache.clear();
+
+    Ok(Json(serde_json::json!({ "entry": entry })))
+}
+
+/// Replace an entry of a custom dictionary, keeping its id
+#[instrument(skip(context, headers, entry))]
+pub async fn update_custom_dictionary_entry(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path((dict_id, entry_id)): Path<(Uuid, i64)>,
+    Json(entry): Json<CustomEntryInput>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let entry = custom_dictionaries::validate_entry(entry)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+
+    let entry = context
+        .custom_dictionaries
+        .update_entry(&user_id, dict_id, entry_id, entry)
+        .map_err(custom_dictionary_error)?
+        .ok_or_else(custom_dictionary_entry_not_found)?;
+    context.lookup_cache.clear();
+
+    Ok(Json(serde_json::json!({ "entry": entry })))
+}
+
+#[instrument(skip(context, headers))]
+pub async fn delete_custom_dictionary_entry(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path((dict_id, entry_id)): Path<(Uuid, i64)>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let deleted = context
+        .custom_dictionaries
+        .delete_entry(&user_id, dict_id, entry_id)
+        .map_err(custom_dictionary_error)?;
+    if !deleted {
+        return Err(custom_dictionary_entry_not_found());
+    }
+    context.lookup_cache.clear();
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+fn entry_notes_error(e: anyhow::Error) -> ApiError {
+    error!(?e, "❌ Entry notes database error");
+    api_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Entry notes database error",
+    )
+}
+
+fn entry_note_not_found() -> ApiError {
+    api_error(StatusCode::NOT_FOUND, "Note not found")
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateEntryNoteRequest {
+    note: Option<String>,
+    is_shared: Option<bool>,
+}
+
+/// Attach a note to a (dictionary, term, reading) entry, optionally shared
+/// with the other users of this instance
+#[instrument(skip(context, headers, request))]
+pub async fn create_entry_note(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Json(mut request): Json<NewEntryNote>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    if request.dictionary.trim().is_empty() || request.term.trim().is_empty() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Dictionary and term are required",
+        ));
+    }
+    request.note = entry_notes::validate_note(&request.note)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+
+    let note = context
+        .entry_notes_db
+        .add_note(&user_id, &request)
+        .await
+        .map_err(entry_notes_error)?;
+    info!(note_id = %note.id, user_id = %user_id, "✅ Added entry note");
+    context.lookup_cache.clear();
+
+    Ok(Json(serde_json::json!({ "note": note })))
+}
+
+/// List the current user's own entry notes, most recently updated first
+#[instrument(skip(context, headers))]
+pub async fn get_entry_notes(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let notes = context
+        .entry_notes_db
+        .get_user_notes(&user_id)
+        .await
+        .map_err(entry_notes_error)?;
+
+    Ok(Json(serde_json::json!({ "notes": notes })))
+}
+
+#[instrument(skip(context, headers, request))]
+pub async fn update_entry_note(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(note_id): Path<Uuid>,
+    Json(request): Json<UpdateEntryNoteRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let text = request
+        .note
+        .as_deref()
+        .map(entry_notes::validate_note)
+        .transpose()
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+
+    let note = context
+        .entry_notes_db
+        .update_note(&user_id, note_id, text.as_deref(), request.is_shared)
+        .await
+        .map_err(entry_notes_error)?
+        .ok_or_else(entry_note_not_found)?;
+    context.lookup_cache.clear();
+
+    Ok(Json(serde_json::json!({ "note": note })))
+}
+
+#[instrument(skip(context, headers))]
+pub async fn delete_entry_note(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(note_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let deleted = context
+        .entry_notes_db
+        .delete_note(&user_id, note_id)
+        .await
+        .map_err(entry_notes_error)?;
+    if !deleted {
+        return Err(entry_note_not_found());
+    }
+    context.lookup_cache.clear();
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+fn integrations_error(e: anyhow::Error) -> ApiError {
+    error!(?e, "❌ Integrations database error");
+    api_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Integrations database error",
+    )
+}
+
+fn integration_provider(provider: &str) -> Result<Provider, ApiError> {
+    provider
+        .parse()
+        .map_err(|_| api_error(StatusCode::NOT_FOUND, "Unknown integration"))
+}
+
+fn integration_not_connected() -> ApiError {
+    api_error(StatusCode::NOT_FOUND, "Integration not connected")
+}
+
+/// The current user's connected integrations and how their last sync went
+#[instrument(skip(context, headers))]
+pub async fn get_integrations(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let integrations = context
+        .integrations
+        .statuses(&user_id)
+        .await
+        .map_err(integrations_error)?;
+
+    Ok(Json(serde_json::json!({ "integrations": integrations })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConnectIntegrationRequest {
+    pub token: String,
+}
+
+/// Save the user's API token for a service once the service accepts it, then
+/// sync the words they know from it in the background
+#[instrument(skip(context, headers, request))]
+pub async fn connect_integration(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(provider): Path<String>,
+    Json(request): Json<ConnectIntegrationRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let provider = integration_provider(&provider)?;
+    let token = request.token.trim();
+    if token.is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Token is required"));
+    }
+
+    let account = context
+        .integrations
+        .connect(&user_id, provider, token)
+        .await
+        .map_err(|e| {
+            warn!(?e, %provider, "⚠️ Failed to connect integration");
+            api_error(
+                StatusCode::BAD_REQUEST,
+                &format!("Failed to connect {provider}: {e:#}"),
+            )
+        })?;
+    info!(%provider, user_id = %user_id, account, "✅ Connected integration");
+
+    let sync_context = context.clone();
+    let sync_user_id = user_id.clone();
+    tokio::spawn(async move {
+        match sync_context
+            .integrations
+            .sync(&sync_user_id, provider)
+            .await
+        {
+            Ok(count) => {
+                info!(%provider, user_id = %sync_user_id, ?count, "✅ Synced known words");
+                sync_context.lookup_cache.clear();
+            }
+            Err(e) => {
+                warn!(%provider, user_id = %sync_user_id, "⚠️ Failed to sync known words: {e:#}")
+            }
+        }
+    });
+
+    let integration = context
+        .integrations
+        .status(&user_id, provider)
+        .await
+        .map_err(integrations_error)?;
+    Ok(Json(serde_json::json!({
+        "account": account,
+        "integration": integration,
+    })))
+}
+
+/// Sync the words the user knows from a connected service now
+#[instrument(skip(context, headers))]
+pub async fn sync_integration(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(provider): Path<String>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let provider = integration_provider(&provider)?;
+    if !provider.has_api() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            &format!("{provider} is imported from an export file"),
+        ));
+    }
+
+    let synced = context
+        .integrations
+        .sync(&user_id, provider)
+        .await
+        .map_err(|e| {
+            warn!(?e, %provider, "⚠️ Failed to sync known words");
+            api_error(
+                StatusCode::BAD_GATEWAY,
+                &format!("Failed to sync {provider}: {e:#}"),
+            )
+        })?;
+    if synced.is_none() {
+        return Err(integration_not_connected());
+    }
+    context.lookup_cache.clear();
+
+    let integration = context
+        .integrations
+        .status(&user_id, provider)
+        .await
+        .map_err(integrations_error)?;
+    Ok(Json(serde_json::json!({ "integration": integration })))
+}
+
+#[derive(TryFromMultipart)]
+pub struct ImportIntegrationRequest {
+    #[form_data(limit = "100MiB")]
+    file: NamedTempFile,
+}
+
+/// Replace the words the user imported from a service without an API with
+/// the words of an export file of it
+#[instrument(skip(context, headers, upload))]
+pub async fn import_integration(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(provider): Path<String>,
+    TypedMultipart(upload): TypedMultipart<ImportIntegrationRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let provider = integration_provider(&provider)?;
+    if provider.has_api() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            &format!("{provider} is synced with an API token"),
+        ));
+    }
+    let data = tokio::fs::read(upload.file.path()).await.map_err(|e| {
+        error!(?e, "Failed to read uploaded export");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read uploaded file",
+        )
+    })?;
+
+    let count = context
+        .integrations
+        .import(&user_id, provider, &data)
+        .await
+        .map_err(|e| {
+            warn!(?e, %provider, "⚠️ Failed to import known words");
+            api_error(
+                StatusCode::BAD_REQUEST,
+                &format!("Failed to import {provider} export: {e:#}"),
+            )
+        })?;
+    info!(%provider, user_id = %user_id, count, "✅ Imported known words");
+    context.lookup_cache.clear();
+
+    let integration = context
+        .integrations
+        .status(&user_id, provider)
+        .await
+        .map_err(integrations_error)?;
+    Ok(Json(serde_json::json!({ "integration": integration })))
+}
+
+/// Forget the user's token for a service and the words synced or imported from it
+#[instrument(skip(context, headers))]
+pub async fn disconnect_integration(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(provider): Path<String>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let provider = integration_provider(&provider)?;
+    let removed = context
+        .integrations
+        .disconnect(&user_id, provider)
+        .await
+        .map_err(integrations_error)?;
+    if !removed {
+        return Err(integration_not_connected());
+    }
+    context.lookup_cache.clear();
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+fn uploads_dir(context: &LookupTermContext) -> Result<&StdPath, ApiError> {
+    context.uploads_dir.as_deref().ok_or_else(|| {
+        error!("❌ UPLOADS_DIR is not configured");
+        api_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Library processing is not configured",
+        )
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LibraryFrequencyQuery {
+    format: Option<String>,
+}
+
+/// Start a background job that builds a frequency list from all of the
+/// current user's books
+#[instrument(skip(context, headers))]
+pub async fn start_library_frequency_job(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    if context.tokenizer.is_none() {
+        return Err(api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Tokenizer not loaded",
+        ));
+    }
+    let library_dir = library::user_library_dir(uploads_dir(&context)?, &user_id)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    let books = library::list_books(&library_dir).map_err(|e| {
+        error!(?e, "❌ Failed to list library books");
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read library")
+    })?;
+    if books.is_empty() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Your library has no books",
+        ));
+    }
+
+    let job_id = context
+        .library_jobs
+        .start(&user_id, LibraryJobKind::FrequencyList, books.len())
+        .await
+        .ok_or_else(|| {
+            api_error(
+                StatusCode::CONFLICT,
+                "A frequency list is already being generated",
+            )
+        })?;
+    info!(job_id = %job_id, user_id = %user_id, books = books.len(), "📚 Starting library frequency list job");
+
+    let context_clone = context.clone();
+    tokio::spawn(async move {
+        let context = context_clone;
+        let handle = tokio::runtime::Handle::current();
+        let blocking_context = context.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let Some(tokenizer) = blocking_context.tokenizer.as_ref() else {
+                anyhow::bail!("Tokenizer not loaded");
+            };
+            library::build_frequency_list(tokenizer, &books, |done| {
+                handle.block_on(blocking_context.library_jobs.set_progress(&job_id, done));
+            })
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|r| r);
+
+        let result = match result {
+            Ok(entries) => {
+                info!(job_id = %job_id, terms = entries.len(), "✅ Library frequency list generated");
+                context
+                    .library_frequency
+                    .save_list(&user_id, &entries)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = &result {
+            error!(job_id = %job_id, ?e, "❌ Library frequency list job failed");
+        }
+        context.library_jobs.finish(&job_id, result).await;
+    });
+
+    Ok(Json(serde_json::json!({ "jobId": job_id })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MergeBooksRequest {
+    upload_ids: Vec<String>,
+    /// Title of the merged book, the first book's title if not given
+    title: Option<String>,
+}
+
+/// Merge several books of the current user's library (e.g. chunks of a
+/// webnovel imported by chapter range) into a new book in the library. The
+/// books are merged in the order given and are left in place.
+#[instrument(skip(context, headers, request))]
+pub async fn merge_books(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Json(request): Json<MergeBooksRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    if request.upload_ids.len() < 2 {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Select at least two books to merge",
+        ));
+    }
+    let library_dir = library::user_library_dir(uploads_dir(&context)?, &user_id)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    let books = library::list_books(&library_dir).map_err(|e| {
+        error!(?e, "❌ Failed to list library books");
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read library")
+    })?;
+
+    let mut book_dirs = Vec::new();
+    for (i, upload_id) in request.upload_ids.iter().enumerate() {
+        if request.upload_ids[..i].contains(upload_id) {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                "A book can only be merged once",
+            ));
+        }
+        let book = books
+            .iter()
+            .find(|book| &book.upload_id == upload_id)
+            .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Book not found"))?;
+        book_dirs.push((upload_id.clone(), book.path.clone()));
+    }
+
+    let upload_id = Uuid::new_v4().to_string();
+    let book_dir = library_dir.join(&upload_id);
+    info!(%user_id, %upload_id, books = book_dirs.len(), "📚 Merging books");
+    let result = tokio::task::spawn_blocking(move || {
+        let mut sources = Vec::new();
+        for (source_id, dir) in &book_dirs {
+            let source = epub::EpubSource::open_dir(dir).map_err(|e| {
+                warn!(?e, %source_id, "⚠️ Failed to read book for merging");
+                api_error(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    &format!("Book {source_id} could not be read: {e:#}"),
+                )
+            })?;
+            sources.push(source);
+        }
+        let (book, stats) = epub::merge_books(&sources, request.title.as_deref()).map_err(|e| {
+            error!(?e, "❌ Failed to merge books");
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to merge books")
+        })?;
+        if let Err(e) = book.write_dir(&book_dir) {
+            error!(?e, ?book_dir, "❌ Failed to write merged book");
+            let _ = std::fs::remove_dir_all(&book_dir);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to write merged book",
+            ));
+        }
+        Ok(serde_json::json!({
+            "title": book.title(),
+            "author": book.creators().join(", "),
+            "spine": book.spine_hrefs(),
+            "toc": book.toc(),
+            "stats": stats,
+        }))
+    })
+    .await
+    .map_err(|e| {
+        error!(?e, "❌ Book merge task panicked");
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to merge books")
+    })??;
+    info!(%upload_id, "✅ Merged books");
+
+    Ok(Json(
+        serde_json::json!({ "upload_id": upload_id, "book": result }),
+    ))
+}
+
+#[derive(TryFromMultipart)]
+pub struct ImportPdfRequest {
+    #[form_data(limit = "unlimited")]
+    file: FieldData<NamedTempFile>,
+    /// Title of the book when the PDF has none, the file name if not given
+    title: Option<String>,
+    pages_per_chapter: Option<usize>,
+}
+
+/// Import a PDF with a text layer (e.g. an OCR'd scan) as a new book in the
+/// current user's library, with a chapter for every `pages_per_chapter` pages
+#[instrument(skip(context, headers, request))]
+pub async fn import_pdf_book(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    TypedMultipart(request): TypedMultipart<ImportPdfRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let library_dir = library::user_library_dir(uploads_dir(&context)?, &user_id)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    let pages_per_chapter = request
+        .pages_per_chapter
+        .unwrap_or(pdf_import::DEFAULT_PAGES_PER_CHAPTER);
+    if !(1..=pdf_import::MAX_PAGES_PER_CHAPTER).contains(&pages_per_chapter) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Pages per chapter must be between 1 and {}",
+                pdf_import::MAX_PAGES_PER_CHAPTER
+            ),
+        ));
+    }
+    let fallback_title = request
+        .title
+        .filter(|title| !title.trim().is_empty())
+        .or_else(|| {
+            let file_name = request.file.metadata.file_name.as_deref()?;
+            Some(
+                StdPath::new(file_name)
+                    .file_stem()?
+                    .to_string_lossy()
+                    .into_owned(),
+            )
+        })
+        .unwrap_or_else(|| "Untitled".to_string());
+
+    let upload_id = Uuid::new_v4().to_string();
+    let book_dir = library_dir.join(&upload_id);
+    info!(%user_id, %upload_id, pages_per_chapter, "📄 Importing PDF");
+    let result = tokio::task::spawn_blocking(move || {
+        let data = std::fs::read(request.file.contents.path()).map_err(|e| {
+            error!(?e, "❌ Failed to read uploaded PDF");
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read upload")
+        })?;
+        let pdf = pdf_import::read_pdf(&data).map_err(|e| {
+            warn!(?e, "⚠️ Failed to read PDF");
+            api_error(StatusCode::UNPROCESSABLE_ENTITY, &format!("{e:#}"))
+        })?;
+        let book = pdf_import::build_book(&pdf, &fallback_title, pages_per_chapter)
+            .map_err(|e| api_error(StatusCode::UNPROCESSABLE_ENTITY, &format!("{e:#}")))?;
+        if let Err(e) = book.write_dir(&book_dir) {
+            error!(?e, ?book_dir, "❌ Failed to write imported PDF");
+            let _ = std::fs::remove_dir_all(&book_dir);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to write imported book",
+            ));
+        }
+        Ok(serde_json::json!({
+            "title": book.title(),
+            "author": book.creators().join(", "),
+            "spine": book.spine_hrefs(),
+            "toc": book.toc(),
+            "pages": pdf.pages.len(),
+            "pagesWithoutText": pdf.pages.iter().filter(|page| page.trim().is_empty()).count(),
+        }))
+    })
+    .await
+    .map_err(|e| {
+        error!(?e, "❌ PDF import task panicked");
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to import PDF")
+    })??;
+    info!(%upload_id, "✅ Imported PDF");
+
+    Ok(Json(
+        serde_json::json!({ "upload_id": upload_id, "book": result }),
+    ))
+}
+
+#[instrument(skip(context, headers))]
+pub async fn get_library_jobs(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let jobs = context.library_jobs.get_user_jobs(&user_id).await;
+    Ok(Json(serde_json::json!({ "jobs": jobs })))
+}
+
+#[instrument(skip(context, headers))]
+pub async fn get_library_job(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let job = context
+        .library_jobs
+        .get(&job_id)
+        .await
+        .filter(|job| job.user_id == user_id)
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Job not found"))?;
+    Ok(Json(serde_json::json!({ "job": job })))
+}
+
+/// Download the generated library frequency list as JSON (default) or CSV
+#[instrument(skip(context, headers))]
+pub async fn download_library_frequency(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Query(params): Query<LibraryFrequencyQuery>,
+) -> Result<Response, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let entries = context
+        .library_frequency
+        .load_list(&user_id)
+        .await
+        .map_err(|e| {
+            error!(?e, "❌ Failed to load library frequency list");
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to load frequency list",
+            )
+        })?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "No frequency list generated yet"))?;
+
+    let (body, content_type, extension) = match params.format.as_deref() {
+        None | Some("json") => (
+            serde_json::to_vec(&entries)
+                .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?,
+            "application/json",
+            "json",
+        ),
+        Some("csv") => (
+            library::frequency_list_to_csv(&entries)
+                .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?
+                .into_bytes(),
+            "text/csv; charset=utf-8",
+            "csv",
+        ),
+        Some(other) => {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                &format!("Unsupported format: {other}"),
+            ))
+        }
+    };
+
+    Response::builder()
+        .header("Content-Type", content_type)
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename=\"library-frequency.{extension}\""),
+        )
+        .body(Body::from(body))
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))
+}
+
+/// Show counts from the generated list in lookups
+#[instrument(skip(context, headers))]
+pub async fn import_library_frequency(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let imported = context
+        .library_frequency
+        .import(&user_id)
+        .await
+        .map_err(|e| {
+            error!(?e, "❌ Failed to import library frequency list");
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to import frequency list",
+            )
+        })?;
+    if !imported {
+        return Err(api_error(
+            StatusCode::NOT_FOUND,
+            "No frequency list generated yet",
+        ));
+    }
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+#[instrument(skip(context, headers))]
+pub async fn remove_library_frequency_import(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    context
+        .library_frequency
+        .remove_import(&user_id)
+        .await
+        .map_err(|e| {
+            error!(?e, "❌ Failed to remove library frequency import");
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to remove frequency list import",
+            )
+        })?;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+// Preferences of `user_id`, falling back to the defaults for users without
+// stored preferences (or without a database)
+async fn user_preferences_or_default(
+    context: &LookupTermContext,
+    user_id: &str,
+) -> Arc<crate::user_preferences::UserPreferences> {
+    if let Ok(uuid) = Uuid::parse_str(user_id) {
+        match context.user_preferences_db.read().await.get(uuid).await {
+            Ok(preferences) => return Arc::new(preferences),
+            Err(e) => warn!(?e, "⚠️ Failed to load user preferences, using defaults"),
+        }
+    }
+    context.yomi_dicts.read().await.default_preferences()
+}
+
+// Preferences are stored per Supabase account, so they need a UUID user id
+fn require_account_id(headers: &HeaderMap) -> Result<Uuid, ApiError> {
+    let user_id = require_user_id(headers)?;
+    Uuid::parse_str(&user_id).map_err(|_| {
+        api_error(
+            StatusCode::BAD_REQUEST,
+            "Preferences are only stored for Supabase accounts",
+        )
+    })
+}
+
+fn user_preferences_db_error(e: anyhow::Error) -> ApiError {
+    error!(?e, "❌ User preferences database error");
+    api_error(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "User preferences are unavailable",
+    )
+}
+
+/// The reader display settings of the user
+#[instrument(skip(context, headers))]
+pub async fn get_reader_preferences(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_account_id(&headers)?;
+    let preferences = context
+        .user_preferences_db
+        .read()
+        .await
+        .get(user_id)
+        .await
+        .map_err(user_preferences_db_error)?;
+    Ok(Json(serde_json::json!({ "reader": preferences.reader })))
+}
+
+/// Replace the reader display settings of the user. Fields left out of the
+/// request get their defaults.
+#[instrument(skip(context, headers, settings))]
+pub async fn put_reader_preferences(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Json(settings): Json<ReaderSettings>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_account_id(&headers)?;
+    settings
+        .validate()
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+
+    let preferences_db = context.user_preferences_db.read().await;
+    let mut preferences = preferences_db
+        .get(user_id)
+        .await
+        .map_err(user_preferences_db_error)?;
+    preferences.reader = settings;
+    preferences_db
+        .save(&preferences)
+        .await
+        .map_err(user_preferences_db_error)?;
+    info!(user_id = %user_id, "✅ Saved reader preferences");
+
+    Ok(Json(serde_json::json!({ "reader": preferences.reader })))
+}
+
+/// What the lookup popup of the user shows
+#[instrument(skip(context, headers))]
+pub async fn get_popup_preferences(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_account_id(&headers)?;
+    let preferences = context
+        .user_preferences_db
+        .read()
+        .await
+        .get(user_id)
+        .await
+        .map_err(user_preferences_db_error)?;
+    Ok(Json(serde_json::json!({ "popup": preferences.popup })))
+}
+
+/// Replace the popup settings of the user. Fields left out of the request get
+/// their defaults.
+#[instrument(skip(context, headers, settings))]
+pub async fn put_popup_preferences(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Json(settings): Json<PopupSettings>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_account_id(&headers)?;
+    settings
+        .validate()
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+
+    let preferences_db = context.user_preferences_db.read().await;
+    let mut preferences = preferences_db
+        .get(user_id)
+        .await
+        .map_err(user_preferences_db_error)?;
+    preferences.popup = settings;
+    preferences_db
+        .save(&preferences)
+        .await
+        .map_err(user_preferences_db_error)?;
+    // Cached lookups were shaped by the old settings
+    context.lookup_cache.clear();
+    info!(user_id = %user_id, "✅ Saved popup preferences");
+
+    Ok(Json(serde_json::json!({ "popup": preferences.popup })))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioPreferences {
+    /// Preferred sources, most preferred first
+    order: Vec<String>,
+    /// Sources that are never returned
+    disabled: Vec<String>,
+}
+
+impl AudioPreferences {
+    fn of(preferences: &UserPreferences) -> Self {
+        let mut disabled: Vec<String> =
+            preferences.audio_disabled_sources.iter().cloned().collect();
+        disabled.sort();
+        Self {
+            order: preferences.audio_source_order.clone(),
+            disabled,
+        }
+    }
+}
+
+/// Which audio sources the user prefers and which they have turned off
+#[instrument(skip(context, headers))]
+pub async fn get_audio_preferences(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_account_id(&headers)?;
+    let preferences = context
+        .user_preferences_db
+        .read()
+        .await
+        .get(user_id)
+        .await
+        .map_err(user_preferences_db_error)?;
+    Ok(Json(
+        serde_json::json!({ "audio": AudioPreferences::of(&preferences) }),
+    ))
+}
+
+/// Replace the preferred order and the turned off audio sources of the user
+#[instrument(skip(context, headers, request))]
+pub async fn put_audio_preferences(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Json(request): Json<AudioPreferences>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_account_id(&headers)?;
+    validate_audio_sources(request.order.iter().chain(&request.disabled))
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+
+    let preferences_db = context.user_preferences_db.read().await;
+    let mut preferences = preferences_db
+        .get(user_id)
+        .await
+        .map_err(user_preferences_db_error)?;
+    let mut order = request.order;
+    let mut seen = HashSet::new();
+    order.retain(|source| seen.insert(source.clone()));
+    preferences.audio_source_order = order;
+    preferences.audio_disabled_sources = request.disabled.into_iter().collect();
+    preferences_db
+        .save(&preferences)
+        .await
+        .map_err(user_preferences_db_error)?;
+    // Cached lookups carry audio picked with the old order and sources
+    context.lookup_cache.clear();
+    info!(user_id = %user_id, "✅ Saved audio preferences");
+
+    Ok(Json(
+        serde_json::json!({ "audio": AudioPreferences::of(&preferences) }),
+    ))
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GlossaryRequest {
+    title: Option<String>,
+    min_occurrences: Option<u64>,
+    max_terms: Option<usize>,
+    /// Terms to leave out because the user already knows them
+    known_terms: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GlossaryExportQuery {
+    format: Option<String>,
+}
+
+/// Start a background job generating the glossary of one of the current
+/// user's books
+#[instrument(skip(context, headers, request))]
+pub async fn start_glossary_job(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(upload_id): Path<String>,
+    request: Option<Json<GlossaryRequest>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    if context.tokenizer.is_none() {
+        return Err(api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Tokenizer not loaded",
+        ));
+    }
+
+    let library_dir = library::user_library_dir(uploads_dir(&context)?, &user_id)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    let book = library::list_books(&library_dir)
+        .map_err(|e| {
+            error!(?e, "❌ Failed to list library books");
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read library")
+        })?
+        .into_iter()
+        .find(|b| b.upload_id == upload_id)
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Book not found"))?;
+
+    let options = GlossaryOptions {
+        min_occurrences: request
+            .min_occurrences
+            .unwrap_or(glossary::DEFAULT_MIN_OCCURRENCES)
+            .max(1),
+        max_terms: request
+            .max_terms
+            .unwrap_or(glossary::DEFAULT_MAX_TERMS)
+            .clamp(1, glossary::MAX_GLOSSARY_TERMS),
+        known_terms: request.known_terms.into_iter().collect(),
+    };
+    let title = request.title.unwrap_or_else(|| upload_id.clone());
+    let user_preferences = user_preferences_or_default(&context, &user_id).await;
+
+    let job_id = context
+        .library_jobs
+        .start(&user_id, LibraryJobKind::Glossary, 1)
+        .await
+        .ok_or_else(|| {
+            api_error(
+                StatusCode::CONFLICT,
+                "A glossary is already being generated",
+            )
+        })?;
+    info!(job_id = %job_id, user_id = %user_id, upload_id = %upload_id, "📖 Starting glossary job");
+
+    let context_clone = context.clone();
+    tokio::spawn(async move {
+        let context = context_clone;
+        let yomi_dicts = context.yomi_dicts.read().await.clone();
+        let blocking_context = context.clone();
+        let result = tokio::task::spawn_blocking(move || -> Result<Vec<GlossaryEntry>> {
+            let Some(tokenizer) = blocking_context.tokenizer.as_ref() else {
+                anyhow::bail!("Tokenizer not loaded");
+            };
+            let mut counter = library::FrequencyCounter::default();
+            library::count_book_terms(&mut tokenizer.new_worker(), &book, &mut counter)?;
+            let book_terms = counter.into_entries();
+
+            let mut entries = Vec::new();
+            for candidate in glossary::select_terms(&book_terms, &options) {
+                if entries.len() >= options.max_terms {
+                    break;
+                }
+                // Terms no dictionary knows about (names, tokenizer noise) are dropped
+                let Some((dictionary, entry)) =
+                    yomi_dicts.lookup_first_entry(&candidate.term, &user_preferences)?
+                else {
+                    continue;
+                };
+                let definition = entry
+                    .definitions
+                    .iter()
+                    .map(jreader_core::definitions::definition_text)
+                    .filter(|d| !d.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                entries.push(GlossaryEntry {
+                    term: candidate.term.clone(),
+                    reading: Some(entry.reading.to_hiragana()).filter(|r| !r.is_empty()),
+                    count: candidate.count,
+                    dictionary,
+                    definition,
+                });
+            }
+            Ok(entries)
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|r| r);
+
+        let result = match result {
+            Ok(entries) => {
+                info!(job_id = %job_id, terms = entries.len(), "✅ Glossary generated");
+                context.library_jobs.set_progress(&job_id, 1).await;
+                let glossary = Glossary {
+                    upload_id,
+                    title,
+                    generated_at: chrono::Utc::now(),
+                    entries,
+                };
+                context.glossaries.save(&user_id, &glossary).await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = &result {
+            error!(job_id = %job_id, ?e, "❌ Glossary job failed");
+        }
+        context.library_jobs.finish(&job_id, result).await;
+    });
+
+    Ok(Json(serde_json::json!({ "jobId": job_id })))
+}
+
+/// Download a generated glossary as JSON (default), CSV, HTML or an Anki import file
+#[instrument(skip(context, headers))]
+pub async fn get_glossary(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(upload_id): Path<String>,
+    Query(params): Query<GlossaryExportQuery>,
+) -> Result<Response, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let glossary = context
+        .glossaries
+        .load(&user_id, &upload_id)
+        .await
+        .map_err(|e| {
+            error!(?e, "❌ Failed to load glossary");
+            api_error(StatusCode::BAD_REQUEST, "Failed to load glossary")
+        })?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "No glossary generated yet"))?;
+
+    let to_500 = |e: anyhow::Error| api_error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string());
+    let (body, content_type, extension) = match params.format.as_deref() {
+        None | Some("json") => (
+            serde_json::to_string(&glossary).map_err(|e| to_500(e.into()))?,
+            "application/json",
+            "json",
+        ),
+        Some("csv") => (
+            glossary.to_csv().map_err(to_500)?,
+            "text/csv; charset=utf-8",
+            "csv",
+        ),
+        Some("html") => (glossary.to_html(), "text/html; charset=utf-8", "html"),
+        Some("anki") => (glossary.to_anki(), "text/plain; charset=utf-8", "txt"),
+        Some(other) => {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                &format!("Unsupported format: {other}"),
+            ))
+        }
+    };
+
+    Response::builder()
+        .header("Content-Type", content_type)
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename=\"glossary-{upload_id}.{extension}\""),
+        )
+        .body(Body::from(body))
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct ExportBookQuery {
+    /// Add furigana to words rarer than `frequency_threshold`
+    furigana: bool,
+    frequency_threshold: Option<u32>,
+    /// Set the words saved to any of the user's vocab lists in bold
+    highlight: bool,
+}
+
+/// Download one of the current user's books as an EPUB for reading on an
+/// e-reader, with furigana on the words outside the frequency threshold and
+/// the words the user has studied in bold if asked for
+#[instrument(skip(context, headers))]
+pub async fn export_book(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(upload_id): Path<String>,
+    Query(params): Query<ExportBookQuery>,
+) -> Result<Response, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    if (params.furigana || params.highlight) && context.tokenizer.is_none() {
+        return Err(api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Tokenizer not loaded",
+        ));
+    }
+
+    let library_dir = library::user_library_dir(uploads_dir(&context)?, &user_id)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    let book = library::list_books(&library_dir)
+        .map_err(|e| {
+            error!(?e, "❌ Failed to list library books");
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read library")
+        })?
+        .into_iter()
+        .find(|b| b.upload_id == upload_id)
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Book not found"))?;
+
+    let studied = if params.highlight {
+        context
+            .vocab_lists_db
+            .get_user_terms(&user_id)
+            .await
+            .map_err(vocab_db_error)?
+    } else {
+        HashSet::new()
+    };
+    let user_preferences = user_preferences_or_default(&context, &user_id).await;
+    let yomi_dicts = context.yomi_dicts.read().await.clone();
+    let threshold = params
+        .frequency_threshold
+        .unwrap_or(book_export::DEFAULT_FREQUENCY_THRESHOLD) as f64;
+    let blocking_context = context.clone();
+    let (title, epub) = tokio::task::spawn_blocking(move || -> Result<(String, Vec<u8>)> {
+        let source = epub::EpubSource::open_dir(&book.path)?;
+        let builder = if params.furigana || params.highlight {
+            let tokenizer = blocking_context
+                .tokenizer
+                .as_ref()
+                .context("Tokenizer not loaded")?;
+            // Dictionary form -> common enough to go without furigana
+            let mut common: HashMap<String, bool> = HashMap::new();
+            book_export::mark_up_epub(&source, &mut tokenizer.new_worker(), |feature| {
+                let highlight = params.highlight && book_export::is_studied(feature, &studied);
+                let furigana = params.furigana
+                    && feature
+                        .dictionary_form
+                        .as_ref()
+                        .or(feature.surface_form.as_ref())
+                        .is_some_and(|term| {
+                            !*common.entry(term.clone()).or_insert_with(|| {
+                                yomi_dicts
+                                    .frequency_ranks([term.as_str()], &user_preferences)
+                                    .map(|ranks| {
+                                        ranks.get(term).is_some_and(|rank| *rank <= threshold)
+                                    })
+                                    .unwrap_or_else(|e| {
+                                        warn!(?e, %term, "⚠️ Failed to look up frequency");
+                                        false
+                                    })
+                            })
+                        });
+                book_export::WordMarkup {
+                    furigana,
+                    highlight,
+                }
+            })?
+        } else {
+            epub::repackage(&source, |_, data| Ok(data))?
+        };
+        let mut epub = std::io::Cursor::new(Vec::new());
+        builder.write_epub(&mut epub)?;
+        Ok((source.title, epub.into_inner()))
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|r| r)
+    .map_err(|e| {
+        error!(?e, %upload_id, "❌ Failed to export book");
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to export book")
+    })?;
+    info!(
+        %upload_id,
+        furigana = params.furigana,
+        highlight = params.highlight,
+        bytes = epub.len(),
+        "📦 Exported book"
+    );
+
+    let title = if title.trim().is_empty() {
+        upload_id.clone()
+    } else {
+        title
+    };
+    Response::builder()
+        .header("Content-Type", "application/epub+zip")
+        .header(
+            "Content-Disposition",
+            http_util::attachment_disposition(
+                &format!("{title}.epub"),
+                Some(&format!("{upload_id}.epub")),
+            ),
+        )
+        .body(Body::from(epub))
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))
+}
+
+fn library_shelf_error(e: anyhow::Error) -> ApiError {
+    error!(?e, "❌ Library shelf error");
+    api_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Failed to read library shelf",
+    )
+}
+
+fn collection_not_found() -> ApiError {
+    api_error(StatusCode::NOT_FOUND, "Collection not found")
+}
+
+/// Tell a field that is null apart from one that is missing
+fn deserialize_present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// The current user's books with their collection, tags, reading progress and
+/// difficulty, filtered and sorted as asked, along with all their collections
+/// and tags
+#[instrument(skip(context, headers))]
+pub async fn get_library_books(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Query(query): Query<ShelfQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let library_dir = library::user_library_dir(uploads_dir(&context)?, &user_id)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    let shelf = context
+        .library_shelves
+        .load(&user_id)
+        .await
+        .map_err(library_shelf_error)?;
+    if query
+        .collection
+        .is_some_and(|id| shelf.collection(&id).is_none())
+    {
+        return Err(collection_not_found());
+    }
+    let collections = shelf.collections.clone();
+    let tags = shelf.tags();
+
+    // Titles come from each book's OPF, which adds up for large libraries
+    let entries = tokio::task::spawn_blocking(move || -> Result<Vec<ShelfEntry>> {
+        Ok(library::list_books(&library_dir)?
+            .into_iter()
+            .map(|b| ShelfEntry {
+                title: library::book_title(&b.path),
+                book: shelf.book(&b.upload_id),
+                upload_id: b.upload_id,
+            })
+            .collect())
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|r| r)
+    .map_err(|e| {
+        error!(?e, "❌ Failed to list library books");
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read library")
+    })?;
+
+    Ok(Json(serde_json::json!({
+        "books": library_shelf::arrange(entries, &query),
+        "collections": collections,
+        "tags": tags,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateLibraryBookRequest {
+    /// Collection to move the book to, null to take it out of its collection
+    #[serde(default, deserialize_with = "deserialize_present")]
+    collection_id: Option<Option<Uuid>>,
+    tags: Option<Vec<String>>,
+    /// Fraction of the book read, which also marks the book as read just now
+    progress: Option<f64>,
+}
+
+/// Change the collection, tags or reading progress of one of the current
+/// user's books. Fields that are left out are left alone.
+#[instrument(skip(context, headers, request))]
+pub async fn update_library_book(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(upload_id): Path<String>,
+    Json(request): Json<UpdateLibraryBookRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let tags = request
+        .tags
+        .as_deref()
+        .map(library_shelf::normalize_tags)
+        .transpose()
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+    if request.progress.is_some_and(|p| !(0.0..=1.0).contains(&p)) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Progress must be between 0 and 1",
+        ));
+    }
+
+    let library_dir = library::user_library_dir(uploads_dir(&context)?, &user_id)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    let book_exists = library::list_books(&library_dir)
+        .map_err(|e| {
+            error!(?e, "❌ Failed to list library books");
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read library")
+        })?
+        .iter()
+        .any(|b| b.upload_id == upload_id);
+    if !book_exists {
+        return Err(api_error(StatusCode::NOT_FOUND, "Book not found"));
+    }
+
+    let book = context
+        .library_shelves
+        .update(&user_id, |shelf| {
+            if let Some(Some(id)) = request.collection_id {
+                shelf.collection(&id)?;
+            }
+            let book = shelf.book_mut(&upload_id);
+            if let Some(collection_id) = request.collection_id {
+                book.collection_id = collection_id;
+            }
+            if let Some(tags) = tags {
+                book.tags = tags;
+            }
+            if let Some(progress) = request.progress {
+                book.progress = Some(progress);
+                book.last_read_at = Some(chrono::Utc::now());
+            }
+            Some(book.clone())
+        })
+        .await
+        .map_err(library_shelf_error)?
+        .ok_or_else(collection_not_found)?;
+
+    Ok(Json(serde_json::json!({ "book": book })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CollectionNameRequest {
+    name: String,
+}
+
+#[instrument(skip(context, headers))]
+pub async fn get_library_collections(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let shelf = context
+        .library_shelves
+        .load(&user_id)
+        .await
+        .map_err(library_shelf_error)?;
+
+    Ok(Json(
+        serde_json::json!({ "collections": shelf.collections }),
+    ))
+}
+
+#[instrument(skip(context, headers, request))]
+pub async fn create_library_collection(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Json(request): Json<CollectionNameRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let name = library_shelf::validate_collection_name(&request.name)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+
+    let collection = context
+        .library_shelves
+        .update(&user_id, |shelf| shelf.create_collection(name))
+        .await
+        .map_err(library_shelf_error)?;
+    info!(collection_id = %collection.id, user_id = %user_id, "✅ Created library collection");
+
+    Ok(Json(serde_json::json!({ "collection": collection })))
+}
+
+#[instrument(skip(context, headers, request))]
+pub async fn rename_library_collection(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(collection_id): Path<Uuid>,
+    Json(request): Json<CollectionNameRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let name = library_shelf::validate_collection_name(&request.name)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+
+    let collection = context
+        .library_shelves
+        .update(&user_id, |shelf| {
+            shelf.rename_collection(&collection_id, name)
+        })
+        .await
+        .map_err(library_shelf_error)?
+        .ok_or_else(collection_not_found)?;
+
+    Ok(Json(serde_json::json!({ "collection": collection })))
+}
+
+/// Delete a collection. Its books stay in the library, outside of any collection.
+#[instrument(skip(context, headers))]
+pub async fn delete_library_collection(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Path(collection_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let deleted = context
+        .library_shelves
+        .update(&user_id, |shelf| shelf.delete_collection(&collection_id))
+        .await
+        .map_err(library_shelf_error)?;
+    if !deleted {
+        return Err(collection_not_found());
+    }
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+fn reading_stats_error(e: anyhow::Error) -> ApiError {
+    error!(?e, "❌ Reading stats error");
+    api_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Failed to read reading stats",
+    )
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordReadingRequest {
+    upload_id: String,
+    /// Characters read in the session
+    characters: u64,
+    /// Time spent reading in the session
+    seconds: u64,
+    /// The reader's local date, today in UTC if left out
+    date: Option<chrono::NaiveDate>,
+}
+
+/// Add a reading session of one of the current user's books to their reading
+/// log, returning the totals of that book for the day
+#[instrument(skip(context, headers, request))]
+pub async fn record_reading(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Json(request): Json<RecordReadingRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    if request.seconds > reading_stats::MAX_SESSION_SECONDS
+        || request.characters > reading_stats::MAX_SESSION_CHARACTERS
+    {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Reading session is too long",
+        ));
+    }
+
+    let library_dir = library::user_library_dir(uploads_dir(&context)?, &user_id)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    let upload_id = request.upload_id.clone();
+    // None if the book doesn't exist, Some(None) if it has no title
+    let title = tokio::task::spawn_blocking(move || -> Result<Option<Option<String>>> {
+        Ok(library::list_books(&library_dir)?
+            .into_iter()
+            .find(|b| b.upload_id == upload_id)
+            .map(|b| library::book_title(&b.path)))
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|r| r)
+    .map_err(|e| {
+        error!(?e, "❌ Failed to list library books");
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read library")
+    })?
+    .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Book not found"))?;
+
+    let date = request
+        .date
+        .unwrap_or_else(|| chrono::Utc::now().date_naive());
+    let day = context
+        .reading_stats
+        .record(
+            &user_id,
+            date,
+            &request.upload_id,
+            title,
+            request.characters,
+            request.seconds,
+        )
+        .await
+        .map_err(reading_stats_error)?;
+
+    Ok(Json(serde_json::json!({ "day": day })))
+}
+
+/// The current user's reading log as immersion trackers import it: a CSV of
+/// date, characters, minutes and title, per book or per day
+#[instrument(skip(context, headers))]
+pub async fn export_reading_stats(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Query(query): Query<reading_stats::ExportQuery>,
+) -> Result<Response, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let log = context
+        .reading_stats
+        .load(&user_id)
+        .await
+        .map_err(reading_stats_error)?;
+    let rows = reading_stats::export_rows(&log, &query);
+
+    let to_500 = |e: anyhow::Error| api_error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string());
+    let (body, content_type, extension) = match query.format.as_deref() {
+        None | Some("csv") => (
+            reading_stats::rows_to_csv(&rows).map_err(to_500)?,
+            "text/csv; charset=utf-8",
+            "csv",
+        ),
+        Some("json") => (
+            serde_json::to_string(&rows).map_err(|e| to_500(e.into()))?,
+            "application/json",
+            "json",
+        ),
+        Some(other) => {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                &format!("Unsupported format: {other}"),
+            ))
+        }
+    };
+
+    Response::builder()
+        .header("Content-Type", content_type)
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename=\"reading-stats.{extension}\""),
+        )
+        .body(Body::from(body))
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LibraryDifficultyQuery {
+    /// The learner's JLPT level, to score books against the level lists
+    /// rather than frequency
+    jlpt: Option<JlptLevel>,
+}
+
+/// Score the difficulty of every book of the current user's library for
+/// sorting, as the share of its words outside the common-word threshold, or
+/// above the JLPT level given as `?jlpt=`
+#[instrument(skip(context, headers))]
+pub async fn start_library_difficulty_job(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Query(query): Query<LibraryDifficultyQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    if query.jlpt.is_some() && context.levels.is_empty() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "No JLPT level lists are installed",
+        ));
+    }
+    if context.tokenizer.is_none() {
+        return Err(api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Tokenizer not loaded",
+        ));
+    }
+    let library_dir = library::user_library_dir(uploads_dir(&context)?, &user_id)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    let books = library::list_books(&library_dir).map_err(|e| {
+        error!(?e, "❌ Failed to list library books");
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read library")
+    })?;
+    if books.is_empty() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Your library has no books",
+        ));
+    }
+    let user_preferences = user_preferences_or_default(&context, &user_id).await;
+
+    let job_id = context
+        .library_jobs
+        .start(&user_id, LibraryJobKind::Difficulty, books.len())
+        .await
+        .ok_or_else(|| {
+            api_error(
+                StatusCode::CONFLICT,
+                "Book difficulty is already being scored",
+            )
+        })?;
+    info!(job_id = %job_id, user_id = %user_id, books = books.len(), "📊 Starting book difficulty job");
+
+    let context_clone = context.clone();
+    tokio::spawn(async move {
+        let context = context_clone;
+        let handle = tokio::runtime::Handle::current();
+        let yomi_dicts = context.yomi_dicts.read().await.clone();
+        let blocking_context = context.clone();
+        let threshold = book_export::DEFAULT_FREQUENCY_THRESHOLD as f64;
+        let result = tokio::task::spawn_blocking(move || -> Result<Vec<(String, Option<f64>)>> {
+            let Some(tokenizer) = blocking_context.tokenizer.as_ref() else {
+                anyhow::bail!("Tokenizer not loaded");
+            };
+            let mut worker = tokenizer.new_worker();
+            let mut scores = Vec::new();
+            for (i, book) in books.iter().enumerate() {
+                let mut counter = library::FrequencyCounter::default();
+                library::count_book_terms(&mut worker, book, &mut counter)?;
+                let book_terms = counter.into_entries();
+                let score = match query.jlpt {
+                    Some(known) => library_shelf::jlpt_difficulty_score(
+                        &book_terms,
+                        &blocking_context.levels,
+                        known,
+                    ),
+                    None => {
+                        let ranks = yomi_dicts.frequency_ranks(
+                            book_terms.iter().map(|e| e.term.as_str()),
+                            &user_preferences,
+                        )?;
+                        library_shelf::difficulty_score(&book_terms, &ranks, threshold)
+                    }
+                };
+                scores.push((book.upload_id.clone(), score));
+                handle.block_on(blocking_context.library_jobs.set_progress(&job_id, i + 1));
+            }
+            Ok(scores)
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|r| r);
+
+        let result = match result {
+            Ok(scores) => {
+                info!(job_id = %job_id, books = scores.len(), "✅ Book difficulty scored");
+                context
+                    .library_shelves
+                    .update(&user_id, |shelf| {
+                        for (upload_id, difficulty) in scores {
+                            shelf.book_mut(&upload_id).difficulty = difficulty;
+                        }
+                    })
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = &result {
+            error!(job_id = %job_id, ?e, "❌ Book difficulty job failed");
+        }
+        context.library_jobs.finish(&job_id, result).await;
+    });
+
+    Ok(Json(serde_json::json!({ "jobId": job_id })))
+}
+
+pub const DEFAULT_EXAMPLES: usize = 10;
+pub const MAX_EXAMPLES: usize = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct ExamplesQuery {
+    term: String,
+    limit: Option<usize>,
+}
+
+/// Example sentences for a term mined from the current user's own books
+#[instrument(skip(context, headers))]
+pub async fn get_examples(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Query(params): Query<ExamplesQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let user_id = require_user_id(&headers)?;
+    let term = params.term.trim().to_string();
+    if term.is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Term must not be empty"));
+    }
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_EXAMPLES)
+        .clamp(1, MAX_EXAMPLES);
+
+    let library_dir = library::user_library_dir(uploads_dir(&context)?, &user_id)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+
+    let search_context = context.clone();
+    let examples = tokio::task::spawn_blocking(move || {
+        let books = library::list_books(&library_dir)?;
+        search_context
+            .library_search
+            .find_examples(&user_id, &books, &term, limit)
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|r| r)
+    .map_err(|e| {
+        error!(?e, "❌ Failed to search library for examples");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to search library",
+        )
+    })?;
+
+    Ok(Json(serde_json::json!({ "examples": examples })))
+}
+
+/// Current values of the runtime-reloadable settings
+#[instrument(skip(context))]
+pub async fn get_config(State(context): State<Arc<LookupTermContext>>) -> Json<serde_json::Value> {
+    // Admin check is handled by the auth middleware
+    Json(serde_json::json!({ "config": *context.config.current() }))
+}
+
+/// Re-read `.env` and apply the runtime-reloadable settings without a restart
+#[instrument(skip(context))]
+pub async fn reload_config(
+    State(context): State<Arc<LookupTermContext>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    // Admin check is handled by the auth middleware
+    let changed = context.config.reload().map_err(|e| {
+        error!(?e, "❌ Failed to reload config");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to reload config: {e}"),
+        )
+    })?;
+
+    Ok(Json(serde_json::json!({
+        "changed": changed,
+        "config": *context.config.current()
+    })))
+}
+
+fn log_levels_response(state: LogLevelsState) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "base": state.base,
+        "overrides": state.overrides,
+        "effective": state.effective_filter(),
+    }))
+}
+
+pub async fn get_log_levels(
+    State(context): State<Arc<LookupTermContext>>,
+) -> Json<serde_json::Value> {
+    // Admin check is handled by the auth middleware
+    log_levels_response(context.log_levels.state())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetLogLevelRequest {
+    /// Module path, e.g. `yomitan_format` or `jreader_service::http_handlers`
+    pub module: String,
+    /// New level; `null` removes the override for the module
+    pub level: Option<String>,
+}
+
+/// Change the log level of a single module without touching the rest of the filter
+#[instrument(skip(context))]
+pub async fn set_log_level(
+    State(context): State<Arc<LookupTermContext>>,
+    Json(request): Json<SetLogLevelRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    // Admin check is handled by the auth middleware
+    let state = context
+        .log_levels
+        .set_module_level(&request.module, request.level.as_deref())
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    Ok(log_levels_response(state))
+}
+
+/// Drop all per-module overrides, going back to the configured filter
+#[instrument(skip(context))]
+pub async fn clear_log_levels(
+    State(context): State<Arc<LookupTermContext>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    // Admin check is handled by the auth middleware
+    let state = context.log_levels.clear_overrides().map_err(|e| {
+        error!(?e, "❌ Failed to reset log levels");
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+    })?;
+    Ok(log_levels_response(state))
+}
+
+/// Lookup recording settings and the record files on disk
+pub async fn get_lookup_diagnostics(
+    State(context): State<Arc<LookupTermContext>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    // Admin check is handled by the auth middleware
+    let files = context.lookup_recorder.files().await.map_err(|e| {
+        error!(?e, "❌ Failed to list lookup records");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to list lookup records",
+        )
+    })?;
+    Ok(Json(serde_json::json!({
+        "settings": context.lookup_recorder.settings(),
+        "files": files,
+    })))
+}
+
+/// Turn lookup recording on or off, for a set of users and/or a sample of all lookups
+#[instrument(skip(context))]
+pub async fn set_lookup_diagnostics(
+    State(context): State<Arc<LookupTermContext>>,
+    Json(settings): Json<DiagnosticsSettings>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    // Admin check is handled by the auth middleware
+    context
+        .lookup_recorder
+        .set_settings(settings)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    info!(settings = ?context.lookup_recorder.settings(), "🩺 Lookup diagnostics updated");
+    Ok(Json(serde_json::json!({
+        "settings": context.lookup_recorder.settings(),
+    })))
+}
+
+const DEFAULT_LOOKUP_RECORDS_LIMIT: usize = 50;
+const MAX_LOOKUP_RECORDS_LIMIT: usize = 500;
+
+#[derive(Debug, Deserialize)]
+pub struct LookupRecordsQuery {
+    user_id: Option<String>,
+    term: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Recorded lookups, most recent first
+#[instrument(skip(context))]
+pub async fn get_lookup_records(
+    State(context): State<Arc<LookupTermContext>>,
+    Query(query): Query<LookupRecordsQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    // Admin check is handled by the auth middleware
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LOOKUP_RECORDS_LIMIT)
+        .clamp(1, MAX_LOOKUP_RECORDS_LIMIT);
+    let records = context
+        .lookup_recorder
+        .recent(query.user_id.as_deref(), query.term.as_deref(), limit)
+        .await
+        .map_err(|e| {
+            error!(?e, "❌ Failed to read lookup records");
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read lookup records",
+            )
+        })?;
+    Ok(Json(serde_json::json!({ "records": records })))
+}
+
+/// Write a generated dictionary to `DICTS_PATH/yomitan` and import it
+async fn import_generated_dictionary(
+    context: &LookupTermContext,
+    filename: &str,
+    generated: GeneratedDictionary,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let dicts_path = std::env::var("DICTS_PATH")
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "DICTS_PATH not set"))?;
+    let yomitan_dir_path = StdPath::new(&dicts_path).join("yomitan");
+    let archive_path = yomitan_dir_path.join(filename);
+    if archive_path.exists() {
+        return Err(api_error(
+            StatusCode::CONFLICT,
+            &format!("A dictionary archive named {filename} already exists"),
+        ));
+    }
+    let write_result = match tokio::fs::create_dir_all(&yomitan_dir_path).await {
+        Ok(()) => tokio::fs::write(&archive_path, &generated.archive).await,
+        Err(e) => Err(e),
+    };
+    write_result.map_err(|e| {
+        error!(?e, "❌ Failed to write generated dictionary");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to write dictionary",
+        )
+    })?;
+
+    let progress_state = ProgressStateTable::new(None).map_err(|e| {
+        error!(?e, "Failed to create progress state");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to create progress state",
+        )
+    })?;
+    dict_db_scan_fs::import_archive(
+        &archive_path,
+        Arc::new(progress_state),
+        context.yomi_dicts.clone(),
+    )
+    .await
+    .map_err(|e| {
+        error!(?e, "❌ Failed to import generated dictionary");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to import dictionary: {e}"),
+        )
+    })?;
+
+    info!(%filename, entries = generated.report.entries, "📥 Imported dictionary from CSV");
+    Ok(Json(serde_json::json!({
+        "filename": filename,
+        "report": generated.report,
+    })))
+}
+
+fn parse_import_spec<T: serde::de::DeserializeOwned>(spec: &str) -> Result<T, ApiError> {
+    serde_json::from_str(spec).map_err(|e| {
+        api_error(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid import spec: {e}"),
+        )
+    })
+}
+
+/// Convert a CSV of (term, reading, rank/count) rows into a frequency dictionary
+#[instrument(skip(context, upload))]
+pub async fn import_frequency_csv(
+    State(context): State<Arc<LookupTermContext>>,
+    TypedMultipart(upload): TypedMultipart<ImportCsvDictRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    // Admin check is handled by the auth middleware
+    let spec: FrequencyImportSpec = parse_import_spec(&upload.spec)?;
+    let data = tokio::fs::read(upload.file.path()).await.map_err(|e| {
+        error!(?e, "Failed to read uploaded CSV");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read uploaded file",
+        )
+    })?;
+    let generated = build_frequency_dictionary(&data, &spec)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    import_generated_dictionary(&context, &spec.metadata.archive_filename(), generated).await
+}
+
+/// Convert a CSV/TSV accent dump of (term, reading, accent positions) rows into a pitch dictionary
+#[instrument(skip(context, upload))]
+pub async fn import_pitch_csv(
+    State(context): State<Arc<LookupTermContext>>,
+    TypedMultipart(upload): TypedMultipart<ImportCsvDictRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    // Admin check is handled by the auth middleware
+    let spec: PitchImportSpec = parse_import_spec(&upload.spec)?;
+    let data = tokio::fs::read(upload.file.path()).await.map_err(|e| {
+        error!(?e, "Failed to read uploaded CSV");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read uploaded file",
+        )
+    })?;
+    let generated = build_pitch_dictionary(&data, &spec)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    import_generated_dictionary(&context, &spec.metadata.archive_filename(), generated).await
+}
+
+/// Content statistics of the term dictionaries, to help users choose between them
+pub async fn get_dict_stats(
+    State(context): State<Arc<LookupTermContext>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let dicts_path = std::env::var("DICTS_PATH")
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "DICTS_PATH not set"))?;
+    let dictionaries = context.yomi_dicts.read().await.term_dictionary_dirs();
+
+    // Dictionaries imported before stats existed get them computed here,
+    // which reads the whole term bank
+    let stats = tokio::task::spawn_blocking(move || {
+        dictionaries
+            .into_iter()
+            .map(|(info, origin)| {
+                let dict_dir = camino::Utf8PathBuf::from(&dicts_path)
+                    .join("db")
+                    .join(&origin);
+                let stats = DictionaryStats::load_or_compute(&dict_dir).unwrap_or_else(|e| {
+                    warn!(?e, title = %info.title, "⚠️ Failed to load dictionary stats");
+                    None
+                });
+                serde_json::json!({
+                    "title": info.title,
+                    "revision": info.revision,
+                    "stats": stats,
+                })
+            })
+            .collect::<Vec<_>>()
+    })
+    .await
+    .map_err(|e| {
+        error!(?e, "❌ Dictionary stats task failed");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to load dictionary stats",
+        )
+    })?;
+
+    Ok(Json(serde_json::json!({ "dictionaries": stats })))
+}
+
+/// Terms a coverage check may ask about, enough for the whole JLPT vocabulary
+pub const MAX_COVERAGE_TERMS: usize = 20_000;
+
+#[derive(Debug, Deserialize)]
+pub struct DictCoverageRequest {
+    terms: Vec<String>,
+}
+
+/// How many terms of a word list (e.g. JLPT N3 vocabulary) each of the user's
+/// enabled term dictionaries has, to help them decide which ones to enable
+#[instrument(skip(context, headers, payload), fields(terms = payload.terms.len()))]
+pub async fn get_dict_coverage(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Json(payload): Json<DictCoverageRequest>,
+) -> Result<Json<TermCoverage>, ApiError> {
+    if payload.terms.len() > MAX_COVERAGE_TERMS {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            &format!("Too many terms, at most {MAX_COVERAGE_TERMS} are allowed"),
+        ));
+    }
+    let terms: Vec<String> = payload
+        .terms
+        .iter()
+        .map(|term| term.trim().nfc().collect::<String>())
+        .filter(|term| !term.is_empty())
+        .collect();
+    let user_id = headers
+        .get("user_id")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default();
+    let user_preferences = user_preferences_or_default(&context, user_id).await;
+
+    // One bulk read of every enabled term bank, off the async workers
+    let dictionaries = context.yomi_dicts.read().await.clone();
+    let coverage =
+        tokio::task::spawn_blocking(move || dictionaries.term_coverage(&terms, &user_preferences))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|coverage| coverage)
+            .map_err(|e| {
+                error!(?e, "❌ Failed to check dictionary coverage");
+                api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to check dictionary coverage",
+                )
+            })?;
+    Ok(Json(coverage))
+}
+
+pub const DEFAULT_SUGGESTIONS: usize = 10;
+pub const MAX_SUGGESTIONS: usize = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct SuggestQuery {
+    q: String,
+    limit: Option<usize>,
+    /// Comma-separated JLPT levels (e.g. "N5,N4") suggestions must be at
+    jlpt: Option<String>,
+}
+
+/// Author, URL and attribution of every loaded dictionary, so deployments can
+/// credit dictionaries whose licenses ask for it
+pub async fn get_dict_attributions(
+    State(context): State<Arc<LookupTermContext>>,
+) -> Json<serde_json::Value> {
+    let attributions = context.yomi_dicts.read().await.attributions();
+    Json(serde_json::json!({ "attributions": attributions }))
+}
+
+/// Headwords starting with `q` for a dictionary search bar, most frequent first.
+/// `q` may also be a wildcard pattern such as `打*` or `*込む`.
+#[instrument(skip(context, headers))]
+pub async fn suggest(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Query(query): Query<SuggestQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    record_telemetry(&context, TelemetryEvent::Suggest).await;
+    let prefix = query.q.trim().nfc().collect::<String>();
+    if prefix.is_empty() {
+        return Ok(Json(serde_json::json!({ "suggestions": [] })));
+    }
+    if prefix.chars().all(|c| matches!(c, '*' | '?' | '＊' | '？')) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Wildcard queries need at least one character besides wildcards",
+        ));
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SUGGESTIONS)
+        .clamp(1, MAX_SUGGESTIONS);
+    // Anonymous users get the default preferences
+    let user_id = headers
+        .get("user_id")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default();
+    let user_preferences = user_preferences_or_default(&context, user_id).await;
+    let jlpt_filter = match query
+        .jlpt
+        .as_deref()
+        .filter(|levels| !levels.trim().is_empty())
+    {
+        Some(levels) => Some(
+            levels
+                .split(',')
+                .map(str::parse)
+                .collect::<Result<HashSet<JlptLevel>>>()
+                .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e.to_string()))?,
+        ),
+        None => None,
+    };
+
+    // With a level filter every candidate is ranked, and the filter applied
+    // before the limit
+    let mut suggestions = context
+        .yomi_dicts
+        .read()
+        .await
+        .suggest(
+            &prefix,
+            if jlpt_filter.is_some() {
+                usize::MAX
+            } else {
+                limit
+            },
+            &user_preferences,
+        )
+        .map_err(|e| {
+            error!(?e, "❌ Failed to get suggestions");
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get suggestions",
+            )
+        })?;
+    for suggestion in &mut suggestions {
+        suggestion.jlpt = context.levels.jlpt_level(&suggestion.term, None);
+    }
+    if let Some(levels) = &jlpt_filter {
+        suggestions.retain(|s| s.jlpt.is_some_and(|level| levels.contains(&level)));
+        suggestions.truncate(limit);
+    }
+    Ok(Json(serde_json::json!({ "suggestions": suggestions })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConjugateQuery {
+    term: String,
+    /// Yomitan rule identifier ("v1", "v5", "vs", "vk", "adj-i"), taken from
+    /// the term's dictionary entry if left out
+    pos: Option<String>,
+    reading: Option<String>,
+}
+
+/// Conjugation table of a verb or adjective, generated from the rules of the
+/// deinflector so it matches the inflections lookups report
+#[instrument(skip(context, headers))]
+pub async fn conjugate(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Query(query): Query<ConjugateQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    record_telemetry(&context, TelemetryEvent::Conjugate).await;
+    let mut term = query.term.trim().nfc().collect::<String>();
+    if term.is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "A term is required"));
+    }
+    let mut reading = query.reading.map(|r| r.trim().nfc().collect::<String>());
+
+    let class = match &query.pos {
+        Some(pos) => deinflect::word_class::from_rule_identifier(pos).ok_or_else(|| {
+            api_error(
+                StatusCode::BAD_REQUEST,
+                "pos must be one of v1, v5, vs, vk or adj-i",
+            )
+        })?,
+        None => {
+            // Anonymous users get the default preferences
+            let user_id = headers
+                .get("user_id")
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or_default();
+            let user_preferences = user_preferences_or_default(&context, user_id).await;
+            let entry = context
+                .yomi_dicts
+                .read()
+                .await
+                .lookup_first_entry(&term, &user_preferences)
+                .map_err(|e| {
+                    error!(?e, "❌ Failed to look up term to conjugate");
+                    api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to look up term")
+                })?;
+            let Some((_, entry)) = entry else {
+                return Err(api_error(StatusCode::NOT_FOUND, "Term not found"));
+            };
+            reading = reading.or(Some(entry.reading.to_hiragana()));
+            entry
+                .rule_identifiers
+                .split_whitespace()
+                .find_map(deinflect::word_class::from_rule_identifier)
+                .ok_or_else(|| {
+                    api_error(
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        "The term's dictionary entry isn't a verb or an i-adjective",
+                    )
+                })?
+        }
+    };
+
+    // JMdict lists する verbs under the noun
+    if class == deinflect::word_class::VS {
+        for form in std::iter::once(&mut term).chain(reading.as_mut()) {
+            if !form.ends_with("する") {
+                form.push_str("する");
+            }
+        }
+    }
+
+    let forms = deinflect::conjugate(&term, class);
+    if forms.is_empty() {
+        return Err(api_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "The term doesn't end like a word of that part of speech",
+        ));
+    }
+    let reading_forms = reading
+        .as_deref()
+        .map(|r| deinflect::conjugate(r, class))
+        .unwrap_or_default();
+    // Forms of the term and its reading come from the same rules in the same
+    // order, so they pair up by their inflections
+    let mut reading_forms = reading_forms.into_iter().map(Some).collect::<Vec<_>>();
+    let forms: Vec<serde_json::Value> = forms
+        .into_iter()
+        .map(|(form, reasons)| {
+            let form_reading = reading_forms
+                .iter_mut()
+                .find(|f| f.as_ref().is_some_and(|(_, r)| *r == reasons))
+                .and_then(Option::take)
+                .map(|(r, _)| r);
+            let inflections: Vec<Inflection> = reasons.into_iter().map(Inflection::from).collect();
+            serde_json::json!({
+                "form": form,
+                "reading": form_reading,
+                "inflections": inflections,
+            })
+        })
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "term": term,
+        "reading": reading,
+        "pos": deinflect::word_class::rule_identifier(class),
+        "forms": forms,
+    })))
+}
+
+pub const DEFAULT_REVERSE_LOOKUP_RESULTS: usize = 20;
+pub const MAX_REVERSE_LOOKUP_RESULTS: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct ReverseLookupQuery {
+    q: String,
+    limit: Option<usize>,
+}
+
+fn dicts_db_dir() -> Result<camino::Utf8PathBuf, ApiError> {
+    let dicts_path = std::env::var("DICTS_PATH")
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "DICTS_PATH not set"))?;
+    Ok(camino::Utf8PathBuf::from(dicts_path).join("db"))
+}
+
+/// English → Japanese lookup in the bilingual term dictionaries, e.g. `q=run`
+#[instrument(skip(context, headers))]
+pub async fn reverse_lookup(
+    State(context): State<Arc<LookupTermContext>>,
+    headers: HeaderMap,
+    Query(query): Query<ReverseLookupQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    record_telemetry(&context, TelemetryEvent::ReverseLookup).await;
+    if crate::reverse_lookup::gloss_words(&query.q).is_empty() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Query must contain at least one word",
+        ));
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_REVERSE_LOOKUP_RESULTS)
+        .clamp(1, MAX_REVERSE_LOOKUP_RESULTS);
+    let db_dir = dicts_db_dir()?;
+    // Anonymous users get the default preferences
+    let user_id = headers
+        .get("user_id")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default();
+    let user_preferences = user_preferences_or_default(&context, user_id).await;
+
+    // The reverse indexes are searched on the blocking pool, under a lookup
+    // permit like the dictionary entries of lookups
+    let _permit = context
+        .lookup_permits
+        .acquire()
+        .await
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+    let yomi_dicts = context.yomi_dicts.clone().read_owned().await;
+    let candidates = tokio::task::spawn_blocking(move || {
+        yomi_dicts.reverse_lookup(&db_dir, &query.q, limit, &user_preferences)
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|candidates| candidates)
+    .map_err(|e| {
+        error!(?e, "❌ Failed to run reverse lookup");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to run reverse lookup",
         )
     })?;
+    Ok(Json(serde_json::json!({ "candidates": candidates })))
+}
 
-    // Construct the full path (same as serve_static_file)
-    let base_static = StdPath::new(&static_path).join("static");
-    let full_path = base_static.join(&normalized_path);
+/// Which term dictionaries support reverse lookup. `supported` is null for
+/// dictionaries whose reverse index hasn't been built yet.
+pub async fn get_reverse_lookup_dictionaries(
+    State(context): State<Arc<LookupTermContext>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let db_dir = dicts_db_dir()?;
+    let dictionaries = context.yomi_dicts.read().await.term_dictionary_dirs();
+    let dictionaries: Vec<_> = dictionaries
+        .into_iter()
+        .map(|(info, origin)| {
+            let supported = crate::reverse_lookup::index_status(&db_dir.join(&origin))
+                .unwrap_or_else(|e| {
+                    warn!(?e, title = %info.title, "⚠️ Failed to read reverse lookup index");
+                    None
+                });
+            serde_json::json!({
+                "title": info.title,
+                "revision": info.revision,
+                "supported": supported,
+            })
+        })
+        .collect();
+    Ok(Json(serde_json::json!({ "dictionaries": dictionaries })))
+}
 
-    // Security check: ensure the path is within the static directory
-    let static_dir = base_static.canonicalize().map_err(|_| {
-        (
+/// Build reverse lookup indexes for term dictionaries imported before they
+/// existed. This reads every term bank without an index, so it can take a while.
+pub async fn build_reverse_lookup_indexes(
+    State(context): State<Arc<LookupTermContext>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let db_dir = dicts_db_dir()?;
+    let dictionaries = context.yomi_dicts.read().await.term_dictionary_dirs();
+
+    let built = tokio::task::spawn_blocking(move || {
+        let mut built = Vec::new();
+        for (info, origin) in dictionaries {
+            let dict_dir = db_dir.join(&origin);
+            if !matches!(crate::reverse_lookup::index_status(&dict_dir), Ok(None)) {
+                continue;
+            }
+            match crate::reverse_lookup::build_index(&dict_dir) {
+                Ok(Some(supported)) => {
+                    info!(title = %info.title, supported, "🔎 Built reverse lookup index");
+                    built.push(serde_json::json!({
+                        "title": info.title,
+                        "revision": info.revision,
+                        "supported": supported,
+                    }));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    warn!(?e, title = %info.title, "⚠️ Failed to build reverse lookup index")
+                }
+            }
+        }
+        built
+    })
+    .await
+    .map_err(|e| {
+        error!(?e, "❌ Reverse lookup index task failed");
+        api_error(
             StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to canonicalize static dir".to_string(),
+            "Failed to build reverse lookup indexes",
         )
     })?;
 
-    let canonical_path = full_path
-        .canonicalize()
-        .map_err(|_| (StatusCode::NOT_FOUND, "File not found".to_string()))?;
+    Ok(Json(serde_json::json!({ "built": built })))
+}
 
-    if !canonical_path.starts_with(&static_dir) {
-        return Err((StatusCode::FORBIDDEN, "Access denied".to_string()));
+#[derive(Debug, Deserialize)]
+pub struct DictSandboxRequest {
+    /// Archive name under `DICTS_PATH/yomitan`, as uploaded
+    filename: String,
+    terms: Vec<String>,
+    #[serde(default)]
+    mode: ImportMode,
+}
+
+/// Import an uploaded archive into a throwaway directory and look up sample
+/// terms in it, so admins can check a dictionary before importing it for real
+#[instrument(skip(request), fields(filename = %request.filename))]
+pub async fn evaluate_dict_sandbox(
+    Json(request): Json<DictSandboxRequest>,
+) -> Result<Json<crate::dict_sandbox::SandboxReport>, ApiError> {
+    // Admin check is handled by the auth middleware
+    if request.terms.is_empty() || request.terms.len() > crate::dict_sandbox::MAX_SANDBOX_TERMS {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Provide between 1 and {} terms",
+                crate::dict_sandbox::MAX_SANDBOX_TERMS
+            ),
+        ));
+    }
+    // Only bare filenames, so the request can't point outside the upload directory
+    if StdPath::new(&request.filename).file_name() != Some(std::ffi::OsStr::new(&request.filename))
+    {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Invalid filename"));
+    }
+    let dicts_path = std::env::var("DICTS_PATH")
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "DICTS_PATH not set"))?;
+    let archive_path = camino::Utf8PathBuf::from(dicts_path)
+        .join("yomitan")
+        .join(&request.filename);
+    if !archive_path.is_file() {
+        return Err(api_error(
+            StatusCode::NOT_FOUND,
+            &format!("No uploaded dictionary named {}", request.filename),
+        ));
     }
 
+    let terms = request
+        .terms
+        .iter()
+        .map(|t| t.trim().nfc().collect::<String>())
+        .collect();
+    let report = crate::dict_sandbox::evaluate_archive(archive_path, terms, request.mode)
+        .await
+        .map_err(|e| {
+            warn!(?e, "⚠️ Dictionary sandbox evaluation failed");
+            api_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                &format!("Failed to evaluate dictionary: {e:#}"),
+            )
+        })?;
     info!(
-        "🖼️ Image request: rel_path={}, static_path={}, full_path={}, canonical_path={}",
-        rel_path,
-        static_path,
-        full_path.display(),
-        canonical_path.display()
+        title = %report.title,
+        import_ms = report.import_ms,
+        "🧪 Evaluated dictionary in sandbox"
     );
+    Ok(Json(report))
+}
+
+/// Space taken by dictionary static assets, and how much content addressing
+/// saves compared to a copy per dictionary
+#[instrument]
+pub async fn get_static_asset_report() -> Result<Json<AssetStorageReport>, ApiError> {
+    let dicts_path = std::env::var("DICTS_PATH")
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "DICTS_PATH not set"))?;
+    let store = AssetStore::new(camino::Utf8PathBuf::from(dicts_path).join("static"));
+    let report = tokio::task::spawn_blocking(move || store.report())
+        .await
+        .map_err(|e| {
+            error!(?e, "Static asset report task failed");
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to build report")
+        })?
+        .map_err(|e| {
+            error!(?e, "Failed to build static asset report");
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to build report")
+        })?;
+    Ok(Json(report))
+}
 
-    let content = tokio::fs::read(&canonical_path).await.map_err(|e| {
-        error!("🖼️ Image read error: {}", e);
-        (StatusCode::NOT_FOUND, format!("Image not found: {}", e))
+/// Diagnostic bundles of failed dictionary imports, most recent first
+pub async fn get_import_failures() -> Result<Json<serde_json::Value>, ApiError> {
+    let dicts_path = std::env::var("DICTS_PATH")
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "DICTS_PATH not set"))?;
+    let failures = tokio::task::spawn_blocking(move || {
+        import_failures::list(camino::Utf8Path::new(&dicts_path))
+    })
+    .await
+    .map_err(|e| {
+        error!(?e, "Import failure listing task failed");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to list import failures",
+        )
+    })?
+    .map_err(|e| {
+        error!(?e, "Failed to list import failures");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to list import failures",
+        )
     })?;
+    Ok(Json(serde_json::json!({ "failures": failures })))
+}
 
-    // 4) MIME type
-    let mime = mime_guess::from_path(&canonical_path)
-        .first_or_octet_stream()
-        .essence_str()
-        .to_string();
+/// Summaries of the saved dictionary scan reports, most recent first
+pub async fn get_scan_reports() -> Result<Json<serde_json::Value>, ApiError> {
+    let dicts_path = std::env::var("DICTS_PATH")
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "DICTS_PATH not set"))?;
+    let reports =
+        tokio::task::spawn_blocking(move || scan_reports::list(camino::Utf8Path::new(&dicts_path)))
+            .await
+            .map_err(|e| {
+                error!(?e, "Scan report listing task failed");
+                api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to list scan reports",
+                )
+            })?
+            .map_err(|e| {
+                error!(?e, "Failed to list scan reports");
+                api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to list scan reports",
+                )
+            })?;
+    Ok(Json(serde_json::json!({ "reports": reports })))
+}
+
+/// One dictionary scan's report, with the outcome of every archive
+pub async fn get_scan_report(Path(id): Path<Uuid>) -> Result<Json<ScanReport>, ApiError> {
+    let dicts_path = std::env::var("DICTS_PATH")
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "DICTS_PATH not set"))?;
+    let report = tokio::task::spawn_blocking(move || {
+        scan_reports::load(camino::Utf8Path::new(&dicts_path), id)
+    })
+    .await
+    .map_err(|e| {
+        error!(?e, "Scan report loading task failed");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to load scan report",
+        )
+    })?
+    .map_err(|e| {
+        error!(?e, %id, "Failed to load scan report");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to load scan report",
+        )
+    })?
+    .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Scan report not found"))?;
+    Ok(Json(report))
+}
 
-    // 5) Response headers
-    let mut resp_headers = axum::http::HeaderMap::new();
-    resp_headers.insert("Content-Type", mime.parse().unwrap());
-    resp_headers.insert("Cache-Control", "public, max-age=3600".parse().unwrap());
+/// What this instance offers the caller: whether they're logged in or a
+/// guest, the limits guests get, the features that need a login, and which
+/// optional subsystems are set up, so clients can hide what won't work
+pub async fn get_capabilities(
+    State(context): State<Arc<LookupTermContext>>,
+    visitor: Option<Extension<Visitor>>,
+) -> Json<serde_json::Value> {
+    let config = context.config.current();
+    let guest_id = match &visitor {
+        Some(Extension(Visitor::Guest(id))) => Some(*id),
+        _ => None,
+    };
+    Json(serde_json::json!({
+        "authenticated": matches!(visitor, Some(Extension(Visitor::User(_)))),
+        "guestId": guest_id,
+        "guest": {
+            "access": config.guest_access,
+            "requestsPerMinute": config.guest_requests_per_minute,
+            "audio": config.guest_audio,
+        },
+        "loginRequired": guest::login_required_features(&config),
+        "subsystems": {
+            "tokenizer": context.tokenizer.is_some(),
+            "audioDb": context.audio_db.load().is_some(),
+            "tts": context.tts.is_some(),
+            "webnovelImport": context.webnovel_import,
+            "levels": !context.levels.is_empty(),
+            "library": context.uploads_dir.is_some(),
+            // There is no OCR backend, PDFs are imported from their text layer
+            "ocr": false,
+        },
+    }))
+}
 
-    // 6) Return response
-    let mut response = Response::builder()
-        .status(StatusCode::OK)
-        .body(Body::from(content))
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to build response".to_string(),
-            )
-        })?;
+/// How long each startup step and dictionary took to load
+pub async fn get_startup_timings(
+    State(context): State<Arc<LookupTermContext>>,
+) -> Json<StartupTimings> {
+    Json((*context.startup_timings).clone())
+}
 
-    *response.headers_mut() = resp_headers;
-    Ok(response)
+/// One failed import's diagnostic bundle, with the archive's `index.json`
+pub async fn get_import_failure(
+    Path(id): Path<Uuid>,
+) -> Result<Json<ImportFailureBundle>, ApiError> {
+    let dicts_path = std::env::var("DICTS_PATH")
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "DICTS_PATH not set"))?;
+    let bundle = tokio::task::spawn_blocking(move || {
+        import_failures::load(camino::Utf8Path::new(&dicts_path), id)
+    })
+    .await
+    .map_err(|e| {
+        error!(?e, "Import failure loading task failed");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to load import failure",
+        )
+    })?
+    .map_err(|e| {
+        error!(?e, %id, "Failed to load import failure");
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to load import failure",
+        )
+    })?
+    .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Import failure not found"))?;
+    Ok(Json(bundle))
 }
 
 #[cfg(test)]
@@ -2194,6 +7916,55 @@ mod tests {
         std::thread::sleep(std::time::Duration::from_millis(1));
     }
 
+    #[tokio::test]
+    async fn test_serve_signed_static_file() {
+        ensure_test_isolation();
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dict_dir = temp_dir.path().join("static").join("Test Dict");
+        std::fs::create_dir_all(&dict_dir).unwrap();
+        std::fs::write(dict_dir.join("style.css"), b"body {}").unwrap();
+        std::env::set_var("DICTS_PATH", temp_dir.path().to_string_lossy().to_string());
+
+        let exp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 3600;
+        let path = "Test%20Dict/style.css";
+
+        // Signed for the /media/img/ route rather than /dicts/
+        let sig = generate_hmac_signature(&format!("/media/img/{path}"), exp, "test-key-123");
+        let result = serve_signed_static_file(
+            HeaderMap::new(),
+            Path(path.to_string()),
+            Query(SigQuery { exp, sig }),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().0, StatusCode::UNAUTHORIZED);
+
+        let sig = generate_hmac_signature(&format!("/dicts/{path}"), exp, "test-key-123");
+        let response = serve_signed_static_file(
+            HeaderMap::new(),
+            Path(path.to_string()),
+            Query(SigQuery { exp, sig }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["Content-Type"], "text/css");
+
+        assert_eq!(
+            "Signed".parse::<DictsStaticAccess>().unwrap(),
+            DictsStaticAccess::Signed
+        );
+        assert_eq!(
+            "".parse::<DictsStaticAccess>().unwrap(),
+            DictsStaticAccess::Public
+        );
+        assert!("private".parse::<DictsStaticAccess>().is_err());
+    }
+
     #[test]
     fn test_sig_query_deserialization() {
         let json = r#"{"exp": 1234567890, "sig": "test-signature"}"#;
@@ -2229,6 +8000,22 @@ mod tests {
         assert_ne!(signature, signature4);
     }
 
+    #[test]
+    fn test_sign_media_url_verifies() {
+        ensure_test_isolation();
+
+        let rel_path = "forvo_files/speaker name/食べる.opus";
+        let url = sign_media_url(rel_path, Duration::from_secs(60)).unwrap();
+
+        let uri: axum::http::Uri = url.parse().unwrap();
+        assert_eq!(
+            uri.path(),
+            "/media/forvo_files/speaker%20name/%E9%A3%9F%E3%81%B9%E3%82%8B.opus"
+        );
+        let Query(sig_query) = Query::<SigQuery>::try_from_uri(&uri).unwrap();
+        assert!(verify_signed_url(rel_path, &sig_query, "/media/", "🎵").is_ok());
+    }
+
     #[test]
     fn test_verify_signed_url_valid_signature() {
         setup_test_env();
@@ -2456,7 +8243,14 @@ mod tests {
         let sig_query = SigQuery { exp, sig };
         let headers = HeaderMap::new();
 
-        let result = serve_signed_media(Path(path.to_string()), Query(sig_query), headers).await;
+        let result = serve_signed_media(
+            Method::GET,
+            Path(path.to_string()),
+            Query(sig_query),
+            Query(AudioFileQuery { trim: None }),
+            headers,
+        )
+        .await;
 
         assert!(result.is_err());
 
@@ -2482,7 +8276,13 @@ mod tests {
 
         let sig_query = SigQuery { exp, sig };
 
-        let result = serve_signed_image(Path(path.to_string()), Query(sig_query)).await;
+        let result = serve_signed_image(
+            Method::GET,
+            HeaderMap::new(),
+            Path(path.to_string()),
+            Query(sig_query),
+        )
+        .await;
 
         assert!(result.is_err());
 
@@ -2511,7 +8311,14 @@ mod tests {
         let sig_query = SigQuery { exp, sig };
         let headers = HeaderMap::new();
 
-        let result = serve_signed_media(Path(path.to_string()), Query(sig_query), headers).await;
+        let result = serve_signed_media(
+            Method::GET,
+            Path(path.to_string()),
+            Query(sig_query),
+            Query(AudioFileQuery { trim: None }),
+            headers,
+        )
+        .await;
 
         assert!(result.is_err());
 
@@ -2575,6 +8382,23 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_find_audio_file_in_dirs_either_normalization() {
+        use tempfile::TempDir;
+
+        let nfc = "がっこう.mp3";
+        let nfd = nfc.nfd().collect::<String>();
+        for (stored, requested) in [(nfc, nfd.as_str()), (nfd.as_str(), nfc)] {
+            let temp_dir = TempDir::new().unwrap();
+            std::fs::write(temp_dir.path().join(stored), b"audio").unwrap();
+            let audio_dirs = temp_dir.path().display().to_string();
+            let found = find_audio_file_in_dirs(&audio_dirs, requested)
+                .await
+                .unwrap();
+            assert_eq!(std::fs::read(found).unwrap(), b"audio");
+        }
+    }
+
     #[tokio::test]
     async fn test_serve_signed_image_missing_dicts_path() {
         // Set up test environment - we need MEDIA_URL_KEY for signature verification
@@ -2594,7 +8418,13 @@ mod tests {
 
         let sig_query = SigQuery { exp, sig };
 
-        let result = serve_signed_image(Path(path.to_string()), Query(sig_query)).await;
+        let result = serve_signed_image(
+            Method::GET,
+            HeaderMap::new(),
+            Path(path.to_string()),
+            Query(sig_query),
+        )
+        .await;
 
         assert!(result.is_err());
 
@@ -2627,7 +8457,13 @@ mod tests {
 
         let sig_query = SigQuery { exp, sig };
 
-        let result = serve_signed_image(Path(path.to_string()), Query(sig_query)).await;
+        let result = serve_signed_image(
+            Method::GET,
+            HeaderMap::new(),
+            Path(path.to_string()),
+            Query(sig_query),
+        )
+        .await;
 
         // Should fail with NOT_FOUND since the file doesn't exist, but should not fail with
         // BAD_REQUEST due to Unicode normalization issues
@@ -2665,7 +8501,13 @@ mod tests {
 
         let sig_query = SigQuery { exp, sig };
 
-        let result = serve_signed_image(Path(raw_path.to_string()), Query(sig_query)).await;
+        let result = serve_signed_image(
+            Method::GET,
+            HeaderMap::new(),
+            Path(raw_path.to_string()),
+            Query(sig_query),
+        )
+        .await;
 
         // Should fail with NOT_FOUND since the file doesn't exist, but should not fail with
         // BAD_REQUEST due to URL decoding issues
@@ -2724,7 +8566,8 @@ mod tests {
         let sig = generate_hmac_signature(&path_for_sig, exp, "test-key-123");
         let sig_query = SigQuery { exp, sig };
 
-        let result = serve_signed_image(Path(path), Query(sig_query)).await;
+        let result =
+            serve_signed_image(Method::GET, HeaderMap::new(), Path(path), Query(sig_query)).await;
 
         // Should succeed regardless of the normalization form used in the path
         assert!(
@@ -2741,8 +8584,13 @@ mod tests {
             sig: sig_encoded,
         };
 
-        let result_encoded =
-            serve_signed_image(Path(encoded_path.to_string()), Query(sig_query_encoded)).await;
+        let result_encoded = serve_signed_image(
+            Method::GET,
+            HeaderMap::new(),
+            Path(encoded_path.to_string()),
+            Query(sig_query_encoded),
+        )
+        .await;
 
         // Should also succeed with URL encoding
         assert!(
@@ -2761,4 +8609,421 @@ mod tests {
         // Clean up
         std::fs::remove_dir_all(&temp_dir).ok();
     }
+
+    #[tokio::test]
+    async fn test_media_file_response_head_and_range() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("clip.ogg");
+        std::fs::write(&path, b"0123456789").unwrap();
+
+        let body = |response: Response| async move {
+            axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+        };
+
+        // HEAD: the full response's headers, without a body
+        let response =
+            media_file_response(&Method::HEAD, &HeaderMap::new(), &path, "audio/ogg", None)
+                .await
+                .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["Content-Length"], "10");
+        assert_eq!(response.headers()["Content-Type"], "audio/ogg");
+        assert_eq!(response.headers()["Accept-Ranges"], "bytes");
+        assert!(body(response).await.is_empty());
+
+        let response =
+            media_file_response(&Method::GET, &HeaderMap::new(), &path, "audio/ogg", None)
+                .await
+                .unwrap();
+        assert_eq!(body(response).await.as_ref(), b"0123456789");
+
+        let mut headers = HeaderMap::new();
+        headers.insert("range", HeaderValue::from_static("bytes=2-5"));
+        let response = media_file_response(&Method::GET, &headers, &path, "audio/ogg", None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()["Content-Range"], "bytes 2-5/10");
+        assert_eq!(response.headers()["Content-Length"], "4");
+        assert_eq!(body(response).await.as_ref(), b"2345");
+
+        let response = media_file_response(&Method::HEAD, &headers, &path, "audio/ogg", None)
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["Content-Length"], "4");
+        assert!(body(response).await.is_empty());
+
+        headers.insert("range", HeaderValue::from_static("bytes=5-10"));
+        let response = media_file_response(&Method::GET, &headers, &path, "audio/ogg", None)
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["Content-Range"], "bytes 5-9/10");
+        assert_eq!(body(response).await.as_ref(), b"56789");
+
+        headers.insert("range", HeaderValue::from_static("bytes=10-12"));
+        let result = media_file_response(&Method::GET, &headers, &path, "audio/ogg", None).await;
+        assert_eq!(result.unwrap_err().0, StatusCode::RANGE_NOT_SATISFIABLE);
+    }
+
+    #[tokio::test]
+    async fn test_media_file_response_not_modified() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("clip.ogg");
+        std::fs::write(&path, b"0123456789").unwrap();
+        let cache_control = Some("public, max-age=3600");
+
+        let response = media_file_response(
+            &Method::GET,
+            &HeaderMap::new(),
+            &path,
+            "audio/ogg",
+            cache_control,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()["ETag"].clone();
+        let last_modified = response.headers()["Last-Modified"].clone();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("if-none-match", etag.clone());
+        let response =
+            media_file_response(&Method::GET, &headers, &path, "audio/ogg", cache_control)
+                .await
+                .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()["ETag"], etag);
+        assert_eq!(response.headers()["Cache-Control"], "public, max-age=3600");
+
+        let mut headers = HeaderMap::new();
+        headers.insert("if-modified-since", last_modified);
+        let response = media_file_response(&Method::GET, &headers, &path, "audio/ogg", None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // A changed file no longer matches
+        std::fs::write(&path, b"01234567890").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("if-none-match", etag);
+        let response = media_file_response(&Method::GET, &headers, &path, "audio/ogg", None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_byte_range() {
+        assert_eq!(byte_range(None, 10), Ok(None));
+        assert_eq!(byte_range(Some("bytes=2-5"), 10), Ok(Some((2, 5))));
+        assert_eq!(byte_range(Some("bytes=4-"), 10), Ok(Some((4, 9))));
+        // Safari asks for the last bytes of a file with a suffix range
+        assert_eq!(byte_range(Some("bytes=-3"), 10), Ok(Some((7, 9))));
+        assert_eq!(byte_range(Some("bytes=-30"), 10), Ok(Some((0, 9))));
+        assert_eq!(byte_range(Some("bytes=0-1, 4-5"), 10), Ok(Some((0, 1))));
+        assert_eq!(byte_range(Some("items=0-1"), 10), Ok(None));
+
+        // Players ask for more than there is
+        assert_eq!(byte_range(Some("bytes=5-10"), 10), Ok(Some((5, 9))));
+        assert_eq!(byte_range(Some("bytes=0-999"), 10), Ok(Some((0, 9))));
+
+        assert_eq!(byte_range(Some("bytes=10-12"), 10), Err(()));
+        assert_eq!(byte_range(Some("bytes=10-"), 10), Err(()));
+        assert_eq!(byte_range(Some("bytes=6-2"), 10), Err(()));
+        assert_eq!(byte_range(Some("bytes=-0"), 10), Err(()));
+        assert_eq!(byte_range(Some("bytes=a-b"), 10), Err(()));
+        assert_eq!(byte_range(Some("bytes=0-"), 0), Err(()));
+    }
+
+    #[test]
+    fn test_list_epub_files_in_volume_order() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        for name in [
+            "n1234ab_10.epub",
+            "n1234ab_2.epub",
+            "n1234ab_1.epub",
+            "cover.jpg",
+        ] {
+            std::fs::write(temp_dir.path().join(name), b"").unwrap();
+        }
+        let files: Vec<_> = list_epub_files(temp_dir.path())
+            .unwrap()
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(
+            files,
+            ["n1234ab_1.epub", "n1234ab_2.epub", "n1234ab_10.epub"]
+        );
+        assert!(cmp_volume_filenames("第2巻.epub", "第10巻.epub").is_lt());
+        assert!(cmp_volume_filenames("vol02.epub", "vol3.epub").is_lt());
+    }
+
+    #[test]
+    fn test_apply_popup_settings() {
+        let entry = TermEntry {
+            text: "猫".to_string(),
+            reading: "ねこ".to_string(),
+            tags: vec!["n".to_string()],
+            rule_identifiers: String::new(),
+            score: 0.0,
+            definitions: vec![
+                Definition::Simple {
+                    content: "cat".to_string(),
+                },
+                Definition::Simple {
+                    content: "geisha".to_string(),
+                },
+            ],
+            sequence_number: 1,
+            term_tags: vec!["P".to_string()],
+            heuristic: false,
+            matched_form: None,
+            inflections: Vec::new(),
+        };
+        let mut response = LookupTermResponse {
+            dictionary_results: ["JMdict", "Daijirin"]
+                .map(|title| DictionaryResult {
+                    title: title.to_string(),
+                    revision: "1".to_string(),
+                    origin: String::new(),
+                    entries: vec![entry.clone()],
+                    collapsed: false,
+                })
+                .to_vec(),
+            pitch_accent_results: HashMap::from([(
+                "猫".to_string(),
+                PitchAccentResult {
+                    title: "NHK".to_string(),
+                    entries: HashMap::new(),
+                },
+            )]),
+            frequency_data_lists: HashMap::from([(
+                "JPDB#1".to_string(),
+                FrequencyDataList { items: Vec::new() },
+            )]),
+            library_frequency: HashMap::new(),
+            frequency_ranks: HashMap::new(),
+            related_words: Vec::new(),
+            related_entries: Vec::new(),
+            notes: Vec::new(),
+            levels: Vec::new(),
+            known_terms: Vec::new(),
+            audio: Vec::new(),
+        };
+
+        response.apply_popup_settings(&PopupSettings::default());
+        assert_eq!(response.pitch_accent_results.len(), 1);
+        assert_eq!(response.frequency_data_lists.len(), 1);
+        assert_eq!(
+            response.dictionary_results[0].entries[0].definitions.len(),
+            2
+        );
+
+        response.apply_popup_settings(&PopupSettings {
+            show_pitch_accent: false,
+            show_frequency: false,
+            show_tags: false,
+            collapsed_dictionaries: HashSet::from(["Daijirin#1".to_string()]),
+            max_definitions: Some(1),
+            frequency_bands: Vec::new(),
+            audio_autoplay: Vec::new(),
+        });
+        assert!(response.pitch_accent_results.is_empty());
+        assert!(response.frequency_data_lists.is_empty());
+        let collapsed: Vec<bool> = response
+            .dictionary_results
+            .iter()
+            .map(|r| r.collapsed)
+            .collect();
+        assert_eq!(collapsed, [false, true]);
+        let entry = &response.dictionary_results[0].entries[0];
+        assert!(entry.tags.is_empty() && entry.term_tags.is_empty());
+        assert_eq!(entry.definitions.len(), 1);
+    }
+
+    #[test]
+    fn test_apply_frequency_bands() {
+        let item = |value| FrequencyData {
+            term: "猫".to_string(),
+            reading: None,
+            value: Some(value),
+            display_value: None,
+            band: None,
+        };
+        let mut response = LookupTermResponse {
+            dictionary_results: Vec::new(),
+            pitch_accent_results: HashMap::new(),
+            frequency_data_lists: HashMap::from([
+                (
+                    "JPDB#1".to_string(),
+                    FrequencyDataList {
+                        items: vec![item(1200), item(90000)],
+                    },
+                ),
+                (
+                    "Novels (occurrences)#1".to_string(),
+                    FrequencyDataList {
+                        items: vec![item(1200)],
+                    },
+                ),
+            ]),
+            library_frequency: HashMap::new(),
+            frequency_ranks: HashMap::from([(
+                "猫".to_string(),
+                FrequencyRank {
+                    rank: 8000.0,
+                    band: None,
+                },
+            )]),
+            related_words: Vec::new(),
+            related_entries: Vec::new(),
+            notes: Vec::new(),
+            levels: Vec::new(),
+            known_terms: Vec::new(),
+            audio: Vec::new(),
+        };
+        let settings = PopupSettings {
+            frequency_bands: vec![
+                crate::user_preferences::FrequencyBand {
+                    label: "common".to_string(),
+                    max_rank: 5000,
+                    color: "#2e7d32".to_string(),
+                },
+                crate::user_preferences::FrequencyBand {
+                    label: "uncommon".to_string(),
+                    max_rank: 20000,
+                    color: "#f9a825".to_string(),
+                },
+            ],
+            ..Default::default()
+        };
+        response.apply_frequency_bands(&settings, &HashSet::from(["JPDB#1".to_string()]));
+
+        let bands: Vec<Option<&str>> = response.frequency_data_lists["JPDB#1"]
+            .items
+            .iter()
+            .map(|i| i.band.as_deref())
+            .collect();
+        assert_eq!(bands, [Some("common"), None]);
+        assert_eq!(
+            response.frequency_data_lists["Novels (occurrences)#1"].items[0].band,
+            None
+        );
+        assert_eq!(
+            response.frequency_ranks["猫"].band.as_deref(),
+            Some("uncommon")
+        );
+    }
+
+    #[test]
+    fn test_pitch_aware_audio_order() {
+        let accent = |reading: &str, position| PitchAccentEntry {
+            reading: reading.to_string(),
+            position,
+            mora_count: 3,
+            morae: Vec::new(),
+            pattern: Vec::new(),
+            nasal: Vec::new(),
+            devoiced: Vec::new(),
+        };
+        let pitch_accent_results = HashMap::from([(
+            "日本".to_string(),
+            PitchAccentResult {
+                title: "NHK".to_string(),
+                entries: HashMap::from([
+                    (
+                        "にほん".to_string(),
+                        PitchAccentEntryList {
+                            entries: vec![accent("にほん", 2)],
+                        },
+                    ),
+                    (
+                        "ニッポン".to_string(),
+                        PitchAccentEntryList {
+                            entries: vec![accent("にっぽん", 3), accent("にっぽん", 0)],
+                        },
+                    ),
+                ]),
+            },
+        )]);
+        assert_eq!(
+            studied_pitch(&pitch_accent_results, "日本", "にほん"),
+            Some(2)
+        );
+        assert_eq!(
+            studied_pitch(&pitch_accent_results, "日本", "ニッポン"),
+            Some(3)
+        );
+        assert_eq!(studied_pitch(&pitch_accent_results, "日本", "やまと"), None);
+        assert_eq!(studied_pitch(&pitch_accent_results, "本", "ほん"), None);
+
+        let recording = |id, source: &str, pitch| AudioEntry {
+            id,
+            expression: "日本".to_string(),
+            reading: Some("にっぽん".to_string()),
+            source: source.to_string(),
+            speaker: None,
+            display: None,
+            file: format!("{id}.opus"),
+            duration_ms: None,
+            peaks: None,
+            gain_db: None,
+            pitch,
+        };
+        let mut entries = vec![
+            recording(1, "forvo", None),
+            recording(2, "nhk16", Some(0)),
+            recording(3, "shinmeikai8", Some(3)),
+            recording(4, "nhk16", Some(3)),
+        ];
+        rank_by_pitch(&mut entries, Some(3));
+        let ids: Vec<i64> = entries.iter().map(|e| e.id).collect();
+        assert_eq!(ids, [3, 4, 1, 2]);
+        // Without an accent to study the order is left alone
+        rank_by_pitch(&mut entries, None);
+        let ids: Vec<i64> = entries.iter().map(|e| e.id).collect();
+        assert_eq!(ids, [3, 4, 1, 2]);
+    }
+
+    #[test]
+    fn test_public_decks_json_has_no_share_tokens() {
+        let now = chrono::Utc::now();
+        let deck = VocabList {
+            id: Uuid::new_v4(),
+            user_id: "owner".to_string(),
+            name: "N3 verbs".to_string(),
+            share_token: Some("secret-token".to_string()),
+            is_public: true,
+            entry_count: 3,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let json = public_decks_json(vec![deck]);
+        let decks = json["decks"].as_array().unwrap();
+        assert_eq!(decks.len(), 1);
+        assert_eq!(decks[0]["name"], "N3 verbs");
+        assert!(decks[0].get("shareToken").is_none());
+        assert!(!json.to_string().contains("secret-token"));
+    }
+
+    #[test]
+    fn test_audio_rel_path() {
+        assert_eq!(
+            audio_rel_path("nhk16_files/audio/taberu.opus"),
+            Some("nhk16_files/audio/taberu.opus")
+        );
+        assert_eq!(
+            audio_rel_path("/audio/forvo_files/speaker/食べる.opus"),
+            Some("forvo_files/speaker/食べる.opus")
+        );
+        assert_eq!(audio_rel_path(""), None);
+        assert_eq!(audio_rel_path("/audio/"), None);
+        assert_eq!(audio_rel_path("nhk16_files/../../etc/passwd"), None);
+        assert_eq!(audio_rel_path("/audio/./nhk16_files/a.opus"), None);
+        assert_eq!(audio_rel_path("nhk16_files\\..\\secret"), None);
+    }
 }
@@ -0,0 +1,204 @@
+//! Helpers for building HTTP responses shared by the handlers.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::http::{HeaderMap, HeaderValue};
+
+/// Characters allowed unescaped in an RFC 5987 `attr-char`
+fn is_attr_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric()
+        || matches!(
+            b,
+            b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~'
+        )
+}
+
+/// Drop characters that have no place in a downloaded file's name: path
+/// separators, control characters and surrounding whitespace
+pub fn sanitize_filename(filename: &str) -> String {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    name.chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// ASCII-only version of `filename` for clients that don't understand
+/// `filename*`. Other characters become `_`; a name with nothing ASCII left
+/// becomes `download` with the original extension.
+pub fn ascii_filename(filename: &str) -> String {
+    let (stem, extension) = match filename.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && ext.is_ascii() => (stem, Some(ext)),
+        _ => (filename, None),
+    };
+    let mut ascii_stem = String::new();
+    for c in stem.chars() {
+        let c = if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' {
+            c
+        } else {
+            '_'
+        };
+        if !(c == '_' && ascii_stem.ends_with('_')) {
+            ascii_stem.push(c);
+        }
+    }
+    let ascii_stem = if ascii_stem.chars().all(|c| c == '_' || c == ' ') {
+        "download"
+    } else {
+        ascii_stem.trim()
+    };
+    match extension {
+        Some(ext) => format!("{ascii_stem}.{ext}"),
+        None => ascii_stem.to_string(),
+    }
+}
+
+/// RFC 5987 `ext-value` (`UTF-8''` followed by the percent-encoded bytes)
+fn rfc5987_value(value: &str) -> String {
+    let mut encoded = String::from("UTF-8''");
+    for b in value.bytes() {
+        if is_attr_char(b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    encoded
+}
+
+/// `Content-Disposition` for downloading a file as `filename`, which may
+/// contain any characters (e.g. a Japanese novel title). The full name is
+/// sent as `filename*`, along with a plain `filename` for older clients:
+/// `ascii_fallback` if given, otherwise derived from `filename`.
+pub fn attachment_disposition(filename: &str, ascii_fallback: Option<&str>) -> HeaderValue {
+    let filename = sanitize_filename(filename);
+    let fallback = ascii_filename(&sanitize_filename(ascii_fallback.unwrap_or(&filename)));
+    let value = format!(
+        "attachment; filename=\"{fallback}\"; filename*={}",
+        rfc5987_value(&filename)
+    );
+    // Only ASCII is left after encoding, so this can't fail
+    HeaderValue::from_str(&value).unwrap_or(HeaderValue::from_static("attachment"))
+}
+
+/// What conditional requests for a file are checked against: an ETag made
+/// from its size and modification time, and the modification time itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileValidators {
+    pub etag: String,
+    pub last_modified: SystemTime,
+}
+
+impl FileValidators {
+    /// None if the file system doesn't record modification times
+    pub fn new(metadata: &std::fs::Metadata) -> Option<Self> {
+        let last_modified = metadata.modified().ok()?;
+        let modified_nanos = last_modified
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        Some(Self {
+            etag: format!("\"{:x}-{:x}\"", metadata.len(), modified_nanos),
+            last_modified,
+        })
+    }
+
+    /// Add `ETag` and `Last-Modified` to a response
+    pub fn insert_headers(&self, headers: &mut HeaderMap) {
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            headers.insert("ETag", etag);
+        }
+        if let Ok(date) = HeaderValue::from_str(&httpdate::fmt_http_date(self.last_modified)) {
+            headers.insert("Last-Modified", date);
+        }
+    }
+
+    /// Whether the client sending `headers` already has this version of the
+    /// file, so that a 304 can be sent instead. `If-None-Match` takes
+    /// precedence over `If-Modified-Since`, as in RFC 9110.
+    pub fn not_modified(&self, headers: &HeaderMap) -> bool {
+        if let Some(if_none_match) = headers.get("if-none-match") {
+            let Ok(if_none_match) = if_none_match.to_str() else {
+                return false;
+            };
+            // Weak comparison: a W/ prefix doesn't matter
+            return if_none_match
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == self.etag);
+        }
+        let Some(since) = headers
+            .get("if-modified-since")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok())
+        else {
+            return false;
+        };
+        // HTTP dates have whole seconds
+        let seconds = |time: SystemTime| time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        seconds(self.last_modified) <= seconds(since)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attachment_disposition() {
+        assert_eq!(
+            attachment_disposition("report.csv", None),
+            "attachment; filename=\"report.csv\"; filename*=UTF-8''report.csv"
+        );
+        assert_eq!(
+            attachment_disposition("転生した件 第1巻.epub", None),
+            "attachment; filename=\"_ _1_.epub\"; \
+             filename*=UTF-8''%E8%BB%A2%E7%94%9F%E3%81%97%E3%81%9F%E4%BB%B6%20%E7%AC%AC1%E5%B7%BB.epub"
+        );
+        assert_eq!(
+            attachment_disposition("転生.epub", Some("n1234ab.epub")),
+            "attachment; filename=\"n1234ab.epub\"; filename*=UTF-8''%E8%BB%A2%E7%94%9F.epub"
+        );
+        // Nothing usable left in ASCII, quotes and path components
+        assert_eq!(ascii_filename("転生.epub"), "download.epub");
+        assert_eq!(ascii_filename("say \"hi\".txt"), "say _hi_.txt");
+        assert_eq!(sanitize_filename("../books/novel\n.epub"), "novel.epub");
+    }
+
+    #[test]
+    fn test_file_validators() {
+        let validators = FileValidators {
+            etag: "\"a-1\"".to_string(),
+            last_modified: UNIX_EPOCH + std::time::Duration::from_millis(1_700_000_000_500),
+        };
+        let headers = |name: &'static str, value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(name, HeaderValue::from_str(value).unwrap());
+            headers
+        };
+        assert!(!validators.not_modified(&HeaderMap::new()));
+        assert!(validators.not_modified(&headers("if-none-match", "\"a-1\"")));
+        assert!(validators.not_modified(&headers("if-none-match", "\"b-2\", W/\"a-1\"")));
+        assert!(validators.not_modified(&headers("if-none-match", "*")));
+        assert!(!validators.not_modified(&headers("if-none-match", "\"b-2\"")));
+
+        let date = httpdate::fmt_http_date(validators.last_modified);
+        assert!(validators.not_modified(&headers("if-modified-since", &date)));
+        let earlier =
+            httpdate::fmt_http_date(UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000));
+        assert!(!validators.not_modified(&headers("if-modified-since", &earlier)));
+        assert!(!validators.not_modified(&headers("if-modified-since", "yesterday")));
+        // A stale ETag wins over a matching date
+        let mut both = headers("if-modified-since", &date);
+        both.insert("if-none-match", HeaderValue::from_static("\"b-2\""));
+        assert!(!validators.not_modified(&both));
+
+        let mut response_headers = HeaderMap::new();
+        validators.insert_headers(&mut response_headers);
+        assert_eq!(response_headers["ETag"], "\"a-1\"");
+        assert_eq!(
+            response_headers["Last-Modified"],
+            "Tue, 14 Nov 2023 22:13:20 GMT"
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! Diagnostic bundles for failed dictionary imports.
+//!
+//! When an archive fails to import, its `index.json`, the JSON file that
+//! failed to parse with a snippet around the error, and the error chain are
+//! saved under `DICTS_PATH/import-failures/<id>`. The admin can fetch them to
+//! report broken dictionaries upstream with evidence.
+
+use std::fs::{self, File};
+use std::io::Read;
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path as Path, Utf8PathBuf as PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+use yomitan_format::kv_store::ArchiveJsonFile;
+use zip::ZipArchive;
+
+/// Directory under `DICTS_PATH` holding one directory per failed import
+pub const IMPORT_FAILURES_DIR: &str = "import-failures";
+
+/// Bundles kept; the oldest are removed as new failures come in
+const MAX_BUNDLES: usize = 50;
+
+/// Bytes of JSON kept on each side of a parse error
+const SNIPPET_CONTEXT_BYTES: usize = 300;
+
+const REPORT_FILE: &str = "report.json";
+const INDEX_FILE: &str = "index.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportFailureReport {
+    pub id: Uuid,
+    pub archive: String,
+    pub failed_at: DateTime<Utc>,
+    /// Outermost error first
+    pub error_chain: Vec<String>,
+    /// JSON file in the archive that failed to parse
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// The JSON around the parse error
+    pub snippet: Option<String>,
+    pub has_index: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportFailureBundle {
+    #[serde(flatten)]
+    pub report: ImportFailureReport,
+    /// The archive's `index.json` as it was, which may not be valid JSON
+    pub index_json: Option<String>,
+}
+
+fn failures_dir(dicts_path: &Path) -> PathBuf {
+    dicts_path.join(IMPORT_FAILURES_DIR)
+}
+
+/// Save a bundle for the failed import of `archive_path` and return its report
+pub fn capture(
+    dicts_path: &Path,
+    archive_path: &Path,
+    error: &anyhow::Error,
+) -> Result<ImportFailureReport> {
+    let id = Uuid::new_v4();
+    let dir = failures_dir(dicts_path).join(id.to_string());
+    fs::create_dir_all(&dir).context("Failed to create import failure directory")?;
+
+    // The archive itself may be what's broken, so every read is best effort
+    let mut archive = File::open(archive_path)
+        .ok()
+        .and_then(|file| ZipArchive::new(file).ok());
+    let index_json = archive
+        .as_mut()
+        .and_then(|archive| read_archive_file(archive, INDEX_FILE));
+    if let Some(index_json) = &index_json {
+        fs::write(dir.join(INDEX_FILE), index_json)?;
+    }
+
+    let file = error
+        .downcast_ref::<ArchiveJsonFile>()
+        .map(|file| file.0.clone());
+    let json_error = error.downcast_ref::<serde_json::Error>();
+    let snippet = match (&file, json_error, archive.as_mut()) {
+        (Some(file), Some(json_error), Some(archive)) => read_archive_file(archive, file)
+            .map(|json| snippet_at(&json, json_error.line(), json_error.column())),
+        _ => None,
+    };
+
+    let report = ImportFailureReport {
+        id,
+        archive: archive_path.file_name().unwrap_or_default().to_string(),
+        failed_at: Utc::now(),
+        error_chain: error.chain().map(|e| e.to_string()).collect(),
+        file,
+        line: json_error.map(|e| e.line()),
+        column: json_error.map(|e| e.column()),
+        snippet,
+        has_index: index_json.is_some(),
+    };
+    fs::write(dir.join(REPORT_FILE), serde_json::to_vec_pretty(&report)?)?;
+    prune(dicts_path)?;
+    Ok(report)
+}
+
+fn read_archive_file(archive: &mut ZipArchive<File>, name: &str) -> Option<Vec<u8>> {
+    let mut file = archive.by_name(name).ok()?;
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).ok()?;
+    Some(contents)
+}
+
+/// The text around the 1-based `line` and `column` serde_json reports,
+/// clamped to the file. Works for minified banks, which are a single line.
+fn snippet_at(json: &[u8], line: usize, column: usize) -> String {
+    let line_start = if line <= 1 {
+        0
+    } else {
+        json.iter()
+            .enumerate()
+            .filter(|(_, b)| **b == b'\n')
+            .nth(line - 2)
+            .map_or(json.len(), |(i, _)| i + 1)
+    };
+    let offset = (line_start + column.saturating_sub(1)).min(json.len());
+    let start = offset.saturating_sub(SNIPPET_CONTEXT_BYTES);
+    let end = (offset + SNIPPET_CONTEXT_BYTES).min(json.len());
+    String::from_utf8_lossy(&json[start..end]).into_owned()
+}
+
+/// Reports of the saved bundles, most recent first
+pub fn list(dicts_path: &Path) -> Result<Vec<ImportFailureReport>> {
+    let dir = failures_dir(dicts_path);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut reports = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let report_path = entry?.path().join(REPORT_FILE);
+        match fs::read(&report_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(serde_json::from_slice(&data)?))
+        {
+            Ok(report) => reports.push(report),
+            Err(e) => warn!(?e, ?report_path, "Skipping unreadable import failure"),
+        }
+    }
+    reports.sort_by_key(|report: &ImportFailureReport| std::cmp::Reverse(report.failed_at));
+    Ok(reports)
+}
+
+/// The bundle saved as `id`, if there is one
+pub fn load(dicts_path: &Path, id: Uuid) -> Result<Option<ImportFailureBundle>> {
+    let dir = failures_dir(dicts_path).join(id.to_string());
+    let report_path = dir.join(REPORT_FILE);
+    if !report_path.exists() {
+        return Ok(None);
+    }
+    let report = serde_json::from_slice(&fs::read(&report_path)?)?;
+    let index_path = dir.join(INDEX_FILE);
+    let index_json = if index_path.exists() {
+        Some(String::from_utf8_lossy(&fs::read(&index_path)?).into_owned())
+    } else {
+        None
+    };
+    Ok(Some(ImportFailureBundle { report, index_json }))
+}
+
+fn prune(dicts_path: &Path) -> Result<()> {
+    for report in list(dicts_path)?.into_iter().skip(MAX_BUNDLES) {
+        fs::remove_dir_all(failures_dir(dicts_path).join(report.id.to_string()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    fn test_snippet_at() {
+        let json = b"[\n  [\"a\"],\n  [\"b\" \"c\"]\n]";
+        assert_eq!(snippet_at(json, 3, 8), String::from_utf8_lossy(json));
+
+        let long = format!("[{}!{}]", " ".repeat(1000), " ".repeat(1000));
+        let snippet = snippet_at(long.as_bytes(), 1, 1002);
+        assert_eq!(snippet.len(), SNIPPET_CONTEXT_BYTES * 2);
+        assert_eq!(snippet.as_bytes()[SNIPPET_CONTEXT_BYTES], b'!');
+        assert_eq!(snippet_at(b"[", 5, 5), "[");
+    }
+
+    #[test]
+    fn test_capture_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let dicts_path = Path::from_path(dir.path()).unwrap();
+        let archive_path = dicts_path.join("broken.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&archive_path).unwrap());
+        writer
+            .start_file("index.json", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(br#"{"title": "Broken"}"#).unwrap();
+        writer
+            .start_file("term_bank_1.json", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(br#"[["a", "b"] ["c"]]"#).unwrap();
+        writer.finish().unwrap();
+
+        let error = serde_json::from_str::<serde_json::Value>(r#"[["a", "b"] ["c"]]"#)
+            .context(ArchiveJsonFile("term_bank_1.json".to_string()))
+            .context("Failed to import")
+            .unwrap_err();
+        let report = capture(dicts_path, &archive_path, &error).unwrap();
+        assert_eq!(report.archive, "broken.zip");
+        assert_eq!(report.file.as_deref(), Some("term_bank_1.json"));
+        assert_eq!(report.error_chain.len(), 3);
+        assert_eq!(report.snippet.as_deref(), Some(r#"[["a", "b"] ["c"]]"#));
+
+        let bundle = load(dicts_path, report.id).unwrap().unwrap();
+        assert_eq!(bundle.index_json.as_deref(), Some(r#"{"title": "Broken"}"#));
+        assert_eq!(list(dicts_path).unwrap().len(), 1);
+        assert!(load(dicts_path, Uuid::new_v4()).unwrap().is_none());
+    }
+}
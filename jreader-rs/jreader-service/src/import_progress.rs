@@ -17,6 +17,10 @@ pub struct ImportProgress {
     pub process_id: Option<u32>,
     pub total_chapters: Option<u32>,
     pub current_chapter: Option<u32>,
+    /// Bytes of an upload received so far, for imports that start with one
+    pub bytes_received: Option<u64>,
+    /// Size of the upload, when the client sent it
+    pub bytes_total: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -47,6 +51,8 @@ impl ImportProgress {
             process_id: None,
             total_chapters: None,
             current_chapter: None,
+            bytes_received: None,
+            bytes_total: None,
         }
     }
 
@@ -63,6 +69,12 @@ impl ImportProgress {
         self.updated_at = chrono::Utc::now();
     }
 
+    pub fn set_transfer_progress(&mut self, received: u64, total: Option<u64>) {
+        self.bytes_received = Some(received);
+        self.bytes_total = total;
+        self.updated_at = chrono::Utc::now();
+    }
+
     pub fn set_process_id(&mut self, process_id: u32) {
         debug!(user_id = %self.user_id, process_id = process_id, "Setting process ID");
         self.process_id = Some(process_id);
@@ -205,6 +217,15 @@ impl ImportProgressManager {
         }
     }
 
+    pub async fn set_transfer_progress(&self, import_id: &Uuid, received: u64, total: Option<u64>) {
+        let mut map = self.progress_map.write().await;
+        if let Some(progress) = map.get_mut(import_id) {
+            progress.set_transfer_progress(received, total);
+        } else {
+            warn!(import_id = %import_id, "Attempted to set transfer progress for non-existent import");
+        }
+    }
+
     pub async fn cancel_import(&self, import_id: &Uuid) -> Result<(), String> {
         let mut map = self.progress_map.write().await;
         if let Some(progress) = map.get_mut(import_id) {
@@ -0,0 +1,73 @@
+//! Bunpro vocabulary exports, as CSV.
+//!
+//! Columns are found by their header: the word is in the first of `Vocab`,
+//! `Vocabulary`, `Japanese` or `Term`, and its reading in `Reading` or `Kana`
+//! if there is one. Every word in the file counts as known, so export only the
+//! reviews that should.
+
+use anyhow::{anyhow, Context, Result};
+use wana_kana::ConvertJapanese;
+
+use super::KnownWord;
+
+const TERM_COLUMNS: [&str; 4] = ["vocab", "vocabulary", "japanese", "term"];
+const READING_COLUMNS: [&str; 2] = ["reading", "kana"];
+
+pub fn parse_export(data: &[u8]) -> Result<Vec<KnownWord>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data);
+    let headers: Vec<String> = reader
+        .headers()
+        .context("Failed to read CSV header")?
+        .iter()
+        .map(|h| h.trim_start_matches('\u{feff}').to_lowercase())
+        .collect();
+    let column = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| headers.iter().position(|h| h == name))
+    };
+    let term_column = column(&TERM_COLUMNS)
+        .ok_or_else(|| anyhow!("Not a Bunpro export: no vocabulary column in the header"))?;
+    let reading_column = column(&READING_COLUMNS);
+
+    let mut words = Vec::new();
+    for row in reader.records() {
+        let row = row.context("Failed to parse CSV")?;
+        let Some(term) = row.get(term_column).filter(|t| !t.is_empty()) else {
+            continue;
+        };
+        // Words written in kana alone have no separate reading
+        let reading = reading_column
+            .and_then(|i| row.get(i))
+            .filter(|r| !r.is_empty())
+            .unwrap_or(term);
+        words.push(KnownWord {
+            term: term.to_string(),
+            reading: reading.to_hiragana(),
+        });
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_export() {
+        let words = parse_export(
+            "\u{feff}Vocab,Reading,Meaning\n食べる,タベル,to eat\nおやつ,,snack\n,,\n".as_bytes(),
+        )
+        .unwrap();
+        let pairs: Vec<(&str, &str)> = words
+            .iter()
+            .map(|w| (w.term.as_str(), w.reading.as_str()))
+            .collect();
+        assert_eq!(pairs, [("食べる", "たべる"), ("おやつ", "おやつ")]);
+
+        assert!(parse_export(b"Meaning,Notes\nto eat,\n").is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! jpdb review exports (Settings → "Export your reviews" as JSON).
+//!
+//! The export lists every vocabulary card with its reviews, oldest first. A
+//! word is known when the last review of one of its cards passed.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use super::KnownWord;
+
+/// Grades of a review the card was remembered in
+const PASSING_GRADES: [&str; 5] = ["okay", "hard", "easy", "known", "pass"];
+
+#[derive(Deserialize)]
+struct Export {
+    #[serde(default)]
+    cards_vocabulary_jp_en: Vec<Card>,
+    #[serde(default)]
+    cards_vocabulary_en_jp: Vec<Card>,
+}
+
+#[derive(Deserialize)]
+struct Card {
+    spelling: String,
+    reading: String,
+    #[serde(default)]
+    reviews: Vec<Review>,
+}
+
+#[derive(Deserialize)]
+struct Review {
+    grade: String,
+}
+
+pub fn parse_export(data: &[u8]) -> Result<Vec<KnownWord>> {
+    let export: Export = serde_json::from_slice(data).context("Not a jpdb review export")?;
+    Ok(export
+        .cards_vocabulary_jp_en
+        .into_iter()
+        .chain(export.cards_vocabulary_en_jp)
+        .filter(|card| {
+            card.reviews
+                .last()
+                .is_some_and(|review| PASSING_GRADES.contains(&review.grade.as_str()))
+        })
+        .map(|card| KnownWord {
+            term: card.spelling,
+            reading: card.reading,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_export() {
+        let words = parse_export(
+            r#"{
+                "cards_vocabulary_jp_en": [
+                    {"vid": 1358280, "spelling": "食べる", "reading": "たべる", "reviews": [
+                        {"timestamp": 1650000000, "grade": "unknown", "from_anki": false},
+                        {"timestamp": 1650086400, "grade": "okay", "from_anki": false}
+                    ]},
+                    {"vid": 1375610, "spelling": "飲む", "reading": "のむ", "reviews": [
+                        {"timestamp": 1650000000, "grade": "okay", "from_anki": false},
+                        {"timestamp": 1650086400, "grade": "fail", "from_anki": false}
+                    ]},
+                    {"vid": 1002050, "spelling": "新しい", "reading": "あたらしい", "reviews": []}
+                ],
+                "cards_vocabulary_en_jp": [
+                    {"vid": 1358280, "spelling": "食べる", "reading": "たべる", "reviews": [
+                        {"timestamp": 1650000000, "grade": "pass", "from_anki": false}
+                    ]}
+                ],
+                "cards_kanji_keyword_char": []
+            }"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let pairs: Vec<(&str, &str)> = words
+            .iter()
+            .map(|w| (w.term.as_str(), w.reading.as_str()))
+            .collect();
+        // The same word through both card types
+        assert_eq!(pairs, [("食べる", "たべる"), ("食べる", "たべる")]);
+
+        assert!(parse_export(b"term,reading").is_err());
+    }
+}
@@ -0,0 +1,422 @@
+//! Integrations with learning services a user has an account with.
+//!
+//! A user connects a service with an API (WaniKani) by saving their API token
+//! for it on the preferences page. The words the service says they know are
+//! synced into their known words (the `"Known Words"` table, tagged with the
+//! service) when connecting, on request, and every `INTEGRATION_SYNC_MINUTES`.
+//! Services without one (jpdb, Bunpro) are imported from an export file
+//! instead. Each sync or import only replaces the words of its own service.
+//! Lookups mark the terms found that the user knows.
+//!
+//! Tokens are kept in the `"User Integrations"` table along with the outcome
+//! of the last sync or import, and are never sent back to clients.
+
+pub mod bunpro;
+pub mod jpdb;
+pub mod wanikani;
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail, Result};
+use deadpool_postgres::Pool;
+use serde::Serialize;
+use tokio_postgres::Row;
+use tracing::{info, warn};
+
+use self::wanikani::WaniKaniClient;
+
+pub const DEFAULT_SYNC_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    WaniKani,
+    Jpdb,
+    Bunpro,
+}
+
+impl Provider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::WaniKani => "wanikani",
+            Self::Jpdb => "jpdb",
+            Self::Bunpro => "bunpro",
+        }
+    }
+
+    /// Whether words are synced with an API token rather than imported from
+    /// an export file
+    pub fn has_api(&self) -> bool {
+        matches!(self, Self::WaniKani)
+    }
+
+    /// The words of an export file of the service
+    pub fn parse_export(&self, data: &[u8]) -> Result<Vec<KnownWord>> {
+        match self {
+            Self::Jpdb => jpdb::parse_export(data),
+            Self::Bunpro => bunpro::parse_export(data),
+            Self::WaniKani => Err(anyhow!("{self} is synced with an API token")),
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Provider {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "wanikani" => Ok(Self::WaniKani),
+            "jpdb" => Ok(Self::Jpdb),
+            "bunpro" => Ok(Self::Bunpro),
+            _ => Err(anyhow!("Unknown integration {s:?}")),
+        }
+    }
+}
+
+/// A word a user knows, with the reading it is known by
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KnownWord {
+    pub term: String,
+    pub reading: String,
+}
+
+/// A connected integration and how its last sync went
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrationStatus {
+    pub provider: Provider,
+    pub connected_at: chrono::DateTime<chrono::Utc>,
+    pub synced_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Words synced by the last successful sync
+    pub known_words: i64,
+    /// Why the last sync failed, cleared by the next successful one
+    pub last_error: Option<String>,
+}
+
+fn row_to_status(row: &Row) -> Result<IntegrationStatus> {
+    Ok(IntegrationStatus {
+        provider: row.get::<_, String>("provider").parse()?,
+        connected_at: row.get::<_, SystemTime>("connected_at").into(),
+        synced_at: row
+            .get::<_, Option<SystemTime>>("synced_at")
+            .map(Into::into),
+        known_words: row.get("known_words"),
+        last_error: row.get("last_error"),
+    })
+}
+
+const STATUS_COLUMNS: &str =
+    r#""provider", "connected_at", "synced_at", "known_words", "last_error""#;
+
+/// Integration tokens and the known words synced with them
+pub struct IntegrationsSupabase {
+    pool: Option<Arc<Pool>>,
+    wanikani: WaniKaniClient,
+}
+
+impl IntegrationsSupabase {
+    pub fn new(pool: Option<Arc<Pool>>) -> Result<Self> {
+        Ok(Self {
+            pool,
+            wanikani: WaniKaniClient::new()?,
+        })
+    }
+
+    fn pool(&self) -> Result<&Pool> {
+        self.pool
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Database not available"))
+    }
+
+    /// Check `token` with the service and save it, replacing the user's
+    /// previous token. Returns the account name the token belongs to.
+    pub async fn connect(&self, user_id: &str, provider: Provider, token: &str) -> Result<String> {
+        let account = match provider {
+            Provider::WaniKani => self.wanikani.user(token).await?.username,
+            Provider::Jpdb | Provider::Bunpro => {
+                bail!("{provider} is imported from an export file")
+            }
+        };
+        let client = self.pool()?.get().await?;
+        client
+            .execute(
+                r#"INSERT INTO "public"."User Integrations"
+                   ("user_id", "provider", "token", "connected_at", "known_words")
+                   VALUES ($1, $2, $3, $4, 0)
+                   ON CONFLICT ("user_id", "provider") DO UPDATE SET
+                   "token" = $3,
+                   "connected_at" = $4,
+                   "last_error" = NULL"#,
+                &[&user_id, &provider.as_str(), &token, &SystemTime::now()],
+            )
+            .await?;
+        Ok(account)
+    }
+
+    /// Remove the user's token and the words synced or imported from the
+    /// service. Returns false if the integration wasn't connected.
+    pub async fn disconnect(&self, user_id: &str, provider: Provider) -> Result<bool> {
+        let mut client = self.pool()?.get().await?;
+        let transaction = client.transaction().await?;
+        transaction
+            .execute(
+                r#"DELETE FROM "public"."Known Words" WHERE "user_id" = $1 AND "source" = $2"#,
+                &[&user_id, &provider.as_str()],
+            )
+            .await?;
+        let removed = transaction
+            .execute(
+                r#"DELETE FROM "public"."User Integrations" WHERE "user_id" = $1 AND "provider" = $2"#,
+                &[&user_id, &provider.as_str()],
+            )
+            .await?;
+        transaction.commit().await?;
+        Ok(removed > 0)
+    }
+
+    pub async fn statuses(&self, user_id: &str) -> Result<Vec<IntegrationStatus>> {
+        let client = self.pool()?.get().await?;
+        let rows = client
+            .query(
+                &format!(
+                    r#"SELECT {STATUS_COLUMNS} FROM "public"."User Integrations"
+                       WHERE "user_id" = $1 ORDER BY "provider""#
+                ),
+                &[&user_id],
+            )
+            .await?;
+        rows.iter().map(row_to_status).collect()
+    }
+
+    pub async fn status(
+        &self,
+        user_id: &str,
+        provider: Provider,
+    ) -> Result<Option<IntegrationStatus>> {
+        let client = self.pool()?.get().await?;
+        let row = client
+            .query_opt(
+                &format!(
+                    r#"SELECT {STATUS_COLUMNS} FROM "public"."User Integrations"
+                       WHERE "user_id" = $1 AND "provider" = $2"#
+                ),
+                &[&user_id, &provider.as_str()],
+            )
+            .await?;
+        row.as_ref().map(row_to_status).transpose()
+    }
+
+    /// Those of `terms` the user knows, from any integration
+    pub async fn known_terms(&self, user_id: &str, terms: &[&str]) -> Result<HashSet<String>> {
+        if terms.is_empty() {
+            return Ok(HashSet::new());
+        }
+        let client = self.pool()?.get().await?;
+        let rows = client
+            .query(
+                r#"SELECT DISTINCT "term" FROM "public"."Known Words"
+                   WHERE "user_id" = $1 AND "term" = ANY($2)"#,
+                &[&user_id, &terms],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Fetch the words the user knows from the service and replace the ones
+    /// synced before. The outcome is recorded in the user's status; None if
+    /// the integration isn't connected.
+    pub async fn sync(&self, user_id: &str, provider: Provider) -> Result<Option<usize>> {
+        let client = self.pool()?.get().await?;
+        let Some(row) = client
+            .query_opt(
+                r#"SELECT "token" FROM "public"."User Integrations"
+                   WHERE "user_id" = $1 AND "provider" = $2"#,
+                &[&user_id, &provider.as_str()],
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+        drop(client);
+        // Imported services have no token
+        let Some(token) = row.get::<_, Option<String>>(0) else {
+            bail!("{provider} is imported from an export file");
+        };
+
+        let result = match provider {
+            Provider::WaniKani => self.wanikani.known_words(&token).await,
+            Provider::Jpdb | Provider::Bunpro => {
+                Err(anyhow!("{provider} is imported from an export file"))
+            }
+        };
+        let result = match result {
+            Ok(words) => self.replace_known_words(user_id, provider, words).await,
+            Err(e) => Err(e),
+        };
+        self.record_sync(user_id, provider, &result).await?;
+        result.map(Some)
+    }
+
+    /// Replace the words the user imported from a service before with the
+    /// words of an export file of it, returning how many there are
+    pub async fn import(&self, user_id: &str, provider: Provider, data: &[u8]) -> Result<usize> {
+        let words = provider.parse_export(data)?;
+        let client = self.pool()?.get().await?;
+        client
+            .execute(
+                r#"INSERT INTO "public"."User Integrations"
+                   ("user_id", "provider", "connected_at", "known_words")
+                   VALUES ($1, $2, $3, 0)
+                   ON CONFLICT ("user_id", "provider") DO NOTHING"#,
+                &[&user_id, &provider.as_str(), &SystemTime::now()],
+            )
+            .await?;
+        drop(client);
+
+        let result = self.replace_known_words(user_id, provider, words).await;
+        self.record_sync(user_id, provider, &result).await?;
+        result
+    }
+
+    async fn replace_known_words(
+        &self,
+        user_id: &str,
+        provider: Provider,
+        words: Vec<KnownWord>,
+    ) -> Result<usize> {
+        let unique: HashSet<KnownWord> = words.into_iter().collect();
+        let terms: Vec<&str> = unique.iter().map(|w| w.term.as_str()).collect();
+        let readings: Vec<&str> = unique.iter().map(|w| w.reading.as_str()).collect();
+
+        let mut client = self.pool()?.get().await?;
+        let transaction = client.transaction().await?;
+        transaction
+            .execute(
+                r#"DELETE FROM "public"."Known Words" WHERE "user_id" = $1 AND "source" = $2"#,
+                &[&user_id, &provider.as_str()],
+            )
+            .await?;
+        transaction
+            .execute(
+                r#"INSERT INTO "public"."Known Words" ("user_id", "source", "term", "reading")
+                   SELECT $1, $2, * FROM unnest($3::text[], $4::text[])"#,
+                &[&user_id, &provider.as_str(), &terms, &readings],
+            )
+            .await?;
+        transaction.commit().await?;
+        Ok(unique.len())
+    }
+
+    // Keep the outcome of a sync or import in the user's status
+    async fn record_sync(
+        &self,
+        user_id: &str,
+        provider: Provider,
+        result: &Result<usize>,
+    ) -> Result<()> {
+        let client = self.pool()?.get().await?;
+        match result {
+            Ok(count) => {
+                client
+                    .execute(
+                        r#"UPDATE "public"."User Integrations"
+                           SET "synced_at" = $3, "known_words" = $4, "last_error" = NULL
+                           WHERE "user_id" = $1 AND "provider" = $2"#,
+                        &[
+                            &user_id,
+                            &provider.as_str(),
+                            &SystemTime::now(),
+                            &(*count as i64),
+                        ],
+                    )
+                    .await?;
+            }
+            Err(e) => {
+                client
+                    .execute(
+                        r#"UPDATE "public"."User Integrations" SET "last_error" = $3
+                           WHERE "user_id" = $1 AND "provider" = $2"#,
+                        &[&user_id, &provider.as_str(), &format!("{e:#}")],
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sync every integration with a token of every user, one after the other
+    pub async fn sync_all(&self) -> Result<()> {
+        let client = self.pool()?.get().await?;
+        let rows = client
+            .query(
+                r#"SELECT "user_id", "provider" FROM "public"."User Integrations"
+                   WHERE "token" IS NOT NULL"#,
+                &[],
+            )
+            .await?;
+        drop(client);
+        for row in rows {
+            let user_id: String = row.get(0);
+            let provider: Provider = match row.get::<_, String>(1).parse() {
+                Ok(provider) => provider,
+                Err(e) => {
+                    warn!(?e, user_id, "⚠️ Skipping unknown integration");
+                    continue;
+                }
+            };
+            match self.sync(&user_id, provider).await {
+                Ok(count) => info!(user_id, %provider, ?count, "✅ Synced known words"),
+                Err(e) => warn!(user_id, %provider, "⚠️ Failed to sync known words: {e:#}"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Spawn a background task that syncs all integrations every `interval`.
+    /// Does nothing when there is no database to keep tokens in.
+    pub fn spawn_sync_task(self: &Arc<Self>, interval: Duration) {
+        if self.pool.is_none() {
+            warn!("⚠️ No database pool, integrations are disabled");
+            return;
+        }
+
+        let integrations = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // The first tick completes immediately, syncs start after an interval
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = integrations.sync_all().await {
+                    warn!("⚠️ Failed to sync integrations: {e:#}");
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_names() {
+        let provider: Provider = "wanikani".parse().unwrap();
+        assert_eq!(provider, Provider::WaniKani);
+        assert_eq!(provider.to_string(), "wanikani");
+        assert_eq!(serde_json::to_value(provider).unwrap(), "wanikani");
+        assert!("WaniKani".parse::<Provider>().is_err());
+        assert!(provider.has_api());
+        assert!(!Provider::Jpdb.has_api());
+    }
+}
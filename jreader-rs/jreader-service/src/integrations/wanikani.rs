@@ -0,0 +1,284 @@
+//! Client of the WaniKani API (v2), used to find the vocabulary a user has
+//! passed Guru on.
+//!
+//! WaniKani allows 60 requests a minute per token. Requests are spaced out to
+//! stay under that, and a 429 is retried once the limit resets.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use super::KnownWord;
+
+const API_URL: &str = "https://api.wanikani.com/v2";
+const API_REVISION: &str = "20170710";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_RATE_LIMIT_RETRIES: usize = 3;
+/// SRS stages from Guru 1 up to Burned
+const KNOWN_SRS_STAGES: &str = "5,6,7,8,9";
+const VOCABULARY_TYPES: &str = "vocabulary,kana_vocabulary";
+/// Subjects the API returns per page, and so the most ids to ask for at once
+const SUBJECTS_PER_PAGE: usize = 1000;
+
+/// Hands out request slots at most one per `interval`
+struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next: Mutex::new(None),
+        }
+    }
+
+    /// When the request made at `now` may be sent, reserving that slot
+    async fn reserve(&self, now: Instant) -> Instant {
+        let mut next = self.next.lock().await;
+        let slot = next.map_or(now, |next| next.max(now));
+        *next = Some(slot + self.interval);
+        slot
+    }
+
+    async fn wait(&self) {
+        tokio::time::sleep_until(self.reserve(Instant::now()).await).await;
+    }
+}
+
+#[derive(Deserialize)]
+struct Resource<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct Collection<T> {
+    pages: Pages,
+    data: Vec<Resource<T>>,
+}
+
+#[derive(Deserialize)]
+struct Pages {
+    next_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct User {
+    pub username: String,
+    pub level: u32,
+}
+
+#[derive(Deserialize)]
+struct Assignment {
+    subject_id: u64,
+}
+
+#[derive(Deserialize)]
+struct Subject {
+    characters: Option<String>,
+    /// Missing for kana-only vocabulary
+    #[serde(default)]
+    readings: Vec<SubjectReading>,
+}
+
+#[derive(Deserialize)]
+struct SubjectReading {
+    reading: String,
+    accepted_answer: bool,
+}
+
+/// The words of vocabulary subjects, with each accepted reading. Kana-only
+/// vocabulary is read as written.
+fn subject_words(subjects: Vec<Resource<Subject>>) -> Vec<KnownWord> {
+    let mut words = Vec::new();
+    for Resource { data: subject } in subjects {
+        let Some(term) = subject.characters.filter(|c| !c.is_empty()) else {
+            continue;
+        };
+        let mut readings: Vec<String> = subject
+            .readings
+            .into_iter()
+            .filter(|r| r.accepted_answer)
+            .map(|r| r.reading)
+            .collect();
+        if readings.is_empty() {
+            readings.push(term.clone());
+        }
+        words.extend(readings.into_iter().map(|reading| KnownWord {
+            term: term.clone(),
+            reading,
+        }));
+    }
+    words
+}
+
+pub struct WaniKaniClient {
+    client: reqwest::Client,
+    limiter: RateLimiter,
+}
+
+impl WaniKaniClient {
+    pub fn new() -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to create WaniKani client")?;
+        Ok(Self {
+            client,
+            limiter: RateLimiter::new(REQUEST_INTERVAL),
+        })
+    }
+
+    async fn get<T: DeserializeOwned>(&self, token: &str, url: &str) -> Result<T> {
+        for _ in 0..=MAX_RATE_LIMIT_RETRIES {
+            self.limiter.wait().await;
+            let response = self
+                .client
+                .get(url)
+                .bearer_auth(token)
+                .header("Wanikani-Revision", API_REVISION)
+                .send()
+                .await
+                .context("Failed to reach WaniKani")?;
+            match response.status() {
+                StatusCode::TOO_MANY_REQUESTS => {
+                    // Seconds since the epoch at which the limit resets
+                    let reset = response
+                        .headers()
+                        .get("RateLimit-Reset")
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.parse::<u64>().ok());
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs();
+                    let wait = reset.map_or(60, |reset| reset.saturating_sub(now).clamp(1, 60));
+                    warn!(wait, "⚠️ WaniKani rate limit reached, waiting");
+                    tokio::time::sleep(Duration::from_secs(wait)).await;
+                }
+                StatusCode::UNAUTHORIZED => bail!("WaniKani rejected the API token"),
+                status if !status.is_success() => {
+                    bail!("WaniKani returned {status} for {url}")
+                }
+                _ => {
+                    return response
+                        .json()
+                        .await
+                        .context("Invalid response from WaniKani")
+                }
+            }
+        }
+        Err(anyhow!("WaniKani kept rate limiting requests"))
+    }
+
+    /// Every item of a paginated collection, starting at `url`
+    async fn get_all<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        url: String,
+    ) -> Result<Vec<Resource<T>>> {
+        let mut items = Vec::new();
+        let mut next_url = Some(url);
+        while let Some(url) = next_url {
+            let page: Collection<T> = self.get(token, &url).await?;
+            items.extend(page.data);
+            next_url = page.pages.next_url;
+        }
+        Ok(items)
+    }
+
+    /// The owner of `token`, which fails if the token isn't valid
+    pub async fn user(&self, token: &str) -> Result<User> {
+        let user: Resource<User> = self.get(token, &format!("{API_URL}/user")).await?;
+        Ok(user.data)
+    }
+
+    /// The vocabulary the owner of `token` has at Guru or above, burned
+    /// items included
+    pub async fn known_words(&self, token: &str) -> Result<Vec<KnownWord>> {
+        let assignments: Vec<Resource<Assignment>> = self
+            .get_all(
+                token,
+                format!(
+                    "{API_URL}/assignments?srs_stages={KNOWN_SRS_STAGES}&subject_types={VOCABULARY_TYPES}&hidden=false"
+                ),
+            )
+            .await?;
+        let subject_ids: Vec<u64> = assignments.iter().map(|a| a.data.subject_id).collect();
+        debug!(count = subject_ids.len(), "Fetched WaniKani assignments");
+
+        let mut words = Vec::new();
+        for ids in subject_ids.chunks(SUBJECTS_PER_PAGE) {
+            let ids: Vec<String> = ids.iter().map(u64::to_string).collect();
+            let subjects = self
+                .get_all(token, format!("{API_URL}/subjects?ids={}", ids.join(",")))
+                .await?;
+            words.extend(subject_words(subjects));
+        }
+        Ok(words)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subject_words() {
+        let page: Collection<Subject> = serde_json::from_str(
+            r#"{
+                "object": "collection",
+                "pages": {"per_page": 1000, "next_url": null, "previous_url": null},
+                "data": [
+                    {"id": 2467, "object": "vocabulary", "data": {
+                        "characters": "一つ",
+                        "readings": [
+                            {"primary": true, "reading": "ひとつ", "accepted_answer": true}
+                        ]
+                    }},
+                    {"id": 2480, "object": "vocabulary", "data": {
+                        "characters": "上る",
+                        "readings": [
+                            {"primary": true, "reading": "のぼる", "accepted_answer": true},
+                            {"primary": false, "reading": "あがる", "accepted_answer": false}
+                        ]
+                    }},
+                    {"id": 9210, "object": "kana_vocabulary", "data": {
+                        "characters": "おやつ"
+                    }}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert!(page.pages.next_url.is_none());
+
+        let words = subject_words(page.data);
+        let pairs: Vec<(&str, &str)> = words
+            .iter()
+            .map(|w| (w.term.as_str(), w.reading.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [("一つ", "ひとつ"), ("上る", "のぼる"), ("おやつ", "おやつ")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_spaces_requests() {
+        let limiter = RateLimiter::new(Duration::from_secs(1));
+        let now = Instant::now();
+        assert_eq!(limiter.reserve(now).await, now);
+        assert_eq!(limiter.reserve(now).await, now + Duration::from_secs(1));
+        // A request long after the last one goes right away
+        let later = now + Duration::from_secs(10);
+        assert_eq!(limiter.reserve(later).await, later);
+    }
+}
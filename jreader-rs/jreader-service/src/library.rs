@@ -0,0 +1,909 @@
+//! Access to a user's uploaded books and the background jobs that process them.
+//!
+//! The frontend extracts every uploaded EPUB into `UPLOADS_DIR/{user_id}/{upload_id}/`,
+//! so a user's library is simply the set of directories under their user id.
+
+use anyhow::{Context, Result};
+use ego_tree::iter::Edge;
+use scraper::{Html, Node, Selector};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use unicode_normalization::UnicodeNormalization;
+use uuid::Uuid;
+use vibrato::tokenizer::worker::Worker;
+
+use crate::epub::EpubSource;
+use crate::mecab::TokenFeature;
+
+/// Elements whose text is not part of the readable content. Ruby annotations
+/// are skipped so furigana doesn't get counted next to its base text.
+const SKIPPED_ELEMENTS: &[&str] = &["rt", "rp", "script", "style", "head", "title"];
+
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "div",
+    "li",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "section",
+    "tr",
+];
+
+/// Parts of speech that never make sense in a frequency list
+const SKIPPED_POS: &[&str] = &["記号", "補助記号", "空白", "助詞", "助動詞"];
+
+#[derive(Debug, Clone)]
+pub struct LibraryBook {
+    pub upload_id: String,
+    pub path: PathBuf,
+}
+
+/// Directory holding all books of `user_id`
+pub fn user_library_dir(uploads_dir: &Path, user_id: &str) -> Result<PathBuf> {
+    if user_id.is_empty() || user_id == "." || user_id == ".." || user_id.contains(['/', '\\']) {
+        anyhow::bail!("Invalid user id for library path: {user_id:?}");
+    }
+    Ok(uploads_dir.join(user_id))
+}
+
+/// Books in a user's library, sorted by upload id. A missing directory is an
+/// empty library.
+pub fn list_books(library_dir: &Path) -> Result<Vec<LibraryBook>> {
+    if !library_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut books = Vec::new();
+    for entry in std::fs::read_dir(library_dir)
+        .with_context(|| format!("Failed to read library: {}", library_dir.display()))?
+    {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type()?.is_dir() && !name.starts_with('.') {
+            books.push(LibraryBook {
+                upload_id: name,
+                path: entry.path(),
+            });
+        }
+    }
+    books.sort_by(|a, b| a.upload_id.cmp(&b.upload_id));
+    Ok(books)
+}
+
+/// All (X)HTML documents of an extracted book, sorted by path
+pub fn book_documents(book_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut documents = Vec::new();
+    let mut pending = vec![book_dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read book directory: {}", dir.display()))?
+        {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("xhtml" | "html" | "htm")
+            ) {
+                documents.push(path);
+            }
+        }
+    }
+    documents.sort();
+    Ok(documents)
+}
+
+/// Title from the book's OPF package document, if there is one
+pub fn book_title(book_dir: &Path) -> Option<String> {
+    let mut pending = vec![book_dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir).ok()?.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|e| e == "opf") {
+                let opf = std::fs::read_to_string(&path).ok()?;
+                let start = opf.find("<dc:title")?;
+                let content_start = start + opf[start..].find('>')? + 1;
+                let content_end = content_start + opf[content_start..].find("</dc:title>")?;
+                let title = opf[content_start..content_end].trim();
+                return (!title.is_empty()).then(|| title.to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Content hash of a book: a hash of the hashes of all its files, sorted.
+/// File names and zip details are left out, so an uploaded EPUB hashes the
+/// same as the book once extracted into the library.
+fn combine_file_hashes(mut hashes: Vec<[u8; 32]>) -> String {
+    hashes.sort_unstable();
+    let mut hasher = Sha256::new();
+    for hash in &hashes {
+        hasher.update(hash);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Content hash of an extracted book
+pub fn book_content_hash(book_dir: &Path) -> Result<String> {
+    let mut hashes = Vec::new();
+    let mut pending = vec![book_dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read book directory: {}", dir.display()))?
+        {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                let data = std::fs::read(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                hashes.push(Sha256::digest(&data).into());
+            }
+        }
+    }
+    Ok(combine_file_hashes(hashes))
+}
+
+/// Content hash of an EPUB file, the same as [`book_content_hash`] of the
+/// book extracted
+pub fn epub_content_hash(epub_path: &Path) -> Result<String> {
+    let file = std::fs::File::open(epub_path)
+        .with_context(|| format!("Failed to open {}", epub_path.display()))?;
+    let mut archive = zip::ZipArchive::new(file)?;
+    let mut hashes = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut entry, &mut hasher)?;
+        hashes.push(hasher.finalize().into());
+    }
+    Ok(combine_file_hashes(hashes))
+}
+
+/// A title or author folded for matching: full-width characters, case,
+/// spacing and punctuation are ignored
+pub fn normalize_for_match(s: &str) -> String {
+    s.nfkc()
+        .flat_map(char::to_lowercase)
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+/// Whether two books look like the same book from their title and author.
+/// Authors are only compared when both books have one.
+pub fn same_title_and_author(
+    (title, author): (&str, &str),
+    (other_title, other_author): (&str, &str),
+) -> bool {
+    let title = normalize_for_match(title);
+    let (author, other_author) = (
+        normalize_for_match(author),
+        normalize_for_match(other_author),
+    );
+    !title.is_empty()
+        && title == normalize_for_match(other_title)
+        && (author.is_empty() || other_author.is_empty() || author == other_author)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateMatch {
+    /// Exactly the same files
+    Content,
+    /// Another copy or edition, going by title and author
+    TitleAndAuthor,
+}
+
+/// A book of the library that an upload duplicates
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateBook {
+    pub upload_id: String,
+    pub title: String,
+    pub author: String,
+    pub matched_by: DuplicateMatch,
+}
+
+/// The book of `books` that has the same content as an upload, or failing
+/// that the same title and author. `content_hashes` are the hashes known by
+/// upload id; the ones computed along the way are added to it.
+pub fn find_duplicate(
+    books: &[LibraryBook],
+    content_hash: &str,
+    title_and_author: (&str, &str),
+    content_hashes: &mut HashMap<String, String>,
+) -> Result<Option<DuplicateBook>> {
+    for book in books {
+        let hash = match content_hashes.get(&book.upload_id) {
+            Some(hash) => hash,
+            None => {
+                let hash = book_content_hash(&book.path)?;
+                content_hashes.entry(book.upload_id.clone()).or_insert(hash)
+            }
+        };
+        if hash == content_hash {
+            let source = EpubSource::open_dir(&book.path).ok();
+            return Ok(Some(DuplicateBook {
+                upload_id: book.upload_id.clone(),
+                title: source.as_ref().map(|s| s.title.clone()).unwrap_or_default(),
+                author: source.map(|s| s.creators.join(", ")).unwrap_or_default(),
+                matched_by: DuplicateMatch::Content,
+            }));
+        }
+    }
+
+    for book in books {
+        // Books that can't be read as EPUBs can't be compared
+        let Ok(source) = EpubSource::open_dir(&book.path) else {
+            continue;
+        };
+        // Uploads only report one of the authors
+        let same_book = if source.creators.is_empty() {
+            same_title_and_author(title_and_author, (&source.title, ""))
+        } else {
+            source
+                .creators
+                .iter()
+                .any(|creator| same_title_and_author(title_and_author, (&source.title, creator)))
+        };
+        if same_book {
+            return Ok(Some(DuplicateBook {
+                upload_id: book.upload_id.clone(),
+                author: source.creators.join(", "),
+                title: source.title,
+                matched_by: DuplicateMatch::TitleAndAuthor,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Readable text of an (X)HTML document, with a line break after every
+/// block-level element
+pub fn html_to_text(html: &str) -> String {
+    let document = Html::parse_document(html);
+    let mut text = String::new();
+    let mut skip_depth = 0;
+    for edge in document.tree.root().traverse() {
+        match edge {
+            Edge::Open(node) => match node.value() {
+                Node::Element(e) if SKIPPED_ELEMENTS.contains(&e.name()) => skip_depth += 1,
+                Node::Element(e) if e.name() == "br" => text.push('\n'),
+                Node::Text(t) if skip_depth == 0 => text.push_str(t),
+                _ => {}
+            },
+            Edge::Close(node) => match node.value() {
+                Node::Element(e) if SKIPPED_ELEMENTS.contains(&e.name()) => skip_depth -= 1,
+                Node::Element(e) if BLOCK_ELEMENTS.contains(&e.name()) => text.push('\n'),
+                _ => {}
+            },
+        }
+    }
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Text of the first heading of a document, falling back to its `<title>`
+pub fn document_title(html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    ["h1", "h2", "h3", "title"].iter().find_map(|tag| {
+        let selector = Selector::parse(tag).ok()?;
+        let element = document.select(&selector).next()?;
+        let title = element
+            .descendants()
+            .filter_map(|n| {
+                let skipped = n.ancestors().any(|a| {
+                    a.value()
+                        .as_element()
+                        .is_some_and(|e| matches!(e.name(), "rt" | "rp"))
+                });
+                n.value()
+                    .as_text()
+                    .filter(|_| !skipped)
+                    .map(|t| t.to_string())
+            })
+            .collect::<String>();
+        let title = title.trim();
+        (!title.is_empty()).then(|| title.to_string())
+    })
+}
+
+/// Split text into sentence-sized chunks so the tokenizer never sees a whole chapter
+pub fn split_sentences(text: &str) -> impl Iterator<Item = &str> {
+    text.split_inclusive(['\n', '。', '！', '？'])
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+}
+
+/// A term of a library frequency list
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryFrequencyEntry {
+    pub term: String,
+    pub reading: Option<String>,
+    pub count: u64,
+    pub book_count: u32,
+}
+
+/// How often a term appears across a user's library, as shown in lookups
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryTermCount {
+    pub count: u64,
+    pub book_count: u32,
+}
+
+/// Accumulates term counts book by book
+#[derive(Debug, Default)]
+pub struct FrequencyCounter {
+    entries: HashMap<String, LibraryFrequencyEntry>,
+    current_book: HashMap<String, u64>,
+}
+
+impl FrequencyCounter {
+    pub fn add_token(&mut self, feature: &TokenFeature) {
+        if feature
+            .pos
+            .as_deref()
+            .is_some_and(|pos| SKIPPED_POS.contains(&pos))
+        {
+            return;
+        }
+        let Some(term) = feature
+            .dictionary_form
+            .as_ref()
+            .or(feature.surface_form.as_ref())
+            .filter(|t| !t.trim().is_empty())
+        else {
+            return;
+        };
+
+        *self.current_book.entry(term.clone()).or_default() += 1;
+        let entry = self
+            .entries
+            .entry(term.clone())
+            .or_insert_with(|| LibraryFrequencyEntry {
+                term: term.clone(),
+                reading: None,
+                count: 0,
+                book_count: 0,
+            });
+        // The tokenizer only gives the reading of the surface form, which is
+        // the reading of the term only where it appears in its dictionary form
+        if entry.reading.is_none() && feature.surface_form.as_ref() == Some(term) {
+            entry.reading = feature.reading.clone();
+        }
+    }
+
+    /// Close the current book, so that the next tokens count towards a new one
+    pub fn finish_book(&mut self) {
+        for (term, count) in self.current_book.drain() {
+            if let Some(entry) = self.entries.get_mut(&term) {
+                entry.count += count;
+                entry.book_count += 1;
+            }
+        }
+    }
+
+    /// Entries sorted by descending count, then by term
+    pub fn into_entries(mut self) -> Vec<LibraryFrequencyEntry> {
+        self.finish_book();
+        let mut entries: Vec<_> = self.entries.into_values().collect();
+        entries.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.term.cmp(&b.term)));
+        entries
+    }
+}
+
+/// Tokenize all documents of `book` into `counter`, closing the book at the end
+pub fn count_book_terms(
+    worker: &mut Worker,
+    book: &LibraryBook,
+    counter: &mut FrequencyCounter,
+) -> Result<()> {
+    for document in book_documents(&book.path)? {
+        let html = std::fs::read_to_string(&document)
+            .with_context(|| format!("Failed to read {}", document.display()))?;
+        let text = html_to_text(&html);
+        for sentence in split_sentences(&text) {
+            worker.reset_sentence(sentence);
+            worker.tokenize();
+            for token in worker.token_iter() {
+                counter.add_token(&TokenFeature::from_feature_string(
+                    token.surface(),
+                    token.feature(),
+                ));
+            }
+        }
+    }
+    counter.finish_book();
+    Ok(())
+}
+
+/// Tokenize every book of a library into a frequency list. `on_book` is
+/// called after each book with the number of books done so far.
+pub fn build_frequency_list(
+    tokenizer: &vibrato::Tokenizer,
+    books: &[LibraryBook],
+    mut on_book: impl FnMut(usize),
+) -> Result<Vec<LibraryFrequencyEntry>> {
+    let mut worker = tokenizer.new_worker();
+    let mut counter = FrequencyCounter::default();
+
+    for (i, book) in books.iter().enumerate() {
+        count_book_terms(&mut worker, book, &mut counter)?;
+        on_book(i + 1);
+    }
+
+    Ok(counter.into_entries())
+}
+
+pub fn frequency_list_to_csv(entries: &[LibraryFrequencyEntry]) -> Result<String> {
+    let mut wtr = csv::Writer::from_writer(vec![]);
+    wtr.write_record(["term", "reading", "count", "books"])?;
+    for entry in entries {
+        wtr.write_record([
+            entry.term.as_str(),
+            entry.reading.as_deref().unwrap_or(""),
+            &entry.count.to_string(),
+            &entry.book_count.to_string(),
+        ])?;
+    }
+    Ok(String::from_utf8(wtr.into_inner()?)?)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LibraryJobKind {
+    FrequencyList,
+    Glossary,
+    Difficulty,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LibraryJobStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryJob {
+    pub id: Uuid,
+    pub user_id: String,
+    pub kind: LibraryJobKind,
+    pub status: LibraryJobStatus,
+    pub books_total: usize,
+    pub books_done: usize,
+    pub error: Option<String>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// How long finished jobs are kept around for their status to be read
+const FINISHED_JOB_RETENTION_HOURS: i64 = 24;
+
+/// In-memory tracking of library jobs, in the same spirit as `ImportProgressManager`
+#[derive(Default)]
+pub struct LibraryJobManager {
+    jobs: RwLock<HashMap<Uuid, LibraryJob>>,
+}
+
+impl LibraryJobManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a new job, unless the user already has one of the same kind running
+    pub async fn start(
+        &self,
+        user_id: &str,
+        kind: LibraryJobKind,
+        books_total: usize,
+    ) -> Option<Uuid> {
+        let mut jobs = self.jobs.write().await;
+        let cutoff = chrono::Utc::now() - chrono::Duration::hours(FINISHED_JOB_RETENTION_HOURS);
+        jobs.retain(|_, j| j.finished_at.map_or(true, |finished| finished > cutoff));
+        if jobs.values().any(|j| {
+            j.user_id == user_id && j.kind == kind && j.status == LibraryJobStatus::Running
+        }) {
+            return None;
+        }
+        let id = Uuid::new_v4();
+        jobs.insert(
+            id,
+            LibraryJob {
+                id,
+                user_id: user_id.to_string(),
+                kind,
+                status: LibraryJobStatus::Running,
+                books_total,
+                books_done: 0,
+                error: None,
+                started_at: chrono::Utc::now(),
+                finished_at: None,
+            },
+        );
+        Some(id)
+    }
+
+    pub async fn set_progress(&self, job_id: &Uuid, books_done: usize) {
+        if let Some(job) = self.jobs.write().await.get_mut(job_id) {
+            job.books_done = books_done;
+        }
+    }
+
+    pub async fn finish(&self, job_id: &Uuid, result: Result<()>) {
+        if let Some(job) = self.jobs.write().await.get_mut(job_id) {
+            match result {
+                Ok(()) => job.status = LibraryJobStatus::Completed,
+                Err(e) => {
+                    job.status = LibraryJobStatus::Failed;
+                    job.error = Some(format!("{e:#}"));
+                }
+            }
+            job.finished_at = Some(chrono::Utc::now());
+        }
+    }
+
+    pub async fn get(&self, job_id: &Uuid) -> Option<LibraryJob> {
+        self.jobs.read().await.get(job_id).cloned()
+    }
+
+    pub async fn get_user_jobs(&self, user_id: &str) -> Vec<LibraryJob> {
+        let mut jobs: Vec<_> = self
+            .jobs
+            .read()
+            .await
+            .values()
+            .filter(|j| j.user_id == user_id)
+            .cloned()
+            .collect();
+        jobs.sort_by_key(|j| std::cmp::Reverse(j.started_at));
+        jobs
+    }
+}
+
+const FREQUENCY_LIST_FILE: &str = "library-frequency.json";
+const FREQUENCY_ENABLED_FILE: &str = "library-frequency.enabled";
+
+type TermCounts = Arc<HashMap<String, LibraryTermCount>>;
+// Users whose imported lists are kept in memory; past this one is dropped,
+// to be read from disk again on its next lookup
+const MAX_CACHED_USERS: usize = 1000;
+
+/// Generated frequency lists, stored per user under `LIBRARY_DATA_DIR`.
+///
+/// A list only shows up in lookups once the user imports it, which is
+/// remembered with a marker file next to the list.
+pub struct LibraryFrequencyStore {
+    data_dir: PathBuf,
+    // None caches "this user has no imported list"
+    imported: RwLock<HashMap<String, Option<TermCounts>>>,
+}
+
+impl LibraryFrequencyStore {
+    pub fn new(data_dir: PathBuf) -> Self {
+        Self {
+            data_dir,
+            imported: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn user_dir(&self, user_id: &str) -> Result<PathBuf> {
+        user_library_dir(&self.data_dir, user_id)
+    }
+
+    pub async fn save_list(&self, user_id: &str, entries: &[LibraryFrequencyEntry]) -> Result<()> {
+        let dir = self.user_dir(user_id)?;
+        tokio::fs::create_dir_all(&dir).await?;
+        tokio::fs::write(dir.join(FREQUENCY_LIST_FILE), serde_json::to_vec(entries)?).await?;
+        // Refresh the lookup data if the previous list was imported
+        self.imported.write().await.remove(user_id);
+        Ok(())
+    }
+
+    pub async fn load_list(&self, user_id: &str) -> Result<Option<Vec<LibraryFrequencyEntry>>> {
+        let path = self.user_dir(user_id)?.join(FREQUENCY_LIST_FILE);
+        match tokio::fs::read(&path).await {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data).with_context(|| {
+                format!("Invalid frequency list: {}", path.display())
+            })?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Use the generated list in lookups. Returns false if there is no list yet.
+    pub async fn import(&self, user_id: &str) -> Result<bool> {
+        if self.load_list(user_id).await?.is_none() {
+            return Ok(false);
+        }
+        tokio::fs::write(self.user_dir(user_id)?.join(FREQUENCY_ENABLED_FILE), b"").await?;
+        self.imported.write().await.remove(user_id);
+        Ok(true)
+    }
+
+    pub async fn remove_import(&self, user_id: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.user_dir(user_id)?.join(FREQUENCY_ENABLED_FILE)).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        self.imported.write().await.remove(user_id);
+        Ok(())
+    }
+
+    async fn imported_counts(&self, user_id: &str) -> Result<Option<TermCounts>> {
+        if let Some(cached) = self.imported.read().await.get(user_id) {
+            return Ok(cached.clone());
+        }
+
+        let enabled = tokio::fs::try_exists(self.user_dir(user_id)?.join(FREQUENCY_ENABLED_FILE))
+            .await
+            .unwrap_or(false);
+        let counts = if enabled {
+            self.load_list(user_id).await?.map(|entries| {
+                Arc::new(
+                    entries
+                        .into_iter()
+                        .map(|e| {
+                            let count = LibraryTermCount {
+                                count: e.count,
+                                book_count: e.book_count,
+                            };
+                            (e.term, count)
+                        })
+                        .collect::<HashMap<_, _>>(),
+                )
+            })
+        } else {
+            None
+        };
+        let mut imported = self.imported.write().await;
+        if imported.len() >= MAX_CACHED_USERS && !imported.contains_key(user_id) {
+            if let Some(evicted) = imported.keys().next().cloned() {
+                imported.remove(&evicted);
+            }
+        }
+        imported.insert(user_id.to_string(), counts.clone());
+        Ok(counts)
+    }
+
+    /// Library counts of `terms` for a user who imported their list
+    pub async fn lookup(
+        &self,
+        user_id: &str,
+        terms: &[&str],
+    ) -> Result<HashMap<String, LibraryTermCount>> {
+        let Some(counts) = self.imported_counts(user_id).await? else {
+            return Ok(HashMap::new());
+        };
+        Ok(terms
+            .iter()
+            .filter_map(|term| counts.get(*term).map(|c| (term.to_string(), *c)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn token(surface: &str, feature: &str) -> TokenFeature {
+        TokenFeature::from_feature_string(surface, feature)
+    }
+
+    #[test]
+    fn test_html_to_text_skips_ruby_and_head() {
+        let html = r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml"><head><title>第一章</title></head>
+<body><p><ruby>猫<rp>(</rp><rt>ねこ</rt><rp>)</rp></ruby>が好き。</p><p>犬も。</p></body></html>"#;
+        assert_eq!(html_to_text(html), "猫が好き。\n犬も。");
+        assert_eq!(document_title(html).as_deref(), Some("第一章"));
+
+        let html = "<html><body><h2><ruby>序<rt>じょ</rt></ruby>章</h2><p>一行目<br/>二行目</p></body></html>";
+        assert_eq!(html_to_text(html), "序章\n一行目\n二行目");
+        assert_eq!(document_title(html).as_deref(), Some("序章"));
+    }
+
+    #[test]
+    fn test_book_title_from_opf() {
+        let temp_dir = TempDir::new().unwrap();
+        let oebps = temp_dir.path().join("OEBPS");
+        std::fs::create_dir_all(&oebps).unwrap();
+        assert_eq!(book_title(temp_dir.path()), None);
+        std::fs::write(
+            oebps.join("content.opf"),
+            r#"<package><metadata><dc:title id="t">吾輩は猫である</dc:title></metadata></package>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            book_title(temp_dir.path()).as_deref(),
+            Some("吾輩は猫である")
+        );
+    }
+
+    #[test]
+    fn test_epub_hashes_like_extracted_book() {
+        let temp_dir = TempDir::new().unwrap();
+        let epub_path = temp_dir.path().join("book.epub");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&epub_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, data) in [
+            ("mimetype", "application/epub+zip"),
+            ("OEBPS/第一章.xhtml", "<p>吾輩は猫である。</p>"),
+        ] {
+            zip.start_file(name, options).unwrap();
+            std::io::Write::write_all(&mut zip, data.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        // Extracted with a differently encoded file name
+        let book_dir = temp_dir.path().join("book");
+        std::fs::create_dir_all(book_dir.join("OEBPS")).unwrap();
+        std::fs::write(book_dir.join("mimetype"), "application/epub+zip").unwrap();
+        let chapter = book_dir.join("OEBPS/%E7%AC%AC%E4%B8%80%E7%AB%A0.xhtml");
+        std::fs::write(&chapter, "<p>吾輩は猫である。</p>").unwrap();
+        let hash = epub_content_hash(&epub_path).unwrap();
+        assert_eq!(book_content_hash(&book_dir).unwrap(), hash);
+
+        std::fs::write(&chapter, "<p>吾輩は犬である。</p>").unwrap();
+        assert_ne!(book_content_hash(&book_dir).unwrap(), hash);
+    }
+
+    #[test]
+    fn test_same_title_and_author() {
+        assert!(same_title_and_author(
+            ("吾輩は猫である", "夏目 漱石"),
+            ("吾輩は猫である　", "夏目漱石")
+        ));
+        assert!(same_title_and_author(
+            ("ＳＰＹ×ＦＡＭＩＬＹ １", ""),
+            ("Spy×Family 1", "遠藤達哉")
+        ));
+        assert!(!same_title_and_author(
+            ("こころ", "夏目漱石"),
+            ("こころ", "森鴎外")
+        ));
+        assert!(!same_title_and_author(
+            ("SPY×FAMILY 1", ""),
+            ("SPY×FAMILY 2", "")
+        ));
+        assert!(!same_title_and_author(("", ""), ("", "")));
+    }
+
+    #[test]
+    fn test_user_library_dir_rejects_traversal() {
+        let uploads = Path::new("/uploads");
+        assert_eq!(
+            user_library_dir(uploads, "alice").unwrap(),
+            PathBuf::from("/uploads/alice")
+        );
+        assert!(user_library_dir(uploads, "..").is_err());
+        assert!(user_library_dir(uploads, "a/../b").is_err());
+        assert!(user_library_dir(uploads, "").is_err());
+    }
+
+    #[test]
+    fn test_frequency_counter_counts_books() {
+        let mut counter = FrequencyCounter::default();
+        counter.add_token(&token("食べ", "動詞,自立,*,*,一段,連用形,食べる,タベ,タベ"));
+        counter.add_token(&token("た", "助動詞,*,*,*,特殊・タ,基本形,た,タ,タ"));
+        counter.add_token(&token(
+            "食べる",
+            "動詞,自立,*,*,一段,基本形,食べる,タベル,タベル",
+        ));
+        counter.add_token(&token("。", "記号,句点,*,*,*,*,。,。,。"));
+        counter.finish_book();
+        counter.add_token(&token(
+            "食べる",
+            "動詞,自立,*,*,一段,基本形,食べる,タベル,タベル",
+        ));
+        counter.add_token(&token("猫", "名詞,一般,*,*,*,*,猫,ネコ,ネコ"));
+
+        let entries = counter.into_entries();
+        assert_eq!(
+            entries,
+            vec![
+                LibraryFrequencyEntry {
+                    term: "食べる".to_string(),
+                    reading: Some("タベル".to_string()),
+                    count: 3,
+                    book_count: 2,
+                },
+                LibraryFrequencyEntry {
+                    term: "猫".to_string(),
+                    reading: Some("ネコ".to_string()),
+                    count: 1,
+                    book_count: 1,
+                },
+            ]
+        );
+        assert_eq!(
+            frequency_list_to_csv(&entries).unwrap(),
+            "term,reading,count,books\n食べる,タベル,3,2\n猫,ネコ,1,1\n"
+        );
+
+        // Only conjugated forms seen: the reading of the term isn't known
+        let mut counter = FrequencyCounter::default();
+        counter.add_token(&token("食べ", "動詞,自立,*,*,一段,連用形,食べる,タベ,タベ"));
+        assert_eq!(counter.into_entries()[0].reading, None);
+    }
+
+    #[tokio::test]
+    async fn test_job_manager_drops_old_finished_jobs() {
+        let manager = LibraryJobManager::new();
+        let old = manager
+            .start("alice", LibraryJobKind::Glossary, 1)
+            .await
+            .unwrap();
+        manager.finish(&old, Ok(())).await;
+        let running = manager
+            .start("alice", LibraryJobKind::Difficulty, 1)
+            .await
+            .unwrap();
+        let expired =
+            chrono::Utc::now() - chrono::Duration::hours(FINISHED_JOB_RETENTION_HOURS + 1);
+        if let Some(job) = manager.jobs.write().await.get_mut(&old) {
+            job.finished_at = Some(expired);
+        }
+
+        let new = manager
+            .start("alice", LibraryJobKind::Glossary, 1)
+            .await
+            .unwrap();
+        assert!(manager.get(&old).await.is_none());
+        assert!(manager.get(&running).await.is_some());
+        assert!(manager.get(&new).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_frequency_store_only_looks_up_imported_lists() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = LibraryFrequencyStore::new(temp_dir.path().to_path_buf());
+        assert!(!store.import("alice").await.unwrap());
+
+        let entries = vec![LibraryFrequencyEntry {
+            term: "猫".to_string(),
+            reading: None,
+            count: 37,
+            book_count: 3,
+        }];
+        store.save_list("alice", &entries).await.unwrap();
+        assert!(store.lookup("alice", &["猫"]).await.unwrap().is_empty());
+
+        assert!(store.import("alice").await.unwrap());
+        let counts = store.lookup("alice", &["猫", "犬"]).await.unwrap();
+        assert_eq!(
+            counts.get("猫"),
+            Some(&LibraryTermCount {
+                count: 37,
+                book_count: 3
+            })
+        );
+        assert_eq!(counts.len(), 1);
+
+        store.remove_import("alice").await.unwrap();
+        assert!(store.lookup("alice", &["猫"]).await.unwrap().is_empty());
+    }
+}
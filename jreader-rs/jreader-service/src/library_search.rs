@@ -0,0 +1,235 @@
+//! Full-text search over a user's books, used to mine example sentences.
+//!
+//! Every book gets its own SQLite FTS5 index of sentences, built lazily on the
+//! first search. The trigram tokenizer is used since Japanese has no spaces
+//! between words, which makes `LIKE '%term%'` queries use the index.
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OpenFlags};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::library::{
+    book_documents, book_title, document_title, html_to_text, split_sentences, user_library_dir,
+    LibraryBook,
+};
+use crate::vocab_lists::escape_like;
+
+/// Bump when the index layout or sentence splitting changes, so old indexes get rebuilt
+const INDEX_VERSION: i64 = 1;
+
+/// Sentences longer than this are not useful as examples
+const MAX_SENTENCE_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExampleSentence {
+    pub sentence: String,
+    pub upload_id: String,
+    pub book_title: Option<String>,
+    /// Path of the chapter document inside the book
+    pub document: String,
+    pub chapter: Option<String>,
+}
+
+/// Per-book search indexes stored under `LIBRARY_DATA_DIR/{user_id}/search/`
+pub struct LibrarySearch {
+    data_dir: PathBuf,
+}
+
+impl LibrarySearch {
+    pub fn new(data_dir: PathBuf) -> Self {
+        Self { data_dir }
+    }
+
+    fn index_path(&self, user_id: &str, book: &LibraryBook) -> Result<PathBuf> {
+        let dir = user_library_dir(&self.data_dir, user_id)?.join("search");
+        Ok(dir.join(format!("{}.sqlite", book.upload_id)))
+    }
+
+    /// Open the index of `book`, building it first if needed
+    pub fn open_index(&self, user_id: &str, book: &LibraryBook) -> Result<Connection> {
+        let path = self.index_path(user_id, book)?;
+        if let Ok(conn) = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY) {
+            let version: Option<i64> = conn
+                .query_row("SELECT version FROM index_info", [], |row| row.get(0))
+                .ok();
+            if version == Some(INDEX_VERSION) {
+                return Ok(conn);
+            }
+        }
+
+        build_index(&path, book)?;
+        Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Failed to open search index: {}", path.display()))
+    }
+
+    /// Up to `limit` sentences containing `term`, taken from the books in order
+    pub fn find_examples(
+        &self,
+        user_id: &str,
+        books: &[LibraryBook],
+        term: &str,
+        limit: usize,
+    ) -> Result<Vec<ExampleSentence>> {
+        let mut examples = Vec::new();
+        for book in books {
+            if examples.len() >= limit {
+                break;
+            }
+            let conn = self.open_index(user_id, book)?;
+            let book_title: Option<String> = conn
+                .query_row("SELECT book_title FROM index_info", [], |row| row.get(0))
+                .ok()
+                .flatten();
+            for (sentence, document, chapter) in search_index(&conn, term, limit - examples.len())?
+            {
+                examples.push(ExampleSentence {
+                    sentence,
+                    upload_id: book.upload_id.clone(),
+                    book_title: book_title.clone(),
+                    document,
+                    chapter,
+                });
+            }
+        }
+        Ok(examples)
+    }
+}
+
+fn search_index(
+    conn: &Connection,
+    term: &str,
+    limit: usize,
+) -> Result<Vec<(String, String, Option<String>)>> {
+    let mut stmt = conn.prepare(
+        "SELECT sentence, document, chapter FROM sentences
+         WHERE sentence LIKE ?1 ESCAPE '\\' ORDER BY rowid LIMIT ?2",
+    )?;
+    let rows = stmt
+        .query_map(
+            params![format!("%{}%", escape_like(term)), limit as i64],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+// Build into a temporary file and rename it, so concurrent searches never see
+// a half-built index
+fn build_index(path: &Path, book: &LibraryBook) -> Result<()> {
+    let dir = path
+        .parent()
+        .context("Search index path has no parent directory")?;
+    std::fs::create_dir_all(dir)?;
+    let temp_file = tempfile::NamedTempFile::new_in(dir)?;
+
+    let mut conn = Connection::open(temp_file.path())?;
+    conn.execute_batch(
+        "CREATE TABLE index_info (version INTEGER NOT NULL, book_title TEXT);
+         CREATE VIRTUAL TABLE sentences USING fts5(
+             sentence, document UNINDEXED, chapter UNINDEXED, tokenize = 'trigram'
+         );",
+    )?;
+
+    let transaction = conn.transaction()?;
+    transaction.execute(
+        "INSERT INTO index_info (version, book_title) VALUES (?1, ?2)",
+        params![INDEX_VERSION, book_title(&book.path)],
+    )?;
+    {
+        let mut insert = transaction
+            .prepare("INSERT INTO sentences (sentence, document, chapter) VALUES (?1, ?2, ?3)")?;
+        for document in book_documents(&book.path)? {
+            let html = std::fs::read_to_string(&document)
+                .with_context(|| format!("Failed to read {}", document.display()))?;
+            let relative = document
+                .strip_prefix(&book.path)
+                .unwrap_or(&document)
+                .to_string_lossy()
+                .to_string();
+            let chapter = document_title(&html);
+            for sentence in split_sentences(&html_to_text(&html)) {
+                if sentence.chars().count() <= MAX_SENTENCE_CHARS {
+                    insert.execute(params![sentence, relative, chapter])?;
+                }
+            }
+        }
+    }
+    transaction.commit()?;
+    drop(conn);
+
+    temp_file
+        .persist(path)
+        .with_context(|| format!("Failed to write search index: {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_book(library: &Path, upload_id: &str, chapters: &[(&str, &str)]) -> LibraryBook {
+        let book_dir = library.join(upload_id);
+        std::fs::create_dir_all(book_dir.join("text")).unwrap();
+        for (name, body) in chapters {
+            std::fs::write(
+                book_dir.join("text").join(name),
+                format!("<html><head><title>{name}</title></head><body>{body}</body></html>"),
+            )
+            .unwrap();
+        }
+        LibraryBook {
+            upload_id: upload_id.to_string(),
+            path: book_dir,
+        }
+    }
+
+    #[test]
+    fn test_find_examples_with_citations() {
+        let uploads = TempDir::new().unwrap();
+        let data = TempDir::new().unwrap();
+        let books = vec![
+            write_book(
+                uploads.path(),
+                "book-a",
+                &[(
+                    "ch1.xhtml",
+                    "<h1>第一章</h1><p>猫が魚を食べる。犬は<ruby>肉<rt>にく</rt></ruby>を食べた。</p>",
+                )],
+            ),
+            write_book(
+                uploads.path(),
+                "book-b",
+                &[("ch1.xhtml", "<p>パンを食べるのが好き。</p>")],
+            ),
+        ];
+        let search = LibrarySearch::new(data.path().to_path_buf());
+
+        let examples = search.find_examples("alice", &books, "食べ", 10).unwrap();
+        assert_eq!(
+            examples
+                .iter()
+                .map(|e| e.sentence.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "猫が魚を食べる。",
+                "犬は肉を食べた。",
+                "パンを食べるのが好き。"
+            ]
+        );
+        assert_eq!(examples[0].upload_id, "book-a");
+        assert_eq!(examples[0].document, "text/ch1.xhtml");
+        assert_eq!(examples[0].chapter.as_deref(), Some("第一章"));
+        assert_eq!(examples[2].chapter.as_deref(), Some("ch1.xhtml"));
+
+        // The limit is applied across books, and the index is reused
+        let examples = search.find_examples("alice", &books, "食べる", 1).unwrap();
+        assert_eq!(examples.len(), 1);
+        assert!(search
+            .find_examples("alice", &books, "100%", 10)
+            .unwrap()
+            .is_empty());
+    }
+}
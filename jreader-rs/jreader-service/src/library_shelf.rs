@@ -0,0 +1,518 @@
+//! How a user organizes their library: collections, tags, reading progress
+//! and difficulty scores of their books.
+//!
+//! Books are only directories under `UPLOADS_DIR`, so this is kept next to
+//! the other per-user library data under `LIBRARY_DATA_DIR`. Entries of books
+//! that have since been deleted are simply never listed.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::glossary::is_japanese_char;
+use crate::levels::{JlptLevel, LevelData};
+use crate::library::{user_library_dir, LibraryFrequencyEntry};
+
+pub const MAX_COLLECTION_NAME_LENGTH: usize = 100;
+pub const MAX_TAG_LENGTH: usize = 50;
+pub const MAX_TAGS_PER_BOOK: usize = 20;
+
+const SHELF_FILE: &str = "library-shelf.json";
+
+/// A folder of books. A book is in at most one collection.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Collection {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ShelfBook {
+    pub collection_id: Option<Uuid>,
+    pub tags: Vec<String>,
+    /// Fraction of the book read, from 0 to 1
+    pub progress: Option<f64>,
+    pub last_read_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Fraction of the words of the book that are uncommon, from 0 to 1
+    pub difficulty: Option<f64>,
+    /// Content hash of the book, kept for spotting duplicate uploads
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+}
+
+/// Everything a user has set on their library
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LibraryShelf {
+    pub collections: Vec<Collection>,
+    pub books: HashMap<String, ShelfBook>,
+}
+
+impl LibraryShelf {
+    pub fn collection(&self, id: &Uuid) -> Option<&Collection> {
+        self.collections.iter().find(|c| c.id == *id)
+    }
+
+    pub fn create_collection(&mut self, name: String) -> Collection {
+        let collection = Collection {
+            id: Uuid::new_v4(),
+            name,
+            created_at: chrono::Utc::now(),
+        };
+        self.collections.push(collection.clone());
+        collection
+    }
+
+    pub fn rename_collection(&mut self, id: &Uuid, name: String) -> Option<Collection> {
+        let collection = self.collections.iter_mut().find(|c| c.id == *id)?;
+        collection.name = name;
+        Some(collection.clone())
+    }
+
+    /// Delete a collection, leaving its books outside of any collection.
+    /// Returns false if there is no such collection.
+    pub fn delete_collection(&mut self, id: &Uuid) -> bool {
+        let before = self.collections.len();
+        self.collections.retain(|c| c.id != *id);
+        for book in self.books.values_mut() {
+            if book.collection_id == Some(*id) {
+                book.collection_id = None;
+            }
+        }
+        self.collections.len() != before
+    }
+
+    pub fn book(&self, upload_id: &str) -> ShelfBook {
+        self.books.get(upload_id).cloned().unwrap_or_default()
+    }
+
+    pub fn book_mut(&mut self, upload_id: &str) -> &mut ShelfBook {
+        self.books.entry(upload_id.to_string()).or_default()
+    }
+
+    /// Every tag in use, sorted
+    pub fn tags(&self) -> Vec<String> {
+        self.books
+            .values()
+            .flat_map(|b| b.tags.iter().cloned())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Trim and check a collection name, returning the cleaned up name
+pub fn validate_collection_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Collection name must not be empty".to_string());
+    }
+    if name.chars().count() > MAX_COLLECTION_NAME_LENGTH {
+        return Err(format!(
+            "Collection name must be at most {} characters",
+            MAX_COLLECTION_NAME_LENGTH
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// Trim, deduplicate and check the tags of a book, keeping their order
+pub fn normalize_tags(tags: &[String]) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if tag.is_empty() || normalized.iter().any(|t| t == tag) {
+            continue;
+        }
+        if tag.chars().count() > MAX_TAG_LENGTH {
+            return Err(format!("Tags must be at most {MAX_TAG_LENGTH} characters"));
+        }
+        normalized.push(tag.to_string());
+    }
+    if normalized.len() > MAX_TAGS_PER_BOOK {
+        return Err(format!("A book can have at most {MAX_TAGS_PER_BOOK} tags"));
+    }
+    Ok(normalized)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BookSort {
+    #[default]
+    Title,
+    RecentlyRead,
+    Progress,
+    Difficulty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl BookSort {
+    /// Most recently read, furthest read and easiest books come first unless
+    /// asked otherwise
+    pub fn default_order(self) -> SortOrder {
+        match self {
+            BookSort::Title | BookSort::Difficulty => SortOrder::Asc,
+            BookSort::RecentlyRead | BookSort::Progress => SortOrder::Desc,
+        }
+    }
+}
+
+/// Which books to list and in what order
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ShelfQuery {
+    pub collection: Option<Uuid>,
+    pub tag: Option<String>,
+    pub sort: BookSort,
+    pub order: Option<SortOrder>,
+}
+
+/// A book of the library as listed
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ShelfEntry {
+    pub upload_id: String,
+    pub title: Option<String>,
+    #[serde(flatten)]
+    pub book: ShelfBook,
+}
+
+impl ShelfEntry {
+    fn display_title(&self) -> &str {
+        self.title.as_deref().unwrap_or(&self.upload_id)
+    }
+}
+
+/// Filter and sort `entries` as asked. Books without the value sorted on
+/// (never opened, not scored yet) always come last, by title.
+pub fn arrange(mut entries: Vec<ShelfEntry>, query: &ShelfQuery) -> Vec<ShelfEntry> {
+    entries.retain(|entry| {
+        query
+            .collection
+            .is_none_or(|id| entry.book.collection_id == Some(id))
+            && query
+                .tag
+                .as_ref()
+                .is_none_or(|tag| entry.book.tags.contains(tag))
+    });
+
+    let order = query.order.unwrap_or(query.sort.default_order());
+    let by_title = |a: &ShelfEntry, b: &ShelfEntry| {
+        a.display_title()
+            .cmp(b.display_title())
+            .then_with(|| a.upload_id.cmp(&b.upload_id))
+    };
+    let directed = |ordering: Ordering| match order {
+        SortOrder::Asc => ordering,
+        SortOrder::Desc => ordering.reverse(),
+    };
+    let value = |entry: &ShelfEntry| match query.sort {
+        BookSort::Title => None,
+        BookSort::RecentlyRead => entry.book.last_read_at.map(|t| t.timestamp_millis() as f64),
+        BookSort::Progress => entry.book.progress,
+        BookSort::Difficulty => entry.book.difficulty,
+    };
+    entries.sort_by(|a, b| {
+        let ordering = match (value(a), value(b)) {
+            _ if query.sort == BookSort::Title => directed(by_title(a, b)),
+            (Some(x), Some(y)) => directed(x.partial_cmp(&y).unwrap_or(Ordering::Equal)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        ordering.then_with(|| by_title(a, b))
+    });
+    entries
+}
+
+/// Difficulty of a book from its term counts: the fraction of its Japanese
+/// words that frequency dictionaries rank above `threshold` or don't list at
+/// all. None for books without any Japanese words.
+pub fn difficulty_score(
+    book_terms: &[LibraryFrequencyEntry],
+    ranks: &HashMap<String, f64>,
+    threshold: f64,
+) -> Option<f64> {
+    uncommon_share(book_terms, |entry| {
+        ranks.get(&entry.term).is_none_or(|rank| *rank > threshold)
+    })
+}
+
+/// Difficulty of a book for a learner at JLPT level `known`: the fraction of
+/// its Japanese words that aren't on the lists of that level or an easier
+/// one. None for books without any Japanese words.
+pub fn jlpt_difficulty_score(
+    book_terms: &[LibraryFrequencyEntry],
+    levels: &LevelData,
+    known: JlptLevel,
+) -> Option<f64> {
+    uncommon_share(book_terms, |entry| {
+        levels
+            .jlpt_level(&entry.term, entry.reading.as_deref())
+            .is_none_or(|level| level > known)
+    })
+}
+
+// Share of the occurrences of Japanese words that are `uncommon`
+fn uncommon_share(
+    book_terms: &[LibraryFrequencyEntry],
+    uncommon: impl Fn(&LibraryFrequencyEntry) -> bool,
+) -> Option<f64> {
+    let mut total = 0;
+    let mut uncommon_count = 0;
+    for entry in book_terms {
+        if !entry.term.chars().any(is_japanese_char) {
+            continue;
+        }
+        total += entry.count;
+        if uncommon(entry) {
+            uncommon_count += entry.count;
+        }
+    }
+    (total > 0).then(|| uncommon_count as f64 / total as f64)
+}
+
+/// Library shelves, stored per user under `LIBRARY_DATA_DIR`
+pub struct LibraryShelfStore {
+    data_dir: PathBuf,
+    // Updates are read-modify-write of the whole file
+    write_lock: Mutex<()>,
+}
+
+impl LibraryShelfStore {
+    pub fn new(data_dir: PathBuf) -> Self {
+        Self {
+            data_dir,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    fn shelf_path(&self, user_id: &str) -> Result<PathBuf> {
+        Ok(user_library_dir(&self.data_dir, user_id)?.join(SHELF_FILE))
+    }
+
+    /// The user's shelf, empty if they haven't organized anything yet
+    pub async fn load(&self, user_id: &str) -> Result<LibraryShelf> {
+        let path = self.shelf_path(user_id)?;
+        match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)
+                .with_context(|| format!("Invalid library shelf: {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(LibraryShelf::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Change the user's shelf with `f` and save it
+    pub async fn update<T>(
+        &self,
+        user_id: &str,
+        f: impl FnOnce(&mut LibraryShelf) -> T,
+    ) -> Result<T> {
+        let _guard = self.write_lock.lock().await;
+        let mut shelf = self.load(user_id).await?;
+        let result = f(&mut shelf);
+        let path = self.shelf_path(user_id)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, serde_json::to_vec(&shelf)?).await?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entry(upload_id: &str, title: &str, book: ShelfBook) -> ShelfEntry {
+        ShelfEntry {
+            upload_id: upload_id.to_string(),
+            title: Some(title.to_string()),
+            book,
+        }
+    }
+
+    fn ids(entries: &[ShelfEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.upload_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_arrange() {
+        let fiction = Uuid::new_v4();
+        let now = chrono::Utc::now();
+        let entries = vec![
+            entry(
+                "a",
+                "こころ",
+                ShelfBook {
+                    collection_id: Some(fiction),
+                    tags: vec!["classic".to_string()],
+                    progress: Some(0.5),
+                    last_read_at: Some(now - chrono::Duration::days(2)),
+                    difficulty: Some(0.3),
+                    ..Default::default()
+                },
+            ),
+            entry(
+                "b",
+                "羅生門",
+                ShelfBook {
+                    collection_id: Some(fiction),
+                    progress: Some(0.9),
+                    last_read_at: Some(now),
+                    ..Default::default()
+                },
+            ),
+            entry(
+                "c",
+                "いちご同盟",
+                ShelfBook {
+                    tags: vec!["classic".to_string()],
+                    difficulty: Some(0.1),
+                    ..Default::default()
+                },
+            ),
+        ];
+
+        let query = |sort, order| ShelfQuery {
+            sort,
+            order,
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(&arrange(entries.clone(), &query(BookSort::Title, None))),
+            ["c", "a", "b"]
+        );
+        assert_eq!(
+            ids(&arrange(
+                entries.clone(),
+                &query(BookSort::RecentlyRead, None)
+            )),
+            ["b", "a", "c"]
+        );
+        assert_eq!(
+            ids(&arrange(
+                entries.clone(),
+                &query(BookSort::Progress, Some(SortOrder::Asc))
+            )),
+            ["a", "b", "c"]
+        );
+        // Unscored books come last either way
+        assert_eq!(
+            ids(&arrange(
+                entries.clone(),
+                &query(BookSort::Difficulty, None)
+            )),
+            ["c", "a", "b"]
+        );
+        assert_eq!(
+            ids(&arrange(
+                entries.clone(),
+                &query(BookSort::Difficulty, Some(SortOrder::Desc))
+            )),
+            ["a", "c", "b"]
+        );
+
+        let filtered = ShelfQuery {
+            collection: Some(fiction),
+            tag: Some("classic".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(&arrange(entries, &filtered)), ["a"]);
+    }
+
+    #[test]
+    fn test_normalize_tags() {
+        let tags = [" 純文学 ", "", "SF", "純文学"].map(String::from);
+        assert_eq!(normalize_tags(&tags).unwrap(), ["純文学", "SF"]);
+        assert!(normalize_tags(&["x".repeat(MAX_TAG_LENGTH + 1)]).is_err());
+        let too_many: Vec<String> = (0..=MAX_TAGS_PER_BOOK).map(|i| i.to_string()).collect();
+        assert!(normalize_tags(&too_many).is_err());
+    }
+
+    #[test]
+    fn test_difficulty_score() {
+        let freq = |term: &str, count| LibraryFrequencyEntry {
+            term: term.to_string(),
+            reading: None,
+            count,
+            book_count: 1,
+        };
+        let book_terms = vec![
+            freq("猫", 6),
+            freq("魑魅魍魎", 2),
+            freq("2024", 5),
+            freq("吾輩", 2),
+        ];
+        let ranks = HashMap::from([("猫".to_string(), 900.0), ("吾輩".to_string(), 20000.0)]);
+        assert_eq!(difficulty_score(&book_terms, &ranks, 5000.0), Some(0.4));
+        assert_eq!(difficulty_score(&[freq("2024", 1)], &ranks, 5000.0), None);
+    }
+
+    #[test]
+    fn test_jlpt_difficulty_score() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join(crate::levels::JLPT_VOCAB_FILE),
+            "猫\tねこ\tN5\n吾輩\t\tN1\n",
+        )
+        .unwrap();
+        let levels = LevelData::load(dir.path()).unwrap();
+        let freq = |term: &str, count| LibraryFrequencyEntry {
+            term: term.to_string(),
+            reading: None,
+            count,
+            book_count: 1,
+        };
+        let book_terms = vec![freq("猫", 6), freq("魑魅魍魎", 2), freq("吾輩", 2)];
+        assert_eq!(
+            jlpt_difficulty_score(&book_terms, &levels, JlptLevel::N3),
+            Some(0.4)
+        );
+        assert_eq!(
+            jlpt_difficulty_score(&book_terms, &levels, JlptLevel::N1),
+            Some(0.2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_deleting_collection_unfiles_books() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = LibraryShelfStore::new(temp_dir.path().to_path_buf());
+        assert!(store.load("alice").await.unwrap().collections.is_empty());
+
+        let collection = store
+            .update("alice", |shelf| {
+                let collection = shelf.create_collection("Novels".to_string());
+                shelf.book_mut("book-1").collection_id = Some(collection.id);
+                collection
+            })
+            .await
+            .unwrap();
+        let shelf = store.load("alice").await.unwrap();
+        assert_eq!(shelf.collection(&collection.id), Some(&collection));
+        assert_eq!(shelf.book("book-1").collection_id, Some(collection.id));
+
+        assert!(store
+            .update("alice", |shelf| shelf.delete_collection(&collection.id))
+            .await
+            .unwrap());
+        let shelf = store.load("alice").await.unwrap();
+        assert!(shelf.collections.is_empty());
+        assert_eq!(shelf.book("book-1").collection_id, None);
+    }
+}
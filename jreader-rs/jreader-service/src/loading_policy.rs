@@ -0,0 +1,210 @@
+//! Which dictionaries a scan loads when space is limited.
+//!
+//! Without a budget every archive is loaded. With one (`DICT_LOAD_BUDGET_MB`,
+//! or `budget_mb` on the scan), archives are taken most important first: by
+//! the importance configured in `DICT_IMPORTANCE`, then by type, term
+//! dictionaries before frequency, pitch and kanji ones. An archive is loaded
+//! whole while it fits in what is left of the budget. One that only fits
+//! without its static assets (images, fonts) is loaded with them deferred
+//! until a later scan has room, and one that doesn't fit at all is skipped.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::warn;
+
+/// Kinds of dictionaries in the order they are loaded in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ArchiveKind {
+    Term,
+    Frequency,
+    Pitch,
+    Kanji,
+    /// Couldn't be told from the archive
+    Unknown,
+}
+
+/// What the policy needs to know about an archive
+#[derive(Debug, Clone)]
+pub struct ArchiveCandidate {
+    /// Archive file name without `.zip`
+    pub name: String,
+    /// Title from `index.json`, if it could be read
+    pub title: Option<String>,
+    pub kind: ArchiveKind,
+    /// Estimated size of the imported banks
+    pub bank_bytes: u64,
+    pub asset_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadDecision {
+    Full,
+    /// Load the banks but not the static assets
+    DeferAssets,
+    Skip,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LoadingPolicy {
+    pub budget_mb: Option<u64>,
+    /// Importance of dictionaries by title or archive name, higher first.
+    /// Dictionaries not listed have importance 0.
+    pub importance: HashMap<String, i64>,
+}
+
+impl LoadingPolicy {
+    pub fn from_env() -> Self {
+        let budget_mb = std::env::var("DICT_LOAD_BUDGET_MB").ok().and_then(|value| {
+            value.trim().parse().ok().or_else(|| {
+                warn!(
+                    value,
+                    "⚠️ Invalid DICT_LOAD_BUDGET_MB, loading without a budget"
+                );
+                None
+            })
+        });
+        let importance = match std::env::var("DICT_IMPORTANCE") {
+            Ok(value) => parse_importance(&value).unwrap_or_else(|e| {
+                warn!(?e, "⚠️ Invalid DICT_IMPORTANCE, ignoring it");
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        Self {
+            budget_mb,
+            importance,
+        }
+    }
+
+    /// The policy with `budget_mb` instead of the configured budget, if given
+    pub fn with_budget(mut self, budget_mb: Option<u64>) -> Self {
+        if budget_mb.is_some() {
+            self.budget_mb = budget_mb;
+        }
+        self
+    }
+
+    fn importance_of(&self, candidate: &ArchiveCandidate) -> i64 {
+        candidate
+            .title
+            .as_ref()
+            .and_then(|title| self.importance.get(title))
+            .or_else(|| self.importance.get(&candidate.name))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// What to do with each of `candidates`, in the same order
+    pub fn plan(&self, candidates: &[ArchiveCandidate]) -> Vec<LoadDecision> {
+        let Some(budget_mb) = self.budget_mb else {
+            return vec![LoadDecision::Full; candidates.len()];
+        };
+        let mut order: Vec<usize> = (0..candidates.len()).collect();
+        order.sort_by_key(|&i| {
+            let candidate = &candidates[i];
+            (
+                std::cmp::Reverse(self.importance_of(candidate)),
+                candidate.kind,
+                candidate.name.clone(),
+            )
+        });
+
+        let mut remaining = budget_mb.saturating_mul(1024 * 1024);
+        let mut decisions = vec![LoadDecision::Skip; candidates.len()];
+        for i in order {
+            let candidate = &candidates[i];
+            let full = candidate.bank_bytes.saturating_add(candidate.asset_bytes);
+            if full <= remaining {
+                remaining -= full;
+                decisions[i] = LoadDecision::Full;
+            } else if candidate.bank_bytes <= remaining {
+                remaining -= candidate.bank_bytes;
+                decisions[i] = LoadDecision::DeferAssets;
+            }
+        }
+        decisions
+    }
+}
+
+/// Parse `DICT_IMPORTANCE`: comma separated `name=importance` pairs, where
+/// the name is a dictionary title or archive name, e.g. `Jitendex=10,JMnedict=-5`
+pub fn parse_importance(value: &str) -> Result<HashMap<String, i64>> {
+    let mut importance = HashMap::new();
+    for pair in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let Some((name, rank)) = pair.rsplit_once('=') else {
+            bail!("Expected name=importance, got {pair:?}");
+        };
+        let rank = rank
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid importance in {pair:?}"))?;
+        importance.insert(name.trim().to_string(), rank);
+    }
+    Ok(importance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    fn candidate(name: &str, kind: ArchiveKind, bank_mb: u64, asset_mb: u64) -> ArchiveCandidate {
+        ArchiveCandidate {
+            name: name.to_string(),
+            title: Some(name.to_uppercase()),
+            kind,
+            bank_bytes: bank_mb * MB,
+            asset_bytes: asset_mb * MB,
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let candidates = [
+            candidate("kanjidic", ArchiveKind::Kanji, 20, 0),
+            candidate("jpdb", ArchiveKind::Frequency, 30, 0),
+            candidate("jitendex", ArchiveKind::Term, 100, 200),
+            candidate("names", ArchiveKind::Term, 300, 0),
+        ];
+        let mut policy = LoadingPolicy::default();
+        assert_eq!(policy.plan(&candidates), vec![LoadDecision::Full; 4]);
+
+        // Terms first: jitendex fits without its images, names doesn't fit,
+        // then frequency and kanji
+        policy.budget_mb = Some(200);
+        assert_eq!(
+            policy.plan(&candidates),
+            vec![
+                LoadDecision::Full,
+                LoadDecision::Full,
+                LoadDecision::DeferAssets,
+                LoadDecision::Skip
+            ]
+        );
+
+        // Importance comes before type, by title or archive name
+        policy.importance = parse_importance("kanjidic=5, NAMES=1").unwrap();
+        policy.budget_mb = Some(330);
+        assert_eq!(
+            policy.plan(&candidates),
+            vec![
+                LoadDecision::Full,
+                LoadDecision::Skip,
+                LoadDecision::Skip,
+                LoadDecision::Full
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_importance() {
+        let importance = parse_importance(" Jitendex=10,JMnedict = -5,, ").unwrap();
+        assert_eq!(importance["Jitendex"], 10);
+        assert_eq!(importance["JMnedict"], -5);
+        assert!(parse_importance("Jitendex").is_err());
+        assert!(parse_importance("Jitendex=high").is_err());
+    }
+}
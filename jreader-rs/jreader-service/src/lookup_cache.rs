@@ -0,0 +1,114 @@
+//! Short-lived cache of lookup responses. A lookup with `warm` set also
+//! computes the results for the next few token boundaries of the sentence in
+//! the background, since the next click is usually nearby; those lookups are
+//! then served from here.
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Token boundaries after the looked up position that a warm lookup computes
+pub const WARM_POSITIONS: usize = 3;
+/// Long enough to cover reading a sentence, short enough that changed
+/// preferences or dictionaries show up quickly
+pub const DEFAULT_TTL: Duration = Duration::from_secs(30);
+pub const DEFAULT_CAPACITY: usize = 2000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LookupCacheKey {
+    /// Results depend on the user's preferences and library
+    pub user_id: Option<String>,
+    pub term: String,
+    pub position: usize,
+    /// Books can have glossary overlays
+    pub book: Option<String>,
+    /// Audio is only looked up when asked for
+    pub include_audio: bool,
+}
+
+struct CacheState<K, V> {
+    entries: HashMap<K, (Instant, V)>,
+    /// Keys in insertion order, for evicting the oldest entry
+    order: VecDeque<K>,
+}
+
+pub struct LookupCache<K, V> {
+    ttl: Duration,
+    capacity: usize,
+    state: Mutex<CacheState<K, V>>,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> LookupCache<K, V> {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            state: Mutex::new(CacheState {
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Expired entries stay until evicted, which keeps `order` in step with
+    /// `entries`
+    pub fn get(&self, key: &K) -> Option<V> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .entries
+            .get(key)
+            .filter(|(inserted, _)| inserted.elapsed() < self.ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state
+            .entries
+            .insert(key.clone(), (Instant::now(), value))
+            .is_none()
+        {
+            state.order.push_back(key);
+        }
+        while state.entries.len() > self.capacity {
+            let Some(oldest) = state.order.pop_front() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.entries.clear();
+        state.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expiry_and_eviction() {
+        let cache = LookupCache::new(Duration::from_secs(60), 2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("a", 3);
+        assert_eq!(cache.get(&"a"), Some(3));
+        cache.insert("c", 4);
+        // "a" was inserted first, so it goes even though it was updated later
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some(2));
+        assert_eq!(cache.get(&"c"), Some(4));
+
+        let expired = LookupCache::new(Duration::ZERO, 2);
+        expired.insert("a", 1);
+        assert!(!expired.contains(&"a"));
+    }
+}
@@ -0,0 +1,307 @@
+//! Opt-in recording of full lookup request/response pairs, for debugging
+//! "term X returns nothing" reports that are hard to reproduce.
+//!
+//! Recording is off by default. Admins enable it for specific users or for a
+//! random sample of all lookups. Records are appended as JSON lines to
+//! `lookups.jsonl`, which is rotated to `lookups.1.jsonl`, `lookups.2.jsonl`, ...
+//! once it grows past the size limit.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+pub const DEFAULT_MAX_FILE_BYTES: u64 = 10 * 1024 * 1024;
+pub const DEFAULT_MAX_FILES: usize = 5;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticsSettings {
+    /// Fraction of all lookups to record, between 0 and 1
+    #[serde(default)]
+    pub sample_rate: f64,
+    /// Users whose lookups are always recorded
+    #[serde(default)]
+    pub users: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LookupRecord {
+    pub recorded_at: chrono::DateTime<chrono::Utc>,
+    pub user_id: Option<String>,
+    pub duration_ms: u64,
+    pub request: serde_json::Value,
+    pub status: u16,
+    pub response: serde_json::Value,
+}
+
+impl LookupRecord {
+    pub fn new(
+        user_id: Option<String>,
+        request: serde_json::Value,
+        status: u16,
+        response: serde_json::Value,
+        duration: Duration,
+    ) -> Self {
+        Self {
+            recorded_at: chrono::Utc::now(),
+            user_id,
+            duration_ms: duration.as_millis() as u64,
+            request,
+            status,
+            response,
+        }
+    }
+
+    fn term(&self) -> Option<&str> {
+        self.request.get("term").and_then(|t| t.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordFile {
+    pub name: String,
+    pub size: u64,
+}
+
+pub struct LookupRecorder {
+    dir: PathBuf,
+    max_file_bytes: u64,
+    max_files: usize,
+    settings: RwLock<DiagnosticsSettings>,
+    // Serializes appends and rotation
+    write_lock: Mutex<()>,
+}
+
+impl LookupRecorder {
+    pub fn new(dir: PathBuf) -> Self {
+        Self::with_limits(dir, DEFAULT_MAX_FILE_BYTES, DEFAULT_MAX_FILES)
+    }
+
+    pub fn with_limits(dir: PathBuf, max_file_bytes: u64, max_files: usize) -> Self {
+        Self {
+            dir,
+            max_file_bytes,
+            max_files: max_files.max(1),
+            settings: RwLock::new(DiagnosticsSettings::default()),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn settings(&self) -> DiagnosticsSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    pub fn set_settings(&self, settings: DiagnosticsSettings) -> Result<()> {
+        if !(0.0..=1.0).contains(&settings.sample_rate) {
+            bail!("sampleRate must be between 0 and 1");
+        }
+        *self.settings.write().unwrap() = settings;
+        Ok(())
+    }
+
+    /// Whether a lookup by `user_id` should be recorded
+    pub fn should_record(&self, user_id: Option<&str>) -> bool {
+        let settings = self.settings.read().unwrap();
+        if user_id.is_some_and(|id| settings.users.contains(id)) {
+            return true;
+        }
+        settings.sample_rate > 0.0 && fastrand::f64() < settings.sample_rate
+    }
+
+    /// `lookups.jsonl` for index 0, `lookups.{index}.jsonl` for rotated files
+    fn file_path(&self, index: usize) -> PathBuf {
+        if index == 0 {
+            self.dir.join("lookups.jsonl")
+        } else {
+            self.dir.join(format!("lookups.{index}.jsonl"))
+        }
+    }
+
+    pub async fn record(&self, record: &LookupRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let _guard = self.write_lock.lock().await;
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let current = self.file_path(0);
+        let size = match tokio::fs::metadata(&current).await {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        if size > 0 && size + line.len() as u64 > self.max_file_bytes {
+            self.rotate().await?;
+        }
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&current)
+            .await?;
+        file.write_all(&line).await?;
+        Ok(())
+    }
+
+    async fn rotate(&self) -> Result<()> {
+        for index in (0..self.max_files - 1).rev() {
+            let from = self.file_path(index);
+            if tokio::fs::try_exists(&from).await? {
+                tokio::fs::rename(&from, self.file_path(index + 1)).await?;
+            }
+        }
+        // With a single file there is nothing to rotate into
+        if self.max_files == 1 {
+            tokio::fs::remove_file(self.file_path(0)).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn files(&self) -> Result<Vec<RecordFile>> {
+        let mut files = Vec::new();
+        for index in 0..self.max_files {
+            let path = self.file_path(index);
+            if let Ok(metadata) = tokio::fs::metadata(&path).await {
+                files.push(RecordFile {
+                    name: path
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string(),
+                    size: metadata.len(),
+                });
+            }
+        }
+        Ok(files)
+    }
+
+    /// Most recent records first, optionally filtered by user and exact term
+    pub async fn recent(
+        &self,
+        user_id: Option<&str>,
+        term: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<LookupRecord>> {
+        let mut records = Vec::new();
+        for index in 0..self.max_files {
+            let data = match tokio::fs::read_to_string(self.file_path(index)).await {
+                Ok(data) => data,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for line in data.lines().rev() {
+                // A line may be cut short if the process died while writing it
+                let Ok(record) = serde_json::from_str::<LookupRecord>(line) else {
+                    continue;
+                };
+                if user_id.is_some_and(|id| record.user_id.as_deref() != Some(id))
+                    || term.is_some_and(|t| record.term() != Some(t))
+                {
+                    continue;
+                }
+                records.push(record);
+                if records.len() >= limit {
+                    return Ok(records);
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn record(user_id: &str, term: &str) -> LookupRecord {
+        LookupRecord::new(
+            Some(user_id.to_string()),
+            serde_json::json!({ "term": term, "position": 0 }),
+            404,
+            serde_json::json!({ "error": "No dictionary entries found" }),
+            Duration::from_millis(3),
+        )
+    }
+
+    #[test]
+    fn test_should_record() {
+        let recorder = LookupRecorder::new(PathBuf::from("unused"));
+        assert!(!recorder.should_record(Some("alice")));
+
+        recorder
+            .set_settings(DiagnosticsSettings {
+                sample_rate: 0.0,
+                users: BTreeSet::from(["alice".to_string()]),
+            })
+            .unwrap();
+        assert!(recorder.should_record(Some("alice")));
+        assert!(!recorder.should_record(Some("bob")));
+        assert!(!recorder.should_record(None));
+
+        recorder
+            .set_settings(DiagnosticsSettings {
+                sample_rate: 1.0,
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(recorder.should_record(None));
+        assert!(recorder
+            .set_settings(DiagnosticsSettings {
+                sample_rate: 1.5,
+                ..Default::default()
+            })
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_record_rotation_and_retrieval() {
+        let temp_dir = TempDir::new().unwrap();
+        let line_len = serde_json::to_vec(&record("alice", "食べる"))
+            .unwrap()
+            .len() as u64
+            + 1;
+        // Two records per file, at most three files
+        let recorder = LookupRecorder::with_limits(temp_dir.path().to_path_buf(), line_len * 2, 3);
+
+        for (i, term) in ["一", "二", "三", "四", "五", "六", "七"]
+            .iter()
+            .enumerate()
+        {
+            let user = if i % 2 == 0 { "alice" } else { "bob" };
+            recorder.record(&record(user, term)).await.unwrap();
+        }
+
+        let files = recorder.files().await.unwrap();
+        assert_eq!(
+            files.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(),
+            vec!["lookups.jsonl", "lookups.1.jsonl", "lookups.2.jsonl"]
+        );
+
+        // The two oldest records were rotated out
+        let terms = |records: Vec<LookupRecord>| {
+            records
+                .iter()
+                .map(|r| r.term().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            terms(recorder.recent(None, None, 10).await.unwrap()),
+            vec!["七", "六", "五", "四", "三"]
+        );
+        assert_eq!(
+            terms(recorder.recent(Some("alice"), None, 2).await.unwrap()),
+            vec!["七", "五"]
+        );
+        assert_eq!(
+            terms(recorder.recent(None, Some("四"), 10).await.unwrap()),
+            vec!["四"]
+        );
+    }
+}
@@ -1,22 +1,61 @@
+pub mod audio_decode;
+pub mod audio_packs;
+pub mod audio_trim;
 pub mod auth;
+pub mod book_export;
+pub mod book_resources;
+pub mod config;
 pub mod conversions;
+pub mod csv_dictionaries;
+pub mod custom_dictionaries;
 pub mod dict_db_scan_fs;
-pub mod dictionaries;
+pub mod dict_sandbox;
+pub mod dict_stats;
+pub mod dict_usage;
+pub mod disk_space;
+pub mod ebook_convert;
+pub mod entry_notes;
+pub mod epub;
+pub mod glossary;
+pub mod guest;
+pub mod http_util;
+pub mod import_failures;
 pub mod import_progress;
-pub mod mecab;
+pub mod integrations;
+pub mod library;
+pub mod library_search;
+pub mod library_shelf;
+pub mod loading_policy;
+pub mod lookup_cache;
+pub mod lookup_diagnostics;
+pub mod mobi;
+pub mod pdf_import;
+pub mod pronunciation;
+pub mod reading_stats;
+pub mod scan_reports;
+pub mod startup_timings;
+pub mod static_assets;
+pub mod telemetry;
+pub mod tts;
 pub mod user_preferences;
 pub mod users;
+pub mod vocab_lists;
 pub mod xml;
 pub mod zip_utils;
 
+pub use jreader_core::{
+    deinflect, dictionaries, furigana, levels, mecab, pitch, reverse_lookup, verb_pairs,
+};
+
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Error};
 use auth::AuthLayer;
 use axum::{
     extract::DefaultBodyLimit,
-    routing::{get, post},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use camino::Utf8Path;
@@ -26,7 +65,7 @@ use tokio::sync::RwLock;
 use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use user_preferences::UserPreferencesSupabase;
 use users::UsersSupabase;
@@ -35,24 +74,32 @@ pub mod http_handlers; // New module for axum handlers
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    // Initialize tracing
+    // Initialize tracing. The filter sits behind a reload layer so it can be
+    // changed at runtime through a config reload.
+    let (log_filter, log_filter_handle) =
+        tracing_subscriber::reload::Layer::new(tracing_subscriber::EnvFilter::new(
+            std::env::var("RUST_LOG").unwrap_or_else(|_| config::DEFAULT_LOG_FILTER.into()),
+        ));
     tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| {
-                "jreader_service_server=debug,jreader_service=debug,jreader_service::http_handlers=debug,yomitan_format=debug,info"
-                    .into()
-            }),
-        ))
+        .with(log_filter)
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    run_http_server().await?;
+    run_http_server(log_filter_handle).await?;
 
     Ok(())
 }
 
-async fn run_http_server() -> Result<(), Error> {
+async fn run_http_server(log_filter_handle: config::LogFilterHandle) -> Result<(), Error> {
+    let mut startup = startup_timings::StartupTimer::start();
     dotenvy::dotenv().context(format!("Failed to load .env file"))?;
+    let config = Arc::new(config::ConfigHandle::new(config::ServiceConfig::from_env()));
+    let log_levels = Arc::new(config::LogLevels::new(
+        log_filter_handle,
+        config.current().log_filter.clone(),
+    ));
+    config::spawn_log_filter_watcher(log_levels.clone(), config.subscribe());
+    startup.step("config");
     let port = 3001;
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
         .await
@@ -60,7 +107,8 @@ async fn run_http_server() -> Result<(), Error> {
     info!("🚀 Starting HTTP server on port: {port}");
 
     // Test syosetu2epub script availability early in startup
-    test_syosetu2epub_availability().await;
+    let webnovel_import = test_syosetu2epub_availability().await;
+    startup.step("syosetu2epub check");
 
     // Ensure output directory exists
     // ensure_output_directory().await;
@@ -68,29 +116,27 @@ async fn run_http_server() -> Result<(), Error> {
     let dicts_path = std::env::var("DICTS_PATH").context(format!("Failed to load DICTS_PATH"))?;
 
     let yomi_dicts = {
-        Arc::new(RwLock::new(
-            YomitanDictionaries::new(Utf8Path::new(format!("{}/db", dicts_path).as_str()))
-                .context(format!("Failed to load Yomitan dictionaries"))?,
-        ))
+        let concurrency = std::env::var("DICT_LOAD_CONCURRENCY")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or_else(dictionaries::default_load_concurrency);
+        let (dicts, summary) = YomitanDictionaries::load(
+            Utf8Path::new(format!("{}/db", dicts_path).as_str()),
+            concurrency,
+        )
+        .context(format!("Failed to load Yomitan dictionaries"))?;
+        startup.set_dictionaries(summary);
+        Arc::new(RwLock::new(dicts))
     };
+    startup.step("dictionaries");
+
+    spawn_idle_bank_closer(yomi_dicts.clone(), config.clone());
 
     let tokenizer = {
         let mecab_dict_path =
             std::env::var("MECAB_DICT_PATH").context(format!("Failed to load MECAB_DICT_PATH"))?;
         if Path::new(&mecab_dict_path).exists() {
-            let file = std::fs::File::open(mecab_dict_path.clone()).context(format!(
-                "Failed to open MeCab dictionary file: {}",
-                mecab_dict_path
-            ))?;
-            let reader = zstd::Decoder::new(file).context(format!(
-                "Failed to create zstd decoder for MeCab dictionary file: {}",
-                mecab_dict_path
-            ))?;
-            let dict = vibrato::Dictionary::read(reader).context(format!(
-                "Failed to read MeCab dictionary file: {}",
-                mecab_dict_path
-            ))?;
-            let tokenizer = vibrato::Tokenizer::new(dict);
+            let tokenizer = jreader_core::load_tokenizer(Utf8Path::new(&mecab_dict_path))?;
             info!(
                 ?mecab_dict_path,
                 "✅ Tokenizer loaded successfully, using MeCab dictionary"
@@ -102,12 +148,65 @@ async fn run_http_server() -> Result<(), Error> {
         }
     };
 
+    startup.step("tokenizer");
+
+    // Opened once and shared, the audio endpoints are disabled without it
+    let audio_db = match std::env::var("AUDIO_DB_PATH") {
+        Ok(audio_db_path) => match http_handlers::open_audio_db(&audio_db_path) {
+            Ok(audio_db) => {
+                info!(?audio_db_path, "✅ Audio database opened");
+                Some(Arc::new(audio_db))
+            }
+            Err(e) => {
+                warn!(
+                    ?e,
+                    ?audio_db_path,
+                    "⚠️ Failed to open audio database, audio is disabled"
+                );
+                None
+            }
+        },
+        Err(_) => {
+            warn!("⚠️ AUDIO_DB_PATH not set, audio is disabled");
+            None
+        }
+    };
+
+    // Synthesized audio for terms without recordings, off unless TTS_BACKEND is set
+    let tts = match tts::TtsService::from_env() {
+        Ok(Some(tts)) => {
+            info!(backend = ?tts.backend(), "✅ TTS fallback enabled");
+            Some(Arc::new(tts))
+        }
+        Ok(None) => None,
+        Err(e) => {
+            warn!(?e, "⚠️ Failed to configure TTS, TTS fallback is disabled");
+            None
+        }
+    };
+
+    startup.step("audio database");
+
+    // JLPT and Kanken lists for tagging lookups, none are bundled
+    let levels_path =
+        PathBuf::from(std::env::var("LEVELS_PATH").unwrap_or_else(|_| "./data/levels".to_string()));
+    let levels = levels::LevelData::load(&levels_path).unwrap_or_else(|e| {
+        warn!(
+            ?e,
+            ?levels_path,
+            "⚠️ Failed to load level lists, levels are disabled"
+        );
+        levels::LevelData::default()
+    });
+
     let dictionary_info = yomi_dicts.read().await.get_dictionaries_info();
 
     // Create a single shared connection pool for Supabase (optional)
     let shared_pool: Option<std::sync::Arc<_>> = match (
         std::env::var("SUPABASE_URL").ok(),
-        std::env::var("SUPABASE_PORT").ok().and_then(|p| p.parse::<u16>().ok()),
+        std::env::var("SUPABASE_PORT")
+            .ok()
+            .and_then(|p| p.parse::<u16>().ok()),
         std::env::var("SUPABASE_USER").ok(),
         std::env::var("SUPABASE_PASSWORD").ok(),
         std::env::var("SUPABASE_DATABASE").ok(),
@@ -139,6 +238,8 @@ async fn run_http_server() -> Result<(), Error> {
         }
     };
 
+    startup.step("database pool");
+
     // Create database services using the shared pool
     let user_preferences_db =
         user_preferences::UserPreferencesSupabase::new(shared_pool.clone(), dictionary_info);
@@ -150,13 +251,101 @@ async fn run_http_server() -> Result<(), Error> {
     let import_progress_manager = Arc::new(ImportProgressManager::new());
     info!("✅ Import progress manager created");
 
+    let dict_usage = Arc::new(dict_usage::DictionaryUsageTracker::new(shared_pool.clone()));
+    dict_usage.spawn_flush_task(config.subscribe());
+    info!("✅ Dictionary usage tracker created");
+
+    let vocab_lists_db = vocab_lists::VocabListsSupabase::new(shared_pool.clone());
+    info!("✅ Vocab lists database service created");
+
+    let entry_notes_db = entry_notes::EntryNotesSupabase::new(shared_pool.clone());
+
+    // Known words synced from learning services, 0 minutes turns off the
+    // scheduled sync
+    let integrations = Arc::new(
+        integrations::IntegrationsSupabase::new(shared_pool.clone())
+            .context("Failed to create integrations")?,
+    );
+    match std::env::var("INTEGRATION_SYNC_MINUTES")
+        .ok()
+        .and_then(|minutes| minutes.trim().parse::<u64>().ok())
+    {
+        Some(0) => info!("Scheduled integration sync is disabled"),
+        Some(minutes) => integrations.spawn_sync_task(Duration::from_secs(minutes * 60)),
+        None => integrations.spawn_sync_task(integrations::DEFAULT_SYNC_INTERVAL),
+    }
+
+    // Extracted books are written by the frontend; the service only reads them
+    let uploads_dir = std::env::var("UPLOADS_DIR").ok().map(PathBuf::from);
+    if uploads_dir.is_none() {
+        warn!("⚠️ UPLOADS_DIR not set, library jobs are disabled");
+    }
+    let library_data_dir =
+        std::env::var("LIBRARY_DATA_DIR").unwrap_or_else(|_| "./data/library".to_string());
+    let library_frequency = library::LibraryFrequencyStore::new(PathBuf::from(&library_data_dir));
+    let glossaries = glossary::GlossaryStore::new(PathBuf::from(&library_data_dir));
+    let library_search = library_search::LibrarySearch::new(PathBuf::from(&library_data_dir));
+    let library_shelves = library_shelf::LibraryShelfStore::new(PathBuf::from(&library_data_dir));
+    let reading_stats = reading_stats::ReadingStatsStore::new(PathBuf::from(&library_data_dir));
+    let custom_dictionaries =
+        custom_dictionaries::CustomDictionaryStore::new(PathBuf::from(&library_data_dir));
+    let book_resources = book_resources::BookResourceCache::new(PathBuf::from(
+        std::env::var("BOOK_RESOURCE_CACHE_DIR")
+            .unwrap_or_else(|_| "./data/resource-cache".to_string()),
+    ));
+    let lookup_recorder = lookup_diagnostics::LookupRecorder::new(PathBuf::from(
+        std::env::var("LOOKUP_DIAGNOSTICS_DIR")
+            .unwrap_or_else(|_| "./data/diagnostics".to_string()),
+    ));
+
+    // Tokenizing and parsing dictionary entries is CPU-bound, as many lookups
+    // as there are cores do it at once by default
+    let lookup_blocking_limit = std::env::var("LOOKUP_BLOCKING_LIMIT")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|limit: &usize| *limit > 0)
+        .unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(usize::from)
+                .unwrap_or(4)
+        });
+
+    startup.step("services");
+
     // Create the context
     let context = Arc::new(http_handlers::LookupTermContext {
         yomi_dicts,
         tokenizer,
+        lookup_permits: Arc::new(tokio::sync::Semaphore::new(lookup_blocking_limit)),
+        audio_db: arc_swap::ArcSwapOption::new(audio_db),
+        tts,
         user_preferences_db: Arc::new(RwLock::new(user_preferences_db)),
         users_db: Arc::new(users_db),
         import_progress_manager,
+        dict_usage,
+        telemetry: Arc::new(telemetry::Telemetry::new()),
+        vocab_lists_db: Arc::new(vocab_lists_db),
+        entry_notes_db: Arc::new(entry_notes_db),
+        integrations,
+        uploads_dir,
+        webnovel_import,
+        levels: Arc::new(levels),
+        library_jobs: Arc::new(library::LibraryJobManager::new()),
+        library_frequency: Arc::new(library_frequency),
+        config,
+        log_levels,
+        lookup_recorder: Arc::new(lookup_recorder),
+        lookup_cache: Arc::new(lookup_cache::LookupCache::new(
+            lookup_cache::DEFAULT_TTL,
+            lookup_cache::DEFAULT_CAPACITY,
+        )),
+        glossaries: Arc::new(glossaries),
+        library_search: Arc::new(library_search),
+        library_shelves: Arc::new(library_shelves),
+        reading_stats: Arc::new(reading_stats),
+        custom_dictionaries: Arc::new(custom_dictionaries),
+        book_resources: Arc::new(book_resources),
+        startup_timings: Arc::new(startup.finish()),
     });
 
     // Configure CORS
@@ -167,18 +356,31 @@ async fn run_http_server() -> Result<(), Error> {
 
     let auth_layer = AuthLayer::new().context(format!("Failed to load AuthLayer"))?;
 
-    // Create a router for dictionary uploads with higher limit
+    // Create a router for dictionary and audio pack uploads with higher limit
     let dict_router = Router::new()
         .route("/api/upload-dict", post(http_handlers::upload_dict))
-        .layer(DefaultBodyLimit::max(1024 * 1024 * 500)); // 500MB for dictionaries
+        .route(
+            "/api/upload-audio-pack",
+            post(http_handlers::upload_audio_pack),
+        )
+        // Streamed to disk, with the size limit from the config checked as it arrives
+        .layer(DefaultBodyLimit::disable());
 
     // Create authenticated API router
     let api_router = Router::new()
         .route("/api/upload", post(http_handlers::upload_book))
+        .route(
+            "/api/upload/convert",
+            post(http_handlers::start_book_conversion),
+        )
+        .route(
+            "/api/upload/convert/:import_id",
+            get(http_handlers::download_converted_book),
+        )
         .route("/api/webnovel", post(http_handlers::webnovel_start))
         .route("/api/webnovel", get(http_handlers::webnovel_fetch))
         .route(
-            "/api/webnovel/download/:filename",
+            "/api/webnovel/download/:import_id/:filename",
             get(http_handlers::download_webnovel_file),
         )
         .route(
@@ -201,50 +403,394 @@ async fn run_http_server() -> Result<(), Error> {
             "/api/import-progress/:import_id/update",
             post(http_handlers::update_import_progress),
         )
+        .route("/api/audio/batch", post(http_handlers::get_audio_batch))
+        .route("/api/audio/sign", post(http_handlers::sign_audio_urls))
+        .route("/api/audio/stats", get(http_handlers::get_audio_stats))
+        .route("/api/audio/reload", post(http_handlers::reload_audio_db))
+        .route(
+            "/api/pronunciation/compare",
+            post(http_handlers::compare_pronunciation),
+        )
         .route("/api/hello", get(http_handlers::say_hello))
         .route("/api/print-dicts", get(http_handlers::print_dicts))
         .route("/api/scan-dicts", get(http_handlers::scan_dicts))
+        .route("/api/dicts/stats", get(http_handlers::get_dict_stats))
+        .route(
+            "/api/dicts/coverage",
+            post(http_handlers::get_dict_coverage),
+        )
+        .route(
+            "/api/reverse-lookup/dictionaries",
+            get(http_handlers::get_reverse_lookup_dictionaries),
+        )
+        .route(
+            "/api/admin/dictionaries/sandbox",
+            post(http_handlers::evaluate_dict_sandbox),
+        )
+        .route(
+            "/api/admin/dictionaries/reverse-index",
+            post(http_handlers::build_reverse_lookup_indexes),
+        )
+        .route("/api/admin/dicts/usage", get(http_handlers::get_dict_usage))
+        .route("/api/admin/telemetry", get(http_handlers::get_telemetry))
+        .route(
+            "/api/admin/dictionaries/static-assets",
+            get(http_handlers::get_static_asset_report),
+        )
+        .route(
+            "/api/admin/dictionaries/import-failures",
+            get(http_handlers::get_import_failures),
+        )
+        .route(
+            "/api/admin/dictionaries/import-failures/:id",
+            get(http_handlers::get_import_failure),
+        )
+        .route(
+            "/api/admin/scan-reports",
+            get(http_handlers::get_scan_reports),
+        )
+        .route(
+            "/api/admin/scan-reports/:id",
+            get(http_handlers::get_scan_report),
+        )
+        .route("/api/admin/config", get(http_handlers::get_config))
+        .route(
+            "/api/admin/startup",
+            get(http_handlers::get_startup_timings),
+        )
+        .route(
+            "/api/admin/dictionaries/import/frequency-csv",
+            post(http_handlers::import_frequency_csv),
+        )
+        .route(
+            "/api/admin/dictionaries/import/pitch-csv",
+            post(http_handlers::import_pitch_csv),
+        )
+        .route(
+            "/api/admin/lookup-diagnostics",
+            get(http_handlers::get_lookup_diagnostics).put(http_handlers::set_lookup_diagnostics),
+        )
+        .route(
+            "/api/admin/lookup-diagnostics/records",
+            get(http_handlers::get_lookup_records),
+        )
+        .route(
+            "/api/admin/log-level",
+            get(http_handlers::get_log_levels)
+                .put(http_handlers::set_log_level)
+                .delete(http_handlers::clear_log_levels),
+        )
+        .route(
+            "/api/admin/config/reload",
+            post(http_handlers::reload_config),
+        )
+        .route(
+            "/api/vocab-lists",
+            post(http_handlers::create_vocab_list).get(http_handlers::get_vocab_lists),
+        )
+        .route(
+            "/api/vocab-lists/:list_id",
+            get(http_handlers::get_vocab_list)
+                .patch(http_handlers::rename_vocab_list)
+                .delete(http_handlers::delete_vocab_list),
+        )
+        .route(
+            "/api/vocab-lists/:list_id/entries",
+            post(http_handlers::add_vocab_list_entry),
+        )
+        .route(
+            "/api/vocab-lists/:list_id/entries/:entry_id",
+            delete(http_handlers::remove_vocab_list_entry),
+        )
+        .route(
+            "/api/vocab-lists/:list_id/reorder",
+            post(http_handlers::reorder_vocab_list),
+        )
+        .route(
+            "/api/vocab-lists/:list_id/share",
+            post(http_handlers::share_vocab_list).delete(http_handlers::unshare_vocab_list),
+        )
+        .route(
+            "/api/vocab-lists/:list_id/visibility",
+            post(http_handlers::set_vocab_list_visibility),
+        )
+        .route(
+            "/api/custom-dictionaries",
+            post(http_handlers::create_custom_dictionary)
+                .get(http_handlers::get_custom_dictionaries),
+        )
+        .route(
+            "/api/custom-dictionaries/:dict_id",
+            get(http_handlers::get_custom_dictionary)
+                .delete(http_handlers::delete_custom_dictionary),
+        )
+        .route(
+            "/api/custom-dictionaries/:dict_id/entries",
+            post(http_handlers::add_custom_dictionary_entry),
+        )
+        .route(
+            "/api/custom-dictionaries/:dict_id/entries/:entry_id",
+            put(http_handlers::update_custom_dictionary_entry)
+                .delete(http_handlers::delete_custom_dictionary_entry),
+        )
+        .route(
+            "/api/entry-notes",
+            post(http_handlers::create_entry_note).get(http_handlers::get_entry_notes),
+        )
+        .route(
+            "/api/entry-notes/:note_id",
+            patch(http_handlers::update_entry_note).delete(http_handlers::delete_entry_note),
+        )
+        .route("/api/integrations", get(http_handlers::get_integrations))
+        .route(
+            "/api/integrations/:provider",
+            put(http_handlers::connect_integration).delete(http_handlers::disconnect_integration),
+        )
+        .route(
+            "/api/integrations/:provider/sync",
+            post(http_handlers::sync_integration),
+        )
+        .route(
+            "/api/integrations/:provider/import",
+            post(http_handlers::import_integration),
+        )
+        .route(
+            "/api/decks/public/:list_id/clone",
+            post(http_handlers::clone_public_deck),
+        )
+        .route(
+            "/api/library/frequency",
+            post(http_handlers::start_library_frequency_job)
+                .get(http_handlers::download_library_frequency),
+        )
+        .route(
+            "/api/library/frequency/import",
+            post(http_handlers::import_library_frequency)
+                .delete(http_handlers::remove_library_frequency_import),
+        )
+        .route(
+            "/api/library/books/:upload_id/glossary",
+            post(http_handlers::start_glossary_job).get(http_handlers::get_glossary),
+        )
+        .route("/api/books/merge", post(http_handlers::merge_books))
+        .route("/api/books/pdf", post(http_handlers::import_pdf_book))
+        .route("/api/library/books", get(http_handlers::get_library_books))
+        .route(
+            "/api/library/books/:upload_id",
+            patch(http_handlers::update_library_book),
+        )
+        .route(
+            "/api/library/collections",
+            post(http_handlers::create_library_collection)
+                .get(http_handlers::get_library_collections),
+        )
+        .route(
+            "/api/library/collections/:collection_id",
+            patch(http_handlers::rename_library_collection)
+                .delete(http_handlers::delete_library_collection),
+        )
+        .route(
+            "/api/library/difficulty",
+            post(http_handlers::start_library_difficulty_job),
+        )
+        .route(
+            "/api/book/:upload_id/resource/*path",
+            get(http_handlers::get_book_resource).options(http_handlers::media_options),
+        )
+        .route(
+            "/api/book/:upload_id/resource-urls",
+            post(http_handlers::sign_book_resource_urls),
+        )
+        .route(
+            "/api/library/books/:upload_id/export",
+            get(http_handlers::export_book),
+        )
+        .route("/api/library/jobs", get(http_handlers::get_library_jobs))
+        .route("/api/stats/reading", post(http_handlers::record_reading))
+        .route(
+            "/api/stats/export",
+            get(http_handlers::export_reading_stats),
+        )
+        .route(
+            "/api/preferences/reader",
+            get(http_handlers::get_reader_preferences).put(http_handlers::put_reader_preferences),
+        )
+        .route(
+            "/api/preferences/popup",
+            get(http_handlers::get_popup_preferences).put(http_handlers::put_popup_preferences),
+        )
+        .route(
+            "/api/preferences/audio",
+            get(http_handlers::get_audio_preferences).put(http_handlers::put_audio_preferences),
+        )
+        .route("/api/examples", get(http_handlers::get_examples))
+        .route(
+            "/api/library/jobs/:job_id",
+            get(http_handlers::get_library_job),
+        )
         .merge(dict_router) // Merge the dictionary router
         .layer(DefaultBodyLimit::max(1024 * 1024 * 250)) // 250MB for books
         .with_state(context.clone())
         .layer(auth_layer);
 
-    // Create main router with static file serving (no auth) and authenticated API routes
+    // Create main router with static file serving and authenticated API routes
     let static_path = format!("{}/static", dicts_path);
     info!("Serving static files from: {}", static_path);
 
     // Create a router for audio files with authentication
     let audio_auth_layer = AuthLayer::new().context("Failed to load AuthLayer for audio")?;
     let audio_router = Router::new()
-        .route("/audio/*path", get(http_handlers::serve_audio_file))
+        .route(
+            "/audio/*path",
+            get(http_handlers::serve_audio_file).options(http_handlers::media_options),
+        )
         .layer(audio_auth_layer);
 
     // Create a router for signed media URLs (no auth needed - signature provides auth)
     let signed_media_router = Router::new()
-        .route("/media/*path", get(http_handlers::serve_signed_media))
-        .route("/media/img/*path", get(http_handlers::serve_signed_image));
+        .route(
+            "/media/*path",
+            get(http_handlers::serve_signed_media).options(http_handlers::media_options),
+        )
+        .route(
+            "/media/img/*path",
+            get(http_handlers::serve_signed_image).options(http_handlers::media_options),
+        )
+        .route(
+            "/book-resource/:user_id/:upload_id/*path",
+            get(http_handlers::serve_signed_book_resource).options(http_handlers::media_options),
+        );
+
+    // Dictionary static files (images, CSS), with access control depending on
+    // the deployment
+    let dicts_static_access = http_handlers::DictsStaticAccess::from_env()?;
+    info!(
+        ?dicts_static_access,
+        "Dictionary static file access control"
+    );
+    let dicts_router = dicts_router(
+        dicts_static_access,
+        AuthLayer::new().context("Failed to load AuthLayer for dictionary static files")?,
+    );
 
     // Create a router for health check (no auth needed)
     let health_router = Router::new().route("/healthz", get(http_handlers::health_check));
 
-    let app = Router::new()
-        .route("/dicts/*path", get(http_handlers::serve_static_file))
+    // Routes that answer without a login, with guest limits for requests
+    // that don't have one
+    let guest_layer = guest::GuestLayer::new(
+        AuthLayer::new()
+            .context("Failed to load AuthLayer for guests")?
+            .auth_service,
+        context.config.clone(),
+    );
+    let public_router = Router::new()
+        .route("/api/capabilities", get(http_handlers::get_capabilities))
         .route("/api/lookup", post(http_handlers::lookup_term))
+        .route("/api/suggest", get(http_handlers::suggest))
+        .route("/api/conjugate", get(http_handlers::conjugate))
+        .route(
+            "/api/dicts/attributions",
+            get(http_handlers::get_dict_attributions),
+        )
+        .route("/api/reverse-lookup", get(http_handlers::reverse_lookup))
         .route("/api/audio", get(http_handlers::get_audio))
+        .route(
+            "/api/audio/speakers",
+            get(http_handlers::get_audio_speakers),
+        )
+        .route(
+            "/api/audio/suggestions",
+            get(http_handlers::get_audio_suggestions),
+        )
+        .route(
+            "/api/audio/display",
+            get(http_handlers::search_audio_display),
+        )
+        .route(
+            "/api/shared/vocab-lists/:share_token",
+            get(http_handlers::get_shared_vocab_list),
+        )
+        .route("/api/decks/public", get(http_handlers::get_public_decks))
+        .route(
+            "/api/decks/public/:list_id",
+            get(http_handlers::get_public_deck),
+        )
+        .layer(guest_layer);
+
+    let app = Router::new()
+        .merge(public_router)
         .merge(health_router)
+        .merge(dicts_router)
         .merge(audio_router)
         .merge(signed_media_router)
         .merge(api_router)
         .with_state(context.clone())
         .layer(cors);
 
-    axum::serve(listener, app)
-        .await
-        .context(format!("Failed to serve HTTP server"))?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .await
+    .context(format!("Failed to serve HTTP server"))?;
 
     Ok(())
 }
 
+/// Router for dictionary static files under `/dicts/*path`, with the access
+/// control of `access`
+fn dicts_router<S, A>(
+    access: http_handlers::DictsStaticAccess,
+    auth_layer: AuthLayer<A>,
+) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    A: auth::AuthService + Clone + Send + Sync + 'static,
+{
+    match access {
+        http_handlers::DictsStaticAccess::Public => {
+            Router::new().route("/dicts/*path", get(http_handlers::serve_static_file))
+        }
+        http_handlers::DictsStaticAccess::Signed => {
+            Router::new().route("/dicts/*path", get(http_handlers::serve_signed_static_file))
+        }
+        http_handlers::DictsStaticAccess::Auth => Router::new()
+            .route("/dicts/*path", get(http_handlers::serve_static_file))
+            .layer(auth_layer),
+    }
+}
+
+/// How often dictionary banks are checked for idleness
+const IDLE_BANK_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Close the dictionary bank databases no lookup has used for
+/// `DICT_IDLE_CLOSE_SECONDS`, so rarely used dictionaries don't keep file
+/// descriptors open
+fn spawn_idle_bank_closer(
+    yomi_dicts: Arc<RwLock<YomitanDictionaries>>,
+    config: Arc<config::ConfigHandle>,
+) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(IDLE_BANK_CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let Some(idle) = config.current().dict_idle_close_after() else {
+                continue;
+            };
+            let dicts = yomi_dicts.read().await;
+            let closed = dicts.close_idle(idle);
+            if closed > 0 {
+                debug!(
+                    closed,
+                    open = dicts.open_banks(),
+                    "Closed idle dictionary banks"
+                );
+            }
+        }
+    });
+}
+
 // Resolve the Python interpreter to use for running syosetu2epub script
 fn resolve_python_interpreter() -> PathBuf {
     // 1) Allow explicit override via environment variable
@@ -253,7 +799,8 @@ fn resolve_python_interpreter() -> PathBuf {
     }
 
     // 2) Prefer project venv (relative to CWD at runtime)
-    let syosetu_dir = std::env::var("SYOSETU2EPUB_DIR").unwrap_or_else(|_| "syosetu2epub".to_string());
+    let syosetu_dir =
+        std::env::var("SYOSETU2EPUB_DIR").unwrap_or_else(|_| "syosetu2epub".to_string());
     let venv_rel = PathBuf::from(&syosetu_dir).join(".venv/bin/python");
     if venv_rel.is_file() {
         // Use absolute path but don't canonicalize to avoid resolving symlinks
@@ -265,9 +812,12 @@ fn resolve_python_interpreter() -> PathBuf {
     PathBuf::from("python3")
 }
 
-async fn test_syosetu2epub_availability() {
+/// Whether webnovel imports can run: the syosetu2epub script and its Python
+/// interpreter are there and the script starts
+async fn test_syosetu2epub_availability() -> bool {
     // Get the path to the syosetu2epub script (same logic as in http_handlers)
-    let syosetu_base = std::env::var("SYOSETU2EPUB_DIR").unwrap_or_else(|_| "./syosetu2epub".to_string());
+    let syosetu_base =
+        std::env::var("SYOSETU2EPUB_DIR").unwrap_or_else(|_| "./syosetu2epub".to_string());
     let syosetu_script_path = std::env::var("SYOSETU_SCRIPT_PATH")
         .unwrap_or_else(|_| format!("{}/syosetu2epub.py", syosetu_base));
 
@@ -292,7 +842,7 @@ async fn test_syosetu2epub_availability() {
             "❌ syosetu2epub script file does not exist at: {}",
             syosetu_script_path
         );
-        return;
+        return false;
     }
 
     // Debug: Check if Python interpreter exists
@@ -301,7 +851,7 @@ async fn test_syosetu2epub_availability() {
             "❌ Python interpreter does not exist at: {}",
             python_path.display()
         );
-        return;
+        return false;
     }
 
     // Debug: Check if syosetu directory exists
@@ -310,7 +860,7 @@ async fn test_syosetu2epub_availability() {
             "❌ syosetu directory does not exist at: {}",
             syosetu_dir.display()
         );
-        return;
+        return false;
     }
 
     info!(
@@ -337,13 +887,13 @@ async fn test_syosetu2epub_availability() {
                     "⚠️ Python interpreter failed with status: {:?}",
                     output.status
                 );
-                return;
+                return false;
             }
         }
         Err(e) => {
             warn!("❌ Python interpreter not found: {}", e);
             warn!("Please ensure Python is installed and in your PATH, or set SYOSETU_PYTHON environment variable");
-            return;
+            return false;
         }
     }
 
@@ -379,17 +929,20 @@ async fn test_syosetu2epub_availability() {
                     "Script help output: {}",
                     String::from_utf8_lossy(&output.stdout)
                 );
+                true
             } else {
                 warn!(
                     "⚠️ syosetu2epub script failed with status: {:?}",
                     output.status
                 );
                 warn!("Error output: {}", String::from_utf8_lossy(&output.stderr));
+                false
             }
         }
         Err(e) => {
             warn!("❌ Failed to execute syosetu2epub script: {}", e);
             warn!("This may indicate Python is not in PATH or the script path is incorrect");
+            false
         }
     }
 }
@@ -431,3 +984,71 @@ async fn ensure_output_directory() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use http_handlers::DictsStaticAccess;
+    use tower::ServiceExt;
+
+    #[derive(Clone)]
+    struct RejectingAuth;
+
+    impl auth::AuthService for RejectingAuth {
+        async fn verify_token(&self, _token: String) -> anyhow::Result<String> {
+            anyhow::bail!("Invalid token")
+        }
+    }
+
+    async fn get_status(app: &Router, uri: &str, username: Option<&str>) -> StatusCode {
+        let mut request = Request::get(uri);
+        if let Some(username) = username {
+            request = request.header("X-Username", username);
+        }
+        app.clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_dicts_router() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("static/dict")).unwrap();
+        std::fs::write(temp_dir.path().join("static/dict/style.css"), "body {}").unwrap();
+        std::env::set_var("DICTS_PATH", temp_dir.path());
+
+        for access in [
+            DictsStaticAccess::Public,
+            DictsStaticAccess::Signed,
+            DictsStaticAccess::Auth,
+        ] {
+            let app: Router = dicts_router(
+                access,
+                AuthLayer {
+                    auth_service: RejectingAuth,
+                },
+            );
+
+            let anonymous = get_status(&app, "/dicts/dict/style.css", None).await;
+            let signed_in = get_status(&app, "/dicts/dict/style.css", Some("user")).await;
+            match access {
+                DictsStaticAccess::Public => {
+                    assert_eq!(anonymous, StatusCode::OK);
+                    assert_eq!(signed_in, StatusCode::OK);
+                }
+                DictsStaticAccess::Signed => {
+                    assert_eq!(anonymous, StatusCode::BAD_REQUEST);
+                    assert_eq!(signed_in, StatusCode::BAD_REQUEST);
+                }
+                DictsStaticAccess::Auth => {
+                    assert_eq!(anonymous, StatusCode::UNAUTHORIZED);
+                    assert_eq!(signed_in, StatusCode::OK);
+                }
+            }
+        }
+    }
+}
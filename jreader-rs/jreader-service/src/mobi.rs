@@ -0,0 +1,666 @@
+//! Native reading of MOBI and AZW3 (KF8) books, for converting them to EPUB
+//! without an external converter.
+//!
+//! Both formats are PalmDB databases: record 0 holds the PalmDOC and MOBI
+//! headers (followed by EXTH metadata), then come the compressed text records
+//! and, from the first image index on, the images. The text is HTML that is
+//! split into chapters at page breaks (or document boundaries for KF8) and
+//! reduced to plain XHTML; styling isn't carried over. Only uncompressed and
+//! PalmDOC compressed text is supported, so HUFF/CDIC compressed and
+//! DRM-protected books are refused.
+
+use anyhow::{bail, Context, Result};
+use ego_tree::NodeRef;
+use lazy_static::lazy_static;
+use regex::Regex;
+use scraper::{node::Element, Html, Node};
+use std::collections::HashMap;
+
+use crate::epub::{escape_xml, xhtml_document, EpubBuilder, NavPoint};
+use crate::library::document_title;
+
+const PALMDB_HEADER_LEN: usize = 78;
+const PALMDB_TYPE: &[u8] = b"BOOKMOBI";
+
+const COMPRESSION_NONE: u16 = 1;
+const COMPRESSION_PALMDOC: u16 = 2;
+const COMPRESSION_HUFF_CDIC: u16 = 17480;
+
+const ENCODING_CP1252: u32 = 1252;
+const ENCODING_UTF8: u32 = 65001;
+
+const EXTH_AUTHOR: u32 = 100;
+const EXTH_COVER_OFFSET: u32 = 201;
+const EXTH_TITLE: u32 = 503;
+const EXTH_LANGUAGE: u32 = 524;
+const EXTH_WRITING_MODE: u32 = 525;
+const EXTH_PAGE_PROGRESSION: u32 = 527;
+
+/// Markup kept as it is; other elements are replaced by their content
+const KEPT_ELEMENTS: &[&str] = &[
+    "p",
+    "div",
+    "blockquote",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "li",
+    "b",
+    "strong",
+    "i",
+    "em",
+    "u",
+    "sub",
+    "sup",
+    "small",
+    "span",
+    "ruby",
+    "rb",
+    "rt",
+    "rp",
+];
+const VOID_ELEMENTS: &[&str] = &["br", "hr"];
+const DROPPED_ELEMENTS: &[&str] = &["head", "title", "script", "style"];
+
+const VERTICAL_STYLESHEET: &str =
+    "html { writing-mode: vertical-rl; -epub-writing-mode: vertical-rl; }\n";
+
+lazy_static! {
+    /// MOBI page breaks, and the start of every document after the first in
+    /// KF8 text
+    static ref CHAPTER_BREAK: Regex =
+        Regex::new(r"(?i)<mbp:pagebreak[^>]*>|(?:<\?xml[^>]*>\s*)?(?:<!DOCTYPE[^>]*>\s*)?<html[\s>]")
+            .unwrap();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MobiFormat {
+    Mobi,
+    /// KF8 only, as written by Kindle Previewer and Calibre
+    Azw3,
+}
+
+impl MobiFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            MobiFormat::Mobi => "MOBI",
+            MobiFormat::Azw3 => "AZW3",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            MobiFormat::Mobi => "mobi",
+            MobiFormat::Azw3 => "azw3",
+        }
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .context("Truncated MOBI header")?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .context("Truncated MOBI header")?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Record offsets of a PalmDB database
+fn record_offsets(data: &[u8]) -> Result<Vec<usize>> {
+    if data.get(60..68) != Some(PALMDB_TYPE) {
+        bail!("Not a MOBI or AZW3 book");
+    }
+    let count = u16_at(data, 76)? as usize;
+    let offsets = (0..count)
+        .map(|i| u32_at(data, PALMDB_HEADER_LEN + i * 8).map(|offset| offset as usize))
+        .collect::<Result<Vec<_>>>()?;
+    if offsets.windows(2).any(|w| w[0] > w[1]) || offsets.last() > Some(&data.len()) {
+        bail!("Corrupt PalmDB record list");
+    }
+    Ok(offsets)
+}
+
+/// Format of a book from its first bytes, None if it isn't a MOBI or AZW3
+/// book. Books whose header lies beyond `data` are taken to be MOBI.
+pub fn detect(data: &[u8]) -> Option<MobiFormat> {
+    if data.get(60..68) != Some(PALMDB_TYPE) {
+        return None;
+    }
+    let record0 = u32_at(data, PALMDB_HEADER_LEN).ok()? as usize;
+    match u32_at(data, record0 + 36) {
+        Ok(version) if version >= 8 => Some(MobiFormat::Azw3),
+        _ => Some(MobiFormat::Mobi),
+    }
+}
+
+struct Book<'a> {
+    data: &'a [u8],
+    offsets: Vec<usize>,
+    compression: u16,
+    text_length: usize,
+    text_records: usize,
+    text_encoding: u32,
+    first_image: Option<usize>,
+    extra_data_flags: u16,
+    full_name: String,
+    exth: Vec<(u32, &'a [u8])>,
+}
+
+impl<'a> Book<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self> {
+        let offsets = record_offsets(data)?;
+        let mut book = Book {
+            data,
+            offsets,
+            compression: 0,
+            text_length: 0,
+            text_records: 0,
+            text_encoding: ENCODING_CP1252,
+            first_image: None,
+            extra_data_flags: 0,
+            full_name: String::new(),
+            exth: Vec::new(),
+        };
+        let header = book.record(0)?;
+        book.compression = u16_at(header, 0)?;
+        book.text_length = u32_at(header, 4)? as usize;
+        book.text_records = u16_at(header, 8)? as usize;
+        if u16_at(header, 12)? != 0 {
+            bail!("DRM-protected books can't be converted");
+        }
+        if header.get(16..20) != Some(b"MOBI") {
+            bail!("Missing MOBI header");
+        }
+        let header_length = u32_at(header, 20)? as usize;
+        book.text_encoding = u32_at(header, 28)?;
+        let first_image = u32_at(header, 108)?;
+        book.first_image = (first_image != u32::MAX).then_some(first_image as usize);
+        if header_length >= 0xE4 {
+            book.extra_data_flags = u16_at(header, 0xF2)?;
+        }
+
+        let name_offset = u32_at(header, 84)? as usize;
+        let name_length = u32_at(header, 88)? as usize;
+        if let Some(name) = header.get(name_offset..name_offset + name_length) {
+            book.full_name = book.decode(name)?;
+        }
+
+        let exth_flags = u32_at(header, 128)?;
+        if exth_flags & 0x40 != 0 {
+            let exth = 16 + header_length;
+            if header.get(exth..exth + 4) == Some(b"EXTH") {
+                let count = u32_at(header, exth + 8)? as usize;
+                let mut offset = exth + 12;
+                for _ in 0..count {
+                    let kind = u32_at(header, offset)?;
+                    let length = u32_at(header, offset + 4)? as usize;
+                    let value = header
+                        .get(offset + 8..offset + length.max(8))
+                        .context("Truncated EXTH record")?;
+                    book.exth.push((kind, value));
+                    offset += length.max(8);
+                }
+            }
+        }
+        Ok(book)
+    }
+
+    fn record(&self, index: usize) -> Result<&'a [u8]> {
+        let start = *self
+            .offsets
+            .get(index)
+            .with_context(|| format!("Missing record {index}"))?;
+        let end = self
+            .offsets
+            .get(index + 1)
+            .copied()
+            .unwrap_or(self.data.len());
+        Ok(&self.data[start..end])
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<String> {
+        match self.text_encoding {
+            ENCODING_UTF8 => Ok(String::from_utf8_lossy(bytes).into_owned()),
+            ENCODING_CP1252 => Ok(decode_cp1252(bytes)),
+            encoding => bail!("Unsupported text encoding {encoding}"),
+        }
+    }
+
+    fn exth_values(&self, kind: u32) -> impl Iterator<Item = String> + '_ {
+        self.exth
+            .iter()
+            .filter(move |(k, _)| *k == kind)
+            .filter_map(|(_, value)| self.decode(value).ok())
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn text(&self) -> Result<String> {
+        let mut text = Vec::with_capacity(self.text_length);
+        for index in 1..=self.text_records {
+            let record = trim_trailing_entries(self.record(index)?, self.extra_data_flags);
+            match self.compression {
+                COMPRESSION_NONE => text.extend_from_slice(record),
+                COMPRESSION_PALMDOC => text.extend(palmdoc_decompress(record)),
+                COMPRESSION_HUFF_CDIC => {
+                    bail!("HUFF/CDIC compressed books need an external converter")
+                }
+                compression => bail!("Unknown compression {compression}"),
+            }
+        }
+        text.truncate(self.text_length);
+        self.decode(&text)
+    }
+
+    /// Image number `n` (1-based, as text references images), if the record is
+    /// an image
+    fn image(&self, n: usize) -> Option<(&'a [u8], &'static str, &'static str)> {
+        let record = self.record(self.first_image? + n.checked_sub(1)?).ok()?;
+        let (media_type, extension) = if record.starts_with(&[0xFF, 0xD8]) {
+            ("image/jpeg", "jpg")
+        } else if record.starts_with(b"\x89PNG") {
+            ("image/png", "png")
+        } else if record.starts_with(b"GIF8") {
+            ("image/gif", "gif")
+        } else {
+            return None;
+        };
+        Some((record, media_type, extension))
+    }
+}
+
+fn decode_cp1252(bytes: &[u8]) -> String {
+    const HIGH: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž',
+        '\u{8f}', '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}',
+        'ž', 'Ÿ',
+    ];
+    bytes
+        .iter()
+        .map(|&b| match b {
+            0x80..=0x9F => HIGH[(b - 0x80) as usize],
+            b => b as char,
+        })
+        .collect()
+}
+
+/// Strip the trailing entries that follow the text of a record. Each flag bit
+/// above the lowest marks an entry ending in its own size; the lowest marks
+/// the bytes of a character continued in the next record.
+fn trim_trailing_entries(mut record: &[u8], flags: u16) -> &[u8] {
+    for _ in 0..(flags >> 1).count_ones() {
+        let mut size = 0usize;
+        for &b in &record[record.len().saturating_sub(4)..] {
+            if b & 0x80 != 0 {
+                size = 0;
+            }
+            size = (size << 7) | (b & 0x7F) as usize;
+        }
+        record = &record[..record.len().saturating_sub(size)];
+    }
+    if flags & 1 != 0 {
+        if let Some(&last) = record.last() {
+            record = &record[..record.len().saturating_sub((last & 3) as usize + 1)];
+        }
+    }
+    record
+}
+
+/// Decompress a PalmDOC (LZ77) compressed record
+fn palmdoc_decompress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() * 2);
+    let mut i = 0;
+    while i < data.len() {
+        let c = data[i];
+        i += 1;
+        match c {
+            // The next c bytes are literals
+            0x01..=0x08 => {
+                let end = (i + c as usize).min(data.len());
+                out.extend_from_slice(&data[i..end]);
+                i = end;
+            }
+            // A space followed by an ASCII character
+            0xC0..=0xFF => {
+                out.push(b' ');
+                out.push(c ^ 0x80);
+            }
+            // A back reference: 11 bits of distance, 3 bits of length - 3
+            0x80..=0xBF => {
+                let Some(&next) = data.get(i) else { break };
+                i += 1;
+                let pair = ((c as usize) << 8) | next as usize;
+                let distance = (pair >> 3) & 0x7FF;
+                let length = (pair & 7) + 3;
+                if distance == 0 || distance > out.len() {
+                    continue;
+                }
+                let start = out.len() - distance;
+                // The source may overlap what is being written
+                for j in 0..length {
+                    out.push(out[start + j]);
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Number of an image referenced by an `<img>`: MOBI uses `recindex`, KF8 a
+/// base 32 `kindle:embed:` URL
+fn image_number(element: &Element) -> Option<usize> {
+    if let Some(recindex) = element.attr("recindex") {
+        return recindex.trim().parse().ok();
+    }
+    let embed = element.attr("src")?.strip_prefix("kindle:embed:")?;
+    usize::from_str_radix(embed.split('?').next()?, 32).ok()
+}
+
+struct Converter<'a> {
+    book: &'a Book<'a>,
+    builder: EpubBuilder,
+    /// Image number -> (path, manifest id), None for records that aren't
+    /// images
+    images: HashMap<usize, Option<(String, String)>>,
+}
+
+impl Converter<'_> {
+    /// Add image `n` to the book if it isn't yet
+    fn image(&mut self, n: usize) -> Result<Option<(String, String)>> {
+        if let Some(image) = self.images.get(&n) {
+            return Ok(image.clone());
+        }
+        let image = match self.book.image(n) {
+            Some((data, media_type, extension)) => {
+                let href = format!("images/image{n}.{extension}");
+                let id = self
+                    .builder
+                    .add_item(&href, media_type, None, data.to_vec())?;
+                Some((href, id))
+            }
+            None => None,
+        };
+        self.images.insert(n, image.clone());
+        Ok(image)
+    }
+
+    fn write_children(&mut self, node: NodeRef<'_, Node>, out: &mut String) -> Result<()> {
+        for child in node.children() {
+            match child.value() {
+                Node::Text(text) => out.push_str(&escape_xml(text)),
+                Node::Element(e) if DROPPED_ELEMENTS.contains(&e.name()) => {}
+                Node::Element(e) if VOID_ELEMENTS.contains(&e.name()) => {
+                    out.push_str(&format!("<{}/>", e.name()));
+                }
+                Node::Element(e) if e.name() == "img" => {
+                    let image = match image_number(e) {
+                        Some(n) => self.image(n)?,
+                        None => None,
+                    };
+                    if let Some((href, _)) = image {
+                        out.push_str(&format!("<img src=\"../{}\" alt=\"\"/>", escape_xml(&href)));
+                    }
+                }
+                Node::Element(e) if KEPT_ELEMENTS.contains(&e.name()) => {
+                    out.push_str(&format!("<{}>", e.name()));
+                    self.write_children(child, out)?;
+                    out.push_str(&format!("</{}>", e.name()));
+                }
+                _ => self.write_children(child, out)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Convert a MOBI or AZW3 book to an EPUB
+pub fn to_epub(data: &[u8]) -> Result<EpubBuilder> {
+    let book = Book::parse(data)?;
+    let title = book
+        .exth_values(EXTH_TITLE)
+        .next()
+        .unwrap_or_else(|| book.full_name.trim().to_string());
+    let mut builder = EpubBuilder::new(&title);
+    for author in book.exth_values(EXTH_AUTHOR) {
+        builder.creator(&author);
+    }
+    let language = book
+        .exth_values(EXTH_LANGUAGE)
+        .next()
+        .unwrap_or_else(|| "ja".to_string());
+    builder.language(&language);
+
+    let vertical = book
+        .exth_values(EXTH_WRITING_MODE)
+        .any(|mode| mode.starts_with("vertical"));
+    let rtl = book
+        .exth_values(EXTH_PAGE_PROGRESSION)
+        .any(|direction| direction == "rtl");
+    if vertical || rtl {
+        builder.page_progression_direction("rtl");
+    }
+    let stylesheet = if vertical {
+        let href = "styles/vertical.css";
+        builder.add_item(href, "text/css", None, VERTICAL_STYLESHEET.into())?;
+        Some(href)
+    } else {
+        None
+    };
+
+    let text = book.text()?;
+    let mut converter = Converter {
+        book: &book,
+        builder,
+        images: HashMap::new(),
+    };
+    if let Some((_, offset)) = book.exth.iter().find(|(k, _)| *k == EXTH_COVER_OFFSET) {
+        if let Some((_, id)) = converter.image(u32_at(offset, 0)? as usize + 1)? {
+            converter.builder.set_cover(&id);
+        }
+    }
+
+    let mut toc = Vec::new();
+    let mut chapters = 0;
+    let mut start = 0;
+    let breaks = CHAPTER_BREAK
+        .find_iter(&text)
+        .map(|m| {
+            let is_page_break = m.as_str()[..4].eq_ignore_ascii_case("<mbp");
+            (m.start(), m.end(), is_page_break)
+        })
+        .chain(std::iter::once((text.len(), text.len(), true)));
+    for (break_start, break_end, is_page_break) in breaks {
+        let part = &text[start..break_start];
+        // A document boundary belongs to the document that follows it
+        start = if is_page_break {
+            break_end
+        } else {
+            break_start
+        };
+        let document = Html::parse_document(part);
+        let mut body = String::new();
+        converter.write_children(document.tree.root(), &mut body)?;
+        if !body.contains("<img") && body.chars().all(char::is_whitespace) {
+            continue;
+        }
+        chapters += 1;
+        let href = format!("text/chapter{chapters}.xhtml");
+        let label = document_title(part);
+        let xhtml = xhtml_document(
+            label.as_deref().unwrap_or(&title),
+            &language,
+            body.trim(),
+            stylesheet,
+        );
+        let id =
+            converter
+                .builder
+                .add_item(&href, "application/xhtml+xml", None, xhtml.into_bytes())?;
+        converter.builder.add_to_spine(&id);
+        if let Some(label) = label {
+            toc.push(NavPoint {
+                label,
+                href,
+                children: Vec::new(),
+            });
+        }
+    }
+    if chapters == 0 {
+        bail!("The book has no text");
+    }
+    if toc.is_empty() {
+        toc.push(NavPoint {
+            label: title,
+            href: "text/chapter1.xhtml".to_string(),
+            children: Vec::new(),
+        });
+    }
+    converter.builder.set_toc(toc);
+    Ok(converter.builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const JPEG: &[u8] = &[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, b'J', b'F', b'I', b'F'];
+
+    /// A MOBI book with uncompressed UTF-8 text in a single record, followed by
+    /// `images`
+    fn mobi(text: &str, exth: &[(u32, &[u8])], images: &[&[u8]]) -> Vec<u8> {
+        let mut exth_data = Vec::new();
+        for (kind, value) in exth {
+            exth_data.extend_from_slice(&kind.to_be_bytes());
+            exth_data.extend_from_slice(&(value.len() as u32 + 8).to_be_bytes());
+            exth_data.extend_from_slice(value);
+        }
+        let full_name = b"Full name";
+
+        let mut header = vec![0u8; 16 + 0xE8];
+        header[0..2].copy_from_slice(&COMPRESSION_NONE.to_be_bytes());
+        header[4..8].copy_from_slice(&(text.len() as u32).to_be_bytes());
+        header[8..10].copy_from_slice(&1u16.to_be_bytes());
+        header[16..20].copy_from_slice(b"MOBI");
+        header[20..24].copy_from_slice(&0xE8u32.to_be_bytes());
+        header[28..32].copy_from_slice(&ENCODING_UTF8.to_be_bytes());
+        header[36..40].copy_from_slice(&6u32.to_be_bytes());
+        let name_offset = header.len() + 12 + exth_data.len();
+        header[84..88].copy_from_slice(&(name_offset as u32).to_be_bytes());
+        header[88..92].copy_from_slice(&(full_name.len() as u32).to_be_bytes());
+        header[108..112].copy_from_slice(&2u32.to_be_bytes());
+        header[128..132].copy_from_slice(&0x40u32.to_be_bytes());
+        header.extend_from_slice(b"EXTH");
+        header.extend_from_slice(&(exth_data.len() as u32 + 12).to_be_bytes());
+        header.extend_from_slice(&(exth.len() as u32).to_be_bytes());
+        header.extend_from_slice(&exth_data);
+        header.extend_from_slice(full_name);
+
+        let records: Vec<&[u8]> = [&header[..], text.as_bytes()]
+            .into_iter()
+            .chain(images.iter().copied())
+            .collect();
+        let mut data = vec![0u8; PALMDB_HEADER_LEN + records.len() * 8];
+        data[60..68].copy_from_slice(PALMDB_TYPE);
+        data[76..78].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        for (i, record) in records.iter().enumerate() {
+            let offset = data.len() as u32;
+            data[PALMDB_HEADER_LEN + i * 8..][..4].copy_from_slice(&offset.to_be_bytes());
+            data.extend_from_slice(record);
+        }
+        data
+    }
+
+    #[test]
+    fn test_palmdoc_decompress() {
+        // Literals, a back reference overlapping its output, a space pair and
+        // escaped bytes
+        let compressed = [b'a', b'b', b'c', 0x80, 0x1A, 0xE4, 0x02, 0xC9, 0x80];
+        assert_eq!(
+            palmdoc_decompress(&compressed),
+            b"abcabcab d\xC9\x80".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_trim_trailing_entries() {
+        // Two bytes of a split character, then an entry one byte long
+        let record = b"text\xE3\x01\x81";
+        assert_eq!(trim_trailing_entries(record, 0b11), b"text");
+        assert_eq!(trim_trailing_entries(record, 0), record);
+    }
+
+    #[test]
+    fn test_to_epub() {
+        let text = concat!(
+            "<html><head><title>ignored</title></head><body>",
+            "<h1>第一章</h1><p>吾輩は<ruby>猫<rt>ねこ</rt></ruby>である。</p>",
+            "<p><img recindex=\"00001\"/></p>",
+            "<mbp:pagebreak/>",
+            "<h2>第二章</h2><p>名前は<font size=\"3\">まだ</font>無い。</p>",
+            "<mbp:pagebreak/>",
+            "</body></html>",
+        );
+        let data = mobi(
+            text,
+            &[
+                (EXTH_TITLE, "吾輩は猫である".as_bytes()),
+                (EXTH_AUTHOR, "夏目漱石".as_bytes()),
+                (EXTH_WRITING_MODE, b"vertical-rl"),
+                (EXTH_COVER_OFFSET, &0u32.to_be_bytes()),
+            ],
+            &[JPEG],
+        );
+        assert_eq!(detect(&data), Some(MobiFormat::Mobi));
+        assert_eq!(detect(b"PK\x03\x04"), None);
+
+        let builder = to_epub(&data).unwrap();
+        assert_eq!(builder.title(), "吾輩は猫である");
+        assert_eq!(builder.creators(), ["夏目漱石"]);
+        assert_eq!(
+            builder.spine_hrefs(),
+            ["text/chapter1.xhtml", "text/chapter2.xhtml"]
+        );
+        let labels: Vec<&str> = builder.toc().iter().map(|p| p.label.as_str()).collect();
+        assert_eq!(labels, ["第一章", "第二章"]);
+
+        let temp_dir = TempDir::new().unwrap();
+        builder.write_dir(temp_dir.path()).unwrap();
+        let content = temp_dir.path().join("OEBPS");
+        let chapter1 = std::fs::read_to_string(content.join("text/chapter1.xhtml")).unwrap();
+        assert!(chapter1.contains("<p>吾輩は<ruby>猫<rt>ねこ</rt></ruby>である。</p>"));
+        assert!(chapter1.contains("<img src=\"../images/image1.jpg\" alt=\"\"/>"));
+        assert!(chapter1.contains("styles/vertical.css"));
+        assert!(!chapter1.contains("ignored"));
+        let chapter2 = std::fs::read_to_string(content.join("text/chapter2.xhtml")).unwrap();
+        assert!(chapter2.contains("<p>名前はまだ無い。</p>"));
+        assert_eq!(
+            std::fs::read(content.join("images/image1.jpg")).unwrap(),
+            JPEG
+        );
+        let opf = std::fs::read_to_string(content.join("content.opf")).unwrap();
+        assert!(opf.contains("page-progression-direction=\"rtl\""));
+        assert!(opf.contains("properties=\"cover-image\""));
+    }
+
+    #[test]
+    fn test_refuses_drm() {
+        let mut data = mobi("<p>本文</p>", &[], &[]);
+        let record0 = u32_at(&data, PALMDB_HEADER_LEN).unwrap() as usize;
+        data[record0 + 12] = 0;
+        data[record0 + 13] = 2;
+        let error = to_epub(&data).err().unwrap().to_string();
+        assert!(error.contains("DRM"), "{error}");
+    }
+}
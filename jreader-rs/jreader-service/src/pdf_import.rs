@@ -0,0 +1,242 @@
+//! Import of PDFs with a text layer (e.g. scanned light novels run through
+//! OCR) as books of the library.
+//!
+//! The text of every page is extracted and cleaned up, and every
+//! `pages_per_chapter` pages become a chapter of a new EPUB. The page images
+//! aren't kept, so PDFs without a text layer have nothing to import.
+
+use anyhow::{bail, Context, Result};
+use lopdf::{Document, Object};
+use tracing::warn;
+
+use crate::epub::{escape_xml, xhtml_document, EpubBuilder, NavPoint};
+use crate::glossary::is_japanese_char;
+
+pub const DEFAULT_PAGES_PER_CHAPTER: usize = 10;
+pub const MAX_PAGES_PER_CHAPTER: usize = 100;
+
+/// Text of a PDF, page by page
+#[derive(Debug, Default)]
+pub struct PdfText {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub pages: Vec<String>,
+}
+
+/// Decode a PDF text string: UTF-16BE or UTF-8 with a byte order mark, or
+/// PDFDocEncoding, which agrees with Latin-1 for printable characters
+fn decode_text_string(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else if let Some(utf8) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        String::from_utf8_lossy(utf8).into_owned()
+    } else {
+        bytes.iter().map(|&b| b as char).collect()
+    }
+}
+
+/// Extract the text of every page of a PDF
+pub fn read_pdf(data: &[u8]) -> Result<PdfText> {
+    let document = Document::load_mem(data).context("Invalid PDF")?;
+    if document.is_encrypted() {
+        bail!("Encrypted PDFs can't be imported");
+    }
+    let pages = document
+        .get_pages()
+        .into_keys()
+        .map(|number| {
+            document.extract_text(&[number]).unwrap_or_else(|e| {
+                warn!(?e, page = number, "⚠️ Failed to extract PDF page text");
+                String::new()
+            })
+        })
+        .collect();
+
+    let info = document
+        .trailer
+        .get(b"Info")
+        .and_then(Object::as_reference)
+        .and_then(|id| document.get_dictionary(id))
+        .ok();
+    let field = |key: &[u8]| {
+        let value = decode_text_string(info?.get(key).ok()?.as_str().ok()?);
+        let value = value.trim();
+        (!value.is_empty()).then(|| value.to_string())
+    };
+    Ok(PdfText {
+        title: field(b"Title"),
+        author: field(b"Author"),
+        pages,
+    })
+}
+
+/// Japanese text, including its punctuation and full-width forms
+fn is_cjk(c: char) -> bool {
+    is_japanese_char(c) || matches!(c, '\u{3000}'..='\u{303F}' | '\u{FF00}'..='\u{FFEF}')
+}
+
+/// Remove the spaces OCR puts between Japanese characters
+fn remove_cjk_spaces(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut result = String::with_capacity(line.len());
+    for (i, &c) in chars.iter().enumerate() {
+        if c == ' ' {
+            let previous = chars[..i].iter().rev().find(|c| **c != ' ');
+            let next = chars[i + 1..].iter().find(|c| **c != ' ');
+            if previous.is_some_and(|c| is_cjk(*c)) && next.is_some_and(|c| is_cjk(*c)) {
+                continue;
+            }
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Paragraphs of the text of a page. Text layers break lines where the page
+/// does, so lines are joined back up: a paragraph only ends at a blank line or
+/// where the next line is indented or starts a quote, as Japanese paragraphs
+/// do. Lines of just a page number are dropped.
+pub fn page_paragraphs(text: &str) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut paragraph = String::new();
+    for line in text.lines() {
+        let starts_paragraph = line.starts_with(['　', '「', '『', '（']);
+        // Keeps the full-width space paragraphs are indented with
+        let line = remove_cjk_spaces(line.trim_matches(|c: char| c.is_whitespace() && c != '　'));
+        let blank = line.trim().is_empty();
+        if blank || line.chars().all(|c| c.is_ascii_digit()) || starts_paragraph {
+            if !paragraph.is_empty() {
+                paragraphs.push(std::mem::take(&mut paragraph));
+            }
+            if blank || !starts_paragraph {
+                continue;
+            }
+        }
+        let joins_words = paragraph
+            .chars()
+            .next_back()
+            .zip(line.chars().next())
+            .is_some_and(|(a, b)| !is_cjk(a) || !is_cjk(b));
+        if joins_words {
+            paragraph.push(' ');
+        }
+        paragraph.push_str(&line);
+    }
+    if !paragraph.is_empty() {
+        paragraphs.push(paragraph);
+    }
+    paragraphs
+}
+
+/// Build a book of `pages_per_chapter` pages per chapter. Every page keeps an
+/// anchor (`page-N`) so positions can be related back to the PDF.
+pub fn build_book(
+    pdf: &PdfText,
+    fallback_title: &str,
+    pages_per_chapter: usize,
+) -> Result<EpubBuilder> {
+    let paragraphs: Vec<Vec<String>> = pdf.pages.iter().map(|p| page_paragraphs(p)).collect();
+    if paragraphs.iter().all(Vec::is_empty) {
+        bail!("The PDF has no text layer; run OCR on it first");
+    }
+
+    let title = pdf.title.as_deref().unwrap_or(fallback_title);
+    let mut builder = EpubBuilder::new(title);
+    if let Some(author) = &pdf.author {
+        builder.creator(author);
+    }
+
+    let mut toc = Vec::new();
+    let pages_per_chapter = pages_per_chapter.clamp(1, MAX_PAGES_PER_CHAPTER);
+    for (chapter, pages) in paragraphs.chunks(pages_per_chapter).enumerate() {
+        let first = chapter * pages_per_chapter + 1;
+        let last = first + pages.len() - 1;
+        let label = if first == last {
+            format!("Page {first}")
+        } else {
+            format!("Pages {first}–{last}")
+        };
+        let mut body = String::new();
+        for (i, page) in pages.iter().enumerate() {
+            body.push_str(&format!("<div id=\"page-{}\">\n", first + i));
+            for paragraph in page {
+                body.push_str(&format!("<p>{}</p>\n", escape_xml(paragraph)));
+            }
+            body.push_str("</div>\n");
+        }
+        let href = format!("text/chapter{}.xhtml", chapter + 1);
+        let id = builder.add_item(
+            &href,
+            "application/xhtml+xml",
+            None,
+            xhtml_document(&label, "ja", body.trim_end(), None).into_bytes(),
+        )?;
+        builder.add_to_spine(&id);
+        toc.push(NavPoint {
+            label,
+            href,
+            children: Vec::new(),
+        });
+    }
+    builder.set_toc(toc);
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_paragraphs() {
+        let text = "　吾輩は猫で\nある。名前は まだ 無い 。\n12\n「どこで生れたか\nとんと見当がつかぬ」\n\nPage one\ncontinues here";
+        assert_eq!(
+            page_paragraphs(text),
+            [
+                "　吾輩は猫である。名前はまだ無い。",
+                "「どこで生れたかとんと見当がつかぬ」",
+                "Page one continues here",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_text_string() {
+        assert_eq!(
+            decode_text_string(&[0xFE, 0xFF, 0x73, 0x2B, 0x30, 0x6E]),
+            "猫の"
+        );
+        assert_eq!(decode_text_string(b"Kokoro"), "Kokoro");
+    }
+
+    #[test]
+    fn test_build_book() {
+        let pdf = PdfText {
+            title: None,
+            author: Some("夏目漱石".to_string()),
+            pages: vec![
+                "　一\n".to_string(),
+                "　吾輩は猫である。".to_string(),
+                String::new(),
+            ],
+        };
+        let builder = build_book(&pdf, "neko", 2).unwrap();
+        assert_eq!(builder.title(), "neko");
+        assert_eq!(builder.creators(), ["夏目漱石"]);
+        let labels: Vec<&str> = builder.toc().iter().map(|p| p.label.as_str()).collect();
+        assert_eq!(labels, ["Pages 1–2", "Page 3"]);
+        assert_eq!(
+            builder.spine_hrefs(),
+            ["text/chapter1.xhtml", "text/chapter2.xhtml"]
+        );
+
+        let scanned = PdfText {
+            pages: vec![String::new(), " \n".to_string()],
+            ..Default::default()
+        };
+        assert!(build_book(&scanned, "scan", 10).is_err());
+    }
+}
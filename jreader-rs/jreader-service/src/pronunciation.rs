@@ -0,0 +1,308 @@
+//! Rough comparison of a user's recording of a word against a reference
+//! recording from the audio database, for pronunciation practice.
+//!
+//! Both recordings are reduced to a pitch contour (f0 tracked with YIN every
+//! 10 ms), expressed in semitones relative to the speaker's median pitch so
+//! that voices of different ranges compare. The contours are then aligned
+//! with dynamic time warping and scored by how far apart they are on average.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::Path;
+
+use crate::audio_decode;
+
+/// Longest recording accepted, to bound the alignment's cost
+pub const MAX_DURATION_SECS: u32 = 15;
+
+// Recordings are decimated to about this rate before pitch tracking
+const ANALYSIS_RATE: u32 = 16_000;
+const HOP_MS: u32 = 10;
+// Range of speaking voices
+const MIN_F0: f32 = 60.0;
+const MAX_F0: f32 = 500.0;
+// Threshold on YIN's cumulative mean normalized difference
+const YIN_THRESHOLD: f32 = 0.15;
+// Frames quieter than this fraction of the loudest frame count as silence
+const SILENCE_RATIO: f32 = 0.05;
+// Mean difference in semitones at which the score reaches 0
+const MAX_MEAN_DIFFERENCE: f32 = 4.0;
+
+/// Pitch at a point of a recording, in semitones above (or below) the
+/// recording's median pitch
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContourPoint {
+    pub time_ms: u32,
+    pub semitones: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PronunciationComparison {
+    /// 0-100, higher is closer to the reference
+    pub score: u8,
+    /// Mean pitch difference along the alignment, in semitones
+    pub mean_difference: f32,
+    /// Voiced points of the reference contour
+    pub reference: Vec<ContourPoint>,
+    /// Voiced points of the user's contour
+    pub recording: Vec<ContourPoint>,
+    /// Pairs of indices into `reference` and `recording` matched by the alignment
+    pub alignment: Vec<[usize; 2]>,
+}
+
+/// Compare the recording at `recording` against the one at `reference`
+pub fn compare_files(reference: &Path, recording: &Path) -> Result<PronunciationComparison> {
+    let reference = pitch_contour(&decode_mono(reference)?)
+        .context("No speech found in the reference recording")?;
+    let recording =
+        pitch_contour(&decode_mono(recording)?).context("No speech found in the recording")?;
+    Ok(compare_contours(reference, recording))
+}
+
+/// Mono samples of a recording, decimated to about [`ANALYSIS_RATE`]
+pub struct Samples {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+/// Decode an audio file, mixing its channels down to mono
+pub fn decode_mono(path: &Path) -> Result<Samples> {
+    let audio = audio_decode::decode(path, MAX_DURATION_SECS)?;
+    Ok(decimate(&audio.mono(), audio.sample_rate))
+}
+
+// Reduce to about ANALYSIS_RATE by averaging runs of samples, which also
+// filters out most of what would alias
+fn decimate(samples: &[f32], sample_rate: u32) -> Samples {
+    let factor = (sample_rate / ANALYSIS_RATE).max(1) as usize;
+    Samples {
+        samples: samples
+            .chunks(factor)
+            .map(|chunk| chunk.iter().sum::<f32>() / chunk.len() as f32)
+            .collect(),
+        sample_rate: sample_rate / factor as u32,
+    }
+}
+
+/// Voiced points of the pitch contour of `audio`, or `None` if no frame is
+/// voiced
+pub fn pitch_contour(audio: &Samples) -> Option<Vec<ContourPoint>> {
+    let rate = audio.sample_rate as f32;
+    let min_lag = (rate / MAX_F0).floor() as usize;
+    let max_lag = (rate / MIN_F0).ceil() as usize;
+    let hop = (audio.sample_rate * HOP_MS / 1000).max(1) as usize;
+    let frame_len = 2 * max_lag;
+    if min_lag < 2 || audio.samples.len() < frame_len {
+        return None;
+    }
+
+    let frames: Vec<(usize, &[f32])> = (0..=audio.samples.len() - frame_len)
+        .step_by(hop)
+        .map(|start| (start, &audio.samples[start..start + frame_len]))
+        .collect();
+    let energies: Vec<f32> = frames.iter().map(|(_, frame)| rms(frame)).collect();
+    let silence = energies.iter().fold(0f32, |a, b| a.max(*b)) * SILENCE_RATIO;
+
+    let pitches: Vec<(u32, f32)> = frames
+        .iter()
+        .zip(&energies)
+        .filter(|(_, energy)| **energy > silence)
+        .filter_map(|((start, frame), _)| {
+            let f0 = yin(frame, min_lag, max_lag, rate)?;
+            Some(((*start as f32 / rate * 1000.0).round() as u32, f0))
+        })
+        .collect();
+    if pitches.is_empty() {
+        return None;
+    }
+
+    let mut sorted: Vec<f32> = pitches.iter().map(|(_, f0)| *f0).collect();
+    sorted.sort_by(f32::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    Some(
+        pitches
+            .into_iter()
+            .map(|(time_ms, f0)| ContourPoint {
+                time_ms,
+                semitones: 12.0 * (f0 / median).log2(),
+            })
+            .collect(),
+    )
+}
+
+fn rms(frame: &[f32]) -> f32 {
+    (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt()
+}
+
+// Fundamental frequency of `frame` (2 * max_lag samples long) using YIN
+fn yin(frame: &[f32], min_lag: usize, max_lag: usize, rate: f32) -> Option<f32> {
+    let window = frame.len() - max_lag;
+    let difference: Vec<f32> = (0..=max_lag)
+        .map(|lag| {
+            (0..window)
+                .map(|i| {
+                    let d = frame[i] - frame[i + lag];
+                    d * d
+                })
+                .sum()
+        })
+        .collect();
+
+    // Cumulative mean normalized difference
+    let mut normalized = vec![1.0; max_lag + 1];
+    let mut running_sum = 0.0;
+    for lag in 1..=max_lag {
+        running_sum += difference[lag];
+        if running_sum > 0.0 {
+            normalized[lag] = difference[lag] * lag as f32 / running_sum;
+        }
+    }
+
+    let mut lag = (min_lag..max_lag).find(|lag| normalized[*lag] < YIN_THRESHOLD)?;
+    while lag + 1 < max_lag && normalized[lag + 1] < normalized[lag] {
+        lag += 1;
+    }
+
+    // Parabolic interpolation around the minimum
+    let (a, b, c) = (normalized[lag - 1], normalized[lag], normalized[lag + 1]);
+    let denominator = a - 2.0 * b + c;
+    let offset = if denominator.abs() > f32::EPSILON {
+        (0.5 * (a - c) / denominator).clamp(-1.0, 1.0)
+    } else {
+        0.0
+    };
+    Some(rate / (lag as f32 + offset))
+}
+
+/// Align two contours with dynamic time warping and score their difference
+pub fn compare_contours(
+    reference: Vec<ContourPoint>,
+    recording: Vec<ContourPoint>,
+) -> PronunciationComparison {
+    let (alignment, total) = dtw(&reference, &recording);
+    let mean_difference = if alignment.is_empty() {
+        0.0
+    } else {
+        total / alignment.len() as f32
+    };
+    let score = (100.0 * (1.0 - mean_difference / MAX_MEAN_DIFFERENCE)).clamp(0.0, 100.0);
+    PronunciationComparison {
+        score: score.round() as u8,
+        mean_difference,
+        reference,
+        recording,
+        alignment,
+    }
+}
+
+// Lowest cost alignment of `a` and `b` as index pairs from the start to the
+// end of both, with the summed pitch difference along it
+fn dtw(a: &[ContourPoint], b: &[ContourPoint]) -> (Vec<[usize; 2]>, f32) {
+    if a.is_empty() || b.is_empty() {
+        return (Vec::new(), 0.0);
+    }
+    let (n, m) = (a.len(), b.len());
+    let cost = |i: usize, j: usize| (a[i].semitones - b[j].semitones).abs();
+    // cumulative[i * m + j]: cost of the best alignment of a[..=i] and b[..=j]
+    let mut cumulative = vec![f32::INFINITY; n * m];
+    for i in 0..n {
+        for j in 0..m {
+            let best_previous = match (i, j) {
+                (0, 0) => 0.0,
+                (0, _) => cumulative[j - 1],
+                (_, 0) => cumulative[(i - 1) * m],
+                _ => cumulative[(i - 1) * m + j - 1]
+                    .min(cumulative[(i - 1) * m + j])
+                    .min(cumulative[i * m + j - 1]),
+            };
+            cumulative[i * m + j] = best_previous + cost(i, j);
+        }
+    }
+
+    let mut path = vec![[n - 1, m - 1]];
+    let (mut i, mut j) = (n - 1, m - 1);
+    while i > 0 || j > 0 {
+        (i, j) = if i == 0 {
+            (0, j - 1)
+        } else if j == 0 {
+            (i - 1, 0)
+        } else {
+            [(i - 1, j - 1), (i - 1, j), (i, j - 1)]
+                .into_iter()
+                .min_by(|x, y| cumulative[x.0 * m + x.1].total_cmp(&cumulative[y.0 * m + y.1]))
+                .unwrap()
+        };
+        path.push([i, j]);
+    }
+    path.reverse();
+    (path, cumulative[n * m - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A tone gliding linearly from `from` to `to` Hz over `secs` seconds
+    fn glide(from: f32, to: f32, secs: f32) -> Samples {
+        let rate = ANALYSIS_RATE as f32;
+        let len = (secs * rate) as usize;
+        let mut phase = 0f32;
+        let samples = (0..len)
+            .map(|i| {
+                let f = from + (to - from) * i as f32 / len as f32;
+                phase += 2.0 * std::f32::consts::PI * f / rate;
+                0.5 * phase.sin()
+            })
+            .collect();
+        Samples {
+            samples,
+            sample_rate: ANALYSIS_RATE,
+        }
+    }
+
+    #[test]
+    fn test_yin_finds_the_pitch_of_a_tone() {
+        let tone = glide(220.0, 220.0, 0.1);
+        let rate = ANALYSIS_RATE as f32;
+        let (min_lag, max_lag) = ((rate / MAX_F0) as usize, (rate / MIN_F0).ceil() as usize);
+        let f0 = yin(&tone.samples[..2 * max_lag], min_lag, max_lag, rate).unwrap();
+        assert!((f0 - 220.0).abs() < 2.0, "{f0}");
+
+        let silence = vec![0.0; 2 * max_lag];
+        assert_eq!(yin(&silence, min_lag, max_lag, rate), None);
+    }
+
+    #[test]
+    fn test_contour_is_relative_to_the_median() {
+        let contour = pitch_contour(&glide(150.0, 300.0, 0.5)).unwrap();
+        let first = contour.first().unwrap();
+        let last = contour.last().unwrap();
+        // An octave in total, around the middle of the glide
+        assert!(
+            first.semitones < -4.0 && last.semitones > 4.0,
+            "{contour:?}"
+        );
+        assert!(first.time_ms < last.time_ms);
+        assert_eq!(pitch_contour(&glide(0.0, 0.0, 0.5)), None);
+    }
+
+    #[test]
+    fn test_compare_contours() {
+        let rising = pitch_contour(&glide(150.0, 300.0, 0.5)).unwrap();
+        // The same rise in a higher voice, spoken slower
+        let rising_slow_high = pitch_contour(&glide(200.0, 400.0, 0.8)).unwrap();
+        let falling = pitch_contour(&glide(300.0, 150.0, 0.5)).unwrap();
+
+        let same = compare_contours(rising.clone(), rising.clone());
+        assert_eq!(same.score, 100);
+        assert_eq!(same.alignment.len(), rising.len());
+
+        let similar = compare_contours(rising.clone(), rising_slow_high);
+        let opposite = compare_contours(rising.clone(), falling);
+        assert!(similar.score > 80, "{}", similar.score);
+        assert!(opposite.score < similar.score);
+        assert_eq!(similar.alignment.first(), Some(&[0, 0]));
+    }
+}
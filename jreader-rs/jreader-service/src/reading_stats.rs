@@ -0,0 +1,290 @@
+//! Reading time and characters read, per user, book and day, for exporting to
+//! immersion trackers.
+//!
+//! The reader reports sessions as it goes and they are added up into one row
+//! per day and book. The title of the book is kept with its rows so the log
+//! still reads right once the book has been deleted.
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+
+use crate::library::user_library_dir;
+
+/// Longest session that can be reported at once
+pub const MAX_SESSION_SECONDS: u64 = 24 * 60 * 60;
+/// Most characters that can be reported for one session
+pub const MAX_SESSION_CHARACTERS: u64 = 1_000_000;
+
+const STATS_FILE: &str = "reading-stats.json";
+
+/// Reading of one book on one day
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadingDay {
+    pub date: NaiveDate,
+    pub upload_id: String,
+    pub title: Option<String>,
+    pub characters: u64,
+    pub seconds: u64,
+}
+
+impl ReadingDay {
+    pub fn minutes(&self) -> f64 {
+        self.seconds as f64 / 60.0
+    }
+}
+
+/// Everything a user has read, ordered by date then book
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ReadingLog {
+    pub days: Vec<ReadingDay>,
+}
+
+impl ReadingLog {
+    /// Add a session to the row of its day and book
+    pub fn record(
+        &mut self,
+        date: NaiveDate,
+        upload_id: &str,
+        title: Option<String>,
+        characters: u64,
+        seconds: u64,
+    ) -> ReadingDay {
+        let i = match self
+            .days
+            .binary_search_by(|d| (d.date, d.upload_id.as_str()).cmp(&(date, upload_id)))
+        {
+            Ok(i) => i,
+            Err(i) => {
+                self.days.insert(
+                    i,
+                    ReadingDay {
+                        date,
+                        upload_id: upload_id.to_string(),
+                        title: None,
+                        characters: 0,
+                        seconds: 0,
+                    },
+                );
+                i
+            }
+        };
+        let day = &mut self.days[i];
+        if title.is_some() {
+            day.title = title;
+        }
+        day.characters += characters;
+        day.seconds += seconds;
+        day.clone()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportGrouping {
+    /// A row per day and book
+    #[default]
+    Book,
+    /// A row per day, the titles of the books read joined together
+    Day,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportQuery {
+    /// First day to export, inclusive
+    pub from: Option<NaiveDate>,
+    /// Last day to export, inclusive
+    pub to: Option<NaiveDate>,
+    /// Only export the reading of this book
+    pub upload_id: Option<String>,
+    pub group_by: ExportGrouping,
+    /// `csv` (the default) or `json`
+    pub format: Option<String>,
+}
+
+/// The days of `log` matching `query`, grouped as it asks
+pub fn export_rows(log: &ReadingLog, query: &ExportQuery) -> Vec<ReadingDay> {
+    let days = log.days.iter().filter(|d| {
+        query.from.is_none_or(|from| d.date >= from)
+            && query.to.is_none_or(|to| d.date <= to)
+            && query.upload_id.as_ref().is_none_or(|id| d.upload_id == *id)
+    });
+    match query.group_by {
+        ExportGrouping::Book => days.cloned().collect(),
+        ExportGrouping::Day => {
+            let mut totals: BTreeMap<NaiveDate, ReadingDay> = BTreeMap::new();
+            for day in days {
+                let total = totals.entry(day.date).or_insert_with(|| ReadingDay {
+                    date: day.date,
+                    upload_id: String::new(),
+                    title: None,
+                    characters: 0,
+                    seconds: 0,
+                });
+                let title = day.title.as_deref().unwrap_or(&day.upload_id);
+                total.title = Some(match total.title.take() {
+                    Some(titles) => format!("{titles}; {title}"),
+                    None => title.to_string(),
+                });
+                total.characters += day.characters;
+                total.seconds += day.seconds;
+            }
+            totals.into_values().collect()
+        }
+    }
+}
+
+/// The CSV immersion trackers import: date, characters, minutes and the title
+/// the reading counts towards
+pub fn rows_to_csv(rows: &[ReadingDay]) -> Result<String> {
+    let mut wtr = csv::Writer::from_writer(vec![]);
+    wtr.write_record(["date", "characters", "minutes", "title"])?;
+    for row in rows {
+        wtr.write_record([
+            row.date.to_string().as_str(),
+            &row.characters.to_string(),
+            &format!("{:.1}", row.minutes()),
+            row.title.as_deref().unwrap_or(&row.upload_id),
+        ])?;
+    }
+    Ok(String::from_utf8(wtr.into_inner()?)?)
+}
+
+/// Reading logs, stored per user under `LIBRARY_DATA_DIR`
+pub struct ReadingStatsStore {
+    data_dir: PathBuf,
+    // Updates are read-modify-write of the whole file
+    write_lock: Mutex<()>,
+}
+
+impl ReadingStatsStore {
+    pub fn new(data_dir: PathBuf) -> Self {
+        Self {
+            data_dir,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    fn log_path(&self, user_id: &str) -> Result<PathBuf> {
+        Ok(user_library_dir(&self.data_dir, user_id)?.join(STATS_FILE))
+    }
+
+    /// The user's reading log, empty if they haven't read anything yet
+    pub async fn load(&self, user_id: &str) -> Result<ReadingLog> {
+        let path = self.log_path(user_id)?;
+        match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)
+                .with_context(|| format!("Invalid reading log: {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ReadingLog::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Add a session to the user's log and save it
+    pub async fn record(
+        &self,
+        user_id: &str,
+        date: NaiveDate,
+        upload_id: &str,
+        title: Option<String>,
+        characters: u64,
+        seconds: u64,
+    ) -> Result<ReadingDay> {
+        let _guard = self.write_lock.lock().await;
+        let mut log = self.load(user_id).await?;
+        let day = log.record(date, upload_id, title, characters, seconds);
+        let path = self.log_path(user_id)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, serde_json::to_vec(&log)?).await?;
+        Ok(day)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, day).unwrap()
+    }
+
+    #[test]
+    fn test_export_rows() {
+        let mut log = ReadingLog::default();
+        log.record(date(2), "b", Some("羅生門".to_string()), 3000, 1200);
+        log.record(date(1), "a", Some("こころ".to_string()), 1000, 600);
+        log.record(date(2), "a", None, 2000, 900);
+        // Added to the row of the same day and book
+        log.record(date(2), "b", None, 500, 300);
+
+        let rows = export_rows(&log, &ExportQuery::default());
+        let summary: Vec<(NaiveDate, &str, u64, u64)> = rows
+            .iter()
+            .map(|r| (r.date, r.upload_id.as_str(), r.characters, r.seconds))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (date(1), "a", 1000, 600),
+                (date(2), "a", 2000, 900),
+                (date(2), "b", 3500, 1500),
+            ]
+        );
+        assert_eq!(
+            rows_to_csv(&rows).unwrap(),
+            "date,characters,minutes,title\n\
+             2024-05-01,1000,10.0,こころ\n\
+             2024-05-02,2000,15.0,a\n\
+             2024-05-02,3500,25.0,羅生門\n"
+        );
+
+        let by_day = export_rows(
+            &log,
+            &ExportQuery {
+                from: Some(date(2)),
+                group_by: ExportGrouping::Day,
+                ..Default::default()
+            },
+        );
+        assert_eq!(by_day.len(), 1);
+        assert_eq!(by_day[0].characters, 5500);
+        assert_eq!(by_day[0].title.as_deref(), Some("a; 羅生門"));
+
+        let one_book = export_rows(
+            &log,
+            &ExportQuery {
+                upload_id: Some("a".to_string()),
+                to: Some(date(1)),
+                ..Default::default()
+            },
+        );
+        assert_eq!(one_book.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_store_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let store = ReadingStatsStore::new(dir.path().to_path_buf());
+        assert!(store.load("user").await.unwrap().days.is_empty());
+        store
+            .record("user", date(1), "a", Some("こころ".to_string()), 100, 60)
+            .await
+            .unwrap();
+        let day = store
+            .record("user", date(1), "a", None, 50, 30)
+            .await
+            .unwrap();
+        assert_eq!((day.characters, day.seconds), (150, 90));
+        assert_eq!(store.load("user").await.unwrap().days, [day]);
+        assert!(store.load("../user").await.is_err());
+    }
+}
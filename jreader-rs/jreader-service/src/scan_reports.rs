@@ -0,0 +1,265 @@
+//! Reports of dictionary scans.
+//!
+//! Each run of `scan_fs` records what happened to every archive in
+//! `DICTS_PATH/yomitan` (imported, skipped and why, or failed with its error
+//! chain), whether its static assets were deferred, and how long it took, saved as `DICTS_PATH/reports/<id>.json`. The
+//! admin can fetch them, so failures don't have to be dug out of the logs.
+
+use std::fs;
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path as Path, Utf8PathBuf as PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+use yomitan_format::kv_store::ImportMode;
+
+/// Directory under `DICTS_PATH` holding the reports
+pub const SCAN_REPORTS_DIR: &str = "reports";
+
+/// Reports kept; the oldest are removed as new scans finish
+const MAX_REPORTS: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "reason")]
+pub enum SkipReason {
+    /// Doesn't fit in what the loading policy's budget had left, even
+    /// without static assets
+    OverBudget { needed_mb: u64 },
+    /// Imported by an earlier scan, only registered
+    AlreadyImported,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "status")]
+pub enum ArchiveOutcome {
+    Imported,
+    Skipped(SkipReason),
+    Failed {
+        /// Outermost error first
+        error_chain: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveReport {
+    pub archive: String,
+    #[serde(flatten)]
+    pub outcome: ArchiveOutcome,
+    pub duration_ms: u64,
+    /// Whether the static assets weren't copied for lack of room in the
+    /// loading budget
+    #[serde(default)]
+    pub deferred_assets: bool,
+    /// Why the dictionary couldn't be added to the loaded ones, if it couldn't
+    pub registration_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanReport {
+    pub id: Uuid,
+    pub mode: ImportMode,
+    pub budget_mb: Option<u64>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_ms: u64,
+    /// Set if the scan couldn't go through the whole directory
+    pub error: Option<String>,
+    pub archives: Vec<ArchiveReport>,
+}
+
+/// The counts of a report, for listing reports without every archive
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanReportSummary {
+    pub id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+    pub imported: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub deferred_assets: usize,
+}
+
+impl ScanReport {
+    pub fn new(mode: ImportMode, budget_mb: Option<u64>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            mode,
+            budget_mb,
+            started_at: Utc::now(),
+            finished_at: None,
+            duration_ms: 0,
+            error: None,
+            archives: Vec::new(),
+        }
+    }
+
+    pub fn finish(&mut self) {
+        let finished_at = Utc::now();
+        self.duration_ms = (finished_at - self.started_at).num_milliseconds().max(0) as u64;
+        self.finished_at = Some(finished_at);
+    }
+
+    fn count(&self, matches: impl Fn(&ArchiveOutcome) -> bool) -> usize {
+        self.archives.iter().filter(|a| matches(&a.outcome)).count()
+    }
+
+    pub fn summary(&self) -> ScanReportSummary {
+        ScanReportSummary {
+            id: self.id,
+            started_at: self.started_at,
+            duration_ms: self.duration_ms,
+            error: self.error.clone(),
+            imported: self.count(|o| matches!(o, ArchiveOutcome::Imported)),
+            skipped: self.count(|o| matches!(o, ArchiveOutcome::Skipped(_))),
+            failed: self.count(|o| matches!(o, ArchiveOutcome::Failed { .. })),
+            deferred_assets: self.archives.iter().filter(|a| a.deferred_assets).count(),
+        }
+    }
+}
+
+fn reports_dir(dicts_path: &Path) -> PathBuf {
+    dicts_path.join(SCAN_REPORTS_DIR)
+}
+
+/// Save `report`, removing the oldest reports past the limit
+pub fn save(dicts_path: &Path, report: &ScanReport) -> Result<()> {
+    let dir = reports_dir(dicts_path);
+    fs::create_dir_all(&dir).context("Failed to create scan report directory")?;
+    fs::write(
+        dir.join(format!("{}.json", report.id)),
+        serde_json::to_vec_pretty(report)?,
+    )?;
+    prune(dicts_path)
+}
+
+fn load_all(dicts_path: &Path) -> Result<Vec<ScanReport>> {
+    let dir = reports_dir(dicts_path);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut reports = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|e| e != "json") {
+            continue;
+        }
+        match fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(serde_json::from_slice(&data)?))
+        {
+            Ok(report) => reports.push(report),
+            Err(e) => warn!(?e, ?path, "Skipping unreadable scan report"),
+        }
+    }
+    reports.sort_by_key(|report: &ScanReport| std::cmp::Reverse(report.started_at));
+    Ok(reports)
+}
+
+/// Summaries of the saved reports, most recent first
+pub fn list(dicts_path: &Path) -> Result<Vec<ScanReportSummary>> {
+    Ok(load_all(dicts_path)?
+        .iter()
+        .map(ScanReport::summary)
+        .collect())
+}
+
+/// The report saved as `id`, if there is one
+pub fn load(dicts_path: &Path, id: Uuid) -> Result<Option<ScanReport>> {
+    let path = reports_dir(dicts_path).join(format!("{id}.json"));
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_slice(&fs::read(&path)?)?))
+}
+
+fn prune(dicts_path: &Path) -> Result<()> {
+    for report in load_all(dicts_path)?.into_iter().skip(MAX_REPORTS) {
+        fs::remove_file(reports_dir(dicts_path).join(format!("{}.json", report.id)))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_list_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let dicts_path = Path::from_path(dir.path()).unwrap();
+        assert!(list(dicts_path).unwrap().is_empty());
+
+        let mut report = ScanReport::new(ImportMode::Lenient, Some(100));
+        report.archives = vec![
+            ArchiveReport {
+                archive: "jmdict.zip".to_string(),
+                outcome: ArchiveOutcome::Imported,
+                duration_ms: 1200,
+                deferred_assets: true,
+                registration_error: None,
+            },
+            ArchiveReport {
+                archive: "huge.zip".to_string(),
+                outcome: ArchiveOutcome::Skipped(SkipReason::OverBudget { needed_mb: 900 }),
+                duration_ms: 0,
+                deferred_assets: false,
+                registration_error: None,
+            },
+            ArchiveReport {
+                archive: "broken.zip".to_string(),
+                outcome: ArchiveOutcome::Failed {
+                    error_chain: vec!["Failed to import".to_string()],
+                },
+                duration_ms: 30,
+                deferred_assets: false,
+                registration_error: None,
+            },
+        ];
+        report.finish();
+        save(dicts_path, &report).unwrap();
+
+        let summaries = list(dicts_path).unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(
+            (
+                summaries[0].imported,
+                summaries[0].skipped,
+                summaries[0].failed,
+                summaries[0].deferred_assets
+            ),
+            (1, 1, 1, 1)
+        );
+        let loaded = load(dicts_path, report.id).unwrap().unwrap();
+        assert_eq!(loaded.archives[1].outcome, report.archives[1].outcome);
+        assert_eq!(loaded.finished_at, report.finished_at);
+        assert!(load(dicts_path, Uuid::new_v4()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_outcome_json() {
+        let archive = ArchiveReport {
+            archive: "huge.zip".to_string(),
+            outcome: ArchiveOutcome::Skipped(SkipReason::AlreadyImported),
+            duration_ms: 5,
+            deferred_assets: false,
+            registration_error: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&archive).unwrap(),
+            serde_json::json!({
+                "archive": "huge.zip",
+                "status": "skipped",
+                "reason": "alreadyImported",
+                "durationMs": 5,
+                "deferredAssets": false,
+                "registrationError": null,
+            })
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! How long each step of service startup took.
+//!
+//! Logged once the service is ready and served to the admin under
+//! `/api/admin/startup`, with the load time of every dictionary, so a slow
+//! start can be traced to the step or dictionary that regressed.
+
+use std::time::Instant;
+
+use serde::Serialize;
+use tracing::info;
+
+use crate::dictionaries::DictionaryLoadSummary;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartupStep {
+    pub name: &'static str,
+    pub duration_ms: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartupTimings {
+    pub steps: Vec<StartupStep>,
+    pub total_ms: f64,
+    pub dictionaries: DictionaryLoadSummary,
+}
+
+pub struct StartupTimer {
+    started: Instant,
+    step_started: Instant,
+    timings: StartupTimings,
+}
+
+impl StartupTimer {
+    pub fn start() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            step_started: now,
+            timings: StartupTimings::default(),
+        }
+    }
+
+    /// Record the time since the previous step, or the start, as `name`
+    pub fn step(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.timings.steps.push(StartupStep {
+            name,
+            duration_ms: (now - self.step_started).as_secs_f64() * 1000.0,
+        });
+        self.step_started = now;
+    }
+
+    pub fn set_dictionaries(&mut self, summary: DictionaryLoadSummary) {
+        self.timings.dictionaries = summary;
+    }
+
+    /// The timings of all steps, logged as a summary
+    pub fn finish(mut self) -> StartupTimings {
+        self.timings.total_ms = self.started.elapsed().as_secs_f64() * 1000.0;
+        let timings = self.timings;
+        info!(
+            total_ms = timings.total_ms,
+            steps = ?timings
+                .steps
+                .iter()
+                .map(|step| (step.name, step.duration_ms.round()))
+                .collect::<Vec<_>>(),
+            slowest_dictionaries = ?timings
+                .dictionaries
+                .slowest(5)
+                .iter()
+                .map(|dict| (&dict.title, dict.duration_ms.round()))
+                .collect::<Vec<_>>(),
+            "⏱️ Startup complete"
+        );
+        timings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_startup_timer() {
+        let mut timer = StartupTimer::start();
+        timer.step("config");
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        timer.step("dictionaries");
+        let timings = timer.finish();
+
+        let names: Vec<_> = timings.steps.iter().map(|step| step.name).collect();
+        assert_eq!(names, ["config", "dictionaries"]);
+        assert!(timings.steps[1].duration_ms >= 5.0);
+        let steps_ms: f64 = timings.steps.iter().map(|step| step.duration_ms).sum();
+        assert!(timings.total_ms >= steps_ms);
+    }
+}
@@ -0,0 +1,256 @@
+//! Content-addressed storage for dictionary static assets (images, CSS, ...).
+//! Many dictionaries ship the same icons and stylesheets, so each file is
+//! stored once under `static/.objects/`, named by its SHA-256, and each
+//! dictionary gets a manifest in `static/.manifests/` mapping its archive paths
+//! to those objects. Dictionaries imported before this keep their plain
+//! `static/{dictionary}/` directory, which is still served as is.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf as PathBuf;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use yomitan_format::normalization::normalize_name;
+
+const OBJECTS_DIR: &str = ".objects";
+const MANIFESTS_DIR: &str = ".manifests";
+
+lazy_static! {
+    /// Parsed manifests by path, along with the modification time they were
+    /// read at, so serving an image doesn't re-read a large manifest
+    static ref MANIFESTS: RwLock<HashMap<PathBuf, (SystemTime, Arc<AssetManifest>)>> =
+        RwLock::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetRef {
+    pub hash: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AssetManifest {
+    /// Path within the dictionary archive (NFC) -> stored object
+    pub files: BTreeMap<String, AssetRef>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DictionaryAssets {
+    pub dictionary: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetStorageReport {
+    pub dictionaries: Vec<DictionaryAssets>,
+    /// Size of all dictionaries' assets as shipped
+    pub total_bytes: u64,
+    /// Size of the distinct files actually stored
+    pub stored_bytes: u64,
+    pub saved_bytes: u64,
+}
+
+pub struct AssetStore {
+    static_dir: PathBuf,
+}
+
+impl AssetStore {
+    pub fn new(static_dir: PathBuf) -> Self {
+        Self { static_dir }
+    }
+
+    fn manifest_path(&self, dictionary: &str) -> PathBuf {
+        self.static_dir
+            .join(MANIFESTS_DIR)
+            .join(format!("{dictionary}.json"))
+    }
+
+    fn object_path(&self, hash: &str) -> PathBuf {
+        self.static_dir
+            .join(OBJECTS_DIR)
+            .join(&hash[..2])
+            .join(hash)
+    }
+
+    pub fn has_manifest(&self, dictionary: &str) -> bool {
+        self.manifest_path(dictionary).exists()
+    }
+
+    /// Store the contents of `reader`, returning the object and whether an
+    /// identical file was already stored
+    pub fn put(&self, reader: &mut impl Read) -> Result<(AssetRef, bool)> {
+        let objects_dir = self.static_dir.join(OBJECTS_DIR);
+        fs::create_dir_all(&objects_dir)?;
+        let mut temp = tempfile::NamedTempFile::new_in(&objects_dir)?;
+
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+        let mut buf = [0u8; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            temp.write_all(&buf[..n])?;
+            size += n as u64;
+        }
+        let hash = format!("{:x}", hasher.finalize());
+
+        let object_path = self.object_path(&hash);
+        let existed = object_path.exists();
+        if !existed {
+            fs::create_dir_all(object_path.parent().context("Object path has no parent")?)?;
+            temp.persist(&object_path)
+                .with_context(|| format!("Failed to store asset {object_path}"))?;
+        }
+        Ok((AssetRef { hash, size }, existed))
+    }
+
+    pub fn save_manifest(&self, dictionary: &str, manifest: &AssetManifest) -> Result<()> {
+        let path = self.manifest_path(dictionary);
+        fs::create_dir_all(path.parent().context("Manifest path has no parent")?)?;
+        let tmp_path = path.with_extension("json.tmp");
+        serde_json::to_writer(File::create(&tmp_path)?, manifest)?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    fn load_manifest(&self, dictionary: &str) -> Result<Option<Arc<AssetManifest>>> {
+        let path = self.manifest_path(dictionary);
+        let modified = match fs::metadata(&path) {
+            Ok(metadata) => metadata.modified()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if let Some((cached_at, manifest)) = MANIFESTS
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&path)
+        {
+            if *cached_at == modified {
+                return Ok(Some(manifest.clone()));
+            }
+        }
+
+        let manifest: Arc<AssetManifest> = Arc::new(
+            serde_json::from_reader(File::open(&path)?)
+                .with_context(|| format!("Failed to parse asset manifest {path}"))?,
+        );
+        MANIFESTS
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(path, (modified, manifest.clone()));
+        Ok(Some(manifest))
+    }
+
+    /// Stored object for `rel_path` (`{dictionary}/{path in archive}`), if the
+    /// dictionary was imported with a manifest and lists that file
+    pub fn resolve(&self, rel_path: &str) -> Option<PathBuf> {
+        let rel_path = normalize_name(rel_path);
+        let (dictionary, file) = rel_path.split_once('/')?;
+        if dictionary.is_empty() || dictionary.starts_with('.') || dictionary.contains('\\') {
+            return None;
+        }
+        let manifest = match self.load_manifest(dictionary) {
+            Ok(manifest) => manifest?,
+            Err(e) => {
+                warn!(?e, %dictionary, "Failed to load asset manifest");
+                return None;
+            }
+        };
+        let asset = manifest.files.get(file)?;
+        if asset.hash.len() < 2 || !asset.hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(self.object_path(&asset.hash))
+    }
+
+    /// Space used by the stored assets compared to storing every dictionary's
+    /// copy separately
+    pub fn report(&self) -> Result<AssetStorageReport> {
+        let mut report = AssetStorageReport::default();
+        let entries = match fs::read_dir(self.static_dir.join(MANIFESTS_DIR)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(report),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut stored = HashSet::new();
+        for entry in entries {
+            let file_name = entry?.file_name();
+            let Some(dictionary) = file_name.to_str().and_then(|n| n.strip_suffix(".json")) else {
+                continue;
+            };
+            let Some(manifest) = self.load_manifest(dictionary)? else {
+                continue;
+            };
+            let bytes = manifest.files.values().map(|a| a.size).sum();
+            for asset in manifest.files.values() {
+                if stored.insert(asset.hash.clone()) {
+                    report.stored_bytes += asset.size;
+                }
+            }
+            report.total_bytes += bytes;
+            report.dictionaries.push(DictionaryAssets {
+                dictionary: dictionary.to_string(),
+                files: manifest.files.len(),
+                bytes,
+            });
+        }
+        report
+            .dictionaries
+            .sort_by(|a, b| a.dictionary.cmp(&b.dictionary));
+        report.saved_bytes = report.total_bytes - report.stored_bytes;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use unicode_normalization::UnicodeNormalization;
+
+    #[test]
+    fn test_dedup_and_resolve() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let static_dir = PathBuf::try_from(temp_dir.path().to_path_buf()).unwrap();
+        let store = AssetStore::new(static_dir.clone());
+
+        let mut manifests = [AssetManifest::default(), AssetManifest::default()];
+        for (manifest, name) in manifests.iter_mut().zip(["icon.png", "img/icon.png"]) {
+            let (asset, _) = store.put(&mut &b"same icon"[..]).unwrap();
+            manifest.files.insert(name.to_string(), asset);
+        }
+        let (style, existed) = store.put(&mut &b"body {}"[..]).unwrap();
+        assert!(!existed);
+        manifests[1].files.insert("style.css".to_string(), style);
+        store.save_manifest("じしょ", &manifests[0]).unwrap();
+        store.save_manifest("辞書B", &manifests[1]).unwrap();
+
+        let icon = store.resolve("辞書B/img/icon.png").unwrap();
+        assert_eq!(fs::read(&icon).unwrap(), b"same icon");
+        assert_eq!(store.resolve("じしょ/icon.png"), Some(icon));
+        assert_eq!(store.resolve("じしょ/missing.png"), None);
+        assert_eq!(store.resolve("unknown/icon.png"), None);
+        // Archive paths are looked up in NFC whatever form the request uses
+        let nfd_path = "じしょ/icon.png".nfd().collect::<String>();
+        assert!(store.resolve(&nfd_path).is_some());
+
+        let report = store.report().unwrap();
+        assert_eq!(report.dictionaries.len(), 2);
+        assert_eq!(report.total_bytes, 9 * 2 + 7);
+        assert_eq!(report.stored_bytes, 9 + 7);
+        assert_eq!(report.saved_bytes, 9);
+    }
+}
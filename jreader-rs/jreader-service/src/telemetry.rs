@@ -0,0 +1,123 @@
+//! Opt-in usage telemetry, aggregated on the instance itself.
+//!
+//! With `TELEMETRY_ENABLED` set, handlers count events per UTC day. Counts are
+//! anonymous (no user ids, terms or book titles are kept), stay in memory on
+//! the server and are only shown to the admin, for capacity planning without
+//! third-party analytics.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use tokio::sync::RwLock;
+
+/// Days of counts kept; older days are dropped as new ones start
+pub const RETENTION_DAYS: usize = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TelemetryEvent {
+    Lookup,
+    Suggest,
+    Conjugate,
+    ReverseLookup,
+    Audio,
+    BookUpload,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyCounts {
+    pub date: NaiveDate,
+    pub counts: BTreeMap<TelemetryEvent, u64>,
+}
+
+#[derive(Default)]
+pub struct Telemetry {
+    days: RwLock<BTreeMap<NaiveDate, BTreeMap<TelemetryEvent, u64>>>,
+}
+
+impl Telemetry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count one `event` today
+    pub async fn record(&self, event: TelemetryEvent) {
+        self.record_on(chrono::Utc::now().date_naive(), event).await;
+    }
+
+    async fn record_on(&self, date: NaiveDate, event: TelemetryEvent) {
+        let mut days = self.days.write().await;
+        *days.entry(date).or_default().entry(event).or_default() += 1;
+        while days.len() > RETENTION_DAYS {
+            days.pop_first();
+        }
+    }
+
+    /// Counts of every day with events, most recent first
+    pub async fn daily_counts(&self) -> Vec<DailyCounts> {
+        self.days
+            .read()
+            .await
+            .iter()
+            .rev()
+            .map(|(date, counts)| DailyCounts {
+                date: *date,
+                counts: counts.clone(),
+            })
+            .collect()
+    }
+
+    /// Totals of each event over the retained days
+    pub async fn totals(&self) -> BTreeMap<TelemetryEvent, u64> {
+        let mut totals = BTreeMap::new();
+        for counts in self.days.read().await.values() {
+            for (event, count) in counts {
+                *totals.entry(*event).or_default() += count;
+            }
+        }
+        totals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_counts_per_day() {
+        let telemetry = Telemetry::new();
+        let day = |d: u32| NaiveDate::from_ymd_opt(2026, 1, d).unwrap();
+        telemetry.record_on(day(1), TelemetryEvent::Lookup).await;
+        telemetry.record_on(day(1), TelemetryEvent::Lookup).await;
+        telemetry.record_on(day(2), TelemetryEvent::Lookup).await;
+        telemetry.record_on(day(2), TelemetryEvent::Audio).await;
+
+        let daily = telemetry.daily_counts().await;
+        assert_eq!(daily.len(), 2);
+        assert_eq!(daily[0].date, day(2));
+        assert_eq!(daily[1].counts[&TelemetryEvent::Lookup], 2);
+        assert_eq!(
+            telemetry.totals().await,
+            BTreeMap::from([(TelemetryEvent::Lookup, 3), (TelemetryEvent::Audio, 1)])
+        );
+        assert_eq!(
+            serde_json::to_value(&daily[1]).unwrap(),
+            serde_json::json!({ "date": "2026-01-01", "counts": { "lookup": 2 } })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_drops_days_past_retention() {
+        let telemetry = Telemetry::new();
+        let start = NaiveDate::from_ymd_opt(2026, 1, 1).unwrap();
+        for offset in 0..=RETENTION_DAYS as u64 {
+            let date = start + chrono::Days::new(offset);
+            telemetry.record_on(date, TelemetryEvent::Suggest).await;
+        }
+        let daily = telemetry.daily_counts().await;
+        assert_eq!(daily.len(), RETENTION_DAYS);
+        assert_eq!(daily.last().unwrap().date, start + chrono::Days::new(1));
+    }
+}
@@ -0,0 +1,368 @@
+//! Synthesized pronunciations for terms the audio database has no recording
+//! of, from the text-to-speech backend configured by `TTS_BACKEND`.
+//!
+//! `voicevox` uses the HTTP API of a VOICEVOX engine at `TTS_URL` (default
+//! `http://127.0.0.1:50021`) with the speaker id in `TTS_VOICE`. `http` sends
+//! `GET {TTS_URL}?text=...&voice=...` to any service that answers with a WAV,
+//! MP3 or Ogg file. Each clip is synthesized once and kept in `TTS_CACHE_DIR`,
+//! named by a hash of the backend, voice and text, and served under
+//! `/audio/tts/`. Only texts up to `MAX_TEXT_CHARS` long are synthesized, and
+//! the least recently written clips are removed once the cache holds more
+//! than `TTS_CACHE_MAX_CLIPS`.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+/// Source name of synthesized clips, which users can disable or request like
+/// the sources of the audio database
+pub const SOURCE: &str = "tts";
+/// Where clips are served, relative to `/audio/`
+pub const URL_PREFIX: &str = "tts/";
+/// Longest text synthesized, in characters: enough for any term or reading
+pub const MAX_TEXT_CHARS: usize = 32;
+
+const DEFAULT_VOICEVOX_URL: &str = "http://127.0.0.1:50021";
+const DEFAULT_VOICEVOX_SPEAKER: &str = "1";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+const DEFAULT_MAX_CACHED_CLIPS: usize = 10_000;
+// Formats a clip can be stored as, by extension
+const CLIP_EXTENSIONS: [&str; 3] = ["wav", "mp3", "ogg"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TtsBackend {
+    Voicevox,
+    Http,
+}
+
+impl FromStr for TtsBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "voicevox" => Ok(Self::Voicevox),
+            "http" => Ok(Self::Http),
+            _ => Err(anyhow!("Unknown TTS_BACKEND {s:?}: use voicevox or http")),
+        }
+    }
+}
+
+impl TtsBackend {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Voicevox => "voicevox",
+            Self::Http => "http",
+        }
+    }
+
+    /// Name of synthesized clips in audio responses
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::Voicevox => "VOICEVOX",
+            Self::Http => "TTS",
+        }
+    }
+}
+
+/// The cache in TTS_CACHE_DIR, read on every request like AUDIO_DATA_DIRS
+fn cache_dir() -> PathBuf {
+    PathBuf::from(std::env::var("TTS_CACHE_DIR").unwrap_or_else(|_| "./data/tts-cache".to_string()))
+}
+
+/// Path of the cached clip `name`, as it appears in the clip URLs. None if
+/// `name` isn't the name of a clip.
+pub fn clip_path(name: &str) -> Option<PathBuf> {
+    let (key, extension) = name.split_once('.')?;
+    let valid_key = key.len() == 64 && key.bytes().all(|b| b.is_ascii_hexdigit());
+    if !valid_key || !CLIP_EXTENSIONS.contains(&extension) {
+        return None;
+    }
+    Some(cache_dir().join(name))
+}
+
+// Extension of the audio file `audio`, recognized by its magic bytes
+fn audio_extension(audio: &[u8]) -> Option<&'static str> {
+    if audio.starts_with(b"RIFF") && audio.get(8..12) == Some(b"WAVE") {
+        Some("wav")
+    } else if audio.starts_with(b"OggS") {
+        Some("ogg")
+    } else if audio.starts_with(b"ID3") || matches!(audio, [0xFF, b, ..] if b & 0xE0 == 0xE0) {
+        Some("mp3")
+    } else {
+        None
+    }
+}
+
+pub struct TtsService {
+    backend: TtsBackend,
+    url: String,
+    voice: Option<String>,
+    cache_dir: PathBuf,
+    max_cached_clips: usize,
+    client: reqwest::Client,
+}
+
+impl TtsService {
+    pub fn new(
+        backend: TtsBackend,
+        url: String,
+        voice: Option<String>,
+        cache_dir: PathBuf,
+        max_cached_clips: usize,
+    ) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to create TTS client")?;
+        Ok(Self {
+            backend,
+            url: url.trim_end_matches('/').to_string(),
+            voice,
+            cache_dir,
+            max_cached_clips,
+            client,
+        })
+    }
+
+    /// The backend configured by TTS_BACKEND, None if it isn't set
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(backend) = std::env::var("TTS_BACKEND") else {
+            return Ok(None);
+        };
+        let backend: TtsBackend = backend.parse()?;
+        let url = match (std::env::var("TTS_URL"), backend) {
+            (Ok(url), _) => url,
+            (Err(_), TtsBackend::Voicevox) => DEFAULT_VOICEVOX_URL.to_string(),
+            (Err(_), TtsBackend::Http) => bail!("TTS_URL must be set for TTS_BACKEND=http"),
+        };
+        let voice = std::env::var("TTS_VOICE").ok().or_else(|| {
+            (backend == TtsBackend::Voicevox).then(|| DEFAULT_VOICEVOX_SPEAKER.to_string())
+        });
+        let max_cached_clips = match std::env::var("TTS_CACHE_MAX_CLIPS") {
+            Ok(max) => max
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid TTS_CACHE_MAX_CLIPS {max:?}"))?,
+            Err(_) => DEFAULT_MAX_CACHED_CLIPS,
+        };
+        Self::new(backend, url, voice, cache_dir(), max_cached_clips).map(Some)
+    }
+
+    pub fn backend(&self) -> TtsBackend {
+        self.backend
+    }
+
+    // Name of the clip of `text` in the cache, without its extension
+    fn clip_key(&self, text: &str) -> String {
+        let mut hasher = Sha256::new();
+        for part in [
+            self.backend.as_str(),
+            self.voice.as_deref().unwrap_or_default(),
+            text,
+        ] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    async fn cached_clip(&self, key: &str) -> Option<String> {
+        for extension in CLIP_EXTENSIONS {
+            let name = format!("{key}.{extension}");
+            if tokio::fs::metadata(self.cache_dir.join(&name))
+                .await
+                .is_ok_and(|m| m.is_file())
+            {
+                return Some(name);
+            }
+        }
+        None
+    }
+
+    /// File name of the clip of `text` in the cache, synthesizing it first if
+    /// it isn't cached yet
+    pub async fn clip(&self, text: &str) -> Result<String> {
+        if text.chars().count() > MAX_TEXT_CHARS {
+            bail!("Text is longer than {MAX_TEXT_CHARS} characters");
+        }
+        let key = self.clip_key(text);
+        if let Some(name) = self.cached_clip(&key).await {
+            return Ok(name);
+        }
+
+        let audio = self.synthesize(text).await?;
+        let extension =
+            audio_extension(&audio).context("TTS backend did not return a WAV, MP3 or Ogg file")?;
+        let name = format!("{key}.{extension}");
+        let cache_dir = self.cache_dir.clone();
+        let file_name = name.clone();
+        let max_cached_clips = self.max_cached_clips;
+        tokio::task::spawn_blocking(move || {
+            write_clip(&cache_dir, &file_name, &audio)?;
+            prune_cache(&cache_dir, max_cached_clips)
+        })
+        .await??;
+        Ok(name)
+    }
+
+    async fn synthesize(&self, text: &str) -> Result<Vec<u8>> {
+        let audio = match self.backend {
+            TtsBackend::Voicevox => {
+                let speaker = self.voice.as_deref().unwrap_or(DEFAULT_VOICEVOX_SPEAKER);
+                let query: serde_json::Value = self
+                    .client
+                    .post(format!("{}/audio_query", self.url))
+                    .query(&[("text", text), ("speaker", speaker)])
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await
+                    .context("Failed to create VOICEVOX audio query")?;
+                self.client
+                    .post(format!("{}/synthesis", self.url))
+                    .query(&[("speaker", speaker)])
+                    .json(&query)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await
+                    .context("Failed to synthesize with VOICEVOX")?
+            }
+            TtsBackend::Http => {
+                let mut request = self.client.get(&self.url).query(&[("text", text)]);
+                if let Some(voice) = &self.voice {
+                    request = request.query(&[("voice", voice.as_str())]);
+                }
+                request
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await
+                    .context("Failed to synthesize with TTS backend")?
+            }
+        };
+        Ok(audio.to_vec())
+    }
+}
+
+// Written aside and moved into place so the audio route never serves half a clip
+fn write_clip(cache_dir: &Path, name: &str, audio: &[u8]) -> Result<()> {
+    std::fs::create_dir_all(cache_dir)
+        .with_context(|| format!("Failed to create {}", cache_dir.display()))?;
+    let mut file = tempfile::NamedTempFile::new_in(cache_dir)?;
+    file.write_all(audio)?;
+    let path = cache_dir.join(name);
+    file.persist(&path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+// Remove the least recently written clips past the first `max_clips`
+fn prune_cache(cache_dir: &Path, max_clips: usize) -> Result<()> {
+    let mut clips = Vec::new();
+    for entry in std::fs::read_dir(cache_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let is_clip = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| CLIP_EXTENSIONS.contains(&e));
+        if is_clip {
+            clips.push((entry.metadata()?.modified()?, path));
+        }
+    }
+    if clips.len() <= max_clips {
+        return Ok(());
+    }
+    clips.sort();
+    for (_, path) in &clips[..clips.len() - max_clips] {
+        if let Err(e) = std::fs::remove_file(path) {
+            warn!(?e, path = %path.display(), "⚠️ Failed to remove cached TTS clip");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(voice: Option<&str>, cache_dir: &Path) -> TtsService {
+        TtsService::new(
+            TtsBackend::Voicevox,
+            // Nothing listens here, so any request would fail
+            "http://127.0.0.1:9".to_string(),
+            voice.map(str::to_string),
+            cache_dir.to_path_buf(),
+            2,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_clip_key_and_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = service(Some("1"), dir.path()).clip_key("たべる");
+        assert_eq!(key, service(Some("1"), dir.path()).clip_key("たべる"));
+        assert_ne!(key, service(Some("2"), dir.path()).clip_key("たべる"));
+        assert_ne!(key, service(Some("1"), dir.path()).clip_key("のむ"));
+
+        assert!(clip_path(&format!("{key}.wav")).is_some());
+        assert!(clip_path(&format!("{key}.exe")).is_none());
+        assert!(clip_path("../secret.wav").is_none());
+        assert!(clip_path(&format!("{}.wav", &key[1..])).is_none());
+    }
+
+    #[test]
+    fn test_audio_extension() {
+        assert_eq!(audio_extension(b"RIFF\0\0\0\0WAVEfmt "), Some("wav"));
+        assert_eq!(audio_extension(b"OggS\0\x02"), Some("ogg"));
+        assert_eq!(audio_extension(b"ID3\x04"), Some("mp3"));
+        assert_eq!(audio_extension(&[0xFF, 0xFB, 0x90]), Some("mp3"));
+        assert_eq!(audio_extension(b"{\"error\": \"busy\"}"), None);
+    }
+
+    #[tokio::test]
+    async fn test_clip_uses_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let tts = service(None, dir.path());
+        assert!(tts.clip("たべる").await.is_err());
+
+        let name = format!("{}.mp3", tts.clip_key("たべる"));
+        write_clip(dir.path(), &name, b"ID3").unwrap();
+        assert_eq!(tts.clip("たべる").await.unwrap(), name);
+
+        let long_text = "あ".repeat(MAX_TEXT_CHARS + 1);
+        assert!(tts.clip(&long_text).await.is_err());
+    }
+
+    #[test]
+    fn test_prune_cache_keeps_newest_clips() {
+        let dir = tempfile::tempdir().unwrap();
+        let tts = service(None, dir.path());
+        let names: Vec<String> = ["あ", "い", "う"]
+            .iter()
+            .map(|text| format!("{}.wav", tts.clip_key(text)))
+            .collect();
+        for name in &names {
+            write_clip(dir.path(), name, b"RIFF").unwrap();
+            // Distinct modification times
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        prune_cache(dir.path(), 2).unwrap();
+        assert!(!dir.path().join(&names[0]).exists());
+        assert!(dir.path().join(&names[1]).exists());
+        assert!(dir.path().join(&names[2]).exists());
+        assert!(dir.path().join("notes.txt").exists());
+    }
+}
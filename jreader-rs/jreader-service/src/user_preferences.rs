@@ -1,57 +1,16 @@
-use crate::dictionaries::{DictionaryInfo, DictionaryType};
+use crate::dictionaries::DictionaryInfo;
 use anyhow::Result;
 use deadpool_postgres::{Config, Pool};
-use std::collections::HashSet;
+pub use jreader_core::preferences::{
+    validate_audio_sources, FrequencyBand, PopupSettings, ReaderSettings, UserPreferences,
+};
+use serde::de::DeserializeOwned;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio_postgres::NoTls;
-use tracing::{info, instrument};
+use tracing::{info, instrument, warn};
 use uuid::Uuid;
 
-#[derive(Debug)]
-pub struct UserPreferences {
-    pub user_id: Uuid,
-    // Term dictionaries
-    pub term_dictionary_order: Vec<String>,
-    pub term_disabled_dictionaries: HashSet<String>,
-    pub term_spoiler_dictionaries: HashSet<String>,
-    pub freq_dictionary_order: Vec<String>,
-    pub freq_disabled_dictionaries: HashSet<String>,
-}
-
-impl UserPreferences {
-    pub fn default(user_id: Uuid, dictionary_info: Vec<DictionaryInfo>) -> Self {
-        // Use the format "title#revision" for the dictionary order
-        let term_dictionaries = dictionary_info
-            .iter()
-            .filter(|d| d.dictionary_type == DictionaryType::Term)
-            .collect::<Vec<_>>();
-        let mut term_dictionary_order = term_dictionaries
-            .iter()
-            .map(|d| format!("{}#{}", d.title, d.revision))
-            .collect::<Vec<_>>();
-        term_dictionary_order.sort();
-
-        let freq_dictionaries = dictionary_info
-            .iter()
-            .filter(|d| d.dictionary_type == DictionaryType::Frequency)
-            .collect::<Vec<_>>();
-        let mut freq_dictionary_order = freq_dictionaries
-            .iter()
-            .map(|d| format!("{}#{}", d.title, d.revision))
-            .collect::<Vec<_>>();
-        freq_dictionary_order.sort();
-        Self {
-            user_id,
-            term_dictionary_order: term_dictionary_order,
-            term_disabled_dictionaries: HashSet::new(),
-            term_spoiler_dictionaries: HashSet::new(),
-            freq_dictionary_order: freq_dictionary_order,
-            freq_disabled_dictionaries: HashSet::new(),
-        }
-    }
-}
-
 pub trait UserPreferencesStoreAsync {
     #[allow(async_fn_in_trait)]
     async fn save(&self, preferences: &UserPreferences) -> Result<()>;
@@ -100,19 +59,26 @@ impl UserPreferencesSupabase {
 
 impl UserPreferencesStoreAsync for UserPreferencesSupabase {
     async fn save(&self, preferences: &UserPreferences) -> Result<()> {
-        let pool = self.pool.as_ref().ok_or_else(|| anyhow::anyhow!("Database not available"))?;
+        let pool = self
+            .pool
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Database not available"))?;
         let client = pool.get().await?;
 
         client.execute(
             r#"INSERT INTO "public"."User Preferences" 
-               ("user_id", "term_order", "term_disabled", "term_spoiler", "freq_order", "freq_disabled") 
-               VALUES ($1, $2, $3, $4, $5, $6)
+               ("user_id", "term_order", "term_disabled", "term_spoiler", "freq_order", "freq_disabled", "audio_disabled", "reader_settings", "popup_settings", "audio_order") 
+               VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
                ON CONFLICT ("user_id") DO UPDATE SET
                "term_order" = $2,
                "term_disabled" = $3,
                "term_spoiler" = $4,
                "freq_order" = $5,
-               "freq_disabled" = $6"#,
+               "freq_disabled" = $6,
+               "audio_disabled" = $7,
+               "reader_settings" = $8,
+               "popup_settings" = $9,
+               "audio_order" = $10"#,
             &[
                 &preferences.user_id,
                 &preferences.term_dictionary_order.join(","),
@@ -120,6 +86,10 @@ impl UserPreferencesStoreAsync for UserPreferencesSupabase {
                 &preferences.term_spoiler_dictionaries.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(","),
                 &preferences.freq_dictionary_order.join(","),
                 &preferences.freq_disabled_dictionaries.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(","),
+                &preferences.audio_disabled_sources.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(","),
+                &serde_json::to_string(&preferences.reader)?,
+                &serde_json::to_string(&preferences.popup)?,
+                &preferences.audio_source_order.join(","),
             ],
         ).await?;
 
@@ -128,10 +98,13 @@ impl UserPreferencesStoreAsync for UserPreferencesSupabase {
 
     #[instrument(skip(self))]
     async fn get(&self, user_id: Uuid) -> Result<UserPreferences> {
-        let pool = self.pool.as_ref().ok_or_else(|| anyhow::anyhow!("Database not available"))?;
+        let pool = self
+            .pool
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Database not available"))?;
         let client = pool.get().await?;
         let statement = client.prepare(
-            r#"SELECT "term_order", "term_disabled", "term_spoiler", "freq_order", "freq_disabled"
+            r#"SELECT "term_order", "term_disabled", "term_spoiler", "freq_order", "freq_disabled", "audio_disabled", "reader_settings", "popup_settings", "audio_order"
                FROM "public"."User Preferences"
                WHERE "user_id" = $1"#,
         ).await?;
@@ -179,13 +152,43 @@ impl UserPreferencesStoreAsync for UserPreferencesSupabase {
                 .filter(|s| !s.is_empty())
                 .map(String::from)
                 .collect(),
+            audio_disabled_sources: row
+                .get::<_, Option<String>>(5)
+                .unwrap_or_default()
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect(),
+            audio_source_order: row
+                .get::<_, Option<String>>(8)
+                .unwrap_or_default()
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect(),
+            reader: parse_settings(row.get::<_, Option<String>>(6).as_deref()),
+            popup: parse_settings(row.get::<_, Option<String>>(7).as_deref()),
         })
     }
 }
 
+// Settings stored as JSON. Rows from before the column existed, or with
+// settings that no longer parse, get the defaults.
+fn parse_settings<T: DeserializeOwned + Default>(json: Option<&str>) -> T {
+    match json.filter(|json| !json.trim().is_empty()) {
+        Some(json) => serde_json::from_str(json).unwrap_or_else(|e| {
+            warn!(?e, json, "⚠️ Invalid stored settings, using defaults");
+            T::default()
+        }),
+        None => T::default(),
+    }
+}
+
+#[cfg(test)]
 mod tests {
     #[allow(unused_imports)]
     use super::*;
+    use std::collections::HashSet;
 
     #[tokio::test]
     #[ignore]
@@ -199,7 +202,7 @@ mod tests {
             &std::env::var("SUPABASE_DATABASE").unwrap(),
         )
         .unwrap();
-        let supabase = UserPreferencesSupabase::new(Arc::new(pool), vec![]);
+        let supabase = UserPreferencesSupabase::new(Some(Arc::new(pool)), vec![]);
         let preferences = UserPreferences {
             user_id: Uuid::new_v4(),
             term_dictionary_order: vec!["".to_string()],
@@ -207,6 +210,10 @@ mod tests {
             term_spoiler_dictionaries: HashSet::new(),
             freq_dictionary_order: vec!["".to_string()],
             freq_disabled_dictionaries: HashSet::new(),
+            audio_disabled_sources: HashSet::new(),
+            audio_source_order: vec!["nhk16".to_string()],
+            reader: ReaderSettings::default(),
+            popup: PopupSettings::default(),
         };
         supabase.save(&preferences).await.unwrap();
         let preferences = supabase.get(preferences.user_id).await.unwrap();
@@ -215,6 +222,30 @@ mod tests {
         assert_eq!(preferences.term_spoiler_dictionaries, HashSet::new());
         assert_eq!(preferences.freq_dictionary_order, vec![""]);
         assert_eq!(preferences.freq_disabled_dictionaries, HashSet::new());
+        assert_eq!(preferences.audio_disabled_sources, HashSet::new());
+        assert_eq!(preferences.audio_source_order, vec!["nhk16"]);
+        assert_eq!(preferences.reader, ReaderSettings::default());
+        assert_eq!(preferences.popup, PopupSettings::default());
         println!("{:?}", preferences);
     }
+
+    #[test]
+    fn test_parse_settings() {
+        assert_eq!(
+            parse_settings::<ReaderSettings>(None),
+            ReaderSettings::default()
+        );
+        assert_eq!(
+            parse_settings::<ReaderSettings>(Some("")),
+            ReaderSettings::default()
+        );
+        assert_eq!(
+            parse_settings::<PopupSettings>(Some("not json")),
+            PopupSettings::default()
+        );
+        assert_eq!(
+            parse_settings::<ReaderSettings>(Some(r#"{"lineHeight": 2.0}"#)).line_height,
+            2.0
+        );
+    }
 }
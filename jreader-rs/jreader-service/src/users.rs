@@ -13,7 +13,10 @@ impl UsersSupabase {
     }
 
     pub async fn get_user_tier(&self, user_id: Uuid) -> Result<i16> {
-        let pool = self.pool.as_ref().ok_or_else(|| anyhow::anyhow!("Database not available"))?;
+        let pool = self
+            .pool
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Database not available"))?;
         let client = pool.get().await?;
 
         let row = client
@@ -44,7 +47,7 @@ mod tests {
             &std::env::var("SUPABASE_DATABASE").unwrap(),
         )
         .unwrap();
-        let users_db = UsersSupabase::new(Arc::new(pool));
+        let users_db = UsersSupabase::new(Some(Arc::new(pool)));
 
         // Test with a known user ID (you'll need to replace this with a real user ID from your database)
         let test_user_id = Uuid::new_v4();
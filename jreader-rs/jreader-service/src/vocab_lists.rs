@@ -0,0 +1,559 @@
+use anyhow::Result;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+/// A named list of vocabulary collected from lookups
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VocabList {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub user_id: String,
+    pub name: String,
+    /// Present when the list is shared through a read-only link
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub share_token: Option<String>,
+    /// Public lists show up in the public deck browser and can be cloned
+    pub is_public: bool,
+    pub entry_count: i64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VocabEntry {
+    pub id: Uuid,
+    pub term: String,
+    pub reading: Option<String>,
+    pub definition: Option<String>,
+    pub sentence: Option<String>,
+    pub position: i32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewVocabEntry {
+    pub term: String,
+    pub reading: Option<String>,
+    pub definition: Option<String>,
+    pub sentence: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VocabListWithEntries {
+    #[serde(flatten)]
+    pub list: VocabList,
+    pub entries: Vec<VocabEntry>,
+}
+
+pub const MAX_LIST_NAME_LENGTH: usize = 100;
+pub const MAX_PUBLIC_DECKS_PAGE_SIZE: i64 = 100;
+
+/// Trim and check a list name, returning the cleaned up name
+pub fn validate_list_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("List name must not be empty".to_string());
+    }
+    if name.chars().count() > MAX_LIST_NAME_LENGTH {
+        return Err(format!(
+            "List name must be at most {} characters",
+            MAX_LIST_NAME_LENGTH
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// Check that `requested` is a permutation of `existing` and return the new
+/// position of every entry
+pub fn reorder_positions(
+    existing: &[Uuid],
+    requested: &[Uuid],
+) -> Result<Vec<(Uuid, i32)>, String> {
+    let existing: HashSet<&Uuid> = existing.iter().collect();
+    let requested_set: HashSet<&Uuid> = requested.iter().collect();
+    if requested_set.len() != requested.len() {
+        return Err("Entry ids must not contain duplicates".to_string());
+    }
+    if existing != requested_set {
+        return Err("Entry ids must match the entries of the list exactly".to_string());
+    }
+    Ok(requested
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (*id, i as i32))
+        .collect())
+}
+
+const LIST_COLUMNS: &str = r#"l."id", l."user_id", l."name", l."share_token", l."is_public", l."created_at", l."updated_at",
+    (SELECT COUNT(*) FROM "public"."Vocab List Entries" e WHERE e."list_id" = l."id") AS "entry_count""#;
+
+/// Escape `%`, `_` and `\` so user input matches literally inside a LIKE pattern
+pub fn escape_like(query: &str) -> String {
+    let mut escaped = String::with_capacity(query.len());
+    for c in query.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn row_to_list(row: &Row) -> VocabList {
+    VocabList {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        name: row.get("name"),
+        share_token: row.get("share_token"),
+        is_public: row.get("is_public"),
+        entry_count: row.get("entry_count"),
+        created_at: row.get::<_, SystemTime>("created_at").into(),
+        updated_at: row.get::<_, SystemTime>("updated_at").into(),
+    }
+}
+
+fn row_to_entry(row: &Row) -> VocabEntry {
+    VocabEntry {
+        id: row.get("id"),
+        term: row.get("term"),
+        reading: row.get("reading"),
+        definition: row.get("definition"),
+        sentence: row.get("sentence"),
+        position: row.get("position"),
+        created_at: row.get::<_, SystemTime>("created_at").into(),
+    }
+}
+
+/// Vocab lists stored in the `"Vocab Lists"` and `"Vocab List Entries"` tables.
+///
+/// All methods taking a `user_id` only touch lists owned by that user; they
+/// return `None`/`false` for lists that don't exist or belong to someone else.
+pub struct VocabListsSupabase {
+    pool: Option<Arc<Pool>>,
+}
+
+impl VocabListsSupabase {
+    pub fn new(pool: Option<Arc<Pool>>) -> Self {
+        Self { pool }
+    }
+
+    fn pool(&self) -> Result<&Pool> {
+        self.pool
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Database not available"))
+    }
+
+    pub async fn create_list(&self, user_id: &str, name: &str) -> Result<VocabList> {
+        let client = self.pool()?.get().await?;
+        let id = Uuid::new_v4();
+        let now = SystemTime::now();
+        client
+            .execute(
+                r#"INSERT INTO "public"."Vocab Lists" ("id", "user_id", "name", "created_at", "updated_at")
+                   VALUES ($1, $2, $3, $4, $4)"#,
+                &[&id, &user_id, &name, &now],
+            )
+            .await?;
+
+        Ok(VocabList {
+            id,
+            user_id: user_id.to_string(),
+            name: name.to_string(),
+            share_token: None,
+            is_public: false,
+            entry_count: 0,
+            created_at: now.into(),
+            updated_at: now.into(),
+        })
+    }
+
+    pub async fn get_user_lists(&self, user_id: &str) -> Result<Vec<VocabList>> {
+        let client = self.pool()?.get().await?;
+        let rows = client
+            .query(
+                &format!(
+                    r#"SELECT {LIST_COLUMNS} FROM "public"."Vocab Lists" l
+                       WHERE l."user_id" = $1 ORDER BY l."updated_at" DESC"#
+                ),
+                &[&user_id],
+            )
+            .await?;
+        Ok(rows.iter().map(row_to_list).collect())
+    }
+
+    /// Every term the user has saved to any of their lists
+    pub async fn get_user_terms(&self, user_id: &str) -> Result<HashSet<String>> {
+        let client = self.pool()?.get().await?;
+        let rows = client
+            .query(
+                r#"SELECT DISTINCT e."term" FROM "public"."Vocab List Entries" e
+                   JOIN "public"."Vocab Lists" l ON l."id" = e."list_id"
+                   WHERE l."user_id" = $1"#,
+                &[&user_id],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get("term")).collect())
+    }
+
+    async fn get_entries(&self, list_id: Uuid) -> Result<Vec<VocabEntry>> {
+        let client = self.pool()?.get().await?;
+        let rows = client
+            .query(
+                r#"SELECT "id", "term", "reading", "definition", "sentence", "position", "created_at"
+                   FROM "public"."Vocab List Entries"
+                   WHERE "list_id" = $1 ORDER BY "position", "created_at""#,
+                &[&list_id],
+            )
+            .await?;
+        Ok(rows.iter().map(row_to_entry).collect())
+    }
+
+    pub async fn get_list(
+        &self,
+        user_id: &str,
+        list_id: Uuid,
+    ) -> Result<Option<VocabListWithEntries>> {
+        let client = self.pool()?.get().await?;
+        let row = client
+            .query_opt(
+                &format!(
+                    r#"SELECT {LIST_COLUMNS} FROM "public"."Vocab Lists" l
+                       WHERE l."id" = $1 AND l."user_id" = $2"#
+                ),
+                &[&list_id, &user_id],
+            )
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        Ok(Some(VocabListWithEntries {
+            list: row_to_list(&row),
+            entries: self.get_entries(list_id).await?,
+        }))
+    }
+
+    /// Read-only access to a list through its share token
+    pub async fn get_shared_list(&self, share_token: &str) -> Result<Option<VocabListWithEntries>> {
+        let client = self.pool()?.get().await?;
+        let row = client
+            .query_opt(
+                &format!(
+                    r#"SELECT {LIST_COLUMNS} FROM "public"."Vocab Lists" l
+                       WHERE l."share_token" = $1"#
+                ),
+                &[&share_token],
+            )
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let list = row_to_list(&row);
+        let entries = self.get_entries(list.id).await?;
+        Ok(Some(VocabListWithEntries { list, entries }))
+    }
+
+    pub async fn rename_list(&self, user_id: &str, list_id: Uuid, name: &str) -> Result<bool> {
+        let client = self.pool()?.get().await?;
+        let updated = client
+            .execute(
+                r#"UPDATE "public"."Vocab Lists" SET "name" = $3, "updated_at" = $4
+                   WHERE "id" = $1 AND "user_id" = $2"#,
+                &[&list_id, &user_id, &name, &SystemTime::now()],
+            )
+            .await?;
+        Ok(updated > 0)
+    }
+
+    pub async fn delete_list(&self, user_id: &str, list_id: Uuid) -> Result<bool> {
+        let client = self.pool()?.get().await?;
+        let deleted = client
+            .execute(
+                r#"DELETE FROM "public"."Vocab Lists" WHERE "id" = $1 AND "user_id" = $2"#,
+                &[&list_id, &user_id],
+            )
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    /// Append an entry to the end of a list
+    pub async fn add_entry(
+        &self,
+        user_id: &str,
+        list_id: Uuid,
+        entry: &NewVocabEntry,
+    ) -> Result<Option<VocabEntry>> {
+        let mut client = self.pool()?.get().await?;
+        let transaction = client.transaction().await?;
+
+        let now = SystemTime::now();
+        let owned = transaction
+            .execute(
+                r#"UPDATE "public"."Vocab Lists" SET "updated_at" = $3
+                   WHERE "id" = $1 AND "user_id" = $2"#,
+                &[&list_id, &user_id, &now],
+            )
+            .await?;
+        if owned == 0 {
+            return Ok(None);
+        }
+
+        let id = Uuid::new_v4();
+        let row = transaction
+            .query_one(
+                r#"INSERT INTO "public"."Vocab List Entries"
+                   ("id", "list_id", "term", "reading", "definition", "sentence", "position", "created_at")
+                   VALUES ($1, $2, $3, $4, $5, $6,
+                       (SELECT COALESCE(MAX("position") + 1, 0) FROM "public"."Vocab List Entries" WHERE "list_id" = $2),
+                       $7)
+                   RETURNING "id", "term", "reading", "definition", "sentence", "position", "created_at""#,
+                &[
+                    &id,
+                    &list_id,
+                    &entry.term,
+                    &entry.reading,
+                    &entry.definition,
+                    &entry.sentence,
+                    &now,
+                ],
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(Some(row_to_entry(&row)))
+    }
+
+    pub async fn remove_entry(&self, user_id: &str, list_id: Uuid, entry_id: Uuid) -> Result<bool> {
+        let client = self.pool()?.get().await?;
+        let deleted = client
+            .execute(
+                r#"DELETE FROM "public"."Vocab List Entries" e
+                   USING "public"."Vocab Lists" l
+                   WHERE e."id" = $1 AND e."list_id" = $2 AND l."id" = e."list_id" AND l."user_id" = $3"#,
+                &[&entry_id, &list_id, &user_id],
+            )
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    /// Reorder the entries of a list. `entry_ids` must contain every entry of
+    /// the list exactly once. Returns `Ok(None)` if the list isn't found.
+    pub async fn reorder_entries(
+        &self,
+        user_id: &str,
+        list_id: Uuid,
+        entry_ids: &[Uuid],
+    ) -> Result<Option<Result<(), String>>> {
+        let mut client = self.pool()?.get().await?;
+        let transaction = client.transaction().await?;
+
+        let owned = transaction
+            .execute(
+                r#"UPDATE "public"."Vocab Lists" SET "updated_at" = $3
+                   WHERE "id" = $1 AND "user_id" = $2"#,
+                &[&list_id, &user_id, &SystemTime::now()],
+            )
+            .await?;
+        if owned == 0 {
+            return Ok(None);
+        }
+
+        let existing: Vec<Uuid> = transaction
+            .query(
+                r#"SELECT "id" FROM "public"."Vocab List Entries" WHERE "list_id" = $1"#,
+                &[&list_id],
+            )
+            .await?
+            .iter()
+            .map(|row| row.get("id"))
+            .collect();
+        let positions = match reorder_positions(&existing, entry_ids) {
+            Ok(positions) => positions,
+            Err(e) => return Ok(Some(Err(e))),
+        };
+
+        let statement = transaction
+            .prepare(r#"UPDATE "public"."Vocab List Entries" SET "position" = $2 WHERE "id" = $1"#)
+            .await?;
+        for (id, position) in positions {
+            transaction.execute(&statement, &[&id, &position]).await?;
+        }
+        transaction.commit().await?;
+
+        Ok(Some(Ok(())))
+    }
+
+    /// Create (or replace) the share token of a list, returning it
+    pub async fn share_list(&self, user_id: &str, list_id: Uuid) -> Result<Option<String>> {
+        let client = self.pool()?.get().await?;
+        let token = Uuid::new_v4().simple().to_string();
+        let updated = client
+            .execute(
+                r#"UPDATE "public"."Vocab Lists" SET "share_token" = $3
+                   WHERE "id" = $1 AND "user_id" = $2"#,
+                &[&list_id, &user_id, &token],
+            )
+            .await?;
+        Ok((updated > 0).then_some(token))
+    }
+
+    pub async fn unshare_list(&self, user_id: &str, list_id: Uuid) -> Result<bool> {
+        let client = self.pool()?.get().await?;
+        let updated = client
+            .execute(
+                r#"UPDATE "public"."Vocab Lists" SET "share_token" = NULL
+                   WHERE "id" = $1 AND "user_id" = $2"#,
+                &[&list_id, &user_id],
+            )
+            .await?;
+        Ok(updated > 0)
+    }
+
+    pub async fn set_public(&self, user_id: &str, list_id: Uuid, is_public: bool) -> Result<bool> {
+        let client = self.pool()?.get().await?;
+        let updated = client
+            .execute(
+                r#"UPDATE "public"."Vocab Lists" SET "is_public" = $3
+                   WHERE "id" = $1 AND "user_id" = $2"#,
+                &[&list_id, &user_id, &is_public],
+            )
+            .await?;
+        Ok(updated > 0)
+    }
+
+    /// Public lists whose name contains `query` (case-insensitive), newest first
+    pub async fn search_public_lists(
+        &self,
+        query: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<VocabList>> {
+        let client = self.pool()?.get().await?;
+        let pattern = format!("%{}%", escape_like(query.unwrap_or("").trim()));
+        let rows = client
+            .query(
+                &format!(
+                    r#"SELECT {LIST_COLUMNS} FROM "public"."Vocab Lists" l
+                       WHERE l."is_public" AND l."name" ILIKE $1
+                       ORDER BY l."updated_at" DESC LIMIT $2 OFFSET $3"#
+                ),
+                &[&pattern, &limit, &offset],
+            )
+            .await?;
+        Ok(rows.iter().map(row_to_list).collect())
+    }
+
+    pub async fn get_public_list(&self, list_id: Uuid) -> Result<Option<VocabListWithEntries>> {
+        let client = self.pool()?.get().await?;
+        let row = client
+            .query_opt(
+                &format!(
+                    r#"SELECT {LIST_COLUMNS} FROM "public"."Vocab Lists" l
+                       WHERE l."id" = $1 AND l."is_public""#
+                ),
+                &[&list_id],
+            )
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        Ok(Some(VocabListWithEntries {
+            list: row_to_list(&row),
+            entries: self.get_entries(list_id).await?,
+        }))
+    }
+
+    /// Copy a public list and all of its entries into `user_id`'s account.
+    ///
+    /// The copy is private and unshared, and is independent of the original:
+    /// later edits on either side don't affect the other.
+    pub async fn clone_public_list(
+        &self,
+        user_id: &str,
+        list_id: Uuid,
+    ) -> Result<Option<VocabList>> {
+        let mut client = self.pool()?.get().await?;
+        let transaction = client.transaction().await?;
+
+        let new_id = Uuid::new_v4();
+        let now = SystemTime::now();
+        let row = transaction
+            .query_opt(
+                r#"INSERT INTO "public"."Vocab Lists" ("id", "user_id", "name", "is_public", "created_at", "updated_at")
+                   SELECT $2, $3, "name", FALSE, $4, $4 FROM "public"."Vocab Lists"
+                   WHERE "id" = $1 AND "is_public"
+                   RETURNING "name""#,
+                &[&list_id, &new_id, &user_id, &now],
+            )
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let entry_count = transaction
+            .execute(
+                r#"INSERT INTO "public"."Vocab List Entries"
+                   ("id", "list_id", "term", "reading", "definition", "sentence", "position", "created_at")
+                   SELECT gen_random_uuid(), $2, "term", "reading", "definition", "sentence", "position", $3
+                   FROM "public"."Vocab List Entries" WHERE "list_id" = $1"#,
+                &[&list_id, &new_id, &now],
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(Some(VocabList {
+            id: new_id,
+            user_id: user_id.to_string(),
+            name: row.get("name"),
+            share_token: None,
+            is_public: false,
+            entry_count: entry_count as i64,
+            created_at: now.into(),
+            updated_at: now.into(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_list_name() {
+        assert_eq!(validate_list_name("  N3 verbs ").unwrap(), "N3 verbs");
+        assert!(validate_list_name("   ").is_err());
+        assert!(validate_list_name(&"語".repeat(MAX_LIST_NAME_LENGTH)).is_ok());
+        assert!(validate_list_name(&"語".repeat(MAX_LIST_NAME_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("JLPT N3"), "JLPT N3");
+        assert_eq!(escape_like("100%_done\\"), "100\\%\\_done\\\\");
+    }
+
+    #[test]
+    fn test_reorder_positions() {
+        let a = Uuid::new_v4();
+        let b = Uuid::new_v4();
+        let c = Uuid::new_v4();
+
+        assert_eq!(
+            reorder_positions(&[a, b, c], &[c, a, b]).unwrap(),
+            vec![(c, 0), (a, 1), (b, 2)]
+        );
+        // Missing, extra and duplicate ids are rejected
+        assert!(reorder_positions(&[a, b, c], &[a, b]).is_err());
+        assert!(reorder_positions(&[a, b], &[a, b, c]).is_err());
+        assert!(reorder_positions(&[a, b], &[a, a]).is_err());
+        assert!(reorder_positions(&[], &[]).unwrap().is_empty());
+    }
+}
@@ -1,11 +1,13 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-use crate::kv_store::IsYomitanSchema;
+use super::IsYomitanSchema;
 
 pub type KanjiBankV3 = Vec<KanjiEntry>;
 
 impl IsYomitanSchema for KanjiBankV3 {
+    type Entry = KanjiEntry;
+
     fn get_schema_prefix() -> &'static str {
         "kanji_bank_"
     }
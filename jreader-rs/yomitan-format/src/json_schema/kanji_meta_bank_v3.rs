@@ -1,10 +1,12 @@
 use serde::{Deserialize, Serialize};
 
-use crate::kv_store::IsYomitanSchema;
+use super::IsYomitanSchema;
 
 pub type KanjiMetaBankV3 = Vec<KanjiMetaEntry>;
 
 impl IsYomitanSchema for KanjiMetaBankV3 {
+    type Entry = KanjiMetaEntry;
+
     fn get_schema_prefix() -> &'static str {
         "kanji_meta_bank_"
     }
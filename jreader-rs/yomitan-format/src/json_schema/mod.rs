@@ -1,6 +1,38 @@
 pub mod index;
 pub mod kanji_bank_v3;
 pub mod kanji_meta_bank_v3;
+pub mod structured_content;
 pub mod tag_bank_v3;
 pub mod term_bank_v3;
 pub mod term_meta_bank_v3;
+#[cfg(feature = "schema-validation")]
+pub mod validation;
+
+use serde::{Deserialize, Serialize};
+
+/// A bank file type, identified inside an archive by its file name prefix
+pub trait IsYomitanSchema {
+    /// One row of the bank
+    type Entry: serde::de::DeserializeOwned;
+
+    fn get_schema_prefix() -> &'static str;
+    fn get_schema_name() -> &'static str;
+
+    /// Sequence number of a raw bank entry, for schemas whose entries group
+    /// into lexemes by one
+    fn get_sequence_number(_entry: &serde_json::Value) -> Option<i64> {
+        None
+    }
+}
+
+/// A place where a bank file doesn't match the official Yomitan JSON schema
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaViolation {
+    pub file: String,
+    /// JSON pointer to the offending value in the file, e.g. `/12/5/0`
+    pub instance_path: String,
+    /// JSON pointer to the rule that failed in the schema
+    pub schema_path: String,
+    pub message: String,
+}
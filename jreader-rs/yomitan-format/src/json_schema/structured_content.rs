@@ -0,0 +1,49 @@
+//! Helpers for Yomitan structured content: the JSON tree of `{tag, content}`
+//! nodes used by `structured-content` definitions.
+
+/// Plain text of a structured content tree. Block elements (`li`, `div`, `p`,
+/// `br`) start a new line; markup and images are dropped.
+pub fn plain_text(content: &serde_json::Value) -> String {
+    fn collect(value: &serde_json::Value, out: &mut String) {
+        match value {
+            serde_json::Value::String(s) => out.push_str(s),
+            serde_json::Value::Array(items) => items.iter().for_each(|v| collect(v, out)),
+            serde_json::Value::Object(map) => {
+                let block = matches!(
+                    map.get("tag").and_then(|t| t.as_str()),
+                    Some("li" | "div" | "p" | "br")
+                );
+                if block && !out.is_empty() && !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                if let Some(content) = map.get("content") {
+                    collect(content, out);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut out = String::new();
+    collect(content, &mut out);
+    out.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_plain_text() {
+        let content = json!([
+            {"tag": "ul", "content": [
+                {"tag": "li", "content": "magic"},
+                {"tag": "li", "content": ["sorcery", {"tag": "span", "content": " (arch.)"}]}
+            ]},
+            {"tag": "img", "path": "img/magic.png"}
+        ]);
+        assert_eq!(plain_text(&content), "magic\nsorcery (arch.)");
+        assert_eq!(plain_text(&json!("to eat")), "to eat");
+    }
+}
@@ -1,10 +1,12 @@
 use serde::{Deserialize, Serialize};
 
-use crate::kv_store::IsYomitanSchema;
+use super::IsYomitanSchema;
 
 pub type TagBankV3 = Vec<TagEntry>;
 
 impl IsYomitanSchema for TagBankV3 {
+    type Entry = TagEntry;
+
     fn get_schema_prefix() -> &'static str {
         "tag_bank_"
     }
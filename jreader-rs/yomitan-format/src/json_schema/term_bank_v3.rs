@@ -3,11 +3,13 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
 
-use crate::kv_store::IsYomitanSchema;
+use super::IsYomitanSchema;
 
 pub type TermBankV3 = Vec<TermEntry>;
 
 impl IsYomitanSchema for TermBankV3 {
+    type Entry = TermEntry;
+
     fn get_schema_prefix() -> &'static str {
         "term_bank_"
     }
@@ -15,6 +17,10 @@ impl IsYomitanSchema for TermBankV3 {
     fn get_schema_name() -> &'static str {
         "Term Bank V3"
     }
+
+    fn get_sequence_number(entry: &serde_json::Value) -> Option<i64> {
+        entry.get(6)?.as_i64()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
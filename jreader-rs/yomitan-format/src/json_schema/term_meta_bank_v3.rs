@@ -4,11 +4,13 @@ use serde::{
 };
 use std::fmt;
 
-use crate::kv_store::IsYomitanSchema;
+use super::IsYomitanSchema;
 
 pub type TermMetaBankV3 = Vec<TermMetaEntry>;
 
 impl IsYomitanSchema for TermMetaBankV3 {
+    type Entry = TermMetaEntry;
+
     fn get_schema_prefix() -> &'static str {
         "term_meta_bank_"
     }
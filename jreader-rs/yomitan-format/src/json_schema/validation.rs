@@ -0,0 +1,160 @@
+//! Validation of bank files against the official Yomitan JSON schemas, for
+//! dictionary authors who want every problem in a file with its location
+//! rather than the first deserialization error.
+//!
+//! The schemas in `schemas/` follow Yomitan's `ext/data/schemas`
+//! (draft-07). They are stricter than the typed schemas used for import,
+//! e.g. they reject unknown keys in structured content.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use jsonschema::Validator;
+use lazy_static::lazy_static;
+
+use super::{
+    kanji_bank_v3::KanjiBankV3, kanji_meta_bank_v3::KanjiMetaBankV3, tag_bank_v3::TagBankV3,
+    term_bank_v3::TermBankV3, term_meta_bank_v3::TermMetaBankV3, IsYomitanSchema, SchemaViolation,
+};
+
+lazy_static! {
+    // Compiled once, keyed by schema name
+    static ref VALIDATORS: HashMap<&'static str, Validator> = [
+        (
+            TermBankV3::get_schema_name(),
+            include_str!("schemas/dictionary-term-bank-v3-schema.json"),
+        ),
+        (
+            TermMetaBankV3::get_schema_name(),
+            include_str!("schemas/dictionary-term-meta-bank-v3-schema.json"),
+        ),
+        (
+            KanjiBankV3::get_schema_name(),
+            include_str!("schemas/dictionary-kanji-bank-v3-schema.json"),
+        ),
+        (
+            KanjiMetaBankV3::get_schema_name(),
+            include_str!("schemas/dictionary-kanji-meta-bank-v3-schema.json"),
+        ),
+        (
+            TagBankV3::get_schema_name(),
+            include_str!("schemas/dictionary-tag-bank-v3-schema.json"),
+        ),
+    ]
+    .into_iter()
+    .map(|(name, source)| {
+        let schema = serde_json::from_str(source).expect("bundled schema is valid JSON");
+        let validator = jsonschema::validator_for(&schema).expect("bundled schema compiles");
+        (name, validator)
+    })
+    .collect();
+}
+
+/// Check the contents of the `SchemaType` bank `file` against its schema,
+/// returning at most `limit` violations
+pub fn validate_bank<SchemaType: IsYomitanSchema>(
+    file: &str,
+    bank: &serde_json::Value,
+    limit: usize,
+) -> Result<Vec<SchemaViolation>> {
+    let name = SchemaType::get_schema_name();
+    let validator = VALIDATORS
+        .get(name)
+        .ok_or_else(|| anyhow::anyhow!("No JSON schema bundled for {name}"))?;
+    Ok(validator
+        .iter_errors(bank)
+        .take(limit)
+        .map(|error| SchemaViolation {
+            file: file.to_string(),
+            instance_path: error.instance_path().to_string(),
+            schema_path: error.schema_path().to_string(),
+            // Masked so a violation at the root doesn't repeat the whole bank
+            message: error.masked().to_string(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_valid_banks() {
+        let term_bank = json!([
+            ["打", "だ", "n", "n", 1, ["da definition 1"], 1, "E1"],
+            ["打つ", "うつ", null, "v5", -1.5, [
+                {"type": "text", "text": "to hit"},
+                {"type": "structured-content", "content": [
+                    "to ",
+                    {"tag": "span", "style": {"fontWeight": "bold"}, "content": "strike"},
+                    {"tag": "img", "path": "img/utsu.png", "width": 2, "sizeUnits": "em"},
+                    {"tag": "a", "href": "?query=打つ", "content": "see also"}
+                ]},
+                ["打つ", ["past"]]
+            ], 3, ""]
+        ]);
+        assert_eq!(
+            validate_bank::<TermBankV3>("term_bank_1.json", &term_bank, 10).unwrap(),
+            []
+        );
+
+        let term_meta_bank = json!([
+            ["打", "freq", 10],
+            ["打つ", "freq", {"reading": "うつ", "frequency": {"value": 3, "displayValue": "3㋕"}}],
+            ["打つ", "pitch", {"reading": "うつ", "pitches": [{"position": 1, "nasal": [2]}]}],
+            ["打つ", "ipa", {"reading": "うつ", "transcriptions": [{"ipa": "ɯ́tsɨ", "tags": []}]}]
+        ]);
+        assert_eq!(
+            validate_bank::<TermMetaBankV3>("term_meta_bank_1.json", &term_meta_bank, 10).unwrap(),
+            []
+        );
+
+        let tag_bank = json!([["n", "partOfSpeech", 0, "noun", 0]]);
+        assert_eq!(
+            validate_bank::<TagBankV3>("tag_bank_1.json", &tag_bank, 10).unwrap(),
+            []
+        );
+        let kanji_bank = json!([["打", "ダ", "う.つ", "jouyou", ["hit"], {"strokes": "5"}]]);
+        assert_eq!(
+            validate_bank::<KanjiBankV3>("kanji_bank_1.json", &kanji_bank, 10).unwrap(),
+            []
+        );
+        let kanji_meta_bank = json!([["打", "freq", {"value": 1000}]]);
+        assert_eq!(
+            validate_bank::<KanjiMetaBankV3>("kanji_meta_bank_1.json", &kanji_meta_bank, 10)
+                .unwrap(),
+            []
+        );
+    }
+
+    #[test]
+    fn test_violation_locations() {
+        let term_bank = json!([
+            ["打", "だ", "n", "n", 1, ["da definition 1"], 1, "E1"],
+            ["打つ", "うつ", "vt", "v5", "high", ["utsu"], 3, "P"],
+            ["打つ", "ぶつ", "vt", "v5", 1, [{"type": "structured-content", "content": {"tag": "span", "colour": "red"}}], 3, "P"],
+            ["打", "だ", "n", "n", 1, [], 1]
+        ]);
+        let violations = validate_bank::<TermBankV3>("term_bank_2.json", &term_bank, 10).unwrap();
+        let paths: Vec<_> = violations
+            .iter()
+            .map(|v| v.instance_path.as_str())
+            .collect();
+        assert!(paths.contains(&"/1/4"), "{violations:#?}");
+        assert!(
+            paths.iter().any(|p| p.starts_with("/2/5/0")),
+            "{violations:#?}"
+        );
+        assert!(paths.contains(&"/3"), "{violations:#?}");
+        assert!(violations.iter().all(|v| v.file == "term_bank_2.json"));
+        assert!(!violations[0].message.contains("utsu"), "{violations:#?}");
+
+        assert_eq!(
+            validate_bank::<TermBankV3>("term_bank_2.json", &term_bank, 1)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
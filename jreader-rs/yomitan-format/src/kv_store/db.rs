@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::os::unix::fs::PermissionsExt;
 use std::sync::{Arc, Mutex};
@@ -22,6 +23,9 @@ where
     schema_type: PhantomData<SchemaType>,
 }
 
+// Keys per query of `get_many`, well below SQLite's limit on bound parameters
+const GET_MANY_CHUNK_SIZE: usize = 500;
+
 fn convert_path_to_uri(path: &Path) -> Result<String> {
     let uri_path = format!(
         "file:{}",
@@ -98,6 +102,19 @@ where
         )?;
         debug!("Created index idx_term_key for path: {:?}", path);
 
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS term_sequence (
+                sequence  INTEGER NOT NULL,
+                key  TEXT NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_term_sequence ON term_sequence(sequence);",
+            [],
+        )?;
+        debug!("Created table term_sequence for path: {:?}", path);
+
         Ok(Self {
             path,
             conn: Mutex::new(conn),
@@ -105,9 +122,18 @@ where
         })
     }
 
-    pub fn open_ro(dir_path: &Path) -> Result<Option<Self>> {
+    fn path_in(dir_path: &Path) -> PathBuf {
         let prefix = SchemaType::get_schema_prefix();
-        let path = dir_path.join(format!("{prefix}dict.db"));
+        dir_path.join(format!("{prefix}dict.db"))
+    }
+
+    /// Whether the dictionary in `dir_path` has a database for this schema
+    pub fn exists(dir_path: &Path) -> bool {
+        Self::path_in(dir_path).exists()
+    }
+
+    pub fn open_ro(dir_path: &Path) -> Result<Option<Self>> {
+        let path = Self::path_in(dir_path);
         if !path.exists() {
             return Ok(None);
         }
@@ -187,6 +213,18 @@ where
             total_processed += batch.len();
         }
 
+        // Index the keys by the sequence numbers of their entries. 0 means
+        // the entry isn't grouped with others.
+        {
+            let mut stmt =
+                tx.prepare("INSERT INTO term_sequence (sequence, key) VALUES (?1, ?2)")?;
+            for (key, json_list) in grouped_json.0.iter() {
+                for sequence in entry_sequences::<SchemaType>(json_list) {
+                    stmt.execute((sequence, key))?;
+                }
+            }
+        }
+
         tx.commit()?;
         debug!(
             "Inserted {} entries successfully for: {:?}",
@@ -195,6 +233,56 @@ where
         Ok(())
     }
 
+    /// Replace the entries stored under `key` with `entries`, removing the key
+    /// when there are none. For databases edited in place rather than
+    /// imported, such as custom dictionaries.
+    pub fn replace(&self, key: &str, entries: &[serde_json::Value]) -> Result<()> {
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire connection lock: {e}"))?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM term_entry WHERE key = ?1", [key])?;
+        tx.execute("DELETE FROM term_sequence WHERE key = ?1", [key])?;
+        if !entries.is_empty() {
+            tx.execute(
+                "INSERT INTO term_entry (key, json) VALUES (?1, ?2)",
+                (key, serde_json::to_string(entries)?),
+            )?;
+            for sequence in entry_sequences::<SchemaType>(entries) {
+                tx.execute(
+                    "INSERT INTO term_sequence (sequence, key) VALUES (?1, ?2)",
+                    (sequence, key),
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Highest sequence number in the sequence index, 0 if it is empty
+    pub fn max_sequence(&self) -> Result<i64> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire connection lock: {e}"))?;
+        let mut stmt = conn.prepare("SELECT COALESCE(MAX(sequence), 0) FROM term_sequence")?;
+        Ok(stmt.query_row([], |row| row.get::<_, i64>(0))?)
+    }
+
+    /// Every key with its JSON, in key order
+    pub fn get_all(&self) -> Result<Vec<(String, String)>> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire connection lock: {e}"))?;
+        let mut stmt = conn.prepare("SELECT key, json FROM term_entry ORDER BY key, id")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
     pub fn get(&self, key: &str) -> Result<Option<String>> {
         let conn = self
             .conn
@@ -211,6 +299,152 @@ where
         }
     }
 
+    /// JSON of each of `keys` that is in the database, like [`DictionaryDB::get`]
+    /// for every key but with one query per chunk of keys rather than per key
+    pub fn get_many(&self, keys: &[&str]) -> Result<HashMap<String, String>> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire connection lock: {e}"))?;
+        let mut found = HashMap::new();
+        for chunk in keys.chunks(GET_MANY_CHUNK_SIZE) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let mut stmt = conn.prepare_cached(&format!(
+                "SELECT key, json FROM term_entry WHERE key IN ({placeholders}) ORDER BY id"
+            ))?;
+            let mut rows = stmt.query(rusqlite::params_from_iter(chunk))?;
+            while let Some(row) = rows.next()? {
+                // The first row of a key, which is the one `get` returns
+                found.entry(row.get(0)?).or_insert(row.get(1)?);
+            }
+        }
+        trace!(
+            "🔍 Found {}/{} keys, path: {:?}",
+            found.len(),
+            keys.len(),
+            self.path
+        );
+        Ok(found)
+    }
+
+    /// Distinct keys starting with `prefix`, in key order. This is a range scan
+    /// on the key index, so it stays fast on large dictionaries.
+    pub fn get_keys_with_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<String>> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire connection lock: {e}"))?;
+        // Every key starting with `prefix` sorts below `prefix` + the last code point
+        let upper_bound = format!("{prefix}{}", char::MAX);
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT key FROM term_entry WHERE key >= ?1 AND key < ?2 ORDER BY key LIMIT ?3",
+        )?;
+        let keys = stmt
+            .query_map((prefix, upper_bound, limit as i64), |row| {
+                row.get::<_, String>(0)
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(keys)
+    }
+
+    /// Distinct keys matching a wildcard `pattern`, where `*` matches any run of
+    /// characters and `?` a single one, in key order. Only the key range of the
+    /// literal prefix is scanned, so a leading wildcard reads every key unless
+    /// `max_scanned` caps the rows read.
+    pub fn get_keys_matching_wildcard(
+        &self,
+        pattern: &str,
+        limit: usize,
+        max_scanned: Option<usize>,
+    ) -> Result<WildcardMatches> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire connection lock: {e}"))?;
+        let prefix: String = pattern
+            .chars()
+            .take_while(|c| !matches!(c, '*' | '?'))
+            .collect();
+        let upper_bound = format!("{prefix}{}", char::MAX);
+        let mut stmt = conn.prepare(
+            "SELECT key, key GLOB ?1 FROM term_entry WHERE key >= ?2 AND key < ?3 ORDER BY key LIMIT ?4",
+        )?;
+        // A negative LIMIT means no limit in SQLite
+        let scan_limit = max_scanned.map_or(-1, |max| max as i64);
+        let mut rows = stmt.query((wildcard_to_glob(pattern), &prefix, upper_bound, scan_limit))?;
+
+        let mut matches = WildcardMatches::default();
+        let mut scanned = 0;
+        while let Some(row) = rows.next()? {
+            scanned += 1;
+            if !row.get::<_, bool>(1)? {
+                continue;
+            }
+            let key: String = row.get(0)?;
+            if matches.keys.last() != Some(&key) {
+                if matches.keys.len() == limit {
+                    return Ok(matches);
+                }
+                matches.keys.push(key);
+            }
+        }
+        matches.scan_limited = max_scanned.is_some_and(|max| scanned >= max);
+        Ok(matches)
+    }
+
+    /// Keys with entries of sequence number `sequence`, from the index built
+    /// by [`DictionaryDB::insert_all`]
+    pub fn get_by_sequence(&self, sequence: i64) -> Result<Vec<String>> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire connection lock: {e}"))?;
+        let mut stmt =
+            conn.prepare("SELECT key FROM term_sequence WHERE sequence = ? ORDER BY key")?;
+        let keys = stmt
+            .query_map([sequence], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(keys)
+    }
+
+    /// Whether the database has the sequence number index, which databases
+    /// imported before it was added lack
+    pub fn has_sequence_index(&self) -> Result<bool> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire connection lock: {e}"))?;
+        let mut stmt = conn.prepare(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'term_sequence'",
+        )?;
+        Ok(stmt.query_row([], |row| row.get::<_, i64>(0))? > 0)
+    }
+
+    /// Distinct keys of the term bank entries with one of the `sequences`.
+    /// Every row's JSON is read, so this is a scan of the whole table, for
+    /// databases without the sequence number index.
+    pub fn get_keys_with_sequences(&self, sequences: &[i64]) -> Result<Vec<String>> {
+        if sequences.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire connection lock: {e}"))?;
+        let placeholders = vec!["?"; sequences.len()].join(", ");
+        // Term bank entries are arrays with the sequence number at index 6
+        let mut stmt = conn.prepare(&format!(
+            "SELECT DISTINCT term_entry.key FROM term_entry, json_each(term_entry.json) AS entry
+             WHERE json_extract(entry.value, '$[6]') IN ({placeholders}) ORDER BY term_entry.key"
+        ))?;
+        let keys = stmt
+            .query_map(rusqlite::params_from_iter(sequences), |row| {
+                row.get::<_, String>(0)
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(keys)
+    }
+
     pub fn get_first_row(&self) -> Result<Option<String>> {
         let conn = self
             .conn
@@ -230,6 +464,54 @@ where
         let mut rows = stmt.query_map([], |row| row.get::<_, i64>(0))?;
         Ok(rows.next().transpose()?.unwrap_or(0))
     }
+
+    /// Whether the bank has any entries, without counting them all
+    pub fn has_rows(&self) -> Result<bool> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire connection lock: {e}"))?;
+        Ok(
+            conn.query_row("SELECT EXISTS (SELECT 1 FROM term_entry)", [], |row| {
+                row.get(0)
+            })?,
+        )
+    }
+}
+
+/// Distinct sequence numbers of `entries` for the sequence index. 0 means
+/// the entry isn't grouped with others, so it isn't indexed.
+fn entry_sequences<SchemaType: IsYomitanSchema>(entries: &[serde_json::Value]) -> Vec<i64> {
+    let mut sequences: Vec<i64> = entries
+        .iter()
+        .filter_map(SchemaType::get_sequence_number)
+        .filter(|sequence| *sequence != 0)
+        .collect();
+    sequences.sort_unstable();
+    sequences.dedup();
+    sequences
+}
+
+/// Keys matched by [`DictionaryDB::get_keys_matching_wildcard`]
+#[derive(Debug, Default, PartialEq)]
+pub struct WildcardMatches {
+    pub keys: Vec<String>,
+    /// The scan stopped at `max_scanned` rows before every candidate key was checked
+    pub scan_limited: bool,
+}
+
+/// Translate a wildcard pattern into a SQLite GLOB pattern. `*` and `?` carry
+/// over as is; GLOB's character class brackets are escaped to match literally.
+fn wildcard_to_glob(pattern: &str) -> String {
+    let mut glob = String::with_capacity(pattern.len());
+    for c in pattern.chars() {
+        match c {
+            '[' => glob.push_str("[[]"),
+            ']' => glob.push_str("[]]"),
+            _ => glob.push(c),
+        }
+    }
+    glob
 }
 
 // Add these unsafe implementations - safe because:
@@ -265,13 +547,18 @@ mod tests {
     use crate::json_schema::tag_bank_v3::TagBankV3;
     use crate::json_schema::term_bank_v3::TermBankV3;
     use crate::json_schema::term_meta_bank_v3::TermMetaBankV3;
+    use crate::StripExtension;
 
     use super::*;
 
     #[test]
     fn test_insert_and_get() {
         let temp_dir = tempfile::tempdir().unwrap();
-        let temp_dir = NormalizedPathBuf::new(Path::from_path(temp_dir.path()).unwrap());
+        let temp_dir = NormalizedPathBuf::new(
+            Path::from_path(temp_dir.path()).unwrap(),
+            StripExtension::None,
+        )
+        .unwrap();
 
         let db: DictionaryDB<TermBankV3> = DictionaryDB::new(temp_dir).unwrap();
         db.insert("打", "{}").unwrap();
@@ -279,10 +566,234 @@ mod tests {
         assert_eq!(term, "{}");
     }
 
+    #[test]
+    fn test_get_many() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_dir = NormalizedPathBuf::new(
+            Path::from_path(temp_dir.path()).unwrap(),
+            StripExtension::None,
+        )
+        .unwrap();
+
+        let db: DictionaryDB<TermBankV3> = DictionaryDB::new(temp_dir).unwrap();
+        db.insert("打", "[1]").unwrap();
+        db.insert("食べる", "[2]").unwrap();
+        assert!(db.get_many(&[]).unwrap().is_empty());
+
+        // More keys than fit in one query
+        let mut keys: Vec<String> = (0..GET_MANY_CHUNK_SIZE)
+            .map(|i| format!("missing{i}"))
+            .collect();
+        keys.push("食べる".to_string());
+        keys.push("打".to_string());
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        let found = db.get_many(&keys).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found["打"], "[1]");
+        assert_eq!(found["食べる"], "[2]");
+    }
+
+    #[test]
+    fn test_exists_and_has_rows() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_dir = NormalizedPathBuf::new(
+            Path::from_path(temp_dir.path()).unwrap(),
+            StripExtension::None,
+        )
+        .unwrap();
+        assert!(!DictionaryDB::<TermBankV3>::exists(&temp_dir.path));
+
+        let db: DictionaryDB<TermBankV3> = DictionaryDB::new(temp_dir.clone()).unwrap();
+        assert!(DictionaryDB::<TermBankV3>::exists(&temp_dir.path));
+        assert!(!DictionaryDB::<TagBankV3>::exists(&temp_dir.path));
+        assert!(!db.has_rows().unwrap());
+        db.insert("打", "{}").unwrap();
+        assert!(db.has_rows().unwrap());
+    }
+
+    #[test]
+    fn test_get_keys_with_prefix() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_dir = NormalizedPathBuf::new(
+            Path::from_path(temp_dir.path()).unwrap(),
+            StripExtension::None,
+        )
+        .unwrap();
+
+        let db: DictionaryDB<TermBankV3> = DictionaryDB::new(temp_dir).unwrap();
+        for key in ["打つ", "打ち込む", "打", "食べる", "打ち込む"] {
+            db.insert(key, "[]").unwrap();
+        }
+        assert_eq!(
+            db.get_keys_with_prefix("打ち", 10).unwrap(),
+            vec!["打ち込む"]
+        );
+        assert_eq!(
+            db.get_keys_with_prefix("打", 2).unwrap(),
+            vec!["打", "打ち込む"]
+        );
+        assert!(db.get_keys_with_prefix("飲", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_get_keys_matching_wildcard() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_dir = NormalizedPathBuf::new(
+            Path::from_path(temp_dir.path()).unwrap(),
+            StripExtension::None,
+        )
+        .unwrap();
+
+        let db: DictionaryDB<TermBankV3> = DictionaryDB::new(temp_dir).unwrap();
+        for key in ["打つ", "打ち込む", "打", "書き込む", "[打]", "打ち込む"] {
+            db.insert(key, "[]").unwrap();
+        }
+        let keys = |pattern: &str, limit: usize, max_scanned: Option<usize>| {
+            db.get_keys_matching_wildcard(pattern, limit, max_scanned)
+                .unwrap()
+        };
+        assert_eq!(keys("打*", 10, None).keys, vec!["打", "打ち込む", "打つ"]);
+        assert_eq!(keys("打?", 10, None).keys, vec!["打つ"]);
+        assert_eq!(keys("*込む", 10, None).keys, vec!["打ち込む", "書き込む"]);
+        assert_eq!(keys("*込む", 1, None).keys, vec!["打ち込む"]);
+        assert_eq!(keys("[打]*", 10, None).keys, vec!["[打]"]);
+
+        let limited = keys("*込む", 10, Some(2));
+        assert!(limited.scan_limited);
+        assert!(limited.keys.len() < 2);
+        assert!(!keys("*込む", 10, Some(100)).scan_limited);
+    }
+
+    #[test]
+    fn test_get_keys_with_sequences() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_dir = NormalizedPathBuf::new(
+            Path::from_path(temp_dir.path()).unwrap(),
+            StripExtension::None,
+        )
+        .unwrap();
+
+        let db: DictionaryDB<TermBankV3> = DictionaryDB::new(temp_dir).unwrap();
+        db.insert(
+            "上がる",
+            r#"[["上がる", "あがる", "", "v5", 1, ["to rise"], 1, ""]]"#,
+        )
+        .unwrap();
+        db.insert(
+            "揚がる",
+            r#"[["揚がる", "あがる", "", "v5", 1, ["to rise"], 1, ""]]"#,
+        )
+        .unwrap();
+        db.insert(
+            "あがる",
+            r#"[["あがる", "あがる", "", "v5", 1, ["to rise"], 1, ""], ["あがる", "あがる", "", "", 1, ["other"], 2, ""]]"#,
+        )
+        .unwrap();
+        db.insert(
+            "打つ",
+            r#"[["打つ", "うつ", "", "v5", 1, ["to hit"], 3, ""]]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            db.get_keys_with_sequences(&[1]).unwrap(),
+            vec!["あがる", "上がる", "揚がる"]
+        );
+        assert_eq!(
+            db.get_keys_with_sequences(&[2, 3]).unwrap(),
+            vec!["あがる", "打つ"]
+        );
+        assert!(db.get_keys_with_sequences(&[4]).unwrap().is_empty());
+        assert!(db.get_keys_with_sequences(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sequence_index() {
+        let progress_state = Arc::new(ProgressStateTable::new(None).unwrap());
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_dir = NormalizedPathBuf::new(
+            Path::from_path(temp_dir.path()).unwrap(),
+            StripExtension::None,
+        )
+        .unwrap();
+
+        #[rustfmt::skip]
+        let grouped_json = GroupedJSON::from_json(vec![
+            json!(["上がる", "あがる", "", "v5", 1, ["to rise"], 1, ""]),
+            json!(["揚がる", "あがる", "", "v5", 1, ["to rise"], 1, ""]),
+            json!(["あがる", "あがる", "", "v5", 1, ["to rise"], 1, ""]),
+            json!(["あがる", "あがる", "", "", 1, ["other"], 2, ""]),
+            json!(["打", "だ", "", "", 1, ["da"], 0, ""]),
+        ])
+        .unwrap();
+        let db: DictionaryDB<TermBankV3> = DictionaryDB::new(temp_dir).unwrap();
+        db.insert_all(
+            &grouped_json,
+            progress_state,
+            "Test Dictionary".to_string(),
+            "1.0".to_string(),
+            ProgressGroupId(Uuid::new_v4()),
+        )
+        .unwrap();
+
+        assert!(db.has_sequence_index().unwrap());
+        assert_eq!(
+            db.get_by_sequence(1).unwrap(),
+            vec!["あがる", "上がる", "揚がる"]
+        );
+        assert_eq!(db.get_by_sequence(2).unwrap(), vec!["あがる"]);
+        assert_eq!(
+            db.get_by_sequence(1).unwrap(),
+            db.get_keys_with_sequences(&[1]).unwrap()
+        );
+        assert!(db.get_by_sequence(0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_replace() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_dir = NormalizedPathBuf::new(
+            Path::from_path(temp_dir.path()).unwrap(),
+            StripExtension::None,
+        )
+        .unwrap();
+
+        let db: DictionaryDB<TermBankV3> = DictionaryDB::new(temp_dir).unwrap();
+        assert_eq!(db.max_sequence().unwrap(), 0);
+        let entries = vec![
+            json!(["打", "だ", "", "", 0, ["da"], 1, ""]),
+            json!(["打", "ダース", "", "", 0, ["dozen"], 2, ""]),
+        ];
+        db.replace("打", &entries).unwrap();
+        db.replace(
+            "打つ",
+            &[json!(["打つ", "うつ", "", "v5", 0, ["to hit"], 3, ""])],
+        )
+        .unwrap();
+        assert_eq!(db.max_sequence().unwrap(), 3);
+        assert_eq!(db.get_by_sequence(2).unwrap(), vec!["打"]);
+
+        db.replace("打", &entries[..1]).unwrap();
+        assert!(db.get_by_sequence(2).unwrap().is_empty());
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&db.get("打").unwrap().unwrap()).unwrap(),
+            json!([entries[0]])
+        );
+
+        db.replace("打つ", &[]).unwrap();
+        assert!(db.get("打つ").unwrap().is_none());
+        let keys: Vec<String> = db.get_all().unwrap().into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["打"]);
+    }
+
     #[test]
     fn test_query_with_no_results() {
         let temp_dir = tempfile::tempdir().unwrap();
-        let temp_dir = NormalizedPathBuf::new(Path::from_path(temp_dir.path()).unwrap());
+        let temp_dir = NormalizedPathBuf::new(
+            Path::from_path(temp_dir.path()).unwrap(),
+            StripExtension::None,
+        )
+        .unwrap();
 
         let db: DictionaryDB<TermBankV3> = DictionaryDB::new(temp_dir).unwrap();
         let term = db.get("打").unwrap();
@@ -293,7 +804,11 @@ mod tests {
     async fn test_create_db_from_json_term_bank() {
         let progress_state = Arc::new(ProgressStateTable::new(None).unwrap());
         let temp_dir = tempfile::tempdir().unwrap();
-        let temp_dir = NormalizedPathBuf::new(Path::from_path(temp_dir.path()).unwrap());
+        let temp_dir = NormalizedPathBuf::new(
+            Path::from_path(temp_dir.path()).unwrap(),
+            StripExtension::None,
+        )
+        .unwrap();
 
         let grouped_json = GroupedJSON::new(vec![Path::new(
             "data/dictionaries/valid-dictionary1/term_bank_1.json",
@@ -325,7 +840,11 @@ mod tests {
         )])
         .unwrap();
         let temp_dir = tempfile::tempdir().unwrap();
-        let temp_dir = NormalizedPathBuf::new(Path::from_path(temp_dir.path()).unwrap());
+        let temp_dir = NormalizedPathBuf::new(
+            Path::from_path(temp_dir.path()).unwrap(),
+            StripExtension::None,
+        )
+        .unwrap();
 
         let db: DictionaryDB<TagBankV3> = DictionaryDB::new(temp_dir).unwrap();
         let group_id = ProgressGroupId(Uuid::new_v4());
@@ -353,7 +872,11 @@ mod tests {
         )])
         .unwrap();
         let temp_dir = tempfile::tempdir().unwrap();
-        let temp_dir = NormalizedPathBuf::new(Path::from_path(temp_dir.path()).unwrap());
+        let temp_dir = NormalizedPathBuf::new(
+            Path::from_path(temp_dir.path()).unwrap(),
+            StripExtension::None,
+        )
+        .unwrap();
 
         let db: DictionaryDB<TermMetaBankV3> = DictionaryDB::new(temp_dir).unwrap();
         let group_id = ProgressGroupId(Uuid::new_v4());
@@ -0,0 +1,65 @@
+//! Text decoding for archives from tools that don't write plain UTF-8: JSON
+//! with byte order marks or in Shift_JIS, and zip entry names in CP932
+//! without the UTF-8 flag.
+
+use std::borrow::Cow;
+
+use anyhow::Result;
+use encoding_rs::{Encoding, SHIFT_JIS, UTF_8};
+
+/// The text of a JSON file. A byte order mark picks the encoding and is
+/// dropped; without one the file is UTF-8, or Shift_JIS if it isn't valid
+/// UTF-8.
+pub fn decode_json(bytes: &[u8]) -> Result<Cow<'_, str>> {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        let (text, had_errors) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+        if had_errors {
+            anyhow::bail!("Invalid {} text", encoding.name());
+        }
+        return Ok(text);
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Ok(Cow::Borrowed(text));
+    }
+    SHIFT_JIS
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .ok_or_else(|| anyhow::anyhow!("Text is neither UTF-8 nor Shift_JIS"))
+}
+
+/// The name of a zip entry from its raw bytes. Names that aren't valid UTF-8
+/// are tried as CP932, which Japanese Windows tools write without setting
+/// the UTF-8 flag, before `fallback` (the zip crate's CP437 reading).
+pub fn decode_entry_name(raw: &[u8], fallback: &str) -> String {
+    if let Some(name) = UTF_8.decode_without_bom_handling_and_without_replacement(raw) {
+        return name.into_owned();
+    }
+    SHIFT_JIS
+        .decode_without_bom_handling_and_without_replacement(raw)
+        .map_or_else(|| fallback.to_string(), Cow::into_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_json() {
+        assert_eq!(decode_json(b"[1]").unwrap(), "[1]");
+        assert_eq!(decode_json(b"\xEF\xBB\xBF[1]").unwrap(), "[1]");
+        assert_eq!(decode_json(b"\xFF\xFE[\x001\x00]\x00").unwrap(), "[1]");
+        let (shift_jis, _, _) = SHIFT_JIS.encode(r#"["猫"]"#);
+        assert_eq!(decode_json(&shift_jis).unwrap(), r#"["猫"]"#);
+        assert!(decode_json(b"\xEF\xBB\xBF\xFF").is_err());
+    }
+
+    #[test]
+    fn test_decode_entry_name() {
+        assert_eq!(
+            decode_entry_name("img/猫.png".as_bytes(), "x"),
+            "img/猫.png"
+        );
+        let (cp932, _, _) = SHIFT_JIS.encode("img/猫.png");
+        assert_eq!(decode_entry_name(&cp932, "x"), "img/猫.png");
+        assert_eq!(decode_entry_name(b"\xFF\xFF", "fallback"), "fallback");
+    }
+}
@@ -1,13 +1,16 @@
 pub mod db;
+pub mod encoding;
 pub mod utils;
 
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::Read;
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use camino::Utf8Path as Path;
+use serde::{Deserialize, Serialize};
 use serde_json;
 use tracing::debug;
 use utils::CreateTaskParams;
@@ -16,13 +19,117 @@ use utils::ProgressStateTable;
 use utils::ProgressTaskType;
 use zip::ZipArchive;
 
-pub trait IsYomitanSchema {
-    fn get_schema_prefix() -> &'static str;
-    fn get_schema_name() -> &'static str;
-}
+#[cfg(feature = "schema-validation")]
+use crate::json_schema::validation;
+pub use crate::json_schema::{IsYomitanSchema, SchemaViolation};
 
 pub struct GroupedJSON(pub HashMap<String, Vec<serde_json::Value>>);
 
+/// Context attached to errors parsing a JSON file from a dictionary archive.
+/// Callers can `downcast_ref` it to find which file in the archive failed.
+#[derive(Debug, Clone)]
+pub struct ArchiveJsonFile(pub String);
+
+impl fmt::Display for ArchiveJsonFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to parse {} in the dictionary archive", self.0)
+    }
+}
+
+/// How an import treats bank rows that don't match their schema
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportMode {
+    /// Store every row as is. A malformed row makes lookups of its whole key
+    /// fail to deserialize.
+    #[default]
+    Strict,
+    /// Check each row against the typed schema and skip the ones that don't
+    /// match, reporting them
+    Lenient,
+    /// Store every row as in strict mode, and check each bank file against
+    /// the official Yomitan JSON schema, reporting every violation
+    #[cfg(feature = "schema-validation")]
+    Validate,
+}
+
+/// Rows kept in a `SchemaErrorReport`; any beyond are only counted. Also the
+/// most schema violations kept.
+pub const MAX_REPORTED_ENTRIES: usize = 1000;
+
+/// A bank row skipped in lenient mode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MalformedEntry {
+    pub file: String,
+    /// Position of the row in its bank file
+    pub index: usize,
+    pub error: String,
+    pub entry: serde_json::Value,
+}
+
+/// Rows of one schema skipped in lenient mode, and the schema violations
+/// found in validate mode
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaErrorReport {
+    pub skipped: usize,
+    pub entries: Vec<MalformedEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub violations: Vec<SchemaViolation>,
+}
+
+impl SchemaErrorReport {
+    pub fn is_empty(&self) -> bool {
+        self.skipped == 0 && self.violations.is_empty()
+    }
+
+    /// Record where the bank `values` from `file` breaks the `SchemaType`
+    /// JSON schema, up to `MAX_REPORTED_ENTRIES` violations in all
+    #[cfg(feature = "schema-validation")]
+    fn validate<SchemaType: IsYomitanSchema>(
+        &mut self,
+        file: &str,
+        values: Vec<serde_json::Value>,
+    ) -> Result<Vec<serde_json::Value>> {
+        let bank = serde_json::Value::Array(values);
+        let limit = MAX_REPORTED_ENTRIES.saturating_sub(self.violations.len());
+        self.violations
+            .extend(validation::validate_bank::<SchemaType>(file, &bank, limit)?);
+        let serde_json::Value::Array(values) = bank else {
+            unreachable!("bank was built as an array");
+        };
+        Ok(values)
+    }
+
+    /// Drop the rows of `values` from `file` that don't deserialize as
+    /// `Entry`, recording them
+    fn retain_valid<Entry: serde::de::DeserializeOwned>(
+        &mut self,
+        file: &str,
+        values: Vec<serde_json::Value>,
+    ) -> Vec<serde_json::Value> {
+        let mut valid = Vec::with_capacity(values.len());
+        for (index, value) in values.into_iter().enumerate() {
+            match Entry::deserialize(&value) {
+                Ok(_) => valid.push(value),
+                Err(e) => {
+                    self.skipped += 1;
+                    if self.entries.len() < MAX_REPORTED_ENTRIES {
+                        self.entries.push(MalformedEntry {
+                            file: file.to_string(),
+                            index,
+                            error: e.to_string(),
+                            entry: value,
+                        });
+                    }
+                }
+            }
+        }
+        valid
+    }
+}
+
 impl GroupedJSON {
     pub fn new(paths: Vec<&Path>) -> Result<Self> {
         let mut merged_json = Vec::new();
@@ -41,10 +148,12 @@ impl GroupedJSON {
         dictionary_title: String,
         dictionary_revision: String,
         group_id: ProgressGroupId,
-    ) -> Result<Self> {
+        mode: ImportMode,
+    ) -> Result<(Self, SchemaErrorReport)> {
         let prefix = SchemaType::get_schema_prefix();
         let json_paths_in_archive = find_files_with_prefix(archive, prefix);
 
+        let mut report = SchemaErrorReport::default();
         let merged_json = {
             let params = CreateTaskParams {
                 task_type: ProgressTaskType::MergeJson,
@@ -57,17 +166,27 @@ impl GroupedJSON {
             let task_id = progress_state.create_task(params, group_id)?;
 
             let mut merged_json: Vec<serde_json::Value> = Vec::new();
-            for file in json_paths_in_archive {
-                let mut file_in_zip = archive.by_name(&file)?;
-                let json_values: Vec<serde_json::Value> =
-                    serde_json::from_reader(&mut file_in_zip)?;
+            for (index, file) in json_paths_in_archive {
+                let mut contents = Vec::new();
+                archive.by_index(index)?.read_to_end(&mut contents)?;
+                let json_values: Vec<serde_json::Value> = encoding::decode_json(&contents)
+                    .and_then(|text| Ok(serde_json::from_str(&text)?))
+                    .with_context(|| ArchiveJsonFile(file.clone()))?;
+                let json_values = match mode {
+                    ImportMode::Strict => json_values,
+                    ImportMode::Lenient => {
+                        report.retain_valid::<SchemaType::Entry>(&file, json_values)
+                    }
+                    #[cfg(feature = "schema-validation")]
+                    ImportMode::Validate => report.validate::<SchemaType>(&file, json_values)?,
+                };
                 merged_json.extend(json_values);
                 progress_state.increment(&task_id, 1)?;
             }
             merged_json
         };
 
-        Ok(Self::from_json(merged_json)?)
+        Ok((Self::from_json(merged_json)?, report))
     }
 
     fn from_json(json: Vec<serde_json::Value>) -> Result<Self> {
@@ -84,13 +203,14 @@ impl GroupedJSON {
     }
 }
 
-fn find_files_with_prefix(archive: &mut ZipArchive<File>, prefix: &str) -> Vec<String> {
+/// Indexes and decoded names of the entries whose names start with `prefix`
+fn find_files_with_prefix(archive: &mut ZipArchive<File>, prefix: &str) -> Vec<(usize, String)> {
     (0..archive.len())
         .filter_map(|i| {
             let file = archive.by_index(i).ok()?;
-            let name = file.name().to_owned();
+            let name = encoding::decode_entry_name(file.name_raw(), file.name());
             if name.starts_with(prefix) {
-                Some(name)
+                Some((i, name))
             } else {
                 None
             }
@@ -119,4 +239,24 @@ mod tests {
         assert_eq!(*term_bank.0.get("打つ").unwrap(), vec![json!(["打つ", "うつ", "vt", "v5", 10, ["utsu definition 1", "utsu definition 2"], 3, "P E1"]), json!(["打つ", "うつ", "vt", "v5", 1, ["utsu definition 3", "utsu definition 4"], 3, "P E2"]), json!(["打つ", "ぶつ", "vt", "v5", 10, ["butsu definition 1", "butsu definition 2"], 3, "P E1"]), json!(["打つ", "ぶつ", "vt", "v5", 1, ["butsu definition 3", "butsu definition 4"], 3, "P E2"])]);
         // TODO: Add the rest of the assertions for the other entries
     }
+
+    #[test]
+    fn test_retain_valid_term_entries() {
+        use crate::json_schema::term_bank_v3::TermEntry;
+
+        let mut report = SchemaErrorReport::default();
+        let valid = report.retain_valid::<TermEntry>(
+            "term_bank_1.json",
+            vec![
+                json!(["打", "だ", "n", "n", 1, ["da"], 1, "E1"]),
+                json!(["打", "だ", "n", "n", "high", ["da"], 1, "E1"]),
+                json!(["打つ", "うつ", "vt", "v5", 10, ["utsu"], 3, "P"]),
+            ],
+        );
+        assert_eq!(valid.len(), 2);
+        assert_eq!(report.skipped, 1);
+        assert_eq!(report.entries[0].index, 1);
+        assert_eq!(report.entries[0].file, "term_bank_1.json");
+        assert_eq!(GroupedJSON::from_json(valid).unwrap().0.len(), 2);
+    }
 }
@@ -1,24 +1,24 @@
+//! Yomitan dictionary format: schema types for the bank files, and (with the
+//! default `storage` feature) importing archives into SQLite.
+//!
+//! Without `storage` the crate has no SQLite, zip or file IO dependencies and
+//! builds for `wasm32-unknown-unknown`, so the web frontend can parse and
+//! preview bank files before uploading an archive.
+//!
+//! The `schema-validation` feature adds checking bank files against the
+//! official Yomitan JSON schemas (`json_schema::validation`).
+
 use camino::{Utf8Path as Path, Utf8PathBuf as PathBuf};
-use unicode_normalization::UnicodeNormalization;
 
 pub mod json_schema;
+#[cfg(feature = "storage")]
 pub mod kv_store;
+pub mod normalization;
 
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
-
 #[derive(Debug, Clone)]
 pub struct NormalizedPathBuf {
     pub path: PathBuf,
@@ -28,21 +28,161 @@ pub struct NormalizedPathBuf {
 #[derive(Debug, Clone)]
 pub struct NormalizedFilename(pub String);
 
+/// The extension `NormalizedPathBuf::new` drops to get the file name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StripExtension {
+    /// Keep the whole name, as for dictionary directories, whose names often
+    /// contain dots ("Jitendex v2.1")
+    None,
+    /// Drop the last extension, if the name has one
+    Last,
+    /// Drop the extension only when it's this one, ignoring case
+    Only(&'static str),
+}
+
 impl NormalizedPathBuf {
-    pub fn new(path: &Path) -> Self {
-        let normalized_path: PathBuf = PathBuf::from(path.as_str().nfc().collect::<String>());
-        let filename = {
-            let filename = normalized_path
-                .file_name()
-                .expect(&format!("Failed to get file name for {normalized_path:?}"));
-            // Remove the .zip extension from the filename
-            let split = filename.split('.').collect::<Vec<&str>>();
-            split[0..split.len() - 1].join(".")
-        };
-
-        Self {
+    /// `path` in the stored normalization form, with its file name less the
+    /// extension picked by `strip`. Leading dots don't start an extension, so
+    /// dotfiles keep their whole name. Errors when `path` has no file name.
+    pub fn new(path: &Path, strip: StripExtension) -> anyhow::Result<Self> {
+        let normalized_path = PathBuf::from(normalization::normalize_name(path.as_str()));
+        let name = normalized_path
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("{path} doesn't have a file name"))?;
+        let filename = match (strip, split_extension(name)) {
+            (StripExtension::Last, Some((stem, _))) => stem,
+            (StripExtension::Only(wanted), Some((stem, extension)))
+                if extension.eq_ignore_ascii_case(wanted) =>
+            {
+                stem
+            }
+            _ => name,
+        }
+        .to_string();
+
+        Ok(Self {
             path: normalized_path,
             filename: NormalizedFilename(filename),
+        })
+    }
+}
+
+/// `name` split at the dot starting its last extension. Names without a
+/// non-empty extension after their leading dots aren't split.
+fn split_extension(name: &str) -> Option<(&str, &str)> {
+    let leading_dots = name.len() - name.trim_start_matches('.').len();
+    let dot = name.rfind('.').filter(|dot| *dot >= leading_dots)?;
+    let extension = &name[dot + 1..];
+    if extension.is_empty() {
+        return None;
+    }
+    Some((&name[..dot], extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use unicode_normalization::UnicodeNormalization;
+
+    #[test]
+    fn it_works() {
+        let result = add(2, 2);
+        assert_eq!(result, 4);
+    }
+
+    fn new(path: &str, strip: StripExtension) -> NormalizedPathBuf {
+        NormalizedPathBuf::new(Path::new(path), strip).unwrap()
+    }
+
+    #[test]
+    fn test_strip_extension() {
+        let cases = [
+            (
+                "yomitan/Jitendex.zip",
+                StripExtension::Only("zip"),
+                "Jitendex",
+            ),
+            (
+                "yomitan/Jitendex v2.1.zip",
+                StripExtension::Only("zip"),
+                "Jitendex v2.1",
+            ),
+            ("yomitan/JMdict.ZIP", StripExtension::Only("zip"), "JMdict"),
+            (
+                "yomitan/JMdict.tar",
+                StripExtension::Only("zip"),
+                "JMdict.tar",
+            ),
+            ("db/Jitendex v2.1", StripExtension::None, "Jitendex v2.1"),
+            ("db/Jitendex v2.1", StripExtension::Last, "Jitendex v2"),
+            ("db/noext", StripExtension::Last, "noext"),
+            ("db/.hidden", StripExtension::Last, ".hidden"),
+            ("db/.hidden.zip", StripExtension::Only("zip"), ".hidden"),
+            ("db/trailing.", StripExtension::Last, "trailing."),
+        ];
+        for (path, strip, filename) in cases {
+            assert_eq!(new(path, strip).filename.0, filename, "{path} {strip:?}");
+        }
+    }
+
+    #[test]
+    fn test_no_file_name() {
+        for path in ["", "/", "db/.."] {
+            assert!(NormalizedPathBuf::new(Path::new(path), StripExtension::None).is_err());
+        }
+    }
+
+    /// Properties over every combination of name parts
+    #[test]
+    fn test_properties() {
+        let prefixes = ["", ".", ".."];
+        let stems = ["a", "辞書", "Jitendex v2.1", "がっこう", "a..b"];
+        let extensions = ["", ".", ".zip", ".ZIP", ".json", ".tar.gz"];
+        let strips = [
+            StripExtension::None,
+            StripExtension::Last,
+            StripExtension::Only("zip"),
+        ];
+        for prefix in prefixes {
+            for stem in stems {
+                for stem in [stem.to_string(), stem.nfd().collect()] {
+                    for extension in extensions {
+                        let name = format!("{prefix}{stem}{extension}");
+                        let nfc_name = name.nfc().collect::<String>();
+                        for strip in strips {
+                            let path = format!("dir/{name}");
+                            let normalized = new(&path, strip);
+                            let filename = &normalized.filename.0;
+
+                            assert!(!filename.is_empty(), "{path}");
+                            assert!(!filename.contains('/'), "{path}");
+                            assert_eq!(normalized.path, format!("dir/{nfc_name}"));
+                            // The file name is the stored name less at most one extension
+                            assert!(nfc_name.starts_with(filename.as_str()), "{path}");
+                            let removed = &nfc_name[filename.len()..];
+                            assert!(
+                                removed.is_empty()
+                                    || (removed.starts_with('.')
+                                        && removed.len() > 1
+                                        && !removed[1..].contains('.')),
+                                "{path} {strip:?}"
+                            );
+                            match strip {
+                                StripExtension::None => assert!(removed.is_empty()),
+                                StripExtension::Only(wanted) => assert_eq!(
+                                    !removed.is_empty(),
+                                    removed[1.min(removed.len())..].eq_ignore_ascii_case(wanted)
+                                ),
+                                StripExtension::Last => {}
+                            }
+                            // Normalizing again changes nothing
+                            let again = new(normalized.path.as_str(), strip);
+                            assert_eq!(again.path, normalized.path);
+                            assert_eq!(&again.filename.0, filename);
+                        }
+                    }
+                }
+            }
         }
     }
 }
@@ -0,0 +1,75 @@
+//! The Unicode normalization policy for dictionary and media file names.
+//!
+//! Names are stored in NFC: archive paths, dictionary directories and asset
+//! manifest keys all go through [`normalize_name`]. Files we didn't write
+//! ourselves (audio collections, directories copied from macOS, which stores
+//! names in NFD) may be in either form, so lookups on disk go through
+//! [`find_existing`], which tries NFC, then NFD, then the name as given.
+
+use std::path::{Path, PathBuf};
+
+use unicode_normalization::UnicodeNormalization;
+
+/// The stored form of a file name or relative path
+pub fn normalize_name(name: &str) -> String {
+    name.nfc().collect()
+}
+
+/// Forms of `name` to look for on disk, most likely first, without repeats
+pub fn lookup_candidates(name: &str) -> Vec<String> {
+    let mut candidates = vec![normalize_name(name)];
+    for candidate in [name.nfd().collect::<String>(), name.to_string()] {
+        if !candidates.contains(&candidate) {
+            candidates.push(candidate);
+        }
+    }
+    candidates
+}
+
+/// `base` joined with the first form of `rel_path` that exists
+pub fn find_existing(base: &Path, rel_path: &str) -> Option<PathBuf> {
+    lookup_candidates(rel_path)
+        .into_iter()
+        .map(|candidate| base.join(candidate))
+        .find(|path| path.exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NFC: &str = "じしょ/が.png";
+
+    fn nfd() -> String {
+        NFC.nfd().collect()
+    }
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name(NFC), NFC);
+        assert_eq!(normalize_name(&nfd()), NFC);
+        assert_eq!(normalize_name("plain.png"), "plain.png");
+    }
+
+    #[test]
+    fn test_lookup_candidates() {
+        assert_eq!(lookup_candidates("plain.png"), vec!["plain.png"]);
+        assert_eq!(lookup_candidates(NFC), vec![NFC.to_string(), nfd()]);
+        assert_eq!(lookup_candidates(&nfd()), vec![NFC.to_string(), nfd()]);
+    }
+
+    #[test]
+    fn test_find_existing_either_form() {
+        for stored in [NFC.to_string(), nfd()] {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join(&stored);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, b"png").unwrap();
+            for requested in [NFC.to_string(), nfd()] {
+                let found = find_existing(dir.path(), &requested).unwrap();
+                assert_eq!(std::fs::read(found).unwrap(), b"png");
+            }
+            assert!(find_existing(dir.path(), "じしょ/missing.png").is_none());
+        }
+    }
+}